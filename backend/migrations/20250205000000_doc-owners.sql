@@ -0,0 +1,10 @@
+-- Owners can be assigned to a single document or a whole directory (a path ending in `/`).
+-- Exactly one of user_id or group_id is set for each row.
+CREATE TABLE doc_owners (
+    path TEXT NOT NULL,
+    user_id INTEGER,
+    group_id INTEGER,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE,
+    CHECK ((user_id IS NULL) != (group_id IS NULL))
+) STRICT;
@@ -0,0 +1,14 @@
+-- Editing tickets let a client edit a document offline, then submit it later against
+-- the revision it started from.
+CREATE TABLE editing_tickets (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    -- The commit the document was read from
+    base_commit TEXT NOT NULL,
+    -- The git blob of the document when the ticket was issued
+    blob_sha TEXT NOT NULL,
+    -- ISO-8601/RFC-3339 string
+    expiration_date TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1,20 @@
+-- Webhook events are queued here as they're received, then processed (and retried on failure)
+-- by a background worker, so an event isn't lost if processing fails.
+CREATE TABLE webhook_events (
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- The `X-GitHub-Event` header, e.g. `push`
+    event_type TEXT NOT NULL,
+    -- The `X-GitHub-Delivery` header, if one was sent
+    delivery_id TEXT,
+    payload TEXT NOT NULL,
+    -- `pending`, `completed`, or `failed` once every attempt has been used up
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    -- RFC-3339 strings in UTC with millisecond precision, so they can be compared as text
+    received_at TEXT NOT NULL,
+    next_attempt_at TEXT NOT NULL,
+    completed_at TEXT
+) STRICT;
+
+CREATE INDEX webhook_events_due ON webhook_events (status, next_attempt_at);
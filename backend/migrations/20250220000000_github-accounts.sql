@@ -0,0 +1,4 @@
+-- The GitHub account each user has linked, so they can be credited as a co-author on commits
+ALTER TABLE users ADD github_id INTEGER;
+ALTER TABLE users ADD github_login TEXT;
+CREATE UNIQUE INDEX users_github_id ON users(github_id);
@@ -0,0 +1,23 @@
+-- Pull requests opened through Hyde, so that release notes can be generated from the
+-- documentation changes that were merged.
+CREATE TABLE hyde_prs (
+    number INTEGER PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    -- The user who opened the pull request, if they're known
+    author_id INTEGER,
+    -- RFC-3339 strings in UTC with millisecond precision, so they can be compared as text
+    created_at TEXT NOT NULL,
+    -- Set from `pull_request` webhook events
+    merged_at TEXT,
+    FOREIGN KEY(author_id) REFERENCES users(id) ON DELETE SET NULL
+) STRICT;
+
+-- The documents changed by each pull request, relative to the documents folder
+CREATE TABLE hyde_pr_docs (
+    number INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    PRIMARY KEY (number, path),
+    FOREIGN KEY(number) REFERENCES hyde_prs(number) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX hyde_prs_merged_at ON hyde_prs (merged_at);
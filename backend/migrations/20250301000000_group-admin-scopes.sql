@@ -0,0 +1,9 @@
+-- Delegated membership management: members of `group_id` may add users to and remove users
+-- from `managed_group_id`, without needing the ManageUsers permission.
+CREATE TABLE group_admin_scopes (
+    group_id INTEGER NOT NULL,
+    managed_group_id INTEGER NOT NULL,
+    PRIMARY KEY (group_id, managed_group_id),
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE,
+    FOREIGN KEY(managed_group_id) REFERENCES groups(id) ON DELETE CASCADE
+) STRICT;
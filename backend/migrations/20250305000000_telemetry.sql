@@ -0,0 +1,13 @@
+-- Opt-in performance metrics reported by the editor and measured by the server. Samples
+-- aren't tied to a user.
+CREATE TABLE telemetry (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- "client" or "server"
+    source TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    -- RFC-3339 strings in UTC with millisecond precision, so they can be compared as text
+    recorded_at TEXT NOT NULL
+) STRICT;
+
+CREATE INDEX telemetry_recorded_at ON telemetry (recorded_at);
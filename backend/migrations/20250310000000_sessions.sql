@@ -0,0 +1,12 @@
+-- Wiki sessions, so that the session cookie is an opaque random id instead of the user's
+-- Discord access token
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- RFC-3339 strings in UTC with millisecond precision, so they can be compared as text
+    created_at TEXT NOT NULL,
+    expiration_date TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX sessions_user_id ON sessions (user_id);
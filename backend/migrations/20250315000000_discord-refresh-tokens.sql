@@ -0,0 +1,3 @@
+-- The Discord refresh token returned alongside each user's access token, so the access token
+-- can be renewed without the user logging in again
+ALTER TABLE users ADD refresh_token TEXT;
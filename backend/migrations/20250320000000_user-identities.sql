@@ -0,0 +1,20 @@
+-- The accounts users can sign in with. A user can link one account from each provider.
+CREATE TABLE user_identities (
+    -- "discord", "github" or "oidc"
+    provider TEXT NOT NULL,
+    -- The provider's id for the account
+    subject TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- The account's name on the provider, which can change
+    username TEXT NOT NULL,
+    PRIMARY KEY (provider, subject),
+    UNIQUE (user_id, provider),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
+
+-- Linked GitHub accounts can be signed in with
+INSERT INTO user_identities (provider, subject, user_id, username)
+SELECT 'github', CAST(github_id AS TEXT), id, github_login FROM users WHERE github_id IS NOT NULL;
+
+-- The provider each session was signed in with, NULL for sessions from before this migration
+ALTER TABLE sessions ADD provider TEXT;
@@ -0,0 +1,16 @@
+-- Tokens scripts and CI pipelines authenticate with instead of a browser session. Only a hash
+-- of each token is stored, the token itself is shown once when it's created.
+CREATE TABLE api_tokens (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    -- What the token is for, e.g. "docs deploy pipeline"
+    name TEXT NOT NULL,
+    -- Hex encoded SHA-256 of the token
+    token_hash TEXT NOT NULL UNIQUE,
+    -- ISO-8601/RFC-3339 strings
+    created_at TEXT NOT NULL,
+    -- NULL if the token doesn't expire
+    expiration_date TEXT,
+    last_used_at TEXT,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1,6 @@
+-- Details admins use to recognize sessions, so that a compromised account can be logged out
+-- ISO-8601/RFC-3339 string, updated at most once a minute
+ALTER TABLE sessions ADD last_used_at TEXT;
+-- Where the user signed in from, as reported by the reverse proxy
+ALTER TABLE sessions ADD ip_address TEXT;
+ALTER TABLE sessions ADD user_agent TEXT;
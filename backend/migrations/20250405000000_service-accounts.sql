@@ -0,0 +1,3 @@
+-- Service accounts are users for bots and scheduled jobs. They're created by admins, can't sign
+-- in, and authenticate only with API tokens.
+ALTER TABLE users ADD COLUMN service_account INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,20 @@
+-- Pending device authorization requests (RFC 8628), for clients that sign in without a
+-- browser. The client polls with the device code while the user approves it in their browser
+-- by entering the user code.
+CREATE TABLE device_codes (
+    -- Hex encoded SHA-256 of the device code
+    device_code_hash TEXT PRIMARY KEY,
+    -- What the user enters, without the dash, e.g. "WDJBMJHT"
+    user_code TEXT NOT NULL UNIQUE,
+    -- What the client called itself, shown to the user when they approve it
+    client_name TEXT NOT NULL,
+    -- ISO-8601/RFC-3339 strings
+    created_at TEXT NOT NULL,
+    expiration_date TEXT NOT NULL,
+    last_polled_at TEXT,
+    -- The user that approved the request, NULL until then
+    user_id INTEGER,
+    -- 1 if the user turned the request down
+    denied INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
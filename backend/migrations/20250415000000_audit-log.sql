@@ -0,0 +1,19 @@
+-- Security relevant events, like signing in and being denied access, kept so that moderation
+-- disputes can be settled after the fact
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY,
+    -- ISO-8601/RFC-3339 string
+    created_at TEXT NOT NULL,
+    -- What kind of event it is, e.g. "auth"
+    category TEXT NOT NULL,
+    -- What happened, e.g. "login"
+    action TEXT NOT NULL,
+    -- The user involved, if any. The username is kept so that entries stay attributable after
+    -- the user is deleted.
+    user_id INTEGER,
+    username TEXT,
+    ip_address TEXT,
+    detail TEXT NOT NULL
+) STRICT;
+
+CREATE INDEX audit_log_category ON audit_log (category, created_at);
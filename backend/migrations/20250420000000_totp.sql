@@ -0,0 +1,13 @@
+-- TOTP (RFC 6238) second factors, asked for before privileged users take destructive actions
+CREATE TABLE totp_secrets (
+    user_id INTEGER PRIMARY KEY,
+    -- The base32 encoded secret, encrypted like users' OAuth tokens
+    secret TEXT NOT NULL,
+    -- 0 until the user confirms enrollment with a code from their authenticator
+    enabled INTEGER NOT NULL DEFAULT 0,
+    -- ISO-8601/RFC-3339 string
+    created_at TEXT NOT NULL,
+    -- The time step of the last accepted code, so that codes can't be replayed
+    last_used_step INTEGER,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1,5 @@
+-- Uploading and deleting assets got its own permission. The admin group gets it, and so does
+-- every group that could manage assets through ManageContent before.
+INSERT INTO group_permissions ( group_id, permission )
+SELECT DISTINCT group_id, 'ManageAssets' FROM group_permissions
+WHERE permission = 'ManageContent' OR group_id = 1;
@@ -0,0 +1,9 @@
+-- Per-branch push permissions: a branch matching any pattern can only be pushed to by members
+-- of the groups with a matching pattern. Branches no pattern matches aren't restricted.
+CREATE TABLE group_branch_rules (
+    group_id INTEGER NOT NULL,
+    -- A branch name, where `*` matches any characters, e.g. "master" or "hyde/*"
+    pattern TEXT NOT NULL,
+    PRIMARY KEY (group_id, pattern),
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+) STRICT;
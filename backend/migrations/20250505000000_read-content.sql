@@ -0,0 +1,5 @@
+-- Reading can require its own permission (`auth.require_read_permission`). The admin group gets
+-- it, and so does every group that could edit content, since they had to be able to read it.
+INSERT INTO group_permissions ( group_id, permission )
+SELECT DISTINCT group_id, 'ReadContent' FROM group_permissions
+WHERE permission IN ('ManageContent', 'SubmitForReview', 'ManageAssets') OR group_id = 1;
@@ -0,0 +1,5 @@
+-- Recloning and other repository maintenance got its own permission, which used to need
+-- ManageUsers. Every group that could do it before gets it.
+INSERT INTO group_permissions ( group_id, permission )
+SELECT DISTINCT group_id, 'ManageRepo' FROM group_permissions
+WHERE permission = 'ManageUsers' OR group_id = 1;
@@ -0,0 +1,16 @@
+-- Deny rules: a permission denied to a user, or to a group they're in, is taken away from them
+-- even if one of their groups grants it, until the rule expires or is removed.
+CREATE TABLE permission_denies (
+    id INTEGER PRIMARY KEY,
+    -- Exactly one of user_id and group_id is set
+    user_id INTEGER,
+    group_id INTEGER,
+    permission TEXT NOT NULL,
+    -- ISO-8601/RFC-3339 string, or NULL if the rule doesn't expire
+    expires_at TEXT,
+    -- ISO-8601/RFC-3339 string
+    created_at TEXT NOT NULL,
+    CHECK ((user_id IS NULL) != (group_id IS NULL)),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1,5 @@
+-- Permissions and memberships can be granted until a point in time, e.g. to give someone
+-- moderator access for an event. Once expired they're ignored, and cleaned up in the background.
+-- ISO-8601/RFC-3339 strings, or NULL if the grant doesn't expire
+ALTER TABLE group_permissions ADD COLUMN expires_at TEXT;
+ALTER TABLE group_membership ADD COLUMN expires_at TEXT;
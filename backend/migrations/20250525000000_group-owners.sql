@@ -0,0 +1,9 @@
+-- Group owners may add users to and remove users from the groups they own, without needing the
+-- ManageUsers permission.
+CREATE TABLE group_owners (
+    group_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    PRIMARY KEY (group_id, user_id),
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1,6 @@
+-- What an audit log entry is about, e.g. a document path or "user:4", and structured details
+-- about the change as a JSON object, alongside the human readable `detail`
+ALTER TABLE audit_log ADD COLUMN target TEXT;
+ALTER TABLE audit_log ADD COLUMN data TEXT;
+CREATE INDEX audit_log_user_id ON audit_log (user_id, created_at);
+CREATE INDEX audit_log_target ON audit_log (target, created_at);
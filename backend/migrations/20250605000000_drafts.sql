@@ -0,0 +1,14 @@
+-- Work in progress the editor autosaves, so it can be recovered after a crash without
+-- committing anything. Each user has at most one draft per document.
+CREATE TABLE drafts (
+    user_id INTEGER NOT NULL,
+    -- The document's path, relative to the documents folder
+    doc_path TEXT NOT NULL,
+    contents TEXT NOT NULL,
+    -- The commit the document was read from before it was edited, if the editor knows it
+    base_commit TEXT,
+    -- ISO-8601/RFC-3339 string
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, doc_path),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1,17 @@
+-- Who last edited each document and when, so the document browser can show it. Updated when
+-- documents are saved through Hyde, and removed when they're deleted.
+CREATE TABLE doc_meta (
+    -- Relative to the documents folder
+    path TEXT PRIMARY KEY NOT NULL,
+    -- The user who last saved the document, NULL if they've been deleted
+    last_editor_id INTEGER,
+    -- Kept so the editor can still be shown after their user is deleted
+    last_editor_name TEXT NOT NULL,
+    -- ISO-8601/RFC-3339 string
+    last_edited_at TEXT NOT NULL,
+    -- How many times the document has been saved through Hyde
+    edit_count INTEGER NOT NULL DEFAULT 0,
+    -- A short summary of the document, set by editors
+    description TEXT,
+    FOREIGN KEY(last_editor_id) REFERENCES users(id) ON DELETE SET NULL
+) STRICT;
@@ -0,0 +1,5 @@
+-- `users.expiration_date` is read as a typed datetime now. Dates that don't parse, like the empty
+-- ones of users who never signed in with Discord, are replaced with the epoch, which reads as an
+-- expired token.
+UPDATE users SET expiration_date = '1970-01-01T00:00:00+00:00'
+WHERE expiration_date NOT LIKE '____-__-__%' OR julianday(expiration_date) IS NULL;
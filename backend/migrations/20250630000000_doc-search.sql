@@ -0,0 +1,10 @@
+-- Full-text index over the documents, derived from the repository. Hyde rebuilds it on startup,
+-- so it's never backed up or copied to replicas. Archived documents aren't indexed.
+CREATE VIRTUAL TABLE doc_search USING fts5(
+    -- Relative to the documents folder
+    path UNINDEXED,
+    title,
+    -- The document without its front matter
+    body,
+    tokenize = 'porter unicode61'
+);
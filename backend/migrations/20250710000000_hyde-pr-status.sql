@@ -0,0 +1,10 @@
+-- The branch each pull request opened through Hyde proposes, and whether it's still open, so
+-- editors can find and resume their proposals. NULL for pull requests recorded before this.
+ALTER TABLE hyde_prs ADD COLUMN branch TEXT;
+
+-- `open`, `closed` or `merged`, set from `pull_request` webhook events
+ALTER TABLE hyde_prs ADD COLUMN status TEXT NOT NULL DEFAULT 'open';
+
+UPDATE hyde_prs SET status = 'merged' WHERE merged_at IS NOT NULL;
+
+CREATE INDEX hyde_prs_author_id ON hyde_prs (author_id, status);
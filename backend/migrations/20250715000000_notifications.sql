@@ -0,0 +1,16 @@
+-- In-app notifications, e.g. a review was requested or a pull request was merged
+CREATE TABLE notifications (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    -- What the notification is about, e.g. "review_requested"
+    kind TEXT NOT NULL,
+    -- The details the frontend shows, a JSON object
+    payload TEXT NOT NULL,
+    -- ISO-8601/RFC-3339 strings
+    created_at TEXT NOT NULL,
+    -- NULL until the user reads it
+    read_at TEXT,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX notifications_user_id ON notifications (user_id, read_at);
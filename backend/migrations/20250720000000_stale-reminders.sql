@@ -0,0 +1,10 @@
+-- When each document owner was last reminded that a document hasn't changed in a while, so the
+-- reminder isn't repeated every time Hyde checks
+CREATE TABLE stale_reminders (
+    path TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- ISO-8601/RFC-3339 string
+    reminded_at TEXT NOT NULL,
+    PRIMARY KEY (path, user_id),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) STRICT;
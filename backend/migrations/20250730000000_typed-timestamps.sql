@@ -0,0 +1,161 @@
+-- The remaining timestamps are read as typed datetimes too. SQLite has no datetime type, so they
+-- stay text, but are rewritten in the format sqlx writes them in (UTC with a `+00:00` offset) so
+-- that they still compare correctly as text. Times that don't parse are replaced with the epoch,
+-- so that expirations read as expired rather than as never expiring.
+UPDATE users SET
+    deactivated_at = CASE WHEN deactivated_at IS NULL THEN NULL
+        WHEN deactivated_at LIKE '____-__-__%' AND julianday(deactivated_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', deactivated_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE group_membership SET
+    expires_at = CASE WHEN expires_at IS NULL THEN NULL
+        WHEN expires_at LIKE '____-__-__%' AND julianday(expires_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', expires_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE group_permissions SET
+    expires_at = CASE WHEN expires_at IS NULL THEN NULL
+        WHEN expires_at LIKE '____-__-__%' AND julianday(expires_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', expires_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE editing_tickets SET
+    expiration_date = CASE WHEN expiration_date IS NULL THEN NULL
+        WHEN expiration_date LIKE '____-__-__%' AND julianday(expiration_date) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', expiration_date)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE webhook_events SET
+    received_at = CASE WHEN received_at IS NULL THEN NULL
+        WHEN received_at LIKE '____-__-__%' AND julianday(received_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', received_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    next_attempt_at = CASE WHEN next_attempt_at IS NULL THEN NULL
+        WHEN next_attempt_at LIKE '____-__-__%' AND julianday(next_attempt_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', next_attempt_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    completed_at = CASE WHEN completed_at IS NULL THEN NULL
+        WHEN completed_at LIKE '____-__-__%' AND julianday(completed_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', completed_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE hyde_prs SET
+    created_at = CASE WHEN created_at IS NULL THEN NULL
+        WHEN created_at LIKE '____-__-__%' AND julianday(created_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', created_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    merged_at = CASE WHEN merged_at IS NULL THEN NULL
+        WHEN merged_at LIKE '____-__-__%' AND julianday(merged_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', merged_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE telemetry SET
+    recorded_at = CASE WHEN recorded_at IS NULL THEN NULL
+        WHEN recorded_at LIKE '____-__-__%' AND julianday(recorded_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', recorded_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE sessions SET
+    created_at = CASE WHEN created_at IS NULL THEN NULL
+        WHEN created_at LIKE '____-__-__%' AND julianday(created_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', created_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    expiration_date = CASE WHEN expiration_date IS NULL THEN NULL
+        WHEN expiration_date LIKE '____-__-__%' AND julianday(expiration_date) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', expiration_date)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    last_used_at = CASE WHEN last_used_at IS NULL THEN NULL
+        WHEN last_used_at LIKE '____-__-__%' AND julianday(last_used_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', last_used_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE api_tokens SET
+    created_at = CASE WHEN created_at IS NULL THEN NULL
+        WHEN created_at LIKE '____-__-__%' AND julianday(created_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', created_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    expiration_date = CASE WHEN expiration_date IS NULL THEN NULL
+        WHEN expiration_date LIKE '____-__-__%' AND julianday(expiration_date) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', expiration_date)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    last_used_at = CASE WHEN last_used_at IS NULL THEN NULL
+        WHEN last_used_at LIKE '____-__-__%' AND julianday(last_used_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', last_used_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE device_codes SET
+    created_at = CASE WHEN created_at IS NULL THEN NULL
+        WHEN created_at LIKE '____-__-__%' AND julianday(created_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', created_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    expiration_date = CASE WHEN expiration_date IS NULL THEN NULL
+        WHEN expiration_date LIKE '____-__-__%' AND julianday(expiration_date) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', expiration_date)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    last_polled_at = CASE WHEN last_polled_at IS NULL THEN NULL
+        WHEN last_polled_at LIKE '____-__-__%' AND julianday(last_polled_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', last_polled_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE audit_log SET
+    created_at = CASE WHEN created_at IS NULL THEN NULL
+        WHEN created_at LIKE '____-__-__%' AND julianday(created_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', created_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE totp_secrets SET
+    created_at = CASE WHEN created_at IS NULL THEN NULL
+        WHEN created_at LIKE '____-__-__%' AND julianday(created_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', created_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE permission_denies SET
+    expires_at = CASE WHEN expires_at IS NULL THEN NULL
+        WHEN expires_at LIKE '____-__-__%' AND julianday(expires_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', expires_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    created_at = CASE WHEN created_at IS NULL THEN NULL
+        WHEN created_at LIKE '____-__-__%' AND julianday(created_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', created_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE drafts SET
+    updated_at = CASE WHEN updated_at IS NULL THEN NULL
+        WHEN updated_at LIKE '____-__-__%' AND julianday(updated_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', updated_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE doc_meta SET
+    last_edited_at = CASE WHEN last_edited_at IS NULL THEN NULL
+        WHEN last_edited_at LIKE '____-__-__%' AND julianday(last_edited_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', last_edited_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE notifications SET
+    created_at = CASE WHEN created_at IS NULL THEN NULL
+        WHEN created_at LIKE '____-__-__%' AND julianday(created_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', created_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END,
+    read_at = CASE WHEN read_at IS NULL THEN NULL
+        WHEN read_at LIKE '____-__-__%' AND julianday(read_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', read_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
+UPDATE stale_reminders SET
+    reminded_at = CASE WHEN reminded_at IS NULL THEN NULL
+        WHEN reminded_at LIKE '____-__-__%' AND julianday(reminded_at) IS NOT NULL
+        THEN strftime('%Y-%m-%dT%H:%M:%f+00:00', reminded_at)
+        ELSE '1970-01-01T00:00:00.000+00:00'
+    END;
@@ -0,0 +1,202 @@
+-- The PostgreSQL schema, matching the SQLite migrations in the parent folder up to and including
+-- the migration with the same version as this one. Later migrations are added to both folders.
+-- Timestamps are ISO-8601/RFC-3339 strings in UTC with millisecond precision like in SQLite, so
+-- they can be compared as text and the same queries work against both databases.
+CREATE TABLE users (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    username TEXT NOT NULL,
+    -- The Discord access token, encrypted if the database has a token key
+    token TEXT NOT NULL,
+    expiration_date TEXT NOT NULL,
+    avatar_url TEXT DEFAULT 'https://cdn.discordapp.com/embed/avatars/1.png',
+    -- The GitHub account the user has linked, so they can be credited as a co-author on commits
+    github_id BIGINT UNIQUE,
+    github_login TEXT,
+    refresh_token TEXT,
+    -- Service accounts are for bots and scheduled jobs, they authenticate only with API tokens
+    service_account BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE groups (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE group_membership (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    group_id BIGINT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    -- NULL if the membership doesn't expire
+    expires_at TEXT
+);
+
+CREATE TABLE group_permissions (
+    group_id BIGINT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    permission TEXT NOT NULL,
+    -- NULL if the permission doesn't expire
+    expires_at TEXT
+);
+
+-- The admin group, which is group 1
+INSERT INTO groups (name) VALUES ('Admin');
+INSERT INTO group_permissions (group_id, permission) VALUES
+    (1, 'ManageContent'),
+    (1, 'ManageUsers'),
+    (1, 'ManageBranches'),
+    (1, 'ManageAssets'),
+    (1, 'ReadContent'),
+    (1, 'ManageRepo');
+
+-- Owners can be assigned to a single document or a whole directory (a path ending in `/`).
+-- Exactly one of user_id or group_id is set for each row.
+CREATE TABLE doc_owners (
+    path TEXT NOT NULL,
+    user_id BIGINT REFERENCES users(id) ON DELETE CASCADE,
+    group_id BIGINT REFERENCES groups(id) ON DELETE CASCADE,
+    CHECK ((user_id IS NULL) != (group_id IS NULL))
+);
+
+CREATE TABLE editing_tickets (
+    id TEXT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    base_commit TEXT NOT NULL,
+    blob_sha TEXT NOT NULL,
+    expiration_date TEXT NOT NULL
+);
+
+CREATE TABLE webhook_events (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    delivery_id TEXT,
+    payload TEXT NOT NULL,
+    -- `pending`, `completed`, or `failed` once every attempt has been used up
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts BIGINT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    received_at TEXT NOT NULL,
+    next_attempt_at TEXT NOT NULL,
+    completed_at TEXT
+);
+
+CREATE INDEX webhook_events_due ON webhook_events (status, next_attempt_at);
+
+CREATE TABLE hyde_prs (
+    number BIGINT PRIMARY KEY,
+    title TEXT NOT NULL,
+    author_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL,
+    merged_at TEXT
+);
+
+CREATE TABLE hyde_pr_docs (
+    number BIGINT NOT NULL REFERENCES hyde_prs(number) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    PRIMARY KEY (number, path)
+);
+
+CREATE INDEX hyde_prs_merged_at ON hyde_prs (merged_at);
+
+CREATE TABLE group_admin_scopes (
+    group_id BIGINT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    managed_group_id BIGINT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, managed_group_id)
+);
+
+CREATE TABLE telemetry (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    source TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    value BIGINT NOT NULL,
+    recorded_at TEXT NOT NULL
+);
+
+CREATE INDEX telemetry_recorded_at ON telemetry (recorded_at);
+
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL,
+    expiration_date TEXT NOT NULL,
+    provider TEXT,
+    last_used_at TEXT,
+    ip_address TEXT,
+    user_agent TEXT
+);
+
+CREATE INDEX sessions_user_id ON sessions (user_id);
+
+CREATE TABLE user_identities (
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    PRIMARY KEY (provider, subject),
+    UNIQUE (user_id, provider)
+);
+
+CREATE TABLE api_tokens (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- Hex encoded SHA-256 of the token
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL,
+    expiration_date TEXT,
+    last_used_at TEXT
+);
+
+CREATE TABLE device_codes (
+    -- Hex encoded SHA-256 of the device code
+    device_code_hash TEXT PRIMARY KEY,
+    user_code TEXT NOT NULL UNIQUE,
+    client_name TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expiration_date TEXT NOT NULL,
+    last_polled_at TEXT,
+    user_id BIGINT REFERENCES users(id) ON DELETE CASCADE,
+    denied BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE audit_log (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    category TEXT NOT NULL,
+    action TEXT NOT NULL,
+    user_id BIGINT,
+    username TEXT,
+    ip_address TEXT,
+    detail TEXT NOT NULL
+);
+
+CREATE INDEX audit_log_category ON audit_log (category, created_at);
+
+CREATE TABLE totp_secrets (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    -- The base32 encoded secret, encrypted like users' OAuth tokens
+    secret TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TEXT NOT NULL,
+    last_used_step BIGINT
+);
+
+CREATE TABLE group_branch_rules (
+    group_id BIGINT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    pattern TEXT NOT NULL,
+    PRIMARY KEY (group_id, pattern)
+);
+
+CREATE TABLE permission_denies (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id BIGINT REFERENCES users(id) ON DELETE CASCADE,
+    group_id BIGINT REFERENCES groups(id) ON DELETE CASCADE,
+    permission TEXT NOT NULL,
+    expires_at TEXT,
+    created_at TEXT NOT NULL,
+    CHECK ((user_id IS NULL) != (group_id IS NULL))
+);
+
+CREATE TABLE group_owners (
+    group_id BIGINT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, user_id)
+);
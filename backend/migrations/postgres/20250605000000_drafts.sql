@@ -0,0 +1,10 @@
+-- Work in progress the editor autosaves, so it can be recovered after a crash without
+-- committing anything. Each user has at most one draft per document.
+CREATE TABLE drafts (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    doc_path TEXT NOT NULL,
+    contents TEXT NOT NULL,
+    base_commit TEXT,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, doc_path)
+);
@@ -0,0 +1,10 @@
+-- Who last edited each document and when, so the document browser can show it. Updated when
+-- documents are saved through Hyde, and removed when they're deleted.
+CREATE TABLE doc_meta (
+    path TEXT PRIMARY KEY,
+    last_editor_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    last_editor_name TEXT NOT NULL,
+    last_edited_at TEXT NOT NULL,
+    edit_count BIGINT NOT NULL DEFAULT 0,
+    description TEXT
+);
@@ -0,0 +1,8 @@
+-- Editor settings like the theme and editor mode, stored on the server so they follow the user
+-- between browsers. Values are JSON.
+CREATE TABLE user_preferences (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (user_id, key)
+);
@@ -0,0 +1,3 @@
+-- Deleting a user deactivates them instead, so the history they're attributed in keeps their
+-- name. NULL for active users.
+ALTER TABLE users ADD COLUMN deactivated_at TEXT;
@@ -0,0 +1,7 @@
+-- `users.expiration_date` is read as a typed datetime now. Dates that don't parse, like the empty
+-- ones of users who never signed in with Discord, are replaced with the epoch.
+ALTER TABLE users ALTER COLUMN expiration_date TYPE TIMESTAMPTZ USING
+    CASE WHEN expiration_date ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}'
+        THEN expiration_date::TIMESTAMPTZ
+        ELSE 'epoch'
+    END;
@@ -0,0 +1,11 @@
+-- Full-text index over the documents, derived from the repository. Hyde rebuilds it on startup.
+CREATE TABLE doc_search (
+    path TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    document TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', title), 'A') || setweight(to_tsvector('english', body), 'B')
+    ) STORED
+);
+
+CREATE INDEX doc_search_document ON doc_search USING GIN (document);
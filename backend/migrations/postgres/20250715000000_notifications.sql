@@ -0,0 +1,11 @@
+-- In-app notifications, e.g. a review was requested or a pull request was merged
+CREATE TABLE notifications (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    read_at TEXT
+);
+
+CREATE INDEX notifications_user_id ON notifications (user_id, read_at);
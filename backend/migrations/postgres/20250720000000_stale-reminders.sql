@@ -0,0 +1,7 @@
+-- When each document owner was last reminded that a document hasn't changed in a while
+CREATE TABLE stale_reminders (
+    path TEXT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reminded_at TEXT NOT NULL,
+    PRIMARY KEY (path, user_id)
+);
@@ -0,0 +1,4 @@
+-- Tickets are now tied to the branch the document was read from, which tickets issued before
+-- didn't record, so they're dropped and have to be requested again
+DELETE FROM editing_tickets;
+ALTER TABLE editing_tickets ADD COLUMN branch TEXT NOT NULL DEFAULT '';
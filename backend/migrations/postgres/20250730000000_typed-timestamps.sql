@@ -0,0 +1,159 @@
+-- The remaining timestamps are read as typed datetimes too. Times that don't parse are replaced
+-- with the epoch, so that expirations read as expired rather than as never expiring.
+ALTER TABLE users
+    ALTER COLUMN deactivated_at TYPE TIMESTAMPTZ USING
+        CASE WHEN deactivated_at IS NULL THEN NULL
+            WHEN deactivated_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN deactivated_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE group_membership
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING
+        CASE WHEN expires_at IS NULL THEN NULL
+            WHEN expires_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN expires_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE group_permissions
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING
+        CASE WHEN expires_at IS NULL THEN NULL
+            WHEN expires_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN expires_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE editing_tickets
+    ALTER COLUMN expiration_date TYPE TIMESTAMPTZ USING
+        CASE WHEN expiration_date IS NULL THEN NULL
+            WHEN expiration_date ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN expiration_date::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE webhook_events
+    ALTER COLUMN received_at TYPE TIMESTAMPTZ USING
+        CASE WHEN received_at IS NULL THEN NULL
+            WHEN received_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN received_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN next_attempt_at TYPE TIMESTAMPTZ USING
+        CASE WHEN next_attempt_at IS NULL THEN NULL
+            WHEN next_attempt_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN next_attempt_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN completed_at TYPE TIMESTAMPTZ USING
+        CASE WHEN completed_at IS NULL THEN NULL
+            WHEN completed_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN completed_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE hyde_prs
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING
+        CASE WHEN created_at IS NULL THEN NULL
+            WHEN created_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN created_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN merged_at TYPE TIMESTAMPTZ USING
+        CASE WHEN merged_at IS NULL THEN NULL
+            WHEN merged_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN merged_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE telemetry
+    ALTER COLUMN recorded_at TYPE TIMESTAMPTZ USING
+        CASE WHEN recorded_at IS NULL THEN NULL
+            WHEN recorded_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN recorded_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE sessions
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING
+        CASE WHEN created_at IS NULL THEN NULL
+            WHEN created_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN created_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN expiration_date TYPE TIMESTAMPTZ USING
+        CASE WHEN expiration_date IS NULL THEN NULL
+            WHEN expiration_date ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN expiration_date::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN last_used_at TYPE TIMESTAMPTZ USING
+        CASE WHEN last_used_at IS NULL THEN NULL
+            WHEN last_used_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN last_used_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE api_tokens
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING
+        CASE WHEN created_at IS NULL THEN NULL
+            WHEN created_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN created_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN expiration_date TYPE TIMESTAMPTZ USING
+        CASE WHEN expiration_date IS NULL THEN NULL
+            WHEN expiration_date ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN expiration_date::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN last_used_at TYPE TIMESTAMPTZ USING
+        CASE WHEN last_used_at IS NULL THEN NULL
+            WHEN last_used_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN last_used_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE device_codes
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING
+        CASE WHEN created_at IS NULL THEN NULL
+            WHEN created_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN created_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN expiration_date TYPE TIMESTAMPTZ USING
+        CASE WHEN expiration_date IS NULL THEN NULL
+            WHEN expiration_date ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN expiration_date::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN last_polled_at TYPE TIMESTAMPTZ USING
+        CASE WHEN last_polled_at IS NULL THEN NULL
+            WHEN last_polled_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN last_polled_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE audit_log
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING
+        CASE WHEN created_at IS NULL THEN NULL
+            WHEN created_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN created_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE totp_secrets
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING
+        CASE WHEN created_at IS NULL THEN NULL
+            WHEN created_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN created_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE permission_denies
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING
+        CASE WHEN expires_at IS NULL THEN NULL
+            WHEN expires_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN expires_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING
+        CASE WHEN created_at IS NULL THEN NULL
+            WHEN created_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN created_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE drafts
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING
+        CASE WHEN updated_at IS NULL THEN NULL
+            WHEN updated_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN updated_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE doc_meta
+    ALTER COLUMN last_edited_at TYPE TIMESTAMPTZ USING
+        CASE WHEN last_edited_at IS NULL THEN NULL
+            WHEN last_edited_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN last_edited_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE notifications
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING
+        CASE WHEN created_at IS NULL THEN NULL
+            WHEN created_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN created_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END,
+    ALTER COLUMN read_at TYPE TIMESTAMPTZ USING
+        CASE WHEN read_at IS NULL THEN NULL
+            WHEN read_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN read_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
+ALTER TABLE stale_reminders
+    ALTER COLUMN reminded_at TYPE TIMESTAMPTZ USING
+        CASE WHEN reminded_at IS NULL THEN NULL
+            WHEN reminded_at ~ '^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}' THEN reminded_at::TIMESTAMPTZ
+            ELSE 'epoch'
+        END;
@@ -0,0 +1,4 @@
+-- Session ids are as good as a password, so only their SHA-256 hash is stored, hex encoded like
+-- API tokens. SQLite can't hash in SQL, so its sessions are hashed when Hyde starts instead, see
+-- `Database::hash_stored_sessions`. Hashes are 64 characters long, unhashed ids are shorter.
+UPDATE sessions SET id = encode(sha256(convert_to(id, 'UTF8')), 'hex') WHERE LENGTH(id) <> 64;
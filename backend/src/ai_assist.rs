@@ -0,0 +1,71 @@
+//! Forwarding document content to an OpenAI-compatible LLM endpoint for suggestions
+//! (summaries, rewrites, et cetera), see `[ai_assist]` in the config
+
+use crate::app_conf::AiAssist;
+use color_eyre::eyre::{bail, ContextCompat};
+use color_eyre::Result;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Fill in a prompt template. `{path}` is replaced with the path of the document,
+/// and `{content}` with the selected content.
+#[allow(clippy::literal_string_with_formatting_args)]
+pub fn render_prompt(template: &str, path: &str, content: &str) -> String {
+    // `{content}` is replaced last so placeholders inside of the document are left alone
+    template
+        .replace("{path}", path)
+        .replace("{content}", content)
+}
+
+/// Send `prompt` to the configured chat completions endpoint, returning the model's reply.
+///
+/// # Errors
+/// This function returns an error if the request fails, or the response isn't a valid
+/// chat completion.
+pub async fn complete(client: &Client, conf: &AiAssist, prompt: String) -> Result<String> {
+    let mut request = client
+        .post(format!(
+            "{}/chat/completions",
+            conf.endpoint.trim_end_matches('/')
+        ))
+        .header("User-Agent", "Hyde")
+        .timeout(Duration::from_secs(60))
+        .json(&json!({
+            "model": conf.model,
+            "messages": [{ "role": "user", "content": prompt }],
+        }));
+    if let Some(api_key) = &conf.api_key {
+        request = request.bearer_auth(api_key);
+    }
+    let response = request.send().await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let response_text = response.text().await?;
+        bail!(
+            "AI assist request failed: {}, Response: {}",
+            status,
+            response_text
+        );
+    }
+
+    let body: Value = response.json().await?;
+    body["choices"][0]["message"]["content"]
+        .as_str()
+        .map(ToString::to_string)
+        .wrap_err("AI assist response is missing `choices[0].message.content`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_rendering() {
+        assert_eq!(
+            render_prompt("Summarize {path}:\n{content}", "foo.md", "Use {path} here"),
+            "Summarize foo.md:\nUse {path} here"
+        );
+    }
+}
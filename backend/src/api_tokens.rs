@@ -0,0 +1,53 @@
+//! Tokens for scripts and CI pipelines, which can't sign in through a browser. A token acts as
+//! the user who created it, and is sent in an `Authorization: Bearer` header.
+//!
+//! Tokens are random, so they're stored as a plain SHA-256 hash: a leaked database doesn't
+//! leak usable tokens, and looking one up doesn't need a slow password hash.
+
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// Every token starts with this, so leaked tokens are easy to search for
+pub const TOKEN_PREFIX: &str = "hyde_";
+/// How many random characters follow the prefix
+const TOKEN_LENGTH: usize = 40;
+/// The longest a token can be valid for
+pub const MAX_TOKEN_LIFETIME_DAYS: u32 = 365;
+
+/// Create a new random token
+pub fn generate() -> String {
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    format!("{TOKEN_PREFIX}{secret}")
+}
+
+/// The hash a token is stored and looked up by, hex encoded
+pub fn hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let token = generate();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + TOKEN_LENGTH);
+        assert_ne!(token, generate(), "generate: tokens should be random");
+        assert_eq!(
+            hash("hyde_test"),
+            "7a85bebf1c37dce14a5d267dcdf716600be9abae40f2b76ba35cf62b31c88643"
+        );
+    }
+}
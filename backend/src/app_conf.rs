@@ -1,19 +1,48 @@
+use crate::identity::IdentityProvider;
+use crate::perms::Permission;
 use color_eyre::eyre::ContextCompat;
 use color_eyre::Result;
-use serde::Deserialize;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsStr;
 use std::fmt::Debug;
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fs, path::Path};
-use tracing::{info, trace};
+use tracing::{info, trace, warn};
 
 #[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct AppConf {
     pub files: Files,
     pub discord: Discord,
     pub oauth: OAuth,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
     pub database: Database,
+    #[serde(default)]
+    pub pull_requests: PullRequests,
+    /// The AI assist endpoint is only enabled if this section is present
+    #[serde(default)]
+    pub ai_assist: Option<AiAssist>,
+    #[serde(default)]
+    pub policy: Policy,
+    #[serde(default)]
+    pub commit: Commit,
+    #[serde(default)]
+    pub git: Git,
+    #[serde(default)]
+    pub plugins: Plugins,
+    #[serde(default)]
+    pub telemetry: Telemetry,
+    #[serde(default)]
+    pub replication: Replication,
+    /// Other wikis hosted by the same process, see [`Tenant`]
+    #[serde(default)]
+    pub tenants: Vec<Tenant>,
 }
 
 #[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
@@ -22,17 +51,206 @@ pub struct Files {
     pub docs_path: String,
     pub repo_path: String,
     pub repo_url: String,
+    /// The folder archived documents are moved into, relative to `docs_path`
+    #[serde(default = "default_archive_path")]
+    pub archive_path: String,
+    /// Whether EXIF (including GPS location) and other metadata is removed from uploaded images
+    #[serde(default = "default_true")]
+    pub strip_image_metadata: bool,
+    /// Remind the owners of documents that haven't changed in this many days, see
+    /// [`crate::stale_content`]. Off if unset.
+    #[serde(default)]
+    pub stale_after_days: Option<u32>,
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+fn default_archive_path() -> String {
+    "archive/".to_string()
+}
+
+/// The identity Hyde commits as. Documents saved through Hyde are still authored by Hyde, this
+/// only changes the name and email on the commits.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    #[serde(default = "default_commit_name")]
+    pub name: String,
+    #[serde(default = "default_commit_email")]
+    pub email: String,
+}
+
+impl Default for Commit {
+    fn default() -> Self {
+        Self {
+            name: default_commit_name(),
+            email: default_commit_email(),
+        }
+    }
+}
+
+impl Commit {
+    /// A signature for this identity at the current time
+    ///
+    /// # Errors
+    /// This function returns an error if the name or email isn't valid in a git signature.
+    pub fn signature(&self) -> Result<git2::Signature<'static>> {
+        Ok(git2::Signature::now(&self.name, &self.email)?)
+    }
+}
+
+/// The identity of commits Hyde makes on its own, like merges when pulling in changes pushed to
+/// GitHub, as opposed to saves made by users
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Git {
+    /// Defaults to `commit.name`
+    #[serde(default)]
+    pub bot_name: Option<String>,
+    /// Defaults to `commit.email`
+    #[serde(default)]
+    pub bot_email: Option<String>,
+}
+
+fn default_commit_name() -> String {
+    "Hyde".to_string()
+}
+
+fn default_commit_email() -> String {
+    "hyde@localhost".to_string()
 }
 
 #[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct Discord {
+    /// No longer used, usernames can be changed and taken by someone else. See `admin_ids`.
+    #[serde(default)]
     pub admin_username: String,
+    /// No longer used, usernames can be changed and taken by someone else. See `admin_ids`.
+    #[serde(default)]
+    pub admin_usernames: Vec<String>,
+    /// The Discord user ids of the users who are added to the admin group when they sign in
+    #[serde(default)]
+    pub admin_ids: Vec<String>,
+    /// The server whose roles group memberships are synced with, see [`crate::discord_roles`]
+    #[serde(default)]
+    pub guild_id: Option<String>,
+    /// Group names by the ID of the Discord role that grants them
+    #[serde(default)]
+    pub role_groups: BTreeMap<String, String>,
+}
+
+impl Discord {
+    /// Whether the Discord account `id` is one of the admins from the config. Admins are only
+    /// matched by id, never by username.
+    pub fn is_admin(&self, id: &str) -> bool {
+        self.admin_ids.iter().any(|admin| admin == id)
+    }
+
+    /// Whether group memberships are synced with Discord roles
+    pub fn syncs_roles(&self) -> bool {
+        self.guild_id.is_some() && !self.role_groups.is_empty()
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct OAuth {
     pub discord: DiscordOAuth,
     pub github: GitHubOAuth,
+    /// Only needed if OpenID Connect is an enabled sign in provider
+    #[serde(default)]
+    pub oidc: Option<OidcOAuth>,
+}
+
+/// How users sign in, see [`crate::identity`]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Auth {
+    /// The providers users can sign in with and link to their account
+    #[serde(default = "default_auth_providers")]
+    pub providers: Vec<IdentityProvider>,
+    /// How many requests each IP address can make to the sign in and token endpoints per
+    /// minute, 0 for no limit
+    #[serde(default = "default_auth_requests_per_minute")]
+    pub requests_per_minute: usize,
+    /// The networks (in CIDR notation) admin endpoints can be reached from, see
+    /// [`crate::ip_allowlist`]. Reachable from anywhere if empty.
+    #[serde(default)]
+    pub admin_allowlist: Vec<String>,
+    /// The reverse proxies (in CIDR notation) whose `X-Forwarded-For` and `X-Real-IP` headers
+    /// are trusted, see [`crate::ip_allowlist::resolve_client_ip`]. Without any, the address
+    /// connected to Hyde is used.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Let anyone read documents and assets without signing in. Changes still need a signed in
+    /// user with the right permissions.
+    #[serde(default)]
+    pub public_read: bool,
+    /// Only let users with the `ReadContent` permission read documents and assets, for wikis
+    /// that shouldn't be readable by everyone who can sign in. Takes precedence over
+    /// `public_read`.
+    #[serde(default)]
+    pub require_read_permission: bool,
+    /// Permissions groups can be given on top of the built-in ones, which Hyde doesn't use
+    /// itself, so deployments can gate their own features with them
+    #[serde(default)]
+    pub custom_permissions: Vec<String>,
+    /// End sessions that haven't been used for this many seconds
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    /// End sessions this many seconds after signing in, even if they could be renewed
+    #[serde(default)]
+    pub max_session_age_secs: Option<u64>,
+    /// The URL users reach Hyde at, like `https://hyde.example.org`. Identity providers send
+    /// users back to it after they sign in. See [`AppConf::public_url`].
+    #[serde(default)]
+    pub public_url: Option<String>,
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self {
+            providers: default_auth_providers(),
+            requests_per_minute: default_auth_requests_per_minute(),
+            admin_allowlist: Vec::new(),
+            trusted_proxies: Vec::new(),
+            public_read: false,
+            require_read_permission: false,
+            custom_permissions: Vec::new(),
+            idle_timeout_secs: None,
+            max_session_age_secs: None,
+            public_url: None,
+        }
+    }
+}
+
+const fn default_auth_requests_per_minute() -> usize {
+    20
+}
+
+fn default_auth_providers() -> Vec<IdentityProvider> {
+    vec![IdentityProvider::Discord]
+}
+
+impl Auth {
+    pub fn is_enabled(&self, provider: IdentityProvider) -> bool {
+        self.providers.contains(&provider)
+    }
+}
+
+/// An OpenID Connect provider users can sign in with, using the authorization code flow
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OidcOAuth {
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    /// Space separated
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: String,
+}
+
+fn default_oidc_scopes() -> String {
+    "openid profile".to_string()
 }
 
 #[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
@@ -41,18 +259,354 @@ pub struct DiscordOAuth {
     pub secret: String,
     pub url: String,
     pub token_url: String,
+    /// Revoke a user's Discord token when they log out, rather than only forgetting it
+    #[serde(default)]
+    pub revoke_on_logout: bool,
+    /// Defaults to Discord's revocation endpoint
+    #[serde(default)]
+    pub revoke_url: Option<String>,
+    /// The scopes users are asked for, replacing the ones in `url` if set. `identify` is always
+    /// asked for.
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct GitHubOAuth {
     pub client_id: String,
-    // Uncomment this if needed
-    // pub secret: String,
+    /// The installation of the GitHub App to use. If unset, the installation for
+    /// `files.repo_url` is looked up.
+    #[serde(default)]
+    pub installation_id: Option<u64>,
+    /// Where the GitHub App's private key is read from. Ignored if the key is provided
+    /// through the `HYDE_GITHUB_PRIVATE_KEY` environment variable.
+    #[serde(default = "default_private_key_path")]
+    pub private_key_path: String,
+    /// Record GitHub API requests, or replay previously recorded ones
+    #[serde(default)]
+    pub fixtures: Option<GitHubFixtures>,
+    /// The GitHub App's client secret, needed for users to link their GitHub accounts. Account
+    /// linking is disabled if this isn't set.
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    /// If set, webhook events are only accepted with this token, either as the last segment of
+    /// the webhook URL or in the `X-Hyde-Webhook-Token` header
+    #[serde(default)]
+    pub webhook_token: Option<String>,
+    /// The secret set on the GitHub App's webhook. If set, webhook events are only accepted with
+    /// a valid `X-Hub-Signature-256` header.
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+}
+
+pub fn default_private_key_path() -> String {
+    "hyde-data/key.pem".to_string()
 }
 
 #[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitHubFixtures {
+    pub mode: FixtureMode,
+    /// The JSON file fixtures are stored in
+    pub path: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FixtureMode {
+    /// Send requests to GitHub, and save them along with their responses
+    #[default]
+    Record,
+    /// Don't send anything to GitHub, serve the saved responses instead
+    Replay,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Database {
+    /// A `postgres://` url, or the SQLite database to use, see [`crate::db::DatabaseUrl`]
+    #[serde(default = "default_database_url")]
     pub url: String,
+    /// Where the key OAuth tokens are encrypted with is kept, see [`crate::token_crypto`]. It's
+    /// created if it doesn't exist. Ignored if the key is provided through the
+    /// `HYDE_TOKEN_KEY` environment variable.
+    #[serde(default = "default_token_key_path")]
+    pub token_key_path: String,
+    /// The passphrase the SQLite database is encrypted with using SQLCipher, if it's encrypted.
+    /// Needs Hyde to be built with the `sqlcipher` feature. Ignored if the key is provided
+    /// through the `HYDE_DATABASE_KEY` environment variable.
+    pub encryption_key: Option<String>,
+    /// How SQLite journals writes, see [`JournalMode`]
+    #[serde(default)]
+    pub journal_mode: JournalMode,
+    /// How often SQLite waits for writes to reach the disk, see [`Synchronous`]
+    #[serde(default)]
+    pub synchronous: Synchronous,
+    /// How long a query waits for another connection's write to finish before failing
+    #[serde(default = "default_busy_timeout_ms")]
+    pub busy_timeout_ms: u64,
+    /// How often expired and orphaned rows are deleted, see [`crate::cleanup`]
+    #[serde(default = "default_cleanup_interval_secs")]
+    pub cleanup_interval_secs: u64,
+    /// How many days drafts that haven't been saved are kept for
+    #[serde(default = "default_draft_retention_days")]
+    pub draft_retention_days: u32,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self {
+            url: default_database_url(),
+            token_key_path: default_token_key_path(),
+            encryption_key: None,
+            journal_mode: JournalMode::default(),
+            synchronous: Synchronous::default(),
+            busy_timeout_ms: default_busy_timeout_ms(),
+            cleanup_interval_secs: default_cleanup_interval_secs(),
+            draft_retention_days: default_draft_retention_days(),
+        }
+    }
+}
+
+impl Database {
+    /// The key the database is encrypted with, from [`crate::db::DATABASE_KEY_ENV_VAR`] if
+    /// it's set, otherwise from `encryption_key`. Empty keys count as unset.
+    pub fn encryption_key(&self) -> Option<String> {
+        std::env::var(crate::db::DATABASE_KEY_ENV_VAR)
+            .ok()
+            .or_else(|| self.encryption_key.clone())
+            .filter(|key| !key.is_empty())
+    }
+
+    /// How connections to a SQLite database are opened, see [`crate::db::SqliteSettings`]
+    pub fn sqlite_settings(&self) -> crate::db::SqliteSettings {
+        crate::db::SqliteSettings {
+            key: self.encryption_key(),
+            journal_mode: self.journal_mode,
+            synchronous: self.synchronous,
+            busy_timeout: Duration::from_millis(self.busy_timeout_ms),
+        }
+    }
+}
+
+/// The SQLite [journal mode](https://www.sqlite.org/pragma.html#pragma_journal_mode)
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    /// Readers don't block the writer and the writer doesn't block readers, so pulls and edits
+    /// don't lock each other out
+    #[default]
+    Wal,
+    Off,
+}
+
+/// The SQLite [synchronous setting](https://www.sqlite.org/pragma.html#pragma_synchronous)
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    /// Safe from corruption in WAL mode, the last writes can be lost if the host loses power
+    #[default]
+    Normal,
+    Full,
+    Extra,
+}
+
+const fn default_busy_timeout_ms() -> u64 {
+    5000
+}
+
+const fn default_cleanup_interval_secs() -> u64 {
+    60 * 10
+}
+
+const fn default_draft_retention_days() -> u32 {
+    30
+}
+
+pub fn default_database_url() -> String {
+    format!("sqlite://{}", crate::db::DATABASE_PATH)
+}
+
+pub fn default_token_key_path() -> String {
+    "hyde-data/token.key".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PullRequests {
+    /// The team slug (without the `@org/` prefix) asked to review pull requests
+    /// when `CODEOWNERS` doesn't assign an owner to any of the changed files
+    pub default_reviewer_team: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AiAssist {
+    /// The base URL of an OpenAI-compatible API, e.g. `https://api.openai.com/v1`
+    pub endpoint: String,
+    pub model: String,
+    /// Sent as a bearer token, if set
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// How many requests each user may make per hour
+    #[serde(default = "default_ai_requests_per_hour")]
+    pub requests_per_hour: usize,
+    /// The maximum number of characters of document content sent with a request
+    #[serde(default = "default_ai_max_content_length")]
+    pub max_content_length: usize,
+    /// Prompt templates users can pick from, by name. These are the only prompts that can be sent.
+    pub prompts: HashMap<String, String>,
+}
+
+const fn default_ai_requests_per_hour() -> usize {
+    20
+}
+
+const fn default_ai_max_content_length() -> usize {
+    20_000
+}
+
+/// What happens when a content policy check fails
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    /// The check isn't run
+    #[default]
+    Off,
+    /// The save goes through, but the user is warned
+    Warn,
+    /// The save is rejected
+    Block,
+}
+
+/// Checks run on content before it's committed, see [`crate::policy`]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    /// Credentials (API keys, tokens, private keys) pasted into a document
+    #[serde(default = "default_block")]
+    pub secrets: PolicyAction,
+    /// Words from `profanity_words` on public-facing pages
+    #[serde(default)]
+    pub profanity: PolicyAction,
+    #[serde(default)]
+    pub profanity_words: Vec<String>,
+    /// Folders (relative to `docs_path`) containing public-facing pages. If empty, every
+    /// page is considered public-facing.
+    #[serde(default)]
+    pub public_paths: Vec<String>,
+    /// Commits changing more files than `max_files_per_commit`
+    #[serde(default = "default_block")]
+    pub max_files: PolicyAction,
+    #[serde(default)]
+    pub max_files_per_commit: Option<usize>,
+    /// Front matter that would break the Jekyll build, or is missing `required_front_matter`
+    #[serde(default = "default_block")]
+    pub front_matter: PolicyAction,
+    /// Keys every document's front matter must have, e.g. `title` and `layout`
+    #[serde(default)]
+    pub required_front_matter: Vec<String>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            secrets: PolicyAction::Block,
+            profanity: PolicyAction::Off,
+            profanity_words: Vec::new(),
+            public_paths: Vec::new(),
+            max_files: PolicyAction::Block,
+            max_files_per_commit: None,
+            front_matter: PolicyAction::Block,
+            required_front_matter: Vec::new(),
+        }
+    }
+}
+
+const fn default_block() -> PolicyAction {
+    PolicyAction::Block
+}
+
+/// Which plugins are run, see [`crate::plugins`]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plugins {
+    /// The names of the enabled plugins, hooks are run in this order
+    #[serde(default)]
+    pub enabled: Vec<String>,
+}
+
+/// Performance metrics reported by the editor, see [`crate::telemetry`]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Telemetry {
+    /// Whether metrics are collected, off unless the deployment opts in
+    #[serde(default)]
+    pub enabled: bool,
+    /// How many days metrics are kept for
+    #[serde(default = "default_telemetry_retention_days")]
+    pub retention_days: u32,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retention_days: default_telemetry_retention_days(),
+        }
+    }
+}
+
+const fn default_telemetry_retention_days() -> u32 {
+    30
+}
+
+/// Running a read-only standby copy of this wiki, see [`crate::replica`]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Replication {
+    /// The shared secret replicas download database snapshots with. Snapshots can't be
+    /// downloaded if this isn't set.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// If set, this instance is a read-only replica of the Hyde instance at this URL
+    #[serde(default)]
+    pub primary_url: Option<String>,
+    /// How often a replica pulls the repository and downloads a database snapshot
+    #[serde(default = "default_sync_interval_secs")]
+    pub sync_interval_secs: u64,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Self {
+            token: None,
+            primary_url: None,
+            sync_interval_secs: default_sync_interval_secs(),
+        }
+    }
+}
+
+const fn default_sync_interval_secs() -> u64 {
+    5 * 60
+}
+
+/// Another wiki hosted by the same process. Each tenant has its own config file (with its own
+/// repository, OAuth apps and asset folder), database, and GitHub App key in its data directory,
+/// and requests are routed to it by their `Host` header.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Tenant {
+    pub name: String,
+    /// Requests to these hostnames (without a port) are routed to this tenant
+    pub hostnames: Vec<String>,
+    /// Where the tenant's config and database are, defaults to `hyde-data/tenants/<name>/`
+    #[serde(default)]
+    pub data_dir: Option<String>,
+}
+
+impl Tenant {
+    pub fn data_dir(&self) -> String {
+        self.data_dir
+            .clone()
+            .unwrap_or_else(|| format!("hyde-data/tenants/{}/", self.name))
+    }
 }
 
 // Trait to validate fields in each struct
@@ -79,15 +633,42 @@ macro_rules! impl_validate {
 }
 
 impl_validate!(Files, asset_path, docs_path, repo_path, repo_url);
-impl_validate!(Discord, admin_username);
 impl_validate!(DiscordOAuth, client_id, secret, url, token_url);
 impl_validate!(GitHubOAuth, client_id);
+impl_validate!(
+    OidcOAuth,
+    client_id,
+    client_secret,
+    authorize_url,
+    token_url,
+    userinfo_url
+);
 impl_validate!(Database, url);
+impl_validate!(AiAssist, endpoint, model, prompts);
+impl_validate!(Tenant, name, hostnames);
+
+impl ValidateFields for Discord {
+    fn validate(&self, path: &str) -> Result<(), String> {
+        if self.admin_ids.is_empty() {
+            return Err(format!(
+                "Field '{}.admin_ids' is empty, admins are matched by Discord user id",
+                path
+            ));
+        }
+        if self.admin_ids.iter().any(String::is_empty) {
+            return Err(format!("Field '{}.admin_ids' contains an empty id", path));
+        }
+        Ok(())
+    }
+}
 
 impl ValidateFields for OAuth {
     fn validate(&self, path: &str) -> Result<(), String> {
         self.discord.validate(&format!("{}.discord", path))?;
         self.github.validate(&format!("{}.github", path))?;
+        if let Some(oidc) = &self.oidc {
+            oidc.validate(&format!("{}.oidc", path))?;
+        }
         Ok(())
     }
 }
@@ -98,10 +679,114 @@ impl ValidateFields for AppConf {
         self.discord.validate(&format!("{}.discord", path))?;
         self.oauth.validate(&format!("{}.oauth", path))?;
         self.database.validate(&format!("{}.database", path))?;
+        if !self.discord.role_groups.is_empty() && self.discord.guild_id.is_none() {
+            return Err(format!(
+                "Field '{}.discord.guild_id' is required when 'role_groups' is set",
+                path
+            ));
+        }
+        if self.auth.providers.is_empty() {
+            return Err(format!("Field '{}.auth.providers' is empty", path));
+        }
+        if self.auth.is_enabled(IdentityProvider::Oidc) && self.oauth.oidc.is_none() {
+            return Err(format!(
+                "Section '{}.oauth.oidc' is required when OpenID Connect sign in is enabled",
+                path
+            ));
+        }
+        if self.auth.is_enabled(IdentityProvider::GitHub)
+            && self.oauth.github.client_secret.is_none()
+        {
+            return Err(format!(
+                "Field '{}.oauth.github.client_secret' is required when GitHub sign in is enabled",
+                path
+            ));
+        }
+        for (field, networks) in [
+            ("admin_allowlist", &self.auth.admin_allowlist),
+            ("trusted_proxies", &self.auth.trusted_proxies),
+        ] {
+            for network in networks {
+                if network.parse::<IpNet>().is_err() && network.parse::<IpAddr>().is_err() {
+                    return Err(format!(
+                        "Field '{}.auth.{field}' contains {network:?}, which isn't an IP address or CIDR range",
+                        path
+                    ));
+                }
+            }
+        }
+        for permission in &self.auth.custom_permissions {
+            if !matches!(Permission::from(permission.as_str()), Permission::Custom(_))
+                || permission.trim().is_empty()
+            {
+                return Err(format!(
+                    "Field '{}.auth.custom_permissions' contains {permission:?}, which is empty or the name of a built-in permission",
+                    path
+                ));
+            }
+        }
+        if let Some(ai_assist) = &self.ai_assist {
+            ai_assist.validate(&format!("{}.ai_assist", path))?;
+        }
+        if self.replication.primary_url.is_some() && self.replication.token.is_none() {
+            return Err(format!(
+                "Field '{}.replication.token' is required when 'primary_url' is set",
+                path
+            ));
+        }
+        if (self.replication.primary_url.is_some() || self.replication.token.is_some())
+            && crate::db::Database::is_postgres_url(&self.database.url)
+        {
+            return Err(format!(
+                "Section '{}.replication' can't be used with PostgreSQL, replicas copy SQLite databases",
+                path
+            ));
+        }
+        if self.database.cleanup_interval_secs == 0 {
+            return Err(format!(
+                "Field '{}.database.cleanup_interval_secs' must be at least 1",
+                path
+            ));
+        }
+        for (i, tenant) in self.tenants.iter().enumerate() {
+            tenant.validate(&format!("{}.tenants[{}]", path, i))?;
+        }
         Ok(())
     }
 }
 impl AppConf {
+    /// The identity commits Hyde makes on its own use, see [`Git`]
+    pub fn bot_identity(&self) -> Commit {
+        Commit {
+            name: self
+                .git
+                .bot_name
+                .clone()
+                .unwrap_or_else(|| self.commit.name.clone()),
+            email: self
+                .git
+                .bot_email
+                .clone()
+                .unwrap_or_else(|| self.commit.email.clone()),
+        }
+    }
+
+    /// The URL users reach Hyde at, without a trailing slash. Defaults to where the
+    /// `redirect_uri` in `oauth.discord.url` points, which Discord includes in the URLs it
+    /// generates. Never taken from the request, so providers can't be made to send users and
+    /// their codes to another host.
+    pub fn public_url(&self) -> Option<String> {
+        if let Some(url) = &self.auth.public_url {
+            return Some(url.trim_end_matches('/').to_string());
+        }
+        let discord_url = reqwest::Url::parse(&self.oauth.discord.url).ok()?;
+        let redirect_uri = discord_url
+            .query_pairs()
+            .find_map(|(key, value)| (key == "redirect_uri").then_some(value))?;
+        let origin = reqwest::Url::parse(&redirect_uri).ok()?.origin();
+        origin.is_tuple().then(|| origin.ascii_serialization())
+    }
+
     /// Deserializes the config located at `path`.
     ///
     /// If a file is passed, it will load that file. If a directory is passed,
@@ -119,13 +804,19 @@ impl AppConf {
         trace!("Loaded config: {:#?}", config);
 
         config.validate("config").expect("Invalid config");
+        if !config.discord.admin_username.is_empty() || !config.discord.admin_usernames.is_empty() {
+            warn!(
+                "`discord.admin_usernames` is ignored, admins are only matched by \
+                 `discord.admin_ids`"
+            );
+        }
 
         Ok(Arc::new(config))
     }
 }
 
 /// Returns the first toml config file in the provided directory, relative to the executable.
-fn locate_config_file<P: AsRef<Path> + Copy + Debug>(path: P) -> Result<Option<PathBuf>> {
+pub fn locate_config_file<P: AsRef<Path> + Copy + Debug>(path: P) -> Result<Option<PathBuf>> {
     info!("Searching directory {path:?} for a config file");
     // Search the directory for a toml file
     let dir = fs::read_dir(path)?;
@@ -0,0 +1,107 @@
+//! Recording security relevant events in the database, so that moderation disputes can be
+//! settled after the fact. Events are also logged to the `audit` tracing target.
+
+use crate::db::{AuditEntry, Database, User};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditCategory {
+    /// Signing in and out, token refreshes, and requests turned away
+    Auth,
+    /// Groups being created and deleted, and changes to group members and permissions
+    Perms,
+    /// Documents and assets being saved, deleted and archived, and documents being sent to the
+    /// AI assist endpoint
+    Content,
+    /// Users and their sessions being created and ended by admins
+    Users,
+    /// Maintenance of the local repository, like recloning it
+    Repo,
+    /// Backups of the database being taken, and replicas being promoted
+    Database,
+}
+
+impl AuditCategory {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Auth => "auth",
+            Self::Perms => "perms",
+            Self::Content => "content",
+            Self::Users => "users",
+            Self::Repo => "repo",
+            Self::Database => "database",
+        }
+    }
+}
+
+/// An entry for an event that happened just now, to be recorded with [`record_entry`] once
+/// its target and data are set
+pub fn entry(
+    category: AuditCategory,
+    action: &str,
+    user: Option<&User>,
+    ip_address: Option<String>,
+    detail: impl Into<String>,
+) -> AuditEntry {
+    AuditEntry {
+        id: 0,
+        created_at: Utc::now(),
+        category: category.as_str().to_string(),
+        action: action.to_string(),
+        user_id: user.map(|u| u.id),
+        username: user.map(|u| u.username.clone()),
+        ip_address,
+        detail: detail.into(),
+        target: None,
+        data: None,
+    }
+}
+
+impl AuditEntry {
+    /// Set what the entry is about, e.g. a document path or `user:4`
+    #[must_use]
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Attach structured details about the event, which should be a JSON object
+    #[must_use]
+    pub fn with_data(mut self, data: &serde_json::Value) -> Self {
+        self.data = Some(data.to_string());
+        self
+    }
+}
+
+/// Record an event. Failing to record it is logged rather than returned, so that it doesn't
+/// fail the request it happened in.
+pub async fn record(
+    db: &Database,
+    category: AuditCategory,
+    action: &str,
+    user: Option<&User>,
+    ip_address: Option<String>,
+    detail: impl Into<String>,
+) {
+    record_entry(db, entry(category, action, user, ip_address, detail)).await;
+}
+
+/// Record an entry built with [`entry`], see [`record`]
+pub async fn record_entry(db: &Database, entry: AuditEntry) {
+    info!(
+        target: "audit",
+        category = entry.category,
+        action = entry.action,
+        user = entry.username,
+        ip_address = entry.ip_address,
+        audit_target = entry.target,
+        "{}",
+        entry.detail
+    );
+    if let Err(e) = db.record_audit(&entry).await {
+        warn!("Failed to record an audit log entry: {e:?}");
+    }
+}
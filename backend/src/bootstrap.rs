@@ -0,0 +1,196 @@
+//! Preparing the data directory (`hyde-data/` by default) on startup. Anything that's missing
+//! is created, and if Hyde can't start until something is filled in, startup fails with
+//! instructions instead of a "file not found" error from deep inside the app.
+
+use crate::app_conf::locate_config_file;
+use crate::gh::PRIVATE_KEY_ENV_VAR;
+use crate::token_crypto::{generate_key, TOKEN_KEY_ENV_VAR};
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use fs_err as fs;
+use std::ffi::OsStr;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Written to `config.toml` on first run
+const CONFIG_TEMPLATE: &str = include_str!("../../default.toml");
+/// Written in place of the GitHub App's private key on first run
+const KEY_PLACEHOLDER: &str =
+    "Replace this file with the private key of your GitHub App, see docs/github.md\n";
+
+/// Create the config directory and a config template if they're missing. This must be called
+/// before the config is loaded.
+///
+/// # Errors
+/// This function returns an error if a config template was created (it needs to be filled in
+/// before Hyde can start), if `path` is a config file that doesn't exist, or if filesystem
+/// operations fail.
+pub fn init_config_dir(path: &str) -> Result<()> {
+    let path = Path::new(path);
+    if path.is_file() {
+        return Ok(());
+    }
+    if path.extension() == Some(OsStr::new("toml")) {
+        bail!(
+            "The config file {path:?} doesn't exist. Copy `default.toml` to {path:?} and fill it \
+            in, see docs/toml.md"
+        );
+    }
+    if !path.exists() {
+        fs::create_dir_all(path)?;
+        info!("Created the data directory {path:?}");
+    }
+    if locate_config_file(path)?.is_some() {
+        return Ok(());
+    }
+    let config_path = path.join("config.toml");
+    write_private(&config_path, CONFIG_TEMPLATE)?;
+    bail!(
+        "No config was found, so a template was created at {config_path:?}. Fill in the \
+        `[files]` and `[oauth]` sections and restart Hyde, see docs/toml.md"
+    );
+}
+
+/// Make sure the GitHub App's private key has been provided, creating a placeholder for it if
+/// it's missing.
+///
+/// # Errors
+/// This function returns an error if the key is missing or still the placeholder, or if
+/// filesystem operations fail.
+pub fn check_private_key(path: &str) -> Result<()> {
+    if std::env::var_os(PRIVATE_KEY_ENV_VAR).is_some() {
+        return Ok(());
+    }
+    let path = Path::new(path);
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(path, KEY_PLACEHOLDER)?;
+    }
+    if fs::read(path)? == KEY_PLACEHOLDER.as_bytes() {
+        bail!(
+            "The GitHub App private key hasn't been set up yet. Generate a private key for your \
+            GitHub App and save it to {path:?} (or set `{PRIVATE_KEY_ENV_VAR}`), see docs/github.md"
+        );
+    }
+    warn_if_world_readable(path);
+    Ok(())
+}
+
+/// Create the database file if it's missing, so that it's only readable by the current user.
+/// The schema is created by the migrations when the database is opened.
+///
+/// # Errors
+/// This function returns an error if filesystem operations fail.
+pub fn init_database_file(path: &str) -> Result<()> {
+    let path = Path::new(path);
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(path, "")?;
+        info!("Created a new database at {path:?}");
+    }
+    warn_if_world_readable(path);
+    Ok(())
+}
+
+/// Create a key to encrypt OAuth tokens with if there isn't one yet, see
+/// [`crate::token_crypto`].
+///
+/// # Errors
+/// This function returns an error if filesystem operations fail.
+pub fn init_token_key(path: &str) -> Result<()> {
+    if std::env::var_os(TOKEN_KEY_ENV_VAR).is_some() {
+        return Ok(());
+    }
+    let path = Path::new(path);
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(path, &format!("{}\n", generate_key()))?;
+        warn!(
+            "Created a new key for encrypting OAuth tokens at {path:?}. Back it up with the \
+            database, tokens can't be read without it"
+        );
+    }
+    warn_if_world_readable(path);
+    Ok(())
+}
+
+/// Write a file that should only be readable by the current user
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    fs::write(path, contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// Warn if a file containing secrets can be read by any user on the system
+fn warn_if_world_readable(path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o004 != 0) {
+            warn!(
+                "{path:?} is readable by every user on this system, restrict it with `chmod 600 {}`",
+                path.display()
+            );
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_run() {
+        let dir = std::env::temp_dir().join(format!("hyde-bootstrap-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(
+            init_config_dir(dir_str).is_err(),
+            "init_config_dir: should fail after creating a template"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("config.toml")).unwrap(),
+            CONFIG_TEMPLATE
+        );
+        assert!(
+            init_config_dir(dir_str).is_ok(),
+            "init_config_dir: should succeed once a config exists"
+        );
+
+        let key_path = dir.join("key.pem");
+        if std::env::var_os(PRIVATE_KEY_ENV_VAR).is_none() {
+            assert!(
+                check_private_key(key_path.to_str().unwrap()).is_err(),
+                "check_private_key: the placeholder shouldn't be accepted as a key"
+            );
+            assert!(key_path.exists());
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join("config.toml"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(
+                mode & 0o077,
+                0,
+                "write_private: created files should only be readable by the owner"
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+//! Per-branch push permissions. Groups are given branch name patterns, and a branch that
+//! matches any group's pattern can only be pushed to by members of the groups with a matching
+//! pattern, e.g. only admins may push `master`, while anyone who can edit may push `hyde/*`.
+//! Branches that no pattern matches can be pushed to by anyone who can edit.
+
+/// Whether `branch` matches `pattern`, where `*` matches any characters (including none)
+pub fn pattern_matches(pattern: &str, branch: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = branch.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`, so the pattern has to match exactly
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Whether a member of `user_groups` may push to `branch`, given every group's patterns as
+/// `(group_id, pattern)`
+pub fn may_push(rules: &[(i64, String)], user_groups: &[i64], branch: &str) -> bool {
+    let mut matching = rules
+        .iter()
+        .filter(|(_, pattern)| pattern_matches(pattern, branch))
+        .peekable();
+    matching.peek().is_none() || matching.any(|(group_id, _)| user_groups.contains(group_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(pattern_matches("master", "master"));
+        assert!(!pattern_matches("master", "master2"));
+        assert!(pattern_matches("hyde/*", "hyde/alice/fix-typo"));
+        assert!(pattern_matches("hyde/*", "hyde/"));
+        assert!(!pattern_matches("hyde/*", "main"));
+        assert!(pattern_matches("*", "anything"));
+        assert!(pattern_matches("release-*-final", "release-1.2-final"));
+        assert!(!pattern_matches("release-*-final", "release-1.2"));
+        assert!(
+            !pattern_matches("a*a", "a"),
+            "pattern_matches: the prefix and suffix shouldn't overlap"
+        );
+    }
+
+    #[test]
+    fn push_rules() {
+        let rules = vec![(1, "master".to_string()), (2, "hyde/*".to_string())];
+        assert!(may_push(&rules, &[1], "master"));
+        assert!(
+            !may_push(&rules, &[2], "master"),
+            "may_push: only the groups with a matching pattern should push"
+        );
+        assert!(may_push(&rules, &[2], "hyde/alice/fix"));
+        assert!(
+            may_push(&rules, &[], "feature"),
+            "may_push: branches no pattern matches shouldn't be restricted"
+        );
+    }
+}
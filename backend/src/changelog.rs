@@ -0,0 +1,95 @@
+//! A summary of documentation changes between two points in history, see
+//! [`crate::git::Interface::changelog`]
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Pages added, changed, and removed between two commits
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Changelog {
+    /// The commit the changelog starts from (exclusive)
+    pub from: String,
+    /// The commit the changelog ends at (inclusive)
+    pub to: String,
+    pub added: Vec<ChangelogEntry>,
+    pub changed: Vec<ChangelogEntry>,
+    pub removed: Vec<ChangelogEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChangelogEntry {
+    /// The path of the page, relative to the documents folder
+    pub path: String,
+    /// Everyone who edited the page in this range, in the order of their first edit
+    pub authors: Vec<String>,
+}
+
+impl Changelog {
+    /// Render the changelog as a markdown document, suitable for release notes or a digest.
+    pub fn to_markdown(&self) -> String {
+        let mut output = format!("# Changes from `{:.7}` to `{:.7}`\n", self.from, self.to);
+        for (heading, entries) in [
+            ("Added", &self.added),
+            ("Changed", &self.changed),
+            ("Removed", &self.removed),
+        ] {
+            if entries.is_empty() {
+                continue;
+            }
+            let _ = write!(output, "\n## {heading}\n\n");
+            for entry in entries {
+                let _ = writeln!(output, "- `{}` ({})", entry.path, entry.authors.join(", "));
+            }
+        }
+        if self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty() {
+            output.push_str("\nNo pages were changed.\n");
+        }
+        output
+    }
+}
+
+/// Determine who made a commit. Commits made through Hyde are all authored by `Hyde`,
+/// but their message starts with `[Hyde]: <username>`, so the real author is taken from there.
+pub fn commit_author(author_name: &str, message: &str) -> String {
+    if author_name == "Hyde" {
+        if let Some(username) = message
+            .strip_prefix("[Hyde]: ")
+            .and_then(|m| m.split_whitespace().next())
+        {
+            return username.to_string();
+        }
+    }
+    author_name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_authors() {
+        assert_eq!(commit_author("Hyde", "[Hyde]: foo updated bar.md"), "foo");
+        assert_eq!(commit_author("Hyde", "Merge: a into b"), "Hyde");
+        assert_eq!(commit_author("Jane", "[Hyde]: foo updated bar.md"), "Jane");
+    }
+
+    #[test]
+    fn markdown_rendering() {
+        let changelog = Changelog {
+            from: "0123456789".to_string(),
+            to: "abcdefghij".to_string(),
+            added: vec![ChangelogEntry {
+                path: "foo.md".to_string(),
+                authors: vec!["alice".to_string(), "bob".to_string()],
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            changelog.to_markdown(),
+            "# Changes from `0123456` to `abcdefg`\n\n## Added\n\n- `foo.md` (alice, bob)\n"
+        );
+        assert!(Changelog::default()
+            .to_markdown()
+            .ends_with("No pages were changed.\n"));
+    }
+}
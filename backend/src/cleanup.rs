@@ -0,0 +1,133 @@
+//! Deleting rows that are no longer needed in the background: expired sessions, API tokens,
+//! device sign in requests and grants, memberships left behind by deleted users and groups, and
+//! drafts that were abandoned. Expired rows are already ignored, this just keeps them from
+//! piling up.
+
+use crate::db::Database;
+use crate::AppState;
+use chrono::{DateTime, Days, Utc};
+use color_eyre::Result;
+use std::time::Duration;
+use tokio::task;
+use tracing::{info, warn};
+
+/// How many rows a cleanup deleted, by kind
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub sessions: u64,
+    pub api_tokens: u64,
+    pub device_codes: u64,
+    /// Expired permissions and group memberships
+    pub grants: u64,
+    pub orphaned_memberships: u64,
+    pub drafts: u64,
+}
+
+impl CleanupReport {
+    /// A summary of what was deleted, `None` if nothing was
+    pub fn summary(&self) -> Option<String> {
+        let parts: Vec<String> = [
+            (self.sessions, "expired sessions"),
+            (self.api_tokens, "expired API tokens"),
+            (self.device_codes, "expired device sign in requests"),
+            (self.grants, "expired permissions and group memberships"),
+            (self.orphaned_memberships, "orphaned group memberships"),
+            (self.drafts, "abandoned drafts"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{count} {what}"))
+        .collect();
+        (!parts.is_empty()).then(|| format!("Deleted {}", parts.join(", ")))
+    }
+}
+
+/// Delete everything that's expired at `now`, and the drafts that haven't been saved in
+/// `draft_retention_days`.
+pub async fn run(
+    db: &Database,
+    now: DateTime<Utc>,
+    draft_retention_days: u32,
+) -> Result<CleanupReport> {
+    let drafts_before = now
+        .checked_sub_days(Days::new(draft_retention_days.into()))
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    Ok(CleanupReport {
+        sessions: db.delete_expired_sessions(now).await?,
+        api_tokens: db.delete_expired_api_tokens(now).await?,
+        device_codes: db.delete_expired_device_codes(now).await?,
+        grants: db.delete_expired_grants(now).await?,
+        orphaned_memberships: db.delete_orphaned_memberships().await?,
+        drafts: db.prune_drafts(drafts_before).await?,
+    })
+}
+
+/// [`run`] every `database.cleanup_interval_secs`, see [`crate::spawn_writers`]
+pub fn spawn(state: AppState) {
+    let interval = Duration::from_secs(state.config.database.cleanup_interval_secs);
+    task::spawn(async move {
+        loop {
+            let retention_days = state.config.database.draft_retention_days;
+            match run(&state.db, Utc::now(), retention_days).await {
+                Ok(report) => {
+                    if let Some(summary) = report.summary() {
+                        info!("{summary}");
+                    }
+                }
+                Err(e) => warn!("Failed to clean up the database: {e:?}"),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Draft;
+
+    #[tokio::test]
+    async fn cleanup() {
+        let db = Database::from_url(":memory:").await.unwrap();
+        let user = db
+            .create_user(
+                "alice".to_string(),
+                "token".to_string(),
+                DateTime::UNIX_EPOCH,
+                "url".to_string(),
+            )
+            .await
+            .unwrap();
+        for (doc_path, updated_at) in [
+            ("old.md", "2025-01-01T00:00:00.000Z"),
+            ("new.md", "2025-06-01T00:00:00.000Z"),
+        ] {
+            db.put_draft(&Draft {
+                user_id: user.id,
+                doc_path: doc_path.to_string(),
+                contents: String::new(),
+                base_commit: None,
+                updated_at: DateTime::parse_from_rfc3339(updated_at).unwrap().to_utc(),
+            })
+            .await
+            .unwrap();
+        }
+        let now = DateTime::parse_from_rfc3339("2025-06-15T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let report = run(&db, now, 30).await.unwrap();
+        assert_eq!(
+            report,
+            CleanupReport {
+                drafts: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            report.summary().as_deref(),
+            Some("Deleted 1 abandoned drafts")
+        );
+        assert_eq!(run(&db, now, 30).await.unwrap().summary(), None);
+        assert_eq!(db.get_user_drafts(user.id).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,182 @@
+//! Parsing for GitHub `CODEOWNERS` files, used to pick reviewers for pull requests
+//!
+//! <https://docs.github.com/en/repositories/managing-your-repositorys-settings-and-features/customizing-your-repository/about-code-owners>
+
+/// The locations GitHub searches for a `CODEOWNERS` file, in order of precedence
+pub const CODEOWNERS_PATHS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// A single `pattern owner owner...` line from a `CODEOWNERS` file
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Rule {
+    pattern: String,
+    owners: Vec<String>,
+}
+
+/// A parsed `CODEOWNERS` file
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+/// The reviewers to request on a pull request, split the way the GitHub API expects them
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Reviewers {
+    /// GitHub usernames, without the leading `@`
+    pub users: Vec<String>,
+    /// Team slugs, without the leading `@org/`
+    pub teams: Vec<String>,
+}
+
+impl Reviewers {
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.teams.is_empty()
+    }
+
+    /// Sort an owner (`@user`, `@org/team`) into the right list. Email owners
+    /// can't be requested as reviewers through the API, so they're ignored.
+    pub fn push(&mut self, owner: &str) {
+        let Some(owner) = owner.strip_prefix('@') else {
+            return;
+        };
+        let (list, name) = match owner.split_once('/') {
+            Some((_org, team)) => (&mut self.teams, team),
+            None => (&mut self.users, owner),
+        };
+        if !list.iter().any(|o| o == name) {
+            list.push(name.to_string());
+        }
+    }
+}
+
+impl CodeOwners {
+    /// Parse the contents of a `CODEOWNERS` file. Invalid lines are skipped.
+    pub fn parse(contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                // Trailing comments are allowed
+                let line = line.split(" #").next().unwrap_or_default();
+                let mut parts = line.split_whitespace();
+                let pattern = parts.next()?.to_string();
+                let owners = parts.map(ToString::to_string).collect();
+                Some(Rule { pattern, owners })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Returns the owners of `path` (relative to the root of the repo). As with GitHub,
+    /// the last matching rule takes precedence.
+    pub fn owners_of(&self, path: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| pattern_matches(&rule.pattern, path))
+            .map_or(&[], |rule| &rule.owners)
+    }
+
+    /// Collect the owners of every path in `paths`.
+    pub fn reviewers_for<S: AsRef<str>>(&self, paths: &[S]) -> Reviewers {
+        let mut reviewers = Reviewers::default();
+        for path in paths {
+            for owner in self.owners_of(path.as_ref()) {
+                reviewers.push(owner);
+            }
+        }
+        reviewers
+    }
+}
+
+/// Whether a gitignore-style `CODEOWNERS` pattern matches `path`.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let path = path.trim_start_matches('/');
+    // A pattern with a slash anywhere but the end is relative to the root of the repo,
+    // otherwise it can match at any depth
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let mut pattern = pattern.trim_start_matches('/').to_string();
+    // Directories match everything inside of them
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    if !anchored {
+        pattern.insert_str(0, "**/");
+    }
+    // A pattern that matches a directory also matches everything inside of it
+    glob_matches(&pattern, path) || glob_matches(&format!("{pattern}/**"), path)
+}
+
+/// A minimal glob matcher supporting `*` (anything but `/`), `**` (anything) and `?`.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+
+    fn inner(p: &[u8], t: &[u8]) -> bool {
+        match p {
+            [] => t.is_empty(),
+            [b'*', b'*', b'/', rest @ ..] => {
+                // `**/` can match zero directories
+                inner(rest, t) || (0..t.len()).any(|i| t[i] == b'/' && inner(rest, &t[i + 1..]))
+            }
+            [b'*', b'*', rest @ ..] => (0..=t.len()).any(|i| inner(rest, &t[i..])),
+            [b'*', rest @ ..] => (0..=t.len())
+                .take_while(|&i| i == 0 || t[i - 1] != b'/')
+                .any(|i| inner(rest, &t[i..])),
+            [b'?', rest @ ..] => !t.is_empty() && t[0] != b'/' && inner(rest, &t[1..]),
+            [c, rest @ ..] => !t.is_empty() && t[0] == *c && inner(rest, &t[1..]),
+        }
+    }
+
+    inner(pattern, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_matching() {
+        assert!(pattern_matches("*", "docs/foo.md"));
+        assert!(pattern_matches("*.md", "docs/foo.md"));
+        assert!(!pattern_matches("*.md", "assets/foo.png"));
+        assert!(pattern_matches("docs/", "docs/guides/foo.md"));
+        assert!(pattern_matches("/docs/", "docs/foo.md"));
+        assert!(!pattern_matches("/docs/", "other/docs/foo.md"));
+        assert!(pattern_matches("guides/", "docs/guides/foo.md"));
+        assert!(pattern_matches("docs/*.md", "docs/foo.md"));
+        assert!(!pattern_matches("docs/*.md", "docs/guides/foo.md"));
+        assert!(pattern_matches("docs/**/foo.md", "docs/a/b/foo.md"));
+        assert!(pattern_matches("docs/guides", "docs/guides/foo.md"));
+    }
+
+    #[test]
+    fn owner_resolution() {
+        let codeowners = CodeOwners::parse(
+            "# Comment\n\
+             *       @org/wiki-team\n\
+             docs/hardware/ @alice @org/hardware # trailing comment\n\
+             *.png   foo@example.com\n",
+        );
+        assert_eq!(
+            codeowners.owners_of("docs/software/foo.md"),
+            ["@org/wiki-team"]
+        );
+        assert_eq!(
+            codeowners.owners_of("docs/hardware/foo.md"),
+            ["@alice", "@org/hardware"]
+        );
+
+        let reviewers =
+            codeowners.reviewers_for(&["docs/hardware/foo.md", "docs/hardware/bar.md", "a.png"]);
+        assert_eq!(
+            reviewers,
+            Reviewers {
+                users: vec!["alice".to_string()],
+                teams: vec!["hardware".to_string()],
+            },
+            "reviewers_for: should dedupe owners and ignore email owners"
+        );
+        assert!(CodeOwners::parse("").reviewers_for(&["foo.md"]).is_empty());
+    }
+}
@@ -1,24 +1,247 @@
 //! Database specific interfaces and abstractions
 
+use crate::api_tokens;
+use crate::app_conf::{JournalMode, Synchronous};
+use crate::identity::DEFAULT_AVATAR_URL;
 use crate::perms::Permission;
-use color_eyre::{eyre::bail, Result};
+use crate::search;
+use crate::token_crypto::{self, TokenCipher};
+use chrono::{DateTime, Utc};
+use color_eyre::{
+    eyre::{bail, ContextCompat, WrapErr},
+    Result,
+};
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
+use sqlx::{Connection, PgPool, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::debug;
 
-pub const DATABASE_URL: &str = "file:hyde-data/data.db?mode=rwc";
+/// The SQLite database used unless `database.url` says otherwise
+pub const DATABASE_PATH: &str = "hyde-data/data.db";
+/// What the config template used to set `database.url` to. It's relative to the backend folder
+/// for the sqlx tooling, Hyde itself always used [`DATABASE_PATH`], so it still does.
+const LEGACY_TEMPLATE_URL: &str = "sqlite://../hyde-data/data.db";
+/// Provides the key a SQLite database is encrypted with instead of `database.encryption_key`,
+/// see [`SqliteSettings`]
+pub const DATABASE_KEY_ENV_VAR: &str = "HYDE_DATABASE_KEY";
+
+// The migrations are embedded into the executable
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("./migrations");
+static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Create an empty file at `path`, which must not exist yet, that only the user Hyde runs as can
+/// read, for copies of the database
+pub async fn create_private_file(path: &Path) -> Result<tokio::fs::File> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+        .open(path)
+        .await
+        .wrap_err_with(|| format!("Failed to create {}", path.display()))
+}
+
+/// A schema migration, see [`Database::migrate`]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct MigrationInfo {
+    /// When the migration was written, e.g. `20250630000000`
+    pub version: i64,
+    pub description: String,
+}
+
+/// Apply `migrator`'s pending migrations with `conn`, see [`Database::migrate`]. Like
+/// [`Migrator::run`], applied migrations that were changed afterwards are an error.
+async fn apply_migrations<C: Migrate>(
+    conn: &mut C,
+    migrator: &Migrator,
+    to: Option<i64>,
+    dry_run: bool,
+) -> Result<Vec<MigrationInfo>> {
+    if let Some(to) = to {
+        if !migrator.version_exists(to) {
+            bail!("There's no migration with version {to}");
+        }
+    }
+    conn.lock().await?;
+    let result = async {
+        conn.ensure_migrations_table().await?;
+        if let Some(version) = conn.dirty_version().await? {
+            bail!(
+                "Migration {version} was only partially applied, the database needs fixing by hand"
+            );
+        }
+        let applied: HashMap<i64, _> = conn
+            .list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|m| (m.version, m.checksum))
+            .collect();
+        let mut pending = Vec::new();
+        for migration in migrator.iter() {
+            if migration.migration_type.is_down_migration() {
+                continue;
+            }
+            match applied.get(&migration.version) {
+                Some(checksum) if *checksum != migration.checksum => {
+                    bail!(
+                        "Migration {} was changed after it was applied",
+                        migration.version
+                    );
+                }
+                Some(_) => {}
+                None if to.is_some_and(|to| migration.version > to) => {}
+                None => {
+                    if !dry_run {
+                        conn.apply(migration).await?;
+                    }
+                    pending.push(MigrationInfo {
+                        version: migration.version,
+                        description: migration.description.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(pending)
+    }
+    .await;
+    conn.unlock().await?;
+    result
+}
+
+/// How connections to a SQLite database are opened, from the `database` section of the config.
+/// Ignored for PostgreSQL databases, except for the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqliteSettings {
+    /// The passphrase the database is encrypted with using SQLCipher, which needs Hyde to be
+    /// built with the `sqlcipher` feature
+    pub key: Option<String>,
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    /// How long a query waits for another connection's write before failing with "database is
+    /// locked"
+    pub busy_timeout: Duration,
+}
+
+impl Default for SqliteSettings {
+    fn default() -> Self {
+        crate::app_conf::Database::default().sqlite_settings()
+    }
+}
+
+/// The options to connect to the SQLite database at `url` with
+fn sqlite_options(url: &str, settings: &SqliteSettings) -> Result<SqliteConnectOptions> {
+    let journal_mode = match settings.journal_mode {
+        JournalMode::Delete => SqliteJournalMode::Delete,
+        JournalMode::Truncate => SqliteJournalMode::Truncate,
+        JournalMode::Persist => SqliteJournalMode::Persist,
+        JournalMode::Memory => SqliteJournalMode::Memory,
+        JournalMode::Wal => SqliteJournalMode::Wal,
+        JournalMode::Off => SqliteJournalMode::Off,
+    };
+    let synchronous = match settings.synchronous {
+        Synchronous::Off => SqliteSynchronous::Off,
+        Synchronous::Normal => SqliteSynchronous::Normal,
+        Synchronous::Full => SqliteSynchronous::Full,
+        Synchronous::Extra => SqliteSynchronous::Extra,
+    };
+    let options = SqliteConnectOptions::from_str(url)?
+        .journal_mode(journal_mode)
+        .synchronous(synchronous)
+        .busy_timeout(settings.busy_timeout);
+    let Some(key) = &settings.key else {
+        return Ok(options);
+    };
+    if !cfg!(feature = "sqlcipher") {
+        bail!(
+            "The database has an encryption key, but Hyde was built without the `sqlcipher` \
+            feature"
+        );
+    }
+    // The key is used as a passphrase, quoted like any other SQL string
+    Ok(options.pragma("key", format!("'{}'", key.replace('\'', "''"))))
+}
+
+/// Where the configured database is, parsed from `database.url` by [`DatabaseUrl::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseUrl {
+    Postgres(String),
+    /// A SQLite database file, relative to the working directory unless it's absolute
+    SqliteFile(PathBuf),
+    /// A SQLite database that only lives as long as the process, for testing
+    SqliteMemory,
+}
+
+impl DatabaseUrl {
+    /// Accepts `postgres://` and `postgresql://` urls, `:memory:` (or `sqlite::memory:`), and
+    /// SQLite file paths, optionally prefixed with `sqlite://`, `sqlite:` or `file:`. Query
+    /// parameters are dropped, Hyde sets the ones it needs.
+    pub fn parse(url: &str) -> Self {
+        let url = url.trim();
+        if Database::is_postgres_url(url) {
+            return Self::Postgres(url.to_string());
+        }
+        if url == LEGACY_TEMPLATE_URL {
+            return Self::SqliteFile(PathBuf::from(DATABASE_PATH));
+        }
+        let path = ["sqlite://", "sqlite:", "file:"]
+            .iter()
+            .find_map(|prefix| url.strip_prefix(prefix))
+            .unwrap_or(url);
+        let path = path.split_once('?').map_or(path, |(path, _query)| path);
+        if path == ":memory:" {
+            Self::SqliteMemory
+        } else {
+            Self::SqliteFile(PathBuf::from(path))
+        }
+    }
+
+    /// The url to connect to the database with, see [`Database::from_url`]. SQLite database
+    /// files are created if they don't exist.
+    pub fn connect_url(&self) -> String {
+        match self {
+            Self::Postgres(url) => url.clone(),
+            Self::SqliteFile(path) => format!("file:{}?mode=rwc", path.display()),
+            Self::SqliteMemory => ":memory:".to_string(),
+        }
+    }
+
+    /// The SQLite database file, if it's one
+    pub fn sqlite_file(&self) -> Option<&Path> {
+        match self {
+            Self::SqliteFile(path) => Some(path),
+            Self::Postgres(_) | Self::SqliteMemory => None,
+        }
+    }
+}
 
 // the ids have to be i64 because that's what sql uses
-#[derive(Debug, PartialEq, Eq, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub username: String,
-    /// The oauth2 auth token
+    /// The oauth2 auth token, encrypted if the database has a token key, see
+    /// [`crate::token_crypto`]
     pub token: String,
-    /// ISO-8601/RFC-3339 string
-    pub expiration_date: String,
+    /// When the OAuth token expires. Users without a token, like service accounts, have the
+    /// epoch.
+    pub expiration_date: DateTime<Utc>,
     /// The CDN url to the user's profile picture
     pub avatar_url: String,
+    /// Service accounts are for bots and scheduled jobs. They're created by admins, can't sign
+    /// in, and authenticate only with API tokens.
+    #[serde(default)]
+    pub service_account: bool,
+    /// When the user was deactivated. Deactivated users can't sign in or make requests, but are
+    /// kept so the history they're attributed in keeps their name.
+    #[serde(default)]
+    pub deactivated_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, PartialEq, Eq, sqlx::FromRow, Serialize, Deserialize)]
@@ -41,347 +264,1421 @@ pub struct GroupPermissions {
     permission: String,
 }
 
-/// A wrapper around the sqlite database, and how consumers should interact with the database in any capacity.
-#[derive(Clone, Debug)]
-pub struct Database {
-    pool: SqlitePool,
+/// The GitHub account a user has linked, see [`Database::link_github_account`]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct GitHubAccount {
+    pub github_id: i64,
+    pub github_login: String,
 }
 
-impl Database {
-    /// Create or connect to the database located at `DATABASE_URL`.
-    pub async fn new() -> Result<Self> {
-        let pool = SqlitePool::connect(DATABASE_URL).await?;
-
-        debug!("Running SQL migrations...");
-        // this should embed the migrations into the executable itself
-        sqlx::migrate!("./migrations").run(&pool).await?;
-        debug!("SQL migrations complete");
+/// An owner assigned to a document or directory. Exactly one of `user_id` and `group_id` is set.
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct DocOwner {
+    /// The path of the document, or directory if it ends in `/`, relative to the documents folder
+    pub path: String,
+    pub user_id: Option<i64>,
+    pub group_id: Option<i64>,
+}
 
-        Ok(Self { pool })
+impl DocOwner {
+    /// Whether this ownership entry covers the document at `doc_path`.
+    pub fn covers(&self, doc_path: &str) -> bool {
+        if self.path.ends_with('/') {
+            doc_path.starts_with(&self.path)
+        } else {
+            doc_path == self.path
+        }
     }
+}
 
-    /// Create or connect to the database with the provided url, useful for testing so that
-    /// you can initialize a database in memory.
-    pub async fn from_url(url: &str) -> Result<Self> {
-        let pool = SqlitePool::connect(url).await?;
+/// A ticket issued to a client so that it can edit a document offline, then submit the edit
+/// against the revision it started from.
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct EditingTicket {
+    pub id: String,
+    pub user_id: i64,
+    /// The path of the document, relative to the documents folder
+    pub path: String,
+    /// The commit the document was read from
+    pub base_commit: String,
+    /// The git blob of the document when the ticket was issued
+    pub blob_sha: String,
+    pub expiration_date: DateTime<Utc>,
+    /// The branch the document was read from, which the edit is saved to
+    pub branch: String,
+}
 
-        debug!("Running SQL migrations...");
-        // this should embed the migrations into the executable itself
-        sqlx::migrate!("./migrations").run(&pool).await?;
-        debug!("SQL migrations complete");
+/// A logged in browser, identified by the random id in its session cookie
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct Session {
+    /// The hash of the id in the session cookie, see [`crate::api_tokens::hash`]. Ids are as
+    /// good as a password, so they're never stored.
+    pub id: String,
+    pub user_id: i64,
+    pub created_at: DateTime<Utc>,
+    pub expiration_date: DateTime<Utc>,
+    /// The identity provider the user signed in with, see [`crate::identity`]
+    pub provider: Option<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// The address the user signed in from
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
 
-        Ok(Self { pool })
-    }
+/// A token a user authenticates scripts with, see [`crate::api_tokens`]. The token's hash is
+/// never read back.
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// `None` if the token doesn't expire
+    pub expiration_date: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
 
-    /// Add a new user to the database, returning the created user. This does not overwrite an existing user
-    pub async fn create_user(
-        &self,
-        username: String,
-        token: String,
-        expiration_date: String,
-        avatar_url: String,
-    ) -> Result<User> {
-        let query_results: User = sqlx::query_as(
-            r"
-            INSERT INTO users (username, token, expiration_date, avatar_url)
-            VALUES (?, ?, ?, ?) RETURNING *;
-            ",
-        )
-        .bind(username)
-        .bind(token)
-        .bind(expiration_date)
-        .bind(avatar_url)
-        .fetch_one(&self.pool)
-        .await?;
+/// A security relevant event, see [`crate::audit`]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub category: String,
+    pub action: String,
+    pub user_id: Option<i64>,
+    pub username: Option<String>,
+    pub ip_address: Option<String>,
+    pub detail: String,
+    /// What the entry is about, e.g. a document path or `user:4`
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Structured details about the event, a JSON object
+    #[serde(default)]
+    pub data: Option<String>,
+}
 
-        Ok(query_results)
-    }
+/// Which audit log entries [`Database::query_audit`] returns. Every filter that's set has to
+/// match.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    pub category: Option<String>,
+    pub action: Option<String>,
+    /// Only entries about this user's actions
+    pub user_id: Option<i64>,
+    pub target: Option<String>,
+    /// Only entries created at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries created before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Only entries older than the entry with this id, to page through the log
+    pub before: Option<i64>,
+}
 
-    /// Returns a user from the database associated with the provided user id.
-    pub async fn get_user(&self, user_id: i64) -> Result<Option<User>> {
-        let query_results: Option<User> = sqlx::query_as(r"SELECT * FROM  users WHERE id = ?;")
-            .bind(user_id)
-            .fetch_optional(&self.pool)
-            .await?;
-        Ok(query_results)
-    }
+/// A pending device authorization request, see [`crate::device_flow`]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct DeviceCode {
+    /// Hex encoded SHA-256 of the device code the client polls with
+    pub device_code_hash: String,
+    pub user_code: String,
+    pub client_name: String,
+    pub created_at: DateTime<Utc>,
+    pub expiration_date: DateTime<Utc>,
+    pub last_polled_at: Option<DateTime<Utc>>,
+    /// The user that approved the request, `None` until then
+    pub user_id: Option<i64>,
+    pub denied: bool,
+}
 
-    /// Returns a user from the database associated with the provided
-    /// authentication token.
-    pub async fn get_user_from_token(&self, token: String) -> Result<Option<User>> {
-        let query_results: Option<User> = sqlx::query_as(r"SELECT * FROM  users WHERE token = ?;")
-            .bind(token)
-            .fetch_optional(&self.pool)
-            .await?;
-        Ok(query_results)
-    }
+/// A permission taken away from a user, or from every member of a group, even if one of their
+/// groups grants it
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct PermissionDeny {
+    pub id: i64,
+    pub user_id: Option<i64>,
+    pub group_id: Option<i64>,
+    pub permission: String,
+    /// `None` if the rule doesn't expire
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
 
-    /// Returns a list of all groups a user is a member of.
-    pub async fn get_user_groups(&self, user_id: i64) -> Result<Vec<Group>> {
-        let groups: Vec<Group> = sqlx::query_as(
-            "SELECT groups.* FROM group_membership 
-            RIGHT JOIN groups ON group_membership.group_id = groups.id
-            WHERE group_membership.user_id = ? ORDER BY groups.id;",
-        )
-        .bind(user_id)
-        .fetch_all(&self.pool)
-        .await?;
+/// A user's TOTP second factor, see [`crate::totp`]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct TotpSecret {
+    pub user_id: i64,
+    /// Base32 encoded, decrypted when read
+    pub secret: String,
+    /// Whether the user has confirmed enrollment
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    /// The time step of the last accepted code
+    pub last_used_step: Option<i64>,
+}
 
-        Ok(groups)
-    }
+/// What a user has contributed, see [`Database::get_user_stats`]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct UserStats {
+    /// How many times they've saved a document
+    pub doc_edits: i64,
+    /// How many different documents they've saved
+    pub docs_edited: i64,
+    pub assets_uploaded: i64,
+    /// Pull requests opened through Hyde
+    pub prs_opened: i64,
+    /// When they last used a session or did something that was audited
+    pub last_active_at: Option<DateTime<Utc>>,
+}
 
-    /// Returns a list of all of the permissions a user has.
-    pub async fn get_user_permissions(&self, user_id: i64) -> Result<Vec<Permission>> {
-        // TODO include get_user_permissions in tests
-        let query_result: Vec<GroupPermissions> = sqlx::query_as(
-            "SELECT DISTINCT gp.* FROM group_permissions gp
-            INNER JOIN group_membership gm ON gp.group_id = gm.group_id WHERE gm.user_id = ?;",
-        )
-        .bind(user_id)
-        .fetch_all(&self.pool)
-        .await?;
+/// Who last edited a document and when, see [`Database::record_doc_edit`]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct DocMeta {
+    /// Relative to the documents folder
+    pub path: String,
+    /// `None` if the user has been deleted
+    pub last_editor_id: Option<i64>,
+    pub last_editor_name: String,
+    pub last_edited_at: DateTime<Utc>,
+    /// How many times the document has been saved through Hyde
+    pub edit_count: i64,
+    pub description: Option<String>,
+}
 
-        let permissions_vec = query_result
-            .into_iter()
-            .map(|e| e.permission.as_str().try_into().unwrap())
-            .collect();
+/// A document matching a search, see [`Database::search_docs`]
+#[derive(Debug, PartialEq, Clone, sqlx::FromRow, Serialize)]
+pub struct SearchHit {
+    /// Relative to the documents folder
+    pub path: String,
+    pub title: String,
+    /// An excerpt around the matches, with each match between [`search::HIGHLIGHT_START`] and
+    /// [`search::HIGHLIGHT_END`]
+    pub snippet: String,
+    /// How relevant the document is, higher is more relevant. Only comparable between hits of
+    /// the same search.
+    pub rank: f64,
+}
 
-        Ok(permissions_vec)
-    }
+/// Work in progress on a document, autosaved by the editor
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct Draft {
+    pub user_id: i64,
+    /// Relative to the documents folder
+    pub doc_path: String,
+    pub contents: String,
+    /// The commit the document was read from before it was edited
+    pub base_commit: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
 
-    /// Returns a list of every user in the database.
-    pub async fn get_all_users(&self) -> Result<Vec<User>> {
-        let query_results: Vec<User> = sqlx::query_as(r"SELECT * FROM users;")
-            .fetch_all(&self.pool)
-            .await?;
+/// Something a user is told about in the app, e.g. that a review was requested from them
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: i64,
+    pub user_id: i64,
+    /// What the notification is about, e.g. `review_requested`, `pr_merged` or `mention`
+    pub kind: String,
+    /// The details of the notification, a JSON object
+    pub payload: String,
+    pub created_at: DateTime<Utc>,
+    /// `None` until the user reads the notification
+    pub read_at: Option<DateTime<Utc>>,
+}
 
-        Ok(query_results)
-    }
+/// An account a user can sign in with, see [`crate::identity`]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct UserIdentity {
+    pub provider: String,
+    /// The provider's id for the account
+    pub subject: String,
+    pub user_id: i64,
+    /// The account's name on the provider
+    pub username: String,
+}
 
-    /// Refresh the database entry for the provided user based off of the user id.
-    ///
-    /// The ID of the user will not be updated.
-    pub async fn update_user(&self, user: &User) -> Result<()> {
-        let query_result = sqlx::query(
-            r"
-            UPDATE users SET username = ?, token = ?, expiration_date = ?
-            WHERE id = ?;",
-        )
-        .bind(&user.username)
-        .bind(&user.token)
-        .bind(&user.expiration_date)
-        .bind(user.id)
-        .execute(&self.pool)
-        .await?;
+/// A webhook event waiting to be processed, or that already has been
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: i64,
+    /// The `X-GitHub-Event` header, e.g. `push`
+    pub event_type: String,
+    /// The `X-GitHub-Delivery` header, if one was sent
+    pub delivery_id: Option<String>,
+    #[serde(skip_serializing)]
+    pub payload: String,
+    /// `pending`, `completed`, or `failed`
+    pub status: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub received_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
 
-        if query_result.rows_affected() != 1 {
-            bail!(
-                "Update user impacted unexpected number of rows, impacted {} rows",
-                query_result.rows_affected()
-            )
-        }
+/// Which webhook events to return, see [`Database::query_webhook_events`]. Unset fields match
+/// every event.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize)]
+pub struct WebhookEventFilter {
+    /// The `X-GitHub-Event` header, e.g. `push`
+    pub event_type: Option<String>,
+    /// `pending`, `completed`, or `failed`
+    pub status: Option<String>,
+    /// Only events received before the event with this id, to page through the history
+    pub before: Option<i64>,
+}
 
-        Ok(())
-    }
+/// A pull request opened through Hyde
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct HydePr {
+    pub number: i64,
+    pub title: String,
+    /// The user who opened the pull request, if they're known
+    pub author_id: Option<i64>,
+    /// The branch the pull request proposes, `None` for pull requests recorded before branches
+    /// were
+    pub branch: Option<String>,
+    /// `open`, `closed` or `merged`
+    pub status: String,
+    /// The documents changed by the pull request, relative to the documents folder
+    #[sqlx(skip)]
+    pub doc_paths: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    /// `None` until the pull request is merged
+    pub merged_at: Option<DateTime<Utc>>,
+}
 
-    /// Delete the user associated with the provided user ID from the database.
-    pub async fn delete_user(&self, user_id: i64) -> Result<()> {
-        let query_result = sqlx::query(r"DELETE FROM users WHERE id = ?")
-            .bind(user_id)
-            .execute(&self.pool)
-            .await?;
+/// A performance metric, see [`crate::telemetry`]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct TelemetrySample {
+    /// Where the sample was measured, "client" or "server"
+    pub source: String,
+    pub metric: String,
+    pub value: i64,
+    pub recorded_at: DateTime<Utc>,
+}
 
-        if query_result.rows_affected() != 1 {
-            bail!(
-                "Delete user impacted unexpected number of rows, impacted {} rows",
-                query_result.rows_affected()
-            )
-        }
+/// How many webhook events are in each state
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookQueueStatus {
+    pub pending: i64,
+    pub completed: i64,
+    pub failed: i64,
+}
 
-        Ok(())
-    }
+/// The connection pool of whichever database Hyde was configured with
+#[derive(Clone, Debug)]
+enum Pool {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
 
-    /// Create a group, returning the created group upon completion.
-    pub async fn create_group(&self, group_name: String) -> Result<Group> {
-        let query_results: Group = sqlx::query_as(
-            r"
-            INSERT INTO groups (name) VALUES (?) RETURNING *;
-            ",
-        )
-        .bind(group_name)
-        .fetch_one(&self.pool)
-        .await?;
+/// Run `$body` with `$pool` bound to the database's connection pool, whichever database it is.
+///
+/// The body is compiled once for each database, so queries have to be written in SQL both
+/// SQLite and PostgreSQL understand: `$1` style parameters, `TRUE` and `FALSE` for booleans,
+/// `ON CONFLICT` instead of `INSERT OR IGNORE`, and `RETURNING` instead of `last_insert_rowid`.
+macro_rules! with_pool {
+    ($self:expr, |$pool:ident| $body:expr) => {
+        match &$self.pool {
+            Pool::Sqlite($pool) => $body,
+            Pool::Postgres($pool) => $body,
+        }
+    };
+}
 
-        Ok(query_results)
-    }
+/// A wrapper around the database, and how consumers should interact with the database in any
+/// capacity. It's SQLite unless a PostgreSQL url is configured, see [`Database::from_url`].
+#[derive(Clone, Debug)]
+pub struct Database {
+    pool: Pool,
+    /// Encrypts the OAuth tokens stored in the database, if set
+    token_cipher: Option<Arc<TokenCipher>>,
+}
 
-    /// Returns a group from the database associated with the provided
-    /// group id.
-    pub async fn get_group(&self, group_id: i64) -> Result<Option<Group>> {
-        let query_results: Option<Group> =
-            sqlx::query_as("SELECT * FROM groups WHERE id = ? LIMIT 1;")
-                .bind(group_id)
-                .fetch_optional(&self.pool)
-                .await?;
+impl Database {
+    /// Create or connect to the configured database, see [`DatabaseUrl`]. SQLite databases
+    /// are opened with `settings`.
+    pub async fn new(url: &DatabaseUrl, settings: &SqliteSettings) -> Result<Self> {
+        Self::connect_unmigrated(&url.connect_url(), settings)
+            .await?
+            .run_migrations()
+            .await
+    }
 
-        Ok(query_results)
+    /// Whether `url` points at a PostgreSQL database rather than a SQLite one
+    pub fn is_postgres_url(url: &str) -> bool {
+        url.starts_with("postgres://") || url.starts_with("postgresql://")
     }
 
-    /// Returns a list of every group in the database.
-    pub async fn get_all_groups(&self) -> Result<Vec<Group>> {
-        let query_results: Vec<Group> = sqlx::query_as(r"SELECT * FROM groups;")
-            .fetch_all(&self.pool)
-            .await?;
+    /// Check that the database answers a query within `timeout`, for health checks. With SQLite,
+    /// the schema is read so that a database locked by another process fails too.
+    pub async fn ping(&self, timeout: Duration) -> Result<()> {
+        let query = async {
+            match &self.pool {
+                Pool::Sqlite(pool) => {
+                    sqlx::query("SELECT 1 FROM sqlite_master LIMIT 1;")
+                        .fetch_optional(pool)
+                        .await?;
+                }
+                Pool::Postgres(pool) => {
+                    sqlx::query("SELECT 1;").fetch_one(pool).await?;
+                }
+            }
+            Ok(())
+        };
+        tokio::time::timeout(timeout, query)
+            .await
+            .wrap_err_with(|| format!("The database didn't respond within {timeout:?}"))?
+    }
 
-        Ok(query_results)
+    /// Close every connection to the database, waiting for queries in progress to finish.
+    pub async fn close(&self) {
+        match &self.pool {
+            Pool::Sqlite(pool) => pool.close().await,
+            Pool::Postgres(pool) => pool.close().await,
+        }
     }
 
-    /// Returns a list of every member in the provided group (by id).
-    pub async fn get_group_members(&self, group_id: i64) -> Result<Vec<User>> {
-        let users: Vec<User> = sqlx::query_as(
-            "SELECT users.* FROM group_membership 
-            RIGHT JOIN users ON group_membership.user_id = users.id
-            WHERE group_membership.group_id = ? ORDER BY users.id;",
-        )
-        .bind(group_id)
-        .fetch_all(&self.pool)
-        .await?;
+    /// Whether this is a PostgreSQL database rather than a SQLite one
+    pub const fn is_postgres(&self) -> bool {
+        matches!(self.pool, Pool::Postgres(_))
+    }
 
-        Ok(users)
+    /// Encrypt the OAuth tokens written to the database from now on, and decrypt them when
+    /// they're read. Use [`Database::encrypt_stored_tokens`] to encrypt existing ones.
+    #[must_use]
+    pub fn with_token_cipher(mut self, cipher: TokenCipher) -> Self {
+        self.token_cipher = Some(Arc::new(cipher));
+        self
     }
 
-    /// Whether a user is a member of a group.
-    pub async fn group_has_member(&self, group_id: i64, user_id: i64) -> Result<bool> {
-        let query_result = sqlx::query(
-            "SELECT * FROM group_membership WHERE group_id = ? AND user_id = ? LIMIT 1;",
-        )
-        .bind(group_id)
-        .bind(user_id)
-        .fetch_optional(&self.pool)
-        .await?;
+    /// Prepare an OAuth token for storage. Empty tokens stand for no token, so they're left as is.
+    fn seal_token(&self, token: &str) -> Result<String> {
+        match &self.token_cipher {
+            Some(cipher) if !token.is_empty() => cipher.encrypt(token),
+            _ => Ok(token.to_string()),
+        }
+    }
 
-        match query_result {
-            Some(_) => Ok(true),
-            None => Ok(false),
+    /// Read an OAuth token stored with [`Database::seal_token`]
+    fn open_token(&self, stored: &str) -> Result<String> {
+        match &self.token_cipher {
+            Some(cipher) => cipher.decrypt(stored),
+            None if token_crypto::is_encrypted(stored) => {
+                bail!("The token is encrypted, but the database has no token key")
+            }
+            None => Ok(stored.to_string()),
         }
     }
 
-    /// Add a user to a group (by id).
+    /// Encrypt every OAuth token that was stored before the database had a token key.
     ///
-    /// Returns true if the user was added successfully, returns false if the user is already
-    /// a member of the group.
-    pub async fn add_group_membership(&self, group_id: i64, user_id: i64) -> Result<bool> {
-        let already_has_member = self.group_has_member(group_id, user_id).await?;
-
-        if already_has_member {
-            Ok(false)
-        } else {
-            sqlx::query("INSERT INTO group_membership (group_id, user_id) VALUES (?, ?);")
-                .bind(group_id)
-                .bind(user_id)
-                .execute(&self.pool)
-                .await?;
+    /// Returns how many users' tokens were encrypted.
+    pub async fn encrypt_stored_tokens(&self) -> Result<u64> {
+        with_pool!(self, |pool| {
+            if self.token_cipher.is_none() {
+                return Ok(0);
+            }
+            let rows: Vec<(i64, String, Option<String>)> =
+                sqlx::query_as("SELECT id, token, refresh_token FROM users;")
+                    .fetch_all(pool)
+                    .await?;
+            let mut encrypted = 0;
+            for (id, token, refresh_token) in rows {
+                let needs_sealing = |t: &str| !t.is_empty() && !token_crypto::is_encrypted(t);
+                if !needs_sealing(&token) && !refresh_token.as_deref().is_some_and(needs_sealing) {
+                    continue;
+                }
+                let token = if needs_sealing(&token) {
+                    self.seal_token(&token)?
+                } else {
+                    token
+                };
+                let refresh_token = match refresh_token {
+                    Some(t) if needs_sealing(&t) => Some(self.seal_token(&t)?),
+                    other => other,
+                };
+                sqlx::query("UPDATE users SET token = $1, refresh_token = $2 WHERE id = $3;")
+                    .bind(token)
+                    .bind(refresh_token)
+                    .bind(id)
+                    .execute(pool)
+                    .await?;
+                encrypted += 1;
+            }
+            Ok(encrypted)
+        })
+    }
 
-            Ok(true)
-        }
+    /// Hash the ids of sessions that were stored before session ids were hashed. SQLite can't
+    /// hash them in a migration, so this is done when Hyde starts. Hashes are 64 characters
+    /// long, unhashed ids are shorter.
+    ///
+    /// Returns how many sessions were hashed.
+    pub async fn hash_stored_sessions(&self) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let ids: Vec<String> =
+                sqlx::query_scalar("SELECT id FROM sessions WHERE LENGTH(id) <> 64;")
+                    .fetch_all(pool)
+                    .await?;
+            let mut transaction = pool.begin().await?;
+            for id in &ids {
+                sqlx::query("UPDATE sessions SET id = $1 WHERE id = $2;")
+                    .bind(api_tokens::hash(id))
+                    .bind(id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            transaction.commit().await?;
+            Ok(ids.len() as u64)
+        })
     }
 
-    /// Remove a user from a group (by id).
+    /// Create or connect to the database with the provided url, useful for testing so that
+    /// you can initialize a database in memory.
     ///
-    /// Returns `true` if the user was removed successfully, returns `false` if the user was not
-    /// a member of the provided group.
-    pub async fn remove_group_membership(&self, group_id: i64, user_id: i64) -> Result<bool> {
-        let already_has_member = self.group_has_member(group_id, user_id).await?;
+    /// `postgres://` and `postgresql://` urls connect to PostgreSQL, anything else is
+    /// treated as a SQLite url.
+    pub async fn from_url(url: &str) -> Result<Self> {
+        Self::connect_unmigrated(url, &SqliteSettings::default())
+            .await?
+            .run_migrations()
+            .await
+    }
 
-        if already_has_member {
-            sqlx::query("DELETE FROM group_membership WHERE group_id = ? AND user_id = ?;")
-                .bind(group_id)
-                .bind(user_id)
-                .execute(&self.pool)
-                .await?;
+    async fn run_migrations(self) -> Result<Self> {
+        debug!("Running SQL migrations...");
+        match &self.pool {
+            Pool::Sqlite(pool) => SQLITE_MIGRATIONS.run(pool).await?,
+            Pool::Postgres(pool) => POSTGRES_MIGRATIONS.run(pool).await?,
+        }
+        debug!("SQL migrations complete");
+        Ok(self)
+    }
 
-            Ok(true)
+    /// Connect to the database with the provided url like [`Database::from_url`], without
+    /// running the migrations. See [`Database::migrate`]. SQLite databases are opened with
+    /// `settings`, PostgreSQL databases can't be encrypted.
+    pub async fn connect_unmigrated(url: &str, settings: &SqliteSettings) -> Result<Self> {
+        let pool = if Self::is_postgres_url(url) {
+            if settings.key.is_some() {
+                bail!("Only SQLite databases can be encrypted, unset database.encryption_key");
+            }
+            Pool::Postgres(PgPool::connect(url).await?)
         } else {
-            Ok(false)
-        }
+            Pool::Sqlite(SqlitePool::connect_with(sqlite_options(url, settings)?).await?)
+        };
+        Ok(Self {
+            pool,
+            token_cipher: None,
+        })
     }
 
-    /// Modify the database entry for the given group.
+    /// Apply the migrations that haven't been applied yet, oldest first. If `to` is set, only
+    /// the ones up to and including that version are. With `dry_run`, nothing is applied.
     ///
-    /// The id of the group will not be updated.
-    pub async fn update_group(&self, group: &Group) -> Result<()> {
-        let query_result = sqlx::query(
-            r"
-            UPDATE groups SET name = ?
-            WHERE id = ?;",
-        )
-        .bind(&group.name)
-        .bind(group.id)
-        .execute(&self.pool)
-        .await?;
+    /// Returns the migrations that were (or with `dry_run`, would be) applied.
+    pub async fn migrate(&self, to: Option<i64>, dry_run: bool) -> Result<Vec<MigrationInfo>> {
+        match &self.pool {
+            Pool::Sqlite(pool) => {
+                let mut conn = pool.acquire().await?;
+                apply_migrations(&mut *conn, &SQLITE_MIGRATIONS, to, dry_run).await
+            }
+            Pool::Postgres(pool) => {
+                let mut conn = pool.acquire().await?;
+                apply_migrations(&mut *conn, &POSTGRES_MIGRATIONS, to, dry_run).await
+            }
+        }
+    }
 
-        if query_result.rows_affected() != 1 {
-            bail!(
-                "Update user impacted unexpected number of rows, impacted {} rows",
-                query_result.rows_affected()
-            )
+    /// The SQLite connection pool, which snapshots are taken and restored with
+    fn sqlite_pool(&self) -> Result<&SqlitePool> {
+        match &self.pool {
+            Pool::Sqlite(pool) => Ok(pool),
+            Pool::Postgres(_) => {
+                bail!("Snapshots are only supported with SQLite, use pg_dump with PostgreSQL")
+            }
         }
+    }
 
+    /// Write a consistent copy of the database to `path`, which must not exist yet, readable only
+    /// by the user Hyde runs as. Only SQLite databases can be snapshotted.
+    pub async fn snapshot(&self, path: &Path) -> Result<()> {
+        self.sqlite_pool()?;
+        // SQLite writes into an empty file as is, keeping its permissions
+        create_private_file(path).await?;
+        let path = path
+            .to_str()
+            .wrap_err("The snapshot path must be valid UTF-8")?;
+        sqlx::query("VACUUM INTO $1;")
+            .bind(path)
+            .execute(self.sqlite_pool()?)
+            .await?;
         Ok(())
     }
 
-    /// Delete the provided group (by id). All users that were a member of that group will be removed from the group upon deletion.
-    pub async fn delete_group(&self, group_id: i64) -> Result<()> {
-        let query_result = sqlx::query(r"DELETE FROM groups WHERE id = ?")
-            .bind(group_id)
-            .execute(&self.pool)
+    /// Replace the contents of every table with their contents in the snapshot at `path`,
+    /// created by [`Database::snapshot`]. The snapshot must have been taken by the same version
+    /// of Hyde.
+    pub async fn restore_snapshot(&self, path: &Path) -> Result<()> {
+        let path = path
+            .to_str()
+            .wrap_err("The snapshot path must be valid UTF-8")?;
+        let mut conn = self.sqlite_pool()?.acquire().await?;
+        sqlx::query("ATTACH DATABASE $1 AS snapshot;")
+            .bind(path)
+            .execute(&mut *conn)
+            .await?;
+        let result = Self::copy_from_snapshot(&mut conn).await;
+        sqlx::query("DETACH DATABASE snapshot;")
+            .execute(&mut *conn)
             .await?;
+        result
+    }
 
-        if query_result.rows_affected() != 1 {
-            bail!(
-                "Delete user impacted unexpected number of rows, impacted {} rows",
-                query_result.rows_affected()
-            )
+    /// Write a backup of the database to `path`, which must not exist yet, creating its folder if
+    /// needed. The backup is written under a temporary name first and renamed once it's
+    /// complete, so a backup that fails partway never looks like a finished one.
+    pub async fn backup_to(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            bail!("There's already a file at {}", path.display());
         }
-
+        if let Some(parent) = path.parent() {
+            fs_err::tokio::create_dir_all(parent).await?;
+        }
+        let partial = path.with_extension("partial");
+        if partial.exists() {
+            fs_err::tokio::remove_file(&partial).await?;
+        }
+        if let Err(e) = self.snapshot(&partial).await {
+            let _ = fs_err::tokio::remove_file(&partial).await;
+            return Err(e);
+        }
+        fs_err::tokio::rename(&partial, path).await?;
         Ok(())
     }
 
-    /// Get a list of all of the permissions tied to a particular group.
-    pub async fn get_group_permissions(&self, group_id: i64) -> Result<Vec<Permission>> {
-        let query_result: Vec<GroupPermissions> =
-            sqlx::query_as("SELECT * FROM group_permissions WHERE group_id = ?;")
-                .bind(group_id)
-                .fetch_all(&self.pool)
-                .await?;
+    /// Replace the SQLite database file at `database_path` with the backup at `backup`, taken by
+    /// [`Database::backup_to`]. Used by `--restore` before the database is opened, so it works even
+    /// if the database is too corrupted to open.
+    ///
+    /// The backup is checked for corruption and brought up to date with this version's
+    /// migrations first, backups taken by a newer version of Hyde are rejected. The replaced
+    /// database is kept next to it, its name is returned. Backups of encrypted databases are
+    /// encrypted with the same key, see [`SqliteSettings`].
+    pub async fn restore_backup(
+        database_path: &Path,
+        backup: &Path,
+        settings: &SqliteSettings,
+    ) -> Result<Option<PathBuf>> {
+        if !backup.is_file() {
+            bail!("There's no backup at {}", backup.display());
+        }
+        let restoring = database_path.with_extension("restoring");
+        fs_err::tokio::copy(backup, &restoring).await?;
+        if let Err(e) = Self::check_backup(&restoring, settings).await {
+            let _ = fs_err::tokio::remove_file(&restoring).await;
+            return Err(e);
+        }
 
-        let permissions_vec: Vec<Permission> = query_result
-            .iter()
-            .map(|e| e.permission.as_str().try_into().unwrap())
-            .collect();
+        let replaced = if database_path.exists() {
+            let stamp = chrono::Utc::now().format("%Y-%m-%dT%H-%M-%SZ");
+            let replaced = database_path.with_extension(format!("replaced-{stamp}.db"));
+            fs_err::tokio::rename(database_path, &replaced).await?;
+            Some(replaced)
+        } else {
+            None
+        };
+        // A journal left behind by the replaced database would otherwise be applied to the backup
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let mut journal = database_path.as_os_str().to_owned();
+            journal.push(suffix);
+            let journal = PathBuf::from(journal);
+            if journal.exists() {
+                fs_err::tokio::remove_file(&journal).await?;
+            }
+        }
+        fs_err::tokio::rename(&restoring, database_path).await?;
+        Ok(replaced)
+    }
 
-        Ok(permissions_vec)
+    /// Check the copy of a backup at `path` for corruption and run any migrations it's missing
+    async fn check_backup(path: &Path, settings: &SqliteSettings) -> Result<()> {
+        let url = format!("file:{}?mode=rw", path.display());
+        let pool = SqlitePool::connect_with(sqlite_options(&url, settings)?).await?;
+        let integrity: String = sqlx::query_scalar("PRAGMA integrity_check;")
+            .fetch_one(&pool)
+            .await?;
+        if integrity != "ok" {
+            pool.close().await;
+            bail!("The backup is corrupted: {integrity}");
+        }
+        let migrated = SQLITE_MIGRATIONS
+            .run(&pool)
+            .await
+            .wrap_err("The backup's schema doesn't match this version of Hyde's migrations");
+        pool.close().await;
+        migrated?;
+        Ok(())
     }
 
-    /// Check whether a group has a permission.
-    pub async fn group_has_permission(
-        &self,
-        group_id: i64,
-        permission: Permission,
-    ) -> Result<bool> {
-        let string_permission = String::from(permission);
-        let query_result = sqlx::query(
-            "SELECT * FROM group_permissions WHERE group_id = ? AND permission = ? LIMIT 1;",
+    /// Copy every table from the attached `snapshot` database, in a single transaction
+    async fn copy_from_snapshot(conn: &mut SqliteConnection) -> Result<()> {
+        let latest_migration = "SELECT MAX(version) FROM {}_sqlx_migrations WHERE success = 1;";
+        let ours: Option<i64> = sqlx::query_scalar(&latest_migration.replace("{}", "main."))
+            .fetch_one(&mut *conn)
+            .await?;
+        let theirs: Option<i64> = sqlx::query_scalar(&latest_migration.replace("{}", "snapshot."))
+            .fetch_one(&mut *conn)
+            .await?;
+        if ours != theirs {
+            bail!(
+                "The snapshot's schema (migration {theirs:?}) doesn't match this database's \
+                (migration {ours:?}), both instances must run the same version of Hyde"
+            );
+        }
+        // The search index and its FTS5 shadow tables are derived from the repository, which the
+        // replica pulls and indexes itself
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM main.sqlite_master WHERE type = 'table' \
+            AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations' \
+            AND name NOT LIKE 'doc_search%';",
         )
-        .bind(group_id)
-        .bind(string_permission)
-        .fetch_optional(&self.pool)
+        .fetch_all(&mut *conn)
         .await?;
 
-        match query_result {
-            Some(_) => Ok(true),
-            None => Ok(false),
+        let mut transaction = conn.begin().await?;
+        // Rows are copied in whatever order, so references are only checked once all of them are
+        sqlx::query("PRAGMA defer_foreign_keys = ON;")
+            .execute(&mut *transaction)
+            .await?;
+        // Everything is deleted first, so cascading deletes can't remove copied rows
+        for table in &tables {
+            sqlx::query(&format!("DELETE FROM main.\"{table}\";"))
+                .execute(&mut *transaction)
+                .await?;
+        }
+        for table in &tables {
+            sqlx::query(&format!(
+                "INSERT INTO main.\"{table}\" SELECT * FROM snapshot.\"{table}\";"
+            ))
+            .execute(&mut *transaction)
+            .await?;
         }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Add a new user to the database, returning the created user. This does not overwrite an existing user
+    pub async fn create_user(
+        &self,
+        username: String,
+        token: String,
+        expiration_date: DateTime<Utc>,
+        avatar_url: String,
+    ) -> Result<User> {
+        with_pool!(self, |pool| {
+            let query_results: User = sqlx::query_as(
+                r"
+                INSERT INTO users (username, token, expiration_date, avatar_url)
+                VALUES ($1, $2, $3, $4) RETURNING *;
+                ",
+            )
+            .bind(username)
+            .bind(self.seal_token(&token)?)
+            .bind(expiration_date)
+            .bind(avatar_url)
+            .fetch_one(pool)
+            .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Create a service account, see [`User::service_account`]
+    pub async fn create_service_account(&self, username: &str) -> Result<User> {
+        with_pool!(self, |pool| {
+            let query_results: User = sqlx::query_as(
+                r"
+                INSERT INTO users (username, token, expiration_date, avatar_url, service_account)
+                VALUES ($1, '', $2, $3, TRUE) RETURNING *;
+                ",
+            )
+            .bind(username)
+            .bind(DateTime::<Utc>::UNIX_EPOCH)
+            .bind(DEFAULT_AVATAR_URL)
+            .fetch_one(pool)
+            .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns a user from the database associated with the provided user id.
+    pub async fn get_user(&self, user_id: i64) -> Result<Option<User>> {
+        with_pool!(self, |pool| {
+            let query_results: Option<User> =
+                sqlx::query_as(r"SELECT * FROM  users WHERE id = $1;")
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await?;
+            Ok(query_results)
+        })
+    }
+
+    /// Returns a list of all groups a user is a member of.
+    pub async fn get_user_groups(&self, user_id: i64) -> Result<Vec<Group>> {
+        let now = Utc::now();
+        with_pool!(self, |pool| {
+            let groups: Vec<Group> = sqlx::query_as(
+                "SELECT groups.* FROM group_membership 
+                RIGHT JOIN groups ON group_membership.group_id = groups.id
+                WHERE group_membership.user_id = $1
+                AND (group_membership.expires_at IS NULL
+                    OR group_membership.expires_at > $2)
+                ORDER BY groups.id;",
+            )
+            .bind(user_id)
+            .bind(now)
+            .fetch_all(pool)
+            .await?;
+
+            Ok(groups)
+        })
+    }
+
+    /// Returns a list of all of the permissions a user has, leaving out expired grants and
+    /// memberships, and the permissions denied to them or one of their groups by a deny rule
+    /// that hasn't expired.
+    pub async fn get_user_permissions(&self, user_id: i64) -> Result<Vec<Permission>> {
+        let now = Utc::now();
+        with_pool!(self, |pool| {
+            let query_result: Vec<GroupPermissions> = sqlx::query_as(
+                "SELECT DISTINCT gp.* FROM group_permissions gp
+                INNER JOIN group_membership gm ON gp.group_id = gm.group_id WHERE gm.user_id = $1
+                AND (gp.expires_at IS NULL OR gp.expires_at > $2)
+                AND (gm.expires_at IS NULL OR gm.expires_at > $2)
+                AND gp.permission NOT IN (
+                    SELECT pd.permission FROM permission_denies pd
+                    WHERE (pd.user_id = $1 OR pd.group_id IN (
+                        SELECT group_id FROM group_membership WHERE user_id = $1
+                        AND (expires_at IS NULL
+                            OR expires_at > $2)
+                    ))
+                    AND (pd.expires_at IS NULL
+                        OR pd.expires_at > $2)
+                );",
+            )
+            .bind(user_id)
+            .bind(now)
+            .fetch_all(pool)
+            .await?;
+
+            let permissions_vec = query_result
+                .into_iter()
+                .map(|e| Permission::from(e.permission.as_str()))
+                .collect();
+
+            Ok(permissions_vec)
+        })
+    }
+
+    /// Returns a list of every user in the database.
+    pub async fn get_all_users(&self) -> Result<Vec<User>> {
+        with_pool!(self, |pool| {
+            let query_results: Vec<User> = sqlx::query_as(r"SELECT * FROM users;")
+                .fetch_all(pool)
+                .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Refresh the database entry for the provided user based off of the user id.
+    ///
+    /// The ID of the user will not be updated.
+    pub async fn update_user(&self, user: &User) -> Result<()> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                r"
+                UPDATE users SET username = $1, token = $2, expiration_date = $3
+                WHERE id = $4;",
+            )
+            .bind(&user.username)
+            .bind(self.seal_token(&user.token)?)
+            .bind(user.expiration_date)
+            .bind(user.id)
+            .execute(pool)
+            .await?;
+
+            if query_result.rows_affected() != 1 {
+                bail!(
+                    "Update user impacted unexpected number of rows, impacted {} rows",
+                    query_result.rows_affected()
+                )
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Returns the Discord access token stored for a user, decrypted, if there is one. [`User`]s
+    /// are read with the token as it's stored.
+    pub async fn get_access_token(&self, user_id: i64) -> Result<Option<String>> {
+        with_pool!(self, |pool| {
+            let token: Option<String> =
+                sqlx::query_scalar("SELECT token FROM users WHERE id = $1;")
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await?;
+            token
+                .filter(|t| !t.is_empty())
+                .map(|t| self.open_token(&t))
+                .transpose()
+        })
+    }
+
+    /// Returns the Discord refresh token stored for a user, if there is one.
+    ///
+    /// It isn't part of [`User`] so that it's never serialized into a response.
+    pub async fn get_refresh_token(&self, user_id: i64) -> Result<Option<String>> {
+        with_pool!(self, |pool| {
+            let refresh_token: Option<Option<String>> =
+                sqlx::query_scalar("SELECT refresh_token FROM users WHERE id = $1;")
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await?;
+            refresh_token
+                .flatten()
+                .map(|t| self.open_token(&t))
+                .transpose()
+        })
+    }
+
+    /// Replace (or with `None`, remove) the Discord refresh token stored for a user.
+    pub async fn set_refresh_token(&self, user_id: i64, refresh_token: Option<&str>) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query("UPDATE users SET refresh_token = $1 WHERE id = $2;")
+                .bind(refresh_token.map(|t| self.seal_token(t)).transpose()?)
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Deactivate a user at `now`, ending their sessions. Their row is kept so the history
+    /// they're attributed in keeps their name, see [`Self::purge_user`].
+    ///
+    /// Returns `false` if there's no such user, or they were already deactivated.
+    pub async fn deactivate_user(&self, user_id: i64, now: DateTime<Utc>) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            let query_result = sqlx::query(
+                "UPDATE users SET deactivated_at = $1 WHERE id = $2 AND deactivated_at IS NULL;",
+            )
+            .bind(now)
+            .bind(user_id)
+            .execute(&mut *transaction)
+            .await?;
+            sqlx::query("DELETE FROM sessions WHERE user_id = $1;")
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Let a deactivated user sign in again.
+    ///
+    /// Returns `false` if there's no such user, or they weren't deactivated.
+    pub async fn reactivate_user(&self, user_id: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                "UPDATE users SET deactivated_at = NULL WHERE id = $1 AND deactivated_at IS NOT NULL;",
+            )
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Permanently delete the user associated with the provided user ID from the database, along
+    /// with everything that belongs to them. Users should usually be deactivated instead, see
+    /// [`Self::deactivate_user`].
+    pub async fn purge_user(&self, user_id: i64) -> Result<()> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(r"DELETE FROM users WHERE id = $1")
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+
+            if query_result.rows_affected() != 1 {
+                bail!(
+                    "Purge user impacted unexpected number of rows, impacted {} rows",
+                    query_result.rows_affected()
+                )
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Link a GitHub account to a user, replacing the account they previously linked.
+    ///
+    /// # Errors
+    /// This function returns an error if the account is already linked to a different user.
+    pub async fn link_github_account(&self, user_id: i64, account: &GitHubAccount) -> Result<()> {
+        with_pool!(self, |pool| {
+            let linked_to: Option<(i64,)> =
+                sqlx::query_as(r"SELECT id FROM users WHERE github_id = $1 AND id != $2;")
+                    .bind(account.github_id)
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await?;
+            if let Some((other_user,)) = linked_to {
+                bail!(
+                    "GitHub account {:?} is already linked to user {other_user}",
+                    account.github_login
+                );
+            }
+            let mut transaction = pool.begin().await?;
+            let query_result =
+                sqlx::query(r"UPDATE users SET github_id = $1, github_login = $2 WHERE id = $3;")
+                    .bind(account.github_id)
+                    .bind(&account.github_login)
+                    .bind(user_id)
+                    .execute(&mut *transaction)
+                    .await?;
+            if query_result.rows_affected() != 1 {
+                bail!("No user with the id {user_id} exists");
+            }
+            // The linked account can also be signed in with
+            sqlx::query("DELETE FROM user_identities WHERE user_id = $1 AND provider = 'github';")
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query(
+                "INSERT INTO user_identities (provider, subject, user_id, username)
+                VALUES ('github', $1, $2, $3);",
+            )
+            .bind(account.github_id.to_string())
+            .bind(user_id)
+            .bind(&account.github_login)
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Remove the GitHub account linked to a user, if there is one.
+    pub async fn unlink_github_account(&self, user_id: i64) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query(r"UPDATE users SET github_id = NULL, github_login = NULL WHERE id = $1;")
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query("DELETE FROM user_identities WHERE user_id = $1 AND provider = 'github';")
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Returns the user an account on an identity provider is linked to, if it's linked.
+    pub async fn get_identity_user(&self, provider: &str, subject: &str) -> Result<Option<User>> {
+        with_pool!(self, |pool| {
+            let user: Option<User> = sqlx::query_as(
+                "SELECT users.* FROM user_identities
+                INNER JOIN users ON user_identities.user_id = users.id
+                WHERE user_identities.provider = $1 AND user_identities.subject = $2;",
+            )
+            .bind(provider)
+            .bind(subject)
+            .fetch_optional(pool)
+            .await?;
+            Ok(user)
+        })
+    }
+
+    /// Returns every account a user can sign in with.
+    pub async fn get_user_identities(&self, user_id: i64) -> Result<Vec<UserIdentity>> {
+        with_pool!(self, |pool| {
+            let identities: Vec<UserIdentity> = sqlx::query_as(
+                "SELECT * FROM user_identities WHERE user_id = $1 ORDER BY provider;",
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+            Ok(identities)
+        })
+    }
+
+    /// Link an account to a user, replacing any account from the same provider they'd linked.
+    /// Use [`Database::link_github_account`] for GitHub accounts.
+    ///
+    /// # Errors
+    /// This function returns an error if the account is already linked to a different user.
+    pub async fn link_identity(&self, identity: &UserIdentity) -> Result<()> {
+        with_pool!(self, |pool| {
+            if let Some(other_user) = self
+                .get_identity_user(&identity.provider, &identity.subject)
+                .await?
+                .filter(|u| u.id != identity.user_id)
+            {
+                bail!(
+                    "The {} account {:?} is already linked to user {}",
+                    identity.provider,
+                    identity.username,
+                    other_user.id
+                );
+            }
+            let mut transaction = pool.begin().await?;
+            sqlx::query("DELETE FROM user_identities WHERE user_id = $1 AND provider = $2;")
+                .bind(identity.user_id)
+                .bind(&identity.provider)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query(
+                "INSERT INTO user_identities (provider, subject, user_id, username)
+                VALUES ($1, $2, $3, $4);",
+            )
+            .bind(&identity.provider)
+            .bind(&identity.subject)
+            .bind(identity.user_id)
+            .bind(&identity.username)
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Unlink a user's account from a provider. Use [`Database::unlink_github_account`] for
+    /// GitHub accounts.
+    ///
+    /// Returns `false` if they hadn't linked an account from that provider.
+    pub async fn unlink_identity(&self, user_id: i64, provider: &str) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result =
+                sqlx::query("DELETE FROM user_identities WHERE user_id = $1 AND provider = $2;")
+                    .bind(user_id)
+                    .bind(provider)
+                    .execute(pool)
+                    .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Returns the GitHub account linked to a user, if they've linked one.
+    pub async fn get_github_account(&self, user_id: i64) -> Result<Option<GitHubAccount>> {
+        with_pool!(self, |pool| {
+            let account = sqlx::query_as(
+            r"SELECT github_id, github_login FROM users WHERE id = $1 AND github_id IS NOT NULL;",
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+            Ok(account)
+        })
+    }
+
+    /// Create a group, returning the created group upon completion.
+    pub async fn create_group(&self, group_name: String) -> Result<Group> {
+        with_pool!(self, |pool| {
+            let query_results: Group = sqlx::query_as(
+                r"
+                INSERT INTO groups (name) VALUES ($1) RETURNING *;
+                ",
+            )
+            .bind(group_name)
+            .fetch_one(pool)
+            .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns a group from the database associated with the provided
+    /// group id.
+    pub async fn get_group(&self, group_id: i64) -> Result<Option<Group>> {
+        with_pool!(self, |pool| {
+            let query_results: Option<Group> =
+                sqlx::query_as("SELECT * FROM groups WHERE id = $1 LIMIT 1;")
+                    .bind(group_id)
+                    .fetch_optional(pool)
+                    .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns a list of every group in the database.
+    pub async fn get_all_groups(&self) -> Result<Vec<Group>> {
+        with_pool!(self, |pool| {
+            let query_results: Vec<Group> = sqlx::query_as(r"SELECT * FROM groups;")
+                .fetch_all(pool)
+                .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns a list of every member in the provided group (by id).
+    pub async fn get_group_members(&self, group_id: i64) -> Result<Vec<User>> {
+        with_pool!(self, |pool| {
+            let users: Vec<User> = sqlx::query_as(
+                "SELECT users.* FROM group_membership 
+                RIGHT JOIN users ON group_membership.user_id = users.id
+                WHERE group_membership.group_id = $1 ORDER BY users.id;",
+            )
+            .bind(group_id)
+            .fetch_all(pool)
+            .await?;
+
+            Ok(users)
+        })
+    }
+
+    /// Whether a user is a member of a group.
+    pub async fn group_has_member(&self, group_id: i64, user_id: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                "SELECT * FROM group_membership WHERE group_id = $1 AND user_id = $2 LIMIT 1;",
+            )
+            .bind(group_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+            match query_result {
+                Some(_) => Ok(true),
+                None => Ok(false),
+            }
+        })
+    }
+
+    /// Add a user to a group (by id).
+    ///
+    /// Returns true if the user was added successfully, returns false if the user is already
+    /// a member of the group.
+    pub async fn add_group_membership(&self, group_id: i64, user_id: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let already_has_member = self.group_has_member(group_id, user_id).await?;
+
+            if already_has_member {
+                Ok(false)
+            } else {
+                sqlx::query("INSERT INTO group_membership (group_id, user_id) VALUES ($1, $2);")
+                    .bind(group_id)
+                    .bind(user_id)
+                    .execute(pool)
+                    .await?;
+
+                Ok(true)
+            }
+        })
+    }
+
+    /// Make a user's membership of a group expire at `expires_at`, or never if it's `None`.
+    pub async fn set_group_membership_expiry(
+        &self,
+        group_id: i64,
+        user_id: i64,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query(
+                "UPDATE group_membership SET expires_at = $1 WHERE group_id = $2 AND user_id = $3;",
+            )
+            .bind(expires_at)
+            .bind(group_id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// Remove a user from a group (by id).
+    ///
+    /// Returns `true` if the user was removed successfully, returns `false` if the user was not
+    /// a member of the provided group.
+    pub async fn remove_group_membership(&self, group_id: i64, user_id: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let already_has_member = self.group_has_member(group_id, user_id).await?;
+
+            if already_has_member {
+                sqlx::query("DELETE FROM group_membership WHERE group_id = $1 AND user_id = $2;")
+                    .bind(group_id)
+                    .bind(user_id)
+                    .execute(pool)
+                    .await?;
+
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })
+    }
+
+    /// Add a user to several groups at once, making the memberships expire at `expires_at`, or
+    /// never if it's `None`. Either every membership is added or none are.
+    ///
+    /// Returns the ids of the groups the user wasn't already a member of.
+    pub async fn add_group_memberships(
+        &self,
+        user_id: i64,
+        group_ids: &[i64],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Vec<i64>> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            let mut added = Vec::new();
+            for &group_id in group_ids {
+                let updated = sqlx::query(
+                    "UPDATE group_membership SET expires_at = $1 WHERE group_id = $2 AND user_id = $3;",
+                )
+                .bind(expires_at)
+                .bind(group_id)
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+                if updated == 0 {
+                    sqlx::query(
+                        "INSERT INTO group_membership (group_id, user_id, expires_at) VALUES ($1, $2, $3);",
+                    )
+                    .bind(group_id)
+                    .bind(user_id)
+                    .bind(expires_at)
+                    .execute(&mut *transaction)
+                    .await?;
+                    added.push(group_id);
+                }
+            }
+            transaction.commit().await?;
+            Ok(added)
+        })
+    }
+
+    /// Remove a user from several groups at once. Either every membership is removed or none are.
+    ///
+    /// Returns the ids of the groups the user was a member of.
+    pub async fn remove_group_memberships(
+        &self,
+        user_id: i64,
+        group_ids: &[i64],
+    ) -> Result<Vec<i64>> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            let mut removed = Vec::new();
+            for &group_id in group_ids {
+                let deleted = sqlx::query(
+                    "DELETE FROM group_membership WHERE group_id = $1 AND user_id = $2;",
+                )
+                .bind(group_id)
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+                if deleted > 0 {
+                    removed.push(group_id);
+                }
+            }
+            transaction.commit().await?;
+            Ok(removed)
+        })
+    }
+
+    /// Modify the database entry for the given group.
+    ///
+    /// The id of the group will not be updated.
+    pub async fn update_group(&self, group: &Group) -> Result<()> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                r"
+                UPDATE groups SET name = $1
+                WHERE id = $2;",
+            )
+            .bind(&group.name)
+            .bind(group.id)
+            .execute(pool)
+            .await?;
+
+            if query_result.rows_affected() != 1 {
+                bail!(
+                    "Update user impacted unexpected number of rows, impacted {} rows",
+                    query_result.rows_affected()
+                )
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Delete the provided group (by id). All users that were a member of that group will be removed from the group upon deletion.
+    pub async fn delete_group(&self, group_id: i64) -> Result<()> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(r"DELETE FROM groups WHERE id = $1")
+                .bind(group_id)
+                .execute(pool)
+                .await?;
+
+            if query_result.rows_affected() != 1 {
+                bail!(
+                    "Delete user impacted unexpected number of rows, impacted {} rows",
+                    query_result.rows_affected()
+                )
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Get a list of all of the permissions tied to a particular group.
+    pub async fn get_group_permissions(&self, group_id: i64) -> Result<Vec<Permission>> {
+        with_pool!(self, |pool| {
+            let query_result: Vec<GroupPermissions> =
+                sqlx::query_as("SELECT * FROM group_permissions WHERE group_id = $1;")
+                    .bind(group_id)
+                    .fetch_all(pool)
+                    .await?;
+
+            let permissions_vec: Vec<Permission> = query_result
+                .iter()
+                .map(|e| Permission::from(e.permission.as_str()))
+                .collect();
+
+            Ok(permissions_vec)
+        })
+    }
+
+    /// Check whether a group has a permission.
+    pub async fn group_has_permission(
+        &self,
+        group_id: i64,
+        permission: Permission,
+    ) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let string_permission = String::from(permission);
+            let query_result = sqlx::query(
+                "SELECT * FROM group_permissions WHERE group_id = $1 AND permission = $2 LIMIT 1;",
+            )
+            .bind(group_id)
+            .bind(string_permission)
+            .fetch_optional(pool)
+            .await?;
+
+            match query_result {
+                Some(_) => Ok(true),
+                None => Ok(false),
+            }
+        })
     }
 
     /// Assign a group a new permission.
@@ -393,21 +1690,27 @@ impl Database {
         group_id: i64,
         permission: Permission,
     ) -> Result<bool> {
-        let already_has_permission = self.group_has_permission(group_id, permission).await?;
+        with_pool!(self, |pool| {
+            let already_has_permission = self
+                .group_has_permission(group_id, permission.clone())
+                .await?;
 
-        if already_has_permission {
-            Ok(false)
-        } else {
-            let string_permission = String::from(permission);
+            if already_has_permission {
+                Ok(false)
+            } else {
+                let string_permission = String::from(permission);
 
-            sqlx::query("INSERT INTO group_permissions (group_id, permission) VALUES (?, ?);")
+                sqlx::query(
+                    "INSERT INTO group_permissions (group_id, permission) VALUES ($1, $2);",
+                )
                 .bind(group_id)
                 .bind(string_permission)
-                .execute(&self.pool)
+                .execute(pool)
                 .await?;
 
-            Ok(true)
-        }
+                Ok(true)
+            }
+        })
     }
 
     /// Remove a permission from a group.
@@ -419,399 +1722,4308 @@ impl Database {
         group_id: i64,
         permission: Permission,
     ) -> Result<bool> {
-        let already_has_permission = self.group_has_permission(group_id, permission).await?;
+        with_pool!(self, |pool| {
+            let already_has_permission = self
+                .group_has_permission(group_id, permission.clone())
+                .await?;
 
-        if already_has_permission {
-            let string_permission = String::from(permission);
+            if already_has_permission {
+                let string_permission = String::from(permission);
 
-            sqlx::query("DELETE FROM group_permissions WHERE group_id = ? AND permission = ?;")
+                sqlx::query(
+                    "DELETE FROM group_permissions WHERE group_id = $1 AND permission = $2;",
+                )
                 .bind(group_id)
                 .bind(string_permission)
-                .execute(&self.pool)
+                .execute(pool)
                 .await?;
 
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Revoke `remove` from a group and grant it `add`, expiring at `expires_at` or never if it's
+    /// `None`. Either every change is made or none are.
+    pub async fn update_group_permissions(
+        &self,
+        group_id: i64,
+        remove: &[Permission],
+        add: &[Permission],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            for permission in remove {
+                sqlx::query(
+                    "DELETE FROM group_permissions WHERE group_id = $1 AND permission = $2;",
+                )
+                .bind(group_id)
+                .bind(String::from(permission.clone()))
+                .execute(&mut *transaction)
+                .await?;
+            }
+            for permission in add {
+                let permission = String::from(permission.clone());
+                let updated = sqlx::query(
+                    "UPDATE group_permissions SET expires_at = $1 WHERE group_id = $2 AND permission = $3;",
+                )
+                .bind(expires_at)
+                .bind(group_id)
+                .bind(&permission)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+                if updated == 0 {
+                    sqlx::query(
+                        "INSERT INTO group_permissions (group_id, permission, expires_at) VALUES ($1, $2, $3);",
+                    )
+                    .bind(group_id)
+                    .bind(&permission)
+                    .bind(expires_at)
+                    .execute(&mut *transaction)
+                    .await?;
+                }
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Store performance metrics.
+    pub async fn record_telemetry(&self, samples: &[TelemetrySample]) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            for sample in samples {
+                sqlx::query(
+                    "INSERT INTO telemetry (source, metric, value, recorded_at)
+                    VALUES ($1, $2, $3, $4);",
+                )
+                .bind(&sample.source)
+                .bind(&sample.metric)
+                .bind(sample.value)
+                .bind(sample.recorded_at)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Returns every performance metric recorded at or after `since`.
+    pub async fn get_telemetry(&self, since: DateTime<Utc>) -> Result<Vec<TelemetrySample>> {
+        with_pool!(self, |pool| {
+            let samples: Vec<TelemetrySample> = sqlx::query_as(
+                "SELECT source, metric, value, recorded_at FROM telemetry WHERE recorded_at >= $1;",
+            )
+            .bind(since)
+            .fetch_all(pool)
+            .await?;
+            Ok(samples)
+        })
+    }
+
+    /// Delete the performance metrics recorded before `before`.
+    ///
+    /// Returns the number of samples deleted.
+    pub async fn prune_telemetry(&self, before: DateTime<Utc>) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM telemetry WHERE recorded_at < $1;")
+                .bind(before)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Returns the groups whose membership is managed by members of `group_id`.
+    pub async fn get_group_admin_scopes(&self, group_id: i64) -> Result<Vec<i64>> {
+        with_pool!(self, |pool| {
+            let managed_groups: Vec<i64> = sqlx::query_scalar(
+                "SELECT managed_group_id FROM group_admin_scopes WHERE group_id = $1
+                ORDER BY managed_group_id;",
+            )
+            .bind(group_id)
+            .fetch_all(pool)
+            .await?;
+            Ok(managed_groups)
+        })
+    }
+
+    /// Replace the groups whose membership is managed by members of `group_id`.
+    pub async fn set_group_admin_scopes(
+        &self,
+        group_id: i64,
+        managed_group_ids: &[i64],
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query("DELETE FROM group_admin_scopes WHERE group_id = $1;")
+                .bind(group_id)
+                .execute(&mut *transaction)
+                .await?;
+            for managed_group_id in managed_group_ids {
+                sqlx::query(
+                    "INSERT INTO group_admin_scopes (group_id, managed_group_id) VALUES ($1, $2)
+                    ON CONFLICT DO NOTHING;",
+                )
+                .bind(group_id)
+                .bind(managed_group_id)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Returns the ids of the users who own `group_id`.
+    pub async fn get_group_owners(&self, group_id: i64) -> Result<Vec<i64>> {
+        with_pool!(self, |pool| {
+            let owners: Vec<i64> = sqlx::query_scalar(
+                "SELECT user_id FROM group_owners WHERE group_id = $1 ORDER BY user_id;",
+            )
+            .bind(group_id)
+            .fetch_all(pool)
+            .await?;
+            Ok(owners)
+        })
+    }
+
+    /// Replace the owners of `group_id`.
+    pub async fn set_group_owners(&self, group_id: i64, user_ids: &[i64]) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query("DELETE FROM group_owners WHERE group_id = $1;")
+                .bind(group_id)
+                .execute(&mut *transaction)
+                .await?;
+            for user_id in user_ids {
+                sqlx::query(
+                    "INSERT INTO group_owners (group_id, user_id) VALUES ($1, $2)
+                    ON CONFLICT DO NOTHING;",
+                )
+                .bind(group_id)
+                .bind(user_id)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Deny `permission` to a user or, if `user_id` is `None`, to every member of `group_id`.
+    /// Returns the id of the rule.
+    pub async fn add_permission_deny(
+        &self,
+        user_id: Option<i64>,
+        group_id: Option<i64>,
+        permission: Permission,
+        expires_at: Option<DateTime<Utc>>,
+        created_at: DateTime<Utc>,
+    ) -> Result<i64> {
+        with_pool!(self, |pool| {
+            let id: i64 = sqlx::query_scalar(
+            "INSERT INTO permission_denies (user_id, group_id, permission, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id;",
+        )
+        .bind(user_id)
+        .bind(group_id)
+        .bind(String::from(permission))
+        .bind(expires_at)
+        .bind(created_at)
+        .fetch_one(pool)
+        .await?;
+            Ok(id)
+        })
+    }
+
+    /// Returns every deny rule, including expired ones, newest first.
+    pub async fn get_permission_denies(&self) -> Result<Vec<PermissionDeny>> {
+        with_pool!(self, |pool| {
+            let denies: Vec<PermissionDeny> =
+                sqlx::query_as("SELECT * FROM permission_denies ORDER BY id DESC;")
+                    .fetch_all(pool)
+                    .await?;
+            Ok(denies)
+        })
+    }
+
+    /// Remove a deny rule. Returns whether it existed.
+    pub async fn delete_permission_deny(&self, id: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let result = sqlx::query("DELETE FROM permission_denies WHERE id = $1;")
+                .bind(id)
+                .execute(pool)
+                .await?;
+            Ok(result.rows_affected() > 0)
+        })
+    }
+
+    /// Make a group's permission expire at `expires_at`, or never if it's `None`.
+    pub async fn set_group_permission_expiry(
+        &self,
+        group_id: i64,
+        permission: Permission,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query(
+            "UPDATE group_permissions SET expires_at = $1 WHERE group_id = $2 AND permission = $3;",
+        )
+        .bind(expires_at)
+        .bind(group_id)
+        .bind(String::from(permission))
+        .execute(pool)
+        .await?;
+            Ok(())
+        })
+    }
+
+    /// Delete the permissions and memberships that expired before `now`. Returns how many were
+    /// deleted.
+    pub async fn delete_expired_grants(&self, now: DateTime<Utc>) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            let permissions = sqlx::query("DELETE FROM group_permissions WHERE expires_at <= $1;")
+                .bind(now)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+            let memberships = sqlx::query("DELETE FROM group_membership WHERE expires_at <= $1;")
+                .bind(now)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+            transaction.commit().await?;
+            Ok(permissions + memberships)
+        })
+    }
+
+    /// Delete group memberships whose user or group no longer exists. Deleting either cascades
+    /// to their memberships, so these are only left behind by databases from before foreign
+    /// keys were enforced.
+    ///
+    /// Returns how many were deleted.
+    pub async fn delete_orphaned_memberships(&self) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                "DELETE FROM group_membership
+                WHERE user_id NOT IN (SELECT id FROM users)
+                OR group_id NOT IN (SELECT id FROM groups);",
+            )
+            .execute(pool)
+            .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Returns the branch patterns members of `group_id` may push to, see [`crate::branch_rules`].
+    pub async fn get_group_branch_rules(&self, group_id: i64) -> Result<Vec<String>> {
+        with_pool!(self, |pool| {
+            let patterns: Vec<String> = sqlx::query_scalar(
+                "SELECT pattern FROM group_branch_rules WHERE group_id = $1 ORDER BY pattern;",
+            )
+            .bind(group_id)
+            .fetch_all(pool)
+            .await?;
+            Ok(patterns)
+        })
+    }
+
+    /// Returns every group's branch patterns, as `(group_id, pattern)`.
+    pub async fn get_all_branch_rules(&self) -> Result<Vec<(i64, String)>> {
+        with_pool!(self, |pool| {
+            let rules: Vec<(i64, String)> = sqlx::query_as(
+                "SELECT group_id, pattern FROM group_branch_rules ORDER BY group_id;",
+            )
+            .fetch_all(pool)
+            .await?;
+            Ok(rules)
+        })
+    }
+
+    /// Replace the branch patterns members of `group_id` may push to.
+    pub async fn set_group_branch_rules(&self, group_id: i64, patterns: &[String]) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query("DELETE FROM group_branch_rules WHERE group_id = $1;")
+                .bind(group_id)
+                .execute(&mut *transaction)
+                .await?;
+            for pattern in patterns {
+                sqlx::query(
+                    "INSERT INTO group_branch_rules (group_id, pattern) VALUES ($1, $2)
+                    ON CONFLICT DO NOTHING;",
+                )
+                .bind(group_id)
+                .bind(pattern)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Returns every group whose membership a user may manage, through the groups they're in or
+    /// because they own it.
+    pub async fn get_user_managed_groups(&self, user_id: i64) -> Result<Vec<i64>> {
+        let now = Utc::now();
+        with_pool!(self, |pool| {
+            let managed_groups: Vec<i64> = sqlx::query_scalar(
+                "SELECT gas.managed_group_id FROM group_admin_scopes gas
+                INNER JOIN group_membership gm ON gas.group_id = gm.group_id
+                WHERE gm.user_id = $1
+                AND (gm.expires_at IS NULL
+                    OR gm.expires_at > $2)
+                UNION
+                SELECT group_id FROM group_owners WHERE user_id = $1
+                ORDER BY 1;",
+            )
+            .bind(user_id)
+            .bind(now)
+            .fetch_all(pool)
+            .await?;
+            Ok(managed_groups)
+        })
+    }
+
+    /// Assign an owner to the provided document or directory path. Pass exactly one of
+    /// `user_id` or `group_id`.
+    ///
+    /// Returns `true` if the owner was added, returns `false` if they already owned that path.
+    pub async fn add_doc_owner(
+        &self,
+        path: &str,
+        user_id: Option<i64>,
+        group_id: Option<i64>,
+    ) -> Result<bool> {
+        with_pool!(self, |pool| {
+            if user_id.is_some() == group_id.is_some() {
+                bail!("A document owner must be exactly one of a user or a group");
+            }
+            let already_owner = sqlx::query(
+                "SELECT * FROM doc_owners WHERE path = $1 AND user_id IS NOT DISTINCT FROM $2
+                AND group_id IS NOT DISTINCT FROM $3 LIMIT 1;",
+            )
+            .bind(path)
+            .bind(user_id)
+            .bind(group_id)
+            .fetch_optional(pool)
+            .await?
+            .is_some();
+
+            if already_owner {
+                Ok(false)
+            } else {
+                sqlx::query(
+                    "INSERT INTO doc_owners (path, user_id, group_id) VALUES ($1, $2, $3);",
+                )
+                .bind(path)
+                .bind(user_id)
+                .bind(group_id)
+                .execute(pool)
+                .await?;
+                Ok(true)
+            }
+        })
+    }
+
+    /// Remove every owner assigned directly to the provided path.
+    ///
+    /// Returns the number of ownership entries removed.
+    pub async fn remove_doc_owners(&self, path: &str) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM doc_owners WHERE path = $1;")
+                .bind(path)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Returns every ownership entry in the database.
+    pub async fn get_all_doc_owners(&self) -> Result<Vec<DocOwner>> {
+        with_pool!(self, |pool| {
+            let query_results: Vec<DocOwner> =
+                sqlx::query_as("SELECT * FROM doc_owners ORDER BY path;")
+                    .fetch_all(pool)
+                    .await?;
+            Ok(query_results)
+        })
+    }
+
+    /// Store a new session, returning it upon completion. The session's `id` must already be
+    /// hashed.
+    pub async fn create_session(&self, session: &Session) -> Result<Session> {
+        with_pool!(self, |pool| {
+            let query_results: Session = sqlx::query_as(
+            r"
+            INSERT INTO sessions
+                (id, user_id, created_at, expiration_date, provider, last_used_at, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *;
+            ",
+        )
+        .bind(&session.id)
+        .bind(session.user_id)
+        .bind(session.created_at)
+        .bind(session.expiration_date)
+        .bind(&session.provider)
+        .bind(session.last_used_at)
+        .bind(&session.ip_address)
+        .bind(&session.user_agent)
+        .fetch_one(pool)
+        .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns the session whose id hashes to `id_hash`.
+    pub async fn get_session_by_hash(&self, id_hash: &str) -> Result<Option<Session>> {
+        with_pool!(self, |pool| {
+            let query_results: Option<Session> =
+                sqlx::query_as("SELECT * FROM sessions WHERE id = $1;")
+                    .bind(id_hash)
+                    .fetch_optional(pool)
+                    .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Move the expiration date of the session whose id hashes to `id_hash` to
+    /// `expiration_date`.
+    pub async fn extend_session(
+        &self,
+        id_hash: &str,
+        expiration_date: DateTime<Utc>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query("UPDATE sessions SET expiration_date = $1 WHERE id = $2;")
+                .bind(expiration_date)
+                .bind(id_hash)
+                .execute(pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Returns every session that hasn't expired at `now`, most recently created first.
+    pub async fn get_active_sessions(&self, now: DateTime<Utc>) -> Result<Vec<Session>> {
+        with_pool!(self, |pool| {
+            let sessions: Vec<Session> = sqlx::query_as(
+                "SELECT * FROM sessions WHERE expiration_date >= $1 ORDER BY created_at DESC;",
+            )
+            .bind(now)
+            .fetch_all(pool)
+            .await?;
+            Ok(sessions)
+        })
+    }
+
+    /// Returns every session of a user that hasn't expired at `now`, most recently created
+    /// first.
+    pub async fn get_user_sessions(
+        &self,
+        user_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Session>> {
+        with_pool!(self, |pool| {
+            let sessions: Vec<Session> = sqlx::query_as(
+                "SELECT * FROM sessions WHERE user_id = $1 AND expiration_date >= $2
+                ORDER BY created_at DESC;",
+            )
+            .bind(user_id)
+            .bind(now)
+            .fetch_all(pool)
+            .await?;
+            Ok(sessions)
+        })
+    }
+
+    /// Record that the session whose id hashes to `id_hash` was used at `now`, unless it was
+    /// already recorded as used since `a_minute_ago`.
+    pub async fn touch_session(
+        &self,
+        id_hash: &str,
+        now: DateTime<Utc>,
+        a_minute_ago: DateTime<Utc>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query(
+                "UPDATE sessions SET last_used_at = $1
+                WHERE id = $2 AND (last_used_at IS NULL OR last_used_at < $3);",
+            )
+            .bind(now)
+            .bind(id_hash)
+            .bind(a_minute_ago)
+            .execute(pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// Delete every session of a user, logging them out everywhere.
+    ///
+    /// Returns how many sessions were deleted.
+    pub async fn delete_user_sessions(&self, user_id: i64) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM sessions WHERE user_id = $1;")
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Delete the session whose id hashes to `id_hash`.
+    ///
+    /// Returns `false` if there was no such session.
+    pub async fn delete_session(&self, id_hash: &str) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM sessions WHERE id = $1;")
+                .bind(id_hash)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Delete every session that expired before `now`.
+    ///
+    /// Returns the number of sessions deleted.
+    pub async fn delete_expired_sessions(&self, now: DateTime<Utc>) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM sessions WHERE expiration_date < $1;")
+                .bind(now)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Delete every API token that expired before `now`.
+    ///
+    /// Returns the number of tokens deleted.
+    pub async fn delete_expired_api_tokens(&self, now: DateTime<Utc>) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM api_tokens WHERE expiration_date < $1;")
+                .bind(now)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Delete every device authorization request that expired before `now`, including the ones
+    /// that were never completed.
+    ///
+    /// Returns the number of requests deleted.
+    pub async fn delete_expired_device_codes(&self, now: DateTime<Utc>) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM device_codes WHERE expiration_date < $1;")
+                .bind(now)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Store a new API token by its hash, returning it upon completion.
+    pub async fn create_api_token(
+        &self,
+        user_id: i64,
+        name: &str,
+        token_hash: &str,
+        created_at: DateTime<Utc>,
+        expiration_date: Option<DateTime<Utc>>,
+    ) -> Result<ApiToken> {
+        with_pool!(self, |pool| {
+            let token: ApiToken = sqlx::query_as(
+                r"
+                INSERT INTO api_tokens (user_id, name, token_hash, created_at, expiration_date)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, user_id, name, created_at, expiration_date, last_used_at;
+                ",
+            )
+            .bind(user_id)
+            .bind(name)
+            .bind(token_hash)
+            .bind(created_at)
+            .bind(expiration_date)
+            .fetch_one(pool)
+            .await?;
+            Ok(token)
+        })
+    }
+
+    /// Returns the API token with the provided hash, if there is one.
+    pub async fn get_api_token_by_hash(&self, token_hash: &str) -> Result<Option<ApiToken>> {
+        with_pool!(self, |pool| {
+            let token: Option<ApiToken> = sqlx::query_as(
+                "SELECT id, user_id, name, created_at, expiration_date, last_used_at
+                FROM api_tokens WHERE token_hash = $1;",
+            )
+            .bind(token_hash)
+            .fetch_optional(pool)
+            .await?;
+            Ok(token)
+        })
+    }
+
+    /// Returns the API token with the provided id, if there is one.
+    pub async fn get_api_token(&self, token_id: i64) -> Result<Option<ApiToken>> {
+        with_pool!(self, |pool| {
+            let token: Option<ApiToken> = sqlx::query_as(
+                "SELECT id, user_id, name, created_at, expiration_date, last_used_at
+                FROM api_tokens WHERE id = $1;",
+            )
+            .bind(token_id)
+            .fetch_optional(pool)
+            .await?;
+            Ok(token)
+        })
+    }
+
+    /// Returns every API token a user created, oldest first.
+    pub async fn get_user_api_tokens(&self, user_id: i64) -> Result<Vec<ApiToken>> {
+        with_pool!(self, |pool| {
+            let tokens: Vec<ApiToken> = sqlx::query_as(
+                "SELECT id, user_id, name, created_at, expiration_date, last_used_at
+                FROM api_tokens WHERE user_id = $1 ORDER BY id;",
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+            Ok(tokens)
+        })
+    }
+
+    /// Record that an API token was used at `now`.
+    pub async fn touch_api_token(&self, token_id: i64, now: DateTime<Utc>) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query("UPDATE api_tokens SET last_used_at = $1 WHERE id = $2;")
+                .bind(now)
+                .bind(token_id)
+                .execute(pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Revoke the API token with the provided id.
+    ///
+    /// Returns `false` if there was no such token.
+    pub async fn delete_api_token(&self, token_id: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM api_tokens WHERE id = $1;")
+                .bind(token_id)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Store an audit log entry. Its id is ignored.
+    pub async fn record_audit(&self, entry: &AuditEntry) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query(
+                r"
+                INSERT INTO audit_log (
+                    created_at, category, action, user_id, username, ip_address, detail, target,
+                    data
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9);
+                ",
+            )
+            .bind(entry.created_at)
+            .bind(&entry.category)
+            .bind(&entry.action)
+            .bind(entry.user_id)
+            .bind(&entry.username)
+            .bind(&entry.ip_address)
+            .bind(&entry.detail)
+            .bind(&entry.target)
+            .bind(&entry.data)
+            .execute(pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// Returns the most recent `limit` audit log entries matching `filter`, newest first.
+    pub async fn query_audit(&self, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditEntry>> {
+        with_pool!(self, |pool| {
+            let entries: Vec<AuditEntry> = sqlx::query_as(
+                r"
+                SELECT * FROM audit_log
+                WHERE ($1 IS NULL OR category = $1)
+                AND ($2 IS NULL OR action = $2)
+                AND ($3 IS NULL OR user_id = $3)
+                AND ($4 IS NULL OR target = $4)
+                AND ($5 IS NULL OR created_at >= $5)
+                AND ($6 IS NULL OR created_at < $6)
+                AND ($7 IS NULL OR id < $7)
+                ORDER BY id DESC LIMIT $8;
+                ",
+            )
+            .bind(&filter.category)
+            .bind(&filter.action)
+            .bind(filter.user_id)
+            .bind(&filter.target)
+            .bind(filter.since)
+            .bind(filter.until)
+            .bind(filter.before)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+            Ok(entries)
+        })
+    }
+
+    /// Store a new device authorization request.
+    pub async fn create_device_code(
+        &self,
+        device_code_hash: &str,
+        user_code: &str,
+        client_name: &str,
+        created_at: DateTime<Utc>,
+        expiration_date: DateTime<Utc>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query(
+                r"
+                INSERT INTO device_codes
+                    (device_code_hash, user_code, client_name, created_at, expiration_date)
+                VALUES ($1, $2, $3, $4, $5);
+                ",
+            )
+            .bind(device_code_hash)
+            .bind(user_code)
+            .bind(client_name)
+            .bind(created_at)
+            .bind(expiration_date)
+            .execute(pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// Returns the device authorization request with the provided device code hash, if there
+    /// is one.
+    pub async fn get_device_code(&self, device_code_hash: &str) -> Result<Option<DeviceCode>> {
+        with_pool!(self, |pool| {
+            let device_code: Option<DeviceCode> =
+                sqlx::query_as("SELECT * FROM device_codes WHERE device_code_hash = $1;")
+                    .bind(device_code_hash)
+                    .fetch_optional(pool)
+                    .await?;
+            Ok(device_code)
+        })
+    }
+
+    /// Returns the device authorization request with the provided user code, if there is one.
+    pub async fn get_device_code_by_user_code(
+        &self,
+        user_code: &str,
+    ) -> Result<Option<DeviceCode>> {
+        with_pool!(self, |pool| {
+            let device_code: Option<DeviceCode> =
+                sqlx::query_as("SELECT * FROM device_codes WHERE user_code = $1;")
+                    .bind(user_code)
+                    .fetch_optional(pool)
+                    .await?;
+            Ok(device_code)
+        })
+    }
+
+    /// Approve or deny a device authorization request on behalf of a user. Returns false if
+    /// the request doesn't exist or was already approved or denied.
+    pub async fn resolve_device_code(
+        &self,
+        user_code: &str,
+        user_id: i64,
+        denied: bool,
+    ) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                r"
+                UPDATE device_codes SET user_id = $1, denied = $2
+                WHERE user_code = $3 AND user_id IS NULL AND denied = FALSE;
+                ",
+            )
+            .bind(user_id)
+            .bind(denied)
+            .bind(user_code)
+            .execute(pool)
+            .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Record that a client polled a device authorization request.
+    pub async fn touch_device_code(
+        &self,
+        device_code_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query("UPDATE device_codes SET last_polled_at = $1 WHERE device_code_hash = $2;")
+                .bind(now)
+                .bind(device_code_hash)
+                .execute(pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Delete a device authorization request, and any that have expired. Returns true if the
+    /// request was deleted, so that a device code can only be exchanged for a token once.
+    pub async fn delete_device_code(
+        &self,
+        device_code_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM device_codes WHERE device_code_hash = $1;")
+                .bind(device_code_hash)
+                .execute(pool)
+                .await?;
+            sqlx::query("DELETE FROM device_codes WHERE expiration_date < $1;")
+                .bind(now)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Start enrolling a user in TOTP, replacing any secret they had. The secret isn't asked for
+    /// until [`Database::enable_totp`] is called.
+    pub async fn set_totp_secret(
+        &self,
+        user_id: i64,
+        secret: &str,
+        created_at: DateTime<Utc>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query(
+                r"
+                INSERT INTO totp_secrets (user_id, secret, enabled, created_at)
+                VALUES ($1, $2, FALSE, $3)
+                ON CONFLICT (user_id) DO UPDATE SET secret = excluded.secret, enabled = FALSE,
+                    created_at = excluded.created_at, last_used_step = NULL;
+                ",
+            )
+            .bind(user_id)
+            .bind(self.seal_token(secret)?)
+            .bind(created_at)
+            .execute(pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// Returns the user's TOTP secret, if they've started enrolling
+    pub async fn get_totp_secret(&self, user_id: i64) -> Result<Option<TotpSecret>> {
+        with_pool!(self, |pool| {
+            let totp: Option<TotpSecret> =
+                sqlx::query_as("SELECT * FROM totp_secrets WHERE user_id = $1;")
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await?;
+            totp.map(|totp| {
+                Ok(TotpSecret {
+                    secret: self.open_token(&totp.secret)?,
+                    ..totp
+                })
+            })
+            .transpose()
+        })
+    }
+
+    /// Confirm a user's enrollment, so codes are asked for from now on
+    pub async fn enable_totp(&self, user_id: i64) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query("UPDATE totp_secrets SET enabled = TRUE WHERE user_id = $1;")
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Record that a code from time step `step` was accepted. Returns false if a code from that
+    /// step (or a later one) was already used, in which case the code is a replay.
+    pub async fn use_totp_step(&self, user_id: i64, step: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                r"
+                UPDATE totp_secrets SET last_used_step = $1
+                WHERE user_id = $2 AND (last_used_step IS NULL OR last_used_step < $1);
+                ",
+            )
+            .bind(step)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Remove a user's TOTP second factor. Returns true if they had one.
+    pub async fn delete_totp_secret(&self, user_id: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM totp_secrets WHERE user_id = $1;")
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Record that `editor` saved the document at `path` at `now`. The description is replaced
+    /// if one is given.
+    pub async fn record_doc_edit(
+        &self,
+        path: &str,
+        editor: &User,
+        now: DateTime<Utc>,
+        description: Option<&str>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query(
+                r"
+                INSERT INTO doc_meta
+                    (path, last_editor_id, last_editor_name, last_edited_at, edit_count, description)
+                VALUES ($1, $2, $3, $4, 1, $5)
+                ON CONFLICT (path) DO UPDATE SET last_editor_id = excluded.last_editor_id,
+                    last_editor_name = excluded.last_editor_name,
+                    last_edited_at = excluded.last_edited_at,
+                    edit_count = doc_meta.edit_count + 1,
+                    description = COALESCE(excluded.description, doc_meta.description);
+                ",
+            )
+            .bind(path)
+            .bind(editor.id)
+            .bind(&editor.username)
+            .bind(now)
+            .bind(description)
+            .execute(pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// Returns a user's contributions, rolled up from the audit log and the pull requests
+    /// opened through Hyde. Returns `None` if there's no such user.
+    pub async fn get_user_stats(&self, user_id: i64) -> Result<Option<UserStats>> {
+        with_pool!(self, |pool| {
+            let stats: Option<UserStats> = sqlx::query_as(
+                r"
+                SELECT
+                    (SELECT COUNT(*) FROM audit_log
+                        WHERE user_id = users.id AND action = 'doc_saved') AS doc_edits,
+                    (SELECT COUNT(DISTINCT target) FROM audit_log
+                        WHERE user_id = users.id AND action = 'doc_saved') AS docs_edited,
+                    (SELECT COUNT(*) FROM audit_log
+                        WHERE user_id = users.id AND action = 'asset_saved') AS assets_uploaded,
+                    (SELECT COUNT(*) FROM hyde_prs WHERE author_id = users.id) AS prs_opened,
+                    (SELECT MAX(active_at) FROM (
+                        SELECT MAX(created_at) AS active_at FROM audit_log
+                            WHERE user_id = users.id
+                        UNION ALL
+                        SELECT MAX(last_used_at) FROM sessions WHERE user_id = users.id
+                    ) AS activity) AS last_active_at
+                FROM users WHERE id = $1;
+                ",
+            )
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+            Ok(stats)
+        })
+    }
+
+    /// Returns the metadata of every document that's been edited through Hyde.
+    pub async fn get_all_doc_meta(&self) -> Result<Vec<DocMeta>> {
+        with_pool!(self, |pool| {
+            let meta: Vec<DocMeta> = sqlx::query_as("SELECT * FROM doc_meta ORDER BY path;")
+                .fetch_all(pool)
+                .await?;
+            Ok(meta)
+        })
+    }
+
+    /// Move a document's metadata to `new_path`, after the document was moved.
+    pub async fn move_doc_meta(&self, path: &str, new_path: &str) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query("DELETE FROM doc_meta WHERE path = $1;")
+                .bind(new_path)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query("UPDATE doc_meta SET path = $1 WHERE path = $2;")
+                .bind(new_path)
+                .bind(path)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Remove a document's metadata, after the document was deleted.
+    pub async fn delete_doc_meta(&self, path: &str) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query("DELETE FROM doc_meta WHERE path = $1;")
+                .bind(path)
+                .execute(pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Returns a user's preferences as `(key, value)` pairs ordered by key, values are JSON.
+    pub async fn get_user_preferences(&self, user_id: i64) -> Result<Vec<(String, String)>> {
+        with_pool!(self, |pool| {
+            let preferences: Vec<(String, String)> = sqlx::query_as(
+                "SELECT key, value FROM user_preferences WHERE user_id = $1 ORDER BY key;",
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+            Ok(preferences)
+        })
+    }
+
+    /// Set several of a user's preferences at once. A value of `None` removes the preference.
+    pub async fn set_user_preferences(
+        &self,
+        user_id: i64,
+        preferences: &[(String, Option<String>)],
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            for (key, value) in preferences {
+                if let Some(value) = value {
+                    sqlx::query(
+                        r"
+                        INSERT INTO user_preferences (user_id, key, value) VALUES ($1, $2, $3)
+                        ON CONFLICT (user_id, key) DO UPDATE SET value = excluded.value;
+                        ",
+                    )
+                    .bind(user_id)
+                    .bind(key)
+                    .bind(value)
+                    .execute(&mut *transaction)
+                    .await?;
+                } else {
+                    sqlx::query("DELETE FROM user_preferences WHERE user_id = $1 AND key = $2;")
+                        .bind(user_id)
+                        .bind(key)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Replace the whole search index with `docs`, as `(path, title, body)` tuples.
+    pub async fn replace_search_index(&self, docs: &[(String, String, String)]) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query("DELETE FROM doc_search;")
+                .execute(&mut *transaction)
+                .await?;
+            for (path, title, body) in docs {
+                sqlx::query("INSERT INTO doc_search (path, title, body) VALUES ($1, $2, $3);")
+                    .bind(path)
+                    .bind(title)
+                    .bind(body)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Add a document to the search index, replacing what was indexed for it before.
+    pub async fn index_doc(&self, path: &str, title: &str, body: &str) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query("DELETE FROM doc_search WHERE path = $1;")
+                .bind(path)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query("INSERT INTO doc_search (path, title, body) VALUES ($1, $2, $3);")
+                .bind(path)
+                .bind(title)
+                .bind(body)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Remove a document from the search index.
+    pub async fn remove_indexed_doc(&self, path: &str) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query("DELETE FROM doc_search WHERE path = $1;")
+                .bind(path)
+                .execute(pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Returns up to `limit` documents containing every one of `terms`, most relevant first.
+    /// The last term also matches words it's the start of, so results show up while typing.
+    /// Terms must be alphanumeric, see [`search::query_terms`].
+    pub async fn search_docs(&self, terms: &[String], limit: i64) -> Result<Vec<SearchHit>> {
+        let Some((last, rest)) = terms.split_last() else {
+            return Ok(Vec::new());
+        };
+        let hits: Vec<SearchHit> = match &self.pool {
+            Pool::Sqlite(pool) => {
+                let query = rest
+                    .iter()
+                    .map(|t| format!("\"{t}\""))
+                    .chain([format!("\"{last}\"*")])
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                // Titles weigh ten times as much as the body, the path isn't indexed
+                sqlx::query_as(
+                    r"
+                    SELECT path, title, snippet(doc_search, 2, $1, $2, '…', 24) AS snippet,
+                        -bm25(doc_search, 0.0, 10.0, 1.0) AS rank
+                    FROM doc_search WHERE doc_search MATCH $3
+                    ORDER BY bm25(doc_search, 0.0, 10.0, 1.0), path LIMIT $4;
+                    ",
+                )
+                .bind(search::HIGHLIGHT_START.to_string())
+                .bind(search::HIGHLIGHT_END.to_string())
+                .bind(query)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+            }
+            Pool::Postgres(pool) => {
+                let query = rest
+                    .iter()
+                    .cloned()
+                    .chain([format!("{last}:*")])
+                    .collect::<Vec<_>>()
+                    .join(" & ");
+                let headline_options = format!(
+                    "StartSel={}, StopSel={}, MaxWords=24, MinWords=12",
+                    search::HIGHLIGHT_START,
+                    search::HIGHLIGHT_END
+                );
+                sqlx::query_as(
+                    r"
+                    SELECT path, title, ts_headline('english', body, query, $1) AS snippet,
+                        ts_rank(document, query)::FLOAT8 AS rank
+                    FROM doc_search, to_tsquery('english', $2) AS query
+                    WHERE document @@ query
+                    ORDER BY rank DESC, path LIMIT $3;
+                    ",
+                )
+                .bind(headline_options)
+                .bind(query)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+            }
+        };
+        Ok(hits)
+    }
+
+    /// Save a draft, replacing the user's previous draft of the same document.
+    pub async fn put_draft(&self, draft: &Draft) -> Result<()> {
+        with_pool!(self, |pool| {
+            sqlx::query(
+                r"
+                INSERT INTO drafts (user_id, doc_path, contents, base_commit, updated_at)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (user_id, doc_path) DO UPDATE SET contents = excluded.contents,
+                    base_commit = excluded.base_commit, updated_at = excluded.updated_at;
+                ",
+            )
+            .bind(draft.user_id)
+            .bind(&draft.doc_path)
+            .bind(&draft.contents)
+            .bind(&draft.base_commit)
+            .bind(draft.updated_at)
+            .execute(pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// Returns a user's draft of the document at `doc_path`, if they have one.
+    pub async fn get_draft(&self, user_id: i64, doc_path: &str) -> Result<Option<Draft>> {
+        with_pool!(self, |pool| {
+            let draft: Option<Draft> =
+                sqlx::query_as("SELECT * FROM drafts WHERE user_id = $1 AND doc_path = $2;")
+                    .bind(user_id)
+                    .bind(doc_path)
+                    .fetch_optional(pool)
+                    .await?;
+            Ok(draft)
+        })
+    }
+
+    /// Returns every draft a user has, most recently updated first.
+    pub async fn get_user_drafts(&self, user_id: i64) -> Result<Vec<Draft>> {
+        with_pool!(self, |pool| {
+            let drafts: Vec<Draft> = sqlx::query_as(
+                "SELECT * FROM drafts WHERE user_id = $1 ORDER BY updated_at DESC, doc_path;",
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+            Ok(drafts)
+        })
+    }
+
+    /// Delete a user's draft of the document at `doc_path`. Returns false if they didn't have
+    /// one.
+    pub async fn delete_draft(&self, user_id: i64, doc_path: &str) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result =
+                sqlx::query("DELETE FROM drafts WHERE user_id = $1 AND doc_path = $2;")
+                    .bind(user_id)
+                    .bind(doc_path)
+                    .execute(pool)
+                    .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Delete the drafts that haven't been saved since `before`.
+    ///
+    /// Returns the number of drafts deleted.
+    pub async fn prune_drafts(&self, before: DateTime<Utc>) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("DELETE FROM drafts WHERE updated_at < $1;")
+                .bind(before)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Notify a user at `now`, returning the stored notification. `payload` is a JSON object.
+    pub async fn create_notification(
+        &self,
+        user_id: i64,
+        kind: &str,
+        payload: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Notification> {
+        with_pool!(self, |pool| {
+            let notification: Notification = sqlx::query_as(
+                r"
+                INSERT INTO notifications (user_id, kind, payload, created_at)
+                VALUES ($1, $2, $3, $4) RETURNING *;
+                ",
+            )
+            .bind(user_id)
+            .bind(kind)
+            .bind(payload)
+            .bind(now)
+            .fetch_one(pool)
+            .await?;
+            Ok(notification)
+        })
+    }
+
+    /// Returns up to `limit` of a user's notifications, newest first. If `unread_only` is set,
+    /// notifications that were read are left out.
+    pub async fn get_notifications(
+        &self,
+        user_id: i64,
+        unread_only: bool,
+        limit: i64,
+    ) -> Result<Vec<Notification>> {
+        with_pool!(self, |pool| {
+            let notifications: Vec<Notification> = sqlx::query_as(
+                r"
+                SELECT * FROM notifications
+                WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+                ORDER BY id DESC LIMIT $3;
+                ",
+            )
+            .bind(user_id)
+            .bind(unread_only)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+            Ok(notifications)
+        })
+    }
+
+    /// Returns how many notifications a user hasn't read.
+    pub async fn count_unread_notifications(&self, user_id: i64) -> Result<i64> {
+        with_pool!(self, |pool| {
+            let count: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL;",
+            )
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?;
+            Ok(count)
+        })
+    }
+
+    /// Mark one of a user's notifications as read at `now`. Returns false if the user has no
+    /// unread notification with that id.
+    pub async fn mark_notification_read(
+        &self,
+        user_id: i64,
+        notification_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                r"
+                UPDATE notifications SET read_at = $1
+                WHERE id = $2 AND user_id = $3 AND read_at IS NULL;
+                ",
+            )
+            .bind(now)
+            .bind(notification_id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Mark every unread notification of a user as read at `now`.
+    ///
+    /// Returns the number of notifications marked.
+    pub async fn mark_all_notifications_read(
+        &self,
+        user_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                "UPDATE notifications SET read_at = $1 WHERE user_id = $2 AND read_at IS NULL;",
+            )
+            .bind(now)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Store a new editing ticket, returning it upon completion.
+    pub async fn create_editing_ticket(&self, ticket: &EditingTicket) -> Result<EditingTicket> {
+        with_pool!(self, |pool| {
+            let query_results: EditingTicket = sqlx::query_as(
+                r"
+                INSERT INTO editing_tickets
+                    (id, user_id, path, base_commit, blob_sha, expiration_date, branch)
+                VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *;
+                ",
+            )
+            .bind(&ticket.id)
+            .bind(ticket.user_id)
+            .bind(&ticket.path)
+            .bind(&ticket.base_commit)
+            .bind(&ticket.blob_sha)
+            .bind(ticket.expiration_date)
+            .bind(&ticket.branch)
+            .fetch_one(pool)
+            .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns the editing ticket with the provided id.
+    pub async fn get_editing_ticket(&self, ticket_id: &str) -> Result<Option<EditingTicket>> {
+        with_pool!(self, |pool| {
+            let query_results: Option<EditingTicket> =
+                sqlx::query_as("SELECT * FROM editing_tickets WHERE id = $1;")
+                    .bind(ticket_id)
+                    .fetch_optional(pool)
+                    .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Delete the editing ticket with the provided id if it was issued to `user_id`, returning
+    /// it. Tickets are claimed before they're redeemed, so that only one submission can redeem
+    /// each ticket.
+    pub async fn claim_editing_ticket(
+        &self,
+        ticket_id: &str,
+        user_id: i64,
+    ) -> Result<Option<EditingTicket>> {
+        with_pool!(self, |pool| {
+            let query_results: Option<EditingTicket> = sqlx::query_as(
+                "DELETE FROM editing_tickets WHERE id = $1 AND user_id = $2 RETURNING *;",
+            )
+            .bind(ticket_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns the owners responsible for the document at `doc_path`.
+    ///
+    /// Owners assigned to the document itself take precedence, followed by the owners of the
+    /// closest parent directory.
+    pub async fn resolve_doc_owners(&self, doc_path: &str) -> Result<Vec<DocOwner>> {
+        let covering: Vec<DocOwner> = with_pool!(self, |pool| {
+            sqlx::query_as(
+                "SELECT * FROM doc_owners WHERE path = $1
+                OR (path LIKE '%/' AND substr($1, 1, length(path)) = path);",
+            )
+            .bind(doc_path)
+            .fetch_all(pool)
+            .await?
+        });
+        let Some(most_specific) = covering.iter().map(|o| o.path.len()).max() else {
+            return Ok(Vec::new());
+        };
+        Ok(covering
+            .into_iter()
+            .filter(|o| o.path.len() == most_specific)
+            .collect())
+    }
+
+    /// Returns the users responsible for the document at `doc_path` (see
+    /// [`Database::resolve_doc_owners`]), with groups replaced by their members.
+    pub async fn resolve_doc_owner_users(&self, doc_path: &str) -> Result<Vec<User>> {
+        let mut users: Vec<User> = Vec::new();
+        for owner in self.resolve_doc_owners(doc_path).await? {
+            let owner_users = match (owner.user_id, owner.group_id) {
+                (Some(user_id), _) => self.get_user(user_id).await?.into_iter().collect(),
+                (None, Some(group_id)) => self.get_group_members(group_id).await?,
+                (None, None) => Vec::new(),
+            };
+            for user in owner_users {
+                if !users.iter().any(|u| u.id == user.id) {
+                    users.push(user);
+                }
+            }
+        }
+        Ok(users)
+    }
+
+    /// Move the owners of the document at `path` to `new_path`, after the document was moved.
+    /// If `path` is a directory (ending in `/`), so is `new_path`, and the owners of
+    /// everything in it are moved too. Owners already assigned to the new paths are removed
+    /// first, they belonged to something that's no longer there.
+    pub async fn move_doc_owners(&self, path: &str, new_path: &str) -> Result<()> {
+        let matches = if path.ends_with('/') {
+            "substr(path, 1, length($1)) = $1"
+        } else {
+            "path = $1"
+        };
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query(&format!("DELETE FROM doc_owners WHERE {matches};"))
+                .bind(new_path)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query(&format!(
+                "UPDATE doc_owners SET path = $2 || substr(path, length($1) + 1) WHERE {matches};"
+            ))
+            .bind(path)
+            .bind(new_path)
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Remove the owners of the directory `path` (ending in `/`) and of everything in it,
+    /// after it was deleted.
+    ///
+    /// Returns the number of ownership entries removed.
+    pub async fn remove_doc_owners_under(&self, path: &str) -> Result<u64> {
+        with_pool!(self, |pool| {
+            let query_result =
+                sqlx::query("DELETE FROM doc_owners WHERE substr(path, 1, length($1)) = $1;")
+                    .bind(path)
+                    .execute(pool)
+                    .await?;
+            Ok(query_result.rows_affected())
+        })
+    }
+
+    /// Record that `user_id` was reminded at `now` that the document at `path` hasn't changed
+    /// in a while. Returns `false` without recording anything if they were already reminded
+    /// after `since`, in which case they shouldn't be reminded again.
+    pub async fn record_stale_reminder(
+        &self,
+        path: &str,
+        user_id: i64,
+        since: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                r"
+                INSERT INTO stale_reminders (path, user_id, reminded_at) VALUES ($1, $2, $3)
+                ON CONFLICT (path, user_id) DO UPDATE SET reminded_at = excluded.reminded_at
+                WHERE stale_reminders.reminded_at <= $4;
+                ",
+            )
+            .bind(path)
+            .bind(user_id)
+            .bind(now)
+            .bind(since)
+            .execute(pool)
+            .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Record a pull request opened through Hyde, along with the documents it changes.
+    pub async fn record_hyde_pr(&self, pr: &HydePr) -> Result<()> {
+        with_pool!(self, |pool| {
+            let mut transaction = pool.begin().await?;
+            sqlx::query(
+                r"
+                INSERT INTO hyde_prs (number, title, author_id, branch, status, created_at, merged_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7);
+                ",
+            )
+            .bind(pr.number)
+            .bind(&pr.title)
+            .bind(pr.author_id)
+            .bind(&pr.branch)
+            .bind(&pr.status)
+            .bind(pr.created_at)
+            .bind(pr.merged_at)
+            .execute(&mut *transaction)
+            .await?;
+            for path in &pr.doc_paths {
+                sqlx::query(
+                    "INSERT INTO hyde_pr_docs (number, path) VALUES ($1, $2)
+                    ON CONFLICT DO NOTHING;",
+                )
+                .bind(pr.number)
+                .bind(path)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Whether the pull request was opened through Hyde
+    pub async fn is_hyde_pr(&self, number: i64) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let found: Option<i64> =
+                sqlx::query_scalar("SELECT number FROM hyde_prs WHERE number = $1;")
+                    .bind(number)
+                    .fetch_optional(pool)
+                    .await?;
+            Ok(found.is_some())
+        })
+    }
+
+    /// Mark a pull request as merged at `merged_at`.
+    ///
+    /// Returns `false` if the pull request wasn't opened through Hyde.
+    pub async fn mark_hyde_pr_merged(&self, number: i64, merged_at: DateTime<Utc>) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                "UPDATE hyde_prs SET merged_at = $1, status = 'merged' WHERE number = $2;",
+            )
+            .bind(merged_at)
+            .bind(number)
+            .execute(pool)
+            .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Mark a pull request as `open` or `closed` (without being merged).
+    ///
+    /// Returns `false` if the pull request wasn't opened through Hyde.
+    pub async fn set_hyde_pr_status(&self, number: i64, status: &str) -> Result<bool> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query("UPDATE hyde_prs SET status = $1 WHERE number = $2;")
+                .bind(status)
+                .bind(number)
+                .execute(pool)
+                .await?;
+            Ok(query_result.rows_affected() == 1)
+        })
+    }
+
+    /// Returns the pull requests `author_id` opened through Hyde, newest first, optionally
+    /// only those with the given `status`.
+    pub async fn get_user_hyde_prs(
+        &self,
+        author_id: i64,
+        status: Option<&str>,
+    ) -> Result<Vec<HydePr>> {
+        with_pool!(self, |pool| {
+            let mut prs: Vec<HydePr> = sqlx::query_as(
+                r"
+                SELECT * FROM hyde_prs
+                WHERE author_id = $1 AND ($2 IS NULL OR status = $2)
+                ORDER BY created_at DESC, number DESC;
+                ",
+            )
+            .bind(author_id)
+            .bind(status)
+            .fetch_all(pool)
+            .await?;
+            for pr in &mut prs {
+                pr.doc_paths = sqlx::query_scalar(
+                    "SELECT path FROM hyde_pr_docs WHERE number = $1 ORDER BY path;",
+                )
+                .bind(pr.number)
+                .fetch_all(pool)
+                .await?;
+            }
+            Ok(prs)
+        })
+    }
+
+    /// Returns every pull request opened through Hyde that was merged at or after `since`,
+    /// oldest first.
+    pub async fn get_merged_hyde_prs(&self, since: DateTime<Utc>) -> Result<Vec<HydePr>> {
+        with_pool!(self, |pool| {
+            let mut prs: Vec<HydePr> = sqlx::query_as(
+                "SELECT * FROM hyde_prs WHERE merged_at >= $1 ORDER BY merged_at, number;",
+            )
+            .bind(since)
+            .fetch_all(pool)
+            .await?;
+            for pr in &mut prs {
+                pr.doc_paths = sqlx::query_scalar(
+                    "SELECT path FROM hyde_pr_docs WHERE number = $1 ORDER BY path;",
+                )
+                .bind(pr.number)
+                .fetch_all(pool)
+                .await?;
+            }
+            Ok(prs)
+        })
+    }
+
+    /// Queue a webhook event to be processed, returning the stored event.
+    pub async fn enqueue_webhook_event(
+        &self,
+        event_type: &str,
+        delivery_id: Option<&str>,
+        payload: &str,
+        now: DateTime<Utc>,
+    ) -> Result<WebhookEvent> {
+        with_pool!(self, |pool| {
+            let query_results: WebhookEvent = sqlx::query_as(
+            r"
+            INSERT INTO webhook_events (event_type, delivery_id, payload, received_at, next_attempt_at)
+            VALUES ($1, $2, $3, $4, $5) RETURNING *;
+            ",
+        )
+        .bind(event_type)
+        .bind(delivery_id)
+        .bind(payload)
+        .bind(now)
+        .bind(now)
+        .fetch_one(pool)
+        .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns the oldest pending webhook event that's due to be attempted at `now`.
+    pub async fn next_due_webhook_event(&self, now: DateTime<Utc>) -> Result<Option<WebhookEvent>> {
+        with_pool!(self, |pool| {
+            let query_results: Option<WebhookEvent> = sqlx::query_as(
+                r"
+                SELECT * FROM webhook_events
+                WHERE status = 'pending' AND next_attempt_at <= $1
+                ORDER BY id LIMIT 1;
+                ",
+            )
+            .bind(now)
+            .fetch_optional(pool)
+            .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Mark a webhook event as successfully processed.
+    pub async fn complete_webhook_event(&self, event_id: i64, now: DateTime<Utc>) -> Result<()> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                r"
+                UPDATE webhook_events
+                SET status = 'completed', attempts = attempts + 1, completed_at = $1
+                WHERE id = $2;
+                ",
+            )
+            .bind(now)
+            .bind(event_id)
+            .execute(pool)
+            .await?;
+
+            if query_result.rows_affected() != 1 {
+                bail!(
+                    "Complete webhook event impacted unexpected number of rows, impacted {} rows",
+                    query_result.rows_affected()
+                )
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Record a failed attempt at processing a webhook event. If `next_attempt_at` is `None`,
+    /// the event won't be retried.
+    pub async fn fail_webhook_event(
+        &self,
+        event_id: i64,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        with_pool!(self, |pool| {
+            let query_result = sqlx::query(
+                r"
+                UPDATE webhook_events
+                SET status = CASE WHEN $1 IS NULL THEN 'failed' ELSE 'pending' END,
+                    attempts = attempts + 1,
+                    last_error = $2,
+                    next_attempt_at = COALESCE($1, next_attempt_at)
+                WHERE id = $3;
+                ",
+            )
+            .bind(next_attempt_at)
+            .bind(error)
+            .bind(event_id)
+            .execute(pool)
+            .await?;
+
+            if query_result.rows_affected() != 1 {
+                bail!(
+                    "Fail webhook event impacted unexpected number of rows, impacted {} rows",
+                    query_result.rows_affected()
+                )
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Returns the most recently received webhook events, newest first.
+    pub async fn get_recent_webhook_events(&self, limit: i64) -> Result<Vec<WebhookEvent>> {
+        with_pool!(self, |pool| {
+            let query_results: Vec<WebhookEvent> =
+                sqlx::query_as("SELECT * FROM webhook_events ORDER BY id DESC LIMIT $1;")
+                    .bind(limit)
+                    .fetch_all(pool)
+                    .await?;
+
+            Ok(query_results)
+        })
+    }
+
+    /// Returns up to `limit` of the webhook events matching `filter`, newest first.
+    pub async fn query_webhook_events(
+        &self,
+        filter: &WebhookEventFilter,
+        limit: i64,
+    ) -> Result<Vec<WebhookEvent>> {
+        with_pool!(self, |pool| {
+            let events: Vec<WebhookEvent> = sqlx::query_as(
+                r"
+                SELECT * FROM webhook_events
+                WHERE ($1 IS NULL OR event_type = $1)
+                AND ($2 IS NULL OR status = $2)
+                AND ($3 IS NULL OR id < $3)
+                ORDER BY id DESC LIMIT $4;
+                ",
+            )
+            .bind(&filter.event_type)
+            .bind(&filter.status)
+            .bind(filter.before)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+            Ok(events)
+        })
+    }
+
+    /// Returns how many webhook events are pending, completed and failed.
+    pub async fn get_webhook_queue_status(&self) -> Result<WebhookQueueStatus> {
+        with_pool!(self, |pool| {
+            let counts: Vec<(String, i64)> =
+                sqlx::query_as("SELECT status, COUNT(*) FROM webhook_events GROUP BY status;")
+                    .fetch_all(pool)
+                    .await?;
+
+            let mut status = WebhookQueueStatus::default();
+            for (state, count) in counts {
+                match state.as_str() {
+                    "pending" => status.pending = count,
+                    "completed" => status.completed = count,
+                    "failed" => status.failed = count,
+                    _ => {}
+                }
+            }
+            Ok(status)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// I am too lazy to type to_owned everywhere
+    macro_rules! s {
+        ($str: tt) => {
+            $str.to_owned()
+        };
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    #[test]
+    fn database_urls() {
+        let file = |path: &str| DatabaseUrl::SqliteFile(PathBuf::from(path));
+        assert_eq!(
+            DatabaseUrl::parse("postgres://hyde@localhost/hyde"),
+            DatabaseUrl::Postgres(s!("postgres://hyde@localhost/hyde"))
+        );
+        assert_eq!(
+            DatabaseUrl::parse("sqlite://data/hyde.db"),
+            file("data/hyde.db")
+        );
+        assert_eq!(
+            DatabaseUrl::parse("sqlite:///var/lib/hyde.db"),
+            file("/var/lib/hyde.db")
+        );
+        assert_eq!(DatabaseUrl::parse("file:hyde.db?mode=ro"), file("hyde.db"));
+        assert_eq!(
+            DatabaseUrl::parse("/var/lib/hyde.db"),
+            file("/var/lib/hyde.db")
+        );
+        assert_eq!(DatabaseUrl::parse(":memory:"), DatabaseUrl::SqliteMemory);
+        assert_eq!(
+            DatabaseUrl::parse("sqlite::memory:"),
+            DatabaseUrl::SqliteMemory
+        );
+        assert_eq!(
+            DatabaseUrl::parse(LEGACY_TEMPLATE_URL),
+            file(DATABASE_PATH),
+            "DatabaseUrl::parse: the old template url should keep using the default database"
+        );
+        assert_eq!(
+            file("/var/lib/hyde.db").connect_url(),
+            "file:/var/lib/hyde.db?mode=rwc"
+        );
+    }
+
+    #[tokio::test]
+    async fn migrations() {
+        let mock_db = Database::connect_unmigrated(":memory:", &SqliteSettings::default())
+            .await
+            .unwrap();
+        let all = mock_db.migrate(None, true).await.unwrap();
+        assert_eq!(all.len(), SQLITE_MIGRATIONS.iter().count());
+        assert_eq!(
+            mock_db.migrate(None, true).await.unwrap(),
+            all,
+            "migrate: a dry run shouldn't apply anything"
+        );
+        let applied = mock_db.migrate(Some(all[2].version), false).await.unwrap();
+        assert_eq!(
+            applied,
+            all[..3],
+            "migrate: should stop at the `to` version"
+        );
+        assert_eq!(mock_db.migrate(None, false).await.unwrap(), all[3..]);
+        assert!(mock_db.migrate(None, false).await.unwrap().is_empty());
+        assert!(mock_db.migrate(Some(1), true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn typed_timestamps_migration() {
+        let mock_db = Database::connect_unmigrated(":memory:", &SqliteSettings::default())
+            .await
+            .unwrap();
+        mock_db.migrate(Some(20250725000000), false).await.unwrap();
+        let Pool::Sqlite(pool) = &mock_db.pool else {
+            unreachable!();
+        };
+        sqlx::query(
+            "INSERT INTO users (id, username, token, expiration_date) VALUES (1, 'username', '', '');
+            INSERT INTO sessions (id, user_id, created_at, expiration_date, last_used_at) VALUES
+                ('old', 1, '2025-01-01T00:00:00.000Z', '2025-02-01T00:00:00.000Z', NULL),
+                ('offset', 1, '2024-12-31T00:00:00.000Z', '2025-02-01T02:00:00+02:00', NULL),
+                ('broken', 1, '2025-01-01T00:00:00.000Z', 'never', 'yesterday');",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        mock_db.migrate(None, false).await.unwrap();
+
+        let sessions = mock_db
+            .get_user_sessions(1, at("2025-01-15T00:00:00Z"))
+            .await
+            .unwrap();
+        assert_eq!(
+            sessions
+                .iter()
+                .map(|s| (s.id.as_str(), s.expiration_date))
+                .collect::<Vec<_>>(),
+            [
+                ("old", at("2025-02-01T00:00:00Z")),
+                ("offset", at("2025-02-01T00:00:00Z"))
+            ],
+            "migration: times should be converted to UTC, and ones that don't parse be expired"
+        );
+        assert_eq!(
+            mock_db
+                .get_session_by_hash("broken")
+                .await
+                .unwrap()
+                .unwrap()
+                .last_used_at,
+            Some(DateTime::UNIX_EPOCH)
+        );
+        assert!(
+            mock_db
+                .get_user_sessions(1, at("2025-02-01T00:00:00.001Z"))
+                .await
+                .unwrap()
+                .is_empty(),
+            "migration: converted times should compare with the ones sqlx writes"
+        );
+    }
+
+    #[tokio::test]
+    async fn ping() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        mock_db.ping(Duration::from_secs(1)).await.unwrap();
+        mock_db.close().await;
+        assert!(
+            mock_db.ping(Duration::from_secs(1)).await.is_err(),
+            "ping: should fail once the database can't be queried"
+        );
+    }
+
+    #[tokio::test]
+    async fn user_management() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+
+        let expiration_date = at("2025-06-25T12:34:56.789Z");
+        let mock_user = mock_db
+            .create_user(
+                s!("username"),
+                s!("token"),
+                expiration_date,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_user.username, "username",
+            "create_user: The new user's username should be the input"
+        );
+        assert_eq!(
+            mock_user.token, "token",
+            "create_user: The new user's token should be the input"
+        );
+        assert_eq!(
+            mock_user.expiration_date, expiration_date,
+            "create_user: The new user's expiration date should be the input"
+        );
+        assert_eq!(
+            mock_user.avatar_url, "https://foo.bar",
+            "create_user: the new user's expiration date should be the input"
+        );
+
+        let fetched_user = mock_db.get_user(mock_user.id).await.unwrap().unwrap();
+        assert_eq!(
+            mock_user, fetched_user,
+            "get_user: The fetched user's id should be the same as the created user"
+        );
+
+        let mut mock_user2 = mock_db
+            .create_user(
+                s!("username2"),
+                s!("token2"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar/2"),
+            )
+            .await
+            .unwrap();
+        let all_users = mock_db.get_all_users().await.unwrap();
+        assert_eq!(
+            all_users.len(),
+            2,
+            "get_all_users: The vector of all users should contain the right amount of users"
+        );
+        assert_eq!(
+            all_users[0], mock_user,
+            "get_all_users: The user vector's first element should be the first user inserted"
+        );
+        assert_eq!(
+            all_users[1], mock_user2,
+            "get_all_users: The user vector's second element should be the second user inserted"
+        );
+
+        "username2_updated".clone_into(&mut mock_user2.username);
+        mock_db.update_user(&mock_user2).await.unwrap();
+        let updated_user2 = mock_db.get_user(mock_user2.id).await.unwrap().unwrap();
+        let all_users2 = mock_db.get_all_users().await.unwrap();
+        assert_eq!(
+            updated_user2.username, "username2_updated",
+            "update_user: The new username should be updated"
+        );
+        assert_eq!(
+            all_users2.len(),
+            2,
+            "update_user: the function should not create any more users"
+        );
+
+        mock_db.purge_user(mock_user2.id).await.unwrap();
+        let all_users3 = mock_db.get_all_users().await.unwrap();
+        assert_eq!(
+            all_users3.len(),
+            1,
+            "purge_user: the function should delete exactly one user"
+        );
+        assert_eq!(
+            all_users3[0], mock_user,
+            "purge_user: the function should delete the correct user"
+        );
+    }
+
+    #[tokio::test]
+    async fn user_deactivation() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        mock_db
+            .create_session(&Session {
+                id: s!("session"),
+                user_id: user.id,
+                created_at: at("2025-06-20T00:00:00.000Z"),
+                expiration_date: at("2025-06-21T00:00:00.000Z"),
+                provider: None,
+                last_used_at: None,
+                ip_address: None,
+                user_agent: None,
+            })
+            .await
+            .unwrap();
+        assert!(mock_db
+            .deactivate_user(user.id, at("2025-06-20T01:00:00.000Z"))
+            .await
+            .unwrap());
+        assert!(
+            !mock_db
+                .deactivate_user(user.id, at("2025-06-20T02:00:00.000Z"))
+                .await
+                .unwrap(),
+            "deactivate_user: should do nothing to a deactivated user"
+        );
+        let deactivated = mock_db.get_user(user.id).await.unwrap().unwrap();
+        assert_eq!(
+            deactivated.deactivated_at,
+            Some(at("2025-06-20T01:00:00.000Z"))
+        );
+        assert!(
+            mock_db
+                .get_session_by_hash("session")
+                .await
+                .unwrap()
+                .is_none(),
+            "deactivate_user: should end the user's sessions"
+        );
+        assert!(mock_db.reactivate_user(user.id).await.unwrap());
+        assert!(!mock_db.reactivate_user(user.id).await.unwrap());
+        let reactivated = mock_db.get_user(user.id).await.unwrap().unwrap();
+        assert_eq!(reactivated.deactivated_at, None);
+    }
+
+    #[tokio::test]
+    async fn group_management() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+
+        let user1 = mock_db
+            .create_user(
+                s!("username1"),
+                s!("token1"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let group1 = mock_db.create_group(s!("groupname1")).await.unwrap();
+        assert_eq!(
+            group1.name, "groupname1",
+            "create_group: The output group's name should be the same as provided"
+        );
+
+        let fetched_group1 = mock_db.get_group(group1.id).await.unwrap().unwrap();
+        assert_eq!(
+            fetched_group1, group1,
+            "get_group: The fetched group should be equal to the created group"
+        );
+
+        let mut group2 = mock_db.create_group(s!("groupname2")).await.unwrap();
+        let all_groups = mock_db.get_all_groups().await.unwrap();
+        assert_eq!(
+            all_groups.len(),
+            3, // includes admin
+            "get_all_groups: should return the right number of groups"
+        );
+        assert_eq!(
+            all_groups[1], group1,
+            "get_all_groups: should return the right groups in the right order"
+        );
+        assert_eq!(
+            all_groups[2], group2,
+            "get_all_groups: should return the right groups in the right order"
+        );
+
+        "groupname2_updated".clone_into(&mut group2.name);
+        mock_db.update_group(&group2).await.unwrap();
+        let fetched_group2 = mock_db.get_group(group2.id).await.unwrap().unwrap();
+        assert_eq!(fetched_group2, group2, "update_group: should work");
+        let all_groups2 = mock_db.get_all_groups().await.unwrap();
+        assert_eq!(
+            all_groups2.len(),
+            3, // includes admin group
+            "update_group: should not create or delete any groups"
+        );
+
+        let user2 = mock_db
+            .create_user(
+                s!("username2"),
+                s!("token2"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let address1 = mock_db
+            .add_group_membership(group1.id, user1.id)
+            .await
+            .unwrap();
+        let address2 = mock_db
+            .add_group_membership(group1.id, user1.id)
+            .await
+            .unwrap();
+        mock_db
+            .add_group_membership(group1.id, user2.id)
+            .await
+            .unwrap();
+        mock_db
+            .add_group_membership(group2.id, user1.id)
+            .await
+            .unwrap();
+        assert!(
+            address1,
+            "add_group_membership: returns true if there is not already a membership"
+        );
+        assert!(
+            !address2,
+            "add_group_membership: returns false if the membership already exists"
+        );
+
+        let user1_in_group1 = mock_db.group_has_member(group1.id, user1.id).await.unwrap();
+        let user2_in_group2 = mock_db.group_has_member(group2.id, user2.id).await.unwrap();
+        assert!(
+            user1_in_group1,
+            "group_has_member: returns true if a group has a user"
+        );
+        assert!(
+            !user2_in_group2,
+            "group_has_member: returns false if a group does not have a user"
+        );
+
+        let group1_members = mock_db.get_group_members(group1.id).await.unwrap();
+        assert_eq!(
+            group1_members.len(),
+            2,
+            "get_group_members: should return the right number of users"
+        );
+        assert_eq!(
+            group1_members[0], user1,
+            "get_group_members: should return the right users in the right order"
+        );
+        assert_eq!(
+            group1_members[1], user2,
+            "get_group_members: should return the right users in the right order"
+        );
+        let user1_groups = mock_db.get_user_groups(user1.id).await.unwrap();
+        assert_eq!(
+            user1_groups.len(),
+            2,
+            "get_user_groups: should return the right number of groups"
+        );
+        assert_eq!(
+            user1_groups[0], group1,
+            "get_user_groups: should return the right groups in the right order"
+        );
+        assert_eq!(
+            user1_groups[1], group2,
+            "get_user_groups: should return the right groups in the right order"
+        );
+
+        let remres1 = mock_db
+            .remove_group_membership(group1.id, user2.id)
+            .await
+            .unwrap();
+        let remres2 = mock_db
+            .remove_group_membership(group2.id, user2.id)
+            .await
+            .unwrap();
+        assert!(
+            remres1,
+            "remove_group_membership: returns true if the membership can be removed"
+        );
+        assert!(
+            !remres2,
+            "remove_group_membership: returns false if the group membership does not exist"
+        );
+        let group1_members2 = mock_db.get_group_members(group1.id).await.unwrap();
+        assert_eq!(
+            group1_members2.len(),
+            1,
+            "remove_group_membership: should work"
+        );
+        assert_eq!(
+            group1_members2[0], user1,
+            "remove_group_membership: removes the right user"
+        );
+
+        mock_db.delete_group(group1.id).await.unwrap();
+        let all_groups3 = mock_db.get_all_groups().await.unwrap();
+        assert_eq!(
+            all_groups3.len(),
+            2, // includes admin group
+            "delete_group: should work"
+        );
+        assert_eq!(
+            all_groups3[1], group2,
+            "delete_group: should delete the right group"
+        );
+        let fetched_group_fail = mock_db.get_group(group1.id).await.unwrap();
+        assert_eq!(
+            fetched_group_fail, None,
+            "get_group: does not get a deleted group"
+        );
+        let group1_members3 = mock_db.get_group_members(group1.id).await.unwrap();
+        assert_eq!(
+            group1_members3.len(),
+            0,
+            "delete_group: deletes all associated memberships along with the group"
+        );
+        mock_db.purge_user(user1.id).await.unwrap();
+        let group2_members = mock_db.get_group_members(group2.id).await.unwrap();
+        assert_eq!(
+            group2_members.len(),
+            0,
+            "purge_user: deletes all associated group memberships along with the user"
+        );
+    }
+
+    #[tokio::test]
+    async fn permissions_management() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let group1 = mock_db.create_group(s!("groupname1")).await.unwrap();
+
+        let permissions1 = mock_db.get_group_permissions(group1.id).await.unwrap();
+        assert_eq!(
+            permissions1.len(),
+            0,
+            "get_group_permissions: returns 0 when no permissions are added"
+        );
+        let has_manage_content1 = mock_db
+            .group_has_permission(group1.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        assert!(
+            !has_manage_content1,
+            "group_has_permission: should return false if the group does not have the permission"
+        );
+
+        let permission_added = mock_db
+            .add_group_permission(group1.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        let has_manage_content2 = mock_db
+            .group_has_permission(group1.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        assert!(
+            permission_added,
+            "add_group_permission: returns true when the permission has been added"
+        );
+        assert!(has_manage_content2, "add_group_permission: works, group_has_permission: should return true if the group does have the permission");
+        let permissions2 = mock_db.get_group_permissions(group1.id).await.unwrap();
+        assert_eq!(
+            permissions2,
+            vec![Permission::ManageContent],
+            "get_group_permissions: returns the right thing"
+        );
+        let already_added = mock_db
+            .add_group_permission(group1.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        assert!(
+            !already_added,
+            "add_group_permission: should return false if group already has the permission"
+        );
+
+        let permission_removed = mock_db
+            .remove_group_permission(group1.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        assert!(
+            permission_removed,
+            "remove_group_permission: returns true when permission has been removed"
+        );
+        let already_removed = mock_db
+            .remove_group_permission(group1.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        assert!(
+            !already_removed,
+            "remove_group_permission: returns false when the group didn't have the permission"
+        );
+        let has_manage_content3 = mock_db
+            .group_has_permission(group1.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        assert!(!has_manage_content3, "remove_group_permission: works");
+        let permissions3 = mock_db.get_group_permissions(group1.id).await.unwrap();
+        assert_eq!(
+            permissions3.len(),
+            0,
+            "get_group_permissions: returns 0 when the permission has been removed"
+        );
+
+        let admin_permissions = mock_db.get_group_permissions(1).await.unwrap();
+        assert_eq!(
+            admin_permissions,
+            vec![
+                Permission::ManageContent,
+                Permission::ManageUsers,
+                Permission::ManageBranches,
+                Permission::ManageAssets,
+                Permission::ReadContent,
+                Permission::ManageRepo
+            ],
+            "admin group should have the right permissions"
+        );
+    }
+
+    #[tokio::test]
+    async fn doc_owner_management() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(
+                s!("username"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let group = mock_db.create_group(s!("groupname")).await.unwrap();
+
+        assert!(
+            mock_db.add_doc_owner("foo/", None, None).await.is_err(),
+            "add_doc_owner: should reject an owner that is neither a user or group"
+        );
+        assert!(
+            mock_db
+                .add_doc_owner("foo/", None, Some(group.id))
+                .await
+                .unwrap(),
+            "add_doc_owner: returns true when the owner was added"
+        );
+        assert!(
+            !mock_db
+                .add_doc_owner("foo/", None, Some(group.id))
+                .await
+                .unwrap(),
+            "add_doc_owner: returns false when the owner already exists"
+        );
+        mock_db
+            .add_doc_owner("foo/bar.md", Some(user.id), None)
+            .await
+            .unwrap();
+
+        let bar_owners = mock_db.resolve_doc_owners("foo/bar.md").await.unwrap();
+        assert_eq!(
+            bar_owners,
+            vec![DocOwner {
+                path: s!("foo/bar.md"),
+                user_id: Some(user.id),
+                group_id: None
+            }],
+            "resolve_doc_owners: the most specific entry should win"
+        );
+        let baz_owners = mock_db.resolve_doc_owners("foo/baz.md").await.unwrap();
+        assert_eq!(
+            baz_owners[0].group_id,
+            Some(group.id),
+            "resolve_doc_owners: documents should inherit directory owners"
+        );
+        assert!(
+            mock_db
+                .resolve_doc_owners("foobar.md")
+                .await
+                .unwrap()
+                .is_empty(),
+            "resolve_doc_owners: unowned documents should have no owners"
+        );
+
+        assert_eq!(
+            mock_db.remove_doc_owners("foo/bar.md").await.unwrap(),
+            1,
+            "remove_doc_owners: should remove the owners of exactly that path"
+        );
+        mock_db
+            .add_doc_owner("foo/", Some(user.id), None)
+            .await
+            .unwrap();
+        let mut owner_ids: Vec<i64> = mock_db
+            .resolve_doc_owner_users("foo/baz.md")
+            .await
+            .unwrap()
+            .iter()
+            .map(|u| u.id)
+            .collect();
+        assert_eq!(
+            owner_ids,
+            vec![user.id],
+            "resolve_doc_owner_users: users and group members should be listed once"
+        );
+        mock_db
+            .add_group_membership(group.id, user.id)
+            .await
+            .unwrap();
+        owner_ids = mock_db
+            .resolve_doc_owner_users("foo/baz.md")
+            .await
+            .unwrap()
+            .iter()
+            .map(|u| u.id)
+            .collect();
+        assert_eq!(owner_ids, vec![user.id]);
+
+        mock_db
+            .add_doc_owner("bar/", Some(user.id), None)
+            .await
+            .unwrap();
+        mock_db.move_doc_owners("foo/", "moved/").await.unwrap();
+        assert_eq!(
+            mock_db
+                .resolve_doc_owners("moved/baz.md")
+                .await
+                .unwrap()
+                .len(),
+            2,
+            "move_doc_owners: the owners of a directory should move with it"
+        );
+        assert!(mock_db
+            .resolve_doc_owners("foo/baz.md")
+            .await
+            .unwrap()
+            .is_empty());
+        mock_db
+            .add_doc_owner("moved/a.md", Some(user.id), None)
+            .await
+            .unwrap();
+        mock_db
+            .move_doc_owners("moved/a.md", "archive/a.md")
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_db.resolve_doc_owners("archive/a.md").await.unwrap()[0].path,
+            "archive/a.md"
+        );
+        assert_eq!(
+            mock_db.remove_doc_owners_under("moved/").await.unwrap(),
+            2,
+            "remove_doc_owners_under: should only remove the owners in the directory"
+        );
+
+        mock_db
+            .add_doc_owner("qux/", None, Some(group.id))
+            .await
+            .unwrap();
+        mock_db.delete_group(group.id).await.unwrap();
+        assert!(
+            mock_db
+                .get_all_doc_owners()
+                .await
+                .unwrap()
+                .iter()
+                .all(|o| o.group_id.is_none()),
+            "delete_group: deletes all associated doc ownership"
+        );
+    }
+
+    #[tokio::test]
+    async fn stale_reminders() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(
+                s!("username"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let remind = |since, now| mock_db.record_stale_reminder("a.md", user.id, since, now);
+        assert!(remind(
+            at("2025-01-01T00:00:00.000Z"),
+            at("2025-02-01T00:00:00.000Z")
+        )
+        .await
+        .unwrap());
+        assert!(
+            !remind(
+                at("2025-01-15T00:00:00.000Z"),
+                at("2025-02-15T00:00:00.000Z")
+            )
+            .await
+            .unwrap(),
+            "record_stale_reminder: shouldn't remind again within the interval"
+        );
+        assert!(remind(
+            at("2025-03-01T00:00:00.000Z"),
+            at("2025-04-01T00:00:00.000Z")
+        )
+        .await
+        .unwrap());
+    }
+
+    #[tokio::test]
+    async fn editing_ticket_management() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(
+                s!("username"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let ticket = EditingTicket {
+            id: s!("ticket"),
+            user_id: user.id,
+            path: s!("foo.md"),
+            base_commit: s!("commit"),
+            blob_sha: s!("blob"),
+            expiration_date: at("2025-02-10T00:00:00.000Z"),
+            branch: s!("main"),
+        };
+
+        let created = mock_db.create_editing_ticket(&ticket).await.unwrap();
+        assert_eq!(
+            created, ticket,
+            "create_editing_ticket: should return the stored ticket"
+        );
+        let fetched = mock_db.get_editing_ticket("ticket").await.unwrap();
+        assert_eq!(
+            fetched,
+            Some(ticket),
+            "get_editing_ticket: should fetch the stored ticket"
+        );
+        assert_eq!(
+            mock_db
+                .claim_editing_ticket("ticket", user.id + 1)
+                .await
+                .unwrap(),
+            None,
+            "claim_editing_ticket: only the user the ticket was issued to should claim it"
+        );
+        assert_eq!(
+            mock_db
+                .claim_editing_ticket("ticket", user.id)
+                .await
+                .unwrap(),
+            fetched
+        );
+        assert!(
+            mock_db
+                .get_editing_ticket("ticket")
+                .await
+                .unwrap()
+                .is_none(),
+            "claim_editing_ticket: should delete the ticket"
+        );
+        assert_eq!(
+            mock_db
+                .claim_editing_ticket("ticket", user.id)
+                .await
+                .unwrap(),
+            None,
+            "claim_editing_ticket: a ticket should only be claimed once"
+        );
+    }
+
+    #[tokio::test]
+    async fn webhook_queue() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let first = mock_db
+            .enqueue_webhook_event("push", Some("abc"), "{}", at("2025-01-01T00:00:00.000Z"))
+            .await
+            .unwrap();
+        mock_db
+            .enqueue_webhook_event("ping", None, "{}", at("2025-01-01T00:00:01.000Z"))
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_db
+                .next_due_webhook_event(at("2025-01-01T00:00:00.500Z"))
+                .await
+                .unwrap(),
+            Some(first.clone()),
+            "next_due_webhook_event: should return the oldest due event"
+        );
+
+        mock_db
+            .fail_webhook_event(first.id, "locked", Some(at("2025-01-01T00:01:00.000Z")))
+            .await
+            .unwrap();
+        let next = mock_db
+            .next_due_webhook_event(at("2025-01-01T00:00:30.000Z"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            next.event_type, "ping",
+            "next_due_webhook_event: should skip events waiting to be retried"
+        );
+        mock_db
+            .complete_webhook_event(next.id, at("2025-01-01T00:00:30.000Z"))
+            .await
+            .unwrap();
+        mock_db
+            .fail_webhook_event(first.id, "locked", None)
+            .await
+            .unwrap();
+        assert!(
+            mock_db
+                .next_due_webhook_event(at("2025-01-02T00:00:00.000Z"))
+                .await
+                .unwrap()
+                .is_none(),
+            "fail_webhook_event: events without a next attempt shouldn't be retried"
+        );
+
+        let events = mock_db.get_recent_webhook_events(10).await.unwrap();
+        assert_eq!(events[1].attempts, 2);
+        assert_eq!(events[1].last_error.as_deref(), Some("locked"));
+        assert_eq!(
+            mock_db.get_webhook_queue_status().await.unwrap(),
+            WebhookQueueStatus {
+                pending: 0,
+                completed: 1,
+                failed: 1
+            }
+        );
+
+        let failed = mock_db
+            .query_webhook_events(
+                &WebhookEventFilter {
+                    status: Some(String::from("failed")),
+                    ..Default::default()
+                },
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].id, first.id);
+        let older = mock_db
+            .query_webhook_events(
+                &WebhookEventFilter {
+                    before: Some(events[0].id),
+                    ..Default::default()
+                },
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(older.len(), events.len() - 1);
+    }
+
+    #[tokio::test]
+    async fn github_account_linking() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user1 = mock_db
+            .create_user(
+                s!("user1"),
+                s!("token1"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let user2 = mock_db
+            .create_user(
+                s!("user2"),
+                s!("token2"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let account = GitHubAccount {
+            github_id: 1234,
+            github_login: s!("octocat"),
+        };
+
+        assert_eq!(mock_db.get_github_account(user1.id).await.unwrap(), None);
+        mock_db
+            .link_github_account(user1.id, &account)
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_db.get_github_account(user1.id).await.unwrap(),
+            Some(account.clone()),
+            "link_github_account: should store the account"
+        );
+        assert!(
+            mock_db
+                .link_github_account(user2.id, &account)
+                .await
+                .is_err(),
+            "link_github_account: an account should only be linked to one user"
+        );
+        assert_eq!(
+            mock_db.get_user(user1.id).await.unwrap().unwrap().username,
+            "user1",
+            "get_user: should still work with a linked account"
+        );
+        mock_db.unlink_github_account(user1.id).await.unwrap();
+        assert_eq!(
+            mock_db.get_github_account(user1.id).await.unwrap(),
+            None,
+            "unlink_github_account: should remove the account"
+        );
+    }
+
+    #[tokio::test]
+    async fn hyde_prs() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let pr = |number, title: &str| HydePr {
+            number,
+            title: s!(title),
+            author_id: None,
+            branch: None,
+            status: s!("open"),
+            doc_paths: vec![s!("guides/foo.md"), s!("guides/bar.md")],
+            created_at: at("2025-01-01T00:00:00.000Z"),
+            merged_at: None,
+        };
+        mock_db.record_hyde_pr(&pr(1, "Old")).await.unwrap();
+        mock_db.record_hyde_pr(&pr(2, "New")).await.unwrap();
+        mock_db.record_hyde_pr(&pr(3, "Unmerged")).await.unwrap();
+        assert!(mock_db.is_hyde_pr(3).await.unwrap());
+        assert!(!mock_db.is_hyde_pr(4).await.unwrap());
+        assert!(mock_db
+            .mark_hyde_pr_merged(1, at("2025-01-02T00:00:00.000Z"))
+            .await
+            .unwrap());
+        assert!(mock_db
+            .mark_hyde_pr_merged(2, at("2025-02-01T00:00:00.000Z"))
+            .await
+            .unwrap());
+        assert!(
+            !mock_db
+                .mark_hyde_pr_merged(4, at("2025-02-01T00:00:00.000Z"))
+                .await
+                .unwrap(),
+            "mark_hyde_pr_merged: pull requests not opened through Hyde should be ignored"
+        );
+
+        let merged = mock_db
+            .get_merged_hyde_prs(at("2025-01-15T00:00:00.000Z"))
+            .await
+            .unwrap();
+        assert_eq!(
+            merged.len(),
+            1,
+            "get_merged_hyde_prs: should only return pull requests merged since the given time"
+        );
+        assert_eq!(merged[0].title, "New");
+        assert_eq!(merged[0].status, "merged");
+        assert_eq!(merged[0].doc_paths, ["guides/bar.md", "guides/foo.md"]);
+    }
+
+    #[tokio::test]
+    async fn user_hyde_prs() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let alice = mock_db
+            .create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        for (number, created_at) in [
+            (1, at("2025-01-01T00:00:00.000Z")),
+            (2, at("2025-01-02T00:00:00.000Z")),
+        ] {
+            mock_db
+                .record_hyde_pr(&HydePr {
+                    number,
+                    title: format!("Proposal {number}"),
+                    author_id: Some(alice.id),
+                    branch: Some(format!("alice/proposal-{number}")),
+                    status: s!("open"),
+                    doc_paths: vec![s!("a.md")],
+                    created_at: s!(created_at),
+                    merged_at: None,
+                })
+                .await
+                .unwrap();
+        }
+        assert!(mock_db.set_hyde_pr_status(1, "closed").await.unwrap());
+        assert!(!mock_db.set_hyde_pr_status(3, "closed").await.unwrap());
+
+        let all = mock_db.get_user_hyde_prs(alice.id, None).await.unwrap();
+        assert_eq!(
+            all.iter().map(|pr| pr.number).collect::<Vec<_>>(),
+            [2, 1],
+            "get_user_hyde_prs: should return the newest pull requests first"
+        );
+        assert_eq!(all[0].branch.as_deref(), Some("alice/proposal-2"));
+        assert_eq!(all[0].doc_paths, ["a.md"]);
+        let open = mock_db
+            .get_user_hyde_prs(alice.id, Some("open"))
+            .await
+            .unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].number, 2);
+    }
+
+    #[tokio::test]
+    async fn group_admin_scopes() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(
+                s!("moderator"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let moderators = mock_db.create_group(s!("Moderators")).await.unwrap();
+        let helpers = mock_db.create_group(s!("Helpers")).await.unwrap();
+        let trusted = mock_db.create_group(s!("Trusted")).await.unwrap();
+        mock_db
+            .add_group_membership(moderators.id, user.id)
+            .await
+            .unwrap();
+
+        mock_db
+            .set_group_admin_scopes(moderators.id, &[helpers.id, trusted.id])
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_db.get_group_admin_scopes(moderators.id).await.unwrap(),
+            [helpers.id, trusted.id],
+            "set_group_admin_scopes: should store every managed group"
+        );
+        assert_eq!(
+            mock_db.get_user_managed_groups(user.id).await.unwrap(),
+            [helpers.id, trusted.id],
+            "get_user_managed_groups: should include the scopes of the user's groups"
+        );
+
+        mock_db
+            .set_group_admin_scopes(moderators.id, &[helpers.id])
+            .await
+            .unwrap();
+        mock_db.delete_group(helpers.id).await.unwrap();
+        assert!(
+            mock_db
+                .get_user_managed_groups(user.id)
+                .await
+                .unwrap()
+                .is_empty(),
+            "set_group_admin_scopes: should replace the previous scopes, \
+            and deleting a managed group should remove its scope"
+        );
+    }
+
+    #[tokio::test]
+    async fn group_owners() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let owner = mock_db
+            .create_user(s!("owner"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        let helpers = mock_db.create_group(s!("Helpers")).await.unwrap();
+        let trusted = mock_db.create_group(s!("Trusted")).await.unwrap();
+        mock_db
+            .set_group_admin_scopes(helpers.id, &[trusted.id])
+            .await
+            .unwrap();
+        mock_db
+            .add_group_membership(helpers.id, owner.id)
+            .await
+            .unwrap();
+
+        mock_db
+            .set_group_owners(helpers.id, &[owner.id])
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_db.get_group_owners(helpers.id).await.unwrap(),
+            [owner.id]
+        );
+        assert_eq!(
+            mock_db.get_user_managed_groups(owner.id).await.unwrap(),
+            [helpers.id, trusted.id],
+            "get_user_managed_groups: should include owned groups along with scopes"
+        );
+
+        mock_db.set_group_owners(helpers.id, &[]).await.unwrap();
+        assert_eq!(
+            mock_db.get_user_managed_groups(owner.id).await.unwrap(),
+            [trusted.id]
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshots() {
+        // `VACUUM INTO` and `ATTACH` don't write files from in-memory databases
+        let dir = std::env::temp_dir().join(format!("hyde-snapshots-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = |name: &str| format!("file:{}?mode=rwc", dir.join(name).display());
+        let primary = Database::from_url(&url("primary.db")).await.unwrap();
+        let replica = Database::from_url(&url("replica.db")).await.unwrap();
+        let user = primary
+            .create_user(
+                s!("primary"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let group = primary.create_group(s!("Editors")).await.unwrap();
+        primary
+            .add_group_membership(group.id, user.id)
+            .await
+            .unwrap();
+        replica
+            .create_user(
+                s!("replica"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+
+        let path = dir.join("snapshot.db");
+        primary.snapshot(&path).await.unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+                0o600,
+                "snapshot: only the owner should be able to read snapshots"
+            );
+        }
+        replica.restore_snapshot(&path).await.unwrap();
+
+        assert_eq!(
+            replica.get_all_users().await.unwrap(),
+            [user],
+            "restore_snapshot: should replace the existing rows"
+        );
+        assert_eq!(
+            replica.get_group_members(group.id).await.unwrap().len(),
+            1,
+            "restore_snapshot: rows referencing other tables should be restored"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn backups() {
+        let dir = std::env::temp_dir().join(format!("hyde-backups-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = |path: &Path| format!("file:{}?mode=rwc", path.display());
+        let db = Database::from_url(&url(&dir.join("data.db")))
+            .await
+            .unwrap();
+        let user = db
+            .create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+
+        let path = dir.join("backups").join("backup.db");
+        db.backup_to(&path).await.unwrap();
+        assert!(
+            !path.with_extension("partial").exists(),
+            "backup_to: should rename the backup into place"
+        );
+        assert!(
+            db.backup_to(&path).await.is_err(),
+            "backup_to: should refuse to overwrite an existing backup"
+        );
+        let backup = Database::from_url(&url(&path)).await.unwrap();
+        assert_eq!(
+            backup.get_all_users().await.unwrap(),
+            [user],
+            "backup_to: the backup should contain the database's rows"
+        );
+        backup.close().await;
+
+        db.create_user(s!("bob"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        db.close().await;
+        let database_path = dir.join("data.db");
+        let replaced = Database::restore_backup(&database_path, &path, &SqliteSettings::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            replaced.exists(),
+            "restore_backup: should keep the replaced database"
+        );
+        let restored = Database::from_url(&url(&database_path)).await.unwrap();
+        assert_eq!(
+            restored.get_all_users().await.unwrap().len(),
+            1,
+            "restore_backup: should replace the database with the backup"
+        );
+        restored.close().await;
+        std::fs::write(dir.join("garbage.db"), "not a database").unwrap();
+        assert!(
+            Database::restore_backup(
+                &database_path,
+                &dir.join("garbage.db"),
+                &SqliteSettings::default(),
+            )
+            .await
+            .is_err(),
+            "restore_backup: should reject files that aren't databases"
+        );
+        assert!(database_path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn encryption() {
+        let dir = std::env::temp_dir().join(format!("hyde-encryption-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = DatabaseUrl::SqliteFile(dir.join("data.db"));
+        let keyed = |key: Option<&str>| SqliteSettings {
+            key: key.map(ToString::to_string),
+            ..Default::default()
+        };
+        if !cfg!(feature = "sqlcipher") {
+            assert!(
+                Database::new(&url, &keyed(Some("hunter2"))).await.is_err(),
+                "new: keys should be rejected without the sqlcipher feature"
+            );
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+
+        let db = Database::new(&url, &keyed(Some("hunter2"))).await.unwrap();
+        db.create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        let backup = dir.join("backup.db");
+        db.backup_to(&backup).await.unwrap();
+        db.close().await;
+        assert!(
+            !std::fs::read(dir.join("data.db"))
+                .unwrap()
+                .starts_with(b"SQLite format 3"),
+            "new: the database file should be encrypted"
+        );
+        assert!(Database::new(&url, &keyed(None)).await.is_err());
+        assert!(Database::new(&url, &keyed(Some("hunter3"))).await.is_err());
+
+        Database::restore_backup(&dir.join("data.db"), &backup, &keyed(Some("hunter2")))
+            .await
+            .unwrap();
+        let db = Database::new(&url, &keyed(Some("hunter2"))).await.unwrap();
+        assert_eq!(
+            db.get_all_users().await.unwrap().len(),
+            1,
+            "restore_backup: backups should be encrypted with the same key"
+        );
+        db.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_settings() {
+        let dir = std::env::temp_dir().join(format!("hyde-sqlite-settings-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = DatabaseUrl::SqliteFile(dir.join("data.db"));
+        let pragmas = |db: Database| async move {
+            let pool = db.sqlite_pool().unwrap();
+            let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode;")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+            let synchronous: i64 = sqlx::query_scalar("PRAGMA synchronous;")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+            let busy_timeout: i64 = sqlx::query_scalar("PRAGMA busy_timeout;")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+            db.close().await;
+            (journal_mode, synchronous, busy_timeout)
+        };
+
+        let db = Database::new(&url, &SqliteSettings::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            pragmas(db).await,
+            (s!("wal"), 1, 5000),
+            "new: should default to WAL mode, synchronous = NORMAL and a 5 second busy timeout"
+        );
+        let db = Database::new(
+            &url,
+            &SqliteSettings {
+                journal_mode: JournalMode::Delete,
+                synchronous: Synchronous::Full,
+                busy_timeout: Duration::from_millis(250),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(pragmas(db).await, (s!("delete"), 2, 250));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn notifications() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let alice = mock_db
+            .create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        let bob = mock_db
+            .create_user(s!("bob"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for kind in ["review_requested", "pr_merged", "mention"] {
+            let notification = mock_db
+                .create_notification(
+                    alice.id,
+                    kind,
+                    r#"{"number":1}"#,
+                    at("2025-07-15T00:00:00.000Z"),
+                )
+                .await
+                .unwrap();
+            assert_eq!(notification.read_at, None);
+            ids.push(notification.id);
+        }
+        assert_eq!(
+            mock_db.count_unread_notifications(alice.id).await.unwrap(),
+            3
+        );
+        assert_eq!(mock_db.count_unread_notifications(bob.id).await.unwrap(), 0);
+
+        assert!(
+            !mock_db
+                .mark_notification_read(bob.id, ids[0], at("2025-07-16T00:00:00.000Z"))
+                .await
+                .unwrap(),
+            "mark_notification_read: users shouldn't be able to read others' notifications"
+        );
+        assert!(mock_db
+            .mark_notification_read(alice.id, ids[0], at("2025-07-16T00:00:00.000Z"))
+            .await
+            .unwrap());
+        assert!(
+            !mock_db
+                .mark_notification_read(alice.id, ids[0], at("2025-07-17T00:00:00.000Z"))
+                .await
+                .unwrap(),
+            "mark_notification_read: notifications should only be read once"
+        );
+        assert_eq!(
+            mock_db.count_unread_notifications(alice.id).await.unwrap(),
+            2
+        );
+
+        let all = mock_db
+            .get_notifications(alice.id, false, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            all.iter().map(|n| n.kind.as_str()).collect::<Vec<_>>(),
+            ["mention", "pr_merged", "review_requested"],
+            "get_notifications: should return the newest notifications first"
+        );
+        assert_eq!(all[2].read_at, Some(at("2025-07-16T00:00:00.000Z")));
+        let unread = mock_db.get_notifications(alice.id, true, 10).await.unwrap();
+        assert_eq!(unread.len(), 2);
+        assert_eq!(
+            mock_db
+                .get_notifications(alice.id, false, 1)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
 
-    /// I am too lazy to type to_owned everywhere
-    macro_rules! s {
-        ($str: tt) => {
-            $str.to_owned()
-        };
+        assert_eq!(
+            mock_db
+                .mark_all_notifications_read(alice.id, at("2025-07-18T00:00:00.000Z"))
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            mock_db.count_unread_notifications(alice.id).await.unwrap(),
+            0
+        );
     }
 
     #[tokio::test]
-    async fn user_management() {
+    async fn sessions() {
         let mock_db = Database::from_url(":memory:").await.unwrap();
-
-        let mock_user = mock_db
+        let user = mock_db
             .create_user(
                 s!("username"),
-                s!("token"),
-                s!("expiration_date"),
+                s!("discord token"),
+                DateTime::UNIX_EPOCH,
                 s!("https://foo.bar"),
             )
             .await
             .unwrap();
+        let session = |id: &str, expiration_date| Session {
+            id: id.to_string(),
+            user_id: user.id,
+            created_at: at("2025-01-01T00:00:00.000Z"),
+            expiration_date,
+            provider: Some(s!("discord")),
+            last_used_at: None,
+            ip_address: Some(s!("203.0.113.7")),
+            user_agent: None,
+        };
+        let current = mock_db
+            .create_session(&session("current", at("2025-02-01T00:00:00.000Z")))
+            .await
+            .unwrap();
+        mock_db
+            .create_session(&session("expired", at("2025-01-02T00:00:00.000Z")))
+            .await
+            .unwrap();
 
         assert_eq!(
-            mock_user.username, "username",
-            "create_user: The new user's username should be the input"
+            mock_db.get_session_by_hash("current").await.unwrap(),
+            Some(current),
+            "get_session_by_hash: should return the stored session"
         );
         assert_eq!(
-            mock_user.token, "token",
-            "create_user: The new user's token should be the input"
+            mock_db
+                .delete_expired_sessions(at("2025-01-15T00:00:00.000Z"))
+                .await
+                .unwrap(),
+            1,
+            "delete_expired_sessions: should only delete expired sessions"
         );
+        mock_db
+            .extend_session("current", at("2025-03-01T00:00:00.000Z"))
+            .await
+            .unwrap();
         assert_eq!(
-            mock_user.expiration_date, "expiration_date",
-            "create_user: The new user's expiration date should be the input"
+            mock_db
+                .get_session_by_hash("current")
+                .await
+                .unwrap()
+                .unwrap()
+                .expiration_date,
+            at("2025-03-01T00:00:00.000Z"),
+            "extend_session: should move the expiration date"
         );
         assert_eq!(
-            mock_user.avatar_url, "https://foo.bar",
-            "create_user: the new user's expiration date should be the input"
+            mock_db
+                .get_active_sessions(at("2025-01-15T00:00:00.000Z"))
+                .await
+                .unwrap()
+                .len(),
+            1
         );
-
-        let fetched_user = mock_db.get_user(mock_user.id).await.unwrap().unwrap();
         assert_eq!(
-            mock_user, fetched_user,
-            "get_user: The fetched user's id should be the same as the created user"
+            mock_db
+                .get_user_sessions(user.id, at("2025-01-15T00:00:00.000Z"))
+                .await
+                .unwrap()
+                .len(),
+            1,
+            "get_user_sessions: should only return the user's active sessions"
         );
-
-        let token_user = mock_db
-            .get_user_from_token(s!("token"))
+        assert!(mock_db
+            .get_user_sessions(user.id + 1, at("2025-01-15T00:00:00.000Z"))
             .await
             .unwrap()
+            .is_empty());
+        let last_used_at = || async {
+            mock_db
+                .get_session_by_hash("current")
+                .await
+                .unwrap()
+                .unwrap()
+                .last_used_at
+        };
+        mock_db
+            .touch_session(
+                "current",
+                at("2025-01-15T00:00:00.000Z"),
+                at("2025-01-14T23:59:00.000Z"),
+            )
+            .await
             .unwrap();
-        assert_eq!(token_user, mock_user, "get_user_from_token: should work");
-
-        let mut mock_user2 = mock_db
-            .create_user(
-                s!("username2"),
-                s!("token2"),
-                s!("expiration_date2"),
-                s!("https://foo.bar/2"),
+        mock_db
+            .touch_session(
+                "current",
+                at("2025-01-15T00:00:30.000Z"),
+                at("2025-01-14T23:59:30.000Z"),
             )
             .await
             .unwrap();
-        let all_users = mock_db.get_all_users().await.unwrap();
         assert_eq!(
-            all_users.len(),
-            2,
-            "get_all_users: The vector of all users should contain the right amount of users"
+            last_used_at().await,
+            Some(at("2025-01-15T00:00:00.000Z")),
+            "touch_session: should only record use once a minute"
         );
+        assert_eq!(mock_db.get_refresh_token(user.id).await.unwrap(), None);
+        mock_db
+            .set_refresh_token(user.id, Some("refresh"))
+            .await
+            .unwrap();
         assert_eq!(
-            all_users[0], mock_user,
-            "get_all_users: The user vector's first element should be the first user inserted"
+            mock_db.get_refresh_token(user.id).await.unwrap().as_deref(),
+            Some("refresh"),
+            "set_refresh_token: should store the refresh token"
+        );
+        assert!(mock_db.delete_session("current").await.unwrap());
+        assert!(
+            !mock_db.delete_session("current").await.unwrap(),
+            "delete_session: should report that the session no longer exists"
         );
+        mock_db
+            .create_session(&session("other", at("2025-02-01T00:00:00.000Z")))
+            .await
+            .unwrap();
+        assert_eq!(mock_db.delete_user_sessions(user.id).await.unwrap(), 1);
+
+        mock_db
+            .create_session(&session("unhashed", at("2025-02-01T00:00:00.000Z")))
+            .await
+            .unwrap();
+        assert_eq!(mock_db.hash_stored_sessions().await.unwrap(), 1);
         assert_eq!(
-            all_users[1], mock_user2,
-            "get_all_users: The user vector's second element should be the second user inserted"
+            mock_db.hash_stored_sessions().await.unwrap(),
+            0,
+            "hash_stored_sessions: should leave hashed ids alone"
         );
+        assert!(mock_db
+            .get_session_by_hash(&api_tokens::hash("unhashed"))
+            .await
+            .unwrap()
+            .is_some());
+    }
 
-        "username2_updated".clone_into(&mut mock_user2.username);
-        mock_db.update_user(&mock_user2).await.unwrap();
-        let updated_user2 = mock_db.get_user(mock_user2.id).await.unwrap().unwrap();
-        let all_users2 = mock_db.get_all_users().await.unwrap();
+    #[tokio::test]
+    async fn user_identities() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let create_user = |username: &str| {
+            mock_db.create_user(
+                username.to_string(),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+        };
+        let alice = create_user("alice").await.unwrap();
+        let bob = create_user("bob").await.unwrap();
+        let identity = |user_id: i64, provider: &str, subject: &str| UserIdentity {
+            provider: provider.to_string(),
+            subject: subject.to_string(),
+            user_id,
+            username: s!("name"),
+        };
+
+        mock_db
+            .link_identity(&identity(alice.id, "discord", "1"))
+            .await
+            .unwrap();
+        mock_db
+            .link_github_account(
+                alice.id,
+                &GitHubAccount {
+                    github_id: 2,
+                    github_login: s!("alice"),
+                },
+            )
+            .await
+            .unwrap();
         assert_eq!(
-            updated_user2.username, "username2_updated",
-            "update_user: The new username should be updated"
+            mock_db
+                .get_identity_user("github", "2")
+                .await
+                .unwrap()
+                .map(|u| u.id),
+            Some(alice.id),
+            "link_github_account: linked GitHub accounts should be signed in with"
+        );
+        assert!(
+            mock_db
+                .link_identity(&identity(bob.id, "discord", "1"))
+                .await
+                .is_err(),
+            "link_identity: an account can't be linked to two users"
         );
+
+        mock_db
+            .link_identity(&identity(alice.id, "discord", "3"))
+            .await
+            .unwrap();
         assert_eq!(
-            all_users2.len(),
-            2,
-            "update_user: the function should not create any more users"
+            mock_db
+                .get_user_identities(alice.id)
+                .await
+                .unwrap()
+                .iter()
+                .map(|i| (i.provider.as_str(), i.subject.as_str()))
+                .collect::<Vec<_>>(),
+            [("discord", "3"), ("github", "2")],
+            "link_identity: should replace the account linked from the same provider"
+        );
+        mock_db.unlink_github_account(alice.id).await.unwrap();
+        assert!(mock_db.unlink_identity(alice.id, "discord").await.unwrap());
+        assert!(mock_db
+            .get_user_identities(alice.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn service_accounts() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(
+                s!("alice"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        assert!(!user.service_account);
+        let account = mock_db
+            .create_service_account("changelog-importer")
+            .await
+            .unwrap();
+        assert!(
+            account.service_account,
+            "create_service_account: should mark the user as a service account"
         );
+        assert_eq!(account.token, "");
+        assert_eq!(mock_db.get_user(account.id).await.unwrap(), Some(account));
+    }
 
-        mock_db.delete_user(mock_user2.id).await.unwrap();
-        let all_users3 = mock_db.get_all_users().await.unwrap();
+    #[tokio::test]
+    async fn device_codes() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(
+                s!("alice"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        mock_db
+            .create_device_code(
+                "abc123",
+                "WDJBMJHT",
+                "hyde-cli",
+                at("2025-04-10T00:00:00.000Z"),
+                at("2025-04-10T00:10:00.000Z"),
+            )
+            .await
+            .unwrap();
+        let pending = mock_db
+            .get_device_code_by_user_code("WDJBMJHT")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.user_id, None);
+        assert!(mock_db
+            .resolve_device_code("WDJBMJHT", user.id, false)
+            .await
+            .unwrap());
+        assert!(
+            !mock_db
+                .resolve_device_code("WDJBMJHT", user.id, true)
+                .await
+                .unwrap(),
+            "resolve_device_code: requests should only be resolved once"
+        );
+        let approved = mock_db.get_device_code("abc123").await.unwrap().unwrap();
+        assert_eq!(approved.user_id, Some(user.id));
+        assert!(!approved.denied);
+        assert!(mock_db
+            .delete_device_code("abc123", at("2025-04-10T00:01:00.000Z"))
+            .await
+            .unwrap());
+        assert!(
+            !mock_db
+                .delete_device_code("abc123", at("2025-04-10T00:01:00.000Z"))
+                .await
+                .unwrap(),
+            "delete_device_code: device codes should only be exchanged once"
+        );
+    }
+
+    #[tokio::test]
+    async fn audit_log() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        for (created_at, category) in [
+            (at("2025-04-15T00:00:00.000Z"), "auth"),
+            (at("2025-04-15T00:01:00.000Z"), "other"),
+            (at("2025-04-15T00:02:00.000Z"), "auth"),
+        ] {
+            mock_db
+                .record_audit(&AuditEntry {
+                    id: 0,
+                    created_at: s!(created_at),
+                    category: s!(category),
+                    action: s!("login"),
+                    user_id: Some(1),
+                    username: Some(s!("alice")),
+                    ip_address: None,
+                    detail: s!("Signed in with Discord"),
+                    target: (category == "other").then(|| s!("docs/a.md")),
+                    data: None,
+                })
+                .await
+                .unwrap();
+        }
+        let filter = |category: Option<&str>| AuditFilter {
+            category: category.map(String::from),
+            ..Default::default()
+        };
+        let auth = mock_db
+            .query_audit(&filter(Some("auth")), 10)
+            .await
+            .unwrap();
+        assert_eq!(auth.len(), 2, "query_audit: should filter by category");
         assert_eq!(
-            all_users3.len(),
-            1,
-            "delete_user: the function should delete exactly one user"
+            auth[0].created_at,
+            at("2025-04-15T00:02:00.000Z"),
+            "query_audit: should return the newest entries first"
         );
         assert_eq!(
-            all_users3[0], mock_user,
-            "delete_user: the function should delete the correct user"
+            mock_db.query_audit(&filter(None), 10).await.unwrap().len(),
+            3
         );
+        let newest = mock_db.query_audit(&filter(None), 1).await.unwrap();
+        assert_eq!(newest.len(), 1);
+        let older = AuditFilter {
+            before: Some(newest[0].id),
+            ..Default::default()
+        };
+        assert_eq!(
+            mock_db.query_audit(&older, 10).await.unwrap().len(),
+            2,
+            "query_audit: should page through entries older than `before`"
+        );
+        let about_doc = AuditFilter {
+            target: Some(s!("docs/a.md")),
+            since: Some(at("2025-04-15T00:01:00.000Z")),
+            ..Default::default()
+        };
+        let about_doc = mock_db.query_audit(&about_doc, 10).await.unwrap();
+        assert_eq!(about_doc.len(), 1, "query_audit: should filter by target");
+        assert_eq!(about_doc[0].category, "other");
+        let since_last = AuditFilter {
+            since: Some(at("2025-04-15T00:02:00.000Z")),
+            ..Default::default()
+        };
+        assert_eq!(mock_db.query_audit(&since_last, 10).await.unwrap().len(), 1);
     }
 
     #[tokio::test]
-    async fn group_management() {
+    async fn permission_denies() {
         let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(s!("username"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        let editors = mock_db.create_group(s!("Editors")).await.unwrap();
+        mock_db
+            .add_group_permission(editors.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        mock_db
+            .add_group_permission(editors.id, Permission::ManageAssets)
+            .await
+            .unwrap();
+        mock_db
+            .add_group_membership(editors.id, user.id)
+            .await
+            .unwrap();
 
-        let user1 = mock_db
-            .create_user(
-                s!("username1"),
-                s!("token1"),
-                s!("exp1"),
-                s!("https://foo.bar"),
+        let deny = mock_db
+            .add_permission_deny(
+                Some(user.id),
+                None,
+                Permission::ManageContent,
+                None,
+                at("2025-01-01T00:00:00.000Z"),
             )
             .await
             .unwrap();
-        let group1 = mock_db.create_group(s!("groupname1")).await.unwrap();
         assert_eq!(
-            group1.name, "groupname1",
-            "create_group: The output group's name should be the same as provided"
+            mock_db.get_user_permissions(user.id).await.unwrap(),
+            vec![Permission::ManageAssets],
+            "get_user_permissions: denied permissions should be left out"
+        );
+        mock_db
+            .add_permission_deny(
+                None,
+                Some(editors.id),
+                Permission::ManageAssets,
+                Some(at("2000-01-01T00:00:00.000Z")),
+                at("2000-01-01T00:00:00.000Z"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_db.get_user_permissions(user.id).await.unwrap(),
+            vec![Permission::ManageAssets],
+            "get_user_permissions: expired deny rules should be ignored"
         );
+        mock_db
+            .add_permission_deny(
+                None,
+                Some(editors.id),
+                Permission::ManageAssets,
+                None,
+                at("2025-01-01T00:00:00.000Z"),
+            )
+            .await
+            .unwrap();
+        assert!(mock_db
+            .get_user_permissions(user.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(mock_db.get_permission_denies().await.unwrap().len(), 3);
 
-        let fetched_group1 = mock_db.get_group(group1.id).await.unwrap().unwrap();
+        assert!(mock_db.delete_permission_deny(deny).await.unwrap());
+        assert!(!mock_db.delete_permission_deny(deny).await.unwrap());
         assert_eq!(
-            fetched_group1, group1,
-            "get_group: The fetched group should be equal to the created group"
+            mock_db.get_user_permissions(user.id).await.unwrap(),
+            vec![Permission::ManageContent]
         );
+    }
 
-        let mut group2 = mock_db.create_group(s!("groupname2")).await.unwrap();
-        let all_groups = mock_db.get_all_groups().await.unwrap();
+    #[tokio::test]
+    async fn expiring_grants() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(s!("username"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        let moderators = mock_db.create_group(s!("Moderators")).await.unwrap();
+        let editors = mock_db.create_group(s!("Editors")).await.unwrap();
+        mock_db
+            .add_group_permission(moderators.id, Permission::ManageUsers)
+            .await
+            .unwrap();
+        mock_db
+            .add_group_permission(editors.id, Permission::ManageContent)
+            .await
+            .unwrap();
+        mock_db
+            .add_group_permission(editors.id, Permission::ManageAssets)
+            .await
+            .unwrap();
+        mock_db
+            .add_group_membership(moderators.id, user.id)
+            .await
+            .unwrap();
+        mock_db
+            .add_group_membership(editors.id, user.id)
+            .await
+            .unwrap();
+
+        mock_db
+            .set_group_membership_expiry(
+                moderators.id,
+                user.id,
+                Some(at("2000-01-01T00:00:00.000Z")),
+            )
+            .await
+            .unwrap();
+        mock_db
+            .set_group_permission_expiry(
+                editors.id,
+                Permission::ManageAssets,
+                Some(at("2000-01-01T00:00:00.000Z")),
+            )
+            .await
+            .unwrap();
         assert_eq!(
-            all_groups.len(),
-            3, // includes admin
-            "get_all_groups: should return the right number of groups"
+            mock_db.get_user_permissions(user.id).await.unwrap(),
+            vec![Permission::ManageContent],
+            "get_user_permissions: expired grants and memberships should be left out"
         );
         assert_eq!(
-            all_groups[1], group1,
-            "get_all_groups: should return the right groups in the right order"
+            mock_db.get_user_groups(user.id).await.unwrap(),
+            vec![editors]
         );
+
         assert_eq!(
-            all_groups[2], group2,
-            "get_all_groups: should return the right groups in the right order"
+            mock_db
+                .delete_expired_grants(at("2025-01-01T00:00:00.000Z"))
+                .await
+                .unwrap(),
+            2
         );
+        assert!(!mock_db
+            .group_has_member(moderators.id, user.id)
+            .await
+            .unwrap());
+    }
 
-        "groupname2_updated".clone_into(&mut group2.name);
-        mock_db.update_group(&group2).await.unwrap();
-        let fetched_group2 = mock_db.get_group(group2.id).await.unwrap().unwrap();
-        assert_eq!(fetched_group2, group2, "update_group: should work");
-        let all_groups2 = mock_db.get_all_groups().await.unwrap();
+    #[tokio::test]
+    async fn group_branch_rules() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let editors = mock_db.create_group(s!("Editors")).await.unwrap();
+        mock_db
+            .set_group_branch_rules(1, &[s!("master")])
+            .await
+            .unwrap();
+        mock_db
+            .set_group_branch_rules(editors.id, &[s!("hyde/*"), s!("drafts/*")])
+            .await
+            .unwrap();
         assert_eq!(
-            all_groups2.len(),
-            3, // includes admin group
-            "update_group: should not create or delete any groups"
+            mock_db.get_group_branch_rules(editors.id).await.unwrap(),
+            vec![s!("drafts/*"), s!("hyde/*")]
+        );
+        assert_eq!(mock_db.get_all_branch_rules().await.unwrap().len(), 3);
+        mock_db
+            .set_group_branch_rules(editors.id, &[s!("hyde/*")])
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_db.get_group_branch_rules(editors.id).await.unwrap(),
+            vec![s!("hyde/*")],
+            "set_group_branch_rules: should replace the previous patterns"
         );
+    }
 
-        let user2 = mock_db
+    #[tokio::test]
+    async fn totp_secrets() {
+        let mock_db = Database::from_url(":memory:")
+            .await
+            .unwrap()
+            .with_token_cipher(TokenCipher::new(&[1; token_crypto::KEY_LEN]).unwrap());
+        let user = mock_db
             .create_user(
-                s!("username2"),
-                s!("token2"),
-                s!("exp2"),
+                s!("alice"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
                 s!("https://foo.bar"),
             )
             .await
             .unwrap();
-        let address1 = mock_db
-            .add_group_membership(group1.id, user1.id)
-            .await
-            .unwrap();
-        let address2 = mock_db
-            .add_group_membership(group1.id, user1.id)
-            .await
-            .unwrap();
+        assert_eq!(mock_db.get_totp_secret(user.id).await.unwrap(), None);
         mock_db
-            .add_group_membership(group1.id, user2.id)
+            .set_totp_secret(user.id, "JBSWY3DPEHPK3PXP", at("2025-04-20T00:00:00.000Z"))
             .await
             .unwrap();
-        mock_db
-            .add_group_membership(group2.id, user1.id)
+        let stored: (String,) = sqlx::query_as("SELECT secret FROM totp_secrets;")
+            .fetch_one(mock_db.sqlite_pool().unwrap())
             .await
             .unwrap();
         assert!(
-            address1,
-            "add_group_membership: returns true if there is not already a membership"
-        );
-        assert!(
-            !address2,
-            "add_group_membership: returns false if the membership already exists"
+            token_crypto::is_encrypted(&stored.0),
+            "set_totp_secret: secrets should be encrypted"
         );
-
-        let user1_in_group1 = mock_db.group_has_member(group1.id, user1.id).await.unwrap();
-        let user2_in_group2 = mock_db.group_has_member(group2.id, user2.id).await.unwrap();
+        let totp = mock_db.get_totp_secret(user.id).await.unwrap().unwrap();
+        assert_eq!(totp.secret, "JBSWY3DPEHPK3PXP");
+        assert!(!totp.enabled);
+        mock_db.enable_totp(user.id).await.unwrap();
         assert!(
-            user1_in_group1,
-            "group_has_member: returns true if a group has a user"
+            mock_db
+                .get_totp_secret(user.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .enabled
         );
+        assert!(mock_db.use_totp_step(user.id, 10).await.unwrap());
         assert!(
-            !user2_in_group2,
-            "group_has_member: returns false if a group does not have a user"
+            !mock_db.use_totp_step(user.id, 10).await.unwrap(),
+            "use_totp_step: codes should only be accepted once"
         );
+        assert!(mock_db.use_totp_step(user.id, 11).await.unwrap());
+        assert!(mock_db.delete_totp_secret(user.id).await.unwrap());
+        assert_eq!(mock_db.get_totp_secret(user.id).await.unwrap(), None);
+    }
 
-        let group1_members = mock_db.get_group_members(group1.id).await.unwrap();
+    #[tokio::test]
+    async fn api_tokens() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(
+                s!("alice"),
+                s!("token"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        let token = mock_db
+            .create_api_token(
+                user.id,
+                "ci",
+                "abc123",
+                at("2025-03-25T00:00:00.000Z"),
+                None,
+            )
+            .await
+            .unwrap();
         assert_eq!(
-            group1_members.len(),
-            2,
-            "get_group_members: should return the right number of users"
+            mock_db.get_api_token_by_hash("abc123").await.unwrap(),
+            Some(token.clone())
         );
+        mock_db
+            .touch_api_token(token.id, at("2025-03-26T00:00:00.000Z"))
+            .await
+            .unwrap();
         assert_eq!(
-            group1_members[0], user1,
-            "get_group_members: should return the right users in the right order"
+            mock_db.get_user_api_tokens(user.id).await.unwrap()[0].last_used_at,
+            Some(at("2025-03-26T00:00:00.000Z"))
         );
+        assert!(mock_db.delete_api_token(token.id).await.unwrap());
+        assert_eq!(mock_db.get_api_token(token.id).await.unwrap(), None);
+        assert!(!mock_db.delete_api_token(token.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn token_encryption() {
+        let plain_db = Database::from_url(":memory:").await.unwrap();
+        let user = plain_db
+            .create_user(
+                s!("alice"),
+                s!("access"),
+                DateTime::UNIX_EPOCH,
+                s!("https://foo.bar"),
+            )
+            .await
+            .unwrap();
+        plain_db
+            .set_refresh_token(user.id, Some("refresh"))
+            .await
+            .unwrap();
+
+        let cipher = || TokenCipher::new(&[1; token_crypto::KEY_LEN]).unwrap();
+        let mock_db = plain_db.clone().with_token_cipher(cipher());
         assert_eq!(
-            group1_members[1], user2,
-            "get_group_members: should return the right users in the right order"
+            mock_db.encrypt_stored_tokens().await.unwrap(),
+            1,
+            "encrypt_stored_tokens: should encrypt tokens stored in plaintext"
         );
-        let user1_groups = mock_db.get_user_groups(user1.id).await.unwrap();
+        assert_eq!(mock_db.encrypt_stored_tokens().await.unwrap(), 0);
+        let stored = plain_db.get_user(user.id).await.unwrap().unwrap().token;
+        assert!(token_crypto::is_encrypted(&stored));
+        assert_eq!(cipher().decrypt(&stored).unwrap(), "access");
         assert_eq!(
-            user1_groups.len(),
-            2,
-            "get_user_groups: should return the right number of groups"
+            mock_db.get_access_token(user.id).await.unwrap().as_deref(),
+            Some("access"),
+            "get_access_token: should decrypt the token"
         );
         assert_eq!(
-            user1_groups[0], group1,
-            "get_user_groups: should return the right groups in the right order"
+            mock_db.get_refresh_token(user.id).await.unwrap().as_deref(),
+            Some("refresh")
         );
-        assert_eq!(
-            user1_groups[1], group2,
-            "get_user_groups: should return the right groups in the right order"
+        assert!(
+            plain_db.get_refresh_token(user.id).await.is_err(),
+            "get_refresh_token: encrypted tokens can't be read without the key"
         );
+    }
 
-        let remres1 = mock_db
-            .remove_group_membership(group1.id, user2.id)
+    #[tokio::test]
+    async fn drafts() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(s!("username"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
             .await
             .unwrap();
-        let remres2 = mock_db
-            .remove_group_membership(group2.id, user2.id)
+        let draft = |doc_path: &str, contents: &str, updated_at| Draft {
+            user_id: user.id,
+            doc_path: s!(doc_path),
+            contents: s!(contents),
+            base_commit: Some(s!("abc123")),
+            updated_at,
+        };
+        mock_db
+            .put_draft(&draft("a.md", "first", at("2025-06-01T00:00:00.000Z")))
+            .await
+            .unwrap();
+        mock_db
+            .put_draft(&draft("b.md", "other", at("2025-06-01T00:01:00.000Z")))
+            .await
+            .unwrap();
+        mock_db
+            .put_draft(&draft("a.md", "second", at("2025-06-01T00:02:00.000Z")))
             .await
             .unwrap();
-        assert!(
-            remres1,
-            "remove_group_membership: returns true if the membership can be removed"
-        );
-        assert!(
-            !remres2,
-            "remove_group_membership: returns false if the group membership does not exist"
-        );
-        let group1_members2 = mock_db.get_group_members(group1.id).await.unwrap();
         assert_eq!(
-            group1_members2.len(),
-            1,
-            "remove_group_membership: should work"
+            mock_db.get_draft(user.id, "a.md").await.unwrap(),
+            Some(draft("a.md", "second", at("2025-06-01T00:02:00.000Z"))),
+            "put_draft: should replace the previous draft of the document"
         );
+        let drafts = mock_db.get_user_drafts(user.id).await.unwrap();
         assert_eq!(
-            group1_members2[0], user1,
-            "remove_group_membership: removes the right user"
+            drafts
+                .iter()
+                .map(|d| d.doc_path.as_str())
+                .collect::<Vec<_>>(),
+            ["a.md", "b.md"],
+            "get_user_drafts: should return the most recently updated drafts first"
         );
-
-        mock_db.delete_group(group1.id).await.unwrap();
-        let all_groups3 = mock_db.get_all_groups().await.unwrap();
-        assert_eq!(
-            all_groups3.len(),
-            2, // includes admin group
-            "delete_group: should work"
+        assert!(mock_db.delete_draft(user.id, "a.md").await.unwrap());
+        assert!(!mock_db.delete_draft(user.id, "a.md").await.unwrap());
+        assert_eq!(mock_db.get_draft(user.id, "a.md").await.unwrap(), None);
+        mock_db.purge_user(user.id).await.unwrap();
+        assert!(
+            mock_db.get_user_drafts(user.id).await.unwrap().is_empty(),
+            "drafts: should be deleted along with their user"
         );
+    }
+
+    #[tokio::test]
+    async fn doc_meta() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let alice = mock_db
+            .create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        let bob = mock_db
+            .create_user(s!("bob"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
+            .await
+            .unwrap();
+        mock_db
+            .record_doc_edit(
+                "a.md",
+                &alice,
+                at("2025-06-10T00:00:00.000Z"),
+                Some("About A"),
+            )
+            .await
+            .unwrap();
+        mock_db
+            .record_doc_edit("a.md", &bob, at("2025-06-10T01:00:00.000Z"), None)
+            .await
+            .unwrap();
         assert_eq!(
-            all_groups3[1], group2,
-            "delete_group: should delete the right group"
+            mock_db.get_all_doc_meta().await.unwrap(),
+            [DocMeta {
+                path: s!("a.md"),
+                last_editor_id: Some(bob.id),
+                last_editor_name: s!("bob"),
+                last_edited_at: at("2025-06-10T01:00:00.000Z"),
+                edit_count: 2,
+                description: Some(s!("About A")),
+            }],
+            "record_doc_edit: should count edits and keep the description unless it's replaced"
         );
-        let fetched_group_fail = mock_db.get_group(group1.id).await.unwrap();
+        mock_db.move_doc_meta("a.md", "archive/a.md").await.unwrap();
+        mock_db.purge_user(bob.id).await.unwrap();
+        let meta = mock_db.get_all_doc_meta().await.unwrap();
+        assert_eq!(meta[0].path, "archive/a.md");
         assert_eq!(
-            fetched_group_fail, None,
-            "get_group: does not get a deleted group"
+            meta[0].last_editor_id, None,
+            "doc_meta: deleting the editor should keep their name"
         );
-        let group1_members3 = mock_db.get_group_members(group1.id).await.unwrap();
+        mock_db.delete_doc_meta("archive/a.md").await.unwrap();
+        assert!(mock_db.get_all_doc_meta().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn doc_search() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        mock_db
+            .replace_search_index(&[
+                (
+                    s!("gpu.md"),
+                    s!("GPU drivers"),
+                    s!("Reinstall drivers with DDU."),
+                ),
+                (
+                    s!("ram.md"),
+                    s!("Memory"),
+                    s!("Run memtest86 to check for faulty drivers."),
+                ),
+            ])
+            .await
+            .unwrap();
+        let hits = mock_db.search_docs(&[s!("drivers")], 10).await.unwrap();
         assert_eq!(
-            group1_members3.len(),
-            0,
-            "delete_group: deletes all associated memberships along with the group"
+            hits.iter().map(|h| h.path.as_str()).collect::<Vec<_>>(),
+            ["gpu.md", "ram.md"],
+            "search_docs: matches in the title should rank higher"
         );
-        mock_db.delete_user(user1.id).await.unwrap();
-        let group2_members = mock_db.get_group_members(group2.id).await.unwrap();
+        assert_eq!(hits[0].snippet, "Reinstall \u{2}drivers\u{3} with DDU.");
         assert_eq!(
-            group2_members.len(),
-            0,
-            "delete_user: deletes all associated group memberships along with the user"
+            mock_db.search_docs(&[s!("mem")], 10).await.unwrap()[0].path,
+            "ram.md",
+            "search_docs: the last term should match as a prefix"
         );
+
+        mock_db
+            .index_doc("gpu.md", "GPU", "Use the manufacturer's installer.")
+            .await
+            .unwrap();
+        mock_db.remove_indexed_doc("ram.md").await.unwrap();
+        assert!(mock_db
+            .search_docs(&[s!("drivers")], 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(mock_db.search_docs(&[], 10).await.unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn permissions_management() {
+    async fn user_stats() {
         let mock_db = Database::from_url(":memory:").await.unwrap();
-        let group1 = mock_db.create_group(s!("groupname1")).await.unwrap();
-
-        let permissions1 = mock_db.get_group_permissions(group1.id).await.unwrap();
-        assert_eq!(
-            permissions1.len(),
-            0,
-            "get_group_permissions: returns 0 when no permissions are added"
-        );
-        let has_manage_content1 = mock_db
-            .group_has_permission(group1.id, Permission::ManageContent)
+        let alice = mock_db
+            .create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
             .await
             .unwrap();
-        assert!(
-            !has_manage_content1,
-            "group_has_permission: should return false if the group does not have the permission"
+        for (created_at, action, target) in [
+            (at("2025-06-01T00:00:00.000Z"), "doc_saved", "a.md"),
+            (at("2025-06-02T00:00:00.000Z"), "doc_saved", "a.md"),
+            (at("2025-06-03T00:00:00.000Z"), "doc_saved", "b.md"),
+            (at("2025-06-04T00:00:00.000Z"), "asset_saved", "a.png"),
+        ] {
+            mock_db
+                .record_audit(&AuditEntry {
+                    id: 0,
+                    created_at: s!(created_at),
+                    category: s!("content"),
+                    action: s!(action),
+                    user_id: Some(alice.id),
+                    username: Some(s!("alice")),
+                    ip_address: None,
+                    detail: String::new(),
+                    target: Some(s!(target)),
+                    data: None,
+                })
+                .await
+                .unwrap();
+        }
+        mock_db
+            .record_hyde_pr(&HydePr {
+                number: 1,
+                title: s!("Fix a typo"),
+                author_id: Some(alice.id),
+                branch: Some(s!("alice/fix-typo")),
+                status: s!("open"),
+                doc_paths: vec![s!("a.md")],
+                created_at: at("2025-06-05T00:00:00.000Z"),
+                merged_at: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_db.get_user_stats(alice.id).await.unwrap(),
+            Some(UserStats {
+                doc_edits: 3,
+                docs_edited: 2,
+                assets_uploaded: 1,
+                prs_opened: 1,
+                last_active_at: Some(at("2025-06-04T00:00:00.000Z")),
+            })
         );
+        assert_eq!(mock_db.get_user_stats(alice.id + 1).await.unwrap(), None);
+    }
 
-        let permission_added = mock_db
-            .add_group_permission(group1.id, Permission::ManageContent)
+    #[tokio::test]
+    async fn user_preferences() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
             .await
             .unwrap();
-        let has_manage_content2 = mock_db
-            .group_has_permission(group1.id, Permission::ManageContent)
+        mock_db
+            .set_user_preferences(
+                user.id,
+                &[
+                    (s!("theme"), Some(s!("\"dark\""))),
+                    (s!("editor_mode"), Some(s!("\"split\""))),
+                ],
+            )
             .await
             .unwrap();
-        assert!(
-            permission_added,
-            "add_group_permission: returns true when the permission has been added"
-        );
-        assert!(has_manage_content2, "add_group_permission: works, group_has_permission: should return true if the group does have the permission");
-        let permissions2 = mock_db.get_group_permissions(group1.id).await.unwrap();
-        assert_eq!(
-            permissions2,
-            vec![Permission::ManageContent],
-            "get_group_permissions: returns the right thing"
-        );
-        let already_added = mock_db
-            .add_group_permission(group1.id, Permission::ManageContent)
+        mock_db
+            .set_user_preferences(
+                user.id,
+                &[
+                    (s!("theme"), Some(s!("\"light\""))),
+                    (s!("editor_mode"), None),
+                ],
+            )
             .await
             .unwrap();
-        assert!(
-            !already_added,
-            "add_group_permission: should return false if group already has the permission"
+        assert_eq!(
+            mock_db.get_user_preferences(user.id).await.unwrap(),
+            [(s!("theme"), s!("\"light\""))],
+            "set_user_preferences: should replace existing values and remove unset ones"
         );
+        mock_db.purge_user(user.id).await.unwrap();
+        assert!(mock_db
+            .get_user_preferences(user.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
 
-        let permission_removed = mock_db
-            .remove_group_permission(group1.id, Permission::ManageContent)
+    #[tokio::test]
+    async fn batch_group_updates() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(s!("alice"), s!("token"), DateTime::UNIX_EPOCH, s!("url"))
             .await
             .unwrap();
-        assert!(
-            permission_removed,
-            "remove_group_permission: returns true when permission has been removed"
+        let editors = mock_db.create_group(s!("Editors")).await.unwrap();
+        let reviewers = mock_db.create_group(s!("Reviewers")).await.unwrap();
+
+        assert_eq!(
+            mock_db
+                .add_group_memberships(user.id, &[editors.id], None)
+                .await
+                .unwrap(),
+            [editors.id]
+        );
+        assert_eq!(
+            mock_db
+                .add_group_memberships(user.id, &[editors.id, reviewers.id], None)
+                .await
+                .unwrap(),
+            [reviewers.id],
+            "add_group_memberships: should only return new memberships"
+        );
+        assert_eq!(
+            mock_db
+                .remove_group_memberships(user.id, &[reviewers.id, reviewers.id + 100])
+                .await
+                .unwrap(),
+            [reviewers.id]
         );
-        let already_removed = mock_db
-            .remove_group_permission(group1.id, Permission::ManageContent)
-            .await
-            .unwrap();
         assert!(
-            !already_removed,
-            "remove_group_permission: returns false when the group didn't have the permission"
+            mock_db
+                .add_group_memberships(user.id, &[reviewers.id, reviewers.id + 100], None)
+                .await
+                .is_err(),
+            "add_group_memberships: a group that doesn't exist should fail the batch"
         );
-        let has_manage_content3 = mock_db
-            .group_has_permission(group1.id, Permission::ManageContent)
-            .await
-            .unwrap();
-        assert!(!has_manage_content3, "remove_group_permission: works");
-        let permissions3 = mock_db.get_group_permissions(group1.id).await.unwrap();
         assert_eq!(
-            permissions3.len(),
-            0,
-            "get_group_permissions: returns 0 when the permission has been removed"
+            mock_db
+                .get_user_groups(user.id)
+                .await
+                .unwrap()
+                .iter()
+                .map(|g| g.id)
+                .collect::<Vec<_>>(),
+            [editors.id],
+            "add_group_memberships: a failed batch shouldn't add anything"
         );
 
-        let admin_permissions = mock_db.get_group_permissions(1).await.unwrap();
+        mock_db
+            .update_group_permissions(
+                editors.id,
+                &[],
+                &[Permission::ManageContent, Permission::ManageAssets],
+                None,
+            )
+            .await
+            .unwrap();
+        mock_db
+            .update_group_permissions(
+                editors.id,
+                &[Permission::ManageAssets],
+                &[Permission::ManageContent, Permission::ReadContent],
+                None,
+            )
+            .await
+            .unwrap();
+        let mut permissions = mock_db.get_group_permissions(editors.id).await.unwrap();
+        permissions.sort_by_key(|p| String::from(p.clone()));
         assert_eq!(
-            admin_permissions,
-            vec![
-                Permission::ManageContent,
-                Permission::ManageUsers,
-                Permission::ManageBranches
-            ],
-            "admin group should have the right permissions"
+            permissions,
+            [Permission::ManageContent, Permission::ReadContent],
+            "update_group_permissions: shouldn't duplicate permissions the group already has"
         );
     }
 }
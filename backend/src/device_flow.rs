@@ -0,0 +1,77 @@
+//! The OAuth 2.0 device authorization grant ([RFC 8628](https://www.rfc-editor.org/rfc/rfc8628)),
+//! for clients like CLI tools that run where a browser can't be redirected back to them.
+//!
+//! The client asks for a device code and a short user code, then polls with the device code
+//! while the user enters the user code in their browser and approves the request. Once
+//! approved, the client is handed an API token (see [`crate::api_tokens`]) acting as the user.
+
+use rand::{seq::SliceRandom, Rng};
+
+/// The `grant_type` clients poll with
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+/// How long the user has to approve a request, in seconds
+pub const DEVICE_CODE_LIFETIME_SECS: i64 = 600;
+/// How often clients may poll, in seconds
+pub const POLL_INTERVAL_SECS: i64 = 5;
+/// How many days the API token handed to the client is valid for
+pub const DEVICE_TOKEN_LIFETIME_DAYS: u32 = 90;
+/// Consonants only, so user codes can't spell words, and none that are easily confused
+const USER_CODE_CHARSET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LENGTH: usize = 8;
+/// How many random characters a device code is
+const DEVICE_CODE_LENGTH: usize = 40;
+
+/// A new random device code
+pub fn generate_device_code() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(DEVICE_CODE_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// A new random user code, as it's stored (see [`format_user_code`])
+pub fn generate_user_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..USER_CODE_LENGTH)
+        .map(|_| char::from(*USER_CODE_CHARSET.choose(&mut rng).unwrap()))
+        .collect()
+}
+
+/// A stored user code as it's shown to the user, e.g. `WDJB-MJHT`
+pub fn format_user_code(user_code: &str) -> String {
+    let (first, second) = user_code.split_at(user_code.len() / 2);
+    format!("{first}-{second}")
+}
+
+/// A user code as it's stored, from however the user typed it in
+pub fn normalize_user_code(input: &str) -> String {
+    input
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_codes() {
+        let user_code = generate_user_code();
+        assert_eq!(user_code.len(), USER_CODE_LENGTH);
+        assert!(user_code.bytes().all(|c| USER_CODE_CHARSET.contains(&c)));
+        assert_eq!(format_user_code("WDJBMJHT"), "WDJB-MJHT");
+        assert_eq!(
+            normalize_user_code(" wdjb-mjht "),
+            "WDJBMJHT",
+            "normalize_user_code: should ignore case and formatting"
+        );
+        assert_eq!(
+            normalize_user_code(&format_user_code(&user_code)),
+            user_code
+        );
+        assert_ne!(generate_device_code(), generate_device_code());
+    }
+}
@@ -0,0 +1,179 @@
+//! Keeping group memberships in sync with roles in a Discord server. Each time a user signs in
+//! with Discord, they're added to the groups their roles map to, and removed from the mapped
+//! groups their roles don't. Groups that no role maps to are left alone, so they can still be
+//! managed by hand.
+
+use crate::app_conf::Discord;
+use crate::audit::{self, AuditCategory};
+use crate::db::{Group, User};
+use crate::AppState;
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use tracing::warn;
+
+/// Needed to read the user's roles in the configured server
+pub const GUILD_MEMBERS_SCOPE: &str = "guilds.members.read";
+
+/// https://discord.com/developers/docs/resources/guild#guild-member-object
+#[derive(Debug, Deserialize)]
+struct GuildMember {
+    roles: Vec<String>,
+}
+
+/// The groups to add a user to and remove them from, by name
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GroupChanges {
+    pub add: BTreeSet<String>,
+    pub remove: BTreeSet<String>,
+}
+
+/// Work out how a user's groups change, given their roles and the groups they're in
+pub fn group_changes(config: &Discord, roles: &[String], current_groups: &[Group]) -> GroupChanges {
+    let wanted: BTreeSet<&String> = config
+        .role_groups
+        .iter()
+        .filter(|(role, _)| roles.contains(role))
+        .map(|(_, group)| group)
+        .collect();
+    let mut changes = GroupChanges::default();
+    for group in config.role_groups.values() {
+        let is_member = current_groups.iter().any(|g| g.name == *group);
+        if wanted.contains(group) && !is_member {
+            changes.add.insert(group.clone());
+        } else if !wanted.contains(group) && is_member {
+            changes.remove.insert(group.clone());
+        }
+    }
+    changes
+}
+
+/// The user's roles in the configured server, empty if they aren't a member of it
+async fn fetch_roles(state: &AppState, guild_id: &str, token: &str) -> Result<Vec<String>> {
+    let response = state
+        .reqwest_client
+        .get(format!(
+            "https://discord.com/api/v10/users/@me/guilds/{guild_id}/member"
+        ))
+        .bearer_auth(token)
+        .header(
+            "User-Agent",
+            "DiscordBot (https://github.com/r-Techsupport/hyde, 0)",
+        )
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(Vec::new());
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let response_text = response.text().await?;
+        bail!(
+            "Failed to fetch the user's guild roles: {}, Response: {}",
+            status,
+            response_text
+        );
+    }
+    let member: GuildMember = response.json().await?;
+    Ok(member.roles)
+}
+
+/// Sync a user's groups with their roles, using the Discord token they just signed in with. If
+/// the roles can't be fetched, their groups are left as they are.
+pub async fn sync_groups(state: &AppState, user: &User, token: &str) -> Result<()> {
+    let config = &state.config.discord;
+    let Some(guild_id) = config.guild_id.as_deref() else {
+        return Ok(());
+    };
+    if config.role_groups.is_empty() {
+        return Ok(());
+    }
+    let roles = match fetch_roles(state, guild_id, token).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            warn!(
+                "Failed to fetch the Discord roles of {:?}, leaving their groups alone: {e:?}",
+                user.username
+            );
+            return Ok(());
+        }
+    };
+    let current_groups = state.db.get_user_groups(user.id).await?;
+    let changes = group_changes(config, &roles, &current_groups);
+    let all_groups = state.db.get_all_groups().await?;
+    let find_group = |name: &str| {
+        let group = all_groups.iter().find(|g| g.name == name);
+        if group.is_none() {
+            warn!("Discord roles are mapped to the group {name:?}, but it doesn't exist");
+        }
+        group
+    };
+    for group in changes.add.iter().filter_map(|name| find_group(name)) {
+        state.db.add_group_membership(group.id, user.id).await?;
+        audit::record(
+            &state.db,
+            AuditCategory::Perms,
+            "member_added",
+            Some(user),
+            None,
+            format!("Added to {:?} based on their Discord roles", group.name),
+        )
+        .await;
+    }
+    for group in changes.remove.iter().filter_map(|name| find_group(name)) {
+        state.db.remove_group_membership(group.id, user.id).await?;
+        audit::record(
+            &state.db,
+            AuditCategory::Perms,
+            "member_removed",
+            Some(user),
+            None,
+            format!("Removed from {:?} based on their Discord roles", group.name),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn changes() {
+        let config = Discord {
+            guild_id: Some("1".to_string()),
+            role_groups: BTreeMap::from([
+                ("10".to_string(), "Editors".to_string()),
+                ("11".to_string(), "Editors".to_string()),
+                ("20".to_string(), "Moderators".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let group = |id: i64, name: &str| Group {
+            id,
+            name: name.to_string(),
+        };
+        let changes = group_changes(
+            &config,
+            &["11".to_string(), "99".to_string()],
+            &[group(1, "Moderators"), group(2, "Admin")],
+        );
+        assert_eq!(
+            changes,
+            GroupChanges {
+                add: BTreeSet::from(["Editors".to_string()]),
+                remove: BTreeSet::from(["Moderators".to_string()]),
+            },
+            "group_changes: groups without a mapped role should be left alone"
+        );
+        assert_eq!(
+            group_changes(&config, &["10".to_string()], &[group(3, "Editors")]),
+            GroupChanges::default(),
+            "group_changes: should do nothing when the groups are in sync"
+        );
+    }
+}
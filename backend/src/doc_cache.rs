@@ -0,0 +1,331 @@
+//! In-memory caches of data derived from the documents folder, and their invalidation.
+//!
+//! Every change to the repository (a webhook pull, a save through Hyde, a branch switch) must be
+//! reported through [`DocCache::invalidate`] or [`DocCache::clear`], which fans out to each cache
+//! so that only entries for the changed paths are dropped.
+
+use crate::git::{ChangeKind, ChangedPath, INode};
+use crate::related::{term_counts, TermCounts};
+use color_eyre::Result;
+use git2::{ObjectType, Oid};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// How many bytes of rendered HTML are kept, after which the least recently used pages are
+/// dropped
+const RENDERED_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+struct Caches {
+    /// The document tree, indexed by whether archived documents are included
+    doc_tree: [Option<INode>; 2],
+    /// Term counts used for related page suggestions, by document path
+    term_counts: HashMap<String, TermCounts>,
+    rendered: RenderedCache,
+}
+
+/// Documents rendered to HTML by document path, see [`DocCache::rendered`]
+#[derive(Debug, Default)]
+struct RenderedCache {
+    entries: HashMap<String, RenderedEntry>,
+    /// The combined length of every entry's HTML
+    size: usize,
+    /// Incremented on every use, so the entry with the lowest `last_used` is the least
+    /// recently used one
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct RenderedEntry {
+    /// The OID of the blob the HTML was rendered from
+    blob: Oid,
+    /// See [`crate::render::config_hash`]
+    config_hash: u64,
+    html: String,
+    last_used: u64,
+}
+
+impl RenderedCache {
+    fn get(&mut self, path: &str, blob: Oid, config_hash: u64) -> Option<String> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(path)?;
+        if entry.blob != blob || entry.config_hash != config_hash {
+            return None;
+        }
+        entry.last_used = self.clock;
+        Some(entry.html.clone())
+    }
+
+    fn insert(&mut self, path: &str, blob: Oid, config_hash: u64, html: String) {
+        self.remove(path);
+        if html.len() > RENDERED_CACHE_BYTES {
+            return;
+        }
+        while self.size + html.len() > RENDERED_CACHE_BYTES {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+        self.clock += 1;
+        self.size += html.len();
+        self.entries.insert(
+            path.to_string(),
+            RenderedEntry {
+                blob,
+                config_hash,
+                html,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    fn remove(&mut self, path: &str) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.size -= entry.html.len();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DocCache {
+    /// The documents folder relative to the root of the repository, without a trailing slash
+    doc_path: String,
+    caches: Arc<Mutex<Caches>>,
+}
+
+impl DocCache {
+    /// `doc_path` is the location of the documents folder relative to the root of the repository.
+    pub fn new(doc_path: &str) -> Self {
+        Self {
+            doc_path: doc_path.trim_matches('/').to_string(),
+            caches: Arc::default(),
+        }
+    }
+
+    /// Returns the cached document tree, calling `load` to read it if it isn't cached.
+    ///
+    /// # Errors
+    /// This function returns an error if `load` fails.
+    pub fn doc_tree<F: FnOnce() -> Result<INode>>(
+        &self,
+        include_archived: bool,
+        load: F,
+    ) -> Result<INode> {
+        let slot = usize::from(include_archived);
+        if let Some(tree) = &self.caches.lock().unwrap().doc_tree[slot] {
+            return Ok(tree.clone());
+        }
+        let tree = load()?;
+        self.caches.lock().unwrap().doc_tree[slot] = Some(tree.clone());
+        Ok(tree)
+    }
+
+    /// Returns the cached term counts for the document at `path` (relative to the documents
+    /// folder), calling `load` to read the document if they aren't cached. Returns `None`
+    /// if the document doesn't exist.
+    ///
+    /// # Errors
+    /// This function returns an error if `load` fails.
+    pub fn term_counts<F: FnOnce() -> Result<Option<String>>>(
+        &self,
+        path: &str,
+        load: F,
+    ) -> Result<Option<TermCounts>> {
+        if let Some(counts) = self.caches.lock().unwrap().term_counts.get(path) {
+            return Ok(Some(counts.clone()));
+        }
+        let Some(contents) = load()? else {
+            return Ok(None);
+        };
+        let counts = term_counts(&contents);
+        self.caches
+            .lock()
+            .unwrap()
+            .term_counts
+            .insert(path.to_string(), counts.clone());
+        Ok(Some(counts))
+    }
+
+    /// Returns the HTML the document at `path` (relative to the documents folder) was rendered
+    /// to, if it was rendered from the same `contents` (compared by blob OID) with the same
+    /// `config_hash` (see [`crate::render::config_hash`]). Otherwise, `render` is called and its
+    /// output cached, dropping the least recently used pages if the cache is full.
+    pub fn rendered<F: FnOnce(&str) -> String>(
+        &self,
+        path: &str,
+        contents: &str,
+        config_hash: u64,
+        render: F,
+    ) -> Result<String> {
+        let blob = Oid::hash_object(ObjectType::Blob, contents.as_bytes())?;
+        let cached = self
+            .caches
+            .lock()
+            .unwrap()
+            .rendered
+            .get(path, blob, config_hash);
+        if let Some(html) = cached {
+            return Ok(html);
+        }
+        let html = render(contents);
+        self.caches
+            .lock()
+            .unwrap()
+            .rendered
+            .insert(path, blob, config_hash, html.clone());
+        Ok(html)
+    }
+
+    /// Drop everything cached for the changed files. Paths outside of the documents
+    /// folder are ignored.
+    pub fn invalidate(&self, changes: &[ChangedPath]) {
+        let prefix = format!("{}/", self.doc_path);
+        let mut caches = self.caches.lock().unwrap();
+        for change in changes {
+            let Some(path) = change.path.strip_prefix(&prefix) else {
+                continue;
+            };
+            debug!("Invalidating cached data for {path:?} ({:?})", change.kind);
+            caches.term_counts.remove(path);
+            caches.rendered.remove(path);
+            // Modifying a file doesn't change the shape of the tree
+            if change.kind != ChangeKind::Modified {
+                caches.doc_tree = [None, None];
+            }
+        }
+    }
+
+    /// Invalidate a single document, where `path` is relative to the documents folder.
+    pub fn invalidate_doc(&self, path: &str, kind: ChangeKind) {
+        self.invalidate(&[ChangedPath {
+            path: format!("{}/{}", self.doc_path, path.trim_start_matches('/')),
+            kind,
+        }]);
+    }
+
+    /// Drop everything, for when the whole working tree may have changed (switching branches,
+    /// re-cloning, et cetera).
+    pub fn clear(&self) {
+        debug!("Clearing all cached document data");
+        *self.caches.lock().unwrap() = Caches::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalidation() {
+        let cache = DocCache::new("docs/");
+        let load_doc = || Ok(Some("Graphics drivers".to_string()));
+        cache.term_counts("a.md", load_doc).unwrap();
+        cache.term_counts("b.md", load_doc).unwrap();
+        cache
+            .doc_tree(false, || {
+                Ok(serde_json::from_str(r#"{"name":"docs","children":[]}"#)?)
+            })
+            .unwrap();
+
+        let unreachable = || -> Result<Option<String>> { panic!("should be cached") };
+        cache.term_counts("a.md", unreachable).unwrap();
+
+        cache.invalidate(&[
+            ChangedPath {
+                path: "docs/a.md".to_string(),
+                kind: ChangeKind::Modified,
+            },
+            ChangedPath {
+                path: "assets/b.md".to_string(),
+                kind: ChangeKind::Deleted,
+            },
+        ]);
+        assert!(
+            cache.term_counts("b.md", unreachable).is_ok(),
+            "invalidate: should ignore changes outside of the documents folder"
+        );
+        assert!(
+            cache.doc_tree(false, || panic!("should be cached")).is_ok(),
+            "invalidate: modifying a document shouldn't invalidate the tree"
+        );
+        assert_eq!(
+            cache.term_counts("a.md", || Ok(None)).unwrap(),
+            None,
+            "invalidate: should drop the changed document"
+        );
+
+        cache.invalidate_doc("c.md", ChangeKind::Added);
+        let mut reloaded = false;
+        cache
+            .doc_tree(false, || {
+                reloaded = true;
+                Ok(serde_json::from_str(r#"{"name":"docs","children":[]}"#)?)
+            })
+            .unwrap();
+        assert!(
+            reloaded,
+            "invalidate: adding a document should invalidate the tree"
+        );
+    }
+
+    #[test]
+    fn rendered() {
+        let cache = DocCache::new("docs");
+        let render = |contents: &str| format!("<p>{contents}</p>");
+        let unreachable = |_: &str| -> String { panic!("should be cached") };
+        assert_eq!(cache.rendered("a.md", "A", 1, render).unwrap(), "<p>A</p>");
+        assert_eq!(
+            cache.rendered("a.md", "A", 1, unreachable).unwrap(),
+            "<p>A</p>"
+        );
+        assert_eq!(
+            cache.rendered("a.md", "B", 1, render).unwrap(),
+            "<p>B</p>",
+            "rendered: a changed document should be rendered again"
+        );
+        assert_eq!(
+            cache.rendered("a.md", "B", 2, render).unwrap(),
+            "<p>B</p>",
+            "rendered: a changed renderer config should render documents again"
+        );
+        cache.invalidate_doc("a.md", ChangeKind::Modified);
+        let mut rerendered = false;
+        cache
+            .rendered("a.md", "B", 2, |contents| {
+                rerendered = true;
+                render(contents)
+            })
+            .unwrap();
+        assert!(rerendered, "invalidate: should drop the rendered document");
+    }
+
+    #[test]
+    fn rendered_eviction() {
+        let mut cache = RenderedCache::default();
+        let blob = Oid::zero();
+        let half = "a".repeat(RENDERED_CACHE_BYTES / 2);
+        cache.insert("a.md", blob, 0, half.clone());
+        cache.insert("b.md", blob, 0, half.clone());
+        // Using `a.md` makes `b.md` the least recently used page
+        assert!(cache.get("a.md", blob, 0).is_some());
+        cache.insert("c.md", blob, 0, half);
+        assert!(cache.get("a.md", blob, 0).is_some());
+        assert!(
+            cache.get("b.md", blob, 0).is_none(),
+            "insert: should drop the least recently used page once full"
+        );
+        assert_eq!(cache.size, RENDERED_CACHE_BYTES);
+        cache.insert("d.md", blob, 0, "a".repeat(RENDERED_CACHE_BYTES + 1));
+        assert!(
+            cache.get("d.md", blob, 0).is_none(),
+            "insert: pages bigger than the cache shouldn't be cached"
+        );
+    }
+}
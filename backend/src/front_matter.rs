@@ -0,0 +1,188 @@
+//! Parsing the Jekyll front matter of documents, the YAML header between two `---` lines that
+//! holds the page's metadata (title, layout, nav order, et cetera).
+//!
+//! Jekyll fails the whole build when a page's front matter isn't valid YAML, so saves are checked
+//! with [`crate::policy::check_doc`] before they're committed.
+
+use serde_json::{Map, Number, Value};
+use std::fmt::{self, Display};
+use yaml_rust2::{parser::Parser, scanner::Marker, Event, ScanError, Yaml, YamlLoader};
+
+/// The keys and values of a document's front matter
+pub type FrontMatter = Map<String, Value>;
+
+/// Why a document's front matter couldn't be parsed
+#[derive(Debug)]
+pub enum FrontMatterError {
+    /// The opening `---` has no closing `---` (or `...`)
+    Unterminated,
+    InvalidYaml(ScanError),
+    /// The front matter uses an alias (`*name`), at the given position
+    Alias(Marker),
+    /// The front matter is valid YAML, but not a mapping of keys to values
+    NotMapping,
+}
+
+impl Display for FrontMatterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unterminated => write!(f, "Front matter is never closed with `---`"),
+            Self::InvalidYaml(e) => write!(f, "Front matter isn't valid YAML: {e}"),
+            Self::Alias(_) => write!(f, "Front matter can't use YAML aliases (`*name`)"),
+            Self::NotMapping => write!(f, "Front matter must be a list of `key: value` pairs"),
+        }
+    }
+}
+
+impl std::error::Error for FrontMatterError {}
+
+/// Parse the front matter of `contents`, returning `None` if the document doesn't have any.
+/// An empty header (`---` directly followed by `---`) is parsed as an empty map.
+pub fn parse(contents: &str) -> Result<Option<FrontMatter>, FrontMatterError> {
+    let Some(yaml) = header(contents)? else {
+        return Ok(None);
+    };
+    reject_aliases(yaml)?;
+    let documents = YamlLoader::load_from_str(yaml).map_err(FrontMatterError::InvalidYaml)?;
+    match documents.into_iter().next().map_or(Value::Null, to_json) {
+        Value::Object(map) => Ok(Some(map)),
+        Value::Null => Ok(Some(Map::new())),
+        _ => Err(FrontMatterError::NotMapping),
+    }
+}
+
+/// The part of `contents` after its front matter, or all of it if it doesn't have any (or it's
+/// never closed)
+pub fn body(contents: &str) -> &str {
+    match header(contents) {
+        Ok(Some(header)) => contents[header.len()..]
+            .split_once('\n')
+            .map_or("", |(_, body)| body),
+        _ => contents,
+    }
+}
+
+/// The loader resolves aliases by copying the node they point to, so a few hundred bytes of
+/// nested aliases would expand to gigabytes. Jekyll pages don't need them, so they're refused
+/// before anything gets loaded.
+fn reject_aliases(yaml: &str) -> Result<(), FrontMatterError> {
+    let mut parser = Parser::new_from_str(yaml);
+    loop {
+        match parser.next_token().map_err(FrontMatterError::InvalidYaml)? {
+            (Event::StreamEnd, _) => return Ok(()),
+            (Event::Alias(_), marker) => return Err(FrontMatterError::Alias(marker)),
+            _ => {}
+        }
+    }
+}
+
+/// Convert a YAML value to JSON. Keys that aren't strings (`1: a`, `true: b`) are converted to
+/// strings, since Jekyll accepts them and JSON objects can't have any other keys.
+fn to_json(yaml: Yaml) -> Value {
+    match yaml {
+        Yaml::Real(real) => real
+            .parse()
+            .ok()
+            .and_then(Number::from_f64)
+            .map_or(Value::String(real), Value::Number),
+        Yaml::Integer(integer) => integer.into(),
+        Yaml::String(string) => Value::String(string),
+        Yaml::Boolean(boolean) => Value::Bool(boolean),
+        Yaml::Array(array) => array.into_iter().map(to_json).collect(),
+        Yaml::Hash(hash) => Value::Object(
+            hash.into_iter()
+                .map(|(key, value)| {
+                    let key = match to_json(key) {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    (key, to_json(value))
+                })
+                .collect(),
+        ),
+        // Aliases are rejected before loading
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Value::Null,
+    }
+}
+
+/// Get the front matter of `contents`, opening `---` included so that line numbers in YAML
+/// errors line up with the document's.
+fn header(contents: &str) -> Result<Option<&str>, FrontMatterError> {
+    let mut lines = contents.split_inclusive('\n');
+    let Some(opening) = lines.next().filter(|line| line.trim_end() == "---") else {
+        return Ok(None);
+    };
+    let mut end = opening.len();
+    for line in lines {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Ok(Some(&contents[..end]));
+        }
+        end += line.len();
+    }
+    Err(FrontMatterError::Unterminated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parsing() {
+        let front_matter = parse("---\ntitle: Foo\nnav_order: 2\ntags: [a, b]\n---\n# Foo\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Value::Object(front_matter),
+            json!({"title": "Foo", "nav_order": 2, "tags": ["a", "b"]})
+        );
+        assert_eq!(parse("---\r\n---\r\nBody").unwrap(), Some(Map::new()));
+        assert_eq!(
+            Value::Object(
+                parse("---\n1: a\ntrue: b\nratio: 0.5\n---\n")
+                    .unwrap()
+                    .unwrap()
+            ),
+            json!({"1": "a", "true": "b", "ratio": 0.5}),
+            "parse: keys that aren't strings should be converted to strings"
+        );
+        assert_eq!(
+            parse("# Foo\n---\ntitle: Foo\n---\n").unwrap(),
+            None,
+            "parse: front matter should only be read from the start of the document"
+        );
+        assert_eq!(body("---\r\ntitle: Foo\r\n---\r\n# Foo\r\n"), "# Foo\r\n");
+        assert_eq!(body("# Foo\n---\n"), "# Foo\n---\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse("---\ntitle: Foo\n# Foo\n"),
+            Err(FrontMatterError::Unterminated)
+        ));
+        assert!(matches!(
+            parse("---\n- a\n- b\n---\n"),
+            Err(FrontMatterError::NotMapping)
+        ));
+        let Err(FrontMatterError::InvalidYaml(e)) = parse("---\ntitle: Foo\n  bad: [\n---\n")
+        else {
+            panic!("parse: should reject invalid YAML");
+        };
+        assert_eq!(
+            e.marker().line(),
+            3,
+            "parse: error lines should be counted from the start of the document"
+        );
+        let mut bomb = String::from("---\na: &a [lol, lol, lol, lol, lol, lol, lol, lol, lol]\n");
+        for (name, previous) in ('b'..='i').zip('a'..) {
+            let aliases = vec![format!("*{previous}"); 9].join(", ");
+            bomb.push_str(&format!("{name}: &{name} [{aliases}]\n"));
+        }
+        bomb.push_str("---\n");
+        let Err(FrontMatterError::Alias(marker)) = parse(&bomb) else {
+            panic!("parse: should reject aliases instead of expanding them");
+        };
+        assert_eq!(marker.line(), 3);
+    }
+}
@@ -1,21 +1,93 @@
 //! Code for interacting with GitHub (authentication, prs, et cetera)
 
-use chrono::DateTime;
-use color_eyre::eyre::{bail, Context};
+use crate::codeowners::Reviewers;
+use crate::gh_fixtures::{Fixtures, SendWithFixtures, REDACTED};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{bail, Context, ContextCompat};
 use color_eyre::Result;
 use fs_err as fs;
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
-use reqwest::Client;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Map, Value};
-use std::io::Read;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 const GITHUB_API_URL: &str = "https://api.github.com";
 
+/// The characters percent-encoded when a ref is put in a URL path. Slashes are kept, as branch
+/// names can contain them, and [`encode_ref`] rejects anything that could climb out of the path.
+const REF_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.');
+
+/// Encode a branch, tag, or commit SHA provided by a user so it can be put in an API URL.
+///
+/// # Errors
+/// This function returns an error if `git_ref` isn't a valid ref name, for example if it
+/// contains `..` or `?`.
+fn encode_ref(git_ref: &str) -> Result<String> {
+    if !git2::Reference::is_valid_name(&format!("refs/heads/{git_ref}")) {
+        bail!("{git_ref:?} isn't a valid branch, tag, or commit");
+    }
+    Ok(utf8_percent_encode(git_ref, REF_ENCODE_SET).to_string())
+}
+
+/// The locations GitHub searches for a pull request template, in order of precedence
+pub const PULL_REQUEST_TEMPLATE_PATHS: [&str; 6] = [
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    ".github/pull_request_template.md",
+    "PULL_REQUEST_TEMPLATE.md",
+    "pull_request_template.md",
+    "docs/PULL_REQUEST_TEMPLATE.md",
+    "docs/pull_request_template.md",
+];
+
+/// Where the user's description goes in a pull request template. If the template doesn't
+/// contain it, the description is put above the template.
+pub const PULL_REQUEST_DESCRIPTION_PLACEHOLDER: &str = "<!-- hyde-description -->";
+
+/// If set, the GitHub App's private key is read from this environment variable (as PEM)
+/// instead of from `oauth.github.private_key_path`
+pub const PRIVATE_KEY_ENV_VAR: &str = "HYDE_GITHUB_PRIVATE_KEY";
+
+/// Fetches everything shown on the dashboard, 100 of each item at a time. Each connection is
+/// only included while it has more pages, and is continued after the `<connection>After`
+/// cursor, see [`DASHBOARD_CONNECTIONS`].
+const DASHBOARD_QUERY: &str = r#"
+query(
+  $owner: String!, $name: String!,
+  $refs: Boolean!, $refsAfter: String,
+  $pullRequests: Boolean!, $pullRequestsAfter: String,
+  $issues: Boolean!, $issuesAfter: String
+) {
+  repository(owner: $owner, name: $name) {
+    refs(refPrefix: "refs/heads/", first: 100, after: $refsAfter) @include(if: $refs) {
+      pageInfo { hasNextPage endCursor }
+      nodes { name branchProtectionRule { id } }
+    }
+    pullRequests(states: OPEN, first: 100, after: $pullRequestsAfter) @include(if: $pullRequests) {
+      pageInfo { hasNextPage endCursor }
+      nodes { number title url headRefName baseRefName }
+    }
+    issues(states: OPEN, first: 100, after: $issuesAfter) @include(if: $issues) {
+      pageInfo { hasNextPage endCursor }
+      nodes { number title url labels(first: 20) { nodes { name } } }
+    }
+  }
+}
+"#;
+
+/// The paginated connections in [`DASHBOARD_QUERY`]
+const DASHBOARD_CONNECTIONS: [&str; 3] = ["refs", "pullRequests", "issues"];
+
 #[derive(Clone)]
 pub struct GitHubClient {
     /// The URL of the GitHub repository this client is associated with.
@@ -24,6 +96,13 @@ pub struct GitHubClient {
     client: Client,
     /// The client ID for GitHub OAuth authentication.
     client_id: String,
+    /// The GitHub App installation to authenticate as, if configured. Otherwise it's
+    /// determined from `repo_url`.
+    installation_id: Option<u64>,
+    /// The GitHub App's private key, used to sign JWTs.
+    private_key: Arc<EncodingKey>,
+    /// Where requests are recorded to or replayed from, if enabled
+    fixtures: Option<Arc<Fixtures>>,
     /// A thread-safe, shared access token for authenticating requests.
     token: Arc<Mutex<String>>,
     /// The expiration time of the current authentication token.
@@ -36,20 +115,39 @@ impl GitHubClient {
     /// # Arguments
     /// - `repo_url` - A `String` representing the URL of the GitHub repository.
     /// - `client` - A `reqwest::Client` used for making HTTP requests to GitHub's API.
-    /// - `token` - A `String` representing the GitHub access token used for authentication.
+    /// - `client_id` - The client ID of the GitHub App used for authentication.
+    /// - `installation_id` - The installation of the GitHub App to use. When `None`, the
+    ///   installation is found by looking up the installation on `repo_url`.
+    /// - `private_key` - The GitHub App's private key, see [`load_private_key`].
     ///
     /// # Returns
     /// - A new `GitHubClient` instance that can be used to interact with the GitHub API.
-    pub fn new(repo_url: String, client: Client, client_id: String) -> Self {
+    pub fn new(
+        repo_url: String,
+        client: Client,
+        client_id: String,
+        installation_id: Option<u64>,
+        private_key: EncodingKey,
+    ) -> Self {
         Self {
             repo_url,
             client,
             client_id,
+            installation_id,
+            private_key: Arc::new(private_key),
+            fixtures: None,
             token: Arc::new(Mutex::new(String::new())),
             expires_at: Arc::new(Mutex::new(UNIX_EPOCH)),
         }
     }
 
+    /// Record every request to the API, or replay them, see [`Fixtures`].
+    #[must_use]
+    pub fn with_fixtures(mut self, fixtures: Fixtures) -> Self {
+        self.fixtures = Some(Arc::new(fixtures));
+        self
+    }
+
     /// Retrieves a valid GitHub access token, refreshing it if necessary.
     ///
     /// This function ensures that a valid access token is always available for making authenticated
@@ -69,6 +167,10 @@ impl GitHubClient {
     /// - The response from the token refresh endpoint cannot be parsed or does not contain valid token data.
     ///
     pub async fn get_token(&self) -> Result<String> {
+        // Nothing is sent when replaying, so there's no need for a real token
+        if self.fixtures.as_ref().is_some_and(|f| f.is_replay()) {
+            return Ok(REDACTED.to_string());
+        }
         let mut token_ref = self.token.lock().await;
 
         // Fetch a new token if more than 59 minutes have passed
@@ -126,10 +228,12 @@ impl GitHubClient {
     /// - `base_branch`: A string slice representing the base branch to which the pull request is created (target branch).
     /// - `pr_title`: A string slice representing the title of the pull request.
     /// - `pr_description`: A string slice representing the description of the pull request.
+    /// - `issue_numbers`: Issues closed by the pull request, referenced in the description.
+    /// - `milestone`: The number of the milestone to assign the pull request to, if any.
     ///
     /// # Returns:
-    /// A `Result<String>`:
-    /// - `Ok(url)`: If the pull request is successfully created, it returns the URL of the created pull request.
+    /// A `Result<PullRequest>`:
+    /// - `Ok(pull_request)`: If the pull request is successfully created, it returns the number and URL of the created pull request.
     /// - `Err(e)`: If the pull request creation fails, it returns an error message describing the failure.
     ///
     /// # Errors:
@@ -145,7 +249,8 @@ impl GitHubClient {
         pr_title: &str,
         pr_description: &str,
         issue_numbers: Option<Vec<u64>>,
-    ) -> Result<String> {
+        milestone: Option<u64>,
+    ) -> Result<PullRequest> {
         // Parse the repository name from self.repo_url
         let repo_name = self.get_repo_name()?;
         let token = self.get_token().await?;
@@ -177,7 +282,7 @@ impl GitHubClient {
             .bearer_auth(&token)
             .header("User-Agent", "Hyde")
             .json(&pr_body_json)
-            .send()
+            .send_with(&self.client, self.fixtures.as_deref())
             .await?;
 
         // Handle the response based on the status code
@@ -187,13 +292,19 @@ impl GitHubClient {
                 head_branch, base_branch
             );
 
-            // Extract the response JSON to get the pull request URL
-            let response_json: Value = response.json().await?;
-            if let Some(url) = response_json.get("html_url").and_then(Value::as_str) {
-                Ok(url.to_string())
-            } else {
-                bail!("Expected URL field not found in the response.");
+            // Extract the response JSON to get the pull request number and URL
+            let pull_request: PullRequest = response
+                .json()
+                .await
+                .wrap_err("Expected number and URL fields not found in the response.")?;
+            // Milestones can't be set when creating a pull request, only afterwards. The pull
+            // request already exists at this point, so failing to set it isn't fatal
+            if let Some(milestone) = milestone {
+                if let Err(e) = self.set_milestone(pull_request.number, milestone).await {
+                    warn!("Failed to assign new pull request to milestone {milestone}: {e:?}");
+                }
             }
+            Ok(pull_request)
         } else {
             let status = response.status();
             let response_text = response.text().await?;
@@ -217,6 +328,7 @@ impl GitHubClient {
     /// - `base_branch` - Optional target base branch to change the pull request's target.
     /// - `issue_numbers` - Optional list of GitHub issue numbers to associate with the pull request.
     ///   These issues will be referenced in the pull request description using the "Closes #<issue_number>" syntax.
+    /// - `milestone` - Optional number of the milestone to assign the pull request to.
     ///
     /// # Returns
     /// A `Result<String>`:
@@ -237,6 +349,7 @@ impl GitHubClient {
         pr_description: Option<&str>,
         base_branch: Option<&str>,
         issue_numbers: Option<Vec<u64>>,
+        milestone: Option<u64>,
     ) -> Result<String> {
         let repo_name = self.get_repo_name()?;
         let token = self.get_token().await?;
@@ -282,12 +395,15 @@ impl GitHubClient {
             .bearer_auth(&token)
             .header("User-Agent", "Hyde")
             .json(&pr_body_json)
-            .send()
+            .send_with(&self.client, self.fixtures.as_deref())
             .await?;
 
         // Handle the response based on the status code
         if response.status().is_success() {
             info!("Pull request #{} updated successfully", pr_number);
+            if let Some(milestone) = milestone {
+                self.set_milestone(pr_number, milestone).await?;
+            }
 
             // Extract the response JSON to get the updated pull request URL
             let response_json: Value = response.json().await?;
@@ -308,6 +424,147 @@ impl GitHubClient {
         }
     }
 
+    /// Fetches the milestones of the GitHub repository.
+    ///
+    /// # Parameters:
+    /// - `state`: The state of the milestones to fetch ("open", "closed", or "all").
+    ///
+    /// # Errors:
+    /// This function may return an error if the request to GitHub fails, or the response
+    /// cannot be deserialized.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn list_milestones(&self, state: &str) -> Result<Vec<Milestone>> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+        let mut milestones = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let response = self
+                .client
+                .get(format!("{}/repos/{}/milestones", GITHUB_API_URL, repo_name))
+                .bearer_auth(&token)
+                .header("User-Agent", "Hyde")
+                .query(&[
+                    ("state", state),
+                    ("sort", "due_on"),
+                    ("per_page", "100"),
+                    ("page", &page.to_string()),
+                ])
+                .send_with(&self.client, self.fixtures.as_deref())
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let response_text = response.text().await?;
+                bail!(
+                    "Failed to fetch milestones: {}, Response: {}",
+                    status,
+                    response_text
+                );
+            }
+
+            let page_milestones: Vec<Milestone> = response.json().await?;
+            if page_milestones.is_empty() {
+                break;
+            }
+            milestones.extend(page_milestones);
+            page += 1;
+        }
+
+        Ok(milestones)
+    }
+
+    /// Assigns a pull request to a milestone. Pull requests are issues as far as milestones
+    /// are concerned, so this goes through the issues API.
+    ///
+    /// # Parameters:
+    /// - `pr_number`: The number of the pull request.
+    /// - `milestone`: The number (not the id) of the milestone.
+    ///
+    /// # Errors:
+    /// This function returns an error if the request to GitHub fails, for example
+    /// if the milestone doesn't exist.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn set_milestone(&self, pr_number: u64, milestone: u64) -> Result<()> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        let response = self
+            .client
+            .patch(format!(
+                "{}/repos/{}/issues/{}",
+                GITHUB_API_URL, repo_name, pr_number
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .json(&json!({ "milestone": milestone }))
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to assign pull request #{} to milestone {}: {}, Response: {}",
+                pr_number,
+                milestone,
+                status,
+                response_text
+            );
+        }
+
+        info!("Pull request #{pr_number} assigned to milestone {milestone}");
+        Ok(())
+    }
+
+    /// Publishes a GitHub release, creating the tag from the default branch if it doesn't exist.
+    ///
+    /// # Parameters:
+    /// - `tag_name`: The tag the release is for, e.g. `docs-2025-02`.
+    /// - `name`: The title of the release.
+    /// - `body`: The release notes, in markdown.
+    ///
+    /// # Returns:
+    /// The URL of the release.
+    ///
+    /// # Errors:
+    /// This function returns an error if the request to GitHub fails, for example if a release
+    /// for `tag_name` already exists.
+    #[tracing::instrument(level = "debug", skip(self, body))]
+    pub async fn create_release(&self, tag_name: &str, name: &str, body: &str) -> Result<String> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        let response = self
+            .client
+            .post(format!("{}/repos/{}/releases", GITHUB_API_URL, repo_name))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .json(&json!({ "tag_name": tag_name, "name": name, "body": body }))
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to create release {:?}: {}, Response: {}",
+                tag_name,
+                status,
+                response_text
+            );
+        }
+
+        let release: Value = response.json().await?;
+        let html_url = release["html_url"]
+            .as_str()
+            .wrap_err("Expected an `html_url` field in the release")?
+            .to_string();
+        info!("Release {tag_name:?} published at {html_url}");
+        Ok(html_url)
+    }
+
     /// Closes a pull request in the specified GitHub repository.
     ///
     /// This function sends a `PATCH` request to the GitHub API to change the state
@@ -352,7 +609,7 @@ impl GitHubClient {
             .bearer_auth(&token)
             .header("User-Agent", "Hyde")
             .json(&pr_body_json)
-            .send()
+            .send_with(&self.client, self.fixtures.as_deref())
             .await?;
 
         // Handle the response
@@ -371,6 +628,95 @@ impl GitHubClient {
         }
     }
 
+    /// Requests reviews on a pull request from the provided users and teams.
+    ///
+    /// # Arguments
+    /// - `pr_number` - The number of the pull request to request reviews on.
+    /// - `reviewers` - The users (by username) and teams (by slug) to request reviews from.
+    ///
+    /// # Errors
+    /// This function returns an error if the repository name can't be determined, or the
+    /// GitHub API rejects the request (for example, if a reviewer isn't a collaborator).
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn request_reviewers(&self, pr_number: u64, reviewers: &Reviewers) -> Result<()> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        let response = self
+            .client
+            .post(format!(
+                "{}/repos/{}/pulls/{}/requested_reviewers",
+                GITHUB_API_URL, repo_name, pr_number
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .json(&json!({
+                "reviewers": reviewers.users,
+                "team_reviewers": reviewers.teams,
+            }))
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if response.status().is_success() {
+            info!(
+                "Requested reviews on pull request #{} from {:?}",
+                pr_number, reviewers
+            );
+            Ok(())
+        } else {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to request reviewers for pull request #{}: {}, Response: {}",
+                pr_number,
+                status,
+                response_text
+            );
+        }
+    }
+
+    /// Assigns users to a pull request (or issue).
+    ///
+    /// # Arguments
+    /// - `pr_number` - The number of the pull request to assign users to.
+    /// - `assignees` - The usernames of the users to assign.
+    ///
+    /// # Errors
+    /// This function returns an error if the repository name can't be determined, or the
+    /// GitHub API rejects the request.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn add_assignees(&self, pr_number: u64, assignees: &[&str]) -> Result<()> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        let response = self
+            .client
+            .post(format!(
+                "{}/repos/{}/issues/{}/assignees",
+                GITHUB_API_URL, repo_name, pr_number
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .json(&json!({ "assignees": assignees }))
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if response.status().is_success() {
+            info!("Assigned {assignees:?} to pull request #{pr_number}");
+            Ok(())
+        } else {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to assign {:?} to pull request #{}: {}, Response: {}",
+                assignees,
+                pr_number,
+                status,
+                response_text
+            );
+        }
+    }
+
     /// Fetches a complete list of branches with detailed information from the specified GitHub repository.
     ///
     /// This function retrieves all branches for a repository by sending paginated GET requests to the GitHub API.
@@ -405,7 +751,7 @@ impl GitHubClient {
                 .bearer_auth(&token)
                 .header("User-Agent", "Hyde")
                 .query(&[("per_page", "100"), ("page", &page.to_string())])
-                .send()
+                .send_with(&self.client, self.fixtures.as_deref())
                 .await?;
 
             // Check response status and handle it accordingly
@@ -432,120 +778,707 @@ impl GitHubClient {
         Ok(branches)
     }
 
-    /// Fetches the default branch of the repository associated with the authenticated user.
-    ///
-    /// This function retrieves the repository name using `get_repo_name`,
-    /// sends a `GET` request to the GitHub API to fetch repository details,
-    /// and extracts the default branch from the response.
-    ///
-    /// # Returns
-    /// A `Result<String>`:
-    /// - `Ok(String)` containing the name of the default branch if successful.
-    /// - `Err(anyhow::Error)` if an error occurs during the process, such as:
-    ///   - Failure to retrieve the repository name.
-    ///   - Issues with sending the `GET` request or network problems.
-    ///   - Failure to parse the response or if the `default_branch` field is missing from the response.
+    /// Fetches whether a pull request is open, closed or merged, and when it was merged.
     ///
     /// # Errors
-    /// Returns an error in the following cases:
-    /// - The repository name cannot be retrieved from the GitHub client.
-    /// - The `GET` request to fetch repository details fails (e.g., due to network issues or API errors).
-    /// - The response from GitHub does not contain a valid `default_branch` field.
+    /// This function returns an error if the request to GitHub fails, or the response
+    /// cannot be deserialized.
     #[tracing::instrument(level = "debug", skip(self))]
-    pub async fn get_default_branch(&self) -> Result<String> {
-        // Extract repository name from `repo_url`
+    pub async fn get_pull_request_state(&self, pr_number: u64) -> Result<PullRequestState> {
         let repo_name = self.get_repo_name()?;
         let token = self.get_token().await?;
 
-        // Make the GET request to fetch repository details
         let response = self
             .client
-            .get(format!("{}/repos/{}", GITHUB_API_URL, repo_name))
+            .get(format!(
+                "{}/repos/{}/pulls/{}",
+                GITHUB_API_URL, repo_name, pr_number
+            ))
             .bearer_auth(&token)
             .header("User-Agent", "Hyde")
-            .send()
+            .send_with(&self.client, self.fixtures.as_deref())
             .await?;
 
-        // Check response status
         if !response.status().is_success() {
             let status = response.status();
             let response_text = response.text().await?;
             bail!(
-                "Failed to fetch repository details: {}, Response: {}",
+                "Failed to fetch pull request #{}: {}, Response: {}",
+                pr_number,
                 status,
                 response_text
             );
         }
 
-        // Deserialize the response to get the repository details
-        let repo_details: Map<String, Value> = response.json().await?;
-
-        // Retrieve the default branch from the response
-        let serialized_default_branch = repo_details
-            .get("default_branch")
-            .expect("GitHub API response missing expected field 'default_branch'");
-        let default_branch = serialized_default_branch.as_str().unwrap().to_owned();
-
-        Ok(default_branch)
+        Ok(response.json().await?)
     }
 
-    /// Fetches issues from the GitHub repository.
-    ///
-    /// This function retrieves issues from the specified repository using the GitHub API.
-    /// You can filter issues based on their issue_state and associated labels.
+    /// Fetches pull requests from the GitHub repository.
     ///
     /// # Parameters:
-    /// - `issue_state`: A string slice representing the issue_state of the issues to fetch (e.g., "open", "closed", "all").
-    ///            Defaults to "open".
-    /// - `labels`: A comma-separated string slice representing labels to filter issues by. Defaults to `None`.
-    ///
-    /// # Returns:
-    /// A `Result<Vec<Value>>`:
-    /// - `Ok(issues)`: A vector of JSON values representing the issues fetched from the repository.
-    /// - `Err(e)`: An error message if the request fails or the response cannot be parsed.
+    /// - `state`: The state of the pull requests to fetch (e.g., "open", "closed", "all").
     ///
     /// # Errors:
-    /// This function may return an error if:
-    /// - The `repo_url` is not in the expected format and cannot be parsed to derive the repository name.
-    /// - The request to fetch issues fails due to authentication issues, invalid input, or network problems.
-    /// - The GitHub API response cannot be parsed as a JSON array.
+    /// This function may return an error if the request to GitHub fails, or the response
+    /// cannot be deserialized.
     #[tracing::instrument(level = "debug", skip(self))]
-    pub async fn get_issues(
-        &self,
-        state: Option<&str>,
-        labels: Option<&str>,
-    ) -> Result<Vec<Value>> {
+    pub async fn list_pull_requests(&self, state: &str) -> Result<Vec<PullRequestSummary>> {
         let repo_name = self.get_repo_name()?;
-
-        let issue_state = state.unwrap_or("open");
         let token = self.get_token().await?;
-        let mut query_params = vec![format!("state={}", issue_state)];
-        if let Some(labels) = labels {
-            query_params.push(format!("labels={}", labels));
-        }
-        let query_string = format!("?{}", query_params.join("&"));
+        let mut pull_requests = Vec::new();
+        let mut page = 1;
 
-        let url = format!(
-            "{}/repos/{}/issues{}",
-            GITHUB_API_URL, repo_name, query_string
-        );
+        loop {
+            let response = self
+                .client
+                .get(format!("{}/repos/{}/pulls", GITHUB_API_URL, repo_name))
+                .bearer_auth(&token)
+                .header("User-Agent", "Hyde")
+                .query(&[
+                    ("state", state),
+                    ("per_page", "100"),
+                    ("page", &page.to_string()),
+                ])
+                .send_with(&self.client, self.fixtures.as_deref())
+                .await?;
 
-        let response = self
-            .client
-            .get(&url)
-            .bearer_auth(&token)
-            .header("Accept", "application/vnd.github+json")
-            .header("User-Agent", "Hyde")
-            .timeout(std::time::Duration::from_secs(10))
-            .send()
-            .await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let response_text = response.text().await?;
+                bail!(
+                    "Failed to fetch pull requests: {}, Response: {}",
+                    status,
+                    response_text
+                );
+            }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
+            let page_pull_requests: Vec<Value> = response.json().await?;
+            if page_pull_requests.is_empty() {
+                break;
+            }
+            for pr in page_pull_requests {
+                pull_requests.push(PullRequestSummary {
+                    number: pr["number"].as_u64().unwrap_or_default(),
+                    title: pr["title"].as_str().unwrap_or_default().to_string(),
+                    html_url: pr["html_url"].as_str().unwrap_or_default().to_string(),
+                    head_branch: pr["head"]["ref"].as_str().unwrap_or_default().to_string(),
+                    base_branch: pr["base"]["ref"].as_str().unwrap_or_default().to_string(),
+                });
+            }
+            page += 1;
+        }
+
+        Ok(pull_requests)
+    }
+
+    /// Fetches every open issue (and pull request, which GitHub counts as issues), a page at a
+    /// time. Unlike [`Self::get_issues`], which only fetches the first page.
+    async fn list_open_issues(&self) -> Result<Vec<Value>> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+        let mut issues = Vec::new();
+        for page in 1.. {
+            let response = self
+                .client
+                .get(format!("{}/repos/{}/issues", GITHUB_API_URL, repo_name))
+                .bearer_auth(&token)
+                .header("User-Agent", "Hyde")
+                .query(&[
+                    ("state", "open"),
+                    ("per_page", "100"),
+                    ("page", &page.to_string()),
+                ])
+                .send_with(&self.client, self.fixtures.as_deref())
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let response_text = response.text().await?;
+                bail!("Failed to fetch issues: {status}, Response: {response_text}");
+            }
+
+            let page_issues: Vec<Value> = response.json().await?;
+            if page_issues.is_empty() {
+                break;
+            }
+            issues.extend(page_issues);
+        }
+        Ok(issues)
+    }
+
+    /// Fetches the branches, open pull requests, and open issues shown on the dashboard.
+    ///
+    /// This uses a GitHub GraphQL query, which takes one request per 100 of each item, falling
+    /// back to the REST API (which takes several more) if the GraphQL request fails.
+    ///
+    /// # Errors
+    /// This function returns an error if both the GraphQL and the REST requests fail.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn get_dashboard(&self) -> Result<DashboardData> {
+        match self.get_dashboard_graphql().await {
+            Ok(data) => Ok(data),
+            Err(e) => {
+                warn!("GraphQL dashboard query failed, falling back to the REST API: {e:?}");
+                self.get_dashboard_rest().await
+            }
+        }
+    }
+
+    /// See [`Self::get_dashboard`]
+    async fn get_dashboard_graphql(&self) -> Result<DashboardData> {
+        let repo_name = self.get_repo_name()?;
+        let (owner, name) = repo_name
+            .split_once('/')
+            .wrap_err("Repository name is not in the format <owner>/<repo>")?;
+        let token = self.get_token().await?;
+
+        let mut nodes: HashMap<&str, Vec<Value>> = HashMap::new();
+        let mut cursors: HashMap<&str, Value> = HashMap::new();
+        let mut pending = DASHBOARD_CONNECTIONS.to_vec();
+        while !pending.is_empty() {
+            let mut variables = json!({ "owner": owner, "name": name });
+            for connection in DASHBOARD_CONNECTIONS {
+                variables[connection] = json!(pending.contains(&connection));
+                variables[format!("{connection}After").as_str()] =
+                    cursors.get(connection).cloned().unwrap_or_default();
+            }
+            let response = self
+                .client
+                .post(format!("{}/graphql", GITHUB_API_URL))
+                .bearer_auth(&token)
+                .header("User-Agent", "Hyde")
+                .json(&json!({ "query": DASHBOARD_QUERY, "variables": variables }))
+                .send_with(&self.client, self.fixtures.as_deref())
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let response_text = response.text().await?;
+                bail!(
+                    "GraphQL request failed: {}, Response: {}",
+                    status,
+                    response_text
+                );
+            }
+
+            let body: Value = response.json().await?;
+            // GraphQL reports errors with a 200 status code
+            if let Some(errors) = body.get("errors") {
+                bail!("GraphQL query returned errors: {errors}");
+            }
+            for connection in std::mem::take(&mut pending) {
+                let page = &body["data"]["repository"][connection];
+                nodes.entry(connection).or_default().extend(
+                    page["nodes"]
+                        .as_array()
+                        .cloned()
+                        .wrap_err_with(|| format!("GraphQL response missing {connection:?}"))?,
+                );
+                if page["pageInfo"]["hasNextPage"].as_bool() == Some(true) {
+                    cursors.insert(connection, page["pageInfo"]["endCursor"].clone());
+                    pending.push(connection);
+                }
+            }
+        }
+        let mut nodes = |connection: &str| nodes.remove(connection).unwrap_or_default();
+
+        let branches = nodes("refs")
+            .into_iter()
+            .map(|b| Branch {
+                name: b["name"].as_str().unwrap_or_default().to_string(),
+                protected: !b["branchProtectionRule"].is_null(),
+            })
+            .collect();
+        let pull_requests = nodes("pullRequests")
+            .into_iter()
+            .map(|pr| PullRequestSummary {
+                number: pr["number"].as_u64().unwrap_or_default(),
+                title: pr["title"].as_str().unwrap_or_default().to_string(),
+                html_url: pr["url"].as_str().unwrap_or_default().to_string(),
+                head_branch: pr["headRefName"].as_str().unwrap_or_default().to_string(),
+                base_branch: pr["baseRefName"].as_str().unwrap_or_default().to_string(),
+            })
+            .collect();
+        let issues = nodes("issues")
+            .into_iter()
+            .map(|issue| IssueSummary {
+                number: issue["number"].as_u64().unwrap_or_default(),
+                title: issue["title"].as_str().unwrap_or_default().to_string(),
+                html_url: issue["url"].as_str().unwrap_or_default().to_string(),
+                labels: issue["labels"]["nodes"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|l| l["name"].as_str().map(ToString::to_string))
+                    .collect(),
+            })
+            .collect();
+
+        Ok(DashboardData {
+            branches,
+            pull_requests,
+            issues,
+        })
+    }
+
+    /// See [`Self::get_dashboard`]
+    async fn get_dashboard_rest(&self) -> Result<DashboardData> {
+        let branches = self.list_branches().await?;
+        let pull_requests = self.list_pull_requests("open").await?;
+        let issues = self
+            .list_open_issues()
+            .await?
+            .into_iter()
+            // The issues endpoint also returns pull requests
+            .filter(|issue| issue.get("pull_request").is_none())
+            .map(|issue| IssueSummary {
+                number: issue["number"].as_u64().unwrap_or_default(),
+                title: issue["title"].as_str().unwrap_or_default().to_string(),
+                html_url: issue["html_url"].as_str().unwrap_or_default().to_string(),
+                labels: issue["labels"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|l| l["name"].as_str().map(ToString::to_string))
+                    .collect(),
+            })
+            .collect();
+
+        Ok(DashboardData {
+            branches,
+            pull_requests,
+            issues,
+        })
+    }
+
+    /// Fetches the repository's metadata, including its default branch. This is cached in
+    /// [`RepoMetadataCache`], so it shouldn't be called on every request.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    /// - The repository name cannot be retrieved from the GitHub client.
+    /// - The `GET` request to fetch repository details fails (e.g., due to network issues or API errors).
+    /// - The response from GitHub cannot be deserialized.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn get_repo_metadata(&self) -> Result<RepoMetadata> {
+        // Extract repository name from `repo_url`
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        // Make the GET request to fetch repository details
+        let response = self
+            .client
+            .get(format!("{}/repos/{}", GITHUB_API_URL, repo_name))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        // Check response status
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to fetch repository details: {}, Response: {}",
+                status,
+                response_text
+            );
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Creates a branch on GitHub using the git refs API, without pushing anything. This is an
+    /// alternative to creating a branch locally and pushing it, for repositories where direct
+    /// pushes are restricted to the GitHub App.
+    ///
+    /// # Arguments
+    /// - `name` - The name of the new branch, without the `refs/heads/` prefix.
+    /// - `from_sha` - The commit the branch should point to.
+    ///
+    /// # Errors
+    /// This function returns an error if the branch already exists, `from_sha` isn't a commit
+    /// in the repository, or the request to GitHub fails.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn create_branch(&self, name: &str, from_sha: &str) -> Result<()> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        let response = self
+            .client
+            .post(format!("{}/repos/{}/git/refs", GITHUB_API_URL, repo_name))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .json(&json!({
+                "ref": format!("refs/heads/{name}"),
+                "sha": from_sha,
+            }))
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if response.status().is_success() {
+            info!("Branch '{name}' created on GitHub at {from_sha}");
+            Ok(())
+        } else {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to create branch {:?}: {}, Response: {}",
+                name,
+                status,
+                response_text
+            );
+        }
+    }
+
+    /// Deletes a branch on GitHub using the git refs API, for example to clean up after a
+    /// branch made with [`GitHubClient::create_branch`] turned out not to be needed.
+    ///
+    /// # Errors
+    /// This function returns an error if the branch doesn't exist, or the request to GitHub
+    /// fails.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn delete_branch(&self, name: &str) -> Result<()> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        let response = self
+            .client
+            .delete(format!(
+                "{}/repos/{}/git/refs/heads/{}",
+                GITHUB_API_URL, repo_name, name
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if response.status().is_success() {
+            info!("Branch '{name}' deleted on GitHub");
+            Ok(())
+        } else {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to delete branch {:?}: {}, Response: {}",
+                name,
+                status,
+                response_text
+            );
+        }
+    }
+
+    /// Creates or overwrites a file on a branch using the contents API, which commits the change
+    /// as the GitHub App. Used together with [`GitHubClient::create_branch`] when changes can't
+    /// be pushed directly.
+    ///
+    /// # Arguments
+    /// - `path` - The path of the file, relative to the root of the repository.
+    /// - `contents` - The new contents of the file.
+    /// - `message` - The commit message.
+    /// - `branch` - The branch to commit to.
+    /// - `existing_blob` - The blob SHA of the file being replaced, or `None` if it's a new file.
+    ///   GitHub rejects the change if this doesn't match the file on `branch`.
+    ///
+    /// # Errors
+    /// This function returns an error if the request to GitHub fails, for example because
+    /// `existing_blob` is out of date.
+    #[tracing::instrument(level = "debug", skip(self, contents))]
+    pub async fn put_file(
+        &self,
+        path: &str,
+        contents: &[u8],
+        message: &str,
+        branch: &str,
+        existing_blob: Option<&str>,
+    ) -> Result<()> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        let mut body = json!({
+            "message": message,
+            "content": BASE64_STANDARD.encode(contents),
+            "branch": branch,
+        });
+        if let Some(sha) = existing_blob {
+            body["sha"] = json!(sha);
+        }
+
+        let response = self
+            .client
+            .put(format!(
+                "{}/repos/{}/contents/{}",
+                GITHUB_API_URL, repo_name, path
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .json(&body)
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if response.status().is_success() {
+            info!("{path:?} committed to branch '{branch}' on GitHub");
+            Ok(())
+        } else {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to commit {:?} to branch {:?}: {}, Response: {}",
+                path,
+                branch,
+                status,
+                response_text
+            );
+        }
+    }
+
+    /// Fetches the CI status of a commit, combining legacy commit statuses with check runs
+    /// (GitHub Actions, the pages build, et cetera).
+    ///
+    /// # Parameters:
+    /// - `git_ref`: A commit SHA, branch name, or tag name.
+    ///
+    /// # Returns:
+    /// A [`CheckSummary`] containing every status and check run reported for the commit,
+    /// along with an overall [`CheckState`].
+    ///
+    /// # Errors:
+    /// This function may return an error if `git_ref` isn't a valid ref name, either request to
+    /// GitHub fails (for example if `git_ref` does not exist), or a response cannot be
+    /// deserialized.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn get_check_runs(&self, git_ref: &str) -> Result<CheckSummary> {
+        let repo_name = self.get_repo_name()?;
+        let git_ref = encode_ref(git_ref)?;
+        let token = self.get_token().await?;
+
+        // Legacy commit statuses, reported by external CI services
+        let response = self
+            .client
+            .get(format!(
+                "{}/repos/{}/commits/{}/status",
+                GITHUB_API_URL, repo_name, git_ref
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .query(&[("per_page", "100")])
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to fetch commit status: {}, Response: {}",
+                status,
+                response_text
+            );
+        }
+
+        let combined: CombinedStatusResponse = response.json().await?;
+
+        // Check runs, reported by GitHub Apps (including GitHub Actions)
+        let response = self
+            .client
+            .get(format!(
+                "{}/repos/{}/commits/{}/check-runs",
+                GITHUB_API_URL, repo_name, git_ref
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .query(&[("per_page", "100")])
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to fetch check runs: {}, Response: {}",
+                status,
+                response_text
+            );
+        }
+
+        let check_runs: CheckRunsResponse = response.json().await?;
+
+        Ok(CheckSummary::new(
+            combined.sha,
+            combined.statuses,
+            check_runs.check_runs,
+        ))
+    }
+
+    /// Fetches the status of the repository's GitHub Pages site, and its latest build.
+    ///
+    /// # Returns:
+    /// - `Ok(Some(status))`: The deployed URL of the site, along with the status of the most
+    ///   recent build (including the error message if it failed).
+    /// - `Ok(None)`: GitHub Pages isn't enabled for the repository.
+    ///
+    /// # Errors:
+    /// This function may return an error if either request to GitHub fails, or a response
+    /// cannot be deserialized.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn get_pages_build_status(&self) -> Result<Option<PagesBuildStatus>> {
+        let repo_name = self.get_repo_name()?;
+        let token = self.get_token().await?;
+
+        let response = self
+            .client
+            .get(format!("{}/repos/{}/pages", GITHUB_API_URL, repo_name))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to fetch GitHub Pages site: {}, Response: {}",
+                status,
+                response_text
+            );
+        }
+        let site: PagesResponse = response.json().await?;
+
+        let response = self
+            .client
+            .get(format!(
+                "{}/repos/{}/pages/builds/latest",
+                GITHUB_API_URL, repo_name
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        // The site may never have been built
+        let latest_build = if response.status() == StatusCode::NOT_FOUND {
+            None
+        } else if response.status().is_success() {
+            Some(response.json::<PagesBuild>().await?)
+        } else {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to fetch latest GitHub Pages build: {}, Response: {}",
+                status,
+                response_text
+            );
+        };
+
+        Ok(Some(PagesBuildStatus::new(site, latest_build)))
+    }
+
+    /// Compares two branches, tags, or commits on GitHub, including ones that haven't been
+    /// fetched into the local clone.
+    ///
+    /// # Parameters:
+    /// - `base`: The branch, tag, or commit SHA to compare against.
+    /// - `head`: The branch, tag, or commit SHA to compare.
+    ///
+    /// # Returns:
+    /// A [`Comparison`] containing the commits on `head` that aren't on `base` (at most 250,
+    /// see `total_commits`), and the files changed between them (at most 300).
+    ///
+    /// # Errors:
+    /// This function may return an error if `base` or `head` isn't a valid ref name, the request
+    /// to GitHub fails (for example if `base` or `head` doesn't exist), or the response cannot
+    /// be deserialized.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn compare(&self, base: &str, head: &str) -> Result<Comparison> {
+        let repo_name = self.get_repo_name()?;
+        let (encoded_base, encoded_head) = (encode_ref(base)?, encode_ref(head)?);
+        let token = self.get_token().await?;
+
+        let response = self
+            .client
+            .get(format!(
+                "{}/repos/{}/compare/{}...{}",
+                GITHUB_API_URL, repo_name, encoded_base, encoded_head
+            ))
+            .bearer_auth(&token)
+            .header("User-Agent", "Hyde")
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to compare {:?} to {:?}: {}, Response: {}",
+                head,
+                base,
+                status,
+                response_text
+            );
+        }
+
+        Ok(Comparison::from(response.json::<CompareResponse>().await?))
+    }
+
+    /// Fetches issues from the GitHub repository.
+    ///
+    /// This function retrieves issues from the specified repository using the GitHub API.
+    /// You can filter issues based on their issue_state and associated labels.
+    ///
+    /// # Parameters:
+    /// - `issue_state`: A string slice representing the issue_state of the issues to fetch (e.g., "open", "closed", "all").
+    ///            Defaults to "open".
+    /// - `labels`: A comma-separated string slice representing labels to filter issues by. Defaults to `None`.
+    ///
+    /// # Returns:
+    /// A `Result<Vec<Value>>`:
+    /// - `Ok(issues)`: A vector of JSON values representing the issues fetched from the repository.
+    /// - `Err(e)`: An error message if the request fails or the response cannot be parsed.
+    ///
+    /// # Errors:
+    /// This function may return an error if:
+    /// - The `repo_url` is not in the expected format and cannot be parsed to derive the repository name.
+    /// - The request to fetch issues fails due to authentication issues, invalid input, or network problems.
+    /// - The GitHub API response cannot be parsed as a JSON array.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn get_issues(
+        &self,
+        state: Option<&str>,
+        labels: Option<&str>,
+    ) -> Result<Vec<Value>> {
+        let repo_name = self.get_repo_name()?;
+
+        let issue_state = state.unwrap_or("open");
+        let token = self.get_token().await?;
+        let mut query_params = vec![format!("state={}", issue_state)];
+        if let Some(labels) = labels {
+            query_params.push(format!("labels={}", labels));
+        }
+        let query_string = format!("?{}", query_params.join("&"));
+
+        let url = format!(
+            "{}/repos/{}/issues{}",
+            GITHUB_API_URL, repo_name, query_string
+        );
+
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(&token)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "Hyde")
+            .timeout(std::time::Duration::from_secs(10))
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
             bail!("GitHub API request failed ({}): {}", status, error_text);
         }
 
@@ -557,6 +1490,71 @@ impl GitHubClient {
     /// Request a github installation access token using the provided reqwest client.
     /// The installation access token will expire after 1 hour.
     /// Returns the new token, and the time of expiration
+    /// Completes the web flow a user goes through to link their GitHub account, returning the
+    /// account they signed in to. The user's access token is only used to look up their account,
+    /// and isn't kept.
+    ///
+    /// # Arguments
+    /// - `client_secret` - The GitHub App's client secret.
+    /// - `code` - The code GitHub sent the user back with.
+    /// - `redirect_uri` - The callback URL the user was sent back to.
+    ///
+    /// # Errors
+    /// This function returns an error if GitHub rejects the code (for example if it expired),
+    /// or either request fails.
+    #[tracing::instrument(level = "debug", skip(self, client_secret, code))]
+    pub async fn get_linked_user(
+        &self,
+        client_secret: &str,
+        code: &str,
+        redirect_uri: &str,
+    ) -> Result<GitHubUser> {
+        let response = self
+            .client
+            .post("https://github.com/login/oauth/access_token")
+            .header("Accept", "application/json")
+            .header("User-Agent", "Hyde")
+            .json(&json!({
+                "client_id": self.client_id,
+                "client_secret": client_secret,
+                "code": code,
+                "redirect_uri": redirect_uri,
+            }))
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+        // Errors are reported with a 200 status and an `error` field
+        let token_response: Value = response.json().await?;
+        let user_token = token_response
+            .get("access_token")
+            .and_then(Value::as_str)
+            .wrap_err_with(|| {
+                format!(
+                    "GitHub didn't return a user access token: {}",
+                    token_response
+                        .get("error_description")
+                        .unwrap_or(&token_response)
+                )
+            })?;
+
+        let response = self
+            .client
+            .get(format!("{}/user", GITHUB_API_URL))
+            .bearer_auth(user_token)
+            .header("User-Agent", "Hyde")
+            .send_with(&self.client, self.fixtures.as_deref())
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
+            bail!(
+                "Failed to fetch the authenticated GitHub user: {}, Response: {}",
+                status,
+                response_text
+            );
+        }
+        Ok(response.json().await?)
+    }
+
     async fn get_access_token(&self) -> Result<(String, SystemTime)> {
         let token = self.gen_jwt_token()?;
         let response = self
@@ -582,43 +1580,88 @@ impl GitHubClient {
 
     /// Fetch the Installation ID. This value is required for most API calls
     ///
+    /// If `oauth.github.installation_id` is configured, that's used. Otherwise, the installation
+    /// on the configured repository is looked up, so the app may be installed on other
+    /// accounts and repositories too.
+    ///
     /// <https://docs.github.com/en/apps/creating-github-apps/authenticating-with-a-github-app/authenticating-as-a-github-app-installation#generating-an-installation-access-token>
     async fn get_installation_id(&self) -> Result<String> {
+        if let Some(id) = self.installation_id {
+            return Ok(id.to_string());
+        }
+
+        let repo_name = self.get_repo_name()?;
         let response = self
             .client
-            .get("https://api.github.com/app/installations")
+            .get(format!(
+                "{}/repos/{}/installation",
+                GITHUB_API_URL, repo_name
+            ))
             .bearer_auth(self.gen_jwt_token()?)
             .header("User-Agent", "Hyde")
             // https://docs.github.com/en/rest/about-the-rest-api/api-versions?apiVersion=2022-11-28
             .header("X-GitHub-Api-Version", "2022-11-28")
             .send()
             .await?;
-        // Validate that there's only one repo the app is installed on
-        let repo_list =
-            &serde_json::from_slice::<Vec<InstallationIdResponse>>(&response.bytes().await?)?;
-        if repo_list.len() != 1 {
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_text = response.text().await?;
             bail!(
-                "Hyde must only be installed on one repo, Github currently reports {} repos",
-                repo_list.len()
+                "Failed to find the GitHub App installation for {repo_name:?} ({status}), make sure \
+                the app is installed on that repository or set `oauth.github.installation_id`. \
+                Response: {response_text}"
             );
         }
-        Ok(repo_list[0].id.to_string())
+
+        let installation: InstallationIdResponse = response.json().await?;
+        Ok(installation.id.to_string())
     }
 
     /// Generate a new JWT token for use with github api interactions.
     fn gen_jwt_token(&self) -> Result<String> {
-        let mut private_key_file = fs::File::open("hyde-data/key.pem")
-            .wrap_err("Failed to read private key from `hyde-data/key.pem`")?;
-        let mut private_key = Vec::new();
-        private_key_file.read_to_end(&mut private_key)?;
         Ok(encode(
             &Header::new(Algorithm::RS256),
             &Claims::new(&self.client_id)?,
-            &EncodingKey::from_rsa_pem(&private_key)?,
+            &self.private_key,
         )?)
     }
 }
 
+/// Build a pull request body from the repository's pull request template, inserting
+/// `description` at [`PULL_REQUEST_DESCRIPTION_PLACEHOLDER`].
+pub fn fill_pull_request_template(template: &str, description: &str) -> String {
+    if template.contains(PULL_REQUEST_DESCRIPTION_PLACEHOLDER) {
+        template.replace(PULL_REQUEST_DESCRIPTION_PLACEHOLDER, description)
+    } else if description.is_empty() {
+        template.to_string()
+    } else {
+        format!("{description}\n\n{template}")
+    }
+}
+
+/// Load the GitHub App's private key. The PEM contents of the [`PRIVATE_KEY_ENV_VAR`] environment
+/// variable are used if it's set, otherwise the key is read from `path`.
+///
+/// # Errors
+/// This function returns an error if the key can't be read, or isn't a valid RSA key in PEM format.
+pub fn load_private_key(path: &str) -> Result<EncodingKey> {
+    let (pem, source) = match std::env::var(PRIVATE_KEY_ENV_VAR) {
+        Ok(pem) => (pem.into_bytes(), format!("`{PRIVATE_KEY_ENV_VAR}`")),
+        Err(_) => (
+            fs::read(path).wrap_err_with(|| {
+                format!(
+                    "Failed to read the GitHub App private key from {path:?}, set \
+                    `oauth.github.private_key_path` or `{PRIVATE_KEY_ENV_VAR}`"
+                )
+            })?,
+            format!("{path:?}"),
+        ),
+    };
+    EncodingKey::from_rsa_pem(&pem)
+        .wrap_err_with(|| format!("The GitHub App private key in {source} is not a valid RSA PEM"))
+}
+
 /// In order to authenticate as a github app or generate an installation access token, you must generate a JSON Web Token (JWT). The JWT must contain predefined *claims*.
 ///
 /// <https://docs.github.com/en/apps/creating-github-apps/authenticating-with-a-github-app/generating-a-json-web-token-jwt-for-a-github-app#about-json-web-tokens-jwts>
@@ -670,13 +1713,791 @@ struct AccessTokenResponse {
     token: String,
 }
 
-#[derive(Deserialize, Debug)]
+/// The subset of GitHub's repository object Hyde uses
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RepoMetadata {
+    /// The repository, as `<owner>/<repo>`
+    pub full_name: String,
+    pub default_branch: String,
+    pub html_url: String,
+    pub private: bool,
+    pub description: Option<String>,
+}
+
+/// The repository's metadata, fetched at startup and refreshed when GitHub reports a change,
+/// so that it isn't fetched on every request
+#[derive(Clone, Debug, Default)]
+pub struct RepoMetadataCache {
+    metadata: Arc<Mutex<Option<RepoMetadata>>>,
+}
+
+impl RepoMetadataCache {
+    /// Returns the cached metadata, fetching it if it isn't cached (for example if fetching it
+    /// at startup failed).
+    ///
+    /// # Errors
+    /// This function returns an error if the metadata isn't cached and fetching it fails.
+    pub async fn get(&self, gh_client: &GitHubClient) -> Result<RepoMetadata> {
+        let cached = self.metadata.lock().await.clone();
+        match cached {
+            Some(metadata) => Ok(metadata),
+            None => self.refresh(gh_client).await,
+        }
+    }
+
+    /// Fetch the metadata from GitHub, replacing the cached copy.
+    ///
+    /// # Errors
+    /// This function returns an error if fetching the metadata fails, in which case the cached
+    /// copy is kept.
+    pub async fn refresh(&self, gh_client: &GitHubClient) -> Result<RepoMetadata> {
+        let metadata = gh_client.get_repo_metadata().await?;
+        self.set(metadata.clone()).await;
+        Ok(metadata)
+    }
+
+    /// Replace the cached metadata
+    async fn set(&self, metadata: RepoMetadata) {
+        debug!(
+            "Cached metadata for {}, the default branch is '{}'",
+            metadata.full_name, metadata.default_branch
+        );
+        *self.metadata.lock().await = Some(metadata);
+    }
+}
+
+/// How long the cached branch and issue lists are served for. Webhook events invalidate them
+/// sooner, this only matters if events aren't being delivered.
+const LIST_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug)]
+struct CachedList<T> {
+    items: Vec<T>,
+    fetched_at: Instant,
+}
+
+impl<T: Clone> CachedList<T> {
+    fn new(items: Vec<T>) -> Self {
+        Self {
+            items,
+            fetched_at: Instant::now(),
+        }
+    }
+
+    /// The cached items, unless they're older than [`LIST_CACHE_TTL`]
+    fn fresh(&self) -> Option<Vec<T>> {
+        (self.fetched_at.elapsed() < LIST_CACHE_TTL).then(|| self.items.clone())
+    }
+}
+
+/// The repository's branches and issues, fetched the first time they're requested and
+/// invalidated when GitHub reports a change through the `create`, `delete` and `issues`
+/// webhook events
+#[derive(Clone, Debug, Default)]
+pub struct RepoListCache {
+    branches: Arc<Mutex<Option<CachedList<Branch>>>>,
+    /// Issues, by the state they were filtered by
+    issues: Arc<Mutex<HashMap<String, CachedList<Value>>>>,
+}
+
+impl RepoListCache {
+    /// Returns the repository's branches, fetching them if they aren't cached.
+    ///
+    /// # Errors
+    /// This function returns an error if the branches aren't cached and fetching them fails.
+    pub async fn branches(&self, gh_client: &GitHubClient) -> Result<Vec<Branch>> {
+        let cached = self
+            .branches
+            .lock()
+            .await
+            .as_ref()
+            .and_then(CachedList::fresh);
+        if let Some(branches) = cached {
+            return Ok(branches);
+        }
+        let branches = gh_client.list_branches().await?;
+        *self.branches.lock().await = Some(CachedList::new(branches.clone()));
+        Ok(branches)
+    }
+
+    /// Returns the repository's issues in the given state, fetching them if they aren't cached.
+    ///
+    /// # Errors
+    /// This function returns an error if the issues aren't cached and fetching them fails.
+    pub async fn issues(&self, gh_client: &GitHubClient, state: &str) -> Result<Vec<Value>> {
+        let cached = self
+            .issues
+            .lock()
+            .await
+            .get(state)
+            .and_then(CachedList::fresh);
+        if let Some(issues) = cached {
+            return Ok(issues);
+        }
+        let issues = gh_client.get_issues(Some(state), None).await?;
+        self.issues
+            .lock()
+            .await
+            .insert(state.to_string(), CachedList::new(issues.clone()));
+        Ok(issues)
+    }
+
+    /// Drop the cached branches, so they're fetched again the next time they're requested
+    pub async fn invalidate_branches(&self) {
+        debug!("Invalidated the cached branch list");
+        *self.branches.lock().await = None;
+    }
+
+    /// Drop the cached issues, so they're fetched again the next time they're requested
+    pub async fn invalidate_issues(&self) {
+        debug!("Invalidated the cached issue lists");
+        self.issues.lock().await.clear();
+    }
+}
+
+/// A GitHub account, as returned by the `/user` endpoint
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GitHubUser {
+    pub login: String,
+    pub id: i64,
+}
+
+/// The subset of GitHub's pull request object Hyde uses
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PullRequest {
+    pub number: u64,
+    pub html_url: String,
+}
+
+/// A milestone that pull requests can be assigned to
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Milestone {
+    pub number: u64,
+    pub title: String,
+    pub description: Option<String>,
+    /// Either `open` or `closed`
+    pub state: String,
+    /// ISO-8601/RFC-3339 string
+    pub due_on: Option<String>,
+    pub open_issues: u64,
+    pub closed_issues: u64,
+    pub html_url: String,
+}
+
+/// A pull request as shown on the dashboard
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PullRequestSummary {
+    pub number: u64,
+    pub title: String,
+    pub html_url: String,
+    pub head_branch: String,
+    pub base_branch: String,
+}
+
+/// The subset of GitHub's pull request object that says whether it's been merged
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PullRequestState {
+    /// "open" or "closed"
+    pub state: String,
+    /// `None` unless the pull request was merged
+    pub merged_at: Option<DateTime<Utc>>,
+}
+
+/// An issue as shown on the dashboard
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct IssueSummary {
+    pub number: u64,
+    pub title: String,
+    pub html_url: String,
+    pub labels: Vec<String>,
+}
+
+/// Everything shown on the dashboard, see [`GitHubClient::get_dashboard`]
+#[derive(Serialize, Debug)]
+pub struct DashboardData {
+    pub branches: Vec<Branch>,
+    pub pull_requests: Vec<PullRequestSummary>,
+    pub issues: Vec<IssueSummary>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Branch {
     pub name: String,
     pub protected: bool,
 }
 
+/// The overall result of the checks on a commit
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckState {
+    /// Every status and check run succeeded (or was skipped)
+    Success,
+    /// At least one status or check run hasn't finished yet, and none have failed
+    Pending,
+    /// At least one status or check run failed
+    Failure,
+    /// Nothing has reported a status for the commit
+    None,
+}
+
+/// A commit status, as reported through the statuses API
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CommitStatus {
+    pub context: String,
+    /// One of `error`, `failure`, `pending`, or `success`
+    pub state: String,
+    pub description: Option<String>,
+    pub target_url: Option<String>,
+}
+
+/// A check run, as reported through the checks API
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CheckRun {
+    pub name: String,
+    /// One of `queued`, `in_progress`, or `completed`
+    pub status: String,
+    /// Only set once `status` is `completed`, e.g. `success`, `failure`, `skipped`
+    pub conclusion: Option<String>,
+    pub html_url: Option<String>,
+}
+
+/// Everything reported about the CI status of a commit, see [`GitHubClient::get_check_runs`]
+#[derive(Serialize, Debug, Clone)]
+pub struct CheckSummary {
+    /// The commit the checks were run against
+    pub sha: String,
+    pub state: CheckState,
+    pub statuses: Vec<CommitStatus>,
+    pub check_runs: Vec<CheckRun>,
+}
+
+impl CheckSummary {
+    /// Build a summary, deriving the overall state from the individual statuses and check runs
+    pub fn new(sha: String, statuses: Vec<CommitStatus>, check_runs: Vec<CheckRun>) -> Self {
+        let results = statuses
+            .iter()
+            .map(|s| match s.state.as_str() {
+                "success" => CheckState::Success,
+                "pending" => CheckState::Pending,
+                _ => CheckState::Failure,
+            })
+            .chain(check_runs.iter().map(|run| {
+                match (run.status.as_str(), run.conclusion.as_deref()) {
+                    ("completed", Some("success" | "neutral" | "skipped")) => CheckState::Success,
+                    ("completed", _) => CheckState::Failure,
+                    _ => CheckState::Pending,
+                }
+            }))
+            .collect::<Vec<_>>();
+
+        let state = if results.is_empty() {
+            CheckState::None
+        } else if results.contains(&CheckState::Failure) {
+            CheckState::Failure
+        } else if results.contains(&CheckState::Pending) {
+            CheckState::Pending
+        } else {
+            CheckState::Success
+        };
+
+        Self {
+            sha,
+            state,
+            statuses,
+            check_runs,
+        }
+    }
+}
+
+/// The deployment status of the repository's GitHub Pages site, see
+/// [`GitHubClient::get_pages_build_status`]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PagesBuildStatus {
+    /// The URL the site is deployed to
+    pub site_url: Option<String>,
+    /// `legacy` if the site is built by GitHub Pages, or `workflow` if it's built with GitHub
+    /// Actions, in which case the Actions run should be checked as well
+    pub build_type: Option<String>,
+    /// The status of the latest build, one of `queued`, `building`, `built`, or `errored`.
+    /// `None` if the site has never been built.
+    pub status: Option<String>,
+    /// The commit the latest build was made from
+    pub commit: Option<String>,
+    /// Why the latest build failed, if it did
+    pub error: Option<String>,
+    /// When the latest build was last updated, as an ISO-8601 string
+    pub updated_at: Option<String>,
+}
+
+impl PagesBuildStatus {
+    fn new(site: PagesResponse, latest_build: Option<PagesBuild>) -> Self {
+        let latest_build = latest_build.unwrap_or_default();
+        Self {
+            site_url: site.html_url,
+            build_type: site.build_type,
+            status: latest_build.status,
+            commit: latest_build.commit,
+            error: latest_build.error.and_then(|e| e.message),
+            updated_at: latest_build.updated_at,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PagesResponse {
+    html_url: Option<String>,
+    build_type: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct PagesBuild {
+    status: Option<String>,
+    commit: Option<String>,
+    error: Option<PagesBuildError>,
+    updated_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PagesBuildError {
+    message: Option<String>,
+}
+
+/// The difference between two refs, see [`GitHubClient::compare`]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    /// One of `ahead`, `behind`, `diverged`, or `identical`, describing `head` relative to `base`
+    pub status: String,
+    pub ahead_by: u64,
+    pub behind_by: u64,
+    /// The number of commits on `head` that aren't on `base`, which may be more than are listed
+    pub total_commits: u64,
+    pub commits: Vec<ComparedCommit>,
+    pub files: Vec<ComparedFile>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ComparedCommit {
+    pub sha: String,
+    pub message: String,
+    pub author_name: Option<String>,
+    /// The GitHub account of the author, if GitHub could match their email to one
+    pub author_login: Option<String>,
+    /// When the commit was authored, as an ISO-8601 string
+    pub date: Option<String>,
+    pub html_url: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ComparedFile {
+    pub filename: String,
+    /// One of `added`, `removed`, `modified`, `renamed`, `copied`, `changed`, or `unchanged`
+    pub status: String,
+    pub additions: u64,
+    pub deletions: u64,
+    pub changes: u64,
+    /// The previous path of a renamed file
+    pub previous_filename: Option<String>,
+}
+
+impl From<CompareResponse> for Comparison {
+    fn from(response: CompareResponse) -> Self {
+        Self {
+            status: response.status,
+            ahead_by: response.ahead_by,
+            behind_by: response.behind_by,
+            total_commits: response.total_commits,
+            commits: response
+                .commits
+                .into_iter()
+                .map(|c| ComparedCommit {
+                    sha: c.sha,
+                    message: c.commit.message,
+                    author_name: c.commit.author.as_ref().map(|a| a.name.clone()),
+                    author_login: c.author.map(|a| a.login),
+                    date: c.commit.author.map(|a| a.date),
+                    html_url: c.html_url,
+                })
+                .collect(),
+            files: response.files,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CompareResponse {
+    status: String,
+    ahead_by: u64,
+    behind_by: u64,
+    total_commits: u64,
+    commits: Vec<CompareCommit>,
+    #[serde(default)]
+    files: Vec<ComparedFile>,
+}
+
+#[derive(Deserialize)]
+struct CompareCommit {
+    sha: String,
+    html_url: String,
+    commit: CompareCommitDetails,
+    author: Option<GitHubUser>,
+}
+
+#[derive(Deserialize)]
+struct CompareCommitDetails {
+    message: String,
+    author: Option<CompareCommitAuthor>,
+}
+
+#[derive(Deserialize)]
+struct CompareCommitAuthor {
+    name: String,
+    date: String,
+}
+
+#[derive(Deserialize)]
+struct CombinedStatusResponse {
+    sha: String,
+    statuses: Vec<CommitStatus>,
+}
+
+#[derive(Deserialize)]
+struct CheckRunsResponse {
+    check_runs: Vec<CheckRun>,
+}
+
 #[derive(Deserialize)]
 struct InstallationIdResponse {
     id: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gh_fixtures::Fixture;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn pull_request_templates() {
+        assert_eq!(
+            fill_pull_request_template(
+                "## Summary\n<!-- hyde-description -->\n\n## Checklist\n- [ ] Tested",
+                "Fixed typos"
+            ),
+            "## Summary\nFixed typos\n\n## Checklist\n- [ ] Tested"
+        );
+        assert_eq!(
+            fill_pull_request_template("## Checklist", "Fixed typos"),
+            "Fixed typos\n\n## Checklist",
+            "fill_pull_request_template: should put the description first without a placeholder"
+        );
+        assert_eq!(
+            fill_pull_request_template("## Checklist", ""),
+            "## Checklist"
+        );
+    }
+
+    #[test]
+    fn check_state() {
+        let status = |state: &str| CommitStatus {
+            context: "ci".to_string(),
+            state: state.to_string(),
+            description: None,
+            target_url: None,
+        };
+        let run = |status: &str, conclusion: Option<&str>| CheckRun {
+            name: "build".to_string(),
+            status: status.to_string(),
+            conclusion: conclusion.map(ToString::to_string),
+            html_url: None,
+        };
+        let state = |statuses, runs| CheckSummary::new(String::new(), statuses, runs).state;
+
+        assert_eq!(state(vec![], vec![]), CheckState::None);
+        assert_eq!(
+            state(
+                vec![status("success")],
+                vec![run("completed", Some("skipped"))]
+            ),
+            CheckState::Success
+        );
+        assert_eq!(
+            state(vec![status("success")], vec![run("in_progress", None)]),
+            CheckState::Pending
+        );
+        assert_eq!(
+            state(
+                vec![status("pending")],
+                vec![run("completed", Some("timed_out"))]
+            ),
+            CheckState::Failure,
+            "CheckSummary::new: a failure should take precedence over pending checks"
+        );
+    }
+
+    #[test]
+    fn pages_build_status() {
+        let site: PagesResponse = serde_json::from_str(
+            r#"{"html_url": "https://wiki.rtech.support/", "build_type": "legacy", "status": "errored"}"#,
+        )
+        .unwrap();
+        let build: PagesBuild = serde_json::from_str(
+            r#"{"status": "errored", "error": {"message": "Page build failed."}, "commit": "abc123",
+                "updated_at": "2025-01-01T00:00:00Z", "duration": 2104}"#,
+        )
+        .unwrap();
+        let status = PagesBuildStatus::new(site, Some(build));
+        assert_eq!(status.error.as_deref(), Some("Page build failed."));
+        assert_eq!(
+            status.site_url.as_deref(),
+            Some("https://wiki.rtech.support/")
+        );
+
+        let never_built = PagesBuildStatus::new(
+            PagesResponse {
+                html_url: None,
+                build_type: None,
+            },
+            None,
+        );
+        assert_eq!(
+            never_built.status, None,
+            "PagesBuildStatus::new: sites that were never built should have no status"
+        );
+    }
+
+    #[tokio::test]
+    async fn replayed_requests() {
+        let fixture = |url: &str, status: u16, body: &str| Fixture {
+            method: "GET".to_string(),
+            url: format!("{GITHUB_API_URL}/repos/foo/bar{url}"),
+            request_body: None,
+            status,
+            headers: BTreeMap::new(),
+            body: body.to_string(),
+        };
+        let client = GitHubClient::new(
+            "https://github.com/foo/bar.git".to_string(),
+            Client::new(),
+            String::new(),
+            None,
+            EncodingKey::from_secret(&[]),
+        )
+        .with_fixtures(Fixtures::replay(vec![
+            fixture(
+                "/branches?per_page=100&page=1",
+                200,
+                r#"[{"name": "master", "protected": true}]"#,
+            ),
+            fixture(
+                "/branches?per_page=100&page=2",
+                200,
+                r#"[{"name": "docs", "protected": false}]"#,
+            ),
+            fixture("/branches?per_page=100&page=3", 200, "[]"),
+            fixture("", 502, "Bad Gateway"),
+        ]));
+
+        let branches = client.list_branches().await.unwrap();
+        assert_eq!(
+            branches.iter().map(|b| b.name.as_str()).collect::<Vec<_>>(),
+            ["master", "docs"],
+            "list_branches: should fetch every page"
+        );
+        let error = client.get_repo_metadata().await.unwrap_err();
+        assert!(
+            error.to_string().contains("502"),
+            "get_repo_metadata: should fail on unsuccessful responses"
+        );
+    }
+
+    #[tokio::test]
+    async fn replayed_dashboard_pages() {
+        let query = |pull_requests_after: Value, first_page: bool| Fixture {
+            method: "POST".to_string(),
+            url: format!("{GITHUB_API_URL}/graphql"),
+            request_body: Some(json!({
+                "query": DASHBOARD_QUERY,
+                "variables": {
+                    "owner": "foo",
+                    "name": "bar",
+                    "refs": first_page,
+                    "refsAfter": null,
+                    "pullRequests": true,
+                    "pullRequestsAfter": pull_requests_after,
+                    "issues": first_page,
+                    "issuesAfter": null,
+                },
+            })),
+            status: 200,
+            headers: BTreeMap::new(),
+            body: String::new(),
+        };
+        let pull_request = |number: u64| json!({ "number": number, "title": "Docs" });
+        let last_page = json!({ "hasNextPage": false, "endCursor": null });
+        let client = GitHubClient::new(
+            "https://github.com/foo/bar.git".to_string(),
+            Client::new(),
+            String::new(),
+            None,
+            EncodingKey::from_secret(&[]),
+        )
+        .with_fixtures(Fixtures::replay(vec![
+            Fixture {
+                body: json!({"data": {"repository": {
+                    "refs": {
+                        "pageInfo": last_page,
+                        "nodes": [{"name": "master", "branchProtectionRule": {"id": "1"}}],
+                    },
+                    "pullRequests": {
+                        "pageInfo": {"hasNextPage": true, "endCursor": "cursor"},
+                        "nodes": (1..=100).map(pull_request).collect::<Vec<_>>(),
+                    },
+                    "issues": {"pageInfo": last_page, "nodes": []},
+                }}})
+                .to_string(),
+                ..query(Value::Null, true)
+            },
+            Fixture {
+                body: json!({"data": {"repository": {
+                    "pullRequests": {"pageInfo": last_page, "nodes": [pull_request(101)]},
+                }}})
+                .to_string(),
+                ..query(json!("cursor"), false)
+            },
+        ]));
+
+        let dashboard = client.get_dashboard_graphql().await.unwrap();
+        assert_eq!(
+            dashboard.pull_requests.len(),
+            101,
+            "get_dashboard: should fetch every page of pull requests"
+        );
+        assert_eq!(dashboard.pull_requests[100].number, 101);
+        assert_eq!(dashboard.branches.len(), 1);
+        assert!(dashboard.branches[0].protected);
+    }
+
+    #[tokio::test]
+    async fn replayed_branch_creation() {
+        let client = GitHubClient::new(
+            "https://github.com/foo/bar.git".to_string(),
+            Client::new(),
+            String::new(),
+            None,
+            EncodingKey::from_secret(&[]),
+        )
+        .with_fixtures(Fixtures::replay(vec![Fixture {
+            method: "POST".to_string(),
+            url: format!("{GITHUB_API_URL}/repos/foo/bar/git/refs"),
+            request_body: Some(json!({"ref": "refs/heads/hyde/foo", "sha": "abc123"})),
+            status: 201,
+            headers: BTreeMap::new(),
+            body: r#"{"ref": "refs/heads/hyde/foo", "object": {"sha": "abc123"}}"#.to_string(),
+        }]));
+
+        client.create_branch("hyde/foo", "abc123").await.unwrap();
+        assert!(
+            client.create_branch("hyde/foo", "def456").await.is_err(),
+            "create_branch: should send the ref name and commit"
+        );
+    }
+
+    #[tokio::test]
+    async fn replayed_comparison() {
+        let client = GitHubClient::new(
+            "https://github.com/foo/bar.git".to_string(),
+            Client::new(),
+            String::new(),
+            None,
+            EncodingKey::from_secret(&[]),
+        )
+        .with_fixtures(Fixtures::replay(vec![Fixture {
+            method: "GET".to_string(),
+            url: format!("{GITHUB_API_URL}/repos/foo/bar/compare/master...feature/docs"),
+            request_body: None,
+            status: 200,
+            headers: BTreeMap::new(),
+            body: json!({
+                "status": "ahead",
+                "ahead_by": 1,
+                "behind_by": 0,
+                "total_commits": 1,
+                "commits": [{
+                    "sha": "abc123",
+                    "html_url": "https://github.com/foo/bar/commit/abc123",
+                    "commit": {
+                        "message": "[Hyde]: Update docs",
+                        "author": {"name": "Hyde", "email": "hyde@localhost", "date": "2025-01-01T00:00:00Z"}
+                    },
+                    "author": null
+                }],
+                "files": [{
+                    "filename": "docs/foo.md",
+                    "status": "modified",
+                    "additions": 2,
+                    "deletions": 1,
+                    "changes": 3
+                }]
+            })
+            .to_string(),
+        }]));
+
+        let comparison = client.compare("master", "feature/docs").await.unwrap();
+        assert_eq!(comparison.commits[0].author_name.as_deref(), Some("Hyde"));
+        assert_eq!(
+            comparison.commits[0].author_login, None,
+            "compare: commits without a matching GitHub account should have no login"
+        );
+        assert_eq!(comparison.files[0].changes, 3);
+    }
+
+    #[test]
+    fn ref_encoding() {
+        assert_eq!(encode_ref("feature/docs").unwrap(), "feature/docs");
+        assert_eq!(encode_ref("v1.0.0").unwrap(), "v1.0.0");
+        assert_eq!(encode_ref("100%#1").unwrap(), "100%25%231");
+        for hostile in [
+            "../../../user",
+            "master?ref=x",
+            "..",
+            "/etc",
+            "a b",
+            "x\\y",
+            "",
+        ] {
+            assert!(
+                encode_ref(hostile).is_err(),
+                "encode_ref: {hostile:?} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn hostile_refs() {
+        // Nothing is recorded, so any request that's sent fails with a different error
+        let client = GitHubClient::new(
+            "https://github.com/foo/bar.git".to_string(),
+            Client::new(),
+            String::new(),
+            None,
+            EncodingKey::from_secret(&[]),
+        )
+        .with_fixtures(Fixtures::replay(Vec::new()));
+
+        let error = client
+            .compare("master", "../../../installation/repositories")
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("isn't a valid branch"),
+            "compare: refs that could reach other API paths should be rejected before sending \
+             anything, got {error}"
+        );
+        let error = client.get_check_runs("main?per_page=1").await.unwrap_err();
+        assert!(
+            error.to_string().contains("isn't a valid branch"),
+            "get_check_runs: refs that could reach other API paths should be rejected before \
+             sending anything, got {error}"
+        );
+    }
+}
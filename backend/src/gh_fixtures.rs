@@ -0,0 +1,262 @@
+//! Recording and replaying GitHub API traffic, see `[oauth.github.fixtures]` in the config.
+//!
+//! In record mode, every request [`GitHubClient`](crate::gh::GitHubClient) makes is sent to
+//! GitHub as usual, and the request and response are saved to a fixtures file. In replay mode,
+//! nothing is sent, and responses are served from that file instead. This makes it possible to
+//! reproduce GitHub related bugs deterministically, to test pagination and error handling
+//! without hitting the API, and to develop offline.
+//!
+//! Authorization headers are never saved, and token-like fields in request and response bodies
+//! are redacted. Installation access token requests aren't recorded at all.
+
+use crate::app_conf::FixtureMode;
+use axum::http;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use fs_err as fs;
+use reqwest::{Client, Request, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+/// JSON fields that are replaced with [`REDACTED`] before fixtures are saved
+const SENSITIVE_FIELDS: &[&str] = &[
+    "token",
+    "access_token",
+    "refresh_token",
+    "client_secret",
+    "private_key",
+];
+pub const REDACTED: &str = "REDACTED";
+/// Response headers that are saved, everything else is dropped
+const RECORDED_HEADERS: &[&str] = &["content-type", "link"];
+
+/// A recorded request and the response GitHub sent back
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    pub method: String,
+    /// The full URL, including the query string
+    pub url: String,
+    /// The JSON body of the request, if it had one
+    pub request_body: Option<Value>,
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct Fixtures {
+    mode: FixtureMode,
+    path: PathBuf,
+    fixtures: Mutex<Vec<Fixture>>,
+    /// Which fixtures have already been replayed
+    replayed: Mutex<Vec<bool>>,
+}
+
+impl Fixtures {
+    /// Open the fixtures file at `path`. In replay mode the file must exist, in record mode it's
+    /// created if it doesn't, and new fixtures are appended to it.
+    ///
+    /// # Errors
+    /// This function returns an error if the file can't be read or parsed.
+    pub fn load(mode: FixtureMode, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let fixtures: Vec<Fixture> = match (mode, path.exists()) {
+            (_, true) => serde_json::from_str(&fs::read_to_string(&path)?)
+                .wrap_err_with(|| format!("Failed to parse GitHub fixtures at {path:?}"))?,
+            (FixtureMode::Record, false) => Vec::new(),
+            (FixtureMode::Replay, false) => bail!("No GitHub fixtures found at {path:?}"),
+        };
+        Ok(Self::new(mode, path, fixtures))
+    }
+
+    /// Replay the provided fixtures, without reading or writing a file
+    #[cfg(test)]
+    pub fn replay(fixtures: Vec<Fixture>) -> Self {
+        Self::new(FixtureMode::Replay, PathBuf::new(), fixtures)
+    }
+
+    fn new(mode: FixtureMode, path: PathBuf, fixtures: Vec<Fixture>) -> Self {
+        Self {
+            mode,
+            path,
+            replayed: Mutex::new(vec![false; fixtures.len()]),
+            fixtures: Mutex::new(fixtures),
+        }
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.mode == FixtureMode::Replay
+    }
+
+    /// Send a request, recording it or replaying a response depending on the mode
+    async fn send(&self, client: &Client, request: Request) -> Result<Response> {
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+        let request_body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .and_then(|b| serde_json::from_slice::<Value>(b).ok())
+            .map(redact);
+
+        if self.is_replay() {
+            let fixture = self.find(&method, &url, request_body.as_ref())?;
+            debug!("Replaying GitHub fixture for {method} {url}");
+            return to_response(&fixture);
+        }
+
+        let response = client.execute(request).await?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| RECORDED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.text().await?;
+        let fixture = Fixture {
+            method,
+            url,
+            request_body,
+            status,
+            headers,
+            body: serde_json::from_str::<Value>(&body)
+                .map_or(body, |json| redact(json).to_string()),
+        };
+        let response = to_response(&fixture)?;
+        self.record(fixture);
+        Ok(response)
+    }
+
+    /// Find the first matching fixture that hasn't been replayed yet. Once every match has been
+    /// replayed, the last one is replayed again.
+    #[allow(clippy::significant_drop_tightening)]
+    fn find(&self, method: &str, url: &str, request_body: Option<&Value>) -> Result<Fixture> {
+        let fixtures = self.fixtures.lock().unwrap();
+        let mut replayed = self.replayed.lock().unwrap();
+        let matches: Vec<usize> = fixtures
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| {
+                f.method == method && f.url == url && f.request_body.as_ref() == request_body
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let Some(&last) = matches.last() else {
+            bail!("No GitHub fixture was recorded for {method} {url}");
+        };
+        let index = matches.into_iter().find(|&i| !replayed[i]).unwrap_or(last);
+        replayed[index] = true;
+        Ok(fixtures[index].clone())
+    }
+
+    #[allow(clippy::significant_drop_tightening)]
+    fn record(&self, fixture: Fixture) {
+        let mut fixtures = self.fixtures.lock().unwrap();
+        fixtures.push(fixture);
+        let saved = serde_json::to_string_pretty(&*fixtures)
+            .map_err(color_eyre::Report::from)
+            .and_then(|json| Ok(fs::write(&self.path, json)?));
+        if let Err(e) = saved {
+            warn!("Failed to save GitHub fixtures to {:?}: {e:?}", self.path);
+        }
+    }
+}
+
+/// Sending requests through [`Fixtures`] if they're enabled
+pub trait SendWithFixtures {
+    /// Like [`RequestBuilder::send`], but recorded or replayed if `fixtures` is set
+    async fn send_with(self, client: &Client, fixtures: Option<&Fixtures>) -> Result<Response>;
+}
+
+impl SendWithFixtures for RequestBuilder {
+    async fn send_with(self, client: &Client, fixtures: Option<&Fixtures>) -> Result<Response> {
+        match fixtures {
+            Some(fixtures) => fixtures.send(client, self.build()?).await,
+            None => Ok(self.send().await?),
+        }
+    }
+}
+
+/// Replace the value of every sensitive field in a JSON value
+fn redact(mut value: Value) -> Value {
+    match &mut value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                *v = if SENSITIVE_FIELDS.contains(&key.as_str()) && !v.is_null() {
+                    Value::String(REDACTED.to_string())
+                } else {
+                    redact(v.take())
+                };
+            }
+        }
+        Value::Array(values) => {
+            for v in values.iter_mut() {
+                *v = redact(v.take());
+            }
+        }
+        _ => {}
+    }
+    value
+}
+
+fn to_response(fixture: &Fixture) -> Result<Response> {
+    let mut builder = http::Response::builder().status(fixture.status);
+    for (name, value) in &fixture.headers {
+        builder = builder.header(name, value);
+    }
+    Ok(Response::from(builder.body(fixture.body.clone())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redaction() {
+        assert_eq!(
+            redact(json!({"token": "ghs_abc", "items": [{"access_token": "x", "name": "y"}]})),
+            json!({"token": REDACTED, "items": [{"access_token": REDACTED, "name": "y"}]})
+        );
+    }
+
+    #[tokio::test]
+    async fn replaying() {
+        let fixture = |body: &str| Fixture {
+            method: "GET".to_string(),
+            url: "https://api.github.com/foo".to_string(),
+            request_body: None,
+            status: 200,
+            headers: BTreeMap::new(),
+            body: body.to_string(),
+        };
+        let fixtures = Fixtures::replay(vec![fixture("1"), fixture("2")]);
+        let client = Client::new();
+        let mut bodies = Vec::new();
+        for _ in 0..3 {
+            let response = client
+                .get("https://api.github.com/foo")
+                .send_with(&client, Some(&fixtures))
+                .await
+                .unwrap();
+            bodies.push(response.text().await.unwrap());
+        }
+        assert_eq!(
+            bodies,
+            ["1", "2", "2"],
+            "send_with: should replay matches in order, then repeat the last one"
+        );
+        assert!(
+            client
+                .get("https://api.github.com/bar")
+                .send_with(&client, Some(&fixtures))
+                .await
+                .is_err(),
+            "send_with: requests without a fixture should fail"
+        );
+    }
+}
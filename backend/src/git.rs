@@ -1,25 +1,82 @@
 //! Abstractions and interfaces over the git repository
 
+use crate::app_conf::Commit;
+use crate::changelog::{commit_author, Changelog, ChangelogEntry};
+use crate::codeowners::CODEOWNERS_PATHS;
+use crate::db::DocMeta;
+use crate::gh::PULL_REQUEST_TEMPLATE_PATHS;
+use crate::plugins::{CommitEvent, PluginRegistry};
+use chrono::{DateTime, NaiveDate, NaiveTime};
 use color_eyre::eyre::{bail, ContextCompat, Result, WrapErr};
 use fs_err as fs;
 use git2::{
-    build::CheckoutBuilder, AnnotatedCommit, BranchType, FetchOptions, IndexAddOption, Oid,
-    Repository, Signature, Status,
+    build::CheckoutBuilder, AnnotatedCommit, BranchType, Delta, DiffOptions, FetchOptions, Index,
+    IndexAddOption, Oid, PushOptions, RemoteCallbacks, Repository, Signature, Sort, Status,
 };
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Display};
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Component, Path};
 use std::{
     path::PathBuf,
     sync::{Arc, Mutex},
 };
 use tracing::{debug, info, warn};
 
+/// Returned by [`Interface::propose_doc`] when the new branch was committed, but not pushed.
+///
+/// This happens on repositories where direct pushes are restricted to the GitHub App, in which
+/// case the branch can be created through the GitHub API instead.
+#[derive(Debug)]
+pub struct PushRejected {
+    /// The commit the branch was created from
+    pub base_commit: String,
+    /// The blob ID of the document on `base_commit`, or `None` if it's a new document
+    pub existing_blob: Option<String>,
+    pub reason: String,
+}
+
+impl Display for PushRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to push the branch to GitHub: {}", self.reason)
+    }
+}
+
+impl std::error::Error for PushRejected {}
+
+/// Returned when a path given to an [`Interface`] can't be used as asked, so that the user can be
+/// told what's wrong rather than getting an internal error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// The path is empty, or leaves the folder it's relative to
+    Invalid(String),
+    /// Nothing exists at the path
+    NotFound(String),
+    /// Something already exists at the path
+    AlreadyExists(String),
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(message) | Self::NotFound(message) | Self::AlreadyExists(message) => {
+                write!(f, "{message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
 /// Interacts with a Jekyll repo's version control and filesystem.
 #[derive(Clone)]
 pub struct Interface {
     repo: Arc<Mutex<Repository>>,
+    /// The folder the repository is cloned into, relative to the server executable.
+    ///
+    /// EG: `./repo`
+    repo_path: PathBuf,
     /// The path to the documents folder, relative to the server executable.
     ///
     /// EG: `./repo/docs`
@@ -28,6 +85,14 @@ pub struct Interface {
     ///
     /// EG: `./repo/assets`
     asset_path: PathBuf,
+    /// The path archived documents are moved into, relative to the documents folder.
+    ///
+    /// EG: `archive/`
+    archive_path: PathBuf,
+    /// The identities commits are made with
+    identities: CommitIdentities,
+    /// Plugins notified of commits and pulls
+    plugins: PluginRegistry,
     /// The remote URL of the repository.
     ///
     /// EG `https://github.com/foo/bar`
@@ -35,13 +100,108 @@ pub struct Interface {
     // TODO: if we move the github token generator here then we can clean up the interface massively
 }
 
+/// Who commits made through an [`Interface`] are attributed to
+#[derive(Debug, Clone)]
+pub struct CommitIdentities {
+    /// Commits made on behalf of users, see `[commit]` in the config
+    pub committer: Commit,
+    /// Commits Hyde makes on its own, like merges when pulling, see `[git]` in the config
+    pub bot: Commit,
+}
+
+/// A file changed by a pull, see [`Interface::pull`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedPath {
+    /// The path of the file, relative to the root of the repository
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
 /// This is used for `get_doc_tree`
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct INode {
     name: String,
-    children: Vec<INode>,
+    children: Vec<Self>,
+    /// Who last edited the document, only set for documents edited through Hyde
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<DocMeta>,
 }
 
+impl INode {
+    /// Returns the paths of every file below this node, relative to this node.
+    pub fn file_paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        for child in &self.children {
+            if child.children.is_empty() {
+                paths.push(child.name.clone());
+            } else {
+                paths.extend(
+                    child
+                        .file_paths()
+                        .into_iter()
+                        .map(|p| format!("{}/{p}", child.name)),
+                );
+            }
+        }
+        paths
+    }
+
+    /// Remove the node at `path` (`/` separated, relative to this node) if there is one, along
+    /// with any folders that are left empty, so they aren't mistaken for files
+    fn remove_path(&mut self, path: &str) {
+        let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+        let Some(index) = self.children.iter().position(|c| c.name == name) else {
+            return;
+        };
+        if !rest.is_empty() {
+            self.children[index].remove_path(rest);
+            if !self.children[index].children.is_empty() {
+                return;
+            }
+        }
+        self.children.remove(index);
+    }
+
+    /// Attach document metadata to the files below this node, keyed by path relative to this node.
+    pub fn attach_meta(&mut self, meta: &HashMap<String, DocMeta>) {
+        fn recurse(node: &mut INode, prefix: &str, meta: &HashMap<String, DocMeta>) {
+            for child in &mut node.children {
+                let path = format!("{prefix}{}", child.name);
+                if child.children.is_empty() {
+                    child.meta = meta.get(&path).cloned();
+                } else {
+                    recurse(child, &format!("{path}/"), meta);
+                }
+            }
+        }
+        recurse(self, "", meta);
+    }
+}
+
+/// A document as it exists in a specific commit, see [`Interface::get_doc_snapshot`].
+#[derive(Debug, Clone)]
+pub struct DocSnapshot {
+    pub contents: String,
+    /// The commit the document was read from
+    pub commit: String,
+    /// The git blob id of the document
+    pub blob: String,
+}
+
+/// The liquid include inserted at the top of archived documents so that the site
+/// renders a banner explaining the page is no longer maintained.
+const ARCHIVE_BANNER: &str = "{% include archived.html %}";
+
+/// The empty file new folders are created with, since git only tracks files
+pub const KEEP_FILE: &str = ".gitkeep";
+
 impl Interface {
     /// Clone the repository into `./repo`, or run `fetch` if an existing repo
     /// was detected
@@ -54,14 +214,22 @@ impl Interface {
         repo_path: String,
         docs_path: String,
         assets_path: String,
+        archive_path: String,
+        identities: CommitIdentities,
+        plugins: PluginRegistry,
     ) -> Result<Self> {
         let doc_path = PathBuf::from(docs_path);
         let asset_path = PathBuf::from(assets_path);
-        let repo = Self::load_repository(&repo_url, &repo_path)?;
+        let archive_path = PathBuf::from(archive_path);
+        let repo = Self::load_repository(&repo_url, &repo_path, &identities.bot.signature()?)?;
         Ok(Self {
             repo: Arc::new(Mutex::new(repo)),
+            repo_path: PathBuf::from(repo_path),
             doc_path,
             asset_path,
+            archive_path,
+            identities,
+            plugins,
             repo_url,
         })
     }
@@ -78,7 +246,9 @@ impl Interface {
     pub fn get_doc<P: AsRef<Path> + std::fmt::Debug>(&self, path: P) -> Result<Option<String>> {
         let mut path_to_doc: PathBuf = PathBuf::from(&self.doc_path);
         path_to_doc.push(path);
-        let doc = Self::get_file(&path_to_doc)?.map(|v| String::from_utf8(v).unwrap());
+        let doc = self
+            .get_file(&path_to_doc)?
+            .map(|v| String::from_utf8(v).unwrap());
         Ok(doc)
     }
 
@@ -95,17 +265,21 @@ impl Interface {
         let mut path_to_asset: PathBuf = PathBuf::from(".");
         path_to_asset.push(&self.asset_path);
         path_to_asset.push(path);
-        let asset = Self::get_file(&path_to_asset)?;
+        let asset = self.get_file(&path_to_asset)?;
         Ok(asset)
     }
 
-    /// Read the document folder into a tree-style structure.
+    /// Read the document folder into a tree-style structure. Archived documents are
+    /// left out unless `include_archived` is set.
     ///
     /// # Errors
     /// This function fails if filesystem ops fail (reading file, reading directory)
     #[tracing::instrument(skip(self))]
-    pub fn get_doc_tree(&self) -> Result<INode> {
-        let doc_tree = Self::get_file_tree(&self.doc_path)?;
+    pub fn get_doc_tree(&self, include_archived: bool) -> Result<INode> {
+        let mut doc_tree = self.get_file_tree(&self.doc_path)?;
+        if !include_archived {
+            doc_tree.remove_path(&to_slash_path(&self.archive_path));
+        }
         Ok(doc_tree)
     }
 
@@ -115,7 +289,7 @@ impl Interface {
     /// This function fails if filesystem ops fail (reading file, reading directory)
     #[tracing::instrument(skip(self))]
     pub fn get_asset_tree(&self) -> Result<INode> {
-        let asset_tree = Self::get_file_tree(&self.asset_path)?;
+        let asset_tree = self.get_file_tree(&self.asset_path)?;
         Ok(asset_tree)
     }
 
@@ -146,17 +320,180 @@ impl Interface {
         let repo = self.repo.lock().unwrap();
         let mut path_to_doc: PathBuf = PathBuf::from(&self.doc_path);
         path_to_doc.push(path.as_ref());
-        Self::put_file(&path_to_doc, new_doc.as_bytes())?;
+        self.put_file(&path_to_doc, new_doc.as_bytes())?;
         let msg = format!("[Hyde]: {message}");
         Self::git_add(&repo, ".")?;
-        let commit_id = Self::git_commit(&repo, msg, None)?;
+        let commit_id =
+            Self::git_commit(&repo, msg, None, &self.identities.committer.signature()?)?;
         debug!("New commit made with ID: {:?}", commit_id);
         Self::git_push(&repo, &self.repo_url, Some(branch), token)?;
+        drop(repo);
         info!(
             "Document {:?} edited, committed to branch '{branch}' and pushed to GitHub with message: {message:?}",
             path.as_ref()
         );
+        self.run_post_commit(commit_id, Some(branch), message, &[&path_to_doc]);
+
+        Ok(())
+    }
+
+    /// Commit the document at `path` to a new branch created from the latest commit on the remote
+    /// `base` branch, and push it. This is all or nothing: if any step fails, the new branch is
+    /// deleted. Either way, the branch that was checked out before is checked out again afterwards.
+    ///
+    /// # Arguments
+    /// - `path` - The path of the document, relative to the documents folder
+    /// - `new_doc` - The new contents of the document
+    /// - `message` - Textual context included with the git commit message
+    /// - `token` - GitHub authentication token
+    /// - `base` - The branch to create the new branch from
+    /// - `branch` - The name of the new branch, which must not exist yet
+    ///
+    /// # Errors
+    /// This function will return an error if the branch already exists, or if any of the
+    /// filesystem or git operations fail. If only the push fails, the error is a
+    /// [`PushRejected`].
+    #[allow(clippy::significant_drop_tightening)]
+    #[tracing::instrument(skip(self, new_doc, token))]
+    pub fn propose_doc(
+        &self,
+        path: &str,
+        new_doc: &str,
+        message: &str,
+        token: &str,
+        base: &str,
+        branch: &str,
+    ) -> Result<()> {
+        let repo = self.repo.lock().unwrap();
+        let previous_head = repo
+            .head()?
+            .name()
+            .wrap_err("HEAD reference name isn't valid UTF-8")?
+            .to_string();
+        if repo.find_branch(branch, BranchType::Local).is_ok() {
+            bail!("Branch {branch:?} already exists");
+        }
+        Self::git_fetch(&repo, Some(base))?;
+        let base_commit = repo
+            .find_reference(&format!("refs/remotes/origin/{base}"))
+            .and_then(|r| r.peel_to_commit())
+            .wrap_err_with(|| format!("Failed to find remote branch {base:?}"))?;
+        repo.branch(branch, &base_commit, false)?;
+
+        let result = (|| -> Result<Oid> {
+            Self::force_checkout(&repo, &format!("refs/heads/{branch}"))?;
+            let mut path_to_doc: PathBuf = PathBuf::from(&self.doc_path);
+            path_to_doc.push(path);
+            self.put_file(&path_to_doc, new_doc.as_bytes())?;
+            Self::git_add(&repo, ".")?;
+            let commit_id = Self::git_commit(
+                &repo,
+                format!("[Hyde]: {message}"),
+                None,
+                &self.identities.committer.signature()?,
+            )?;
+            debug!("New commit made with ID: {:?}", commit_id);
+            Self::push_new_branch(&repo, &self.repo_url, branch, token).map_err(|e| {
+                let existing_blob = base_commit
+                    .tree()
+                    .and_then(|tree| tree.get_path(Path::new(&self.doc_repo_path(path))))
+                    .ok()
+                    .map(|entry| entry.id().to_string());
+                color_eyre::Report::new(PushRejected {
+                    base_commit: base_commit.id().to_string(),
+                    existing_blob,
+                    reason: format!("{e:#}"),
+                })
+            })?;
+            Ok(commit_id)
+        })();
+
+        Self::force_checkout(&repo, &previous_head)?;
+        let commit_id = match result {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                warn!("Failed to propose changes on branch {branch:?}, deleting it: {e:?}");
+                repo.find_branch(branch, BranchType::Local)?.delete()?;
+                return Err(e);
+            }
+        };
+        drop(base_commit);
+        drop(repo);
+        info!("Document {path:?} committed to new branch '{branch}' and pushed to GitHub");
+        self.run_post_commit(
+            commit_id,
+            Some(branch),
+            message,
+            &[&self.doc_path.join(path)],
+        );
+        Ok(())
+    }
 
+    /// Delete `branch` both locally and on the remote, for example to clean up after a failed
+    /// [`Interface::propose_doc`].
+    ///
+    /// # Errors
+    /// This function will return an error if the branch is checked out, or if deleting either
+    /// branch fails.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn delete_branch(&self, branch: &str, token: &str) -> Result<()> {
+        let repo = self.repo.lock().unwrap();
+        let authenticated_url = self
+            .repo_url
+            .replace("https://", &format!("https://x-access-token:{token}@"));
+        repo.remote_set_pushurl("origin", Some(&authenticated_url))?;
+        let mut remote = repo.find_remote("origin")?;
+        remote.push(&[&format!(":refs/heads/{branch}")], None)?;
+        repo.find_branch(branch, BranchType::Local)?.delete()?;
+        info!("Deleted branch '{branch}' locally and on GitHub");
+        Ok(())
+    }
+
+    /// Push a newly created `branch`. Unlike [`Interface::git_push`], this returns an error if
+    /// GitHub rejects the branch, not just if the push itself fails.
+    fn push_new_branch(repo: &Repository, repo_url: &str, branch: &str, token: &str) -> Result<()> {
+        let authenticated_url =
+            repo_url.replace("https://", &format!("https://x-access-token:{token}@"));
+        repo.remote_set_pushurl("origin", Some(&authenticated_url))?;
+        let mut remote = repo.find_remote("origin")?;
+
+        let mut rejection = None;
+        {
+            let mut callbacks = RemoteCallbacks::new();
+            callbacks.push_update_reference(|_, status| {
+                rejection = status.map(ToString::to_string);
+                Ok(())
+            });
+            let mut options = PushOptions::new();
+            options.remote_callbacks(callbacks);
+            remote.push(
+                &[&format!("refs/heads/{branch}:refs/heads/{branch}")],
+                Some(&mut options),
+            )?;
+        }
+        if let Some(reason) = rejection {
+            bail!("GitHub rejected branch {branch:?}: {reason}");
+        }
+        Ok(())
+    }
+
+    /// Check out `reference` (e.g. `refs/heads/master`), discarding uncommitted changes
+    fn force_checkout(repo: &Repository, reference: &str) -> Result<()> {
+        repo.set_head(reference)?;
+        repo.checkout_head(Some(CheckoutBuilder::default().force()))?;
+        Ok(())
+    }
+
+    /// Reset the checked out branch, the index and the working tree to `commit`, removing any files
+    /// added since. Undoes changes that failed partway through, like a commit that couldn't be
+    /// pushed, so that they don't end up in the next commit.
+    fn discard_changes(repo: &Repository, commit: Oid) -> Result<()> {
+        let commit = repo.find_commit(commit)?;
+        repo.reset(
+            commit.as_object(),
+            git2::ResetType::Hard,
+            Some(CheckoutBuilder::default().force().remove_untracked(true)),
+        )?;
         Ok(())
     }
 
@@ -192,16 +529,19 @@ impl Interface {
         let repo = self.repo.lock().unwrap();
         let mut path_to_asset: PathBuf = PathBuf::from(&self.asset_path);
         path_to_asset.push(path.as_ref());
-        Self::put_file(&path_to_asset, contents)?;
+        self.put_file(&path_to_asset, contents)?;
         let msg = format!("[Hyde]: {message}");
         Self::git_add(&repo, ".")?;
-        let commit_id = Self::git_commit(&repo, msg, None)?;
+        let commit_id =
+            Self::git_commit(&repo, msg, None, &self.identities.committer.signature()?)?;
         debug!("New commit made with ID: {:?}", commit_id);
         Self::git_push(&repo, &self.repo_url, None, token)?;
+        drop(repo);
         info!(
             "Asset {:?} edited and pushed to GitHub with message: {message:?}",
             path.as_ref()
         );
+        self.run_post_commit(commit_id, None, message, &[&path_to_asset]);
         debug!("Commit cleanup completed");
         Ok(())
     }
@@ -229,9 +569,10 @@ impl Interface {
         let mut path_to_doc: PathBuf = PathBuf::from(&self.doc_path);
         path_to_doc.push(path);
         let msg = format!("[Hyde]: {message}");
-        Self::delete_file(&path_to_doc)?;
+        self.delete_file(&path_to_doc)?;
         Self::git_add(&repo, ".")?;
-        let commit_id = Self::git_commit(&repo, msg, None)?;
+        let commit_id =
+            Self::git_commit(&repo, msg, None, &self.identities.committer.signature()?)?;
         debug!("New commit made with ID: {:?}", commit_id);
         Self::git_push(&repo, &self.repo_url, None, token)?;
         drop(repo);
@@ -239,6 +580,7 @@ impl Interface {
             "Document {:?} removed and changes synced to Github with message: {message:?}",
             path.as_ref()
         );
+        self.run_post_commit(commit_id, None, message, &[&path_to_doc]);
         debug!("Commit cleanup completed");
         Ok(())
     }
@@ -267,9 +609,10 @@ impl Interface {
         path_to_asset.push(path);
         let msg = format!("[Hyde]: {message}");
         // Standard practice is to stage commits by adding them to an index.
-        Self::delete_file(&path_to_asset)?;
+        self.delete_file(&path_to_asset)?;
         Self::git_add(&repo, ".")?;
-        let commit_id = Self::git_commit(&repo, msg, None)?;
+        let commit_id =
+            Self::git_commit(&repo, msg, None, &self.identities.committer.signature()?)?;
         debug!("New commit made with ID: {:?}", commit_id);
         Self::git_push(&repo, &self.repo_url, None, token)?;
         drop(repo);
@@ -277,17 +620,738 @@ impl Interface {
             "Asset {:?} removed and changes synced to Github with message: {message:?}",
             path.as_ref()
         );
+        self.run_post_commit(commit_id, None, message, &[&path_to_asset]);
         debug!("Commit cleanup completed");
         Ok(())
     }
 
+    /// Create an empty folder at `path` (relative to the documents folder), so documents can be
+    /// added to it later. Git doesn't track empty folders, so it's committed with an empty
+    /// [`KEEP_FILE`] in it, and pushed.
+    /// `message` will be included in the commit message, and `token` is a valid github auth token.
+    ///
+    /// # Panics
+    /// This function will panic if it's called when the repo mutex is already held by the current
+    /// thread.
+    ///
+    /// # Errors
+    /// This function will return an error if `path` is empty, leaves the documents folder, or
+    /// already exists, if filesystem operations fail, or if any of the git operations fail.
+    pub fn create_doc_dir<P: AsRef<Path> + Copy + Debug>(
+        &self,
+        path: P,
+        message: &str,
+        token: &str,
+    ) -> Result<()> {
+        self.create_dir(&self.doc_path, path.as_ref(), message, token)
+    }
+
+    /// Like [`Interface::create_doc_dir`], for a folder in the assets folder
+    ///
+    /// # Panics
+    /// This function will panic if it's called when the repo mutex is already held by the current
+    /// thread.
+    ///
+    /// # Errors
+    /// This function will return an error if `path` is empty, leaves the assets folder, or
+    /// already exists, if filesystem operations fail, or if any of the git operations fail.
+    pub fn create_asset_dir<P: AsRef<Path> + Copy + Debug>(
+        &self,
+        path: P,
+        message: &str,
+        token: &str,
+    ) -> Result<()> {
+        self.create_dir(&self.asset_path, path.as_ref(), message, token)
+    }
+
+    /// Returns the paths (relative to the documents folder) of every file in the folder at `path`
+    /// (relative to the documents folder), or `None` if there's no folder there.
+    ///
+    /// # Errors
+    /// This function will return an error if `path` is empty or leaves the documents folder, or if
+    /// filesystem operations fail.
+    pub fn get_doc_dir_files<P: AsRef<Path> + Copy + Debug>(
+        &self,
+        path: P,
+    ) -> Result<Option<Vec<String>>> {
+        self.get_dir_files(&self.doc_path, path.as_ref())
+    }
+
+    /// Like [`Interface::get_doc_dir_files`], for `path` relative to `root`
+    fn get_dir_files(&self, root: &Path, path: &Path) -> Result<Option<Vec<String>>> {
+        check_subdir(root, path)?;
+        let path_to_dir = root.join(path);
+        if !repo_fs_path(&self.repo_path, &path_to_dir)?.is_dir() {
+            return Ok(None);
+        }
+        let prefix = to_slash_path(path);
+        let mut files: Vec<String> = self
+            .get_file_tree(&path_to_dir)?
+            .file_paths()
+            .into_iter()
+            .map(|file| format!("{prefix}/{file}"))
+            .collect();
+        files.sort();
+        Ok(Some(files))
+    }
+
+    /// Delete the folder at `path` (relative to the documents folder) and everything in it, in a
+    /// single commit. Returns the paths of the deleted files, relative to the documents folder.
+    /// `message` will be included in the commit message, and `token` is a valid github auth token.
+    ///
+    /// # Panics
+    /// This function will panic if it's called when the repo mutex is already held by the current
+    /// thread.
+    ///
+    /// # Errors
+    /// This function will return an error if there's no folder at `path`, if `path` is empty or
+    /// leaves the documents folder, if filesystem operations fail, or if any of the git
+    /// operations fail. Changes are discarded if committing or pushing them fails.
+    #[allow(clippy::significant_drop_tightening)]
+    #[tracing::instrument(skip(self, token))]
+    pub fn delete_doc_dir<P: AsRef<Path> + Copy + Debug>(
+        &self,
+        path: P,
+        message: &str,
+        token: &str,
+    ) -> Result<Vec<String>> {
+        let repo = self.repo.lock().unwrap();
+        let files = self
+            .get_doc_dir_files(path)?
+            .ok_or_else(|| PathError::NotFound(format!("No folder exists at {path:?}")))?;
+        let path_to_dir = self.doc_path.join(path);
+        let head = Self::find_last_commit(&repo)?.id();
+
+        let result = (|| -> Result<Oid> {
+            fs::remove_dir_all(repo_fs_path(&self.repo_path, &path_to_dir)?)
+                .wrap_err_with(|| format!("Failed to remove the folder at {path_to_dir:?}"))?;
+            Self::git_add(&repo, ".")?;
+            let commit_id = Self::git_commit(
+                &repo,
+                format!("[Hyde]: {message}"),
+                None,
+                &self.identities.committer.signature()?,
+            )?;
+            debug!("New commit made with ID: {:?}", commit_id);
+            Self::git_push(&repo, &self.repo_url, None, token)?;
+            Ok(commit_id)
+        })();
+        let commit_id = match result {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                warn!("Failed to delete the folder {path_to_dir:?}, discarding changes: {e:?}");
+                Self::discard_changes(&repo, head)?;
+                return Err(e);
+            }
+        };
+        drop(repo);
+        info!(
+            "Folder {path_to_dir:?} ({} files) removed and changes synced to Github with message: {message:?}",
+            files.len()
+        );
+        let deleted: Vec<PathBuf> = files.iter().map(|f| self.doc_path.join(f)).collect();
+        self.run_post_commit(
+            commit_id,
+            None,
+            message,
+            &deleted.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+        );
+        Ok(files)
+    }
+
+    /// Rename or move the folder at `from` (relative to the documents folder) to `to`, along with
+    /// everything in it, in a single commit. The files' contents don't change, so git sees them
+    /// as renamed and their history follows them. Returns the old and new paths of every moved
+    /// file, relative to the documents folder.
+    /// `message` will be included in the commit message, and `token` is a valid github auth token.
+    ///
+    /// # Panics
+    /// This function will panic if it's called when the repo mutex is already held by the current
+    /// thread.
+    ///
+    /// # Errors
+    /// This function will return an error if there's no folder at `from`, if something already
+    /// exists at `to`, if `to` is inside `from`, if either leaves the documents folder, if
+    /// filesystem operations fail, or if any of the git operations fail.
+    pub fn move_doc_dir<P: AsRef<Path> + Copy + Debug>(
+        &self,
+        from: P,
+        to: P,
+        message: &str,
+        token: &str,
+    ) -> Result<Vec<(String, String)>> {
+        self.move_dir(&self.doc_path, from.as_ref(), to.as_ref(), message, token)
+    }
+
+    /// Like [`Interface::move_doc_dir`], for a folder in the assets folder
+    ///
+    /// # Panics
+    /// This function will panic if it's called when the repo mutex is already held by the current
+    /// thread.
+    ///
+    /// # Errors
+    /// This function will return an error if there's no folder at `from`, if something already
+    /// exists at `to`, if `to` is inside `from`, if either leaves the assets folder, if
+    /// filesystem operations fail, or if any of the git operations fail.
+    pub fn move_asset_dir<P: AsRef<Path> + Copy + Debug>(
+        &self,
+        from: P,
+        to: P,
+        message: &str,
+        token: &str,
+    ) -> Result<Vec<(String, String)>> {
+        self.move_dir(&self.asset_path, from.as_ref(), to.as_ref(), message, token)
+    }
+
+    #[allow(clippy::significant_drop_tightening)]
+    #[tracing::instrument(skip(self, token))]
+    fn move_dir(
+        &self,
+        root: &Path,
+        from: &Path,
+        to: &Path,
+        message: &str,
+        token: &str,
+    ) -> Result<Vec<(String, String)>> {
+        check_subdir(root, to)?;
+        if Path::new(&to_slash_path(to)).starts_with(to_slash_path(from)) {
+            return Err(PathError::Invalid(format!(
+                "The folder {from:?} can't be moved into itself"
+            ))
+            .into());
+        }
+        let repo = self.repo.lock().unwrap();
+        let files = self
+            .get_dir_files(root, from)?
+            .ok_or_else(|| PathError::NotFound(format!("No folder exists at {from:?}")))?;
+        let fs_from = repo_fs_path(&self.repo_path, root.join(from))?;
+        let fs_to = repo_fs_path(&self.repo_path, root.join(to))?;
+        if fs_to.exists() {
+            return Err(PathError::AlreadyExists(format!(
+                "There's already a file or folder at {to:?}"
+            ))
+            .into());
+        }
+        let head = Self::find_last_commit(&repo)?.id();
+
+        let result = (|| -> Result<Oid> {
+            if let Some(parent) = fs_to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&fs_from, &fs_to)
+                .wrap_err_with(|| format!("Failed to move the folder at {from:?} to {to:?}"))?;
+            // Staging both the removals and the additions lets git pair them up as renames
+            Self::git_add(&repo, ".")?;
+            let commit_id = Self::git_commit(
+                &repo,
+                format!("[Hyde]: {message}"),
+                None,
+                &self.identities.committer.signature()?,
+            )?;
+            debug!("New commit made with ID: {:?}", commit_id);
+            Self::git_push(&repo, &self.repo_url, None, token)?;
+            Ok(commit_id)
+        })();
+        let commit_id = match result {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                warn!("Failed to move the folder {from:?} to {to:?}, discarding changes: {e:?}");
+                Self::discard_changes(&repo, head)?;
+                return Err(e);
+            }
+        };
+        drop(repo);
+        let (from, to) = (to_slash_path(from), to_slash_path(to));
+        let moved: Vec<(String, String)> = files
+            .into_iter()
+            .map(|file| {
+                let new_path = format!("{to}{}", &file[from.len()..]);
+                (file, new_path)
+            })
+            .collect();
+        info!(
+            "Folder {from:?} ({} files) moved to {to:?} and changes synced to Github with message: {message:?}",
+            moved.len()
+        );
+        let changed: Vec<PathBuf> = moved
+            .iter()
+            .flat_map(|(old, new)| [root.join(old), root.join(new)])
+            .collect();
+        self.run_post_commit(
+            commit_id,
+            None,
+            message,
+            &changed.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+        );
+        Ok(moved)
+    }
+
+    #[allow(clippy::significant_drop_tightening)]
+    #[tracing::instrument(skip(self, token))]
+    fn create_dir(&self, root: &Path, path: &Path, message: &str, token: &str) -> Result<()> {
+        check_subdir(root, path)?;
+        let repo = self.repo.lock().unwrap();
+        let path_to_dir = root.join(path);
+        let fs_path = repo_fs_path(&self.repo_path, &path_to_dir)?;
+        if fs_path.exists() {
+            return Err(PathError::AlreadyExists(format!(
+                "There's already a file or folder at {path:?}"
+            ))
+            .into());
+        }
+        let path_to_keep_file = path_to_dir.join(KEEP_FILE);
+        let head = Self::find_last_commit(&repo)?.id();
+
+        let result = (|| -> Result<Oid> {
+            fs::create_dir_all(&fs_path)?;
+            self.put_file(&path_to_keep_file, &[])?;
+            Self::git_add(&repo, ".")?;
+            let commit_id = Self::git_commit(
+                &repo,
+                format!("[Hyde]: {message}"),
+                None,
+                &self.identities.committer.signature()?,
+            )?;
+            debug!("New commit made with ID: {:?}", commit_id);
+            Self::git_push(&repo, &self.repo_url, None, token)?;
+            Ok(commit_id)
+        })();
+        let commit_id = match result {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                warn!("Failed to create the folder {path_to_dir:?}, discarding changes: {e:?}");
+                Self::discard_changes(&repo, head)?;
+                return Err(e);
+            }
+        };
+        drop(repo);
+        info!(
+            "Folder {path_to_dir:?} created and changes synced to Github with message: {message:?}"
+        );
+        self.run_post_commit(commit_id, None, message, &[&path_to_keep_file]);
+        Ok(())
+    }
+
+    /// Move the document at the specified `path` into the archive folder, marking it with
+    /// `archived: true` front matter and a banner include. Returns the new location of
+    /// the document, relative to the root of the documents folder.
+    /// `message` will be included in the commit message, and `token` is a valid github auth token.
+    ///
+    /// # Panics
+    /// This function will panic if it's called when the repo mutex is already held by the current
+    /// thread.
+    ///
+    /// # Errors
+    /// This function will return an error if the document doesn't exist or is already archived,
+    /// if a document was already archived at the same path, if `path` leaves the documents
+    /// folder, if filesystem operations fail, or if any of the git operations fail. Changes are
+    /// discarded if committing or pushing them fails.
+    #[allow(clippy::significant_drop_tightening)]
+    #[tracing::instrument(skip(self, token))]
+    pub fn archive_doc<P: AsRef<Path> + Copy + Debug>(
+        &self,
+        path: P,
+        message: &str,
+        token: &str,
+    ) -> Result<PathBuf> {
+        check_subdir(&self.doc_path, path.as_ref())?;
+        if Path::new(&to_slash_path(path)).starts_with(to_slash_path(&self.archive_path)) {
+            return Err(PathError::Invalid(format!(
+                "The document at {path:?} is already archived"
+            ))
+            .into());
+        }
+        let repo = self.repo.lock().unwrap();
+        let mut path_to_doc: PathBuf = PathBuf::from(&self.doc_path);
+        path_to_doc.push(path);
+        let doc = self
+            .get_file(&path_to_doc)?
+            .ok_or_else(|| PathError::NotFound(format!("No document exists at {path:?}")))?;
+        let doc = String::from_utf8(doc).wrap_err("Document is not valid UTF-8")?;
+
+        let mut archived_path = self.archive_path.clone();
+        archived_path.push(path);
+        check_subdir(&self.doc_path, &archived_path)?;
+        let mut path_to_archived_doc = PathBuf::from(&self.doc_path);
+        path_to_archived_doc.push(&archived_path);
+        if repo_fs_path(&self.repo_path, &path_to_archived_doc)?.exists() {
+            return Err(PathError::AlreadyExists(format!(
+                "A document was already archived at {archived_path:?}, rename one of them first"
+            ))
+            .into());
+        }
+        let head = Self::find_last_commit(&repo)?.id();
+
+        let result = (|| -> Result<Oid> {
+            if let Some(parent) = path_to_archived_doc.parent() {
+                fs::create_dir_all(repo_fs_path(&self.repo_path, parent)?)?;
+            }
+            self.put_file(&path_to_archived_doc, mark_archived(&doc).as_bytes())?;
+            self.delete_file(&path_to_doc)?;
+            Self::git_add(&repo, ".")?;
+            let commit_id = Self::git_commit(
+                &repo,
+                format!("[Hyde]: {message}"),
+                None,
+                &self.identities.committer.signature()?,
+            )?;
+            debug!("New commit made with ID: {:?}", commit_id);
+            Self::git_push(&repo, &self.repo_url, None, token)?;
+            Ok(commit_id)
+        })();
+        let commit_id = match result {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                warn!("Failed to archive the document {path:?}, discarding changes: {e:?}");
+                Self::discard_changes(&repo, head)?;
+                return Err(e);
+            }
+        };
+        drop(repo);
+        info!(
+            "Document {:?} archived to {archived_path:?} and changes synced to Github with message: {message:?}",
+            path.as_ref()
+        );
+        self.run_post_commit(
+            commit_id,
+            None,
+            message,
+            &[&path_to_doc, &path_to_archived_doc],
+        );
+        Ok(archived_path)
+    }
+
+    /// Read the repository's `CODEOWNERS` file from whichever location GitHub would use,
+    /// returning `None` if the repository doesn't have one.
+    ///
+    /// # Errors
+    /// This function will return an error if filesystem operations fail.
+    pub fn get_codeowners(&self) -> Result<Option<String>> {
+        for path in CODEOWNERS_PATHS {
+            if let Some(file) = self.get_file(path)? {
+                return Ok(Some(
+                    String::from_utf8(file).wrap_err("CODEOWNERS is not valid UTF-8")?,
+                ));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the repository's pull request template from whichever location GitHub would use,
+    /// returning `None` if the repository doesn't have one.
+    ///
+    /// # Errors
+    /// This function will return an error if filesystem operations fail.
+    pub fn get_pull_request_template(&self) -> Result<Option<String>> {
+        for path in PULL_REQUEST_TEMPLATE_PATHS {
+            if let Some(file) = self.get_file(path)? {
+                return Ok(Some(
+                    String::from_utf8(file)
+                        .wrap_err("The pull request template is not valid UTF-8")?,
+                ));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the paths (relative to the root of the repo) of every file changed on `head`
+    /// since it diverged from `base`, similar to `git diff base...head --name-only`.
+    ///
+    /// Branches are looked up locally first, then on `origin`.
+    ///
+    /// # Errors
+    /// This function will return an error if either branch can't be found, or the diff fails.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn changed_files(&self, base: &str, head: &str) -> Result<Vec<String>> {
+        let repo = self.repo.lock().unwrap();
+        let base_commit = Self::find_branch_commit(&repo, base)?;
+        let head_commit = Self::find_branch_commit(&repo, head)?;
+        let ancestor = repo.find_commit(repo.merge_base(base_commit.id(), head_commit.id())?)?;
+        let diff =
+            repo.diff_tree_to_tree(Some(&ancestor.tree()?), Some(&head_commit.tree()?), None)?;
+        let paths = diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        Ok(paths)
+    }
+
+    /// The latest commit on `branch`, which is looked up locally first, then on `origin`
+    fn find_branch_commit<'a>(repo: &'a Repository, branch: &str) -> Result<git2::Commit<'a>> {
+        repo.find_reference(&format!("refs/heads/{branch}"))
+            .or_else(|_| repo.find_reference(&format!("refs/remotes/origin/{branch}")))
+            .and_then(|r| r.peel_to_commit())
+            .wrap_err_with(|| format!("Failed to find branch {branch:?}"))
+    }
+
+    /// Read the document at `path` (relative to the documents folder) from the latest commit on
+    /// `branch`, returning `None` if it doesn't exist.
+    ///
+    /// # Errors
+    /// This function will return an error if the branch doesn't exist, any of the git
+    /// operations fail, or if the document isn't valid UTF-8.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn get_doc_snapshot<P: AsRef<Path> + Debug>(
+        &self,
+        path: P,
+        branch: &str,
+    ) -> Result<Option<DocSnapshot>> {
+        let repo = self.repo.lock().unwrap();
+        let path_in_repo = self.doc_path.join(path);
+        let commit = Self::find_branch_commit(&repo, branch)?;
+        let Ok(entry) = commit.tree()?.get_path(&path_in_repo) else {
+            return Ok(None);
+        };
+        let blob = repo.find_blob(entry.id())?;
+        Ok(Some(DocSnapshot {
+            contents: String::from_utf8(blob.content().to_vec())
+                .wrap_err("Document is not valid UTF-8")?,
+            commit: commit.id().to_string(),
+            blob: blob.id().to_string(),
+        }))
+    }
+
+    /// Replay an edit to the document at `path` that was made against `base_commit` on top
+    /// of the latest commit on `branch`, returning the contents that should be saved.
+    ///
+    /// If the document hasn't changed on `branch` since `base_commit`, `contents` is returned
+    /// as is. Otherwise a three way merge is attempted, and `None` is returned if the changes
+    /// conflict.
+    ///
+    /// # Errors
+    /// This function will return an error if `branch` doesn't exist, any of the git operations
+    /// fail, or if the document didn't exist in `base_commit`.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn rebase_doc_edit<P: AsRef<Path> + Debug>(
+        &self,
+        path: P,
+        base_commit: &str,
+        contents: &str,
+        branch: &str,
+    ) -> Result<Option<String>> {
+        let repo = self.repo.lock().unwrap();
+        let path_in_repo = self.doc_path.join(path);
+        let base_tree = repo.find_commit(Oid::from_str(base_commit)?)?.tree()?;
+        let current_tree = Self::find_branch_commit(&repo, branch)?.tree()?;
+        let base_blob = base_tree.get_path(&path_in_repo)?.id();
+        let current_blob = current_tree.get_path(&path_in_repo).ok().map(|e| e.id());
+        if current_blob == Some(base_blob) {
+            return Ok(Some(contents.to_string()));
+        }
+
+        info!("{path_in_repo:?} changed since {base_commit}, attempting to merge the edit");
+        // Build the tree the client would have committed if they'd been online
+        let mut index = Index::new()?;
+        index.read_tree(&base_tree)?;
+        let mut entry = index
+            .get_path(&path_in_repo, 0)
+            .wrap_err("Document is missing from the base commit's index")?;
+        entry.id = repo.blob(contents.as_bytes())?;
+        entry.file_size = u32::try_from(contents.len())?;
+        index.add(&entry)?;
+        let edited_tree = repo.find_tree(index.write_tree_to(&repo)?)?;
+
+        let merged = repo.merge_trees(&base_tree, &current_tree, &edited_tree, None)?;
+        if merged.has_conflicts() {
+            return Ok(None);
+        }
+        let merged_entry = merged
+            .get_path(&path_in_repo, 0)
+            .wrap_err("Document is missing from the merged index")?;
+        let merged_blob = repo.find_blob(merged_entry.id)?;
+        Ok(Some(
+            String::from_utf8(merged_blob.content().to_vec())
+                .wrap_err("Merged document is not valid UTF-8")?,
+        ))
+    }
+
+    /// Summarize the documents added, changed, and removed after `from` up to and including `to`
+    /// (or `HEAD` if `to` isn't provided).
+    ///
+    /// `from` and `to` can be anything git understands as a revision (a commit hash, tag,
+    /// or branch), or a date in the format `YYYY-MM-DD` or RFC-3339.
+    ///
+    /// # Errors
+    /// This function will return an error if a revision can't be resolved, or if any of the git
+    /// operations fail.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn changelog(&self, from: &str, to: Option<&str>) -> Result<Changelog> {
+        let repo = self.repo.lock().unwrap();
+        let from_commit = Self::resolve_revision(&repo, from, false)?;
+        let to_commit = match to {
+            Some(to) => Self::resolve_revision(&repo, to, true)?
+                .wrap_err_with(|| format!("No commits exist before {to:?}"))?,
+            None => Self::find_last_commit(&repo)?,
+        };
+        let doc_diff = |old: Option<&git2::Tree>, new: &git2::Tree| {
+            let mut diff_options = DiffOptions::new();
+            diff_options.pathspec(&self.doc_path);
+            repo.diff_tree_to_tree(old, Some(new), Some(&mut diff_options))
+        };
+        let relative_path = |delta: &git2::DiffDelta| {
+            delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(|p| {
+                    p.strip_prefix(&self.doc_path)
+                        .unwrap_or(p)
+                        .to_string_lossy()
+                        .to_string()
+                })
+        };
+
+        // Walk every commit in the range to find who touched each document
+        let mut authors: HashMap<String, Vec<String>> = HashMap::new();
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(to_commit.id())?;
+        if let Some(from_commit) = &from_commit {
+            revwalk.hide(from_commit.id())?;
+        }
+        revwalk.set_sorting(Sort::TIME | Sort::REVERSE)?;
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            // Changes brought in by merges are attributed to the commits being merged
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            let parent_tree = commit.parents().next().map(|p| p.tree()).transpose()?;
+            let author = commit_author(
+                commit.author().name().unwrap_or_default(),
+                commit.message().unwrap_or_default(),
+            );
+            for delta in doc_diff(parent_tree.as_ref(), &commit.tree()?)?.deltas() {
+                if let Some(path) = relative_path(&delta) {
+                    let path_authors = authors.entry(path).or_default();
+                    if !path_authors.contains(&author) {
+                        path_authors.push(author.clone());
+                    }
+                }
+            }
+        }
+
+        // Then compare both ends of the range to find the overall effect on each document
+        let from_tree = from_commit.as_ref().map(git2::Commit::tree).transpose()?;
+        let mut changelog = Changelog {
+            from: from_commit
+                .as_ref()
+                .map(|c| c.id().to_string())
+                .unwrap_or_default(),
+            to: to_commit.id().to_string(),
+            ..Default::default()
+        };
+        for delta in doc_diff(from_tree.as_ref(), &to_commit.tree()?)?.deltas() {
+            let Some(path) = relative_path(&delta) else {
+                continue;
+            };
+            let entry = ChangelogEntry {
+                authors: authors.remove(&path).unwrap_or_default(),
+                path,
+            };
+            match delta.status() {
+                Delta::Added => changelog.added.push(entry),
+                Delta::Deleted => changelog.removed.push(entry),
+                _ => changelog.changed.push(entry),
+            }
+        }
+        Ok(changelog)
+    }
+
+    /// When each of the documents at `paths` (relative to the documents folder) last changed, as
+    /// the Unix timestamp of the latest commit at `HEAD` changing it. Documents without such a
+    /// commit are left out.
+    ///
+    /// # Errors
+    /// This function will return an error if any of the git operations fail.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn docs_last_changed(&self, paths: &[String]) -> Result<HashMap<String, i64>> {
+        let repo = self.repo.lock().unwrap();
+        let wanted: HashSet<&str> = paths.iter().map(String::as_str).collect();
+        let mut last_changed = HashMap::new();
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push_head()?;
+        revwalk.set_sorting(Sort::TIME)?;
+        for oid in revwalk {
+            if last_changed.len() == wanted.len() {
+                break;
+            }
+            let commit = repo.find_commit(oid?)?;
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            let parent_tree = commit.parents().next().map(|p| p.tree()).transpose()?;
+            let mut diff_options = DiffOptions::new();
+            diff_options.pathspec(&self.doc_path);
+            let diff = repo.diff_tree_to_tree(
+                parent_tree.as_ref(),
+                Some(&commit.tree()?),
+                Some(&mut diff_options),
+            )?;
+            for delta in diff.deltas() {
+                let Some(path) = delta.new_file().path() else {
+                    continue;
+                };
+                let path = to_slash_path(path.strip_prefix(&self.doc_path).unwrap_or(path));
+                if wanted.contains(path.as_str()) {
+                    last_changed
+                        .entry(path)
+                        .or_insert_with(|| commit.time().seconds());
+                }
+            }
+        }
+        Ok(last_changed)
+    }
+
+    /// Resolve a revision (commit hash, tag, branch) or a date into a commit. For dates, the
+    /// latest commit at or before the start of that day (or the end if `end_of_day` is set) is
+    /// returned, and `None` if there are no commits before then.
+    fn resolve_revision<'a>(
+        repo: &'a Repository,
+        revision: &str,
+        end_of_day: bool,
+    ) -> Result<Option<git2::Commit<'a>>> {
+        if let Ok(object) = repo.revparse_single(revision) {
+            return Ok(Some(object.peel_to_commit()?));
+        }
+        let timestamp = if let Ok(date) = DateTime::parse_from_rfc3339(revision) {
+            date.timestamp()
+        } else if let Ok(date) = NaiveDate::parse_from_str(revision, "%Y-%m-%d") {
+            let time = if end_of_day {
+                NaiveTime::from_hms_opt(23, 59, 59)
+            } else {
+                NaiveTime::from_hms_opt(0, 0, 0)
+            };
+            date.and_time(time.unwrap_or_default())
+                .and_utc()
+                .timestamp()
+        } else {
+            bail!("{revision:?} is not a valid revision or date");
+        };
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push_head()?;
+        revwalk.set_sorting(Sort::TIME)?;
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            if commit.time().seconds() <= timestamp {
+                return Ok(Some(commit));
+            }
+        }
+        Ok(None)
+    }
+
     /// If the repository at the provided path exists, open it and fetch the latest changes from the `master` branch.
     /// If not, clone into the provided path.
-    #[tracing::instrument]
-    fn load_repository(repo_url: &str, repo_path: &str) -> Result<Repository> {
+    #[tracing::instrument(skip(committer))]
+    fn load_repository(
+        repo_url: &str,
+        repo_path: &str,
+        committer: &Signature,
+    ) -> Result<Repository> {
         if let Ok(repo) = Repository::open(repo_path) {
             info!("Existing repository detected, fetching latest changes");
-            Self::git_pull(&repo)?;
+            Self::git_pull(&repo, committer)?;
             return Ok(repo);
         }
 
@@ -306,8 +1370,10 @@ impl Interface {
     pub fn reclone(&self) -> Result<()> {
         // First clone a repo into `repo__tmp`, open that, swap out
         // TODO: nuke `repo__tmp` if it exists already
-        let repo_path = Path::new("./repo"); // TODO: Possibly implement this path into new config?
-        let tmp_path = Path::new("./repo__tmp"); // TODO: Same here?
+        let repo_path = self.repo_path.as_path();
+        let mut tmp_path = self.repo_path.clone().into_os_string();
+        tmp_path.push("__tmp");
+        let tmp_path = Path::new(&tmp_path);
         info!("Re-cloning repository, temporary repo will be created at {tmp_path:?}");
         let tmp_repo = Repository::clone(&self.repo_url, tmp_path)?;
         info!("Pointing changes to new temp repository");
@@ -323,10 +1389,53 @@ impl Interface {
         Ok(())
     }
 
-    /// Pull changes from upstream
-    pub fn pull(&self) -> Result<()> {
-        let guard = self.repo.lock().unwrap();
-        Self::git_pull(&guard)
+    /// Pull changes from upstream, returning every file (relative to the root of the repository)
+    /// that changed.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn pull(&self) -> Result<Vec<ChangedPath>> {
+        // The repository is unlocked before plugins are notified
+        let changes = {
+            let guard = self.repo.lock().unwrap();
+            let old_tree = guard.head()?.peel_to_tree()?;
+            Self::git_pull(&guard, &self.identities.bot.signature()?)?;
+            let new_tree = guard.head()?.peel_to_tree()?;
+            let diff = guard.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+            diff.deltas()
+                .filter_map(|delta| {
+                    let (file, kind) = match delta.status() {
+                        Delta::Added | Delta::Copied => (delta.new_file(), ChangeKind::Added),
+                        Delta::Deleted => (delta.old_file(), ChangeKind::Deleted),
+                        Delta::Modified | Delta::Typechange => {
+                            (delta.new_file(), ChangeKind::Modified)
+                        }
+                        _ => return None,
+                    };
+                    Some(ChangedPath {
+                        path: file.path()?.to_string_lossy().to_string(),
+                        kind,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        self.plugins.post_pull(&changes);
+        Ok(changes)
+    }
+
+    /// Run the plugins' post-commit hooks for a commit that was pushed, where `paths` are the
+    /// changed files relative to the root of the repository
+    fn run_post_commit(
+        &self,
+        commit_id: Oid,
+        branch: Option<&str>,
+        message: &str,
+        paths: &[&Path],
+    ) {
+        self.plugins.post_commit(&CommitEvent {
+            commit_id: commit_id.to_string(),
+            branch,
+            message,
+            paths: paths.iter().map(to_slash_path).collect(),
+        });
     }
 
     /// A code level re-implementation of `git add`.
@@ -437,20 +1546,30 @@ impl Interface {
     }
 
     /// Writes the current index as a commit, updating HEAD. This means it will only commit changes
-    /// tracked by the index. If an author is not specified, the commit will be attributed to `Hyde`. Returns
-    /// the id (A full or partial hash associated with a git object) tied to that commit.
-    fn git_commit(repo: &Repository, message: String, author: Option<Signature>) -> Result<Oid> {
-        let sig = match author {
-            Some(sig) => sig,
-            None => Signature::now("Hyde", "Hyde")?,
-        };
+    /// tracked by the index. If an author is not specified, the commit will be attributed to the
+    /// `committer`. Returns the id (A full or partial hash associated with a git object) tied to
+    /// that commit.
+    fn git_commit(
+        repo: &Repository,
+        message: String,
+        author: Option<Signature>,
+        committer: &Signature,
+    ) -> Result<Oid> {
+        let author = author.as_ref().unwrap_or(committer);
         let tree = {
             let mut index = repo.index()?;
             let oid = index.write_tree()?;
             repo.find_tree(oid)?
         };
         let parent_commit = Self::find_last_commit(repo)?;
-        Ok(repo.commit(Some("HEAD"), &sig, &sig, &message, &tree, &[&parent_commit])?)
+        Ok(repo.commit(
+            Some("HEAD"),
+            author,
+            committer,
+            &message,
+            &tree,
+            &[&parent_commit],
+        )?)
     }
 
     /// Pushes commits to a specified branch on a remote repository, or pushes all branches if no branch name is provided.
@@ -516,12 +1635,12 @@ impl Interface {
     ///
     /// Under the hood, `git pull` is shorthand for `git fetch`, followed by `git merge FETCH_HEAD`,
     /// where `FETCH_HEAD` is a reference to the latest commit that has just been fetched from the remote repository.
-    fn git_pull(repo: &Repository) -> Result<()> {
+    fn git_pull(repo: &Repository, committer: &Signature) -> Result<()> {
         // https://github.com/rust-lang/git2-rs/blob/master/examples/pull.rs
         // TODO: configure branch via environment variables
         let fetch_head = Self::git_fetch(repo, None)?;
         info!("Successfully fetched latest changes, merging...");
-        Self::git_merge(repo, "master", fetch_head)?;
+        Self::git_merge(repo, "master", fetch_head, committer)?;
         info!("Successfully merged latest changes");
         Ok(())
     }
@@ -767,6 +1886,7 @@ impl Interface {
         repo: &Repository,
         remote_branch: &str,
         fetch_commit: AnnotatedCommit<'_>,
+        committer: &Signature,
     ) -> Result<()> {
         // First perform a merge analysis to understand how to proceed
         let analysis = repo.merge_analysis(&[&fetch_commit])?;
@@ -789,7 +1909,7 @@ impl Interface {
         else if analysis.0.is_normal() {
             debug!("Performing normal merge from branch '{}'", remote_branch);
             let head_commit = repo.reference_to_annotated_commit(&repo.head()?)?;
-            Self::normal_merge(repo, &fetch_commit, &head_commit)?;
+            Self::normal_merge(repo, &fetch_commit, &head_commit, committer)?;
         }
         // If no merging is needed
         else {
@@ -809,6 +1929,7 @@ impl Interface {
         repo: &Repository,
         source: &AnnotatedCommit,
         destination: &AnnotatedCommit,
+        committer: &Signature,
     ) -> Result<()> {
         let source_tree = repo.find_commit(source.id())?.tree()?;
         let destination_tree = repo.find_commit(destination.id())?.tree()?;
@@ -825,13 +1946,12 @@ impl Interface {
         let result_tree = repo.find_tree(idx.write_tree()?)?;
         let _merge_commit = {
             let msg = format!("Merge: {} into {}", source.id(), destination.id());
-            let sig = repo.signature()?;
             let destination_commit_parent = repo.find_commit(destination.id())?;
             let source_commit_parent = repo.find_commit(source.id())?;
             repo.commit(
                 Some("HEAD"),
-                &sig,
-                &sig,
+                committer,
+                committer,
                 &msg,
                 &result_tree,
                 &[&destination_commit_parent, &source_commit_parent],
@@ -871,7 +1991,7 @@ impl Interface {
     /// Returns the latest commit from `HEAD`.
     ///
     /// <https://zsiciarz.github.io/24daysofrust/book/vol2/day16.html>
-    pub fn find_last_commit(repo: &Repository) -> Result<git2::Commit, git2::Error> {
+    pub fn find_last_commit(repo: &Repository) -> Result<git2::Commit<'_>, git2::Error> {
         let obj = repo.head()?.resolve()?.peel(git2::ObjectType::Commit)?;
         obj.into_commit()
             .map_err(|_| git2::Error::from_str("Couldn't find commit"))
@@ -914,6 +2034,33 @@ impl Interface {
         Ok(())
     }
 
+    /// Returns the paths (relative to the root of the repo) of every file with uncommitted changes.
+    /// These will be included in the next commit made through Hyde.
+    ///
+    /// # Errors
+    /// This function will return an error if the repository status can't be read.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn uncommitted_files(&self) -> Result<Vec<String>> {
+        let repo = self.repo.lock().unwrap();
+        let statuses = repo.statuses(None)?;
+        let files = statuses
+            .iter()
+            .filter(|entry| !entry.status().is_ignored())
+            .filter_map(|entry| entry.path().map(ToString::to_string))
+            .collect();
+        Ok(files)
+    }
+
+    /// Returns the path of a document relative to the root of the repository
+    pub fn doc_repo_path(&self, path: &str) -> String {
+        to_slash_path(self.doc_path.join(path))
+    }
+
+    /// Returns the path of an asset relative to the root of the repository
+    pub fn asset_repo_path(&self, path: &str) -> String {
+        to_slash_path(self.asset_path.join(path))
+    }
+
     /// Fetches the current branch name from the repository.
     ///
     /// This method locks the repository to ensure thread safety and retrieves the current
@@ -937,9 +2084,8 @@ impl Interface {
 }
 
 impl RepoFileSystem for Interface {
-    fn get_file<P: AsRef<Path> + Copy>(path: P) -> Result<Option<Vec<u8>>> {
-        let mut path_to_file: PathBuf = PathBuf::from("./repo");
-        path_to_file.push(path);
+    fn get_file<P: AsRef<Path> + Copy>(&self, path: P) -> Result<Option<Vec<u8>>> {
+        let path_to_file = repo_fs_path(&self.repo_path, path)?;
         if !path_to_file.exists() {
             return Ok(None);
         }
@@ -950,10 +2096,9 @@ impl RepoFileSystem for Interface {
         Ok(Some(o))
     }
 
-    #[tracing::instrument(skip(contents))]
-    fn put_file<P: AsRef<Path> + Copy + Debug>(path: P, contents: &[u8]) -> Result<()> {
-        let mut path_to_file: PathBuf = PathBuf::from("./repo");
-        path_to_file.push(path);
+    #[tracing::instrument(skip(self, contents))]
+    fn put_file<P: AsRef<Path> + Copy + Debug>(&self, path: P, contents: &[u8]) -> Result<()> {
+        let path_to_file = repo_fs_path(&self.repo_path, path)?;
         // wipe the file
         let mut file = fs::File::create(path_to_file).wrap_err_with(|| {
             format!(
@@ -971,15 +2116,14 @@ impl RepoFileSystem for Interface {
         Ok(())
     }
 
-    fn delete_file<P: AsRef<Path> + Copy>(path: P) -> Result<()> {
-        let mut path_to_file: PathBuf = PathBuf::from("./repo");
-        path_to_file.push(path);
+    fn delete_file<P: AsRef<Path> + Copy>(&self, path: P) -> Result<()> {
+        let path_to_file = repo_fs_path(&self.repo_path, path)?;
         fs::remove_file(&path_to_file)
             .wrap_err_with(|| format!("Failed to remove the document at {path_to_file:?}"))?;
         Ok(())
     }
 
-    fn get_file_tree<P: AsRef<Path> + Copy>(path: P) -> Result<INode> {
+    fn get_file_tree<P: AsRef<Path> + Copy>(&self, path: P) -> Result<INode> {
         fn recurse_tree(dir: &Path, node: &mut INode) -> Result<()> {
             for entry in fs::read_dir(dir)? {
                 let entry = entry?;
@@ -990,6 +2134,7 @@ impl RepoFileSystem for Interface {
                     let mut inner_node = INode {
                         name: entry_name,
                         children: Vec::new(),
+                        meta: None,
                     };
                     recurse_tree(&path, &mut inner_node)?;
                     node.children.push(inner_node);
@@ -998,6 +2143,7 @@ impl RepoFileSystem for Interface {
                     node.children.push(INode {
                         name: entry_name,
                         children: Vec::new(),
+                        meta: None,
                     });
                 }
             }
@@ -1014,30 +2160,99 @@ impl RepoFileSystem for Interface {
                 .to_string_lossy()
                 .to_string(),
             children: Vec::new(),
+            meta: None,
         };
-        let mut trunk_path = PathBuf::from("./repo");
-        trunk_path.push(path.as_ref());
-        recurse_tree(&trunk_path, &mut root_node)?;
+        recurse_tree(&repo_fs_path(&self.repo_path, path)?, &mut root_node)?;
         Ok(root_node)
     }
 }
 
+/// Add `archived: true` to the front matter of `doc` (creating a front matter block if there
+/// isn't one), and insert [`ARCHIVE_BANNER`] directly after it.
+fn mark_archived(doc: &str) -> String {
+    let (front_matter, body) = doc
+        .strip_prefix("---\n")
+        .and_then(|rest| rest.split_once("\n---\n"))
+        .unwrap_or(("", doc));
+    let mut output = String::from("---\n");
+    for line in front_matter.lines() {
+        if !line.starts_with("archived:") {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+    output.push_str("archived: true\n---\n");
+    if !body.trim_start().starts_with(ARCHIVE_BANNER) {
+        output.push_str(ARCHIVE_BANNER);
+        output.push_str("\n\n");
+    }
+    output.push_str(body);
+    output
+}
+
+/// Returns an error unless `path` names a folder below `root`, rather than `root` itself or
+/// something outside of it
+fn check_subdir(root: &Path, path: &Path) -> Result<()> {
+    if to_slash_path(path).is_empty() {
+        return Err(PathError::Invalid("No folder name was given".to_string()).into());
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(
+            PathError::Invalid(format!("The folder {path:?} must be inside {root:?}")).into(),
+        );
+    }
+    Ok(())
+}
+
+/// Returns where `path` (relative to the root of the repo) is on disk, given the folder the repo
+/// was cloned into. Paths from the API always use `/` as a separator, which is normalized for the
+/// current platform. Leading `/`s and `.`s are ignored, and paths containing `..` are rejected
+/// with [`PathError::Invalid`], so the result is always inside the repo folder.
+fn repo_fs_path<P: AsRef<Path>>(repo_path: &Path, path: P) -> Result<PathBuf> {
+    let path = path.as_ref();
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(
+            PathError::Invalid(format!("The path {path:?} must be inside the repository")).into(),
+        );
+    }
+    let mut fs_path = repo_path.to_path_buf();
+    fs_path.extend(
+        path.components()
+            .filter(|c| matches!(c, Component::Normal(_))),
+    );
+    Ok(fs_path)
+}
+
+/// Formats a path the way git and the GitHub API expect, with `/` as the separator on every
+/// platform and without a trailing separator.
+pub fn to_slash_path<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref()
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 /// An abstraction over the filesystem for the git repository. Does not implement the version
 /// control side of things
 trait RepoFileSystem {
     /// Read the file at the provided location, relative to the root of the repo
-    fn get_file<P: AsRef<Path> + Copy + Debug>(path: P) -> Result<Option<Vec<u8>>>;
+    fn get_file<P: AsRef<Path> + Copy + Debug>(&self, path: P) -> Result<Option<Vec<u8>>>;
 
     /// Create a file at the provided location, or overwrite it if it exists, relative to
     /// the root of the repo
-    fn put_file<P: AsRef<Path> + Copy + Debug>(path: P, contents: &[u8]) -> Result<()>;
+    fn put_file<P: AsRef<Path> + Copy + Debug>(&self, path: P, contents: &[u8]) -> Result<()>;
 
     /// Delete the file at the provided location, relative to the root of the repo
-    fn delete_file<P: AsRef<Path> + Copy + Debug>(path: P) -> Result<()>;
+    fn delete_file<P: AsRef<Path> + Copy + Debug>(&self, path: P) -> Result<()>;
 
     /// Read the directory at the provided location and create a representation of that dir's
     /// filesystem tree.
-    fn get_file_tree<P: AsRef<Path> + Copy + Debug>(path: P) -> Result<INode>;
+    fn get_file_tree<P: AsRef<Path> + Copy + Debug>(&self, path: P) -> Result<INode>;
 }
 
 // TODO: Split git code out into a new (hopefully git backend agnostic) trait so that the impl block
@@ -1045,3 +2260,351 @@ trait RepoFileSystem {
 // trait Git {}
 
 // TODO: unit tests for get_inode_path and that sort of thing
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clone of a new repository with `docs/guide.md` and `assets/logo.png` committed in it,
+    /// along with the folder the bare repository it was cloned from is in. Deleting that folder
+    /// makes pushes fail.
+    fn test_repo(name: &str) -> (Interface, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("hyde-git-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let remote_path = dir.join("remote");
+        let remote = Repository::init_bare(&remote_path).unwrap();
+        let mut root = remote.treebuilder(None).unwrap();
+        for (folder, file, contents) in [
+            ("docs", "guide.md", "# Guide\n"),
+            ("assets", "logo.png", "png"),
+        ] {
+            let blob = remote.blob(contents.as_bytes()).unwrap();
+            let mut subtree = remote.treebuilder(None).unwrap();
+            subtree.insert(file, blob, 0o100_644).unwrap();
+            root.insert(folder, subtree.write().unwrap(), 0o040_000)
+                .unwrap();
+        }
+        let tree = remote.find_tree(root.write().unwrap()).unwrap();
+        let signature = Commit::default().signature().unwrap();
+        remote
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "Initial commit",
+                &tree,
+                &[],
+            )
+            .unwrap();
+
+        let interface = Interface::new(
+            remote_path.to_str().unwrap().to_string(),
+            dir.join("repo").to_str().unwrap().to_string(),
+            "docs".to_string(),
+            "assets".to_string(),
+            "archive".to_string(),
+            CommitIdentities {
+                committer: Commit::default(),
+                bot: Commit::default(),
+            },
+            PluginRegistry::default(),
+        )
+        .unwrap();
+        (interface, remote_path)
+    }
+
+    /// Whether the working tree and index match `HEAD`, with no untracked files
+    fn is_clean(interface: &Interface) -> bool {
+        let repo = interface.repo.lock().unwrap();
+        let clean = repo.statuses(None).unwrap().is_empty();
+        clean
+    }
+
+    #[test]
+    fn creating_folders() {
+        let (interface, remote) = test_repo("create-dir");
+        let head = || {
+            Interface::find_last_commit(&interface.repo.lock().unwrap())
+                .unwrap()
+                .id()
+        };
+        let error = interface
+            .create_doc_dir("guide.md", "Create guide.md", "")
+            .unwrap_err();
+        assert!(
+            matches!(error.downcast_ref(), Some(PathError::AlreadyExists(_))),
+            "create_doc_dir: existing paths should be rejected, got {error:?}"
+        );
+        let error = interface
+            .create_doc_dir("../assets/new", "Escape", "")
+            .unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(PathError::Invalid(_))));
+
+        interface
+            .create_doc_dir("guides", "Create guides", "")
+            .unwrap();
+        assert!(interface
+            .get_doc_dir_files("guides")
+            .unwrap()
+            .is_some_and(|files| files == vec![format!("guides/{KEEP_FILE}")]));
+
+        let before = head();
+        std::fs::remove_dir_all(&remote).unwrap();
+        assert!(interface
+            .create_asset_dir("icons", "Create icons", "")
+            .is_err());
+        assert_eq!(
+            head(),
+            before,
+            "create_asset_dir: the commit should be undone if it can't be pushed"
+        );
+        assert!(
+            is_clean(&interface),
+            "create_asset_dir: changes should be discarded"
+        );
+        assert!(
+            !interface.repo_path.join("assets/icons").exists(),
+            "create_asset_dir: the new folder should be removed"
+        );
+    }
+
+    #[test]
+    fn moving_folders() {
+        let (interface, remote) = test_repo("move-dir");
+        interface
+            .create_doc_dir("guides", "Create guides", "")
+            .unwrap();
+        let error =
+            |result: Result<Vec<(String, String)>>| result.unwrap_err().downcast::<PathError>();
+        assert!(matches!(
+            error(interface.move_doc_dir("missing", "moved", "Move", "")),
+            Ok(PathError::NotFound(_))
+        ));
+        assert!(matches!(
+            error(interface.move_doc_dir("guides", "guide.md", "Move", "")),
+            Ok(PathError::AlreadyExists(_))
+        ));
+        assert!(
+            matches!(
+                error(interface.move_doc_dir("guides", "guides/nested", "Move", "")),
+                Ok(PathError::Invalid(_))
+            ),
+            "move_doc_dir: folders shouldn't be moved into themselves"
+        );
+
+        assert_eq!(
+            interface
+                .move_doc_dir("guides", "how-to/guides", "Move guides", "")
+                .unwrap(),
+            vec![(
+                format!("guides/{KEEP_FILE}"),
+                format!("how-to/guides/{KEEP_FILE}")
+            )]
+        );
+
+        let head = Interface::find_last_commit(&interface.repo.lock().unwrap())
+            .unwrap()
+            .id();
+        std::fs::remove_dir_all(&remote).unwrap();
+        assert!(interface
+            .move_doc_dir("how-to", "tutorials", "Move how-to", "")
+            .is_err());
+        assert_eq!(
+            Interface::find_last_commit(&interface.repo.lock().unwrap())
+                .unwrap()
+                .id(),
+            head,
+            "move_doc_dir: the commit should be undone if it can't be pushed"
+        );
+        assert!(
+            is_clean(&interface),
+            "move_doc_dir: a failed move should leave the tree clean"
+        );
+        assert!(interface
+            .get_doc_dir_files("how-to/guides")
+            .unwrap()
+            .is_some());
+        assert!(interface.get_doc_dir_files("tutorials").unwrap().is_none());
+    }
+
+    #[test]
+    fn deleting_folders() {
+        let (interface, remote) = test_repo("delete-dir");
+        interface
+            .create_doc_dir("guides", "Create guides", "")
+            .unwrap();
+        assert!(matches!(
+            interface
+                .delete_doc_dir("missing", "Delete", "")
+                .unwrap_err()
+                .downcast(),
+            Ok(PathError::NotFound(_))
+        ));
+
+        let head = Interface::find_last_commit(&interface.repo.lock().unwrap())
+            .unwrap()
+            .id();
+        std::fs::remove_dir_all(&remote).unwrap();
+        assert!(interface
+            .delete_doc_dir("guides", "Delete guides", "")
+            .is_err());
+        assert_eq!(
+            Interface::find_last_commit(&interface.repo.lock().unwrap())
+                .unwrap()
+                .id(),
+            head,
+            "delete_doc_dir: the commit should be undone if it can't be pushed"
+        );
+        assert!(
+            is_clean(&interface),
+            "delete_doc_dir: a failed deletion should leave the tree clean"
+        );
+        assert!(
+            interface.get_doc_dir_files("guides").unwrap().is_some(),
+            "delete_doc_dir: the folder should be restored"
+        );
+    }
+
+    #[test]
+    fn archived_tree() {
+        let (interface, remote) = test_repo("archived-tree");
+        let mut tree: INode = serde_json::from_value(serde_json::json!({
+            "name": "docs",
+            "children": [
+                {"name": "guide.md", "children": []},
+                {"name": "old", "children": [
+                    {"name": "archive", "children": [{"name": "a.md", "children": []}]},
+                ]},
+                {"name": "other", "children": [
+                    {"name": "archive", "children": [{"name": "b.md", "children": []}]},
+                    {"name": "c.md", "children": []},
+                ]},
+            ],
+        }))
+        .unwrap();
+        tree.remove_path("other/archive");
+        assert_eq!(
+            tree.file_paths(),
+            vec!["guide.md", "old/archive/a.md", "other/c.md"]
+        );
+        tree.remove_path("old/archive");
+        assert_eq!(
+            tree.file_paths(),
+            vec!["guide.md", "other/c.md"],
+            "remove_path: folders left empty should be removed too"
+        );
+        tree.remove_path("missing/archive");
+
+        interface
+            .put_doc("copy.md", "# Copy\n", "Add copy.md", "", "master")
+            .unwrap();
+        interface.archive_doc("copy.md", "Archive", "").unwrap();
+        interface
+            .put_doc("copy.md", "# Copy\n", "Add copy.md", "", "master")
+            .unwrap();
+        assert!(
+            matches!(
+                interface
+                    .archive_doc("copy.md", "Archive", "")
+                    .unwrap_err()
+                    .downcast(),
+                Ok(PathError::AlreadyExists(_))
+            ),
+            "archive_doc: archived documents shouldn't be overwritten"
+        );
+        assert!(matches!(
+            interface
+                .archive_doc("../assets/logo.png", "Archive", "")
+                .unwrap_err()
+                .downcast(),
+            Ok(PathError::Invalid(_))
+        ));
+        assert!(
+            matches!(
+                interface
+                    .archive_doc("/archive/copy.md", "Archive", "")
+                    .unwrap_err()
+                    .downcast(),
+                Ok(PathError::Invalid(_))
+            ),
+            "archive_doc: archived documents shouldn't be archived again"
+        );
+
+        let head = Interface::find_last_commit(&interface.repo.lock().unwrap())
+            .unwrap()
+            .id();
+        std::fs::remove_dir_all(&remote).unwrap();
+        assert!(interface.archive_doc("guide.md", "Archive", "").is_err());
+        assert_eq!(
+            Interface::find_last_commit(&interface.repo.lock().unwrap())
+                .unwrap()
+                .id(),
+            head,
+            "archive_doc: the commit should be undone if it can't be pushed"
+        );
+        assert!(
+            is_clean(&interface),
+            "archive_doc: a failed archive should leave the tree clean"
+        );
+    }
+
+    #[test]
+    fn archiving_front_matter() {
+        assert_eq!(
+            mark_archived("---\ntitle: Foo\n---\n# Foo\n"),
+            "---\ntitle: Foo\narchived: true\n---\n{% include archived.html %}\n\n# Foo\n",
+            "mark_archived: should extend existing front matter"
+        );
+        assert_eq!(
+            mark_archived("# Foo\n"),
+            "---\narchived: true\n---\n{% include archived.html %}\n\n# Foo\n",
+            "mark_archived: should create front matter if none exists"
+        );
+        let archived = mark_archived("---\ntitle: Foo\n---\n# Foo\n");
+        assert_eq!(
+            mark_archived(&archived),
+            archived,
+            "mark_archived: should not duplicate the flag or banner"
+        );
+    }
+
+    #[test]
+    fn path_normalization() {
+        assert_eq!(
+            to_slash_path(Path::new("docs").join("guides/drivers.md")),
+            "docs/guides/drivers.md"
+        );
+        assert_eq!(
+            to_slash_path("archive/"),
+            "archive",
+            "to_slash_path: should drop trailing separators"
+        );
+        assert_eq!(
+            repo_fs_path(Path::new("repo"), "/docs/./foo.md").unwrap(),
+            Path::new("repo").join("docs").join("foo.md"),
+        );
+        assert!(
+            matches!(
+                repo_fs_path(Path::new("repo"), "docs/../../x")
+                    .unwrap_err()
+                    .downcast(),
+                Ok(PathError::Invalid(_))
+            ),
+            "repo_fs_path: paths leaving the repo folder should be rejected"
+        );
+    }
+
+    #[test]
+    fn subdirectories() {
+        let root = Path::new("docs");
+        assert!(check_subdir(root, Path::new("guides/drivers")).is_ok());
+        assert!(
+            check_subdir(root, Path::new("/")).is_err(),
+            "check_subdir: the root itself isn't a subdirectory"
+        );
+        assert!(check_subdir(root, Path::new("")).is_err());
+        assert!(
+            check_subdir(root, Path::new("guides/../../src")).is_err(),
+            "check_subdir: should reject paths leaving the root"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! An optional proxy to an LLM endpoint for summarizing documents or suggesting rewrites.
+//!
+//! Only configured prompt templates can be used, and the only context that can be sent is
+//! the content of a document in the documents folder (or a selection from it), so nothing
+//! else (config, tokens, user data) can leak to the endpoint. Archived documents and content
+//! that looks like it contains credentials (see [`policy::scan_secrets`]) are refused, and
+//! every request is recorded in the audit log.
+use std::ffi::OsStr;
+use std::path::{Component, Path};
+use std::time::Duration;
+
+use axum::routing::{get, post};
+use axum::{extract::State, http::HeaderMap, middleware, Json, Router};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::client_ip;
+use crate::audit::{self, AuditCategory};
+use crate::policy::{self, Location};
+use crate::{
+    ai_assist::{self, render_prompt},
+    app_conf::AiAssist,
+    eyre_to_axum_err,
+    perms::required::MANAGE_CONTENT,
+    require_permission, AppState, AuthenticatedUser,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AiAssistRequestBody {
+    /// The path of the document, relative to the documents folder
+    path: String,
+    /// The name of one of the configured prompt templates
+    prompt: String,
+    /// Only send this part of the document. It must be an exact excerpt of the document.
+    selection: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AiAssistResponse {
+    suggestion: String,
+}
+
+/// The window per-user AI assist rate limits apply to
+pub const AI_ASSIST_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+fn ai_assist_conf(state: &AppState) -> Result<&'static AiAssist, (StatusCode, String)> {
+    state.config.ai_assist.as_ref().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "AI assist is not enabled on this instance.".to_string(),
+        )
+    })
+}
+
+/// Lists the names of the prompt templates that can be used
+pub async fn get_ai_prompts_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    let conf = ai_assist_conf(&state)?;
+    let mut prompts: Vec<String> = conf.prompts.keys().cloned().collect();
+    prompts.sort();
+    Ok(Json(prompts))
+}
+
+/// Fill in the requested prompt template with the document (or selection), and forward it
+/// to the configured endpoint.
+pub async fn post_ai_assist_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+    Json(body): Json<AiAssistRequestBody>,
+) -> Result<Json<AiAssistResponse>, (StatusCode, String)> {
+    let conf = ai_assist_conf(&state)?;
+
+    let template = conf.prompts.get(&body.prompt).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("{:?} is not a configured prompt.", body.prompt),
+        )
+    })?;
+
+    // Only markdown documents inside of the documents folder may be sent
+    let path = Path::new(&body.path);
+    if path.extension() != Some(OsStr::new("md"))
+        || !path.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Only documents in the documents folder can be sent.".to_string(),
+        ));
+    }
+    // Archived documents are kept out of the wiki, so they aren't sent either
+    if path.starts_with(state.config.files.archive_path.trim_matches('/')) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Archived documents can't be sent.".to_string(),
+        ));
+    }
+    let doc = state
+        .git
+        .get_doc(&body.path)
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "The file at the provided path was not found.".to_string(),
+            )
+        })?;
+    let content = match &body.selection {
+        Some(selection) if doc.contains(selection.as_str()) => selection.clone(),
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The selection must be an excerpt of the document.".to_string(),
+            ))
+        }
+        None => doc,
+    };
+    if content.chars().count() > conf.max_content_length {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Only {} characters can be sent at once, try selecting part of the document.",
+                conf.max_content_length
+            ),
+        ));
+    }
+
+    if let Some(secret) = policy::scan_secrets(content.as_bytes()).first() {
+        let Location { line, column } = secret.location;
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Possible {} at line {line}, column {column}, it can't be sent.",
+                secret.pattern
+            ),
+        ));
+    }
+
+    if !state.ai_rate_limiter.try_acquire(user.id) {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "Only {} AI assist requests can be made per hour.",
+                conf.requests_per_hour
+            ),
+        ));
+    }
+
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "ai_assist_request",
+        Some(&user),
+        client_ip(&headers),
+        format!(
+            "Sent {} to AI assist with the {:?} prompt",
+            body.path, body.prompt
+        ),
+    );
+    let data = serde_json::json!({
+        "prompt": body.prompt,
+        "selection": body.selection.is_some(),
+        "characters": content.chars().count(),
+    });
+    audit::record_entry(&state.db, entry.with_target(&body.path).with_data(&data)).await;
+    let suggestion = ai_assist::complete(
+        &state.reqwest_client,
+        conf,
+        render_prompt(template, &body.path, &content),
+    )
+    .await
+    .map_err(|e| {
+        warn!("AI assist request from {:?} failed: {e:?}", user.username);
+        (
+            StatusCode::BAD_GATEWAY,
+            "The AI assist request failed, check server logs for more info".to_string(),
+        )
+    })?;
+
+    Ok(Json(AiAssistResponse { suggestion }))
+}
+
+pub async fn create_ai_assist_route() -> Router<AppState> {
+    Router::new()
+        .route("/ai/prompts", get(get_ai_prompts_handler))
+        .route("/ai/assist", post(post_ai_assist_handler))
+        .route_layer(middleware::from_fn(require_permission::<MANAGE_CONTENT>))
+}
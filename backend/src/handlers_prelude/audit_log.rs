@@ -0,0 +1,97 @@
+//! Reading the audit log, see [`crate::audit`]
+use axum::routing::get;
+use axum::{
+    extract::{Query, State},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditCategory;
+use crate::db::{AuditEntry, AuditFilter};
+use crate::{eyre_to_axum_err, perms::required::MANAGE_USERS, AppState, RequirePermission};
+
+/// The most entries returned at once
+const MAX_AUDIT_ENTRIES: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    /// Only entries in this category
+    pub category: Option<AuditCategory>,
+    /// Only entries with this action, e.g. `login`
+    pub action: Option<String>,
+    /// Only entries about this user's actions
+    pub user_id: Option<i64>,
+    /// Only entries about this target, e.g. a document path
+    pub target: Option<String>,
+    /// Only entries created at or after this RFC-3339 timestamp
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries created before this RFC-3339 timestamp
+    pub until: Option<DateTime<Utc>>,
+    /// Only entries older than the entry with this id. Pass the id of the last entry returned
+    /// to get the next page.
+    pub before: Option<i64>,
+    /// How many of the most recent entries to return, defaults to 100
+    pub limit: Option<i64>,
+}
+
+/// An audit log entry, with its data as JSON rather than the string it's stored as
+#[derive(Debug, Serialize)]
+pub struct AuditEntryResponse {
+    id: i64,
+    created_at: DateTime<Utc>,
+    category: String,
+    action: String,
+    user_id: Option<i64>,
+    username: Option<String>,
+    ip_address: Option<String>,
+    detail: String,
+    target: Option<String>,
+    data: Option<serde_json::Value>,
+}
+
+impl From<AuditEntry> for AuditEntryResponse {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            id: entry.id,
+            created_at: entry.created_at,
+            category: entry.category,
+            action: entry.action,
+            user_id: entry.user_id,
+            username: entry.username,
+            ip_address: entry.ip_address,
+            detail: entry.detail,
+            target: entry.target,
+            data: entry.data.and_then(|data| serde_json::from_str(&data).ok()),
+        }
+    }
+}
+
+/// The most recent audit log entries matching the query, newest first
+pub async fn get_audit_log_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_USERS>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntryResponse>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_AUDIT_ENTRIES);
+    let filter = AuditFilter {
+        category: query.category.map(|c| c.as_str().to_string()),
+        action: query.action,
+        user_id: query.user_id,
+        target: query.target,
+        since: query.since,
+        until: query.until,
+        before: query.before,
+    };
+    let entries = state
+        .db
+        .query_audit(&filter, limit)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(entries.into_iter().map(Into::into).collect()))
+}
+
+pub async fn create_audit_log_route() -> Router<AppState> {
+    Router::new().route("/audit", get(get_audit_log_handler))
+}
@@ -0,0 +1,54 @@
+//! Backing up the database on demand. Backups are written to the `backups/` folder next to the
+//! database, `hyde-data/backups/` by default.
+use axum::routing::post;
+use axum::{extract::State, http::HeaderMap, Json, Router};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use super::client_ip;
+use crate::audit::{self, AuditCategory};
+use crate::{eyre_to_axum_err, perms::required::MANAGE_USERS, AppState, RequirePermission};
+
+#[derive(Debug, Serialize)]
+pub struct BackupResponse {
+    /// The backup's file name, in the backups folder
+    filename: String,
+    bytes: u64,
+}
+
+/// Write a consistent backup of the database to the backups folder. Only SQLite databases can be
+/// backed up this way, PostgreSQL databases should be backed up with `pg_dump`.
+pub async fn post_backup_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+) -> Result<Json<BackupResponse>, (StatusCode, String)> {
+    if state.db.is_postgres() {
+        return Err((
+            StatusCode::CONFLICT,
+            String::from("Only SQLite databases can be backed up, use pg_dump with PostgreSQL"),
+        ));
+    }
+    let filename = format!("hyde-{}.db", Utc::now().format("%Y-%m-%dT%H-%M-%S%.3fZ"));
+    let path = state.backup_dir.join(&filename);
+    state.db.backup_to(&path).await.map_err(eyre_to_axum_err)?;
+    let bytes = fs_err::tokio::metadata(&path)
+        .await
+        .map_err(|e| eyre_to_axum_err(e.into()))?
+        .len();
+    let entry = audit::entry(
+        AuditCategory::Database,
+        "backup_created",
+        Some(&user),
+        client_ip(&headers),
+        format!("Backed up the database to {filename}"),
+    );
+    let data = serde_json::json!({ "bytes": bytes });
+    audit::record_entry(&state.db, entry.with_target(&filename).with_data(&data)).await;
+    Ok(Json(BackupResponse { filename, bytes }))
+}
+
+pub async fn create_backups_route() -> Router<AppState> {
+    Router::new().route("/admin/backup", post(post_backup_handler))
+}
@@ -0,0 +1,71 @@
+//! A machine readable summary of documentation changes between two revisions or dates
+use axum::routing::get;
+use axum::{
+    extract::{Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use tracing::warn;
+
+use super::ReadAccess;
+use crate::AppState;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetChangelogQuery {
+    /// A revision (commit, tag, branch) or date, exclusive
+    pub from: String,
+    /// A revision (commit, tag, branch) or date, inclusive. Defaults to the latest commit
+    pub to: Option<String>,
+    /// Either `json` (the default) or `markdown`
+    #[serde(default)]
+    pub format: ChangelogFormat,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangelogFormat {
+    #[default]
+    Json,
+    Markdown,
+}
+
+/// This handler accepts a `GET` request to `/api/changelog?from=&to=&format=`, returning the
+/// pages added, changed, and removed in that range along with who edited them. Gated like the
+/// documents themselves, see [`ReadAccess`].
+pub async fn get_changelog_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Query(query): Query<GetChangelogQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    // Almost every failure here comes from a revision or date that couldn't be resolved
+    let changelog = state
+        .git
+        .changelog(&query.from, query.to.as_deref())
+        .map_err(|e| {
+            warn!("Failed to build changelog for {query:?}: {e:?}");
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Failed to build changelog: {e}"),
+            )
+        })?;
+
+    Ok(match query.format {
+        ChangelogFormat::Json => Json(changelog).into_response(),
+        ChangelogFormat::Markdown => {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                CONTENT_TYPE,
+                "text/markdown; charset=utf-8".parse().unwrap(),
+            );
+            (headers, changelog.to_markdown()).into_response()
+        }
+    })
+}
+
+pub async fn create_changelog_route() -> Router<AppState> {
+    Router::new().route("/changelog", get(get_changelog_handler))
+}
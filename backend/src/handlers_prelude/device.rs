@@ -0,0 +1,315 @@
+//! The device authorization grant, see [`crate::device_flow`]
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Form, Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::{client_ip, create_api_token, public_url, CreateApiTokenBody};
+use crate::api_tokens;
+use crate::audit::{self, AuditCategory};
+use crate::db::DeviceCode;
+use crate::device_flow::{
+    format_user_code, generate_device_code, generate_user_code, normalize_user_code,
+    DEVICE_CODE_GRANT_TYPE, DEVICE_CODE_LIFETIME_SECS, DEVICE_TOKEN_LIFETIME_DAYS,
+    POLL_INTERVAL_SECS,
+};
+use crate::{eyre_to_axum_err, AppState, AuthenticatedUser};
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceAuthorizationRequest {
+    /// Hyde doesn't register clients, so this is only the name the user is shown
+    pub client_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceAuthorizationResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: String,
+    expires_in: i64,
+    interval: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceTokenRequest {
+    pub grant_type: String,
+    pub device_code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceTokenResponse {
+    /// An API token acting as the user that approved the request
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+}
+
+/// An error in the format OAuth clients expect
+#[derive(Debug, Serialize)]
+struct OAuthError {
+    error: &'static str,
+    error_description: &'static str,
+}
+
+fn oauth_error(error: &'static str, error_description: &'static str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(OAuthError {
+            error,
+            error_description,
+        }),
+    )
+        .into_response()
+}
+
+/// Where users approve requests
+fn verification_uri(state: &AppState) -> Result<String, (StatusCode, String)> {
+    if cfg!(debug_assertions) {
+        // The frontend is served separately in development, see `home_redirect`
+        return Ok("http://localhost:5173/device".to_string());
+    }
+    Ok(format!("{}/device", public_url(state)?))
+}
+
+/// Start a device authorization request
+pub async fn post_device_authorization_handler(
+    State(state): State<AppState>,
+    Form(body): Form<DeviceAuthorizationRequest>,
+) -> Result<Json<DeviceAuthorizationResponse>, (StatusCode, String)> {
+    let client_name = body
+        .client_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or("Unnamed client");
+    let device_code = generate_device_code();
+    let user_code = generate_user_code();
+    let now = Utc::now();
+    state
+        .db
+        .create_device_code(
+            &api_tokens::hash(&device_code),
+            &user_code,
+            client_name,
+            now,
+            now + Duration::seconds(DEVICE_CODE_LIFETIME_SECS),
+        )
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let verification_uri = verification_uri(&state)?;
+    let user_code = format_user_code(&user_code);
+    Ok(Json(DeviceAuthorizationResponse {
+        device_code,
+        verification_uri_complete: format!("{verification_uri}?user_code={user_code}"),
+        verification_uri,
+        user_code,
+        expires_in: DEVICE_CODE_LIFETIME_SECS,
+        interval: POLL_INTERVAL_SECS,
+    }))
+}
+
+/// Polled by the client until the user approves or denies the request
+pub async fn post_device_token_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(body): Form<DeviceTokenRequest>,
+) -> Result<Json<DeviceTokenResponse>, Response> {
+    let internal_err = |e| eyre_to_axum_err(e).into_response();
+    if body.grant_type != DEVICE_CODE_GRANT_TYPE {
+        return Err(oauth_error(
+            "unsupported_grant_type",
+            "Only the device code grant type is supported",
+        ));
+    }
+    let device_code_hash = api_tokens::hash(&body.device_code);
+    let Some(device_code) = state
+        .db
+        .get_device_code(&device_code_hash)
+        .await
+        .map_err(internal_err)?
+    else {
+        return Err(oauth_error("invalid_grant", "Unknown device code"));
+    };
+    let now = Utc::now();
+    if device_code.expiration_date < now {
+        state
+            .db
+            .delete_device_code(&device_code_hash, now)
+            .await
+            .map_err(internal_err)?;
+        return Err(oauth_error("expired_token", "The device code has expired"));
+    }
+    if device_code.denied {
+        state
+            .db
+            .delete_device_code(&device_code_hash, now)
+            .await
+            .map_err(internal_err)?;
+        return Err(oauth_error("access_denied", "The request was denied"));
+    }
+    let Some(user_id) = device_code.user_id else {
+        let polled_too_soon = device_code
+            .last_polled_at
+            .is_some_and(|last| now < last + Duration::seconds(POLL_INTERVAL_SECS));
+        state
+            .db
+            .touch_device_code(&device_code_hash, now)
+            .await
+            .map_err(internal_err)?;
+        return Err(if polled_too_soon {
+            oauth_error("slow_down", "Polled more often than the interval allows")
+        } else {
+            oauth_error(
+                "authorization_pending",
+                "The request hasn't been approved yet",
+            )
+        });
+    };
+
+    // Only the first poll after the request is approved gets a token
+    if !state
+        .db
+        .delete_device_code(&device_code_hash, now)
+        .await
+        .map_err(internal_err)?
+    {
+        return Err(oauth_error("invalid_grant", "Unknown device code"));
+    }
+    let user = state
+        .db
+        .get_user(user_id)
+        .await
+        .map_err(internal_err)?
+        .filter(|u| u.deactivated_at.is_none())
+        .ok_or_else(|| oauth_error("access_denied", "The approving user no longer exists"))?;
+    let token = create_api_token(
+        &state,
+        &user,
+        &user,
+        client_ip(&headers),
+        CreateApiTokenBody {
+            name: format!("{} (device sign in)", device_code.client_name),
+            expires_in_days: Some(DEVICE_TOKEN_LIFETIME_DAYS),
+        },
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+    Ok(Json(DeviceTokenResponse {
+        access_token: token.token,
+        token_type: "Bearer",
+        expires_in: i64::from(DEVICE_TOKEN_LIFETIME_DAYS) * 24 * 60 * 60,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceVerificationQuery {
+    pub user_code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceVerificationResponse {
+    client_name: String,
+    expiration_date: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceVerificationBody {
+    pub user_code: String,
+    /// False to deny the request
+    pub approve: bool,
+}
+
+/// Returns the pending request with the user code the user entered
+async fn find_pending(
+    state: &AppState,
+    user_code: &str,
+) -> Result<DeviceCode, (StatusCode, String)> {
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            "That code is invalid or has expired".to_string(),
+        )
+    };
+    let device_code = state
+        .db
+        .get_device_code_by_user_code(&normalize_user_code(user_code))
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(not_found)?;
+    if device_code.expiration_date < Utc::now()
+        || device_code.user_id.is_some()
+        || device_code.denied
+    {
+        return Err(not_found());
+    }
+    Ok(device_code)
+}
+
+/// The client that's asking to sign in, so the user can check it's theirs before approving it
+pub async fn get_device_verification_handler(
+    State(state): State<AppState>,
+    _: AuthenticatedUser,
+    Query(query): Query<DeviceVerificationQuery>,
+) -> Result<Json<DeviceVerificationResponse>, (StatusCode, String)> {
+    let device_code = find_pending(&state, &query.user_code).await?;
+    Ok(Json(DeviceVerificationResponse {
+        client_name: device_code.client_name,
+        expiration_date: device_code.expiration_date,
+    }))
+}
+
+/// Approve or deny a request as the current user
+pub async fn post_device_verification_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+    Json(body): Json<DeviceVerificationBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let device_code = find_pending(&state, &body.user_code).await?;
+    if !state
+        .db
+        .resolve_device_code(&device_code.user_code, user.id, !body.approve)
+        .await
+        .map_err(eyre_to_axum_err)?
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            "That code has already been used".to_string(),
+        ));
+    }
+    let entry = audit::entry(
+        AuditCategory::Auth,
+        if body.approve {
+            "device_sign_in_approved"
+        } else {
+            "device_sign_in_denied"
+        },
+        Some(&user),
+        client_ip(&headers),
+        format!(
+            "{} the device sign in of {:?}",
+            if body.approve { "Approved" } else { "Denied" },
+            device_code.client_name
+        ),
+    );
+    let data = serde_json::json!({ "client_name": device_code.client_name });
+    audit::record_entry(&state.db, entry.with_data(&data)).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_device_route() -> Router<AppState> {
+    Router::new()
+        .route("/oauth/device", post(post_device_authorization_handler))
+        .route("/oauth/device/token", post(post_device_token_handler))
+        .route(
+            "/oauth/device/verify",
+            post(post_device_verification_handler).get(get_device_verification_handler),
+        )
+}
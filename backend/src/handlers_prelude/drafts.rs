@@ -0,0 +1,159 @@
+//! Work in progress the editor autosaves, so it can be recovered after a crash. Drafts are only
+//! stored in the database, nothing is committed until the document is saved.
+use axum::routing::get;
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Draft;
+use crate::{eyre_to_axum_err, AppState, AuthenticatedUser};
+
+/// The most documents a user can have drafts of at once
+const MAX_DRAFTS_PER_USER: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct DraftQuery {
+    /// The document's path, relative to the documents folder. Without it, every draft the user
+    /// has is listed.
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutDraftBody {
+    pub path: String,
+    pub contents: String,
+    /// The commit the document was read from before it was edited
+    pub base_commit: Option<String>,
+}
+
+/// A draft, without its contents
+#[derive(Debug, Serialize)]
+pub struct DraftSummary {
+    path: String,
+    base_commit: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DraftResponse {
+    path: String,
+    contents: String,
+    base_commit: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<Draft> for DraftResponse {
+    fn from(draft: Draft) -> Self {
+        Self {
+            path: draft.doc_path,
+            contents: draft.contents,
+            base_commit: draft.base_commit,
+            updated_at: draft.updated_at,
+        }
+    }
+}
+
+/// Returns the document's path, or an error if it's missing or empty
+fn required_path(path: Option<String>) -> Result<String, (StatusCode, String)> {
+    path.filter(|p| !p.trim().is_empty()).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            String::from("The document's path is required"),
+        )
+    })
+}
+
+/// The user's draft of the document at `path`, or if no path is given, a summary of every
+/// draft they have, most recently updated first
+pub async fn get_drafts_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<DraftQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(path) = query.path else {
+        let drafts: Vec<DraftSummary> = state
+            .db
+            .get_user_drafts(user.id)
+            .await
+            .map_err(eyre_to_axum_err)?
+            .into_iter()
+            .map(|d| DraftSummary {
+                path: d.doc_path,
+                base_commit: d.base_commit,
+                updated_at: d.updated_at,
+            })
+            .collect();
+        return Ok(Json(drafts).into_response());
+    };
+    let draft = state
+        .db
+        .get_draft(user.id, &path)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No draft of {path:?}")))?;
+    Ok(Json(DraftResponse::from(draft)).into_response())
+}
+
+/// Save a draft, replacing the user's previous draft of the document
+pub async fn put_draft_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(body): Json<PutDraftBody>,
+) -> Result<Json<DraftResponse>, (StatusCode, String)> {
+    let path = required_path(Some(body.path))?;
+    let existing = state
+        .db
+        .get_user_drafts(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if existing.len() >= MAX_DRAFTS_PER_USER && !existing.iter().any(|d| d.doc_path == path) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "You already have drafts of {MAX_DRAFTS_PER_USER} documents, save or discard some first"
+            ),
+        ));
+    }
+    let draft = Draft {
+        user_id: user.id,
+        doc_path: path,
+        contents: body.contents,
+        base_commit: body.base_commit,
+        updated_at: Utc::now(),
+    };
+    state.db.put_draft(&draft).await.map_err(eyre_to_axum_err)?;
+    Ok(Json(draft.into()))
+}
+
+/// Discard the user's draft of the document at `path`
+pub async fn delete_draft_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<DraftQuery>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let path = required_path(query.path)?;
+    let deleted = state
+        .db
+        .delete_draft(user.id, &path)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err((StatusCode::NOT_FOUND, format!("No draft of {path:?}")))
+    }
+}
+
+pub async fn create_drafts_route() -> Router<AppState> {
+    Router::new().route(
+        "/drafts",
+        get(get_drafts_handler)
+            .put(put_draft_handler)
+            .delete(delete_draft_handler),
+    )
+}
@@ -1,15 +1,29 @@
-use crate::handlers_prelude::eyre_to_axum_err;
+use crate::audit::{self, AuditCategory};
+use crate::codeowners::CodeOwners;
+use crate::db::{HydePr, User};
+use crate::gh::{
+    fill_pull_request_template, CheckSummary, Comparison, DashboardData, Milestone,
+    PagesBuildStatus, RepoMetadata,
+};
+use crate::handlers_prelude::github_link::assign_linked_account;
+use crate::handlers_prelude::owners::doc_owner_reviewers;
+use crate::handlers_prelude::{
+    client_ip, eyre_to_axum_err, require_permission, require_sign_in, AuthenticatedUser, ReadAccess,
+};
+use crate::perms::required::{MANAGE_BRANCHES, MANAGE_CONTENT, MANAGE_REPO};
+use crate::search;
 use crate::AppState;
 use axum::routing::{get, post, put};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    middleware, Json, Router,
 };
+use chrono::Utc;
 use color_eyre::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
 /// General API response structure
 #[derive(Serialize, Debug)]
@@ -20,6 +34,7 @@ pub struct ApiResponse<T> {
 }
 
 /// Error response structure
+#[allow(dead_code)]
 #[derive(Serialize, Debug)]
 pub struct ApiErrorResponse {
     pub error: String,
@@ -29,6 +44,7 @@ pub struct ApiErrorResponse {
 #[derive(Serialize, Debug)]
 pub struct CreatePRData {
     pub pull_request_url: String,
+    pub pull_request_number: u64,
 }
 
 /// Represents the structure for a branch listing response
@@ -45,6 +61,8 @@ pub struct CreatePRRequest {
     pub title: String,
     pub description: String,
     pub issue_numbers: Option<Vec<u64>>,
+    /// The number of the milestone to assign the pull request to
+    pub milestone: Option<u64>,
 }
 
 #[derive(Serialize, Debug)]
@@ -52,6 +70,7 @@ pub struct IssuesData {
     pub issues: Vec<Value>,
 }
 
+#[allow(dead_code)]
 #[derive(Serialize)]
 pub struct Issue {
     pub id: u64,
@@ -67,16 +86,31 @@ pub struct UpdatePRRequest {
     pub description: Option<String>,
     pub base_branch: Option<String>,
     pub issue_numbers: Option<Vec<u64>>,
+    /// The number of the milestone to assign the pull request to
+    pub milestone: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MilestonesQuery {
+    /// "open", "closed", or "all", defaults to "open"
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MyPullsQuery {
+    /// "open", "closed", "merged", or "all", defaults to "open"
+    pub status: Option<String>,
 }
 
 /// Fetches the list of branches from a GitHub repository.
 pub async fn list_branches_handler(
     State(state): State<AppState>,
+    _: ReadAccess,
 ) -> Result<(StatusCode, Json<ApiResponse<BranchesData>>), (StatusCode, String)> {
-    // Fetch the branch details from GitHub using the GitHubClient instance
+    // Served from the cache if possible, it's invalidated by `create` and `delete` webhook events
     let branch_details = state
-        .gh_client
-        .list_branches()
+        .repo_lists
+        .branches(&state.gh_client)
         .await
         .map_err(eyre_to_axum_err)?;
 
@@ -108,8 +142,11 @@ pub async fn list_branches_handler(
 /// Handler to create a pull request from a specified head branch to a base branch.
 pub async fn create_pull_request_handler(
     State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
     Json(payload): Json<CreatePRRequest>,
 ) -> Result<(StatusCode, Json<ApiResponse<CreatePRData>>), (StatusCode, String)> {
+    let description = pull_request_description(&state, &payload.description);
+
     // Create the pull request using the new method from GitHubClient
     match state
         .gh_client
@@ -117,23 +154,62 @@ pub async fn create_pull_request_handler(
             &payload.head_branch,
             &payload.base_branch,
             &payload.title,
-            &payload.description,
+            &description,
             payload.issue_numbers,
+            payload.milestone,
         )
         .await
     {
-        Ok(pull_request_url) => {
+        Ok(pull_request) => {
             // If the pull request creation is successful, respond with the pull request URL
             info!(
                 "Pull request created successfully from {} to {}",
                 payload.head_branch, payload.base_branch
             );
+            assign_linked_account(&state, &user, pull_request.number).await;
+            let docs_folder = format!("{}/", state.git.doc_repo_path(""));
+            let doc_paths = state
+                .git
+                .changed_files(&payload.base_branch, &payload.head_branch)
+                .unwrap_or_else(|e| {
+                    warn!("Failed to list the documents changed by the pull request: {e:?}");
+                    Vec::new()
+                })
+                .into_iter()
+                .filter_map(|p| p.strip_prefix(&docs_folder).map(ToString::to_string))
+                .collect();
+            record_hyde_pr(
+                &state,
+                pull_request.number,
+                &payload.title,
+                Some(&user),
+                &payload.head_branch,
+                doc_paths,
+            )
+            .await;
+            // Failing to find reviewers shouldn't fail the whole request, the PR already exists
+            if let Err(e) = request_code_owner_reviews(
+                &state,
+                pull_request.number,
+                &payload.base_branch,
+                &payload.head_branch,
+            )
+            .await
+            {
+                warn!(
+                    "Failed to request reviews for pull request #{}: {e:?}",
+                    pull_request.number
+                );
+            }
             Ok((
                 StatusCode::CREATED,
                 Json(ApiResponse {
                     status: "success".to_string(),
                     message: "Pull request created successfully".to_string(),
-                    data: Some(CreatePRData { pull_request_url }),
+                    data: Some(CreatePRData {
+                        pull_request_url: pull_request.html_url,
+                        pull_request_number: pull_request.number,
+                    }),
                 }),
             ))
         }
@@ -146,6 +222,79 @@ pub async fn create_pull_request_handler(
     }
 }
 
+/// Record a pull request opened through Hyde so that it's included in release notes once it's
+/// merged. The pull request already exists, so failures are only logged.
+pub(super) async fn record_hyde_pr(
+    state: &AppState,
+    pr_number: u64,
+    title: &str,
+    author: Option<&User>,
+    branch: &str,
+    doc_paths: Vec<String>,
+) {
+    let pr = HydePr {
+        number: i64::try_from(pr_number).unwrap_or(i64::MAX),
+        title: title.to_string(),
+        author_id: author.map(|u| u.id),
+        branch: Some(branch.to_string()),
+        status: "open".to_string(),
+        doc_paths,
+        created_at: Utc::now(),
+        merged_at: None,
+    };
+    if let Err(e) = state.db.record_hyde_pr(&pr).await {
+        warn!("Failed to record pull request #{pr_number}: {e:?}");
+    }
+}
+
+/// Fill in the repository's pull request template with `description`, if it has one
+pub(super) fn pull_request_description(state: &AppState, description: &str) -> String {
+    match state.git.get_pull_request_template() {
+        Ok(Some(template)) => fill_pull_request_template(&template, description),
+        Ok(None) => description.to_string(),
+        Err(e) => {
+            warn!("Failed to read the pull request template, it will not be used: {e:?}");
+            description.to_string()
+        }
+    }
+}
+
+/// Request reviews on a freshly created pull request from the `CODEOWNERS` of every file
+/// it changes and the owners of every document it changes (see [`doc_owner_reviewers`]),
+/// falling back to the configured default reviewer team.
+pub(super) async fn request_code_owner_reviews(
+    state: &AppState,
+    pr_number: u64,
+    base_branch: &str,
+    head_branch: &str,
+) -> Result<()> {
+    let codeowners = state
+        .git
+        .get_codeowners()?
+        .map(|c| CodeOwners::parse(&c))
+        .unwrap_or_default();
+    let changed_files = state.git.changed_files(base_branch, head_branch)?;
+    let mut reviewers = codeowners.reviewers_for(&changed_files);
+    for login in doc_owner_reviewers(state, pr_number, &changed_files).await? {
+        if !reviewers.users.contains(&login) {
+            reviewers.users.push(login);
+        }
+    }
+    if reviewers.is_empty() {
+        match &state.config.pull_requests.default_reviewer_team {
+            Some(team) => reviewers.teams.push(team.clone()),
+            None => {
+                debug!("No code owners or default reviewers found for pull request #{pr_number}");
+                return Ok(());
+            }
+        }
+    }
+    state
+        .gh_client
+        .request_reviewers(pr_number, &reviewers)
+        .await
+}
+
 pub async fn update_pull_request_handler(
     State(state): State<AppState>,
     Json(payload): Json<UpdatePRRequest>,
@@ -159,6 +308,7 @@ pub async fn update_pull_request_handler(
             payload.description.as_deref(),
             payload.base_branch.as_deref(),
             payload.issue_numbers,
+            payload.milestone,
         )
         .await
     {
@@ -214,6 +364,8 @@ pub async fn checkout_or_create_branch_handler(
     // Use the git interface to perform operations
     match state.git.checkout_or_create_branch(&branch_name) {
         Ok(_) => {
+            state.doc_cache.clear();
+            search::spawn_rebuild(state);
             info!("Successfully checked out/created branch: {}", branch_name);
             Ok((
                 StatusCode::OK,
@@ -238,6 +390,8 @@ pub async fn pull_handler(
     // Attempt to pull the latest changes for the specified branch
     match state.git.git_pull_branch(&branch) {
         Ok(_) => {
+            state.doc_cache.clear();
+            search::spawn_rebuild(state);
             info!("Repository pulled successfully for branch '{}'.", branch);
             Ok((
                 StatusCode::OK,
@@ -290,13 +444,13 @@ pub async fn get_current_branch_handler(
     }
 }
 
-/// Handler for fetching the default branch of the repository.
+/// Handler for fetching the default branch of the repository, served from the cached
+/// repository metadata.
 pub async fn get_default_branch_handler(
     State(state): State<AppState>,
 ) -> Result<(StatusCode, Json<ApiResponse<String>>), (StatusCode, String)> {
-    // Use the `get_default_branch` method from the `Gh` struct in AppState
-    match state.gh_client.get_default_branch().await {
-        Ok(default_branch) => {
+    match state.repo_metadata.get(&state.gh_client).await {
+        Ok(RepoMetadata { default_branch, .. }) => {
             info!("Default branch is: {}", default_branch);
 
             // Return the default branch name in the response
@@ -319,15 +473,118 @@ pub async fn get_default_branch_handler(
     }
 }
 
+/// Fetches the repository's metadata from GitHub again, replacing the cached copy. The cache is
+/// refreshed automatically on `repository` webhook events, this is for when those aren't set up.
+pub async fn refresh_repo_metadata_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<ApiResponse<RepoMetadata>>), (StatusCode, String)> {
+    match state.repo_metadata.refresh(&state.gh_client).await {
+        Ok(metadata) => {
+            audit::record(
+                &state.db,
+                AuditCategory::Repo,
+                "metadata_refreshed",
+                Some(&user),
+                client_ip(&headers),
+                "Refreshed the repository's metadata from GitHub",
+            )
+            .await;
+            Ok((
+                StatusCode::OK,
+                Json(ApiResponse {
+                    status: "success".to_string(),
+                    message: "Repository metadata refreshed successfully.".to_string(),
+                    data: Some(metadata),
+                }),
+            ))
+        }
+        Err(err) => {
+            error!("Failed to refresh repository metadata: {err:?}");
+            Err((
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to refresh repository metadata: {err}"),
+            ))
+        }
+    }
+}
+
+/// Compares two refs through the GitHub API, so it works for branches that aren't in the local
+/// clone. The path is `{base}...{head}`, where either can contain slashes.
+pub async fn compare_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Path(range): Path<String>,
+) -> Result<(StatusCode, Json<ApiResponse<Comparison>>), (StatusCode, String)> {
+    let (base, head) = range.split_once("...").ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "The comparison must be in the format `{base}...{head}`".to_string(),
+        )
+    })?;
+    match state.gh_client.compare(base, head).await {
+        Ok(comparison) => Ok((
+            StatusCode::OK,
+            Json(ApiResponse {
+                status: "success".to_string(),
+                message: "Comparison fetched successfully.".to_string(),
+                data: Some(comparison),
+            }),
+        )),
+        Err(err) => {
+            error!("Failed to compare {head:?} to {base:?}: {err:?}");
+            Err((
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to compare {head:?} to {base:?}: {err}"),
+            ))
+        }
+    }
+}
+
+/// Fetches the deployment status of the GitHub Pages site, so editors can check that the site
+/// rebuilt after their changes were merged. `data` is `None` if GitHub Pages isn't enabled.
+pub async fn get_deploy_status_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+) -> Result<(StatusCode, Json<ApiResponse<PagesBuildStatus>>), (StatusCode, String)> {
+    match state.gh_client.get_pages_build_status().await {
+        Ok(Some(status)) => Ok((
+            StatusCode::OK,
+            Json(ApiResponse {
+                status: "success".to_string(),
+                message: "Deployment status fetched successfully.".to_string(),
+                data: Some(status),
+            }),
+        )),
+        Ok(None) => Ok((
+            StatusCode::OK,
+            Json(ApiResponse {
+                status: "success".to_string(),
+                message: "GitHub Pages is not enabled for this repository.".to_string(),
+                data: None,
+            }),
+        )),
+        Err(err) => {
+            error!("Failed to fetch deployment status: {err:?}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch deployment status: {}", err),
+            ))
+        }
+    }
+}
+
 /// Handler to fetch issues from a GitHub repository.
 pub async fn get_issues_handler(
     State(state): State<AppState>,
+    _: ReadAccess,
     Path(state_param): Path<String>,
 ) -> Result<(StatusCode, Json<ApiResponse<IssuesData>>), (StatusCode, String)> {
     let state_param = state_param.as_str();
 
-    // Fetch issues using the GitHub client
-    match state.gh_client.get_issues(Some(state_param), None).await {
+    // Served from the cache if possible, it's invalidated by `issues` webhook events
+    match state.repo_lists.issues(&state.gh_client, state_param).await {
         Ok(issues) => {
             info!("Issues fetched successfully.");
             let response = ApiResponse {
@@ -346,22 +603,150 @@ pub async fn get_issues_handler(
     }
 }
 
-/// Route definitions for GitHub operations
+/// Fetches the repository's milestones, so pull requests can be assigned to them.
+pub async fn list_milestones_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Query(query): Query<MilestonesQuery>,
+) -> Result<(StatusCode, Json<ApiResponse<Vec<Milestone>>>), (StatusCode, String)> {
+    let milestones = state
+        .gh_client
+        .list_milestones(query.state.as_deref().unwrap_or("open"))
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ApiResponse {
+            status: "success".to_string(),
+            message: "Milestones fetched successfully.".to_string(),
+            data: Some(milestones),
+        }),
+    ))
+}
+
+/// Handler to fetch the branches, open pull requests, and open issues shown on the dashboard
+/// in one request.
+pub async fn get_dashboard_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+) -> Result<(StatusCode, Json<ApiResponse<DashboardData>>), (StatusCode, String)> {
+    let dashboard = state
+        .gh_client
+        .get_dashboard()
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ApiResponse {
+            status: "success".to_string(),
+            message: "Dashboard fetched successfully.".to_string(),
+            data: Some(dashboard),
+        }),
+    ))
+}
+
+/// Lists the pull requests the signed in user opened through Hyde, newest first, so they can
+/// find and resume their proposals.
+pub async fn get_my_pulls_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<MyPullsQuery>,
+) -> Result<(StatusCode, Json<ApiResponse<Vec<HydePr>>>), (StatusCode, String)> {
+    let status = match query.status.as_deref() {
+        None => Some("open"),
+        Some("all") => None,
+        Some(status @ ("open" | "closed" | "merged")) => Some(status),
+        Some(status) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown pull request status {status:?}"),
+            ))
+        }
+    };
+    let pulls = state
+        .db
+        .get_user_hyde_prs(user.id, status)
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ApiResponse {
+            status: "success".to_string(),
+            message: "Pull requests fetched successfully.".to_string(),
+            data: Some(pulls),
+        }),
+    ))
+}
+
+/// Fetches the CI status (commit statuses and check runs) of the latest commit on a branch,
+/// so editors can check that the site builds before requesting a merge.
+pub async fn get_branch_checks_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Path(branch_name): Path<String>,
+) -> Result<(StatusCode, Json<ApiResponse<CheckSummary>>), (StatusCode, String)> {
+    let checks = state
+        .gh_client
+        .get_check_runs(&branch_name)
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ApiResponse {
+            status: "success".to_string(),
+            message: format!("Checks for branch '{}' fetched successfully.", branch_name),
+            data: Some(checks),
+        }),
+    ))
+}
+
+/// Route definitions for GitHub operations. Everything requires signing in, changing the
+/// repository's branches or pull requests requires `ManageContent`, closing pull requests
+/// requires `ManageBranches`, and refreshing the repository's metadata requires `ManageRepo`.
+/// Endpoints returning the repository's contents, issues or pull requests are also gated like
+/// the documents themselves, see [`ReadAccess`].
 pub async fn github_routes() -> Router<AppState> {
+    let manage_content = || middleware::from_fn(require_permission::<MANAGE_CONTENT>);
+    let manage_branches = || middleware::from_fn(require_permission::<MANAGE_BRANCHES>);
+    let manage_repo = || middleware::from_fn(require_permission::<MANAGE_REPO>);
     Router::new()
         .route("/branches", get(list_branches_handler))
-        .route("/pulls", post(create_pull_request_handler))
+        .route("/branches/{name}/checks", get(get_branch_checks_handler))
+        .route(
+            "/pulls",
+            post(create_pull_request_handler).route_layer(manage_content()),
+        )
         .route(
             "/checkout/branches/{branch_name}",
-            put(checkout_or_create_branch_handler),
+            put(checkout_or_create_branch_handler).route_layer(manage_content()),
+        )
+        .route("/pulls/mine", get(get_my_pulls_handler))
+        .route(
+            "/pulls/update",
+            put(update_pull_request_handler).route_layer(manage_content()),
         )
-        .route("/pulls/update", put(update_pull_request_handler))
         .route(
             "/pull-requests/{pr_number}/close",
-            post(close_pull_request_handler),
+            post(close_pull_request_handler).route_layer(manage_branches()),
+        )
+        .route(
+            "/pull/{branch}",
+            post(pull_handler).route_layer(manage_content()),
         )
-        .route("/pull/{branch}", post(pull_handler))
         .route("/current-branch", get(get_current_branch_handler))
         .route("/issues/{state}", get(get_issues_handler))
         .route("/repos/default-branch", get(get_default_branch_handler))
+        .route(
+            "/repos/refresh",
+            post(refresh_repo_metadata_handler).route_layer(manage_repo()),
+        )
+        .route("/repo/deploy-status", get(get_deploy_status_handler))
+        .route("/repo/compare/{*range}", get(compare_handler))
+        .route("/dashboard", get(get_dashboard_handler))
+        .route("/milestones", get(list_milestones_handler))
+        .route_layer(middleware::from_fn(require_sign_in))
 }
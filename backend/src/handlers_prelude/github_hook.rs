@@ -1,25 +1,269 @@
 //! Github Webhook events are sent here
 
-use axum::routing::post;
-use axum::{extract::State, http::HeaderMap, Router};
-use tracing::{debug, error, info};
+use axum::routing::{get, post};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json, Router,
+};
+use reqwest::StatusCode;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
 
-use crate::AppState;
+use crate::db::{WebhookEvent, WebhookEventFilter, WebhookQueueStatus};
+use crate::replica::token_matches;
+use crate::{eyre_to_axum_err, perms::required::MANAGE_REPO, AppState, RequirePermission};
 
-pub async fn github_hook_handler(State(state): State<AppState>, headers: HeaderMap) {
-    let event_type = headers.get("x-github-event").unwrap().to_str().unwrap();
-    debug!("Received Github webhook event of type {event_type:?}");
-    if event_type == "push" {
-        info!("New changes pushed to Github, pulling changes...");
-        match state.git.pull() {
-            Ok(_) => {}
-            Err(e) => {
-                error!("Failed to auto-pull changes with error: {e:?}");
-            }
-        }
+/// How many of the most recent events are included in the queue status
+const RECENT_EVENTS_LIMIT: i64 = 50;
+
+#[derive(Debug, Serialize)]
+pub struct WebhookQueueResponse {
+    #[serde(flatten)]
+    status: WebhookQueueStatus,
+    /// The most recently received events, newest first
+    recent_events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveriesQuery {
+    #[serde(flatten)]
+    filter: WebhookEventFilter,
+    /// The maximum number of deliveries to return
+    #[serde(default = "default_deliveries_limit")]
+    limit: i64,
+}
+
+const fn default_deliveries_limit() -> i64 {
+    50
+}
+
+/// Requests for more deliveries than this are clamped
+const MAX_DELIVERIES_LIMIT: i64 = 500;
+
+#[derive(Debug, Serialize)]
+pub struct WebhookDeliveriesResponse {
+    #[serde(flatten)]
+    status: WebhookQueueStatus,
+    /// The deliveries matching the query, newest first. Pass the last one's `id` as `before`
+    /// to get the next page.
+    deliveries: Vec<WebhookEvent>,
+}
+
+/// The header proxies in front of Hyde can pass the webhook token in, see
+/// [`crate::app_conf::GitHubOAuth::webhook_token`]
+const WEBHOOK_TOKEN_HEADER: &str = "x-hyde-webhook-token";
+
+/// Returns an error unless the request has the webhook token, if one is configured. `path_token`
+/// is the token in the webhook URL, if there is one.
+fn require_webhook_token(
+    state: &AppState,
+    headers: &HeaderMap,
+    path_token: Option<&str>,
+) -> Result<(), (StatusCode, String)> {
+    let Some(expected) = &state.config.oauth.github.webhook_token else {
+        return Ok(());
+    };
+    let header_token = headers
+        .get(WEBHOOK_TOKEN_HEADER)
+        .and_then(|h| h.to_str().ok());
+    if [path_token, header_token]
+        .into_iter()
+        .flatten()
+        .any(|provided| token_matches(expected, provided))
+    {
+        Ok(())
+    } else {
+        warn!("Turned away a webhook event without a valid webhook token");
+        Err((
+            StatusCode::UNAUTHORIZED,
+            "Missing or invalid webhook token".to_string(),
+        ))
+    }
+}
+
+/// The header GitHub sends the HMAC-SHA256 signature of the payload in
+const SIGNATURE_HEADER: &str = "x-hub-signature-256";
+
+/// Whether `signature`, as sent in the `X-Hub-Signature-256` header (`sha256=<hex digest>`), is
+/// the signature of `payload` with `secret`
+fn signature_matches(secret: &str, payload: &[u8], signature: &str) -> bool {
+    let Some(digest) = signature.strip_prefix("sha256=").and_then(decode_hex) else {
+        return false;
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, payload, &digest).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
     }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Returns an error unless the payload is signed with the webhook secret, if one is configured
+fn require_signature(
+    state: &AppState,
+    headers: &HeaderMap,
+    payload: &str,
+) -> Result<(), (StatusCode, String)> {
+    let Some(secret) = &state.config.oauth.github.webhook_secret else {
+        return Ok(());
+    };
+    let signature = headers.get(SIGNATURE_HEADER).and_then(|h| h.to_str().ok());
+    if signature.is_some_and(|signature| signature_matches(secret, payload.as_bytes(), signature)) {
+        Ok(())
+    } else {
+        warn!("Turned away a webhook event without a valid signature");
+        Err((
+            StatusCode::UNAUTHORIZED,
+            "Missing or invalid webhook signature".to_string(),
+        ))
+    }
+}
+
+/// Queue the event to be processed in the background, so it isn't lost if processing fails
+pub async fn github_hook_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    payload: String,
+) -> Result<StatusCode, (StatusCode, String)> {
+    require_webhook_token(&state, &headers, None)?;
+    require_signature(&state, &headers, &payload)?;
+    queue_event(&state, &headers, &payload).await
+}
+
+/// [`github_hook_handler`], with the webhook token in the URL for setups where it can't be sent
+/// in a header, like GitHub's own webhook settings
+pub async fn github_hook_with_token_handler(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    payload: String,
+) -> Result<StatusCode, (StatusCode, String)> {
+    require_webhook_token(&state, &headers, Some(&token))?;
+    require_signature(&state, &headers, &payload)?;
+    queue_event(&state, &headers, &payload).await
+}
+
+async fn queue_event(
+    state: &AppState,
+    headers: &HeaderMap,
+    payload: &str,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let event_type = headers
+        .get("x-github-event")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Missing X-GitHub-Event header".to_string(),
+            )
+        })?;
+    let delivery_id = headers
+        .get("x-github-delivery")
+        .and_then(|h| h.to_str().ok());
+    debug!("Received Github webhook event of type {event_type:?}");
+    state
+        .webhook_queue
+        .enqueue(state, event_type, delivery_id, payload)
+        .await
+        .map_err(|e| {
+            error!("Failed to queue webhook event: {e:?}");
+            eyre_to_axum_err(e)
+        })?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Returns how many webhook events are pending, completed and failed, along with the
+/// most recent events
+pub async fn get_webhook_queue_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_REPO>,
+) -> Result<Json<WebhookQueueResponse>, (StatusCode, String)> {
+    let status = state
+        .db
+        .get_webhook_queue_status()
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let recent_events = state
+        .db
+        .get_recent_webhook_events(RECENT_EVENTS_LIMIT)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(WebhookQueueResponse {
+        status,
+        recent_events,
+    }))
+}
+
+/// Returns the webhook deliveries GitHub has made, newest first, filtered by
+/// `?event_type=&status=&before=`, along with how many are pending, completed and failed.
+pub async fn get_webhook_deliveries_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_REPO>,
+    Query(query): Query<WebhookDeliveriesQuery>,
+) -> Result<Json<WebhookDeliveriesResponse>, (StatusCode, String)> {
+    let status = state
+        .db
+        .get_webhook_queue_status()
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let deliveries = state
+        .db
+        .query_webhook_events(&query.filter, query.limit.clamp(0, MAX_DELIVERIES_LIMIT))
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(WebhookDeliveriesResponse { status, deliveries }))
 }
 
 pub async fn create_github_route() -> Router<AppState> {
-    Router::new().route("/hooks/github", post(github_hook_handler))
+    Router::new()
+        .route("/hooks/github", post(github_hook_handler))
+        .route("/hooks/github/queue", get(get_webhook_queue_handler))
+        .route("/admin/webhooks", get(get_webhook_deliveries_handler))
+        .route(
+            "/hooks/github/{token}",
+            post(github_hook_with_token_handler),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures() {
+        // The example from GitHub's documentation on validating webhook deliveries
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(signature_matches(
+            "It's a Secret to Everybody",
+            b"Hello, World!",
+            signature
+        ));
+        assert!(
+            !signature_matches("It's a Secret to Everybody", b"Hello, World?", signature),
+            "signature_matches: a changed payload should be rejected"
+        );
+        assert!(!signature_matches(
+            "another secret",
+            b"Hello, World!",
+            signature
+        ));
+        assert!(!signature_matches(
+            "It's a Secret to Everybody",
+            b"Hello, World!",
+            "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        ));
+        assert!(!signature_matches(
+            "It's a Secret to Everybody",
+            b"Hello, World!",
+            "sha256=zz"
+        ));
+    }
 }
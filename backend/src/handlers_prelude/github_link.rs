@@ -0,0 +1,213 @@
+//! Linking Hyde users to their GitHub accounts, so that contribution graphs credit the people
+//! who actually made a change. Commits are made by Hyde, but list the linked account in a
+//! `Co-authored-by` trailer, and pull requests are assigned to it.
+use axum::routing::get;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Redirect,
+    Router,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use super::{client_ip, public_url, record_identity_linked};
+use crate::db::{GitHubAccount, User};
+use crate::identity::IdentityProvider;
+use crate::{eyre_to_axum_err, AppState, AuthenticatedUser};
+
+/// The cookie the OAuth `state` parameter is stored in while the user is on GitHub
+const STATE_COOKIE: &str = "github-link-state";
+
+#[derive(Debug, Deserialize)]
+pub struct GitHubLinkCallbackQuery {
+    pub code: String,
+    pub state: String,
+}
+
+/// Append a `Co-authored-by` trailer crediting `user`'s linked GitHub account to a commit
+/// message. The message is returned unchanged if they haven't linked an account.
+pub async fn with_co_author(state: &AppState, user: &User, message: String) -> String {
+    match state.db.get_github_account(user.id).await {
+        Ok(Some(account)) => format!(
+            "{message}\n\nCo-authored-by: {} <{}+{}@users.noreply.github.com>",
+            account.github_login, account.github_id, account.github_login
+        ),
+        Ok(None) => message,
+        Err(e) => {
+            warn!(
+                "Failed to look up the GitHub account of {:?}: {e:?}",
+                user.username
+            );
+            message
+        }
+    }
+}
+
+/// Assign a pull request to `user`'s linked GitHub account, if they've linked one. The pull
+/// request already exists, so failures are only logged.
+pub async fn assign_linked_account(state: &AppState, user: &User, pr_number: u64) {
+    let account = match state.db.get_github_account(user.id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(
+                "Failed to look up the GitHub account of {:?}: {e:?}",
+                user.username
+            );
+            return;
+        }
+    };
+    if let Err(e) = state
+        .gh_client
+        .add_assignees(pr_number, &[&account.github_login])
+        .await
+    {
+        warn!("Failed to assign pull request #{pr_number} to {account:?}: {e:?}");
+    }
+}
+
+/// Where GitHub sends users back to after they authorize Hyde
+fn callback_url(state: &AppState) -> Result<String, (StatusCode, String)> {
+    Ok(format!("{}/api/github/link/callback", public_url(state)?))
+}
+
+fn client_secret(state: &AppState) -> Result<&str, (StatusCode, String)> {
+    state
+        .config
+        .oauth
+        .github
+        .client_secret
+        .as_deref()
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "GitHub account linking isn't enabled, `oauth.github.client_secret` isn't set"
+                    .to_string(),
+            )
+        })
+}
+
+/// Send the user to GitHub to authorize Hyde, after which they're sent back to
+/// [`get_github_link_callback_handler`]
+pub async fn get_github_link_handler(
+    State(state): State<AppState>,
+    _: AuthenticatedUser,
+) -> Result<(HeaderMap, Redirect), (StatusCode, String)> {
+    client_secret(&state)?;
+
+    let csrf_state: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    let url = reqwest::Url::parse_with_params(
+        "https://github.com/login/oauth/authorize",
+        &[
+            ("client_id", state.config.oauth.github.client_id.as_str()),
+            ("redirect_uri", &callback_url(&state)?),
+            ("state", &csrf_state),
+        ],
+    )
+    .map_err(|e| eyre_to_axum_err(e.into()))?;
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.append(
+        "Set-Cookie",
+        format!(
+            "{STATE_COOKIE}={csrf_state}; Secure; HttpOnly; SameSite=Lax; Path=/api/github/link; Max-Age=600"
+        )
+        .parse()
+        .map_err(|e: axum::http::header::InvalidHeaderValue| eyre_to_axum_err(e.into()))?,
+    );
+    Ok((response_headers, Redirect::to(url.as_str())))
+}
+
+/// GitHub sends users here after they authorize Hyde. Their account is linked, then they're
+/// sent back to the homepage.
+pub async fn get_github_link_callback_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+    Query(query): Query<GitHubLinkCallbackQuery>,
+) -> Result<(HeaderMap, Redirect), (StatusCode, String)> {
+    let client_secret = client_secret(&state)?;
+
+    let expected_state = headers
+        .get_all("Cookie")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split("; "))
+        .find_map(|cookie| cookie.strip_prefix(&format!("{STATE_COOKIE}=")));
+    if expected_state != Some(query.state.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The link request expired or didn't come from Hyde, try linking your account again"
+                .to_string(),
+        ));
+    }
+
+    let github_user = state
+        .gh_client
+        .get_linked_user(client_secret, &query.code, &callback_url(&state)?)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let account = GitHubAccount {
+        github_id: github_user.id,
+        github_login: github_user.login,
+    };
+    state
+        .db
+        .link_github_account(user.id, &account)
+        .await
+        .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+    record_identity_linked(
+        &state,
+        &user,
+        client_ip(&headers),
+        IdentityProvider::GitHub,
+        &account.github_login,
+    )
+    .await;
+
+    let redirect = if cfg!(debug_assertions) {
+        Redirect::to("http://localhost:5173/")
+    } else {
+        Redirect::to("/")
+    };
+    let mut response_headers = HeaderMap::new();
+    response_headers.append(
+        "Set-Cookie",
+        format!("{STATE_COOKIE}=; Secure; HttpOnly; Path=/api/github/link; Max-Age=0")
+            .parse()
+            .map_err(|e: axum::http::header::InvalidHeaderValue| eyre_to_axum_err(e.into()))?,
+    );
+    Ok((response_headers, redirect))
+}
+
+/// Unlink the current user's GitHub account
+pub async fn delete_github_link_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .db
+        .unlink_github_account(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    info!("User {:?} unlinked their GitHub account", user.username);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_github_link_route() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/github/link",
+            get(get_github_link_handler).delete(delete_github_link_handler),
+        )
+        .route(
+            "/github/link/callback",
+            get(get_github_link_callback_handler),
+        )
+}
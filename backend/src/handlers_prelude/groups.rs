@@ -8,11 +8,14 @@ use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
+use super::{client_ip, parse_expiry, require_second_factor};
 use crate::{
+    audit::{self, AuditCategory},
     db::{Database, Group},
     eyre_to_axum_err,
-    perms::Permission,
-    require_perms, AppState,
+    perms::{required::MANAGE_USERS, Permission},
+    webhook_queue::timestamp,
+    AppState, AuthenticatedUser, RequirePermission,
 };
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -28,6 +31,12 @@ pub struct GroupResponse {
     name: String,
     permissions: Vec<Permission>,
     members: Vec<Member>,
+    /// The groups whose members can be managed by members of this group
+    managed_groups: Vec<i64>,
+    /// The branch patterns members of this group may push to, see [`crate::branch_rules`]
+    branch_patterns: Vec<String>,
+    /// The ids of the users who can manage this group's members
+    owners: Vec<i64>,
 }
 
 pub async fn create_group_response(
@@ -44,6 +53,21 @@ pub async fn create_group_response(
         .await
         .map_err(eyre_to_axum_err)?;
 
+    let managed_groups = db
+        .get_group_admin_scopes(group.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    let branch_patterns = db
+        .get_group_branch_rules(group.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    let owners = db
+        .get_group_owners(group.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+
     Ok(GroupResponse {
         id: group.id,
         name: group.name,
@@ -56,20 +80,43 @@ pub async fn create_group_response(
                 avatar_url: m.avatar_url,
             })
             .collect::<Vec<_>>(),
+        managed_groups,
+        branch_patterns,
+        owners,
     })
 }
 
 pub async fn get_groups_handler(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthenticatedUser(user): AuthenticatedUser,
 ) -> Result<Json<Vec<GroupResponse>>, (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
+    let is_admin = state
+        .db
+        .get_user_permissions(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .contains(&Permission::ManageUsers);
+    // Delegated admins only see the groups they manage
+    let managed_groups = state
+        .db
+        .get_user_managed_groups(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !is_admin && managed_groups.is_empty() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("User {:?} doesn't manage any groups.", user.username),
+        ));
+    }
 
     match state.db.get_all_groups().await {
         Ok(groups) => {
             let mut get_groups_response = Vec::new();
 
             for group in groups {
+                if !is_admin && !managed_groups.contains(&group.id) {
+                    continue;
+                }
                 get_groups_response.push(create_group_response(&state.db, group).await?);
             }
 
@@ -94,37 +141,42 @@ pub struct CreateGroupRequestBody {
 
 pub async fn post_group_handler(
     State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
     headers: HeaderMap,
     Json(body): Json<CreateGroupRequestBody>,
 ) -> Result<Json<GroupResponse>, (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
-
-    Ok(Json(
-        create_group_response(
-            &state.db,
-            state
-                .db
-                .create_group(body.group_name)
-                .await
-                .map_err(eyre_to_axum_err)?,
-        )
-        .await?,
-    ))
+    let group = state
+        .db
+        .create_group(body.group_name)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    audit::record(
+        &state.db,
+        AuditCategory::Perms,
+        "group_created",
+        Some(&user),
+        client_ip(&headers),
+        format!("Created the group {:?} ({})", group.name, group.id),
+    )
+    .await;
+    Ok(Json(create_group_response(&state.db, group).await?))
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct UpdateGroupPermissionsRequestBody {
     permissions: Vec<Permission>,
+    /// When permissions being added expire, an RFC-3339 timestamp. They don't if unset.
+    #[serde(default)]
+    expires_at: Option<String>,
 }
 
 pub async fn put_group_permissions_handler(
     State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
     headers: HeaderMap,
     Path(group_id): Path<i64>,
     Json(body): Json<UpdateGroupPermissionsRequestBody>,
 ) -> Result<Json<GroupResponse>, (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
-
     let current_permissions = state
         .db
         .get_group_permissions(group_id)
@@ -132,31 +184,73 @@ pub async fn put_group_permissions_handler(
         .map_err(eyre_to_axum_err)?;
 
     let new_permissions = body.permissions;
+    let expires_at = parse_expiry(body.expires_at.as_deref())?;
+    if let Some(unknown) = new_permissions
+        .iter()
+        .find(|perm| !perm.is_declared(&state.config.auth.custom_permissions))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "{:?} isn't a permission, custom permissions have to be declared in \
+                    auth.custom_permissions",
+                String::from(unknown.clone())
+            ),
+        ));
+    }
 
     let permissions_to_remove = current_permissions
         .iter()
         .filter(|perm| !new_permissions.contains(perm))
+        .cloned()
         .collect::<Vec<_>>();
 
     let permissions_to_add = new_permissions
         .iter()
         .filter(|perm| !current_permissions.contains(perm))
+        .cloned()
         .collect::<Vec<_>>();
 
+    state
+        .db
+        .update_group_permissions(
+            group_id,
+            &permissions_to_remove,
+            &permissions_to_add,
+            expires_at,
+        )
+        .await
+        .map_err(eyre_to_axum_err)?;
+
     for perm in permissions_to_remove {
-        state
-            .db
-            .remove_group_permission(group_id, *perm)
-            .await
-            .map_err(eyre_to_axum_err)?;
+        audit::record(
+            &state.db,
+            AuditCategory::Perms,
+            "permission_revoked",
+            Some(&user),
+            client_ip(&headers),
+            format!(
+                "Revoked {} from group {group_id}",
+                String::from(perm.clone())
+            ),
+        )
+        .await;
     }
 
     for perm in permissions_to_add {
-        state
-            .db
-            .add_group_permission(group_id, *perm)
-            .await
-            .map_err(eyre_to_axum_err)?;
+        audit::record(
+            &state.db,
+            AuditCategory::Perms,
+            "permission_granted",
+            Some(&user),
+            client_ip(&headers),
+            format!(
+                "Granted {} to group {group_id} until {}",
+                String::from(perm.clone()),
+                expires_at.map_or_else(|| "it's revoked".to_string(), timestamp)
+            ),
+        )
+        .await;
     }
 
     Ok(Json(
@@ -173,18 +267,205 @@ pub async fn put_group_permissions_handler(
     ))
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct UpdateManagedGroupsRequestBody {
+    managed_group_ids: Vec<i64>,
+}
+
+/// Delegate membership management of other groups to the members of a group.
+pub async fn put_managed_groups_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(group_id): Path<i64>,
+    Json(body): Json<UpdateManagedGroupsRequestBody>,
+) -> Result<Json<GroupResponse>, (StatusCode, String)> {
+    let group = state
+        .db
+        .get_group(group_id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("No group with id {group_id}"),
+            )
+        })?;
+    for managed_group_id in &body.managed_group_ids {
+        if state
+            .db
+            .get_group(*managed_group_id)
+            .await
+            .map_err(eyre_to_axum_err)?
+            .is_none()
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("No group with id {managed_group_id}"),
+            ));
+        }
+    }
+
+    state
+        .db
+        .set_group_admin_scopes(group_id, &body.managed_group_ids)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    audit::record(
+        &state.db,
+        AuditCategory::Perms,
+        "managed_groups_changed",
+        Some(&user),
+        client_ip(&headers),
+        format!(
+            "Let members of group {group_id} manage the groups {:?}",
+            body.managed_group_ids
+        ),
+    )
+    .await;
+
+    Ok(Json(create_group_response(&state.db, group).await?))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateBranchPatternsRequestBody {
+    branch_patterns: Vec<String>,
+}
+
+/// Replace the branch patterns members of a group may push to. Once a branch matches any
+/// group's pattern, only members of the groups with a matching pattern may push to it.
+pub async fn put_branch_patterns_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(group_id): Path<i64>,
+    Json(body): Json<UpdateBranchPatternsRequestBody>,
+) -> Result<Json<GroupResponse>, (StatusCode, String)> {
+    let group = state
+        .db
+        .get_group(group_id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("No group with id {group_id}"),
+            )
+        })?;
+    if body
+        .branch_patterns
+        .iter()
+        .any(|pattern| pattern.trim().is_empty())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            String::from("Branch patterns can't be empty"),
+        ));
+    }
+
+    state
+        .db
+        .set_group_branch_rules(group_id, &body.branch_patterns)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    audit::record(
+        &state.db,
+        AuditCategory::Perms,
+        "branch_patterns_changed",
+        Some(&user),
+        client_ip(&headers),
+        format!(
+            "Let members of group {group_id} push to the branches {:?}",
+            body.branch_patterns
+        ),
+    )
+    .await;
+
+    Ok(Json(create_group_response(&state.db, group).await?))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateGroupOwnersRequestBody {
+    owner_ids: Vec<i64>,
+}
+
+/// Replace the owners of a group, who can add and remove its members without `ManageUsers`.
+pub async fn put_group_owners_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(group_id): Path<i64>,
+    Json(body): Json<UpdateGroupOwnersRequestBody>,
+) -> Result<Json<GroupResponse>, (StatusCode, String)> {
+    let group = state
+        .db
+        .get_group(group_id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("No group with id {group_id}"),
+            )
+        })?;
+    for owner_id in &body.owner_ids {
+        if state
+            .db
+            .get_user(*owner_id)
+            .await
+            .map_err(eyre_to_axum_err)?
+            .is_none()
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("No user with id {owner_id}"),
+            ));
+        }
+    }
+
+    state
+        .db
+        .set_group_owners(group_id, &body.owner_ids)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    audit::record(
+        &state.db,
+        AuditCategory::Perms,
+        "owners_changed",
+        Some(&user),
+        client_ip(&headers),
+        format!(
+            "Made the users {:?} the owners of group {group_id}",
+            body.owner_ids
+        ),
+    )
+    .await;
+
+    Ok(Json(create_group_response(&state.db, group).await?))
+}
+
 pub async fn delete_group_handler(
     State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
     headers: HeaderMap,
     Path(group_id): Path<i64>,
 ) -> Result<(), (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
-
+    require_second_factor(&state, &user, &headers).await?;
     state
         .db
         .delete_group(group_id)
         .await
-        .map_err(eyre_to_axum_err)
+        .map_err(eyre_to_axum_err)?;
+    audit::record(
+        &state.db,
+        AuditCategory::Perms,
+        "group_deleted",
+        Some(&user),
+        client_ip(&headers),
+        format!("Deleted group {group_id}"),
+    )
+    .await;
+    Ok(())
 }
 
 pub async fn create_group_route() -> Router<AppState> {
@@ -195,4 +476,13 @@ pub async fn create_group_route() -> Router<AppState> {
             "/groups/{group_id}/permissions",
             put(put_group_permissions_handler),
         )
+        .route(
+            "/groups/{group_id}/managed-groups",
+            put(put_managed_groups_handler),
+        )
+        .route(
+            "/groups/{group_id}/branches",
+            put(put_branch_patterns_handler),
+        )
+        .route("/groups/{group_id}/owners", put(put_group_owners_handler))
 }
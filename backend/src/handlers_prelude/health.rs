@@ -0,0 +1,45 @@
+//! A health check for load balancers and uptime monitors
+use std::time::Duration;
+
+use axum::routing::get;
+use axum::{extract::State, http::StatusCode, Json, Router};
+use serde::Serialize;
+use tracing::error;
+
+use crate::AppState;
+
+/// How long the database has to answer before the instance is reported unhealthy. Load
+/// balancers usually give up on a health check after a few seconds.
+const DATABASE_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    /// `ok`, or `unavailable` if any of the checks failed
+    status: &'static str,
+    /// Whether the database answered a query in time, see [`crate::db::Database::ping`]
+    database: bool,
+}
+
+/// Returns `200 OK` if this instance can serve requests, and `503 Service Unavailable` if it
+/// can't. Doesn't need signing in.
+pub async fn get_health_handler(
+    State(state): State<AppState>,
+) -> (StatusCode, Json<HealthResponse>) {
+    let database = match state.db.ping(DATABASE_PING_TIMEOUT).await {
+        Ok(()) => true,
+        Err(e) => {
+            error!("Health check failed, the database isn't responding: {e:?}");
+            false
+        }
+    };
+    let (code, status) = if database {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+    (code, Json(HealthResponse { status, database }))
+}
+
+pub async fn create_health_route() -> Router<AppState> {
+    Router::new().route("/health", get(get_health_handler))
+}
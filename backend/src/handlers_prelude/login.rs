@@ -0,0 +1,647 @@
+//! Signing in with any enabled identity provider, and linking more providers to an account, see
+//! [`crate::identity`]. Discord sends users back to [`super::get_oauth2_handler`] instead of
+//! the callback here, because that's the redirect URL existing Discord apps are set up with.
+use axum::routing::{delete, get};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::USER_AGENT, HeaderMap, StatusCode},
+    response::Redirect,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use oauth2::CsrfToken;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Deserialize;
+use tracing::info;
+
+use super::{
+    client_ip, discord_authorize_url, stored_discord_account_id, SESSION_COOKIE,
+    SESSION_COOKIE_MAX_AGE, SESSION_LIFETIME,
+};
+use crate::api_tokens;
+use crate::audit::{self, AuditCategory};
+use crate::db::{GitHubAccount, Session, User, UserIdentity};
+use crate::identity::{fetch_oidc_identity, ExternalIdentity, IdentityProvider};
+use crate::{eyre_to_axum_err, require_perms, AppState, AuthenticatedUser};
+
+/// The cookie the OAuth `state` parameter and what the user is doing are stored in while
+/// they're on the provider's site
+const LOGIN_STATE_COOKIE: &str = "login-state";
+
+/// Why a user was sent to an identity provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginIntent {
+    SignIn,
+    /// Add the account to the signed in user
+    Link,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginQuery {
+    #[serde(default)]
+    pub link: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginCallbackQuery {
+    pub code: String,
+    pub state: String,
+}
+
+fn parse_provider(
+    state: &AppState,
+    provider: &str,
+) -> Result<IdentityProvider, (StatusCode, String)> {
+    let provider =
+        IdentityProvider::try_from(provider).map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    require_provider(state, provider)?;
+    Ok(provider)
+}
+
+pub(super) fn require_provider(
+    state: &AppState,
+    provider: IdentityProvider,
+) -> Result<(), (StatusCode, String)> {
+    if state.config.auth.is_enabled(provider) {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            format!(
+                "Signing in with {} is not enabled on this instance.",
+                provider.as_str()
+            ),
+        ))
+    }
+}
+
+/// [`crate::app_conf::AppConf::public_url`], which providers send users back to
+pub(super) fn public_url(state: &AppState) -> Result<String, (StatusCode, String)> {
+    state.config.public_url().ok_or_else(|| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "`auth.public_url` isn't set, so providers can't be told where to send users back to"
+                .to_string(),
+        )
+    })
+}
+
+/// Where the provider sends users back to after they sign in
+fn redirect_uri(
+    state: &AppState,
+    provider: IdentityProvider,
+) -> Result<String, (StatusCode, String)> {
+    let public_url = public_url(state)?;
+    Ok(match provider {
+        IdentityProvider::Discord => format!("{public_url}/api/oauth"),
+        _ => format!("{public_url}/api/login/{}/callback", provider.as_str()),
+    })
+}
+
+/// A random OAuth `state` parameter
+pub(super) fn new_csrf_state() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// The `Set-Cookie` header remembering the `state` a user is sent to a provider with, and why
+pub(super) fn login_state_cookie(
+    csrf_state: &str,
+    intent: LoginIntent,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let intent = match intent {
+        LoginIntent::SignIn => "sign-in",
+        LoginIntent::Link => "link",
+    };
+    let mut headers = HeaderMap::new();
+    headers.append(
+        "Set-Cookie",
+        format!(
+            "{LOGIN_STATE_COOKIE}={csrf_state}:{intent}; Secure; HttpOnly; SameSite=Lax; Path=/api; Max-Age=600"
+        )
+        .parse()
+        .map_err(|e: axum::http::header::InvalidHeaderValue| eyre_to_axum_err(e.into()))?,
+    );
+    Ok(headers)
+}
+
+/// Check the `state` a provider sent the user back with against the one they left with, see
+/// [`login_state_cookie`]
+pub(super) fn login_intent(
+    headers: &HeaderMap,
+    query_state: &str,
+) -> Result<LoginIntent, (StatusCode, String)> {
+    let cookie = headers
+        .get_all("Cookie")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split("; "))
+        .find_map(|cookie| cookie.strip_prefix(&format!("{LOGIN_STATE_COOKIE}=")));
+    match cookie.and_then(|cookie| cookie.split_once(':')) {
+        Some((csrf_state, "link")) if csrf_state == query_state => Ok(LoginIntent::Link),
+        Some((csrf_state, _)) if csrf_state == query_state => Ok(LoginIntent::SignIn),
+        _ => Err((
+            StatusCode::BAD_REQUEST,
+            "The sign in request expired or didn't come from Hyde, try signing in again"
+                .to_string(),
+        )),
+    }
+}
+
+/// Link an account to a user
+async fn link(
+    state: &AppState,
+    user_id: i64,
+    identity: &ExternalIdentity,
+) -> color_eyre::Result<()> {
+    if identity.provider == IdentityProvider::GitHub {
+        // Linked GitHub accounts are also credited on commits, see `github_link`
+        state
+            .db
+            .link_github_account(
+                user_id,
+                &GitHubAccount {
+                    github_id: identity.subject.parse()?,
+                    github_login: identity.username.clone(),
+                },
+            )
+            .await
+    } else {
+        state
+            .db
+            .link_identity(&UserIdentity {
+                provider: identity.provider.as_str().to_string(),
+                subject: identity.subject.clone(),
+                user_id,
+                username: identity.username.clone(),
+            })
+            .await
+    }
+}
+
+/// Record that `user` linked an account in the audit log
+pub(super) async fn record_identity_linked(
+    state: &AppState,
+    user: &User,
+    ip_address: Option<String>,
+    provider: IdentityProvider,
+    account: &str,
+) {
+    let provider = provider.as_str();
+    let entry = audit::entry(
+        AuditCategory::Auth,
+        "identity_linked",
+        Some(user),
+        ip_address,
+        format!("Linked the {provider} account {account:?}"),
+    );
+    let data = serde_json::json!({ "provider": provider, "account": account });
+    audit::record_entry(&state.db, entry.with_data(&data)).await;
+}
+
+/// Before accounts were linked, Discord users were matched by username. Most are linked to
+/// their Discord account when Hyde starts (see [`link_legacy_discord_users`]), the rest are
+/// matched the first time they sign in since. Usernames can be taken by someone else after a
+/// rename, so a user is only matched if their stored Discord token belongs to the account
+/// signing in.
+async fn find_legacy_discord_user(
+    state: &AppState,
+    identity: &ExternalIdentity,
+) -> color_eyre::Result<Option<User>> {
+    for user in state.db.get_all_users().await? {
+        if user.username != identity.username || user.token.is_empty() || user.service_account {
+            continue;
+        }
+        let identities = state.db.get_user_identities(user.id).await?;
+        if identities.iter().any(|i| i.provider == "discord") {
+            continue;
+        }
+        if stored_discord_account_id(state, user.id).await?.as_deref() == Some(&identity.subject) {
+            return Ok(Some(user));
+        }
+    }
+    Ok(None)
+}
+
+/// Find the user an account belongs to, creating one if the account is new, or link the
+/// account to the signed in user
+pub(super) async fn sign_in_user(
+    state: &AppState,
+    headers: HeaderMap,
+    identity: &ExternalIdentity,
+    intent: LoginIntent,
+) -> Result<User, (StatusCode, String)> {
+    let provider = identity.provider.as_str();
+    if intent == LoginIntent::Link {
+        let ip_address = client_ip(&headers);
+        let user = require_perms(State(state), headers, &[]).await?;
+        link(state, user.id, identity)
+            .await
+            .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+        record_identity_linked(
+            state,
+            &user,
+            ip_address,
+            identity.provider,
+            &identity.username,
+        )
+        .await;
+        return Ok(user);
+    }
+
+    let existing_user = state
+        .db
+        .get_identity_user(provider, &identity.subject)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let user = if let Some(user) = existing_user {
+        info!("User {:?} signed in with {provider}", user.username);
+        user
+    } else if let Some(user) = match identity.provider {
+        IdentityProvider::Discord => find_legacy_discord_user(state, identity)
+            .await
+            .map_err(eyre_to_axum_err)?,
+        _ => None,
+    } {
+        info!(
+            "User {:?} signed in with Discord for the first time since accounts could be linked",
+            user.username
+        );
+        user
+    } else {
+        // Discord tokens are filled in by the Discord handler
+        let user = state
+            .db
+            .create_user(
+                identity.username.clone(),
+                String::new(),
+                DateTime::UNIX_EPOCH,
+                identity.avatar_url.clone(),
+            )
+            .await
+            .map_err(eyre_to_axum_err)?;
+        info!(
+            "New user {:?} signed in with {provider}, entry added to database",
+            user.username
+        );
+        user
+    };
+    if user.deactivated_at.is_some() {
+        info!(
+            "Deactivated user {:?} was turned away signing in with {provider}",
+            user.username
+        );
+        return Err((
+            StatusCode::FORBIDDEN,
+            String::from("This account has been deactivated"),
+        ));
+    }
+    // Also keeps the account's stored name up to date
+    link(state, user.id, identity)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(user)
+}
+
+/// A new session for `user`, along with the random id that goes in its cookie. Only the id's
+/// hash is stored, so that a leaked database doesn't leak usable sessions.
+fn new_session(
+    user: &User,
+    provider: IdentityProvider,
+    headers: &HeaderMap,
+    now: DateTime<Utc>,
+) -> (String, Session) {
+    let session_id: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(48)
+        .map(char::from)
+        .collect();
+    let session = Session {
+        id: api_tokens::hash(&session_id),
+        user_id: user.id,
+        created_at: now,
+        expiration_date: now + SESSION_LIFETIME,
+        provider: Some(provider.as_str().to_string()),
+        last_used_at: None,
+        ip_address: client_ip(headers),
+        user_agent: headers
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string),
+    };
+    (session_id, session)
+}
+
+/// Start a session for a user who signed in, or just tidy up after a user linked an account.
+/// Returns the `Set-Cookie` headers to respond with.
+pub(super) async fn finish_sign_in(
+    state: &AppState,
+    headers: &HeaderMap,
+    user: &User,
+    provider: IdentityProvider,
+    intent: LoginIntent,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let mut cookies = vec![format!(
+        "{LOGIN_STATE_COOKIE}=; Secure; HttpOnly; Path=/api; Max-Age=0"
+    )];
+    if intent == LoginIntent::SignIn {
+        let now = Utc::now();
+        let (session_id, session) = new_session(user, provider, headers, now);
+        state
+            .db
+            .create_session(&session)
+            .await
+            .map_err(eyre_to_axum_err)?;
+        // Sessions that expired are only cleaned up when someone logs in
+        state
+            .db
+            .delete_expired_sessions(now)
+            .await
+            .map_err(eyre_to_axum_err)?;
+        audit::record(
+            &state.db,
+            AuditCategory::Auth,
+            "login",
+            Some(user),
+            client_ip(headers),
+            format!("Signed in with {}", provider.as_str()),
+        )
+        .await;
+        cookies.push(format!(
+            "{SESSION_COOKIE}={}; Secure; HttpOnly; Path=/; Max-Age={}",
+            session_id,
+            SESSION_COOKIE_MAX_AGE.num_seconds()
+        ));
+        cookies.push(format!(
+            "username={}; Path=/; Max-Age={}",
+            utf8_percent_encode(&user.username, NON_ALPHANUMERIC),
+            SESSION_COOKIE_MAX_AGE.num_seconds()
+        ));
+    }
+    let mut headers = HeaderMap::new();
+    for cookie in cookies {
+        headers.append(
+            "Set-Cookie",
+            cookie
+                .parse()
+                .map_err(|e: axum::http::header::InvalidHeaderValue| eyre_to_axum_err(e.into()))?,
+        );
+    }
+    Ok(headers)
+}
+
+/// After signing in, send them back to the homepage
+pub(super) fn home_redirect() -> Redirect {
+    if cfg!(debug_assertions) {
+        Redirect::to("http://localhost:5173/")
+    } else {
+        Redirect::to("/")
+    }
+}
+
+/// The providers users can sign in with
+pub async fn get_login_providers_handler(
+    State(state): State<AppState>,
+) -> Json<Vec<IdentityProvider>> {
+    Json(state.config.auth.providers.clone())
+}
+
+/// Send the user to a provider to sign in, or to link the account to the signed in user with
+/// `?link=true`. GitHub accounts are linked through [`get_github_link_handler`], which also
+/// works when signing in with GitHub isn't enabled.
+pub async fn get_login_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+    Query(query): Query<LoginQuery>,
+) -> Result<(HeaderMap, Redirect), (StatusCode, String)> {
+    let provider = IdentityProvider::try_from(provider.as_str())
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    if query.link && provider == IdentityProvider::GitHub {
+        return Ok((HeaderMap::new(), Redirect::to("/api/github/link")));
+    }
+    require_provider(&state, provider)?;
+    let intent = if query.link {
+        require_perms(State(&state), headers.clone(), &[]).await?;
+        LoginIntent::Link
+    } else {
+        LoginIntent::SignIn
+    };
+
+    let csrf_state = new_csrf_state();
+    let redirect_uri = redirect_uri(&state, provider)?;
+    let url = match provider {
+        IdentityProvider::Discord => {
+            discord_authorize_url(&state, CsrfToken::new(csrf_state.clone()))
+        }
+        IdentityProvider::GitHub => reqwest::Url::parse_with_params(
+            "https://github.com/login/oauth/authorize",
+            &[
+                ("client_id", state.config.oauth.github.client_id.as_str()),
+                ("redirect_uri", &redirect_uri),
+                ("state", &csrf_state),
+            ],
+        )
+        .map_err(|e| eyre_to_axum_err(e.into()))?,
+        IdentityProvider::Oidc => {
+            let oidc = state.config.oauth.oidc.as_ref().ok_or_else(|| {
+                eyre_to_axum_err(color_eyre::eyre::eyre!("`oauth.oidc` isn't configured"))
+            })?;
+            reqwest::Url::parse_with_params(
+                &oidc.authorize_url,
+                &[
+                    ("response_type", "code"),
+                    ("client_id", &oidc.client_id),
+                    ("redirect_uri", &redirect_uri),
+                    ("scope", &oidc.scopes),
+                    ("state", &csrf_state),
+                ],
+            )
+            .map_err(|e| eyre_to_axum_err(e.into()))?
+        }
+    };
+    Ok((
+        login_state_cookie(&csrf_state, intent)?,
+        Redirect::to(url.as_str()),
+    ))
+}
+
+/// GitHub and OpenID Connect providers send users here after they sign in
+pub async fn get_login_callback_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+    Query(query): Query<LoginCallbackQuery>,
+) -> Result<(HeaderMap, Redirect), (StatusCode, String)> {
+    let provider = parse_provider(&state, &provider)?;
+    let intent = login_intent(&headers, &query.state)?;
+    let redirect_uri = redirect_uri(&state, provider)?;
+    let identity = match provider {
+        IdentityProvider::Discord => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                "Discord sends users to /api/oauth".to_string(),
+            ))
+        }
+        IdentityProvider::GitHub => {
+            // Required by the config when GitHub sign in is enabled
+            let client_secret = state
+                .config
+                .oauth
+                .github
+                .client_secret
+                .as_deref()
+                .unwrap_or_default();
+            let github_user = state
+                .gh_client
+                .get_linked_user(client_secret, &query.code, &redirect_uri)
+                .await
+                .map_err(eyre_to_axum_err)?;
+            ExternalIdentity {
+                provider,
+                subject: github_user.id.to_string(),
+                avatar_url: format!("https://avatars.githubusercontent.com/u/{}", github_user.id),
+                username: github_user.login,
+            }
+        }
+        IdentityProvider::Oidc => {
+            let oidc = state.config.oauth.oidc.as_ref().ok_or_else(|| {
+                eyre_to_axum_err(color_eyre::eyre::eyre!("`oauth.oidc` isn't configured"))
+            })?;
+            fetch_oidc_identity(&state.reqwest_client, oidc, &query.code, &redirect_uri)
+                .await
+                .map_err(eyre_to_axum_err)?
+        }
+    };
+    let user = sign_in_user(&state, headers.clone(), &identity, intent).await?;
+    let response_headers = finish_sign_in(&state, &headers, &user, provider, intent).await?;
+    Ok((response_headers, home_redirect()))
+}
+
+/// The accounts the current user can sign in with
+pub async fn get_identities_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<UserIdentity>>, (StatusCode, String)> {
+    let identities = state
+        .db
+        .get_user_identities(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(identities))
+}
+
+/// Unlink an account from the current user. Their last account can't be unlinked, because they
+/// couldn't sign in any more.
+pub async fn delete_identity_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let provider = IdentityProvider::try_from(provider.as_str())
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    let identities = state
+        .db
+        .get_user_identities(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !identities.iter().any(|i| i.provider == provider.as_str()) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No {} account is linked", provider.as_str()),
+        ));
+    }
+    if identities.len() == 1 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The only account you can sign in with can't be unlinked".to_string(),
+        ));
+    }
+    if provider == IdentityProvider::GitHub {
+        state.db.unlink_github_account(user.id).await
+    } else {
+        state
+            .db
+            .unlink_identity(user.id, provider.as_str())
+            .await
+            .map(|_| ())
+    }
+    .map_err(eyre_to_axum_err)?;
+    if provider == IdentityProvider::Discord {
+        // Their Discord token shouldn't be renewed without the account
+        state
+            .db
+            .set_refresh_token(user.id, None)
+            .await
+            .map_err(eyre_to_axum_err)?;
+    }
+    let entry = audit::entry(
+        AuditCategory::Auth,
+        "identity_unlinked",
+        Some(&user),
+        client_ip(&headers),
+        format!("Unlinked their {} account", provider.as_str()),
+    );
+    let data = serde_json::json!({ "provider": provider.as_str() });
+    audit::record_entry(&state.db, entry.with_data(&data)).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_login_route() -> Router<AppState> {
+    Router::new()
+        .route("/login/providers", get(get_login_providers_handler))
+        .route("/login/{provider}", get(get_login_handler))
+        .route(
+            "/login/{provider}/callback",
+            get(get_login_callback_handler),
+        )
+        .route("/identities", get(get_identities_handler))
+        .route("/identities/{provider}", delete(delete_identity_handler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[tokio::test]
+    async fn stored_sessions() {
+        let mock_db = Database::from_url(":memory:").await.unwrap();
+        let user = mock_db
+            .create_user(
+                "alice".to_string(),
+                String::new(),
+                DateTime::UNIX_EPOCH,
+                String::new(),
+            )
+            .await
+            .unwrap();
+        let (session_id, session) = new_session(
+            &user,
+            IdentityProvider::Discord,
+            &HeaderMap::new(),
+            Utc::now(),
+        );
+        mock_db.create_session(&session).await.unwrap();
+
+        let stored = mock_db.get_active_sessions(Utc::now()).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert!(
+            stored.iter().all(|s| s.id != session_id),
+            "new_session: the id in the cookie shouldn't be stored"
+        );
+        assert_eq!(
+            mock_db
+                .get_session_by_hash(&api_tokens::hash(&session_id))
+                .await
+                .unwrap(),
+            Some(session),
+            "new_session: the session should be found by the hash of the id in the cookie"
+        );
+    }
+}
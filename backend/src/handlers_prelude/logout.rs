@@ -1,18 +1,168 @@
-use crate::AppState;
-use axum::http::HeaderMap;
+use super::{bearer_token, client_ip, session_id, SESSION_COOKIE};
+use crate::audit::{self, AuditCategory};
+use crate::db::User;
+use crate::identity::IdentityProvider;
+use crate::{api_tokens, eyre_to_axum_err, AppState};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
 use axum::routing::get;
 use axum::Router;
+use color_eyre::eyre::{bail, Context};
+use tracing::{info, warn};
+
+/// Where Discord tokens are revoked, unless `oauth.discord.revoke_url` is set
+const DISCORD_REVOKE_URL: &str = "https://discord.com/api/oauth2/token/revoke";
+
+/// Ask Discord to revoke a user's token (RFC 7009), which ends Hyde's authorization to act on
+/// their behalf, then forget the tokens Hyde has stored. Revoking an access token revokes the
+/// refresh token issued with it too.
+async fn revoke_discord_token(state: &AppState, user: &User) -> color_eyre::Result<()> {
+    let Some(token) = state.db.get_access_token(user.id).await? else {
+        return Ok(());
+    };
+    let discord = &state.config.oauth.discord;
+    let response = state
+        .reqwest_client
+        .post(discord.revoke_url.as_deref().unwrap_or(DISCORD_REVOKE_URL))
+        .basic_auth(&discord.client_id, Some(&discord.secret))
+        .form(&[
+            ("token", token.as_str()),
+            ("token_type_hint", "access_token"),
+        ])
+        .send()
+        .await
+        .wrap_err("Discord token revocation request failed")?;
+    if !response.status().is_success() {
+        bail!(
+            "Discord refused to revoke the token with {}: {}",
+            response.status(),
+            response.text().await.unwrap_or_default()
+        );
+    }
+    state
+        .db
+        .update_user(&User {
+            id: user.id,
+            username: user.username.clone(),
+            token: String::new(),
+            expiration_date: user.expiration_date,
+            avatar_url: user.avatar_url.clone(),
+            service_account: user.service_account,
+            deactivated_at: user.deactivated_at,
+        })
+        .await?;
+    state.db.set_refresh_token(user.id, None).await?;
+    Ok(())
+}
+
+/// Log the user out by deleting their session, so that the session id is useless even if it was
+/// stolen, then tell the browser on the other end to overwrite the session cookie. Scripts
+/// logging out with an API token revoke the token.
+pub async fn get_logout_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    if let Some(session_id) = session_id(&headers) {
+        let id_hash = api_tokens::hash(session_id);
+        let session = state
+            .db
+            .get_session_by_hash(&id_hash)
+            .await
+            .map_err(eyre_to_axum_err)?;
+        if state
+            .db
+            .delete_session(&id_hash)
+            .await
+            .map_err(eyre_to_axum_err)?
+        {
+            info!("A session was ended by logging out");
+        }
+        if let Some(session) = session {
+            let user = state
+                .db
+                .get_user(session.user_id)
+                .await
+                .map_err(eyre_to_axum_err)?;
+            audit::record(
+                &state.db,
+                AuditCategory::Auth,
+                "logout",
+                user.as_ref(),
+                client_ip(&headers),
+                "Logged out",
+            )
+            .await;
+            // Sessions from before sign in providers were recorded were all signed in with Discord
+            let discord = IdentityProvider::Discord.as_str();
+            let signed_in_with_discord = session.provider.as_deref().unwrap_or(discord) == discord;
+            if let Some(user) = user
+                .filter(|_| state.config.oauth.discord.revoke_on_logout && signed_in_with_discord)
+            {
+                // Logging out shouldn't fail because Discord couldn't be reached
+                match revoke_discord_token(&state, &user).await {
+                    Ok(()) => {
+                        audit::record(
+                            &state.db,
+                            AuditCategory::Auth,
+                            "token_revoked",
+                            Some(&user),
+                            client_ip(&headers),
+                            "Discord token revoked on logout",
+                        )
+                        .await;
+                    }
+                    Err(e) => warn!(
+                        "Failed to revoke the Discord token of user {:?}: {e:?}",
+                        user.username
+                    ),
+                }
+            }
+        }
+    }
+
+    if let Some(api_token) = bearer_token(&headers) {
+        let token = state
+            .db
+            .get_api_token_by_hash(&api_tokens::hash(api_token))
+            .await
+            .map_err(eyre_to_axum_err)?;
+        if let Some(token) = token {
+            state
+                .db
+                .delete_api_token(token.id)
+                .await
+                .map_err(eyre_to_axum_err)?;
+            let user = state
+                .db
+                .get_user(token.user_id)
+                .await
+                .map_err(eyre_to_axum_err)?;
+            let entry = audit::entry(
+                AuditCategory::Auth,
+                "api_token_revoked",
+                user.as_ref(),
+                client_ip(&headers),
+                format!("Revoked the API token {:?} by logging out", token.name),
+            );
+            let data = serde_json::json!({ "token_id": token.id, "token_name": token.name });
+            audit::record_entry(
+                &state.db,
+                entry
+                    .with_target(format!("user:{}", token.user_id))
+                    .with_data(&data),
+            )
+            .await;
+        }
+    }
 
-/// Tell the browser on the other end to overwrite the access token, effectively logging the user out
-pub async fn get_logout_handler() -> HeaderMap {
     let mut response_headers = HeaderMap::new();
     response_headers.insert(
         "Set-Cookie",
-        "access-token=logged-out; Secure; HttpOnly; Path=/; "
+        format!("{SESSION_COOKIE}=logged-out; Secure; HttpOnly; Path=/; Max-Age=0")
             .parse()
             .expect("Statically defined logout cookie isn't valid"),
     );
-    response_headers
+    Ok(response_headers)
 }
 
 pub async fn create_logout_route() -> Router<AppState> {
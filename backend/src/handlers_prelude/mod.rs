@@ -2,8 +2,15 @@
 
 use std::collections::HashMap;
 
-use axum::{extract::State, http::HeaderMap};
+use axum::{
+    extract::{Extension, FromRequestParts, Request, State},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use chrono::{DateTime, Utc};
+mod audit_log;
+pub use audit_log::*;
 mod repo_fs;
 pub use repo_fs::*;
 mod oauth;
@@ -12,6 +19,8 @@ mod users;
 pub use users::*;
 mod groups;
 pub use groups::*;
+mod login;
+pub use login::*;
 mod logout;
 pub use logout::*;
 mod github_hook;
@@ -20,15 +29,65 @@ mod reclone;
 pub use reclone::*;
 mod github_handlers;
 pub use github_handlers::*;
+mod owners;
+pub use owners::*;
+mod tickets;
+pub use tickets::*;
+mod changelog;
+pub use changelog::*;
+mod ai_assist;
+pub use ai_assist::*;
+mod permissions;
+pub use permissions::*;
+mod propose;
+pub use propose::*;
+mod device;
+pub use device::*;
+mod drafts;
+pub use drafts::*;
+mod preferences;
+pub use preferences::*;
+mod backups;
+pub use backups::*;
+mod github_link;
+pub use github_link::*;
+mod replication;
+pub use replication::*;
+mod reports;
+pub use reports::*;
+mod second_factor;
+pub use second_factor::*;
+mod service_accounts;
+pub use service_accounts::*;
+mod sessions;
+pub use sessions::*;
+mod telemetry;
+pub use telemetry::*;
+mod tokens;
+pub use tokens::*;
+mod whoami;
+pub use whoami::*;
+mod health;
+pub use health::*;
 
 use color_eyre::{
-    eyre::{Context, ContextCompat},
+    eyre::{eyre, Context, ContextCompat},
     Report,
 };
 use reqwest::StatusCode;
-use tracing::{debug, error, trace};
+use tracing::{debug, error, trace, warn};
 
-use crate::{db::User, perms::Permission, AppState};
+use crate::{
+    api_tokens,
+    app_conf::Auth,
+    audit::{self, AuditCategory},
+    branch_rules,
+    db::{Database, Session, User},
+    identity::IdentityProvider,
+    perms::Permission,
+    replica::Role,
+    AppState,
+};
 
 /// Quick and dirty way to convert an eyre error to a (StatusCode, message) response, meant for use with `map_err`, so that errors can be propagated out of
 /// axum handlers with `?`.
@@ -42,12 +101,148 @@ pub fn eyre_to_axum_err(e: Report) -> (StatusCode, String) {
 
 /// The output of a find_user call, used to differentiate between expired users and valid users
 enum FoundUser {
-    ExpiredUser(User),
+    ExpiredUser(User, Session),
     User(User),
 }
 
-/// Find the user attached to a particular request, if there is one, and the access token is still valid
+/// The cookie holding the id of the user's session
+pub const SESSION_COOKIE: &str = "session";
+/// The header holding the address a request came from, once it's been resolved
+pub const REAL_IP_HEADER: &str = "X-Real-IP";
+
+/// Parses an `expires_at` sent in a request body, an RFC-3339 timestamp
+pub fn parse_expiry(
+    expires_at: Option<&str>,
+) -> Result<Option<DateTime<Utc>>, (StatusCode, String)> {
+    expires_at
+        .map(|expires_at| {
+            DateTime::parse_from_rfc3339(expires_at)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("expires_at isn't an RFC-3339 timestamp: {e}"),
+                    )
+                })
+        })
+        .transpose()
+}
+
+/// The address a request came from. [`crate::ip_allowlist::resolve_client_address`] replaces
+/// whatever the client sent in `X-Real-IP` with the address it resolved, before any handler runs.
+pub fn client_ip(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(REAL_IP_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|ip| ip.trim().to_string())
+}
+
+/// The API token a request was made with, if any
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .filter(|t| t.starts_with(api_tokens::TOKEN_PREFIX))
+}
+
+/// The id of the session a request was made with, if any
+pub fn session_id(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all("Cookie")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split("; "))
+        .find_map(|cookie| cookie.strip_prefix(SESSION_COOKIE)?.strip_prefix('='))
+}
+
+/// The address a request came from, see [`client_ip`]
+pub fn request_ip(request: &Request) -> Option<String> {
+    client_ip(request.headers())
+}
+
+/// Whether a session was signed in with Discord, so that it can be renewed with the user's
+/// Discord token. Sessions from before other providers could be signed in with all were.
+fn signed_in_with_discord(session: &Session) -> bool {
+    matches!(session.provider.as_deref(), None | Some("discord"))
+}
+
+/// Whether Discord tokens can be refreshed. Replicas don't: refreshing replaces the refresh token,
+/// so the primary's copy would stop working, and the new one would be lost with the next snapshot.
+fn can_refresh_tokens(state: &AppState) -> bool {
+    state.replica.role(state.config) != Role::Replica
+}
+
+/// Why a session has to end regardless of whether it could be renewed, if it does, see
+/// `auth.idle_timeout_secs` and `auth.max_session_age_secs`
+fn session_timed_out(session: &Session, auth: &Auth, now: DateTime<Utc>) -> Option<&'static str> {
+    let elapsed_since =
+        |time: DateTime<Utc>| u64::try_from((now - time).num_seconds()).unwrap_or(0);
+    if let Some(max_age) = auth.max_session_age_secs {
+        if elapsed_since(session.created_at) >= max_age {
+            return Some("it's older than the maximum session age");
+        }
+    }
+    if let Some(idle_timeout) = auth.idle_timeout_secs {
+        let last_used_at = session.last_used_at.unwrap_or(session.created_at);
+        if elapsed_since(last_used_at) >= idle_timeout {
+            return Some("it was idle for too long");
+        }
+    }
+    None
+}
+
+/// Find the user attached to a particular request, if there is one, and their session is still valid
 async fn find_user(state: &AppState, headers: HeaderMap) -> color_eyre::Result<Option<FoundUser>> {
+    // Scripts authenticate with an API token instead of a session
+    if let Some(token) = bearer_token(&headers) {
+        trace!("Request was made that contains an API token");
+        let Some(api_token) = state
+            .db
+            .get_api_token_by_hash(&api_tokens::hash(token))
+            .await?
+        else {
+            debug!("Request was made with an API token that doesn't exist or was revoked");
+            return Ok(None);
+        };
+        let now = Utc::now();
+        if let Some(expiration_date) = api_token.expiration_date {
+            if expiration_date < now {
+                debug!(
+                    "Request was made with the expired API token {}",
+                    api_token.id
+                );
+                let user = state.db.get_user(api_token.user_id).await?;
+                audit::record(
+                    &state.db,
+                    AuditCategory::Auth,
+                    "api_token_expired",
+                    user.as_ref(),
+                    client_ip(&headers),
+                    format!(
+                        "Turned away with the expired API token {:?}",
+                        api_token.name
+                    ),
+                )
+                .await;
+                return Ok(None);
+            }
+        }
+        state.db.touch_api_token(api_token.id, now).await?;
+        let user = state
+            .db
+            .get_user(api_token.user_id)
+            .await?
+            .wrap_err("API token belongs to a user that doesn't exist")?;
+        if user.deactivated_at.is_some() {
+            debug!(
+                "Deactivated user {:?} made a request with an API token",
+                user.username
+            );
+            return Ok(None);
+        }
+        return Ok(Some(FoundUser::User(user)));
+    }
     let mut cookies: HashMap<&str, &str> = HashMap::new();
     // There can be multiple cookie headers, and each cookie header can contain multiple cookies
     let cookie_headers = headers.get_all("Cookie");
@@ -60,23 +255,86 @@ async fn find_user(state: &AppState, headers: HeaderMap) -> color_eyre::Result<O
             cookies.insert(name, value);
         }
     }
-    if let Some(token) = cookies.get("access-token") {
-        trace!("Request was made that contains an access-token cookie");
-        if let Some(user) = state.db.get_user_from_token(token.to_string()).await? {
-            let expiration_date = DateTime::parse_from_rfc3339(&user.expiration_date)
-                .wrap_err("Expiration time in database is not a valid time")?;
-            if expiration_date < Utc::now() {
-                debug!("User {:?} made a request that requires a valid access token but their access token expired", user.username);
-                return Ok(Some(FoundUser::ExpiredUser(user)));
+    if let Some(session_id) = cookies.get(SESSION_COOKIE) {
+        trace!("Request was made that contains a session cookie");
+        if let Some(session) = state
+            .db
+            .get_session_by_hash(&api_tokens::hash(session_id))
+            .await?
+        {
+            let user = state
+                .db
+                .get_user(session.user_id)
+                .await?
+                .wrap_err("Session belongs to a user that doesn't exist")?;
+            if user.deactivated_at.is_some() {
+                debug!(
+                    "Deactivated user {:?} made a request with a session",
+                    user.username
+                );
+                return Ok(None);
+            }
+            if let Some(provider) = &session.provider {
+                // Sessions end when the account they were signed in with is unlinked, or its
+                // provider is disabled
+                let provider_enabled = IdentityProvider::try_from(provider.as_str())
+                    .is_ok_and(|p| state.config.auth.is_enabled(p));
+                let identities = state.db.get_user_identities(user.id).await?;
+                if !provider_enabled || !identities.iter().any(|i| i.provider == *provider) {
+                    debug!(
+                        "User {:?} made a request with a session from a {provider} account that can't be signed in with any more",
+                        user.username
+                    );
+                    return Ok(None);
+                }
+            }
+            if let Some(reason) = session_timed_out(&session, &state.config.auth, Utc::now()) {
+                debug!(
+                    "Ended a session of user {:?} because {reason}",
+                    user.username
+                );
+                state.db.delete_session(&session.id).await?;
+                audit::record(
+                    &state.db,
+                    AuditCategory::Auth,
+                    "session_timed_out",
+                    Some(&user),
+                    client_ip(&headers),
+                    format!("Session ended because {reason}"),
+                )
+                .await;
+                return Ok(None);
+            }
+            if session.expiration_date < Utc::now() {
+                debug!("User {:?} made a request that requires a valid session but their session expired", user.username);
+                return Ok(Some(FoundUser::ExpiredUser(user, session)));
             } else {
-                debug!("User {:?} made a request that requires a valid access token and they have a valid access token", user.username);
+                debug!("User {:?} made a request that requires a valid session and they have a valid session", user.username);
+                if signed_in_with_discord(&session)
+                    && discord_token_expired(&user)
+                    && can_refresh_tokens(state)
+                {
+                    // Renew it in the background so that the request isn't held up
+                    let state = state.clone();
+                    let user_id = user.id;
+                    tokio::spawn(async move {
+                        if let Err(e) = refresh_discord_token(&state, user_id).await {
+                            warn!("Failed to refresh the Discord token of user {user_id}: {e:?}");
+                        }
+                    });
+                }
+                let now = Utc::now();
+                state
+                    .db
+                    .touch_session(&session.id, now, now - chrono::Duration::minutes(1))
+                    .await?;
                 return Ok(Some(FoundUser::User(user)));
             }
         } else {
-            trace!("No user was found in the database with the request's access token");
+            trace!("No session was found in the database with the request's session id");
         }
     } else {
-        trace!("Request was made that lacked an access-token cookie");
+        trace!("Request was made that lacked a session cookie");
     }
 
     Ok(None)
@@ -87,46 +345,391 @@ async fn find_user(state: &AppState, headers: HeaderMap) -> color_eyre::Result<O
 /// When placed at the top of an Axum handler, you can specify permission(s)
 /// to require. If they are missing from the user, it will return an error,
 /// which you can propagate through the handler with `?`.
-// TODO: Write unit tests for this. May require refactoring so that
-// it only needs a database, instead of the whole app state
+///
+/// Handlers that always need the user to be signed in should take an [`AuthenticatedUser`] or
+/// [`RequirePermission`] instead.
 pub async fn require_perms(
     State(state): State<&AppState>,
     headers: HeaderMap,
     perms: &[Permission],
 ) -> Result<User, (StatusCode, String)> {
+    let ip_address = client_ip(&headers);
     let maybe_user = find_user(state, headers).await.map_err(eyre_to_axum_err)?;
-    match maybe_user {
-        Some(user) => match user {
-            FoundUser::ExpiredUser(u) => Err((
-                StatusCode::UNAUTHORIZED,
-                format!(
-                    "The access token has expired for the user {}, they must authenticate again.",
-                    u.username
-                ),
-            )),
-            FoundUser::User(u) => {
-                let user_perms = &state
+    let u = match maybe_user {
+        Some(FoundUser::User(u)) => u,
+        Some(FoundUser::ExpiredUser(u, session)) => {
+            // If Discord still lets us act on the user's behalf, they don't have to log in again
+            if signed_in_with_discord(&session)
+                && can_refresh_tokens(state)
+                && refresh_discord_token(state, u.id)
+                    .await
+                    .map_err(eyre_to_axum_err)?
+            {
+                state
                     .db
-                    .get_user_permissions(u.id)
+                    .extend_session(&session.id, Utc::now() + SESSION_LIFETIME)
                     .await
                     .map_err(eyre_to_axum_err)?;
-                let has_permissions = perms.iter().all(|perm| user_perms.contains(perm));
-                if has_permissions {
-                    Ok(u)
-                } else {
-                    Err((
-                        StatusCode::FORBIDDEN,
-                        format!(
-                            "User {:?} lacks the permission to edit documents.",
-                            u.username
-                        ),
-                    ))
-                }
+                debug!("Renewed the expired session of user {:?}", u.username);
+                u
+            } else {
+                audit::record(
+                    &state.db,
+                    AuditCategory::Auth,
+                    "session_expired",
+                    Some(&u),
+                    ip_address,
+                    "Turned away with an expired session",
+                )
+                .await;
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    format!(
+                        "The session has expired for the user {}, they must authenticate again.",
+                        u.username
+                    ),
+                ));
             }
-        },
-        None => Err((
+        }
+        None => return Err((
             StatusCode::UNAUTHORIZED,
             "No valid user is authenticated, perhaps you forgot to add `{credentials: \"include\"}` in your fetch options?.".to_string(),
         )),
+    };
+    audited_check_perms(state, &u, perms, ip_address)
+        .await
+        .map(|()| u)
+}
+
+/// [`check_perms`], recording users being turned away in the audit log
+async fn audited_check_perms(
+    state: &AppState,
+    user: &User,
+    perms: &[Permission],
+    ip_address: Option<String>,
+) -> Result<(), (StatusCode, String)> {
+    let result = check_perms(&state.db, user, perms).await;
+    if matches!(&result, Err((StatusCode::FORBIDDEN, _))) {
+        audit::record(
+            &state.db,
+            AuditCategory::Auth,
+            "permission_denied",
+            Some(user),
+            ip_address,
+            format!("Turned away for lacking {perms:?}"),
+        )
+        .await;
+    }
+    result
+}
+
+/// Returns an error unless `user` has every permission in `perms`
+pub async fn check_perms(
+    db: &Database,
+    user: &User,
+    perms: &[Permission],
+) -> Result<(), (StatusCode, String)> {
+    let user_perms = db
+        .get_user_permissions(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let has_permissions = perms.iter().all(|perm| user_perms.contains(perm));
+    if has_permissions {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::FORBIDDEN,
+            format!(
+                "User {:?} lacks the permission to edit documents.",
+                user.username
+            ),
+        ))
+    }
+}
+
+/// The signed in user, reusing the one a permission middleware already resolved (see
+/// [`require_sign_in`]) so they aren't looked up twice
+async fn resolve_user(
+    parts: &Parts,
+    state: &AppState,
+    perms: &[Permission],
+) -> Result<User, (StatusCode, String)> {
+    match parts.extensions.get::<User>() {
+        Some(user) => audited_check_perms(state, user, perms, client_ip(&parts.headers))
+            .await
+            .map(|()| user.clone()),
+        None => require_perms(State(state), parts.headers.clone(), perms).await,
+    }
+}
+
+/// Extracts the signed in user, rejecting the request if nobody is signed in
+pub struct AuthenticatedUser(pub User);
+
+impl FromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        resolve_user(parts, state, &[]).await.map(Self)
+    }
+}
+
+/// Extracts the signed in user, rejecting the request if nobody is signed in or they lack the
+/// permission `P`, one of [`crate::perms::required`]
+pub struct RequirePermission<const P: u8>(pub User);
+
+impl<const P: u8> FromRequestParts<AppState> for RequirePermission<P> {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let permission = Permission::from_id(P)
+            .ok_or_else(|| eyre_to_axum_err(eyre!("{P} isn't the id of a permission")))?;
+        resolve_user(parts, state, &[permission]).await.map(Self)
+    }
+}
+
+/// Rejects the request unless the user is signed in and has every permission in `perms`,
+/// attaching the user to the request's extensions for the handler's extractors
+async fn authorize(
+    state: &AppState,
+    request: Request,
+    next: Next,
+    perms: &[Permission],
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+    match resolve_user(&parts, state, perms).await {
+        Ok(user) => {
+            parts.extensions.insert(user);
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Middleware rejecting requests unless someone is signed in, meant to be added to a whole
+/// router with `route_layer(middleware::from_fn(require_sign_in))` so new endpoints can't
+/// forget the check. The app state is taken from the request's extensions, because routers
+/// are built before it exists.
+pub async fn require_sign_in(
+    Extension(state): Extension<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    authorize(&state, request, next, &[]).await
+}
+
+/// Like [`require_sign_in`], also requiring the permission `P`, one of
+/// [`crate::perms::required`]
+pub async fn require_permission<const P: u8>(
+    Extension(state): Extension<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(permission) = Permission::from_id(P) else {
+        return eyre_to_axum_err(eyre!("{P} isn't the id of a permission")).into_response();
+    };
+    authorize(&state, request, next, &[permission]).await
+}
+
+/// Rejects the request unless `auth.public_read` is set or someone is signed in. If
+/// `auth.require_read_permission` is set, they have to have [`Permission::ReadContent`]
+/// instead. Taken by the endpoints that read documents and assets.
+pub struct ReadAccess;
+
+impl FromRequestParts<AppState> for ReadAccess {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let auth = &state.config.auth;
+        if auth.require_read_permission {
+            return resolve_user(parts, state, &[Permission::ReadContent])
+                .await
+                .map(|_| Self);
+        }
+        if auth.public_read {
+            return Ok(Self);
+        }
+        resolve_user(parts, state, &[]).await.map(|_| Self)
+    }
+}
+
+/// Middleware applying [`ReadAccess`] to a service that isn't a handler, like the static
+/// asset files
+pub async fn read_access_guard(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+    if let Err(rejection) = ReadAccess::from_request_parts(&mut parts, &state).await {
+        return rejection.into_response();
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
+
+/// Whether `user` may add members to and remove members from the group `group_id`.
+///
+/// Users with [`Permission::ManageUsers`] can manage every group. Otherwise, a user can only
+/// manage the groups they own or that are delegated to one of their groups, and never a group
+/// holding `ManageUsers` itself, so that delegated admins can't grant themselves full admin
+/// rights.
+pub async fn can_manage(
+    state: &AppState,
+    user: &User,
+    group_id: i64,
+) -> Result<bool, (StatusCode, String)> {
+    let user_perms = state
+        .db
+        .get_user_permissions(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if user_perms.contains(&Permission::ManageUsers) {
+        return Ok(true);
+    }
+    let managed_groups = state
+        .db
+        .get_user_managed_groups(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !managed_groups.contains(&group_id) {
+        return Ok(false);
+    }
+    let grants_admin = state
+        .db
+        .group_has_permission(group_id, Permission::ManageUsers)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(!grants_admin)
+}
+
+/// Whether `user` may push to `branch`, see [`crate::branch_rules`]
+pub async fn may_push_branch(db: &Database, user: &User, branch: &str) -> color_eyre::Result<bool> {
+    let rules = db.get_all_branch_rules().await?;
+    if rules.is_empty() {
+        return Ok(true);
+    }
+    let groups: Vec<i64> = db
+        .get_user_groups(user.id)
+        .await?
+        .iter()
+        .map(|group| group.id)
+        .collect();
+    Ok(branch_rules::may_push(&rules, &groups, branch))
+}
+
+/// Returns an error unless `user` may push to `branch`, see [`crate::branch_rules`].
+/// Called before anything is committed and pushed.
+pub async fn check_branch_push(
+    state: &AppState,
+    user: &User,
+    branch: &str,
+) -> Result<(), (StatusCode, String)> {
+    if may_push_branch(&state.db, user, branch)
+        .await
+        .map_err(eyre_to_axum_err)?
+    {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::FORBIDDEN,
+            format!(
+                "User {:?} lacks the permission to push to the branch {branch:?}.",
+                user.username
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perms::required;
+
+    #[test]
+    fn session_timeouts() {
+        let at = |time: &str| DateTime::parse_from_rfc3339(time).unwrap().to_utc();
+        let session = Session {
+            id: String::new(),
+            user_id: 1,
+            created_at: at("2025-04-20T00:00:00Z"),
+            expiration_date: at("2025-05-20T00:00:00Z"),
+            provider: None,
+            last_used_at: Some(at("2025-04-20T12:00:00Z")),
+            ip_address: None,
+            user_agent: None,
+        };
+        let auth = Auth {
+            idle_timeout_secs: Some(60 * 60),
+            max_session_age_secs: Some(24 * 60 * 60),
+            ..Default::default()
+        };
+        assert_eq!(
+            session_timed_out(&session, &auth, at("2025-04-20T12:30:00Z")),
+            None
+        );
+        assert!(
+            session_timed_out(&session, &auth, at("2025-04-20T13:00:00Z")).is_some(),
+            "session_timed_out: should end sessions that were idle for too long"
+        );
+        let last_used_now = Session {
+            last_used_at: Some(at("2025-04-21T00:00:00Z")),
+            ..session.clone()
+        };
+        assert!(
+            session_timed_out(&last_used_now, &auth, at("2025-04-21T00:00:00Z")).is_some(),
+            "session_timed_out: should end sessions older than the maximum age"
+        );
+        assert_eq!(
+            session_timed_out(&session, &Auth::default(), at("2026-01-01T00:00:00Z")),
+            None,
+            "session_timed_out: sessions shouldn't time out by default"
+        );
+    }
+
+    #[tokio::test]
+    async fn permission_checks() {
+        let db = Database::from_url(":memory:").await.unwrap();
+        let user = db
+            .create_user(
+                "alice".to_string(),
+                String::new(),
+                DateTime::UNIX_EPOCH,
+                String::new(),
+            )
+            .await
+            .unwrap();
+        let group = db.create_group("Editors".to_string()).await.unwrap();
+        db.add_group_membership(group.id, user.id).await.unwrap();
+        db.add_group_permission(group.id, Permission::ManageContent)
+            .await
+            .unwrap();
+
+        check_perms(&db, &user, &[Permission::ManageContent])
+            .await
+            .expect("check_perms: should allow users with the permission");
+        check_perms(&db, &user, &[])
+            .await
+            .expect("check_perms: should allow any user when no permissions are required");
+        let (status, _) = check_perms(&db, &user, &[Permission::ManageUsers])
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn permission_ids() {
+        assert_eq!(
+            Permission::from_id(required::MANAGE_USERS),
+            Some(Permission::ManageUsers)
+        );
+        assert_eq!(Permission::from_id(u8::MAX), None);
     }
 }
@@ -1,17 +1,39 @@
 use axum::routing::get;
 use axum::{
-    extract::{Query, Request, State},
+    extract::{Query, State},
     http::{HeaderMap, StatusCode},
     response::Redirect,
     Router,
 };
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use color_eyre::eyre::{Context, ContextCompat};
-use oauth2::{AuthorizationCode, CsrfToken, RedirectUrl, TokenResponse};
+use oauth2::{
+    basic::BasicTokenResponse, AuthorizationCode, CsrfToken, RedirectUrl, RefreshToken,
+    RequestTokenError, TokenResponse,
+};
 use serde::{Deserialize, Serialize};
-use tracing::{error, info};
+use std::collections::BTreeSet;
+use tracing::{debug, error, info};
+
+use super::{
+    finish_sign_in, home_redirect, login_intent, login_state_cookie, new_csrf_state,
+    require_provider, sign_in_user, LoginIntent,
+};
+use crate::audit::{self, AuditCategory};
+use crate::db::{User, UserIdentity};
+use crate::discord_roles::{self, GUILD_MEMBERS_SCOPE};
+use crate::identity::{ExternalIdentity, IdentityProvider, DEFAULT_AVATAR_URL};
+use crate::AppState;
 
-use crate::{db::User, AppState};
+/// How long users stay logged in for, regardless of when their Discord token expires. Once a
+/// session expires, it's renewed if the user's Discord token can still be refreshed.
+pub const SESSION_LIFETIME: Duration = Duration::days(30);
+/// The session cookie outlives the session, so that expired sessions can be renewed
+pub(super) const SESSION_COOKIE_MAX_AGE: Duration = Duration::days(400);
+/// The scope every sign in needs, to read the user's Discord account
+const IDENTIFY_SCOPE: &str = "identify";
+/// Discord tokens are refreshed this long before they expire
+const DISCORD_TOKEN_REFRESH_MARGIN: Duration = Duration::minutes(5);
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetOAuthQuery {
@@ -30,29 +52,193 @@ pub struct DiscordUserObject {
 /// are sent here by discord after they authenticate, then they're redirected to the homepage
 pub async fn get_oauth2_handler(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(query): Query<GetOAuthQuery>,
-    req: Request,
 ) -> Result<(HeaderMap, Redirect), (StatusCode, String)> {
-    match get_oath_processor(&state, query, req).await {
-        Ok(redirect) => Ok(redirect),
+    require_provider(&state, IdentityProvider::Discord)?;
+    let intent = login_intent(&headers, &query.state)?;
+    let (token_data, identity) = get_oath_processor(&state, query)
+        .await
+        .map_err(oauth_error)?;
+    let user = sign_in_user(&state, headers.clone(), &identity, intent).await?;
+    store_discord_token(&state, &user, &identity, &token_data)
+        .await
+        .map_err(oauth_error)?;
+    discord_roles::sync_groups(&state, &user, token_data.access_token().secret())
+        .await
+        .map_err(oauth_error)?;
+    // If the user is one of the admins specified in the config, give them the admin role
+    if state.config.discord.is_admin(&identity.subject) {
+        grant_admin(&state, &user).await.map_err(oauth_error)?;
+    }
+    let response_headers =
+        finish_sign_in(&state, &headers, &user, IdentityProvider::Discord, intent).await?;
+    Ok((response_headers, home_redirect()))
+}
+
+fn oauth_error(e: color_eyre::Report) -> (StatusCode, String) {
+    error!("An error was encountered during oauth processing: {:#?}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!(
+            "An error was encountered during oauth processing: {:?}",
+            e.to_string()
+        ),
+    )
+}
+
+/// The URL to send users to to sign in with Discord. The `state` it carries is stored in a
+/// cookie and checked by [`get_oauth2_handler`].
+/// <https://discord.com/developers/docs/topics/oauth2#state-and-security>
+pub async fn get_oauth2_url(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, String), (StatusCode, String)> {
+    let csrf_state = new_csrf_state();
+    let url = discord_authorize_url(&state, CsrfToken::new(csrf_state.clone()));
+    Ok((
+        login_state_cookie(&csrf_state, LoginIntent::SignIn)?,
+        url.to_string(),
+    ))
+}
+
+/// The URL users are sent to to sign in with Discord
+pub(super) fn discord_authorize_url(state: &AppState, csrf_token: CsrfToken) -> reqwest::Url {
+    let (url, _token) = state.oauth.authorize_url(|| csrf_token).url();
+    let mut extra_scopes = Vec::new();
+    if state.config.discord.syncs_roles() {
+        extra_scopes.push(GUILD_MEMBERS_SCOPE);
+    }
+    with_scopes(
+        url,
+        state.config.oauth.discord.scopes.as_deref(),
+        &extra_scopes,
+    )
+}
+
+/// Set the scopes an authorization URL requests. `scopes` replaces the ones the URL requests
+/// if set, and `identify` and `extra_scopes` are always added. Discord only reads one `scope`.
+fn with_scopes(
+    mut url: reqwest::Url,
+    scopes: Option<&[String]>,
+    extra_scopes: &[&str],
+) -> reqwest::Url {
+    let mut all_scopes: BTreeSet<String> = scopes.map_or_else(
+        || {
+            url.query_pairs()
+                .filter(|(key, _)| key == "scope")
+                .flat_map(|(_, value)| {
+                    value
+                        .split_whitespace()
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        },
+        |scopes| scopes.iter().cloned().collect(),
+    );
+    all_scopes.insert(IDENTIFY_SCOPE.to_string());
+    all_scopes.extend(extra_scopes.iter().map(|s| (*s).to_string()));
+    let other_params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "scope")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(other_params)
+        .append_pair(
+            "scope",
+            &all_scopes.into_iter().collect::<Vec<_>>().join(" "),
+        );
+    url
+}
+
+/// Fetch the Discord account an access token belongs to
+async fn fetch_discord_user(
+    state: &AppState,
+    access_token: &str,
+) -> color_eyre::Result<DiscordUserObject> {
+    let response = state
+        .reqwest_client
+        .get("https://discord.com/api/v10/users/@me")
+        .bearer_auth(access_token)
+        .header(
+            "User-Agent",
+            "DiscordBot (https://github.com/r-Techsupport/hyde, 0)",
+        )
+        .send()
+        .await?
+        .error_for_status()?;
+    // https://discord.com/developers/docs/resources/user#user-object
+    Ok(serde_json::from_slice(&response.bytes().await?)?)
+}
+
+/// The id of the Discord account a user's stored Discord token belongs to, if it can still be
+/// used. Users who signed in before accounts were linked are only known by their Discord
+/// username, which can be taken by someone else after a rename, so this is how they're
+/// matched to their Discord account instead.
+pub(super) async fn stored_discord_account_id(
+    state: &AppState,
+    user_id: i64,
+) -> color_eyre::Result<Option<String>> {
+    let Some(token) = state.db.get_access_token(user_id).await? else {
+        return Ok(None);
+    };
+    match fetch_discord_user(state, &token).await {
+        Ok(discord_user) => Ok(Some(discord_user.id)),
         Err(e) => {
-            error!("An error was encountered during oauth processing: {:#?}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!(
-                    "An error was encountered during oauth processing: {:?}",
-                    e.to_string()
-                ),
-            ))
+            debug!("The stored Discord token of user {user_id} can't be used: {e:?}");
+            Ok(None)
         }
     }
 }
 
-pub async fn get_oauth2_url(State(state): State<AppState>) -> String {
-    // TODO: actually validate CSRF token
-    // <https://discord.com/developers/docs/topics/oauth2#state-and-security>
-    let (url, _token) = state.oauth.authorize_url(CsrfToken::new_random).url();
-    url.to_string()
+/// Link the Discord accounts of users who signed in before accounts were linked, by asking
+/// Discord who their stored tokens belong to. Returns how many were linked. Users whose tokens
+/// have expired are matched when they next sign in, if their tokens can still be used then.
+pub async fn link_legacy_discord_users(state: &AppState) -> color_eyre::Result<usize> {
+    let mut linked = 0;
+    for user in state.db.get_all_users().await? {
+        if user.token.is_empty() || user.service_account {
+            continue;
+        }
+        let identities = state.db.get_user_identities(user.id).await?;
+        if identities.iter().any(|i| i.provider == "discord") {
+            continue;
+        }
+        let Some(token) = state.db.get_access_token(user.id).await? else {
+            continue;
+        };
+        let discord_user = match fetch_discord_user(state, &token).await {
+            Ok(discord_user) => discord_user,
+            Err(e) => {
+                debug!(
+                    "Couldn't link the Discord account of user {:?}: {e:?}",
+                    user.username
+                );
+                continue;
+            }
+        };
+        if state
+            .db
+            .get_identity_user("discord", &discord_user.id)
+            .await?
+            .is_some()
+        {
+            continue;
+        }
+        state
+            .db
+            .link_identity(&UserIdentity {
+                provider: IdentityProvider::Discord.as_str().to_string(),
+                subject: discord_user.id,
+                user_id: user.id,
+                username: discord_user.username,
+            })
+            .await?;
+        linked += 1;
+    }
+    Ok(linked)
 }
 
 /// This is pretty stupid, but I want to be able to use `color_eyre::Result` and `?` for error handling, but
@@ -61,24 +247,17 @@ pub async fn get_oauth2_url(State(state): State<AppState>) -> String {
 async fn get_oath_processor(
     state: &AppState,
     query: GetOAuthQuery,
-    req: Request,
-) -> color_eyre::Result<(HeaderMap, Redirect)> {
-    // Support for dev and local environments, where discord sends the user
-    // after the first step of the handshake.
-    // HTTPS is required in production for full functionality so it's ok to hardcode that in
-    let redirect_url = if cfg!(debug_assertions) {
-        format!(
-            "http://{}/api/oauth",
-            req.headers().get("host").unwrap().to_str()?
-        )
-    } else {
-        format!(
-            "https://{}/api/oauth",
-            req.headers().get("host").unwrap().to_str()?
-        )
-    };
+) -> color_eyre::Result<(BasicTokenResponse, ExternalIdentity)> {
+    // Where Discord sent the user after the first step of the handshake
+    let redirect_url = format!(
+        "{}/api/oauth",
+        state
+            .config
+            .public_url()
+            .wrap_err("`auth.public_url` isn't set")?
+    );
     // The obtained token after they authenticate
-    let token_data: oauth2::StandardTokenResponse<_, _> = state
+    let token_data: BasicTokenResponse = state
         .oauth
         .exchange_code(AuthorizationCode::new(query.code))
         .set_redirect_uri(std::borrow::Cow::Owned(RedirectUrl::new(redirect_url)?))
@@ -86,112 +265,162 @@ async fn get_oath_processor(
         .await
         .wrap_err("OAuth token request failed")?;
 
-    let token = token_data.access_token().secret();
     // Use that token to request user data
-    let response = state
-        .reqwest_client
-        .get("https://discord.com/api/v10/users/@me")
-        .bearer_auth(token_data.access_token().secret())
-        .header(
-            "User-Agent",
-            "DiscordBot (https://github.com/r-Techsupport/hyde, 0)",
-        )
-        .send()
-        .await?;
-    // https://discord.com/developers/docs/resources/user#user-object
-    let discord_user_info: DiscordUserObject = serde_json::from_slice(&response.bytes().await?)?;
+    let discord_user_info = fetch_discord_user(state, token_data.access_token().secret()).await?;
+    // https://discord.com/developers/docs/reference#image-formatting
     let avatar_url = if let Some(hash) = discord_user_info.avatar {
         format!(
             "https://cdn.discordapp.com/avatars/{}/{hash}.png",
             discord_user_info.id
         )
     } else {
-        "https://cdn.discordapp.com/embed/avatars/0.png".to_string()
+        DEFAULT_AVATAR_URL.to_string()
     };
-    // https://discord.com/developers/docs/reference#image-formatting
-    let all_users = state.db.get_all_users().await?;
+    let identity = ExternalIdentity {
+        provider: IdentityProvider::Discord,
+        subject: discord_user_info.id,
+        username: discord_user_info.username,
+        avatar_url,
+    };
+    Ok((token_data, identity))
+}
+
+/// Store the Discord token a user signed in with, so Hyde can act on their behalf
+async fn store_discord_token(
+    state: &AppState,
+    user: &User,
+    identity: &ExternalIdentity,
+    token_data: &BasicTokenResponse,
+) -> color_eyre::Result<()> {
     let expiration_date = Utc::now()
         + token_data
             .expires_in()
             .wrap_err("Discord OAuth2 response didn't include an expiration date")?;
-    // Update the user entry if one is already there, otherwise create a user
-    if let Some(existing_user) = all_users
-        .iter()
-        .find(|u| u.username == discord_user_info.username)
-    {
+    state
+        .db
+        .update_user(&User {
+            id: user.id,
+            username: user.username.clone(),
+            token: token_data.access_token().secret().to_string(),
+            expiration_date,
+            avatar_url: identity.avatar_url.clone(),
+            service_account: user.service_account,
+            deactivated_at: user.deactivated_at,
+        })
+        .await?;
+    // Discord only returns a refresh token for some grant types, so an old one is kept otherwise
+    if let Some(refresh_token) = token_data.refresh_token() {
         state
             .db
-            .update_user(&User {
-                id: existing_user.id,
-                username: existing_user.username.clone(),
-                token: token.to_string(),
-                expiration_date: expiration_date.to_rfc3339(),
-                avatar_url,
-            })
+            .set_refresh_token(user.id, Some(refresh_token.secret()))
             .await?;
-        info!("User {:?} re-authenticated", existing_user.username);
-    } else {
+    }
+    Ok(())
+}
+
+/// Add a user to the admin group, if they aren't in it already
+async fn grant_admin(state: &AppState, user: &User) -> color_eyre::Result<()> {
+    let their_groups = state.db.get_user_groups(user.id).await?;
+    // If they don't have the admin group, add it
+    if !their_groups.iter().any(|g| g.name == "Admin") {
+        let all_groups = state.db.get_all_groups().await?;
+        let admin_group = all_groups
+            .into_iter()
+            .find(|g| g.name == "Admin")
+            .expect("No admin group in database");
         state
             .db
-            .create_user(
-                discord_user_info.username.to_string(),
-                token.to_string(),
-                expiration_date.to_rfc3339(),
-                avatar_url,
-            )
+            .add_group_membership(admin_group.id, user.id)
             .await?;
         info!(
-            "New user {:?} authenticated, entry added to database",
-            discord_user_info.username
+            "User {:?} was automatically added to the admin group based off of the server config",
+            user.username
         );
     }
-    // If the user is the admin specified in the config, give them the admin role
-    let admin_username = &state.config.discord.admin_username;
-    let all_users = state.db.get_all_users().await?;
-    let maybe_admin_user = all_users.iter().find(|u| u.username == *admin_username);
-    if let Some(admin_user) = maybe_admin_user {
-        let their_groups = state.db.get_user_groups(admin_user.id).await?;
-        // If they don't have the admin group, add it
-        if !their_groups.iter().any(|g| g.name == "Admin") {
-            let all_groups = state.db.get_all_groups().await?;
-            let admin_group = all_groups
-                .into_iter()
-                .find(|g| g.name == "Admin")
-                .expect("No admin group in database");
-            state
-                .db
-                .add_group_membership(admin_group.id, admin_user.id)
-                .await?;
-            info!("User {admin_username:?} was automatically added to the admin group based off of the server config");
-        }
-    }
+    Ok(())
+}
 
-    // After authenticating, send them back to the homepage
-    let redirect = if cfg!(debug_assertions) {
-        Redirect::to("http://localhost:5173/")
-    } else {
-        Redirect::to("/")
-    };
+/// Whether a user's Discord access token has expired, or is about to
+pub(super) fn discord_token_expired(user: &User) -> bool {
+    user.expiration_date < Utc::now() + DISCORD_TOKEN_REFRESH_MARGIN
+}
 
-    let mut headers = HeaderMap::new();
-    headers.append(
-        "Set-Cookie",
-        format!(
-            "access-token={token}; Secure; HttpOnly; Path=/; Max-Age={}",
-            token_data.expires_in().unwrap().as_secs()
-        )
-        .parse()?,
-    );
-    headers.append(
-        "Set-Cookie",
-        format!(
-            "username={}; Path=/; Max-Age={}",
-            discord_user_info.username,
-            token_data.expires_in().unwrap().as_secs()
-        )
-        .parse()?,
-    );
-    Ok((headers, redirect))
+/// Renew a user's Discord access token with their stored refresh token, if it has expired.
+///
+/// Returns `false` if the token couldn't be renewed because the user has no refresh token, or
+/// Discord rejected it (for example because they removed Hyde from their authorized apps), in
+/// which case they have to log in again.
+#[allow(clippy::significant_drop_tightening)]
+pub(super) async fn refresh_discord_token(
+    state: &AppState,
+    user_id: i64,
+) -> color_eyre::Result<bool> {
+    // Refresh tokens can only be used once, so concurrent refreshes would invalidate each other
+    let _guard = state.discord_refresh_lock.lock().await;
+    let user = state
+        .db
+        .get_user(user_id)
+        .await?
+        .wrap_err("Can't refresh the Discord token of a user that doesn't exist")?;
+    if !discord_token_expired(&user) {
+        return Ok(true);
+    }
+    let Some(refresh_token) = state.db.get_refresh_token(user_id).await? else {
+        return Ok(false);
+    };
+    let token_data = match state
+        .oauth
+        .exchange_refresh_token(&RefreshToken::new(refresh_token))
+        .request_async(&state.reqwest_client)
+        .await
+    {
+        Ok(token_data) => token_data,
+        Err(RequestTokenError::ServerResponse(e)) => {
+            info!(
+                "Discord rejected the refresh token of user {:?}, they'll have to log in again: {e}",
+                user.username
+            );
+            state.db.set_refresh_token(user_id, None).await?;
+            audit::record(
+                &state.db,
+                AuditCategory::Auth,
+                "token_refresh_failed",
+                Some(&user),
+                None,
+                "Discord rejected the refresh token",
+            )
+            .await;
+            return Ok(false);
+        }
+        Err(e) => return Err(e).wrap_err("Discord token refresh request failed"),
+    };
+    let expiration_date = Utc::now()
+        + token_data
+            .expires_in()
+            .wrap_err("Discord OAuth2 response didn't include an expiration date")?;
+    let user = User {
+        token: token_data.access_token().secret().to_string(),
+        expiration_date,
+        ..user
+    };
+    state.db.update_user(&user).await?;
+    if let Some(refresh_token) = token_data.refresh_token() {
+        state
+            .db
+            .set_refresh_token(user_id, Some(refresh_token.secret()))
+            .await?;
+    }
+    debug!("Refreshed the Discord token of user {user_id}");
+    audit::record(
+        &state.db,
+        AuditCategory::Auth,
+        "token_refresh",
+        Some(&user),
+        None,
+        "Discord token refreshed",
+    )
+    .await;
+    Ok(true)
 }
 
 pub async fn create_oauth_route() -> Router<AppState> {
@@ -199,3 +428,70 @@ pub async fn create_oauth_route() -> Router<AppState> {
         .route("/oauth", get(get_oauth2_handler))
         .route("/oauth/url", get(get_oauth2_url))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes() {
+        let url = || {
+            reqwest::Url::parse(
+                "https://discord.com/oauth2/authorize?client_id=1&scope=identify+email&state=x",
+            )
+            .unwrap()
+        };
+        let scope = |url: reqwest::Url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "scope")
+                .unwrap()
+                .1
+                .into_owned()
+        };
+        assert_eq!(scope(with_scopes(url(), None, &[])), "email identify");
+        assert_eq!(
+            scope(with_scopes(url(), None, &[GUILD_MEMBERS_SCOPE])),
+            "email guilds.members.read identify"
+        );
+        assert_eq!(
+            scope(with_scopes(url(), Some(&["guilds".to_string()]), &[])),
+            "guilds identify",
+            "with_scopes: configured scopes should replace the URL's, keeping identify"
+        );
+        let url = with_scopes(url(), None, &[]);
+        assert!(url
+            .query_pairs()
+            .any(|(key, value)| key == "state" && value == "x"));
+    }
+
+    #[test]
+    fn login_state() {
+        let cookie = login_state_cookie("abc", LoginIntent::Link).unwrap();
+        let cookie = cookie["Set-Cookie"].to_str().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("Cookie", cookie.split_once(';').unwrap().0.parse().unwrap());
+        assert_eq!(login_intent(&headers, "abc").unwrap(), LoginIntent::Link);
+        assert!(login_intent(&headers, "xyz").is_err());
+        assert!(
+            login_intent(&HeaderMap::new(), "abc").is_err(),
+            "login_intent: signing in without the state cookie should be refused"
+        );
+    }
+
+    #[test]
+    fn public_url() {
+        let mut config = crate::app_conf::AppConf::default();
+        config.oauth.discord.url = "https://discord.com/oauth2/authorize?client_id=1&redirect_uri=https%3A%2F%2Fhyde.example.org%2Fapi%2Foauth&scope=identify".to_string();
+        assert_eq!(
+            config.public_url().as_deref(),
+            Some("https://hyde.example.org")
+        );
+        config.auth.public_url = Some("https://wiki.example.org/".to_string());
+        assert_eq!(
+            config.public_url().as_deref(),
+            Some("https://wiki.example.org"),
+            "public_url: the configured URL should be used over Discord's"
+        );
+        assert_eq!(crate::app_conf::AppConf::default().public_url(), None);
+    }
+}
@@ -0,0 +1,163 @@
+//! Endpoints for assigning owners to documents and directories, and reporting on unowned content.
+//! Owners are asked to review pull requests changing their documents, see
+//! [`doc_owner_reviewers`], and reminded about documents that haven't changed in a while, see
+//! [`crate::stale_content`].
+use axum::routing::get;
+use axum::{
+    extract::{Query, State},
+    Json, Router,
+};
+use chrono::Utc;
+use color_eyre::Result;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+use crate::{
+    db::DocOwner, eyre_to_axum_err, perms::required::MANAGE_CONTENT, AppState, AuthenticatedUser,
+    RequirePermission,
+};
+
+use super::{GetDocQuery, ReadAccess};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PutDocOwnerRequestBody {
+    /// The document, or directory if it ends in `/`, relative to the documents folder
+    path: String,
+    user_id: Option<i64>,
+    group_id: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UnownedDocsResponse {
+    paths: Vec<String>,
+}
+
+/// Returns the owners responsible for the document at `?path=`, including owners
+/// inherited from parent directories.
+pub async fn get_doc_owners_handler(
+    State(state): State<AppState>,
+    _: AuthenticatedUser,
+    _: ReadAccess,
+    Query(query): Query<GetDocQuery>,
+) -> Result<Json<Vec<DocOwner>>, (StatusCode, String)> {
+    Ok(Json(
+        state
+            .db
+            .resolve_doc_owners(&query.path)
+            .await
+            .map_err(eyre_to_axum_err)?,
+    ))
+}
+
+/// Assign an owner to a document or directory
+pub async fn put_doc_owner_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_CONTENT>,
+    Json(body): Json<PutDocOwnerRequestBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if body.user_id.is_some() == body.group_id.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Exactly one of `user_id` or `group_id` must be provided".to_string(),
+        ));
+    }
+    let added = state
+        .db
+        .add_doc_owner(&body.path, body.user_id, body.group_id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    Ok(if added {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    })
+}
+
+/// Remove every owner assigned directly to `?path=`
+pub async fn delete_doc_owners_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_CONTENT>,
+    Query(query): Query<GetDocQuery>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .db
+        .remove_doc_owners(&query.path)
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lists every document that has no owner, directly or through a parent directory
+pub async fn get_unowned_docs_handler(
+    State(state): State<AppState>,
+    _: AuthenticatedUser,
+    _: ReadAccess,
+) -> Result<Json<UnownedDocsResponse>, (StatusCode, String)> {
+    let owners = state
+        .db
+        .get_all_doc_owners()
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let paths = state
+        .doc_cache
+        .doc_tree(false, || state.git.get_doc_tree(false))
+        .map_err(eyre_to_axum_err)?
+        .file_paths()
+        .into_iter()
+        .filter(|path| !owners.iter().any(|o| o.covers(path)))
+        .collect();
+
+    Ok(Json(UnownedDocsResponse { paths }))
+}
+
+/// Notify the owners of the documents among `changed_files` (relative to the root of the
+/// repository) that pull request `pr_number` needs their review, returning the GitHub logins of
+/// the owners who've linked an account, so the review can be requested from them on GitHub too.
+pub(super) async fn doc_owner_reviewers(
+    state: &AppState,
+    pr_number: u64,
+    changed_files: &[String],
+) -> Result<Vec<String>> {
+    let docs_prefix = format!("{}/", state.config.files.docs_path.trim_matches('/'));
+    // The documents each owner is asked to review, by user id
+    let mut owned_paths: BTreeMap<i64, Vec<&str>> = BTreeMap::new();
+    for path in changed_files
+        .iter()
+        .filter_map(|p| p.strip_prefix(&docs_prefix))
+    {
+        for owner in state.db.resolve_doc_owner_users(path).await? {
+            owned_paths.entry(owner.id).or_default().push(path);
+        }
+    }
+    let now = Utc::now();
+    let mut logins = Vec::new();
+    for (user_id, paths) in owned_paths {
+        let payload = serde_json::json!({ "pull_request": pr_number, "paths": paths });
+        if let Err(e) = state
+            .db
+            .create_notification(user_id, "review_requested", &payload.to_string(), now)
+            .await
+        {
+            warn!("Failed to notify user {user_id} of pull request #{pr_number}: {e:?}");
+        }
+        if let Some(account) = state.db.get_github_account(user_id).await? {
+            logins.push(account.github_login);
+        }
+    }
+    Ok(logins)
+}
+
+pub async fn create_owners_route() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/doc/owners",
+            get(get_doc_owners_handler)
+                .put(put_doc_owner_handler)
+                .delete(delete_doc_owners_handler),
+        )
+        .route("/reports/unowned", get(get_unowned_docs_handler))
+}
@@ -0,0 +1,207 @@
+//! Listing the permissions groups can be given, so that clients don't have to hardcode them,
+//! and deny rules, which take permissions away from users regardless of their groups
+use axum::routing::{delete, get};
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json, Router,
+};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::{client_ip, parse_expiry};
+use crate::audit::{self, AuditCategory};
+use crate::db::PermissionDeny;
+use crate::webhook_queue::timestamp;
+use crate::{
+    eyre_to_axum_err,
+    perms::{required::MANAGE_USERS, Permission},
+    AppState, AuthenticatedUser, RequirePermission,
+};
+
+#[derive(Debug, Serialize)]
+pub struct PermissionResponse {
+    permission: Permission,
+    /// The name shown to admins
+    label: String,
+    description: String,
+    /// Whether the permission is declared in `auth.custom_permissions`
+    custom: bool,
+    /// The ids of the groups that have the permission
+    groups: Vec<i64>,
+}
+
+/// Every built-in and custom permission, along with the groups that have them
+pub async fn get_permissions_handler(
+    State(state): State<AppState>,
+    _: AuthenticatedUser,
+) -> Result<Json<Vec<PermissionResponse>>, (StatusCode, String)> {
+    let mut group_permissions = Vec::new();
+    for group in state.db.get_all_groups().await.map_err(eyre_to_axum_err)? {
+        let permissions = state
+            .db
+            .get_group_permissions(group.id)
+            .await
+            .map_err(eyre_to_axum_err)?;
+        group_permissions.push((group.id, permissions));
+    }
+
+    let custom = state
+        .config
+        .auth
+        .custom_permissions
+        .iter()
+        .map(|name| Permission::Custom(name.clone()));
+    let catalog = Permission::BUILT_IN
+        .into_iter()
+        .chain(custom)
+        .map(|permission| PermissionResponse {
+            label: permission.label().to_string(),
+            description: permission.description().to_string(),
+            custom: matches!(permission, Permission::Custom(_)),
+            groups: group_permissions
+                .iter()
+                .filter(|(_, permissions)| permissions.contains(&permission))
+                .map(|(group_id, _)| *group_id)
+                .collect(),
+            permission,
+        })
+        .collect();
+    Ok(Json(catalog))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDenyRequestBody {
+    /// The user to deny the permission to, set either this or `group_id`
+    user_id: Option<i64>,
+    /// The group whose members to deny the permission to
+    group_id: Option<i64>,
+    permission: Permission,
+    /// An RFC-3339 timestamp, the rule applies until it's removed if unset
+    expires_at: Option<String>,
+}
+
+/// Every deny rule, including expired ones, newest first
+pub async fn get_denies_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_USERS>,
+) -> Result<Json<Vec<PermissionDeny>>, (StatusCode, String)> {
+    Ok(Json(
+        state
+            .db
+            .get_permission_denies()
+            .await
+            .map_err(eyre_to_axum_err)?,
+    ))
+}
+
+/// Deny a permission to a user or the members of a group, until `expires_at` if it's set
+pub async fn post_deny_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Json(body): Json<CreateDenyRequestBody>,
+) -> Result<(StatusCode, Json<PermissionDeny>), (StatusCode, String)> {
+    let subject = match (body.user_id, body.group_id) {
+        (Some(user_id), None) => format!("user {user_id}"),
+        (None, Some(group_id)) => format!("group {group_id}"),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                String::from("Set exactly one of user_id and group_id"),
+            ))
+        }
+    };
+    if !body
+        .permission
+        .is_declared(&state.config.auth.custom_permissions)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "{:?} isn't a permission",
+                String::from(body.permission.clone())
+            ),
+        ));
+    }
+    let expires_at = parse_expiry(body.expires_at.as_deref())?;
+
+    let created_at = Utc::now();
+    let id = state
+        .db
+        .add_permission_deny(
+            body.user_id,
+            body.group_id,
+            body.permission.clone(),
+            expires_at,
+            created_at,
+        )
+        .await
+        .map_err(eyre_to_axum_err)?;
+    audit::record(
+        &state.db,
+        AuditCategory::Perms,
+        "deny_added",
+        Some(&user),
+        client_ip(&headers),
+        format!(
+            "Denied {} to {subject} until {}",
+            String::from(body.permission.clone()),
+            expires_at.map_or_else(|| "the rule is removed".to_string(), timestamp)
+        ),
+    )
+    .await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PermissionDeny {
+            id,
+            user_id: body.user_id,
+            group_id: body.group_id,
+            permission: body.permission.into(),
+            expires_at,
+            created_at,
+        }),
+    ))
+}
+
+/// Remove a deny rule, giving the permission back
+pub async fn delete_deny_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(deny_id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let deleted = state
+        .db
+        .delete_permission_deny(deny_id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !deleted {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No deny rule with id {deny_id}"),
+        ));
+    }
+    audit::record(
+        &state.db,
+        AuditCategory::Perms,
+        "deny_removed",
+        Some(&user),
+        client_ip(&headers),
+        format!("Removed deny rule {deny_id}"),
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_permissions_route() -> Router<AppState> {
+    Router::new()
+        .route("/permissions", get(get_permissions_handler))
+        .route(
+            "/permissions/denies",
+            get(get_denies_handler).post(post_deny_handler),
+        )
+        .route("/permissions/denies/{deny_id}", delete(delete_deny_handler))
+}
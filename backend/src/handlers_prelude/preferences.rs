@@ -0,0 +1,99 @@
+//! Per-user editor settings (theme, editor mode, notification options, et cetera), stored on the
+//! server so they follow the user between browsers. The server doesn't interpret them, each
+//! preference is a key with an arbitrary JSON value.
+use axum::routing::get;
+use axum::{extract::State, Json, Router};
+use reqwest::StatusCode;
+use serde_json::{Map, Value};
+
+use crate::{eyre_to_axum_err, AppState, AuthenticatedUser};
+
+/// The most preferences a user can have
+const MAX_PREFERENCES: usize = 64;
+/// The longest a preference's key can be, in bytes
+const MAX_KEY_LEN: usize = 64;
+/// The longest a preference's value can be once serialized, in bytes
+const MAX_VALUE_LEN: usize = 4096;
+
+/// Returns the user's preferences as a JSON object
+async fn preferences_object(
+    state: &AppState,
+    user_id: i64,
+) -> Result<Map<String, Value>, (StatusCode, String)> {
+    let preferences = state
+        .db
+        .get_user_preferences(user_id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(preferences
+        .into_iter()
+        .map(|(key, value)| {
+            let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+            (key, value)
+        })
+        .collect())
+}
+
+/// The current user's preferences, as an object of keys to JSON values
+pub async fn get_preferences_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Map<String, Value>>, (StatusCode, String)> {
+    Ok(Json(preferences_object(&state, user.id).await?))
+}
+
+/// Update the current user's preferences. The body is an object of keys to JSON values, keys it
+/// leaves out are kept and keys set to `null` are removed. Returns every preference afterwards.
+pub async fn put_preferences_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(body): Json<Map<String, Value>>,
+) -> Result<Json<Map<String, Value>>, (StatusCode, String)> {
+    let mut changes = Vec::with_capacity(body.len());
+    for (key, value) in body {
+        if key.trim().is_empty() || key.len() > MAX_KEY_LEN {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Preference keys must be between 1 and {MAX_KEY_LEN} bytes long"),
+            ));
+        }
+        let value = (!value.is_null()).then(|| value.to_string());
+        if value.as_ref().is_some_and(|v| v.len() > MAX_VALUE_LEN) {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("The value of {key:?} is longer than {MAX_VALUE_LEN} bytes"),
+            ));
+        }
+        changes.push((key, value));
+    }
+
+    let existing = preferences_object(&state, user.id).await?;
+    let added = changes
+        .iter()
+        .filter(|(key, value)| value.is_some() && !existing.contains_key(key))
+        .count();
+    let removed = changes
+        .iter()
+        .filter(|(key, value)| value.is_none() && existing.contains_key(key))
+        .count();
+    if existing.len() + added - removed > MAX_PREFERENCES {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Users can have at most {MAX_PREFERENCES} preferences"),
+        ));
+    }
+
+    state
+        .db
+        .set_user_preferences(user.id, &changes)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(preferences_object(&state, user.id).await?))
+}
+
+pub async fn create_preferences_route() -> Router<AppState> {
+    Router::new().route(
+        "/users/me/preferences",
+        get(get_preferences_handler).put(put_preferences_handler),
+    )
+}
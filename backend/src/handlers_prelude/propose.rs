@@ -0,0 +1,343 @@
+//! Saving a document as a pull request in one step: the branch is created, the document is
+//! committed and pushed, and the pull request is opened. If any step fails, the branch is
+//! cleaned up so that no orphan branches are left behind.
+//!
+//! On repositories where direct pushes are restricted to the GitHub App, the push is rejected,
+//! so the branch is created and the document committed through the GitHub API instead.
+use axum::routing::post;
+use axum::{extract::State, http::HeaderMap, Json, Router};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    db::User, eyre_to_axum_err, gh::RepoMetadata, git::PushRejected, perms::Permission,
+    policy::PolicyViolation, AppState, AuthenticatedUser,
+};
+
+use super::{
+    check_branch_push, client_ip,
+    github_handlers::{pull_request_description, record_hyde_pr, request_code_owner_reviews},
+    github_link::{assign_linked_account, with_co_author},
+    repo_fs::{enforce_doc_policy, get_gh_token},
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProposeDocRequestBody {
+    /// The path of the document, relative to the documents folder
+    pub path: String,
+    pub contents: String,
+    pub commit_message: String,
+    pub title: String,
+    pub description: String,
+    /// The branch the pull request should be merged into, defaults to the repository's
+    /// default branch
+    pub base_branch: Option<String>,
+    pub issue_numbers: Option<Vec<u64>>,
+    /// The number of the milestone to assign the pull request to
+    pub milestone: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProposeDocResponse {
+    pub pull_request_url: String,
+    pub pull_request_number: u64,
+    /// The branch the document was committed to
+    pub branch: String,
+    /// Content policy checks that failed, but aren't configured to block saving
+    pub warnings: Vec<PolicyViolation>,
+}
+
+/// Generate a name for the branch a document is proposed on, e.g.
+/// `hyde/username/installing-drivers-20250101120000`
+fn proposal_branch_name(username: &str, path: &str) -> String {
+    let sanitize = |s: &str| -> String {
+        s.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .collect::<String>()
+            .trim_matches('-')
+            .to_string()
+    };
+    let stem = path
+        .rsplit('/')
+        .next()
+        .unwrap_or(path)
+        .trim_end_matches(".md");
+    format!(
+        "hyde/{}/{}-{}",
+        sanitize(username),
+        sanitize(stem),
+        Utc::now().format("%Y%m%d%H%M%S")
+    )
+}
+
+/// Create the proposal branch and commit the document through the GitHub API, for when the
+/// branch couldn't be pushed. The branch is deleted again if the commit fails.
+async fn propose_doc_via_api(
+    state: &AppState,
+    path: &str,
+    contents: &str,
+    message: &str,
+    branch: &str,
+    rejected: &PushRejected,
+) -> Result<(), (StatusCode, String)> {
+    let to_axum_err = |e: color_eyre::Report| {
+        error!("Failed to propose changes through the GitHub API: {e:?}");
+        (
+            StatusCode::BAD_GATEWAY,
+            "Failed to commit the document to GitHub, check server logs for more info".to_string(),
+        )
+    };
+    state
+        .gh_client
+        .create_branch(branch, &rejected.base_commit)
+        .await
+        .map_err(to_axum_err)?;
+    if let Err(e) = state
+        .gh_client
+        .put_file(
+            &state.git.doc_repo_path(path),
+            contents.as_bytes(),
+            &format!("[Hyde]: {message}"),
+            branch,
+            rejected.existing_blob.as_deref(),
+        )
+        .await
+    {
+        if let Err(e) = state.gh_client.delete_branch(branch).await {
+            error!("Failed to delete orphaned branch {branch:?}: {e:?}");
+        }
+        return Err(to_axum_err(e));
+    }
+    Ok(())
+}
+
+/// The repository's default branch, from the cached repository metadata
+pub(super) async fn default_branch(state: &AppState) -> Result<String, (StatusCode, String)> {
+    to_default_branch(state.repo_metadata.get(&state.gh_client).await)
+}
+
+/// The repository's default branch, fetched from GitHub rather than read from the cache. Used
+/// where a wrong default branch would let users push somewhere they aren't allowed to.
+pub(super) async fn fetch_default_branch(state: &AppState) -> Result<String, (StatusCode, String)> {
+    to_default_branch(state.repo_metadata.refresh(&state.gh_client).await)
+}
+
+fn to_default_branch(
+    metadata: color_eyre::Result<RepoMetadata>,
+) -> Result<String, (StatusCode, String)> {
+    Ok(metadata
+        .map_err(|e| {
+            error!("Failed to fetch the default branch: {e:?}");
+            (
+                StatusCode::BAD_GATEWAY,
+                "Failed to fetch the repository's default branch".to_string(),
+            )
+        })?
+        .default_branch)
+}
+
+/// Commit a document to a new branch and open a pull request for it. Users need either the
+/// `ManageContent` or the `SubmitForReview` permission.
+pub async fn post_propose_doc_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(author): AuthenticatedUser,
+    headers: HeaderMap,
+    Json(body): Json<ProposeDocRequestBody>,
+) -> Result<(StatusCode, Json<ProposeDocResponse>), (StatusCode, String)> {
+    let permissions = state
+        .db
+        .get_user_permissions(author.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !permissions.contains(&Permission::ManageContent)
+        && !permissions.contains(&Permission::SubmitForReview)
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!(
+                "User {:?} lacks the permission to propose changes.",
+                author.username
+            ),
+        ));
+    }
+    propose_doc(&state, &author, client_ip(&headers), body)
+        .await
+        .map(|r| (StatusCode::CREATED, Json(r)))
+}
+
+/// Commit a document to a new branch and open a pull request for it, for a user who's allowed
+/// to propose changes
+pub(super) async fn propose_doc(
+    state: &AppState,
+    author: &User,
+    ip_address: Option<String>,
+    body: ProposeDocRequestBody,
+) -> Result<ProposeDocResponse, (StatusCode, String)> {
+    let warnings =
+        enforce_doc_policy(state, author, ip_address, &body.path, &body.contents).await?;
+
+    let base_branch = match body.base_branch {
+        Some(base_branch) => base_branch,
+        None => default_branch(state).await?,
+    };
+    let branch = proposal_branch_name(&author.username, &body.path);
+    // Only the proposal branch is pushed, the base branch is changed by merging the pull request
+    check_branch_push(state, author, &branch).await?;
+    let default_commit_message = format!("{} updated {}", author.username, body.path);
+    let final_commit_message = with_co_author(
+        state,
+        author,
+        format!("{}\n\n{}", default_commit_message, body.commit_message),
+    )
+    .await;
+    let token = get_gh_token(state).await?;
+
+    let pushed_via_api = match state.git.propose_doc(
+        &body.path,
+        &body.contents,
+        &final_commit_message,
+        &token,
+        &base_branch,
+        &branch,
+    ) {
+        Ok(()) => false,
+        Err(e) => match e.downcast::<PushRejected>() {
+            Ok(rejected) => {
+                warn!("{rejected}, creating branch {branch:?} through the GitHub API instead");
+                propose_doc_via_api(
+                    state,
+                    &body.path,
+                    &body.contents,
+                    &final_commit_message,
+                    &branch,
+                    &rejected,
+                )
+                .await?;
+                true
+            }
+            Err(e) => {
+                error!("Failed to commit proposed changes: {e:?}");
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to commit the document, check server logs for more info".to_string(),
+                ));
+            }
+        },
+    };
+
+    let pull_request = match state
+        .gh_client
+        .create_pull_request(
+            &branch,
+            &base_branch,
+            &body.title,
+            &pull_request_description(state, &body.description),
+            body.issue_numbers,
+            body.milestone,
+        )
+        .await
+    {
+        Ok(pull_request) => pull_request,
+        Err(e) => {
+            error!("Failed to open a pull request for {branch:?}, deleting the branch: {e:?}");
+            let deleted = if pushed_via_api {
+                state.gh_client.delete_branch(&branch).await
+            } else {
+                state.git.delete_branch(&branch, &token)
+            };
+            if let Err(e) = deleted {
+                error!("Failed to delete orphaned branch {branch:?}: {e:?}");
+            }
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to create pull request: {e}"),
+            ));
+        }
+    };
+    info!(
+        "{} proposed changes to {:?} in pull request #{}",
+        author.username, body.path, pull_request.number
+    );
+    assign_linked_account(state, author, pull_request.number).await;
+    record_hyde_pr(
+        state,
+        pull_request.number,
+        &body.title,
+        Some(author),
+        &branch,
+        vec![body.path.clone()],
+    )
+    .await;
+    // The pull request already exists, failing to find reviewers shouldn't fail the request
+    if let Err(e) =
+        request_code_owner_reviews(state, pull_request.number, &base_branch, &branch).await
+    {
+        warn!(
+            "Failed to request reviews for pull request #{}: {e:?}",
+            pull_request.number
+        );
+    }
+
+    Ok(ProposeDocResponse {
+        pull_request_url: pull_request.html_url,
+        pull_request_number: pull_request.number,
+        branch,
+        warnings,
+    })
+}
+
+pub async fn create_propose_route() -> Router<AppState> {
+    Router::new().route("/doc/propose", post(post_propose_doc_handler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::handlers_prelude::may_push_branch;
+    use chrono::DateTime;
+
+    #[test]
+    fn branch_names() {
+        let name = proposal_branch_name("Some.User", "guides/Installing Drivers.md");
+        assert!(
+            name.starts_with("hyde/some-user/installing-drivers-"),
+            "proposal_branch_name: should only contain characters valid in branch names, got {name:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn proposal_push_rules() {
+        let db = Database::from_url(":memory:").await.unwrap();
+        let author = db
+            .create_user(
+                "alice".to_string(),
+                String::new(),
+                DateTime::UNIX_EPOCH,
+                String::new(),
+            )
+            .await
+            .unwrap();
+        let reviewers = db.create_group("Reviewers".to_string()).await.unwrap();
+        db.set_group_branch_rules(reviewers.id, &["hyde/*".to_string()])
+            .await
+            .unwrap();
+        let branch = proposal_branch_name(&author.username, "guides/drivers.md");
+        assert!(
+            !may_push_branch(&db, &author, &branch).await.unwrap(),
+            "proposals should be refused by rules on the proposal branches"
+        );
+        db.add_group_membership(reviewers.id, author.id)
+            .await
+            .unwrap();
+        assert!(may_push_branch(&db, &author, &branch).await.unwrap());
+    }
+}
@@ -2,16 +2,30 @@ use axum::routing::post;
 use axum::{extract::State, http::HeaderMap, Router};
 use reqwest::StatusCode;
 
-use crate::{perms::Permission, AppState};
+use crate::audit::{self, AuditCategory};
+use crate::search;
+use crate::{perms::required::MANAGE_REPO, AppState, RequirePermission};
 
-use super::{eyre_to_axum_err, require_perms};
+use super::{client_ip, eyre_to_axum_err, require_second_factor};
 
 pub async fn post_reclone_handler(
     State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_REPO>,
     headers: HeaderMap,
 ) -> Result<(), (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
+    require_second_factor(&state, &user, &headers).await?;
     state.git.reclone().map_err(eyre_to_axum_err)?;
+    state.doc_cache.clear();
+    search::spawn_rebuild(state.clone());
+    audit::record(
+        &state.db,
+        AuditCategory::Repo,
+        "recloned",
+        Some(&user),
+        client_ip(&headers),
+        "Recloned the repository",
+    )
+    .await;
     Ok(())
 }
 
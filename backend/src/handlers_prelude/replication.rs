@@ -0,0 +1,114 @@
+//! Endpoints for running a read-only standby, see [`crate::replica`]
+use axum::routing::{get, post};
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, header::CONTENT_TYPE, HeaderMap},
+    response::IntoResponse,
+    Json, Router,
+};
+use fs_err::tokio as fs;
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use super::client_ip;
+use crate::audit::{self, AuditCategory};
+use crate::replica::{token_matches, Role, SyncStatus};
+use crate::{eyre_to_axum_err, perms::required::MANAGE_USERS, AppState, RequirePermission};
+
+#[derive(Debug, Serialize)]
+pub struct ReplicationStatusResponse {
+    role: Role,
+    #[serde(flatten)]
+    status: SyncStatus,
+}
+
+/// Serves a snapshot of the database to replicas, authenticated with `replication.token`
+pub async fn get_snapshot_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let expected = state.config.replication.token.as_deref().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "Replication is not enabled on this instance.".to_string(),
+        )
+    })?;
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !token_matches(expected, provided) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Invalid replication token.".to_string(),
+        ));
+    }
+
+    // Written next to the database rather than to the shared temporary folder, since it holds
+    // everything the database does
+    let path = state
+        .data_dir
+        .join(format!("hyde-snapshot-{}.db", rand::random::<u64>()));
+    state.db.snapshot(&path).await.map_err(eyre_to_axum_err)?;
+    let snapshot = fs::read(&path).await;
+    fs::remove_file(&path)
+        .await
+        .map_err(|e| eyre_to_axum_err(e.into()))?;
+    let snapshot = snapshot.map_err(|e| eyre_to_axum_err(e.into()))?;
+    let entry = audit::entry(
+        AuditCategory::Database,
+        "snapshot_downloaded",
+        None,
+        client_ip(&headers),
+        "Database snapshot downloaded by a replica",
+    );
+    let data = serde_json::json!({ "bytes": snapshot.len() });
+    audit::record_entry(&state.db, entry.with_data(&data)).await;
+    Ok(([(CONTENT_TYPE, "application/vnd.sqlite3")], snapshot))
+}
+
+/// Whether this instance is a primary, replica, or promoted replica, and how syncing is going
+pub async fn get_replication_status_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_USERS>,
+) -> Result<Json<ReplicationStatusResponse>, (StatusCode, String)> {
+    Ok(Json(ReplicationStatusResponse {
+        role: state.replica.role(state.config),
+        status: state.replica.status(),
+    }))
+}
+
+/// Stop following the primary and start accepting changes
+pub async fn post_promote_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if state.replica.role(state.config) != Role::Replica {
+        return Err((
+            StatusCode::CONFLICT,
+            "This instance isn't a read-only replica.".to_string(),
+        ));
+    }
+    if state.replica.promote() {
+        crate::spawn_writers(&state);
+        audit::record(
+            &state.db,
+            AuditCategory::Database,
+            "replica_promoted",
+            Some(&user),
+            client_ip(&headers),
+            "Promoted the replica, it now accepts changes",
+        )
+        .await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_replication_route() -> Router<AppState> {
+    Router::new()
+        .route("/replication/snapshot", get(get_snapshot_handler))
+        .route("/replication/status", get(get_replication_status_handler))
+        .route("/replication/promote", post(post_promote_handler))
+}
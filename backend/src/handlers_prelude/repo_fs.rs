@@ -1,21 +1,45 @@
 //! Endpoints for interacting with the repository's filesystem (create doc/asset, read doc/asset, et cetera)
-use crate::git::INode;
+use crate::{
+    api_tokens,
+    audit::{self, AuditCategory},
+    db::{Database, DocOwner, SearchHit, User},
+    front_matter::{self, FrontMatter},
+    git::{to_slash_path, ChangeKind, ChangedPath, INode, PathError, KEEP_FILE},
+    image_metadata::{strip_metadata, StrippedMetadata},
+    policy::{check_asset, check_doc, PolicyReport, PolicyViolation},
+    related::{RelatedIndex, RelatedPage},
+    render, search,
+};
 use axum::{
     body::Bytes,
     debug_handler,
     extract::{DefaultBodyLimit, Path, Query, State},
     http::{HeaderMap, StatusCode},
     response::IntoResponse,
-    routing::get,
+    routing::{get, post},
     Json, Router,
 };
+use chrono::Utc;
 use reqwest::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
-use tracing::{error, warn};
+use std::time::Instant;
+use tracing::{error, info, warn};
 
-use crate::{perms::Permission, require_perms, AppState};
+use crate::{
+    perms::{
+        required::{MANAGE_ASSETS, MANAGE_CONTENT},
+        Permission,
+    },
+    AppState, AuthenticatedUser, RequirePermission,
+};
 
-use super::eyre_to_axum_err;
+use super::{
+    check_branch_push, client_ip, eyre_to_axum_err,
+    github_link::with_co_author,
+    propose::{fetch_default_branch, propose_doc, ProposeDocRequestBody},
+    telemetry::record_server_save,
+    ReadAccess,
+};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetDocQuery {
@@ -25,9 +49,256 @@ pub struct GetDocQuery {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetDocResponse {
     pub contents: String,
+    /// The users and groups responsible for this document
+    pub owners: Vec<DocOwner>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetRenderedDocResponse {
+    /// The document's title, see [`search::indexed_text`]
+    pub title: String,
+    /// The document rendered to sanitized HTML, see [`render::render`]
+    pub html: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetDocMetaResponse {
+    /// The document's parsed front matter, or `None` if it doesn't have any
+    pub front_matter: Option<FrontMatter>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetDocTreeQuery {
+    /// Whether documents in the archive folder should be included
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateDirRequestBody {
+    /// The folder to create, relative to the documents or assets folder
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteDirQuery {
+    /// The folder to delete, relative to the documents folder
+    pub path: String,
+    /// The `confirm` token returned by a request without it
+    pub confirm: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteDirResponse {
+    pub path: String,
+    /// How many files were (or without confirmation, would be) deleted
+    pub changed_files: usize,
+    /// Pass this back as `?confirm=` to delete the folder, `None` once it's deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirm: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MoveDirRequestBody {
+    /// The folder to move, relative to the documents or assets folder
+    pub from: String,
+    /// Where to move it, relative to the same folder. Missing parent folders are created.
+    pub to: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MoveDirResponse {
+    /// The new location of the folder
+    pub path: String,
+    /// How many files were moved
+    pub changed_files: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetRelatedDocsQuery {
+    pub path: String,
+    /// The maximum number of pages to return
+    #[serde(default = "default_related_limit")]
+    pub limit: usize,
+}
+
+const fn default_related_limit() -> usize {
+    5
+}
+
+/// Requests for more related pages than this are clamped
+const MAX_RELATED_LIMIT: usize = 25;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SearchDocsQuery {
+    /// What to search for. Punctuation is ignored, every word must be present.
+    pub q: String,
+    /// The maximum number of documents to return
+    #[serde(default = "default_search_limit")]
+    pub limit: i64,
+}
+
+const fn default_search_limit() -> i64 {
+    20
+}
+
+/// Requests for more search results than this are clamped
+const MAX_SEARCH_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ArchiveDocResponse {
+    /// The new location of the document, relative to the documents folder
+    pub path: String,
+}
+
+/// Returned when a document or asset is saved
+#[derive(Debug, Serialize)]
+pub struct PutFileResponse {
+    /// Content policy checks that failed, but aren't configured to block saving
+    pub warnings: Vec<PolicyViolation>,
+    /// Metadata removed from an uploaded image
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stripped_metadata: Vec<StrippedMetadata>,
+    /// The pull request the save was submitted for review in, see
+    /// [`Permission::SubmitForReview`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_request_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PutAssetQuery {
+    /// Keep the image's metadata, even if `files.strip_image_metadata` is enabled
+    #[serde(default)]
+    pub keep_metadata: bool,
+}
+
+/// Run the content policy checks on a document about to be saved by `author`. If a check
+/// configured to block fails, the save is rejected and recorded in the audit log, otherwise the
+/// failed checks are returned so they can be shown to the user.
+pub(super) async fn enforce_doc_policy(
+    state: &AppState,
+    author: &User,
+    ip_address: Option<String>,
+    path: &str,
+    contents: &str,
+) -> Result<Vec<PolicyViolation>, (StatusCode, String)> {
+    let repo_path = state.git.doc_repo_path(path);
+    let files_in_commit = count_files_in_commit(state, repo_path.clone())?;
+    let mut report = check_doc(&state.config.policy, path, contents, files_in_commit);
+    report
+        .violations
+        .extend(state.plugins.pre_save(&repo_path, contents.as_bytes()));
+    enforce_policy_report(&state.db, author, ip_address, path, report).await
+}
+
+/// Like [`enforce_doc_policy`], for an asset at `path` (relative to the assets folder)
+pub(super) async fn enforce_asset_policy(
+    state: &AppState,
+    author: &User,
+    ip_address: Option<String>,
+    path: &str,
+    contents: &[u8],
+) -> Result<Vec<PolicyViolation>, (StatusCode, String)> {
+    let repo_path = state.git.asset_repo_path(path);
+    let files_in_commit = count_files_in_commit(state, repo_path.clone())?;
+    let mut report = check_asset(&state.config.policy, contents, files_in_commit);
+    report
+        .violations
+        .extend(state.plugins.pre_save(&repo_path, contents));
+    enforce_policy_report(&state.db, author, ip_address, path, report).await
+}
+
+/// How many files the next commit will change if the file at `repo_path` is written.
+/// Hyde commits everything in the working tree.
+fn count_files_in_commit(
+    state: &AppState,
+    repo_path: String,
+) -> Result<usize, (StatusCode, String)> {
+    let mut files_in_commit = state.git.uncommitted_files().map_err(eyre_to_axum_err)?;
+    if !files_in_commit.contains(&repo_path) {
+        files_in_commit.push(repo_path);
+    }
+    Ok(files_in_commit.len())
+}
+
+/// The failed content policy checks, as recorded in the audit log
+pub(super) fn policy_audit_data(violations: &[PolicyViolation]) -> serde_json::Value {
+    violations
+        .iter()
+        .map(|v| serde_json::json!({ "check": v.check, "action": v.action, "message": v.message }))
+        .collect()
+}
+
+async fn enforce_policy_report(
+    db: &Database,
+    author: &User,
+    ip_address: Option<String>,
+    path: &str,
+    report: PolicyReport,
+) -> Result<Vec<PolicyViolation>, (StatusCode, String)> {
+    if report.is_blocked() {
+        let entry = audit::entry(
+            AuditCategory::Content,
+            "content_policy_blocked",
+            Some(author),
+            ip_address,
+            format!("Saving {path} was blocked by the content policy"),
+        );
+        let data = serde_json::json!({ "violations": policy_audit_data(&report.violations) });
+        audit::record_entry(db, entry.with_target(path).with_data(&data)).await;
+        let messages: Vec<&str> = report
+            .violations
+            .iter()
+            .map(|v| v.message.as_str())
+            .collect();
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "The save was blocked by the content policy: {}",
+                messages.join("; ")
+            ),
+        ));
+    }
+    Ok(report.violations)
 }
 
-async fn get_gh_token(state: &AppState) -> Result<String, (StatusCode, String)> {
+/// Invalidate cached data after the document at `path` was written to `branch`. Writing to a
+/// branch other than the one that was checked out switches branches, which can change
+/// every document.
+pub(super) fn invalidate_written_doc(
+    state: &AppState,
+    previous_branch: Option<&str>,
+    branch: &str,
+    path: &str,
+) {
+    if previous_branch == Some(branch) {
+        state.doc_cache.invalidate_doc(path, ChangeKind::Added);
+        search::spawn_update_doc(state, path, ChangeKind::Added);
+    } else {
+        state.doc_cache.clear();
+        search::spawn_rebuild(state.clone());
+    }
+}
+
+/// Record `author` as the last editor of the document at `path`. Failures are only logged, the
+/// document has already been saved.
+pub(super) async fn record_doc_edit(
+    state: &AppState,
+    path: &str,
+    author: &User,
+    description: Option<&str>,
+) {
+    let now = Utc::now();
+    if let Err(e) = state
+        .db
+        .record_doc_edit(path, author, now, description)
+        .await
+    {
+        warn!("Failed to record the edit of {path:?}: {e:?}");
+    }
+}
+
+pub(super) async fn get_gh_token(state: &AppState) -> Result<String, (StatusCode, String)> {
     state.gh_client.get_token().await.map_err(|e| {
         error!("Failed to retrieve GitHub token: {e}");
         (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
@@ -38,16 +309,113 @@ async fn get_gh_token(state: &AppState) -> Result<String, (StatusCode, String)>
 /// TODO: refactor to pass it in directly as a url path instead of doing the whole url arguments thing
 pub async fn get_doc_handler(
     State(state): State<AppState>,
+    _: ReadAccess,
     Query(query): Query<GetDocQuery>,
 ) -> Result<Json<GetDocResponse>, (StatusCode, &'static str)> {
     match state.git.get_doc(&query.path) {
-        Ok(maybe_doc) => maybe_doc.map_or(
+        Ok(Some(doc)) => {
+            let owners = state
+                .db
+                .resolve_doc_owners(&query.path)
+                .await
+                .map_err(|e| {
+                    error!("Failed to resolve owners for {:?}: {e:?}", query.path);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Fetch failed, check server logs for more info",
+                    )
+                })?;
+            Ok(Json(GetDocResponse {
+                contents: doc,
+                owners,
+            }))
+        }
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            "The file at the provided path was not found.",
+        )),
+        Err(e) => {
+            warn!(
+                "Failed to fetch doc with path: {:?}; error: {:?}",
+                query.path, e
+            );
             Err((
-                StatusCode::NOT_FOUND,
-                "The file at the provided path was not found.",
-            )),
-            |doc| Ok(Json(GetDocResponse { contents: doc })),
-        ),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fetch failed, check server logs for more info",
+            ))
+        }
+    }
+}
+
+/// This handler accepts a `GET` request to `/api/doc/meta?path=`, returning the document's front
+/// matter as JSON. Front matter that can't be parsed is rejected with `422 Unprocessable Entity`.
+pub async fn get_doc_meta_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Query(query): Query<GetDocQuery>,
+) -> Result<Json<GetDocMetaResponse>, (StatusCode, String)> {
+    match state.git.get_doc(&query.path) {
+        Ok(Some(doc)) => match front_matter::parse(&doc) {
+            Ok(front_matter) => Ok(Json(GetDocMetaResponse { front_matter })),
+            Err(e) => Err((StatusCode::UNPROCESSABLE_ENTITY, e.to_string())),
+        },
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            String::from("The file at the provided path was not found."),
+        )),
+        Err(e) => {
+            warn!(
+                "Failed to fetch doc with path: {:?}; error: {:?}",
+                query.path, e
+            );
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                String::from("Fetch failed, check server logs for more info"),
+            ))
+        }
+    }
+}
+
+/// This handler accepts a `GET` request to `/api/doc/rendered?path=`, returning the document
+/// rendered to HTML the way the site would show it, minus what Jekyll fills in (includes, site
+/// variables).
+pub async fn get_rendered_doc_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Query(query): Query<GetDocQuery>,
+) -> Result<Json<GetRenderedDocResponse>, (StatusCode, &'static str)> {
+    match state.git.get_doc(&query.path) {
+        Ok(Some(doc)) => {
+            let (title, _) = search::indexed_text(&query.path, &doc);
+            let repo_path = format!(
+                "{}/{}",
+                state.config.files.docs_path.trim_matches('/'),
+                query.path.trim_start_matches('/')
+            );
+            let html = state
+                .doc_cache
+                .rendered(
+                    &query.path,
+                    &doc,
+                    render::config_hash(&state.plugins),
+                    |doc| state.plugins.on_render(&repo_path, render::render(doc)),
+                )
+                .map_err(|e| {
+                    warn!(
+                        "Failed to render doc with path: {:?}; error: {e:?}",
+                        query.path
+                    );
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Rendering failed, check server logs for more info",
+                    )
+                })?;
+            Ok(Json(GetRenderedDocResponse { title, html }))
+        }
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            "The file at the provided path was not found.",
+        )),
         Err(e) => {
             warn!(
                 "Failed to fetch doc with path: {:?}; error: {:?}",
@@ -67,27 +435,93 @@ pub struct PutDocRequestBody {
     path: String,
     commit_message: String,
     branch_name: String,
+    /// A short summary of the document shown in the file tree, the previous one is kept if unset
+    #[serde(default)]
+    description: Option<String>,
 }
 
+/// Saves a document. Users with the `SubmitForReview` permission but not `ManageContent` can't
+/// save to the default branch, so their saves to it are proposed in a pull request instead, and
+/// `202 Accepted` is returned. Saves to any other branch are checked against the branch push
+/// rules.
 #[debug_handler]
 pub async fn put_doc_handler(
     State(state): State<AppState>,
+    AuthenticatedUser(author): AuthenticatedUser,
     headers: HeaderMap,
     Json(body): Json<PutDocRequestBody>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    let author = require_perms(
-        axum::extract::State(&state),
-        headers,
-        &[Permission::ManageContent],
-    )
-    .await?;
+) -> Result<(StatusCode, Json<PutFileResponse>), (StatusCode, String)> {
+    let started = Instant::now();
+
+    let permissions = state
+        .db
+        .get_user_permissions(author.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !permissions.contains(&Permission::ManageContent) {
+        if !permissions.contains(&Permission::SubmitForReview) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!(
+                    "User {:?} lacks the permission to edit documents.",
+                    author.username
+                ),
+            ));
+        }
+        // Fetched rather than cached, so review-only users can't push straight to the default
+        // branch if the cache is stale
+        let base_branch = fetch_default_branch(&state).await?;
+        if body.branch_name == base_branch {
+            let proposal = propose_doc(
+                &state,
+                &author,
+                client_ip(&headers),
+                ProposeDocRequestBody {
+                    title: format!("{} updated {}", author.username, body.path),
+                    description: body.commit_message.clone(),
+                    path: body.path,
+                    contents: body.contents,
+                    commit_message: body.commit_message,
+                    base_branch: Some(base_branch),
+                    issue_numbers: None,
+                    milestone: None,
+                },
+            )
+            .await?;
+            return Ok((
+                StatusCode::ACCEPTED,
+                Json(PutFileResponse {
+                    warnings: proposal.warnings,
+                    stripped_metadata: Vec::new(),
+                    pull_request_url: Some(proposal.pull_request_url),
+                }),
+            ));
+        }
+    }
+
+    check_branch_push(&state, &author, &body.branch_name).await?;
 
     // Generate commit message combining author and default update message
     let default_commit_message = format!("{} updated {}", author.username, body.path);
-    let final_commit_message = format!("{}\n\n{}", default_commit_message, body.commit_message);
+    let final_commit_message = with_co_author(
+        &state,
+        &author,
+        format!("{}\n\n{}", default_commit_message, body.commit_message),
+    )
+    .await;
+
+    let warnings = enforce_doc_policy(
+        &state,
+        &author,
+        client_ip(&headers),
+        &body.path,
+        &body.contents,
+    )
+    .await?;
 
     // Use the branch name from the request body
     let branch_name = &body.branch_name;
+    let previous_branch = state.git.get_current_branch().await.ok();
 
     match state.git.put_doc(
         &body.path,
@@ -96,7 +530,32 @@ pub async fn put_doc_handler(
         &get_gh_token(&state).await?,
         branch_name,
     ) {
-        Ok(_) => Ok(StatusCode::CREATED),
+        Ok(_) => {
+            invalidate_written_doc(&state, previous_branch.as_deref(), branch_name, &body.path);
+            record_server_save(&state, started).await;
+            record_doc_edit(&state, &body.path, &author, body.description.as_deref()).await;
+            let entry = audit::entry(
+                AuditCategory::Content,
+                "doc_saved",
+                Some(&author),
+                client_ip(&headers),
+                format!("Saved {} to {branch_name}", body.path),
+            );
+            let data = serde_json::json!({
+                "branch": branch_name,
+                "bytes": body.contents.len(),
+                "violations": policy_audit_data(&warnings),
+            });
+            audit::record_entry(&state.db, entry.with_target(&body.path).with_data(&data)).await;
+            Ok((
+                StatusCode::CREATED,
+                Json(PutFileResponse {
+                    warnings,
+                    stripped_metadata: Vec::new(),
+                    pull_request_url: None,
+                }),
+            ))
+        }
         Err(e) => {
             error!("Failed to complete put_doc call with error: {e:?}");
             Err((
@@ -107,38 +566,121 @@ pub async fn put_doc_handler(
     }
 }
 
+/// Returns an error unless `user` may push to the branch that's checked out, which changes that
+/// don't name a branch are pushed to
+async fn check_current_branch_push(
+    state: &AppState,
+    user: &User,
+) -> Result<(), (StatusCode, String)> {
+    let branch = state
+        .git
+        .get_current_branch()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    check_branch_push(state, user, &branch).await
+}
+
 /// Deletes the document at the provided path, if the user has perms.
 pub async fn delete_doc_handler(
     State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_CONTENT>,
     headers: HeaderMap,
     Query(query): Query<GetDocQuery>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    let author = require_perms(
-        axum::extract::State(&state),
-        headers,
-        &[Permission::ManageContent],
-    )
-    .await?;
-
+    check_current_branch_push(&state, &author).await?;
+    let message = format!("{} deleted {}", author.username, query.path);
     state
         .git
         .delete_doc(
             &query.path,
-            &format!("{} deleted {}", author.username, query.path),
+            &with_co_author(&state, &author, message).await,
             &get_gh_token(&state).await?,
         )
         .map_err(eyre_to_axum_err)?;
+    state
+        .doc_cache
+        .invalidate_doc(&query.path, ChangeKind::Deleted);
+    search::spawn_update_doc(&state, &query.path, ChangeKind::Deleted);
+    if let Err(e) = state.db.delete_doc_meta(&query.path).await {
+        warn!("Failed to delete the metadata of {:?}: {e:?}", query.path);
+    }
+    if let Err(e) = state.db.remove_doc_owners(&query.path).await {
+        warn!("Failed to remove the owners of {:?}: {e:?}", query.path);
+    }
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "doc_deleted",
+        Some(&author),
+        client_ip(&headers),
+        format!("Deleted {}", query.path),
+    );
+    audit::record_entry(&state.db, entry.with_target(&query.path)).await;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Moves the document at the provided path into the archive folder, if the user has perms.
+pub async fn post_archive_doc_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_CONTENT>,
+    headers: HeaderMap,
+    Query(query): Query<GetDocQuery>,
+) -> Result<Json<ArchiveDocResponse>, (StatusCode, String)> {
+    check_current_branch_push(&state, &author).await?;
+    let message = format!("{} archived {}", author.username, query.path);
+    let archived_path = state
+        .git
+        .archive_doc(
+            &query.path,
+            &with_co_author(&state, &author, message).await,
+            &get_gh_token(&state).await?,
+        )
+        .map_err(path_err_to_axum)?;
+    state
+        .doc_cache
+        .invalidate_doc(&query.path, ChangeKind::Deleted);
+    state
+        .doc_cache
+        .invalidate_doc(&to_slash_path(&archived_path), ChangeKind::Added);
+    // Archived documents aren't indexed
+    search::spawn_update_doc(&state, &query.path, ChangeKind::Deleted);
+    if let Err(e) = state
+        .db
+        .move_doc_meta(&query.path, &to_slash_path(&archived_path))
+        .await
+    {
+        warn!("Failed to move the metadata of {:?}: {e:?}", query.path);
+    }
+    // Archived documents aren't maintained, so their owners aren't asked to review them
+    if let Err(e) = state.db.remove_doc_owners(&query.path).await {
+        warn!("Failed to remove the owners of {:?}: {e:?}", query.path);
+    }
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "doc_archived",
+        Some(&author),
+        client_ip(&headers),
+        format!("Archived {}", query.path),
+    );
+    let data = serde_json::json!({ "archived_path": to_slash_path(&archived_path) });
+    audit::record_entry(&state.db, entry.with_target(&query.path).with_data(&data)).await;
+
+    Ok(Json(ArchiveDocResponse {
+        path: to_slash_path(&archived_path),
+    }))
+}
+
 /// This handler reads the document folder and builds a tree style object
 /// representing the state of the tree. This is used in the viewer for directory navigation.
+///
+/// Archived documents are hidden unless `?include_archived=true` is passed.
 pub async fn get_doc_tree_handler(
     State(state): State<AppState>,
+    _: ReadAccess,
+    Query(query): Query<GetDocTreeQuery>,
 ) -> Result<Json<INode>, (StatusCode, &'static str)> {
-    match state.git.get_doc_tree() {
-        Ok(t) => Ok(Json(t)),
+    match doc_tree_with_meta(&state, query.include_archived).await {
+        Ok(tree) => Ok(Json(tree)),
         Err(e) => {
             error!("An error was encountered fetching the document tree: {e:?}");
             Err((
@@ -150,10 +692,308 @@ pub async fn get_doc_tree_handler(
     }
 }
 
+/// Like [`eyre_to_axum_err`], but tells the user what's wrong with the path they sent if that's
+/// why it failed, see [`PathError`]
+fn path_err_to_axum(e: color_eyre::Report) -> (StatusCode, String) {
+    let status = match e.downcast_ref::<PathError>() {
+        Some(PathError::Invalid(_)) => StatusCode::BAD_REQUEST,
+        Some(PathError::NotFound(_)) => StatusCode::NOT_FOUND,
+        Some(PathError::AlreadyExists(_)) => StatusCode::CONFLICT,
+        None => return eyre_to_axum_err(e),
+    };
+    (status, e.to_string())
+}
+
+/// The document tree, with who last edited each document attached
+async fn doc_tree_with_meta(state: &AppState, include_archived: bool) -> color_eyre::Result<INode> {
+    let mut tree = state.doc_cache.doc_tree(include_archived, || {
+        state.git.get_doc_tree(include_archived)
+    })?;
+    let meta = state.db.get_all_doc_meta().await?;
+    tree.attach_meta(&meta.into_iter().map(|m| (m.path.clone(), m)).collect());
+    Ok(tree)
+}
+
+/// This handler accepts a `POST` request to `/api/tree/doc` with the path of a new, empty folder
+/// in the documents folder, for a new category of documents. The folder is committed with an
+/// empty `.gitkeep` file in it, since git doesn't track empty folders. Returns the updated tree.
+pub async fn post_doc_dir_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_CONTENT>,
+    headers: HeaderMap,
+    Json(body): Json<CreateDirRequestBody>,
+) -> Result<(StatusCode, Json<INode>), (StatusCode, String)> {
+    check_current_branch_push(&state, &author).await?;
+    let message = format!("{} created the folder {}", author.username, body.path);
+    state
+        .git
+        .create_doc_dir(
+            &body.path,
+            &with_co_author(&state, &author, message).await,
+            &get_gh_token(&state).await?,
+        )
+        .map_err(path_err_to_axum)?;
+    let keep_file = format!("{}/{KEEP_FILE}", body.path.trim_end_matches('/'));
+    state
+        .doc_cache
+        .invalidate_doc(&keep_file, ChangeKind::Added);
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "doc_dir_created",
+        Some(&author),
+        client_ip(&headers),
+        format!("Created the folder {}", body.path),
+    );
+    audit::record_entry(&state.db, entry.with_target(&body.path)).await;
+
+    let tree = doc_tree_with_meta(&state, false)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok((StatusCode::CREATED, Json(tree)))
+}
+
+/// The token deleting the folder at `path` containing `files` has to be confirmed with. It changes
+/// whenever the files in the folder do, so a confirmation can't delete files the user wasn't
+/// told about.
+fn delete_dir_confirmation(path: &str, files: &[String]) -> String {
+    api_tokens::hash(&format!("{path}\n{}", files.join("\n")))
+}
+
+/// This handler accepts a `DELETE` request to `/api/tree/doc?path=`, deleting the folder and every
+/// document in it in a single commit. Without `&confirm=`, nothing is deleted: `428 Precondition
+/// Required` is returned with how many files would be deleted, along with the token to confirm
+/// the deletion with.
+pub async fn delete_doc_dir_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_CONTENT>,
+    headers: HeaderMap,
+    Query(query): Query<DeleteDirQuery>,
+) -> Result<(StatusCode, Json<DeleteDirResponse>), (StatusCode, String)> {
+    let path = to_slash_path(&query.path);
+    let files = state
+        .git
+        .get_doc_dir_files(&path)
+        .map_err(path_err_to_axum)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("No folder exists at {path:?}"),
+            )
+        })?;
+    let confirmation = delete_dir_confirmation(&path, &files);
+    if query.confirm.as_deref() != Some(confirmation.as_str()) {
+        return Ok((
+            StatusCode::PRECONDITION_REQUIRED,
+            Json(DeleteDirResponse {
+                path,
+                changed_files: files.len(),
+                confirm: Some(confirmation),
+            }),
+        ));
+    }
+
+    check_current_branch_push(&state, &author).await?;
+    let message = format!(
+        "{} deleted the folder {path} ({} files)",
+        author.username,
+        files.len()
+    );
+    let deleted = state
+        .git
+        .delete_doc_dir(
+            &path,
+            &with_co_author(&state, &author, message).await,
+            &get_gh_token(&state).await?,
+        )
+        .map_err(path_err_to_axum)?;
+    let changes: Vec<ChangedPath> = deleted
+        .iter()
+        .map(|file| ChangedPath {
+            path: state.git.doc_repo_path(file),
+            kind: ChangeKind::Deleted,
+        })
+        .collect();
+    state.doc_cache.invalidate(&changes);
+    search::spawn_update(state.clone(), changes);
+    for file in &deleted {
+        if let Err(e) = state.db.delete_doc_meta(file).await {
+            warn!("Failed to delete the metadata of {file:?}: {e:?}");
+        }
+    }
+    let owner_path = format!("{}/", path.trim_matches('/'));
+    if let Err(e) = state.db.remove_doc_owners_under(&owner_path).await {
+        warn!("Failed to remove the owners of {owner_path:?}: {e:?}");
+    }
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "doc_dir_deleted",
+        Some(&author),
+        client_ip(&headers),
+        format!("Deleted the folder {path} ({} files)", deleted.len()),
+    );
+    let data = serde_json::json!({ "files": deleted });
+    audit::record_entry(&state.db, entry.with_target(&path).with_data(&data)).await;
+
+    Ok((
+        StatusCode::OK,
+        Json(DeleteDirResponse {
+            path,
+            changed_files: deleted.len(),
+            confirm: None,
+        }),
+    ))
+}
+
+/// This handler accepts a `POST` request to `/api/tree/doc/move`, renaming or moving a folder and
+/// every document in it in a single commit. The documents keep their history and metadata.
+pub async fn post_move_doc_dir_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_CONTENT>,
+    headers: HeaderMap,
+    Json(body): Json<MoveDirRequestBody>,
+) -> Result<Json<MoveDirResponse>, (StatusCode, String)> {
+    check_current_branch_push(&state, &author).await?;
+    let message = format!(
+        "{} moved the folder {} to {}",
+        author.username, body.from, body.to
+    );
+    let moved = state
+        .git
+        .move_doc_dir(
+            &body.from,
+            &body.to,
+            &with_co_author(&state, &author, message).await,
+            &get_gh_token(&state).await?,
+        )
+        .map_err(path_err_to_axum)?;
+    let changes = moved_changes(&moved, |path| state.git.doc_repo_path(path));
+    state.doc_cache.invalidate(&changes);
+    search::spawn_update(state.clone(), changes);
+    for (old, new) in &moved {
+        if let Err(e) = state.db.move_doc_meta(old, new).await {
+            warn!("Failed to move the metadata of {old:?}: {e:?}");
+        }
+    }
+    let (from_owners, to_owners) = (
+        format!("{}/", to_slash_path(&body.from).trim_matches('/')),
+        format!("{}/", to_slash_path(&body.to).trim_matches('/')),
+    );
+    if let Err(e) = state.db.move_doc_owners(&from_owners, &to_owners).await {
+        warn!("Failed to move the owners of {from_owners:?}: {e:?}");
+    }
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "doc_dir_moved",
+        Some(&author),
+        client_ip(&headers),
+        format!("Moved the folder {} to {}", body.from, body.to),
+    );
+    let data = serde_json::json!({ "to": to_slash_path(&body.to), "files": moved.len() });
+    audit::record_entry(&state.db, entry.with_target(&body.from).with_data(&data)).await;
+
+    Ok(Json(MoveDirResponse {
+        path: to_slash_path(&body.to),
+        changed_files: moved.len(),
+    }))
+}
+
+/// The changes made by moving a folder, given the old and new paths of the files in it and how
+/// to make those relative to the root of the repository
+fn moved_changes(
+    moved: &[(String, String)],
+    repo_path: impl Fn(&str) -> String,
+) -> Vec<ChangedPath> {
+    moved
+        .iter()
+        .flat_map(|(old, new)| {
+            [
+                ChangedPath {
+                    path: repo_path(old),
+                    kind: ChangeKind::Deleted,
+                },
+                ChangedPath {
+                    path: repo_path(new),
+                    kind: ChangeKind::Added,
+                },
+            ]
+        })
+        .collect()
+}
+
+/// This handler accepts a `GET` request to `/api/doc/related?path=&limit=`, returning the
+/// pages with the most similar content to the provided page. Archived pages are never suggested.
+pub async fn get_related_docs_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Query(query): Query<GetRelatedDocsQuery>,
+) -> Result<Json<Vec<RelatedPage>>, (StatusCode, &'static str)> {
+    let internal_error = |e: color_eyre::Report| {
+        error!("Failed to find pages related to {:?}: {e:?}", query.path);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Fetch failed, check server logs for more info",
+        )
+    };
+    let paths = state
+        .doc_cache
+        .doc_tree(false, || state.git.get_doc_tree(false))
+        .map_err(internal_error)?
+        .file_paths();
+    let mut docs = Vec::new();
+    for path in paths.into_iter().filter(|p| p.ends_with(".md")) {
+        let counts = state
+            .doc_cache
+            .term_counts(&path, || state.git.get_doc(&path))
+            .map_err(internal_error)?;
+        if let Some(counts) = counts {
+            docs.push((path, counts));
+        }
+    }
+
+    RelatedIndex::new(docs)
+        .related(&query.path, query.limit.min(MAX_RELATED_LIMIT))
+        .map(Json)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "The file at the provided path was not found.",
+        ))
+}
+
+/// This handler accepts a `GET` request to `/api/doc/search?q=&limit=`, returning the documents
+/// matching the query, most relevant first. Snippets are HTML, with matches wrapped in `<mark>`.
+pub async fn get_search_docs_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Query(query): Query<SearchDocsQuery>,
+) -> Result<Json<Vec<SearchHit>>, (StatusCode, &'static str)> {
+    let terms = search::query_terms(&query.q);
+    let hits = state
+        .db
+        .search_docs(&terms, query.limit.clamp(0, MAX_SEARCH_LIMIT))
+        .await
+        .map_err(|e| {
+            error!("Failed to search for {:?}: {e:?}", query.q);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Search failed, check server logs for more info",
+            )
+        })?;
+
+    Ok(Json(
+        hits.into_iter()
+            .map(|hit| SearchHit {
+                snippet: search::highlight(&hit.snippet),
+                ..hit
+            })
+            .collect(),
+    ))
+}
+
 /// This handler reads the assets folder and builds a tree style object
 /// representing the state of the tree. This is used in the viewer for directory navigation.
 pub async fn get_asset_tree_handler(
     State(state): State<AppState>,
+    _: ReadAccess,
 ) -> Result<Json<INode>, (StatusCode, &'static str)> {
     match state.git.get_asset_tree() {
         Ok(t) => Ok(Json(t)),
@@ -168,9 +1008,81 @@ pub async fn get_asset_tree_handler(
     }
 }
 
+/// Like [`post_doc_dir_handler`], for a folder in the assets folder
+pub async fn post_asset_dir_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_ASSETS>,
+    headers: HeaderMap,
+    Json(body): Json<CreateDirRequestBody>,
+) -> Result<(StatusCode, Json<INode>), (StatusCode, String)> {
+    check_current_branch_push(&state, &author).await?;
+    let message = format!("{} created the folder {}", author.username, body.path);
+    state
+        .git
+        .create_asset_dir(
+            &body.path,
+            &with_co_author(&state, &author, message).await,
+            &get_gh_token(&state).await?,
+        )
+        .map_err(path_err_to_axum)?;
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "asset_dir_created",
+        Some(&author),
+        client_ip(&headers),
+        format!("Created the folder {}", body.path),
+    );
+    audit::record_entry(&state.db, entry.with_target(&body.path)).await;
+
+    let tree = state.git.get_asset_tree().map_err(eyre_to_axum_err)?;
+    Ok((StatusCode::CREATED, Json(tree)))
+}
+
+/// Like [`post_move_doc_dir_handler`], for a folder in the assets folder
+pub async fn post_move_asset_dir_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_ASSETS>,
+    headers: HeaderMap,
+    Json(body): Json<MoveDirRequestBody>,
+) -> Result<Json<MoveDirResponse>, (StatusCode, String)> {
+    check_current_branch_push(&state, &author).await?;
+    let message = format!(
+        "{} moved the folder {} to {}",
+        author.username, body.from, body.to
+    );
+    let moved = state
+        .git
+        .move_asset_dir(
+            &body.from,
+            &body.to,
+            &with_co_author(&state, &author, message).await,
+            &get_gh_token(&state).await?,
+        )
+        .map_err(path_err_to_axum)?;
+    // The assets folder can be inside the documents folder, in which case documents moved too
+    let changes = moved_changes(&moved, |path| state.git.asset_repo_path(path));
+    state.doc_cache.invalidate(&changes);
+    search::spawn_update(state.clone(), changes);
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "asset_dir_moved",
+        Some(&author),
+        client_ip(&headers),
+        format!("Moved the folder {} to {}", body.from, body.to),
+    );
+    let data = serde_json::json!({ "to": to_slash_path(&body.to), "files": moved.len() });
+    audit::record_entry(&state.db, entry.with_target(&body.from).with_data(&data)).await;
+
+    Ok(Json(MoveDirResponse {
+        path: to_slash_path(&body.to),
+        changed_files: moved.len(),
+    }))
+}
+
 /// This handler fetches an asset from the repo's asset folder
 pub async fn get_asset_handler(
     State(state): State<AppState>,
+    _: ReadAccess,
     Path(path): Path<Vec<String>>,
 ) -> impl IntoResponse {
     let file_name = path.last().unwrap().clone();
@@ -198,19 +1110,43 @@ pub async fn get_asset_handler(
 /// with a new asset
 pub async fn put_asset_handler(
     State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_ASSETS>,
     headers: HeaderMap,
     Path(path): Path<Vec<String>>,
+    Query(query): Query<PutAssetQuery>,
     body: Bytes,
-) -> Result<StatusCode, (StatusCode, String)> {
+) -> Result<(StatusCode, Json<PutFileResponse>), (StatusCode, String)> {
     let path = path.join("/");
-    let author = require_perms(
-        axum::extract::State(&state),
-        headers,
-        &[Permission::ManageContent],
-    )
-    .await?;
+    check_current_branch_push(&state, &author).await?;
     // Generate commit message combining author and default update message
-    let message = format!("{} updated {}", author.username, path);
+    let message = with_co_author(
+        &state,
+        &author,
+        format!("{} updated {}", author.username, path),
+    )
+    .await;
+
+    let (body, stripped_metadata) =
+        if state.config.files.strip_image_metadata && !query.keep_metadata {
+            strip_metadata(&body).map_err(|e| {
+                (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("Failed to remove metadata from the image: {e}"),
+                )
+            })?
+        } else {
+            if query.keep_metadata {
+                info!(
+                    target: "audit",
+                    user = author.username,
+                    path,
+                    "Image uploaded with metadata kept"
+                );
+            }
+            (body.to_vec(), Vec::new())
+        };
+
+    let warnings = enforce_asset_policy(&state, &author, client_ip(&headers), &path, &body).await?;
 
     // Call put_asset to update the asset, passing the required parameters
     state
@@ -220,39 +1156,88 @@ pub async fn put_asset_handler(
             error!("Failed to update asset: {e}");
             (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
         })?;
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "asset_saved",
+        Some(&author),
+        client_ip(&headers),
+        format!("Uploaded {path}"),
+    );
+    let data = serde_json::json!({
+        "bytes": body.len(),
+        "metadata_kept": query.keep_metadata,
+        "violations": policy_audit_data(&warnings),
+    });
+    audit::record_entry(&state.db, entry.with_target(&path).with_data(&data)).await;
 
-    Ok(StatusCode::CREATED)
+    Ok((
+        StatusCode::CREATED,
+        Json(PutFileResponse {
+            warnings,
+            stripped_metadata,
+            pull_request_url: None,
+        }),
+    ))
 }
 
 /// This handler creates or replaces the asset at the provided path
 /// with a new asset
 pub async fn delete_asset_handler(
     State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_ASSETS>,
     headers: HeaderMap,
     Path(path): Path<Vec<String>>,
 ) -> Result<StatusCode, (StatusCode, String)> {
     let path = path.join("/");
-    let author = require_perms(State(&state), headers, &[Permission::ManageContent]).await?;
+    check_current_branch_push(&state, &author).await?;
     // Generate commit message combining author and default update message
-    let message = format!("{} deleted {}", author.username, path);
+    let message = with_co_author(
+        &state,
+        &author,
+        format!("{} deleted {}", author.username, path),
+    )
+    .await;
     state
         .git
         .delete_asset(&path, &message, &get_gh_token(&state).await?)
         .map_err(eyre_to_axum_err)?;
+    let entry = audit::entry(
+        AuditCategory::Content,
+        "asset_deleted",
+        Some(&author),
+        client_ip(&headers),
+        format!("Deleted {path}"),
+    );
+    audit::record_entry(&state.db, entry.with_target(&path)).await;
 
     Ok(StatusCode::OK)
 }
 
 pub async fn create_tree_route() -> Router<AppState> {
     Router::new()
-        .route("/tree/doc", get(get_doc_tree_handler))
+        .route(
+            "/tree/doc",
+            get(get_doc_tree_handler)
+                .post(post_doc_dir_handler)
+                .delete(delete_doc_dir_handler),
+        )
+        .route("/tree/doc/move", post(post_move_doc_dir_handler))
         .route(
             "/doc",
             get(get_doc_handler)
                 .put(put_doc_handler)
                 .delete(delete_doc_handler),
         )
-        .route("/tree/asset", get(get_asset_tree_handler))
+        .route("/doc/archive", post(post_archive_doc_handler))
+        .route("/doc/related", get(get_related_docs_handler))
+        .route("/doc/meta", get(get_doc_meta_handler))
+        .route("/doc/rendered", get(get_rendered_doc_handler))
+        .route("/doc/search", get(get_search_docs_handler))
+        .route(
+            "/tree/asset",
+            get(get_asset_tree_handler).post(post_asset_dir_handler),
+        )
+        .route("/tree/asset/move", post(post_move_asset_dir_handler))
         .route(
             "/asset/{*path}",
             get(get_asset_handler)
@@ -264,3 +1249,63 @@ pub async fn create_tree_route() -> Router<AppState> {
             (256_u32 * (2_u32.pow(20))).try_into().unwrap(),
         ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_conf::PolicyAction, db::AuditFilter};
+    use chrono::DateTime;
+
+    #[tokio::test]
+    async fn blocked_saves_are_audited() {
+        let db = Database::from_url(":memory:").await.unwrap();
+        let author = db
+            .create_user(
+                "alice".to_string(),
+                String::new(),
+                DateTime::UNIX_EPOCH,
+                String::new(),
+            )
+            .await
+            .unwrap();
+        let report = PolicyReport {
+            violations: vec![PolicyViolation {
+                check: "secrets",
+                action: PolicyAction::Block,
+                message: "Found what looks like a GitHub token".to_string(),
+                location: None,
+            }],
+        };
+        let result = enforce_policy_report(
+            &db,
+            &author,
+            Some("127.0.0.1".to_string()),
+            "docs/a.md",
+            report,
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().0,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "enforce_policy_report: should reject a save a check blocks"
+        );
+
+        let filter = AuditFilter {
+            action: Some("content_policy_blocked".to_string()),
+            ..Default::default()
+        };
+        let entries = db.query_audit(&filter, 10).await.unwrap();
+        assert_eq!(
+            entries.len(),
+            1,
+            "enforce_policy_report: should record the blocked save"
+        );
+        assert_eq!(entries[0].category, "content");
+        assert_eq!(entries[0].user_id, Some(author.id));
+        assert_eq!(entries[0].target.as_deref(), Some("docs/a.md"));
+        let data: serde_json::Value =
+            serde_json::from_str(entries[0].data.as_deref().unwrap()).unwrap();
+        assert_eq!(data["violations"][0]["check"], "secrets");
+        assert_eq!(data["violations"][0]["action"], "block");
+    }
+}
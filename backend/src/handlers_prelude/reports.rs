@@ -0,0 +1,136 @@
+//! Release notes built from the pull requests opened through Hyde, see [`crate::reports`]
+use axum::routing::{get, post};
+use axum::{
+    extract::{Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap},
+    middleware,
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use super::{require_sign_in, ChangelogFormat, ReadAccess};
+use crate::reports::{parse_since, ChangeReport, MergedChange};
+use crate::{eyre_to_axum_err, perms::required::MANAGE_BRANCHES, AppState, RequirePermission};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetChangeReportQuery {
+    /// A date (`YYYY-MM-DD`) or RFC-3339 timestamp, inclusive
+    pub since: String,
+    /// Either `json` (the default) or `markdown`
+    #[serde(default)]
+    pub format: ChangelogFormat,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PublishReleaseRequestBody {
+    /// A date (`YYYY-MM-DD`) or RFC-3339 timestamp, inclusive
+    pub since: String,
+    /// The tag the release is for, created from the default branch if it doesn't exist
+    pub tag_name: String,
+    /// The title of the release, defaults to `tag_name`
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PublishReleaseResponse {
+    pub release_url: String,
+}
+
+/// Build a report of the pull requests opened through Hyde that were merged since `since`
+async fn build_report(state: &AppState, since: &str) -> Result<ChangeReport, (StatusCode, String)> {
+    let since = parse_since(since).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let prs = state
+        .db
+        .get_merged_hyde_prs(since)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let mut pull_requests = Vec::with_capacity(prs.len());
+    for pr in prs {
+        let author = match pr.author_id {
+            Some(id) => state
+                .db
+                .get_user(id)
+                .await
+                .map_err(eyre_to_axum_err)?
+                .map(|u| u.username),
+            None => None,
+        };
+        pull_requests.push(MergedChange {
+            number: pr.number,
+            title: pr.title,
+            author,
+            merged_at: pr.merged_at.unwrap_or_default(),
+            doc_paths: pr.doc_paths,
+        });
+    }
+    Ok(ChangeReport {
+        since,
+        pull_requests,
+    })
+}
+
+/// This handler accepts a `GET` request to `/api/reports/changes?since=&format=`, returning the
+/// pull requests opened through Hyde that were merged since then. The report lists the pages
+/// each one changed, so it needs [`ReadAccess`] on top of being signed in.
+pub async fn get_change_report_handler(
+    State(state): State<AppState>,
+    _: ReadAccess,
+    Query(query): Query<GetChangeReportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let report = build_report(&state, &query.since).await?;
+
+    Ok(match query.format {
+        ChangelogFormat::Json => Json(report).into_response(),
+        ChangelogFormat::Markdown => {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                CONTENT_TYPE,
+                "text/markdown; charset=utf-8".parse().unwrap(),
+            );
+            (headers, report.to_markdown()).into_response()
+        }
+    })
+}
+
+/// Publish the report as a GitHub release
+pub async fn post_release_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_BRANCHES>,
+    Json(body): Json<PublishReleaseRequestBody>,
+) -> Result<(StatusCode, Json<PublishReleaseResponse>), (StatusCode, String)> {
+    let report = build_report(&state, &body.since).await?;
+    let name = body.name.as_deref().unwrap_or(&body.tag_name);
+
+    let release_url = state
+        .gh_client
+        .create_release(&body.tag_name, name, &report.to_markdown())
+        .await
+        .map_err(|e| {
+            error!("Failed to publish release {:?}: {e:?}", body.tag_name);
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to publish the release: {e}"),
+            )
+        })?;
+    info!(
+        target: "audit",
+        user = user.username,
+        tag = body.tag_name,
+        "Release notes for {} pull requests published to {release_url}",
+        report.pull_requests.len()
+    );
+    Ok((
+        StatusCode::CREATED,
+        Json(PublishReleaseResponse { release_url }),
+    ))
+}
+
+pub async fn create_reports_route() -> Router<AppState> {
+    Router::new()
+        .route("/reports/changes", get(get_change_report_handler))
+        .route("/reports/changes/release", post(post_release_handler))
+        .route_layer(middleware::from_fn(require_sign_in))
+}
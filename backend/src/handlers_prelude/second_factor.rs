@@ -0,0 +1,252 @@
+//! Enrolling in a TOTP second factor (see [`crate::totp`]), which users with the `ManageUsers` or
+//! `ManageRepo` permission are asked for before destructive actions once they've enrolled
+use axum::routing::{get, post};
+use axum::{extract::State, http::HeaderMap, Json, Router};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::client_ip;
+use crate::audit::{self, AuditCategory};
+use crate::db::User;
+use crate::perms::Permission;
+use crate::totp::{self, TOTP_HEADER};
+use crate::{eyre_to_axum_err, AppState, AuthenticatedUser};
+
+/// Shown as the account's issuer in authenticator apps
+const TOTP_ISSUER: &str = "Hyde";
+
+#[derive(Debug, Serialize)]
+pub struct TotpStatusResponse {
+    /// Whether codes are asked for
+    enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollmentResponse {
+    /// The base32 encoded secret, for authenticator apps that can't scan `uri`
+    secret: String,
+    /// The `otpauth://` URI, to be shown as a QR code
+    uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpVerifyRequest {
+    code: String,
+}
+
+/// Returns an error if the user sent too many wrong codes recently, see
+/// [`crate::rate_limit::FailureBackoff`]
+fn check_totp_lockout(state: &AppState, user: &User) -> Result<(), (StatusCode, String)> {
+    state
+        .totp_failures
+        .locked_for(&user.id)
+        .map_or(Ok(()), |remaining| {
+            Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                format!(
+                    "Too many invalid TOTP codes, try again in {} seconds",
+                    remaining.as_secs() + 1
+                ),
+            ))
+        })
+}
+
+/// Returns an error unless `code` is a valid, unused code for the user's TOTP secret, and the
+/// user isn't locked out for sending too many wrong ones. Passes if the user hasn't enabled TOTP.
+async fn check_totp_code(
+    state: &AppState,
+    user: &User,
+    code: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, String)> {
+    let Some(secret) = state
+        .db
+        .get_totp_secret(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .filter(|s| s.enabled)
+    else {
+        return Ok(());
+    };
+    let Some(code) = code else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            format!("A TOTP code is required, send it in the {TOTP_HEADER} header"),
+        ));
+    };
+    check_totp_lockout(state, user)?;
+    let accepted = match totp::verify(&secret.secret, code, Utc::now()) {
+        Some(step) => state
+            .db
+            .use_totp_step(user.id, step)
+            .await
+            .map_err(eyre_to_axum_err)?,
+        None => false,
+    };
+    if !accepted {
+        state.totp_failures.record_failure(user.id);
+        audit::record(
+            &state.db,
+            AuditCategory::Auth,
+            "totp_failed",
+            Some(user),
+            client_ip(headers),
+            "Sent an invalid or reused TOTP code",
+        )
+        .await;
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            String::from("The TOTP code is invalid or has already been used"),
+        ));
+    }
+    state.totp_failures.reset(&user.id);
+    Ok(())
+}
+
+/// Returns an error if `user` has the `ManageUsers` or `ManageRepo` permission and has enabled
+/// TOTP, but didn't send a valid code in the [`TOTP_HEADER`] header. Called before destructive
+/// actions.
+pub async fn require_second_factor(
+    state: &AppState,
+    user: &User,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, String)> {
+    let permissions = state
+        .db
+        .get_user_permissions(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !permissions.contains(&Permission::ManageUsers)
+        && !permissions.contains(&Permission::ManageRepo)
+    {
+        return Ok(());
+    }
+    let code = headers.get(TOTP_HEADER).and_then(|h| h.to_str().ok());
+    check_totp_code(state, user, code, headers).await
+}
+
+pub async fn get_totp_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<TotpStatusResponse>, (StatusCode, String)> {
+    let totp = state
+        .db
+        .get_totp_secret(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(TotpStatusResponse {
+        enabled: totp.is_some_and(|t| t.enabled),
+    }))
+}
+
+/// Start enrolling, generating a new secret. It isn't asked for until it's confirmed with
+/// [`post_totp_verify_handler`]. Replacing an enabled secret requires a code from it.
+pub async fn post_totp_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+) -> Result<Json<TotpEnrollmentResponse>, (StatusCode, String)> {
+    let code = headers.get(TOTP_HEADER).and_then(|h| h.to_str().ok());
+    check_totp_code(&state, &user, code, &headers).await?;
+    let secret = totp::generate_secret();
+    state
+        .db
+        .set_totp_secret(user.id, &secret, Utc::now())
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(TotpEnrollmentResponse {
+        uri: totp::provisioning_uri(TOTP_ISSUER, &user.username, &secret),
+        secret,
+    }))
+}
+
+/// Finish enrolling by confirming a code from the authenticator app
+pub async fn post_totp_verify_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+    Json(body): Json<TotpVerifyRequest>,
+) -> Result<Json<TotpStatusResponse>, (StatusCode, String)> {
+    let secret = state
+        .db
+        .get_totp_secret(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                String::from("TOTP enrollment hasn't been started"),
+            )
+        })?;
+    if secret.enabled {
+        return Ok(Json(TotpStatusResponse { enabled: true }));
+    }
+    check_totp_lockout(&state, &user)?;
+    let step = totp::verify(&secret.secret, &body.code, Utc::now()).ok_or_else(|| {
+        state.totp_failures.record_failure(user.id);
+        (
+            StatusCode::UNAUTHORIZED,
+            String::from("The TOTP code is invalid"),
+        )
+    })?;
+    state.totp_failures.reset(&user.id);
+    state
+        .db
+        .use_totp_step(user.id, step)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    state
+        .db
+        .enable_totp(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    audit::record(
+        &state.db,
+        AuditCategory::Auth,
+        "totp_enabled",
+        Some(&user),
+        client_ip(&headers),
+        "Enabled a TOTP second factor",
+    )
+    .await;
+    Ok(Json(TotpStatusResponse { enabled: true }))
+}
+
+/// Remove the second factor. Requires a code from it if it's enabled.
+pub async fn delete_totp_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let code = headers.get(TOTP_HEADER).and_then(|h| h.to_str().ok());
+    check_totp_code(&state, &user, code, &headers).await?;
+    let deleted = state
+        .db
+        .delete_totp_secret(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if deleted {
+        audit::record(
+            &state.db,
+            AuditCategory::Auth,
+            "totp_disabled",
+            Some(&user),
+            client_ip(&headers),
+            "Removed their TOTP second factor",
+        )
+        .await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_second_factor_route() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/users/me/totp",
+            get(get_totp_handler)
+                .post(post_totp_handler)
+                .delete(delete_totp_handler),
+        )
+        .route("/users/me/totp/verify", post(post_totp_verify_handler))
+}
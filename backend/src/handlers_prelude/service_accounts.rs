@@ -0,0 +1,133 @@
+//! Creating service accounts and their API tokens, see [`crate::db::User::service_account`].
+//! Service accounts are added to groups and deleted like any other user.
+use axum::routing::{get, post};
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json, Router,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    client_ip, create_api_token, create_user_response, CreateApiTokenBody, CreateApiTokenResponse,
+    UserResponse,
+};
+use crate::audit::{self, AuditCategory};
+use crate::db::{ApiToken, User};
+use crate::{eyre_to_axum_err, perms::required::MANAGE_USERS, AppState, RequirePermission};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateServiceAccountBody {
+    /// What the account shows up as in commits and the audit log, e.g. "changelog-importer"
+    pub username: String,
+}
+
+/// Every service account
+pub async fn get_service_accounts_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_USERS>,
+) -> Result<Json<Vec<UserResponse>>, (StatusCode, String)> {
+    let users = state.db.get_all_users().await.map_err(eyre_to_axum_err)?;
+    let mut response = Vec::new();
+    for user in users.into_iter().filter(|u| u.service_account) {
+        response.push(create_user_response(&state.db, user).await?);
+    }
+    Ok(Json(response))
+}
+
+pub async fn post_service_account_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Json(body): Json<CreateServiceAccountBody>,
+) -> Result<Json<UserResponse>, (StatusCode, String)> {
+    let username = body.username.trim();
+    if username.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Service accounts need a username".to_string(),
+        ));
+    }
+    // A service account that shares a name with someone would be confused for them in the
+    // commit history
+    let users = state.db.get_all_users().await.map_err(eyre_to_axum_err)?;
+    if users.iter().any(|u| u.username == username) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("There's already a user named {username:?}"),
+        ));
+    }
+    let account = state
+        .db
+        .create_service_account(username)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let entry = audit::entry(
+        AuditCategory::Users,
+        "service_account_created",
+        Some(&author),
+        client_ip(&headers),
+        format!("Created the service account {:?}", account.username),
+    );
+    audit::record_entry(&state.db, entry.with_target(format!("user:{}", account.id))).await;
+    Ok(Json(create_user_response(&state.db, account).await?))
+}
+
+/// Returns the service account with the id `user_id`
+async fn get_service_account(state: &AppState, user_id: i64) -> Result<User, (StatusCode, String)> {
+    state
+        .db
+        .get_user(user_id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .filter(|u| u.service_account)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("No service account with the id {user_id}"),
+            )
+        })
+}
+
+/// A service account's API tokens
+pub async fn get_service_account_tokens_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_USERS>,
+    Path(user_id): Path<i64>,
+) -> Result<Json<Vec<ApiToken>>, (StatusCode, String)> {
+    let account = get_service_account(&state, user_id).await?;
+    let tokens = state
+        .db
+        .get_user_api_tokens(account.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(tokens))
+}
+
+/// Create a token that acts as a service account. Tokens are revoked like any other, with a
+/// `DELETE` to `/tokens/{token_id}`.
+pub async fn post_service_account_token_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(user_id): Path<i64>,
+    Json(body): Json<CreateApiTokenBody>,
+) -> Result<Json<CreateApiTokenResponse>, (StatusCode, String)> {
+    let account = get_service_account(&state, user_id).await?;
+    Ok(Json(
+        create_api_token(&state, &author, &account, client_ip(&headers), body).await?,
+    ))
+}
+
+pub async fn create_service_accounts_route() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/service-accounts",
+            get(get_service_accounts_handler).post(post_service_account_handler),
+        )
+        .route(
+            "/service-accounts/{user_id}/tokens",
+            post(post_service_account_token_handler).get(get_service_account_tokens_handler),
+        )
+}
@@ -0,0 +1,155 @@
+//! Listing and ending users' sessions, so that admins can log out a compromised account
+use axum::routing::{delete, get};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::client_ip;
+use crate::audit::{self, AuditCategory};
+use crate::db::Session;
+use crate::{eyre_to_axum_err, perms::required::MANAGE_USERS, AppState, RequirePermission};
+
+/// A session. Session ids are as good as a password, so only their hash is stored, and
+/// sessions are referred to by it.
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    id: String,
+    user_id: i64,
+    username: Option<String>,
+    created_at: DateTime<Utc>,
+    expiration_date: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    provider: Option<String>,
+}
+
+impl SessionResponse {
+    fn new(session: Session, username: Option<String>) -> Self {
+        Self {
+            id: session.id,
+            user_id: session.user_id,
+            username,
+            created_at: session.created_at,
+            expiration_date: session.expiration_date,
+            last_used_at: session.last_used_at,
+            ip_address: session.ip_address,
+            user_agent: session.user_agent,
+            provider: session.provider,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionsQuery {
+    /// Only the sessions of this user
+    pub user_id: Option<i64>,
+}
+
+/// Every session that hasn't expired, most recently created first
+pub async fn get_sessions_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_USERS>,
+    Query(query): Query<SessionsQuery>,
+) -> Result<Json<Vec<SessionResponse>>, (StatusCode, String)> {
+    let now = Utc::now();
+    let sessions = match query.user_id {
+        Some(user_id) => state.db.get_user_sessions(user_id, now).await,
+        None => state.db.get_active_sessions(now).await,
+    }
+    .map_err(eyre_to_axum_err)?;
+    let users = state.db.get_all_users().await.map_err(eyre_to_axum_err)?;
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|s| {
+                let username = users
+                    .iter()
+                    .find(|u| u.id == s.user_id)
+                    .map(|u| u.username.clone());
+                SessionResponse::new(s, username)
+            })
+            .collect(),
+    ))
+}
+
+/// End a session, by the id listed by [`get_sessions_handler`]
+pub async fn delete_session_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let session = state
+        .db
+        .get_session_by_hash(&id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !state
+        .db
+        .delete_session(&id)
+        .await
+        .map_err(eyre_to_axum_err)?
+    {
+        return Err((StatusCode::NOT_FOUND, format!("No session {id:?}")));
+    }
+    let entry = audit::entry(
+        AuditCategory::Users,
+        "session_ended",
+        Some(&user),
+        client_ip(&headers),
+        "Ended one of a user's sessions",
+    );
+    let data = serde_json::json!({ "session": id });
+    let entry = match session {
+        Some(session) => entry.with_target(format!("user:{}", session.user_id)),
+        None => entry,
+    };
+    audit::record_entry(&state.db, entry.with_data(&data)).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// End every session of a user, logging them out everywhere
+pub async fn delete_user_sessions_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(user_id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let deleted = state
+        .db
+        .delete_user_sessions(user_id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let entry = audit::entry(
+        AuditCategory::Users,
+        "sessions_ended",
+        Some(&user),
+        client_ip(&headers),
+        format!("Ended all {deleted} of a user's sessions"),
+    );
+    let data = serde_json::json!({ "sessions": deleted });
+    audit::record_entry(
+        &state.db,
+        entry
+            .with_target(format!("user:{user_id}"))
+            .with_data(&data),
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_sessions_route() -> Router<AppState> {
+    Router::new()
+        .route("/sessions", get(get_sessions_handler))
+        .route("/sessions/{id}", delete(delete_session_handler))
+        .route(
+            "/users/{user_id}/sessions",
+            delete(delete_user_sessions_handler),
+        )
+}
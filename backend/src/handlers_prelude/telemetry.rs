@@ -0,0 +1,132 @@
+//! Opt-in performance metrics from the editor, see [`crate::telemetry`]
+use std::time::Instant;
+
+use axum::routing::get;
+use axum::{
+    extract::{Query, State},
+    Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::db::TelemetrySample;
+use crate::reports::parse_since;
+use crate::telemetry::{
+    ClientEvent, TelemetrySummary, MAX_EVENTS_PER_REPORT, METRIC_SAVE_LATENCY, SOURCE_CLIENT,
+    SOURCE_SERVER,
+};
+use crate::{
+    eyre_to_axum_err, perms::required::MANAGE_USERS, AppState, AuthenticatedUser, RequirePermission,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TelemetryReportBody {
+    events: Vec<ClientEvent>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetTelemetryQuery {
+    /// A date (`YYYY-MM-DD`) or RFC-3339 timestamp, inclusive. Defaults to the start of the
+    /// retention period.
+    pub since: Option<String>,
+}
+
+fn require_telemetry_enabled(state: &AppState) -> Result<(), (StatusCode, String)> {
+    if state.config.telemetry.enabled {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            "Telemetry is not enabled on this instance.".to_string(),
+        ))
+    }
+}
+
+/// The start of the period metrics are kept for
+fn retention_start(state: &AppState) -> DateTime<Utc> {
+    Utc::now() - Duration::days(i64::from(state.config.telemetry.retention_days))
+}
+
+/// Store metrics reported by the editor. The user has to be logged in, but isn't recorded.
+pub async fn post_telemetry_handler(
+    State(state): State<AppState>,
+    _: AuthenticatedUser,
+    Json(body): Json<TelemetryReportBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    require_telemetry_enabled(&state)?;
+    if body.events.len() > MAX_EVENTS_PER_REPORT {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Only {MAX_EVENTS_PER_REPORT} events can be reported at once."),
+        ));
+    }
+
+    let recorded_at = Utc::now();
+    let mut samples = Vec::with_capacity(body.events.len());
+    for event in body.events {
+        let (metric, value) = event
+            .to_metric()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        samples.push(TelemetrySample {
+            source: SOURCE_CLIENT.to_string(),
+            metric: metric.to_string(),
+            value,
+            recorded_at,
+        });
+    }
+    state
+        .db
+        .record_telemetry(&samples)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    state
+        .db
+        .prune_telemetry(retention_start(&state))
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Summarize the metrics recorded since `since`
+pub async fn get_telemetry_handler(
+    State(state): State<AppState>,
+    _: RequirePermission<MANAGE_USERS>,
+    Query(query): Query<GetTelemetryQuery>,
+) -> Result<Json<TelemetrySummary>, (StatusCode, String)> {
+    require_telemetry_enabled(&state)?;
+    let since = match query.since {
+        Some(since) => parse_since(&since).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?,
+        None => retention_start(&state),
+    };
+    let samples = state
+        .db
+        .get_telemetry(since)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(TelemetrySummary::new(since, &samples)))
+}
+
+/// Record how long the server spent on a save that started at `started`, if telemetry is enabled
+pub(super) async fn record_server_save(state: &AppState, started: Instant) {
+    if !state.config.telemetry.enabled {
+        return;
+    }
+    let sample = TelemetrySample {
+        source: SOURCE_SERVER.to_string(),
+        metric: METRIC_SAVE_LATENCY.to_string(),
+        value: i64::try_from(started.elapsed().as_millis()).unwrap_or(i64::MAX),
+        recorded_at: Utc::now(),
+    };
+    if let Err(e) = state.db.record_telemetry(&[sample]).await {
+        warn!("Failed to record save latency: {e:?}");
+    }
+}
+
+pub async fn create_telemetry_route() -> Router<AppState> {
+    Router::new().route(
+        "/telemetry",
+        get(get_telemetry_handler).post(post_telemetry_handler),
+    )
+}
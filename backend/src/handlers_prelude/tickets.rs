@@ -0,0 +1,246 @@
+//! Editing tickets, which let clients on unreliable connections edit a document offline
+//! and submit it later, even if the document changed in the meantime
+use axum::routing::post;
+use axum::{extract::State, http::HeaderMap, Json, Router};
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    db::{EditingTicket, User},
+    eyre_to_axum_err,
+    perms::required::MANAGE_CONTENT,
+    policy::PolicyViolation,
+    AppState, RequirePermission,
+};
+
+use super::{
+    check_branch_push, client_ip,
+    github_link::with_co_author,
+    repo_fs::{enforce_doc_policy, get_gh_token, invalidate_written_doc, record_doc_edit},
+};
+
+/// How long a client has to submit an edit before the ticket expires
+const TICKET_LIFETIME: Duration = Duration::days(7);
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IssueTicketRequestBody {
+    /// The path of the document, relative to the documents folder
+    path: String,
+    /// The branch to read the document from and save the edit to, defaults to the branch that's
+    /// checked out
+    branch_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EditingTicketResponse {
+    /// The id used to redeem the ticket
+    ticket: String,
+    path: String,
+    /// The branch the document was read from, which the edit will be saved to
+    branch_name: String,
+    contents: String,
+    /// The git blob id of the document when the ticket was issued
+    blob_sha: String,
+    /// ISO-8601/RFC-3339 string
+    expiration_date: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SubmitTicketRequestBody {
+    ticket: String,
+    contents: String,
+    commit_message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubmitTicketResponse {
+    /// Whether the document changed after the ticket was issued and the edit had to be merged
+    merged: bool,
+    /// Content policy checks that failed, but aren't configured to block saving
+    warnings: Vec<PolicyViolation>,
+}
+
+/// Issue an editing ticket for a document, containing the document and the revision it was read
+/// from.
+pub async fn post_editing_ticket_handler(
+    State(state): State<AppState>,
+    RequirePermission(user): RequirePermission<MANAGE_CONTENT>,
+    Json(body): Json<IssueTicketRequestBody>,
+) -> Result<(StatusCode, Json<EditingTicketResponse>), (StatusCode, String)> {
+    let branch = match body.branch_name {
+        Some(branch) => branch,
+        None => state
+            .git
+            .get_current_branch()
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+    };
+    let snapshot = state
+        .git
+        .get_doc_snapshot(&body.path, &branch)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "The file at the provided path was not found.".to_string(),
+            )
+        })?;
+    let ticket = state
+        .db
+        .create_editing_ticket(&EditingTicket {
+            id: rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect(),
+            user_id: user.id,
+            path: body.path,
+            base_commit: snapshot.commit,
+            blob_sha: snapshot.blob.clone(),
+            expiration_date: Utc::now() + TICKET_LIFETIME,
+            branch,
+        })
+        .await
+        .map_err(eyre_to_axum_err)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(EditingTicketResponse {
+            ticket: ticket.id,
+            path: ticket.path,
+            branch_name: ticket.branch,
+            contents: snapshot.contents,
+            blob_sha: snapshot.blob,
+            expiration_date: ticket.expiration_date,
+        }),
+    ))
+}
+
+/// Redeem an editing ticket, saving the edited document to the branch it was read from. If the
+/// document changed after the ticket was issued, the edit is merged with those changes, and
+/// `409 Conflict` is returned if that isn't possible.
+///
+/// The ticket is claimed before anything is saved, so it can't be redeemed twice by concurrent
+/// submissions. If saving fails, it's handed back so the edit can be submitted again.
+pub async fn put_editing_ticket_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_CONTENT>,
+    headers: HeaderMap,
+    Json(body): Json<SubmitTicketRequestBody>,
+) -> Result<Json<SubmitTicketResponse>, (StatusCode, String)> {
+    let Some(ticket) = state
+        .db
+        .claim_editing_ticket(&body.ticket, author.id)
+        .await
+        .map_err(eyre_to_axum_err)?
+    else {
+        let issued = state
+            .db
+            .get_editing_ticket(&body.ticket)
+            .await
+            .map_err(eyre_to_axum_err)?;
+        return Err(match issued {
+            Some(_) => (
+                StatusCode::FORBIDDEN,
+                "This editing ticket was issued to a different user".to_string(),
+            ),
+            None => (
+                StatusCode::NOT_FOUND,
+                "Unknown editing ticket, or it was already redeemed".to_string(),
+            ),
+        });
+    };
+    if ticket.expiration_date < Utc::now() {
+        return Err((
+            StatusCode::GONE,
+            "This editing ticket has expired, request a new one".to_string(),
+        ));
+    }
+
+    match redeem_ticket(&state, &author, client_ip(&headers), &ticket, body).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            if let Err(e) = state.db.create_editing_ticket(&ticket).await {
+                error!(
+                    "Failed to restore editing ticket for {:?}: {e:?}",
+                    ticket.path
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Save the edit submitted with a claimed `ticket`
+async fn redeem_ticket(
+    state: &AppState,
+    author: &User,
+    ip_address: Option<String>,
+    ticket: &EditingTicket,
+    body: SubmitTicketRequestBody,
+) -> Result<SubmitTicketResponse, (StatusCode, String)> {
+    check_branch_push(state, author, &ticket.branch).await?;
+
+    let contents = state
+        .git
+        .rebase_doc_edit(
+            &ticket.path,
+            &ticket.base_commit,
+            &body.contents,
+            &ticket.branch,
+        )
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| {
+            (
+                StatusCode::CONFLICT,
+                format!(
+                    "{} was changed after this ticket was issued and the changes conflict",
+                    ticket.path
+                ),
+            )
+        })?;
+    let merged = contents != body.contents;
+    let warnings = enforce_doc_policy(state, author, ip_address, &ticket.path, &contents).await?;
+
+    let default_commit_message = format!("{} updated {}", author.username, ticket.path);
+    let final_commit_message = with_co_author(
+        state,
+        author,
+        format!("{}\n\n{}", default_commit_message, body.commit_message),
+    )
+    .await;
+    let previous_branch = state.git.get_current_branch().await.ok();
+    state
+        .git
+        .put_doc(
+            &ticket.path,
+            &contents,
+            &final_commit_message,
+            &get_gh_token(state).await?,
+            &ticket.branch,
+        )
+        .map_err(eyre_to_axum_err)?;
+    invalidate_written_doc(
+        state,
+        previous_branch.as_deref(),
+        &ticket.branch,
+        &ticket.path,
+    );
+    record_doc_edit(state, &ticket.path, author, None).await;
+    info!(
+        "Editing ticket for {:?} redeemed by {:?} (merged: {merged})",
+        ticket.path, author.username
+    );
+
+    Ok(SubmitTicketResponse { merged, warnings })
+}
+
+pub async fn create_ticket_route() -> Router<AppState> {
+    Router::new().route(
+        "/doc/ticket",
+        post(post_editing_ticket_handler).put(put_editing_ticket_handler),
+    )
+}
@@ -0,0 +1,174 @@
+//! Creating and revoking API tokens, see [`crate::api_tokens`]
+use axum::routing::{delete, get};
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json, Router,
+};
+use chrono::{Duration, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::client_ip;
+use crate::api_tokens::{self, MAX_TOKEN_LIFETIME_DAYS};
+use crate::audit::{self, AuditCategory};
+use crate::db::{ApiToken, User};
+use crate::{eyre_to_axum_err, perms::Permission, AppState, AuthenticatedUser};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateApiTokenBody {
+    /// What the token is for
+    pub name: String,
+    /// How many days the token is valid for, defaults to the longest allowed
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateApiTokenResponse {
+    /// Only shown here, it can't be looked up again
+    pub token: String,
+    #[serde(flatten)]
+    pub details: ApiToken,
+}
+
+/// The current user's API tokens
+pub async fn get_api_tokens_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<ApiToken>>, (StatusCode, String)> {
+    let tokens = state
+        .db
+        .get_user_api_tokens(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    Ok(Json(tokens))
+}
+
+/// Create a token that acts as the current user
+pub async fn post_api_token_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+    Json(body): Json<CreateApiTokenBody>,
+) -> Result<Json<CreateApiTokenResponse>, (StatusCode, String)> {
+    Ok(Json(
+        create_api_token(&state, &user, &user, client_ip(&headers), body).await?,
+    ))
+}
+
+/// Create a token that acts as `owner`, on behalf of `author`, who made the request from
+/// `ip_address`
+pub(super) async fn create_api_token(
+    state: &AppState,
+    author: &User,
+    owner: &User,
+    ip_address: Option<String>,
+    body: CreateApiTokenBody,
+) -> Result<CreateApiTokenResponse, (StatusCode, String)> {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Tokens need a name".to_string()));
+    }
+    let lifetime_days = body.expires_in_days.unwrap_or(MAX_TOKEN_LIFETIME_DAYS);
+    if !(1..=MAX_TOKEN_LIFETIME_DAYS).contains(&lifetime_days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Tokens can be valid for 1 to {MAX_TOKEN_LIFETIME_DAYS} days"),
+        ));
+    }
+
+    let token = api_tokens::generate();
+    let now = Utc::now();
+    let details = state
+        .db
+        .create_api_token(
+            owner.id,
+            name,
+            &api_tokens::hash(&token),
+            now,
+            Some(now + Duration::days(i64::from(lifetime_days))),
+        )
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let entry = audit::entry(
+        AuditCategory::Auth,
+        "api_token_created",
+        Some(author),
+        ip_address,
+        format!(
+            "Created the API token {:?} for {:?}",
+            details.name, owner.username
+        ),
+    );
+    let data = serde_json::json!({ "token_id": details.id, "token_name": details.name });
+    audit::record_entry(
+        &state.db,
+        entry
+            .with_target(format!("user:{}", owner.id))
+            .with_data(&data),
+    )
+    .await;
+    Ok(CreateApiTokenResponse { token, details })
+}
+
+/// Revoke a token. Users can revoke their own tokens, and admins can revoke anyone's.
+pub async fn delete_api_token_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+    Path(token_id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            format!("No API token with the id {token_id}"),
+        )
+    };
+    let token = state
+        .db
+        .get_api_token(token_id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(not_found)?;
+    if token.user_id != user.id {
+        let user_perms = state
+            .db
+            .get_user_permissions(user.id)
+            .await
+            .map_err(eyre_to_axum_err)?;
+        // Other users' tokens don't exist as far as non-admins can tell
+        if !user_perms.contains(&Permission::ManageUsers) {
+            return Err(not_found());
+        }
+    }
+    state
+        .db
+        .delete_api_token(token_id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let entry = audit::entry(
+        AuditCategory::Auth,
+        "api_token_revoked",
+        Some(&user),
+        client_ip(&headers),
+        format!("Revoked the API token {:?}", token.name),
+    );
+    let data = serde_json::json!({ "token_id": token_id, "token_name": token.name });
+    audit::record_entry(
+        &state.db,
+        entry
+            .with_target(format!("user:{}", token.user_id))
+            .with_data(&data),
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_api_tokens_route() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/tokens",
+            get(get_api_tokens_handler).post(post_api_token_handler),
+        )
+        .route("/tokens/{token_id}", delete(delete_api_token_handler))
+}
@@ -4,15 +4,20 @@ use axum::{
     http::HeaderMap,
     Json, Router,
 };
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
+use super::{client_ip, parse_expiry, require_second_factor};
 use crate::{
-    db::{Database, Group, User},
+    audit::{self, AuditCategory},
+    can_manage,
+    db::{Database, Group, User, UserStats},
     eyre_to_axum_err,
-    perms::Permission,
-    require_perms, AppState,
+    perms::{required::MANAGE_USERS, Permission},
+    webhook_queue::timestamp,
+    AppState, AuthenticatedUser, RequirePermission,
 };
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -20,8 +25,14 @@ pub struct UserResponse {
     id: i64,
     username: String,
     avatar_url: String,
+    /// The GitHub account the user has linked, if any
+    github_login: Option<String>,
     groups: Vec<Group>,
     permissions: Vec<Permission>,
+    /// Whether the user is a service account, which can only authenticate with API tokens
+    service_account: bool,
+    /// When the user was deactivated, if they were
+    deactivated_at: Option<DateTime<Utc>>,
 }
 
 pub async fn create_user_response(
@@ -38,20 +49,47 @@ pub async fn create_user_response(
         .await
         .map_err(eyre_to_axum_err)?;
 
+    let github_account = db
+        .get_github_account(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+
     Ok(UserResponse {
         id: user.id,
         username: user.username,
         avatar_url: user.avatar_url,
+        github_login: github_account.map(|a| a.github_login),
         groups,
         permissions,
+        service_account: user.service_account,
+        deactivated_at: user.deactivated_at,
     })
 }
 
 pub async fn get_users_handler(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthenticatedUser(user): AuthenticatedUser,
 ) -> Result<Json<Vec<UserResponse>>, (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
+    // Delegated admins need the user list to pick who to add to the groups they manage
+    let is_admin = state
+        .db
+        .get_user_permissions(user.id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .contains(&Permission::ManageUsers);
+    if !is_admin
+        && state
+            .db
+            .get_user_managed_groups(user.id)
+            .await
+            .map_err(eyre_to_axum_err)?
+            .is_empty()
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("User {:?} doesn't manage any users.", user.username),
+        ));
+    }
 
     match state.db.get_all_users().await {
         Ok(users) => {
@@ -77,31 +115,69 @@ pub async fn get_users_handler(
 
 pub async fn get_current_user_handler(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthenticatedUser(user): AuthenticatedUser,
 ) -> Result<Json<UserResponse>, (StatusCode, String)> {
-    let user = require_perms(axum::extract::State(&state), headers, &[]).await?;
     Ok(Json(create_user_response(&state.db, user).await?))
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct UpdateUserGroupsRequestBody {
     group_ids: Vec<i64>,
+    /// When memberships being added expire, an RFC-3339 timestamp. They don't if unset.
+    #[serde(default)]
+    expires_at: Option<String>,
+}
+
+/// Returns an error unless `user` may manage the membership of every group in `group_ids`.
+async fn require_can_manage(
+    state: &AppState,
+    user: &User,
+    group_ids: &[i64],
+) -> Result<(), (StatusCode, String)> {
+    for &group_id in group_ids {
+        if !can_manage(state, user, group_id).await? {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!(
+                    "User {:?} may not manage the members of group {group_id}.",
+                    user.username
+                ),
+            ));
+        }
+    }
+    Ok(())
 }
 
 pub async fn post_user_membership_handler(
     State(state): State<AppState>,
+    AuthenticatedUser(author): AuthenticatedUser,
     headers: HeaderMap,
     Path(user_id): Path<i64>,
     Json(body): Json<UpdateUserGroupsRequestBody>,
 ) -> Result<Json<UserResponse>, (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
+    require_can_manage(&state, &author, &body.group_ids).await?;
+    let expires_at = parse_expiry(body.expires_at.as_deref())?;
 
+    let added = state
+        .db
+        .add_group_memberships(user_id, &body.group_ids, expires_at)
+        .await
+        .map_err(eyre_to_axum_err)?;
     for group_id in body.group_ids {
-        state
-            .db
-            .add_group_membership(group_id, user_id)
-            .await
-            .map_err(eyre_to_axum_err)?;
+        if added.contains(&group_id) || expires_at.is_some() {
+            audit::record(
+                &state.db,
+                AuditCategory::Perms,
+                "member_added",
+                Some(&author),
+                client_ip(&headers),
+                format!(
+                    "Added user {user_id} to group {group_id} until {}",
+                    expires_at.map_or_else(|| "they're removed".to_string(), timestamp)
+                ),
+            )
+            .await;
+        }
     }
 
     let user = state
@@ -116,18 +192,28 @@ pub async fn post_user_membership_handler(
 
 pub async fn delete_user_membership_handler(
     State(state): State<AppState>,
+    AuthenticatedUser(author): AuthenticatedUser,
     headers: HeaderMap,
     Path(user_id): Path<i64>,
     Json(body): Json<UpdateUserGroupsRequestBody>,
 ) -> Result<Json<UserResponse>, (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
+    require_can_manage(&state, &author, &body.group_ids).await?;
 
-    for group_id in body.group_ids {
-        state
-            .db
-            .remove_group_membership(group_id, user_id)
-            .await
-            .map_err(eyre_to_axum_err)?;
+    let removed = state
+        .db
+        .remove_group_memberships(user_id, &body.group_ids)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    for group_id in removed {
+        audit::record(
+            &state.db,
+            AuditCategory::Perms,
+            "member_removed",
+            Some(&author),
+            client_ip(&headers),
+            format!("Removed user {user_id} from group {group_id}"),
+        )
+        .await;
     }
 
     let user = state
@@ -140,31 +226,160 @@ pub async fn delete_user_membership_handler(
     Ok(Json(create_user_response(&state.db, user).await?))
 }
 
+/// Deactivates a user, who can then no longer sign in. They're kept so the history they're
+/// attributed in keeps their name, see [`purge_user_handler`] to delete them for good.
 pub async fn delete_user_handler(
     State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_USERS>,
     headers: HeaderMap,
     Path(user_id): Path<i64>,
 ) -> Result<(), (StatusCode, String)> {
-    require_perms(State(&state), headers, &[Permission::ManageUsers]).await?;
+    let deactivated = state
+        .db
+        .deactivate_user(user_id, Utc::now())
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !deactivated {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("There's no active user {user_id}"),
+        ));
+    }
+    let entry = audit::entry(
+        AuditCategory::Users,
+        "user_deactivated",
+        Some(&author),
+        client_ip(&headers),
+        format!("Deactivated user {user_id}"),
+    );
+    audit::record_entry(&state.db, entry.with_target(format!("user:{user_id}"))).await;
+    Ok(())
+}
+
+/// Lets a deactivated user sign in again
+pub async fn post_reactivate_user_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(user_id): Path<i64>,
+) -> Result<Json<UserResponse>, (StatusCode, String)> {
+    let reactivated = state
+        .db
+        .reactivate_user(user_id)
+        .await
+        .map_err(eyre_to_axum_err)?;
+    if !reactivated {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("There's no deactivated user {user_id}"),
+        ));
+    }
+    let entry = audit::entry(
+        AuditCategory::Users,
+        "user_reactivated",
+        Some(&author),
+        client_ip(&headers),
+        format!("Reactivated user {user_id}"),
+    );
+    audit::record_entry(&state.db, entry.with_target(format!("user:{user_id}"))).await;
+    let user = state
+        .db
+        .get_user(user_id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("There's no user {user_id}")))?;
+    Ok(Json(create_user_response(&state.db, user).await?))
+}
 
+/// Permanently deletes a user along with their sessions, tokens and memberships. Only
+/// deactivated users can be purged, so that it's never done by accident, and the second factor
+/// is required if it's enabled.
+pub async fn post_purge_user_handler(
+    State(state): State<AppState>,
+    RequirePermission(author): RequirePermission<MANAGE_USERS>,
+    headers: HeaderMap,
+    Path(user_id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    require_second_factor(&state, &author, &headers).await?;
+    let user = state
+        .db
+        .get_user(user_id)
+        .await
+        .map_err(eyre_to_axum_err)?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("There's no user {user_id}")))?;
+    if user.deactivated_at.is_none() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "User {:?} has to be deactivated before they're purged",
+                user.username
+            ),
+        ));
+    }
     state
         .db
-        .delete_user(user_id)
+        .purge_user(user_id)
         .await
-        .map_err(eyre_to_axum_err)
+        .map_err(eyre_to_axum_err)?;
+    let entry = audit::entry(
+        AuditCategory::Users,
+        "user_purged",
+        Some(&author),
+        client_ip(&headers),
+        format!("Purged user {:?} ({user_id})", user.username),
+    );
+    audit::record_entry(&state.db, entry.with_target(format!("user:{user_id}"))).await;
+    Ok(StatusCode::NO_CONTENT)
 }
 
+/// Deactivates the current user, see [`delete_user_handler`]
 pub async fn delete_current_user(
     State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
     headers: HeaderMap,
 ) -> Result<(), (StatusCode, String)> {
-    let user = require_perms(axum::extract::State(&state), headers, &[]).await?;
+    state
+        .db
+        .deactivate_user(user.id, Utc::now())
+        .await
+        .map_err(eyre_to_axum_err)?;
+    let entry = audit::entry(
+        AuditCategory::Users,
+        "user_deactivated",
+        Some(&user),
+        client_ip(&headers),
+        String::from("Deactivated their own account"),
+    );
+    audit::record_entry(&state.db, entry.with_target(format!("user:{}", user.id))).await;
+    Ok(())
+}
 
+/// Returns what a user has contributed. Users can see their own, admins can see anyone's.
+pub async fn get_user_stats_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(user_id): Path<i64>,
+) -> Result<Json<UserStats>, (StatusCode, String)> {
+    if user.id != user_id
+        && !state
+            .db
+            .get_user_permissions(user.id)
+            .await
+            .map_err(eyre_to_axum_err)?
+            .contains(&Permission::ManageUsers)
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            String::from("Only admins can see the statistics of other users"),
+        ));
+    }
     state
         .db
-        .delete_user(user.id)
+        .get_user_stats(user_id)
         .await
-        .map_err(eyre_to_axum_err)
+        .map_err(eyre_to_axum_err)?
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("There's no user {user_id}")))
 }
 
 pub async fn create_user_route() -> Router<AppState> {
@@ -175,6 +390,12 @@ pub async fn create_user_route() -> Router<AppState> {
             post(post_user_membership_handler).delete(delete_user_membership_handler),
         )
         .route("/users/{user_id}", delete(delete_user_handler))
+        .route(
+            "/users/{user_id}/reactivate",
+            post(post_reactivate_user_handler),
+        )
+        .route("/users/{user_id}/purge", post(post_purge_user_handler))
+        .route("/users/{user_id}/stats", get(get_user_stats_handler))
         .route(
             "/users/me",
             get(get_current_user_handler).delete(delete_current_user),
@@ -0,0 +1,75 @@
+//! Describing how the current request is authenticated, so that clients don't have to piece it
+//! together from cookies
+use axum::routing::get;
+use axum::{extract::State, http::HeaderMap, Json, Router};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use super::{bearer_token, create_user_response, session_id, UserResponse};
+use crate::{api_tokens, eyre_to_axum_err, AppState, AuthenticatedUser};
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialKind {
+    /// A browser session, see [`super::SESSION_COOKIE`]
+    Session,
+    /// An API token, see [`crate::api_tokens`]
+    ApiToken,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WhoAmIResponse {
+    /// The user, along with their groups and effective permissions
+    user: UserResponse,
+    credential: CredentialKind,
+    /// When the session or token expires, `None` for tokens that don't. Sessions are renewed
+    /// past this if the provider they were signed in with allows it.
+    expiration_date: Option<DateTime<Utc>>,
+    /// The provider a session was signed in with
+    provider: Option<String>,
+}
+
+pub async fn get_whoami_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<WhoAmIResponse>, (StatusCode, String)> {
+    // API tokens take precedence over sessions, the same as when the user was found
+    let (credential, expiration_date, provider) = if let Some(token) = bearer_token(&headers) {
+        let token = state
+            .db
+            .get_api_token_by_hash(&api_tokens::hash(token))
+            .await
+            .map_err(eyre_to_axum_err)?;
+        (
+            CredentialKind::ApiToken,
+            token.and_then(|t| t.expiration_date),
+            None,
+        )
+    } else {
+        let session = match session_id(&headers) {
+            Some(session_id) => state
+                .db
+                .get_session_by_hash(&api_tokens::hash(session_id))
+                .await
+                .map_err(eyre_to_axum_err)?,
+            None => None,
+        };
+        (
+            CredentialKind::Session,
+            session.as_ref().map(|s| s.expiration_date),
+            session.and_then(|s| s.provider),
+        )
+    };
+    Ok(Json(WhoAmIResponse {
+        user: create_user_response(&state.db, user).await?,
+        credential,
+        expiration_date,
+        provider,
+    }))
+}
+
+pub async fn create_whoami_route() -> Router<AppState> {
+    Router::new().route("/auth/whoami", get(get_whoami_handler))
+}
@@ -0,0 +1,179 @@
+//! The accounts users sign in with. Discord, GitHub and OpenID Connect can be enabled at once,
+//! and a user can link one account from each provider, then sign in with any of them.
+
+use crate::app_conf::OidcOAuth;
+use color_eyre::eyre::{bail, ContextCompat};
+use color_eyre::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentityProvider {
+    Discord,
+    GitHub,
+    Oidc,
+}
+
+impl IdentityProvider {
+    /// How the provider is stored in the database and written in URLs
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Discord => "discord",
+            Self::GitHub => "github",
+            Self::Oidc => "oidc",
+        }
+    }
+}
+
+impl TryFrom<&str> for IdentityProvider {
+    type Error = &'static str;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "discord" => Ok(Self::Discord),
+            "github" => Ok(Self::GitHub),
+            "oidc" => Ok(Self::Oidc),
+            _ => Err("Not a valid identity provider"),
+        }
+    }
+}
+
+/// An account on an identity provider, as reported by the provider after the user signs in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalIdentity {
+    pub provider: IdentityProvider,
+    /// The provider's id for the account, which unlike the username never changes
+    pub subject: String,
+    pub username: String,
+    pub avatar_url: String,
+}
+
+/// Shown for accounts without a profile picture
+pub const DEFAULT_AVATAR_URL: &str = "https://cdn.discordapp.com/embed/avatars/0.png";
+
+/// The claims from an OpenID Connect userinfo response Hyde uses
+#[derive(Debug, Deserialize)]
+struct OidcUserInfo {
+    sub: String,
+    preferred_username: Option<String>,
+    name: Option<String>,
+    email: Option<String>,
+    picture: Option<String>,
+}
+
+impl From<OidcUserInfo> for ExternalIdentity {
+    fn from(info: OidcUserInfo) -> Self {
+        Self {
+            provider: IdentityProvider::Oidc,
+            username: info
+                .preferred_username
+                .or(info.name)
+                .or(info.email)
+                .unwrap_or_else(|| info.sub.clone()),
+            subject: info.sub,
+            avatar_url: info
+                .picture
+                .unwrap_or_else(|| DEFAULT_AVATAR_URL.to_string()),
+        }
+    }
+}
+
+/// Complete the authorization code flow with an OpenID Connect provider, returning the
+/// account the user signed in to. The access token is only used to fetch the account, and
+/// isn't kept.
+///
+/// # Errors
+/// This function returns an error if the provider rejects the code, or either request fails.
+pub async fn fetch_oidc_identity(
+    client: &Client,
+    conf: &OidcOAuth,
+    code: &str,
+    redirect_uri: &str,
+) -> Result<ExternalIdentity> {
+    let response = client
+        .post(&conf.token_url)
+        .header("Accept", "application/json")
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", &conf.client_id),
+            ("client_secret", &conf.client_secret),
+        ])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let response_text = response.text().await?;
+        bail!(
+            "Failed to exchange the OpenID Connect code: {}, Response: {}",
+            status,
+            response_text
+        );
+    }
+    let token_response: Value = response.json().await?;
+    let access_token = token_response
+        .get("access_token")
+        .and_then(Value::as_str)
+        .wrap_err("The OpenID Connect provider didn't return an access token")?;
+
+    let response = client
+        .get(&conf.userinfo_url)
+        .bearer_auth(access_token)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let response_text = response.text().await?;
+        bail!(
+            "Failed to fetch the OpenID Connect user info: {}, Response: {}",
+            status,
+            response_text
+        );
+    }
+    let user_info: OidcUserInfo = response.json().await?;
+    Ok(user_info.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oidc_usernames() {
+        let info: OidcUserInfo = serde_json::from_str(
+            r#"{"sub": "248289761001", "name": "Jane Doe", "email": "jane@example.com"}"#,
+        )
+        .unwrap();
+        let identity = ExternalIdentity::from(info);
+        assert_eq!(identity.subject, "248289761001");
+        assert_eq!(
+            identity.username, "Jane Doe",
+            "ExternalIdentity::from: should fall back to the name without a preferred username"
+        );
+        assert_eq!(identity.avatar_url, DEFAULT_AVATAR_URL);
+
+        let info: OidcUserInfo = serde_json::from_str(r#"{"sub": "248289761001"}"#).unwrap();
+        assert_eq!(
+            ExternalIdentity::from(info).username,
+            "248289761001",
+            "ExternalIdentity::from: should fall back to the subject without any names"
+        );
+    }
+
+    #[test]
+    fn provider_names() {
+        for provider in [
+            IdentityProvider::Discord,
+            IdentityProvider::GitHub,
+            IdentityProvider::Oidc,
+        ] {
+            assert_eq!(IdentityProvider::try_from(provider.as_str()), Ok(provider));
+            assert_eq!(
+                serde_json::to_string(&provider).unwrap(),
+                format!("{:?}", provider.as_str())
+            );
+        }
+    }
+}
@@ -0,0 +1,495 @@
+//! Stripping metadata (EXIF, including GPS location, XMP, comments, et cetera) from uploaded
+//! images, so photos don't leak more than intended once they're on a public site.
+//!
+//! JPEG and PNG files are rewritten without their metadata segments/chunks. In HEIF/HEIC files,
+//! metadata is stored as items referenced by offset from elsewhere in the file, so their
+//! contents are zeroed out in place instead.
+
+use color_eyre::eyre::{bail, ContextCompat};
+use color_eyre::Result;
+use serde::Serialize;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// PNG chunks that only hold metadata
+const PNG_METADATA_CHUNKS: &[(&[u8; 4], &str)] = &[
+    (b"eXIf", "EXIF"),
+    (b"tEXt", "text"),
+    (b"zTXt", "text"),
+    (b"iTXt", "text"),
+    (b"tIME", "timestamp"),
+];
+/// `ftyp` brands used by HEIF based formats
+const HEIF_BRANDS: &[&[u8; 4]] = &[
+    b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1", b"avif", b"avis",
+];
+
+/// A piece of metadata that was removed from an image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StrippedMetadata {
+    /// What kind of metadata it was, e.g. `EXIF`
+    pub kind: &'static str,
+    /// How many bytes of metadata were removed
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Heif,
+}
+
+impl ImageFormat {
+    /// Identify an image by its contents, returning `None` if it isn't a supported format.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if data.starts_with(PNG_SIGNATURE) {
+            Some(Self::Png)
+        } else if data.get(4..8) == Some(b"ftyp")
+            && data
+                .get(8..12)
+                .is_some_and(|brand| HEIF_BRANDS.iter().any(|b| b.as_slice() == brand))
+        {
+            Some(Self::Heif)
+        } else {
+            None
+        }
+    }
+}
+
+/// Remove the metadata from an image, returning the new image and what was removed. Files that
+/// aren't a supported image format are returned unchanged.
+///
+/// # Errors
+/// This function returns an error if the file looks like a supported image, but is malformed.
+pub fn strip_metadata(data: &[u8]) -> Result<(Vec<u8>, Vec<StrippedMetadata>)> {
+    match ImageFormat::detect(data) {
+        Some(ImageFormat::Jpeg) => strip_jpeg(data),
+        Some(ImageFormat::Png) => strip_png(data),
+        Some(ImageFormat::Heif) => {
+            let mut data = data.to_vec();
+            let stripped = strip_heif(&mut data)?;
+            Ok((data, stripped))
+        }
+        None => Ok((data.to_vec(), Vec::new())),
+    }
+}
+
+/// Identify a JPEG APPn/COM segment that only holds metadata
+fn jpeg_metadata_kind(marker: u8, payload: &[u8]) -> Option<&'static str> {
+    match marker {
+        // APP1
+        0xE1 if payload.starts_with(b"Exif\0") => Some("EXIF"),
+        0xE1 if payload.starts_with(b"http://ns.adobe.com/") => Some("XMP"),
+        // APP13, Photoshop IRB (IPTC)
+        0xED => Some("IPTC"),
+        0xFE => Some("comment"),
+        _ => None,
+    }
+}
+
+fn strip_jpeg(data: &[u8]) -> Result<(Vec<u8>, Vec<StrippedMetadata>)> {
+    let mut output = data[..2].to_vec();
+    let mut stripped = Vec::new();
+    let mut pos = 2;
+    loop {
+        let marker = *data
+            .get(pos + 1)
+            .wrap_err("JPEG ended before the image data")?;
+        if data[pos] != 0xFF {
+            bail!("Invalid JPEG segment at offset {pos}");
+        }
+        // Start of scan, everything from here on out is image data
+        if marker == 0xDA {
+            output.extend_from_slice(&data[pos..]);
+            return Ok((output, stripped));
+        }
+        let length_bytes = data
+            .get(pos + 2..pos + 4)
+            .wrap_err("JPEG ended before the image data")?;
+        let length = usize::from(u16::from_be_bytes([length_bytes[0], length_bytes[1]]));
+        if length < 2 {
+            bail!("Invalid JPEG segment length at offset {pos}");
+        }
+        let segment = data
+            .get(pos..pos + 2 + length)
+            .wrap_err("JPEG segment extends past the end of the file")?;
+        match jpeg_metadata_kind(marker, &segment[4..]) {
+            Some(kind) => stripped.push(StrippedMetadata {
+                kind,
+                bytes: segment.len(),
+            }),
+            None => output.extend_from_slice(segment),
+        }
+        pos += segment.len();
+    }
+}
+
+fn strip_png(data: &[u8]) -> Result<(Vec<u8>, Vec<StrippedMetadata>)> {
+    let mut output = PNG_SIGNATURE.to_vec();
+    let mut stripped = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < data.len() {
+        let length = read_u32(data, pos)? as usize;
+        let chunk_type = data
+            .get(pos + 4..pos + 8)
+            .wrap_err("PNG chunk is truncated")?;
+        // length, type, data, and CRC
+        let chunk = data
+            .get(pos..pos + 12 + length)
+            .wrap_err("PNG chunk extends past the end of the file")?;
+        match PNG_METADATA_CHUNKS
+            .iter()
+            .find(|(t, _)| t.as_slice() == chunk_type)
+        {
+            Some((_, kind)) => stripped.push(StrippedMetadata {
+                kind,
+                bytes: chunk.len(),
+            }),
+            None => output.extend_from_slice(chunk),
+        }
+        pos += chunk.len();
+    }
+    Ok((output, stripped))
+}
+
+/// An ISOBMFF box, with offsets into the file
+#[derive(Debug, Clone, Copy)]
+struct IsoBox<'a> {
+    kind: &'a [u8],
+    /// Where the box's contents start
+    start: usize,
+    end: usize,
+}
+
+/// Iterate over the boxes between `start` and `end`
+fn iso_boxes(data: &[u8], mut start: usize, end: usize) -> Result<Vec<IsoBox<'_>>> {
+    let mut boxes = Vec::new();
+    while start < end {
+        let size = read_u32(data, start)? as usize;
+        let kind = data
+            .get(start + 4..start + 8)
+            .wrap_err("HEIF box header is truncated")?;
+        let (header_size, size) = match size {
+            0 => (8, end - start),
+            1 => (16, usize::try_from(read_u64(data, start + 8)?)?),
+            size => (8, size),
+        };
+        let box_end = start + size;
+        if size < header_size || box_end > end {
+            bail!(
+                "HEIF box {:?} has an invalid size",
+                String::from_utf8_lossy(kind)
+            );
+        }
+        boxes.push(IsoBox {
+            kind,
+            start: start + header_size,
+            end: box_end,
+        });
+        start = box_end;
+    }
+    Ok(boxes)
+}
+
+fn find_box<'a>(boxes: &[IsoBox<'a>], kind: &[u8]) -> Option<IsoBox<'a>> {
+    boxes.iter().find(|b| b.kind == kind).copied()
+}
+
+/// Zero out the contents of every EXIF and XMP item
+fn strip_heif(data: &mut [u8]) -> Result<Vec<StrippedMetadata>> {
+    let top_level = iso_boxes(data, 0, data.len())?;
+    let meta = find_box(&top_level, b"meta").wrap_err("HEIF file has no meta box")?;
+    // `meta` is a full box, skip the version and flags
+    let meta_children = iso_boxes(data, meta.start + 4, meta.end)?;
+
+    let mut metadata_items: Vec<(u32, &'static str)> = Vec::new();
+    if let Some(iinf) = find_box(&meta_children, b"iinf") {
+        let version = read_sized(data, iinf.start, 1)?;
+        let entries_start = iinf.start + if version == 0 { 6 } else { 8 };
+        for infe in iso_boxes(data, entries_start, iinf.end)? {
+            if infe.kind != b"infe" {
+                continue;
+            }
+            if let Some(item) = heif_metadata_item(data, infe)? {
+                metadata_items.push(item);
+            }
+        }
+    }
+    if metadata_items.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let iloc = find_box(&meta_children, b"iloc").wrap_err("HEIF file has no iloc box")?;
+    let idat = find_box(&meta_children, b"idat");
+    let mut stripped = Vec::new();
+    for (item_id, extents) in heif_item_extents(data, iloc, idat)? {
+        let Some((_, kind)) = metadata_items.iter().find(|(id, _)| *id == item_id) else {
+            continue;
+        };
+        let mut bytes = 0;
+        for (offset, length) in extents {
+            let extent = data
+                .get_mut(offset..offset + length)
+                .wrap_err("HEIF item extends past the end of the file")?;
+            extent.fill(0);
+            bytes += length;
+        }
+        stripped.push(StrippedMetadata { kind, bytes });
+    }
+    Ok(stripped)
+}
+
+/// Returns the item id and kind if an `infe` box describes a metadata item
+fn heif_metadata_item(data: &[u8], infe: IsoBox) -> Result<Option<(u32, &'static str)>> {
+    let version = read_sized(data, infe.start, 1)?;
+    // Versions before 2 don't have an item type
+    if version < 2 {
+        return Ok(None);
+    }
+    let mut pos = infe.start + 4;
+    let item_id = if version == 2 {
+        let id = read_u16(data, pos)?;
+        pos += 2;
+        u32::from(id)
+    } else {
+        let id = read_u32(data, pos)?;
+        pos += 4;
+        id
+    };
+    // Skip the protection index
+    pos += 2;
+    let item_type = data
+        .get(pos..pos + 4)
+        .wrap_err("HEIF infe box is truncated")?;
+    pos += 4;
+    match item_type {
+        b"Exif" => Ok(Some((item_id, "EXIF"))),
+        b"mime" => {
+            // The item name, then the content type, both null terminated
+            let strings = &data[pos.min(infe.end)..infe.end];
+            let content_type = strings.split(|&b| b == 0).nth(1).unwrap_or_default();
+            Ok((content_type == b"application/rdf+xml").then_some((item_id, "XMP")))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The absolute offset and length of each part of an item
+type Extents = Vec<(usize, usize)>;
+
+/// Read an `iloc` box, returning each item's id and extents
+fn heif_item_extents(
+    data: &[u8],
+    iloc: IsoBox,
+    idat: Option<IsoBox>,
+) -> Result<Vec<(u32, Extents)>> {
+    let version = read_sized(data, iloc.start, 1)?;
+    let mut pos = iloc.start + 4;
+    let sizes = data
+        .get(pos..pos + 2)
+        .wrap_err("HEIF iloc box is truncated")?;
+    let offset_size = usize::from(sizes[0] >> 4);
+    let length_size = usize::from(sizes[0] & 0xF);
+    let base_offset_size = usize::from(sizes[1] >> 4);
+    let index_size = if version == 0 {
+        0
+    } else {
+        usize::from(sizes[1] & 0xF)
+    };
+    pos += 2;
+    let item_count = if version < 2 {
+        pos += 2;
+        u32::from(read_u16(data, pos - 2)?)
+    } else {
+        pos += 4;
+        read_u32(data, pos - 4)?
+    };
+
+    let mut items = Vec::new();
+    for _ in 0..item_count {
+        let item_id = if version < 2 {
+            pos += 2;
+            u32::from(read_u16(data, pos - 2)?)
+        } else {
+            pos += 4;
+            read_u32(data, pos - 4)?
+        };
+        let construction_method = if version == 0 {
+            0
+        } else {
+            pos += 2;
+            read_u16(data, pos - 2)? & 0xF
+        };
+        // Skip the data reference index
+        pos += 2;
+        let base_offset = read_sized(data, pos, base_offset_size)?;
+        pos += base_offset_size;
+        let extent_count = read_u16(data, pos)?;
+        pos += 2;
+
+        let base = match construction_method {
+            // Offsets into the file
+            0 => 0,
+            // Offsets into the idat box
+            1 => {
+                idat.wrap_err("HEIF item is stored in a missing idat box")?
+                    .start
+            }
+            _ => bail!("Unsupported HEIF item construction method {construction_method}"),
+        };
+        let mut extents = Vec::new();
+        for _ in 0..extent_count {
+            pos += index_size;
+            let offset = read_sized(data, pos, offset_size)?;
+            pos += offset_size;
+            let length = read_sized(data, pos, length_size)?;
+            pos += length_size;
+            extents.push((base + base_offset + offset, length));
+        }
+        items.push((item_id, extents));
+    }
+    Ok(items)
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16> {
+    Ok(u16::try_from(read_sized(data, pos, 2)?)?)
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32> {
+    Ok(u32::try_from(read_sized(data, pos, 4)?)?)
+}
+
+fn read_u64(data: &[u8], pos: usize) -> Result<u64> {
+    Ok(u64::try_from(read_sized(data, pos, 8)?)?)
+}
+
+/// Read a big endian integer that's `size` bytes long
+fn read_sized(data: &[u8], pos: usize, size: usize) -> Result<usize> {
+    let bytes = data
+        .get(pos..pos + size)
+        .wrap_err("Unexpected end of file")?;
+    let value = bytes
+        .iter()
+        .fold(0_u64, |value, &b| (value << 8) | u64::from(b));
+    Ok(usize::try_from(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let length = u16::try_from(payload.len() + 2).unwrap();
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&length.to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn iso_box(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let size = u32::try_from(contents.len() + 8).unwrap();
+        let mut b = size.to_be_bytes().to_vec();
+        b.extend_from_slice(kind);
+        b.extend_from_slice(contents);
+        b
+    }
+
+    #[test]
+    fn jpeg_stripping() {
+        let app0 = jpeg_segment(0xE0, b"JFIF\0\x01\x02");
+        let exif = jpeg_segment(0xE1, b"Exif\0\0GPS data");
+        let comment = jpeg_segment(0xFE, b"shot on my phone");
+        let scan = [0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9];
+        let image = [&[0xFF, 0xD8][..], &app0, &exif, &comment, &scan].concat();
+
+        let (stripped_image, stripped) = strip_metadata(&image).unwrap();
+        assert_eq!(stripped_image, [&[0xFF, 0xD8][..], &app0, &scan].concat());
+        assert_eq!(
+            stripped,
+            [
+                StrippedMetadata {
+                    kind: "EXIF",
+                    bytes: exif.len()
+                },
+                StrippedMetadata {
+                    kind: "comment",
+                    bytes: comment.len()
+                }
+            ]
+        );
+        assert!(
+            strip_metadata(&image[..10]).is_err(),
+            "strip_metadata: truncated images should be rejected"
+        );
+    }
+
+    #[test]
+    fn png_stripping() {
+        let chunk = |kind: &[u8; 4], contents: &[u8]| {
+            let mut c = u32::try_from(contents.len())
+                .unwrap()
+                .to_be_bytes()
+                .to_vec();
+            c.extend_from_slice(kind);
+            c.extend_from_slice(contents);
+            c.extend_from_slice(&[0; 4]);
+            c
+        };
+        let ihdr = chunk(b"IHDR", &[0; 13]);
+        let text = chunk(b"tEXt", b"Author\0me");
+        let iend = chunk(b"IEND", &[]);
+        let image = [PNG_SIGNATURE, &ihdr, &text, &iend].concat();
+
+        let (stripped_image, stripped) = strip_metadata(&image).unwrap();
+        assert_eq!(stripped_image, [PNG_SIGNATURE, &ihdr, &iend].concat());
+        assert_eq!(stripped[0].kind, "text");
+        assert_eq!(
+            strip_metadata(b"not an image").unwrap(),
+            (b"not an image".to_vec(), Vec::new()),
+            "strip_metadata: unsupported files should be left alone"
+        );
+    }
+
+    #[test]
+    fn heif_stripping() {
+        let ftyp = iso_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+        // infe version 2: item 1 is the image, item 2 is EXIF
+        let infe_image = iso_box(b"infe", b"\x02\0\0\0\0\x01\0\0hvc1\0");
+        let infe_exif = iso_box(b"infe", b"\x02\0\0\0\0\x02\0\0Exif\0");
+        let iinf = iso_box(
+            b"iinf",
+            &[&[0, 0, 0, 0, 0, 2][..], &infe_image, &infe_exif].concat(),
+        );
+        let exif_data = b"\0\0\0\0MM\0*GPS";
+        // Placeholder iloc, the offset is filled in below. Version 0, 4 byte offsets and lengths,
+        // no base offset, 1 item with 1 extent
+        let mut iloc_contents = vec![0, 0, 0, 0, 0x44, 0x00, 0, 1, 0, 2, 0, 0, 0, 1];
+        iloc_contents.extend_from_slice(&[0; 4]);
+        iloc_contents.extend_from_slice(&u32::try_from(exif_data.len()).unwrap().to_be_bytes());
+        let meta_len = 8 + 4 + iinf.len() + 8 + iloc_contents.len();
+        let exif_offset = ftyp.len() + meta_len + 8;
+        iloc_contents[14..18].copy_from_slice(&u32::try_from(exif_offset).unwrap().to_be_bytes());
+        let iloc = iso_box(b"iloc", &iloc_contents);
+        let meta = iso_box(b"meta", &[&[0, 0, 0, 0][..], &iinf, &iloc].concat());
+        let mdat = iso_box(b"mdat", exif_data);
+        let image = [ftyp, meta, mdat].concat();
+        assert_eq!(ImageFormat::detect(&image), Some(ImageFormat::Heif));
+
+        let (stripped_image, stripped) = strip_metadata(&image).unwrap();
+        assert_eq!(
+            stripped,
+            [StrippedMetadata {
+                kind: "EXIF",
+                bytes: exif_data.len()
+            }]
+        );
+        assert_eq!(stripped_image.len(), image.len());
+        assert_eq!(
+            stripped_image[exif_offset..],
+            vec![0; exif_data.len()],
+            "strip_metadata: HEIF metadata should be zeroed in place"
+        );
+    }
+}
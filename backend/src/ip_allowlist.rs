@@ -0,0 +1,240 @@
+//! Only letting admin endpoints be reached from trusted networks, so that a stolen admin
+//! session or token is useless from anywhere else, and working out which address a request
+//! came from in the first place
+
+use crate::handlers_prelude::{request_ip, REAL_IP_HEADER};
+use crate::AppState;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use reqwest::StatusCode;
+use std::net::{IpAddr, SocketAddr};
+use tracing::warn;
+
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Whether a path (relative to `/api`) manages users, groups, or the instance itself
+fn is_admin_path(path: &str) -> bool {
+    if path == "/users/me" || path.starts_with("/users/me/") {
+        return false;
+    }
+    [
+        "/users",
+        "/groups",
+        "/reclone",
+        "/sessions",
+        "/service-accounts",
+        "/audit",
+        "/permissions/denies",
+        "/admin",
+        "/replication/status",
+        "/replication/promote",
+        "/hooks/github/queue",
+        "/repos/refresh",
+        "/reports/changes/release",
+    ]
+    .iter()
+    .any(|prefix| path == *prefix || path.starts_with(&format!("{prefix}/")))
+}
+
+/// Whether `address` is in one of the networks in `allowlist`, which are CIDR ranges or single
+/// addresses. Entries that are neither are ignored, config validation rejects them.
+pub fn is_allowed(allowlist: &[String], address: IpAddr) -> bool {
+    allowlist.iter().any(|network| {
+        network.parse::<IpNet>().map_or_else(
+            |_| network.parse::<IpAddr>() == Ok(address),
+            |network| network.contains(&address),
+        )
+    })
+}
+
+/// The address a request came from. That's the peer connected to Hyde, unless it's one of
+/// `trusted_proxies`, in which case it's the rightmost address in `X-Forwarded-For` (or
+/// `X-Real-IP` without it) that isn't a trusted proxy, since every proxy appends the address it
+/// got the request from and anything left of that could've been made up by the client. `None`
+/// if the proxies reported an address that can't be parsed.
+pub fn resolve_client_ip(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    trusted_proxies: &[String],
+) -> Option<IpAddr> {
+    if !is_allowed(trusted_proxies, peer) {
+        return Some(peer);
+    }
+    let mut forwarded = headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .flat_map(|h| {
+            h.to_str()
+                .map_or_else(|_| vec![""], |h| h.split(',').collect())
+        })
+        .collect::<Vec<_>>();
+    if forwarded.is_empty() {
+        forwarded.extend(
+            headers
+                .get(REAL_IP_HEADER)
+                .map(|h| h.to_str().unwrap_or("")),
+        );
+    }
+    for address in forwarded.iter().rev() {
+        let address = address.trim().parse::<IpAddr>().ok()?;
+        if !is_allowed(trusted_proxies, address) {
+            return Some(address);
+        }
+    }
+    // Every hop was a trusted proxy, so the request was made by one of them
+    forwarded
+        .first()
+        .map_or(Some(peer), |a| a.trim().parse().ok())
+}
+
+/// Middleware resolving the address a request came from (see [`resolve_client_ip`]) and
+/// passing it on in `X-Real-IP`, replacing the forwarding headers the client sent, so that
+/// every handler reading [`crate::handlers_prelude::client_ip`] gets an address it can trust
+pub async fn resolve_client_address(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let address = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .and_then(|ConnectInfo(peer)| {
+            resolve_client_ip(
+                peer.ip(),
+                request.headers(),
+                &state.config.auth.trusted_proxies,
+            )
+        });
+    let headers = request.headers_mut();
+    headers.remove(FORWARDED_FOR_HEADER);
+    headers.remove(REAL_IP_HEADER);
+    if let Some(address) = address {
+        headers.insert(
+            REAL_IP_HEADER,
+            HeaderValue::from_str(&address.to_string()).expect("IP addresses are valid headers"),
+        );
+    }
+    next.run(request).await
+}
+
+/// Middleware turning away requests to admin endpoints from outside `auth.admin_allowlist`.
+/// It runs before the permission check, so even admins are turned away.
+pub async fn admin_ip_allowlist(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let allowlist = &state.config.auth.admin_allowlist;
+    if allowlist.is_empty() || !is_admin_path(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let address = request_ip(&request);
+    if address
+        .as_deref()
+        .and_then(|a| a.parse::<IpAddr>().ok())
+        .is_some_and(|a| is_allowed(allowlist, a))
+    {
+        return next.run(request).await;
+    }
+    warn!(
+        "{address:?} was turned away from {}, it's not in the admin allowlist",
+        request.uri().path()
+    );
+    (
+        StatusCode::FORBIDDEN,
+        "Admin endpoints can't be reached from this address.",
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlist() {
+        let allowlist = vec!["10.0.0.0/8".to_string(), "192.168.1.5".to_string()];
+        assert!(is_allowed(&allowlist, "10.1.2.3".parse().unwrap()));
+        assert!(is_allowed(&allowlist, "192.168.1.5".parse().unwrap()));
+        assert!(!is_allowed(&allowlist, "192.168.1.6".parse().unwrap()));
+        assert!(!is_allowed(&allowlist, "::1".parse().unwrap()));
+        assert!(is_admin_path("/groups/3/permissions"));
+        assert!(is_admin_path("/reclone"));
+        assert!(is_admin_path("/permissions/denies/4"));
+        assert!(is_admin_path("/admin/webhooks"));
+        for path in [
+            "/replication/status",
+            "/replication/promote",
+            "/hooks/github/queue",
+            "/repos/refresh",
+            "/reports/changes/release",
+        ] {
+            assert!(is_admin_path(path), "is_admin_path: {path} is admin only");
+        }
+        assert!(!is_admin_path("/hooks/github"));
+        assert!(!is_admin_path("/reports/changes"));
+        assert!(!is_admin_path("/permissions"));
+        assert!(
+            !is_admin_path("/users/me"),
+            "is_admin_path: every user can manage their own account"
+        );
+        assert!(!is_admin_path("/users/me/totp"));
+        assert!(!is_admin_path("/doc"));
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(FORWARDED_FOR_HEADER, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn client_addresses() {
+        let proxies = vec!["10.0.0.0/8".to_string()];
+        let allowlist = vec!["10.0.0.1".to_string()];
+        let attacker: IpAddr = "203.0.113.7".parse().unwrap();
+        let spoofed = resolve_client_ip(attacker, &forwarded_for("10.0.0.1"), &proxies);
+        assert_eq!(
+            spoofed,
+            Some(attacker),
+            "resolve_client_ip: forwarding headers from untrusted peers should be ignored"
+        );
+        assert!(!is_allowed(&allowlist, spoofed.unwrap()));
+        assert_eq!(
+            resolve_client_ip(attacker, &forwarded_for("10.0.0.1"), &[]),
+            Some(attacker),
+            "resolve_client_ip: no peer should be trusted by default"
+        );
+
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(
+            resolve_client_ip(
+                proxy,
+                &forwarded_for("10.0.0.1, 203.0.113.7, 10.0.0.3"),
+                &proxies
+            ),
+            Some(attacker),
+            "resolve_client_ip: should take the rightmost address that isn't a trusted proxy"
+        );
+        assert_eq!(
+            resolve_client_ip(proxy, &HeaderMap::new(), &proxies),
+            Some(proxy)
+        );
+        let mut real_ip = HeaderMap::new();
+        real_ip.insert(REAL_IP_HEADER, "203.0.113.7".parse().unwrap());
+        assert_eq!(resolve_client_ip(proxy, &real_ip, &proxies), Some(attacker));
+        assert_eq!(
+            resolve_client_ip(proxy, &forwarded_for("10.0.0.1, 10.0.0.3"), &proxies),
+            Some("10.0.0.1".parse().unwrap())
+        );
+        assert_eq!(
+            resolve_client_ip(proxy, &forwarded_for("nonsense"), &proxies),
+            None
+        );
+    }
+}
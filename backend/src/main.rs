@@ -2,45 +2,85 @@
 // While it would be ideal if this wasn't an issue, we don't have the dev team to do this
 #![allow(clippy::multiple_crate_versions)]
 // A lot of database methods have been preemptively implemented
+mod ai_assist;
+mod api_tokens;
 mod app_conf;
+mod audit;
+mod bootstrap;
+mod branch_rules;
+mod changelog;
+mod cleanup;
+mod codeowners;
 #[allow(dead_code)]
 mod db;
+mod device_flow;
+mod discord_roles;
+mod doc_cache;
+mod front_matter;
 mod gh;
+mod gh_fixtures;
 pub mod git;
 mod handlers_prelude;
+mod identity;
+mod image_metadata;
+mod ip_allowlist;
 pub mod perms;
+mod plugins;
+mod policy;
+mod rate_limit;
+mod related;
+mod render;
+mod replica;
+mod reports;
+mod search;
+mod stale_content;
+mod telemetry;
+mod token_crypto;
+mod totp;
+mod webhook_queue;
 
 use axum::{
-    extract::MatchedPath,
-    http::{HeaderValue, Request},
+    extract::{Extension, MatchedPath, State},
+    http::{header::HOST, HeaderValue, Request},
+    middleware,
     response::Response,
     Router,
 };
 use clap::{
     builder::{PossibleValuesParser, TypedValueParser},
-    Parser,
+    Parser, Subcommand,
 };
-use color_eyre::eyre::Context;
+use color_eyre::eyre::{bail, Context};
 use color_eyre::Result;
-use db::Database;
-use gh::GitHubClient;
+use db::{Database, DatabaseUrl};
+use doc_cache::DocCache;
+use gh::{GitHubClient, RepoListCache, RepoMetadataCache};
+use gh_fixtures::Fixtures;
 use handlers_prelude::*;
+use jsonwebtoken::EncodingKey;
 use oauth2::{
     basic::BasicClient, AuthUrl, ClientId, ClientSecret, EndpointNotSet, EndpointSet, TokenUrl,
 };
 use reqwest::{
-    header::{ACCEPT, ALLOW, CONTENT_TYPE},
+    header::{HeaderName, ACCEPT, ALLOW, CONTENT_TYPE},
     Client, Method,
 };
+use std::collections::HashMap;
 use std::env::current_exe;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::LazyLock;
 use std::time::Duration;
+use token_crypto::TokenCipher;
+use totp::TOTP_HEADER;
 use tracing::{debug, info, info_span, warn};
 use tracing::{Level, Span};
+use webhook_queue::WebhookQueue;
 
 use crate::app_conf::AppConf;
 use tokio::task;
+use tower::{ServiceBuilder, ServiceExt};
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tower_http::{normalize_path::NormalizePathLayer, services::ServeDir};
@@ -58,7 +98,25 @@ pub struct AppState {
     oauth: BasicClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointSet>,
     reqwest_client: Client,
     gh_client: GitHubClient,
+    repo_metadata: RepoMetadataCache,
+    repo_lists: RepoListCache,
     db: Database,
+    ai_rate_limiter: rate_limit::RateLimiter,
+    /// Limits sign in attempts by IP address
+    auth_rate_limiter: rate_limit::RateLimiter<String>,
+    /// Locks users out of entering TOTP codes after too many wrong ones
+    totp_failures: rate_limit::FailureBackoff,
+    doc_cache: DocCache,
+    webhook_queue: WebhookQueue,
+    plugins: plugins::PluginRegistry,
+    replica: replica::Replica,
+    /// Held while a user's Discord token is refreshed
+    discord_refresh_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Where database backups are written, the `backups/` folder next to the SQLite database
+    backup_dir: PathBuf,
+    /// The folder the SQLite database is in, where replication snapshots are written while
+    /// they're transferred
+    data_dir: PathBuf,
 }
 
 #[derive(Parser, Debug)]
@@ -81,6 +139,32 @@ struct Args {
         default_value_t = String::from("hyde-data/"),
     )]
     cfg: String,
+    #[arg(
+        long = "restore",
+        help = "Replace the database with a backup taken with `POST /api/admin/backup` before starting."
+    )]
+    restore: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Apply the database migrations of this wiki and its tenants, then exit without starting
+    /// the server. They're also applied when the server starts.
+    Migrate {
+        #[arg(
+            long,
+            help = "List the migrations that would be applied, without applying them."
+        )]
+        dry_run: bool,
+        #[arg(
+            long,
+            value_name = "VERSION",
+            help = "Only apply the migrations up to and including this version."
+        )]
+        to: Option<i64>,
+    },
 }
 
 #[tokio::main]
@@ -107,12 +191,43 @@ async fn main() -> Result<()> {
         );
     }
 
+    // Set up the data directory before the config is loaded from it
+    bootstrap::init_config_dir(&cli_args.cfg)?;
+
+    if let Some(Command::Migrate { dry_run, to }) = cli_args.command {
+        return migrate(dry_run, to).await;
+    }
+
+    if let Some(backup) = &cli_args.restore {
+        let database_url = DatabaseUrl::parse(&CONFIG.database.url);
+        let Some(database_path) = database_url.sqlite_file() else {
+            bail!("--restore only restores SQLite database files, use pg_restore with PostgreSQL");
+        };
+        let settings = CONFIG.database.sqlite_settings();
+        let replaced = Database::restore_backup(database_path, backup, &settings)
+            .await
+            .wrap_err("Failed to restore the database backup")?;
+        info!(
+            "Restored the database from {backup:?}{}",
+            replaced.map_or_else(String::new, |p| format!(
+                ", the replaced database was moved to {p:?}"
+            ))
+        );
+    }
+
     // Initialize app and config
-    let state: AppState = init_state(&cli_args)
+    let state: AppState = init_state(&CONFIG)
         .await
         .wrap_err("Failed to initialize app state")?;
+    let tenants = init_tenants().await?;
 
     debug!("Initialized app state");
+    replica::Replica::spawn_sync(state.clone());
+    spawn_writers(&state);
+    for tenant in &tenants {
+        replica::Replica::spawn_sync(tenant.state.clone());
+        spawn_writers(&tenant.state);
+    }
     // https://github.com/r-Techsupport/hyde/issues/27
     // In docker, because the process is running with a PID of 1,
     // we need to implement our own SIGINT/TERM handlers
@@ -131,90 +246,452 @@ async fn main() -> Result<()> {
             });
         }
     }
+    #[cfg(not(target_family = "unix"))]
+    {
+        use tracing::error;
+        debug!("Non-unix environment detected, starting Ctrl+C handler");
+        task::spawn(async {
+            tokio::signal::ctrl_c()
+                .await
+                .expect("Failed to initialize a Ctrl+C handler");
+            error!("Ctrl+C received, terminating.");
+            std::process::exit(0);
+        });
+    }
 
-    start_server(state, cli_args).await?;
+    start_server(state, tenants, cli_args).await?;
     Ok(())
 }
 
-/// Initialize an instance of [`AppState`]
-#[tracing::instrument]
-async fn init_state(cli_args: &Args) -> Result<AppState> {
-    let repo_url = CONFIG.files.repo_url.clone();
-    let repo_path = CONFIG.files.repo_path.clone();
-    let docs_path = CONFIG.files.docs_path.clone();
-    let asset_path = CONFIG.files.asset_path.clone();
-
-    let git =
-        task::spawn(async { git::Interface::new(repo_url, repo_path, docs_path, asset_path) })
-            .await??;
+/// `hyde migrate`: apply (or with `dry_run`, list) the pending migrations of every wiki's
+/// database
+async fn migrate(dry_run: bool, to: Option<i64>) -> Result<()> {
+    let mut wikis = vec![("this wiki".to_string(), &**CONFIG)];
+    wikis.extend(
+        CONFIG
+            .tenants
+            .iter()
+            .map(|tenant| format!("tenant {:?}", tenant.name))
+            .zip(load_tenant_configs()?),
+    );
+    for (wiki, config) in wikis {
+        let database_url = DatabaseUrl::parse(&config.database.url);
+        if let Some(path) = database_url.sqlite_file() {
+            bootstrap::init_database_file(&path.to_string_lossy())?;
+        }
+        let settings = config.database.sqlite_settings();
+        let db = Database::connect_unmigrated(&database_url.connect_url(), &settings).await?;
+        let migrations = db
+            .migrate(to, dry_run)
+            .await
+            .wrap_err_with(|| format!("Failed to migrate the database of {wiki}"))?;
+        db.close().await;
+        if migrations.is_empty() {
+            info!("The database of {wiki} is up to date");
+            continue;
+        }
+        let verb = if dry_run { "Would apply" } else { "Applied" };
+        for migration in &migrations {
+            info!(
+                "{verb} migration {} ({}) to the database of {wiki}",
+                migration.version, migration.description
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Start the background tasks that change the database or the repository. Replicas don't run
+/// them until they're promoted, since everything they'd change is replaced with the primary's.
+pub fn spawn_writers(state: &AppState) {
+    if state.replica.role(state.config) == replica::Role::Replica {
+        return;
+    }
+    WebhookQueue::spawn_worker(state.clone());
+    spawn_legacy_discord_linking(state.clone());
+    cleanup::spawn(state.clone());
+    search::spawn_rebuild(state.clone());
+    stale_content::spawn(state.clone());
+}
+
+/// Link the Discord accounts of users from before accounts were linked in the background, since
+/// it takes a request to Discord per user
+fn spawn_legacy_discord_linking(state: AppState) {
+    task::spawn(async move {
+        match link_legacy_discord_users(&state).await {
+            Ok(0) => {}
+            Ok(linked) => {
+                info!("Linked the Discord accounts of {linked} users by their Discord id")
+            }
+            Err(e) => warn!("Failed to link the Discord accounts of existing users: {e:?}"),
+        }
+    });
+}
+
+/// Another wiki served by this process, see `[[tenants]]` in the config
+struct TenantState {
+    hostnames: Vec<String>,
+    state: AppState,
+}
+
+/// Load the config of every tenant and initialize their [`AppState`]s
+async fn init_tenants() -> Result<Vec<TenantState>> {
+    let mut tenants = Vec::new();
+    for (tenant, config) in CONFIG.tenants.iter().zip(load_tenant_configs()?) {
+        let state = init_state(config)
+            .await
+            .wrap_err_with(|| format!("Failed to initialize tenant {:?}", tenant.name))?;
+        info!(
+            "Tenant {:?} initialized, serving {:?}",
+            tenant.name, tenant.hostnames
+        );
+        tenants.push(TenantState {
+            hostnames: tenant.hostnames.clone(),
+            state,
+        });
+    }
+    Ok(tenants)
+}
+
+/// Load the config of every tenant, in the order they're listed in `[[tenants]]`
+fn load_tenant_configs() -> Result<Vec<&'static AppConf>> {
+    let mut configs = Vec::new();
+    let mut repo_paths = vec![CONFIG.files.repo_path.clone()];
+    for tenant in &CONFIG.tenants {
+        let data_dir = tenant.data_dir();
+        bootstrap::init_config_dir(&data_dir)?;
+        let config = AppConf::load(data_dir.as_str())
+            .wrap_err_with(|| format!("Failed to load the config of tenant {:?}", tenant.name))?;
+        if !config.tenants.is_empty() {
+            bail!(
+                "The config of tenant {:?} has tenants of its own, tenants can't be nested",
+                tenant.name
+            );
+        }
+        if repo_paths.contains(&config.files.repo_path) {
+            bail!(
+                "Tenant {:?} uses the same `files.repo_path` as another wiki, every tenant needs its own repository folder",
+                tenant.name
+            );
+        }
+        repo_paths.push(config.files.repo_path.clone());
+        let mut config = Arc::unwrap_or_clone(config);
+        // Every tenant has its own GitHub App, so the key is looked for in the tenant's data
+        // directory unless it's been set explicitly
+        if config.oauth.github.private_key_path == app_conf::default_private_key_path() {
+            config.oauth.github.private_key_path = Path::new(&data_dir)
+                .join("key.pem")
+                .to_string_lossy()
+                .to_string();
+        }
+        // Likewise for the database. The url older config templates had counts as unset too.
+        if DatabaseUrl::parse(&config.database.url)
+            == DatabaseUrl::parse(&app_conf::default_database_url())
+        {
+            config.database.url = Path::new(&data_dir)
+                .join("data.db")
+                .to_string_lossy()
+                .to_string();
+        }
+        if config.database.token_key_path == app_conf::default_token_key_path() {
+            config.database.token_key_path = Path::new(&data_dir)
+                .join("token.key")
+                .to_string_lossy()
+                .to_string();
+        }
+        // Tenants live as long as the process, like `CONFIG`
+        configs.push(&*Box::leak(Box::new(config)));
+    }
+    Ok(configs)
+}
+
+/// Initialize an instance of [`AppState`] for the wiki configured by `config`
+#[tracing::instrument(skip(config))]
+async fn init_state(config: &'static AppConf) -> Result<AppState> {
+    let fixtures = config
+        .oauth
+        .github
+        .fixtures
+        .as_ref()
+        .map(|conf| Fixtures::load(conf.mode, &conf.path))
+        .transpose()?;
+    // Checked first so a missing or invalid key fails startup instead of the first push.
+    // Requests aren't signed when they're replayed, so the key isn't needed.
+    let private_key = if fixtures.as_ref().is_some_and(Fixtures::is_replay) {
+        warn!("Replaying recorded GitHub API responses, no requests will be sent to GitHub");
+        EncodingKey::from_secret(&[])
+    } else {
+        bootstrap::check_private_key(&config.oauth.github.private_key_path)?;
+        gh::load_private_key(&config.oauth.github.private_key_path)?
+    };
+    if config.oauth.github.webhook_secret.is_none() && config.oauth.github.webhook_token.is_none() {
+        warn!(
+            "Neither a webhook secret nor a webhook token is set, anyone can send webhook events"
+        );
+    }
+    let database_url = DatabaseUrl::parse(&config.database.url);
+    if let Some(path) = database_url.sqlite_file() {
+        bootstrap::init_database_file(&path.to_string_lossy())?;
+    }
+    bootstrap::init_token_key(&config.database.token_key_path)?;
+    let db = Database::new(&database_url, &config.database.sqlite_settings())
+        .await?
+        .with_token_cipher(TokenCipher::load(&config.database.token_key_path)?);
+    let encrypted = db.encrypt_stored_tokens().await?;
+    if encrypted > 0 {
+        info!("Encrypted the stored OAuth tokens of {encrypted} users");
+    }
+    let hashed = db.hash_stored_sessions().await?;
+    if hashed > 0 {
+        info!("Hashed the ids of {hashed} stored sessions");
+    }
+    // In-memory databases use the default database's folder
+    let data_dir = database_url
+        .sqlite_file()
+        .unwrap_or_else(|| Path::new(db::DATABASE_PATH))
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .to_path_buf();
+    let repo_url = config.files.repo_url.clone();
+    let repo_path = config.files.repo_path.clone();
+    let docs_path = config.files.docs_path.clone();
+    let asset_path = config.files.asset_path.clone();
+    let archive_path = config.files.archive_path.clone();
+    let plugins = plugins::PluginRegistry::new(&config.plugins)?;
+
+    let git_plugins = plugins.clone();
+
+    let git = task::spawn(async {
+        git::Interface::new(
+            repo_url,
+            repo_path,
+            docs_path,
+            asset_path,
+            archive_path,
+            git::CommitIdentities {
+                committer: config.commit.clone(),
+                bot: config.bot_identity(),
+            },
+            git_plugins,
+        )
+    })
+    .await??;
     let reqwest_client = Client::new();
 
-    let oauth = BasicClient::new(ClientId::new(CONFIG.oauth.discord.client_id.clone()))
-        .set_client_secret(ClientSecret::new(CONFIG.oauth.discord.secret.clone()))
-        .set_auth_uri(AuthUrl::new(CONFIG.oauth.discord.url.clone())?)
-        .set_token_uri(TokenUrl::new(CONFIG.oauth.discord.token_url.clone())?);
+    let mut gh_client = GitHubClient::new(
+        config.files.repo_url.clone(),
+        reqwest_client.clone(),
+        config.oauth.github.client_id.clone(),
+        config.oauth.github.installation_id,
+        private_key,
+    );
+    if let Some(fixtures) = fixtures {
+        gh_client = gh_client.with_fixtures(fixtures);
+    }
+    // If this fails, it's fetched again the first time it's needed
+    let repo_metadata = RepoMetadataCache::default();
+    if let Err(e) = repo_metadata.refresh(&gh_client).await {
+        warn!("Failed to fetch repository metadata from GitHub: {e:?}");
+    }
+
+    let oauth = BasicClient::new(ClientId::new(config.oauth.discord.client_id.clone()))
+        .set_client_secret(ClientSecret::new(config.oauth.discord.secret.clone()))
+        .set_auth_uri(AuthUrl::new(config.oauth.discord.url.clone())?)
+        .set_token_uri(TokenUrl::new(config.oauth.discord.token_url.clone())?);
 
     Ok(AppState {
-        config: &CONFIG,
+        config,
         git,
         oauth,
         reqwest_client: reqwest_client.clone(),
-        gh_client: GitHubClient::new(
-            CONFIG.files.repo_url.clone(),
-            reqwest_client.clone(),
-            CONFIG.oauth.github.client_id.clone(),
+        gh_client,
+        repo_metadata,
+        repo_lists: RepoListCache::default(),
+        db,
+        doc_cache: DocCache::new(&config.files.docs_path),
+        webhook_queue: WebhookQueue::default(),
+        plugins,
+        replica: replica::Replica::default(),
+        discord_refresh_lock: Arc::default(),
+        backup_dir: data_dir.join("backups"),
+        data_dir,
+        auth_rate_limiter: rate_limit::RateLimiter::new(
+            rate_limit::AUTH_RATE_LIMIT_WINDOW,
+            config.auth.requests_per_minute,
+        ),
+        totp_failures: rate_limit::FailureBackoff::new(
+            totp::FREE_FAILURES,
+            totp::BASE_LOCKOUT,
+            totp::MAX_LOCKOUT,
+        ),
+        ai_rate_limiter: rate_limit::RateLimiter::new(
+            AI_ASSIST_RATE_LIMIT_WINDOW,
+            config
+                .ai_assist
+                .as_ref()
+                .map_or(0, |conf| conf.requests_per_hour),
         ),
-        db: Database::new().await?,
     })
 }
 
-async fn start_server(state: AppState, cli_args: Args) -> Result<()> {
+/// The routes of every wiki served by this process, see [`route_by_hostname`]
+#[derive(Clone)]
+struct WikiRouters {
+    /// Serves requests that don't match any tenant
+    default: Router,
+    by_hostname: Arc<HashMap<String, Router>>,
+}
+
+/// Pass a request on to the wiki its `Host` header belongs to
+async fn route_by_hostname(
+    State(routers): State<WikiRouters>,
+    request: axum::extract::Request,
+) -> Response {
+    let hostname = request
+        .headers()
+        .get(HOST)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.rsplit_once(':').map_or(h, |(hostname, _port)| hostname))
+        .map(str::to_lowercase);
+    let router = hostname
+        .and_then(|h| routers.by_hostname.get(&h))
+        .unwrap_or(&routers.default)
+        .clone();
+    match router.oneshot(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
+
+async fn start_server(state: AppState, tenants: Vec<TenantState>, cli_args: Args) -> Result<()> {
     // files are served relative to the location of the executable, not where the
     // executable was run from
     let mut frontend_dir = current_exe()?;
     // current_exe returns the path of the file, we need the dir the file is in
     frontend_dir.pop();
     frontend_dir.push("web");
-    let config = state.config;
-    let asset_path = &config.files.asset_path;
 
     // Initialize the handler and router
     let api_routes = Router::new()
         .merge(create_oauth_route().await)
         .merge(create_user_route().await)
         .merge(create_group_route().await)
+        .merge(create_permissions_route().await)
+        .merge(create_api_tokens_route().await)
+        .merge(create_login_route().await)
+        .merge(create_audit_log_route().await)
+        .merge(create_whoami_route().await)
+        .merge(create_device_route().await)
+        .merge(create_service_accounts_route().await)
+        .merge(create_sessions_route().await)
+        .merge(create_second_factor_route().await)
         .merge(create_logout_route().await)
         .merge(create_reclone_route().await)
         .merge(create_github_route().await)
         .merge(create_tree_route().await)
+        .merge(create_owners_route().await)
+        .merge(create_ticket_route().await)
+        .merge(create_drafts_route().await)
+        .merge(create_preferences_route().await)
+        .merge(create_backups_route().await)
+        .merge(create_changelog_route().await)
+        .merge(create_reports_route().await)
+        .merge(create_replication_route().await)
+        .merge(create_telemetry_route().await)
+        .merge(create_ai_assist_route().await)
+        .merge(create_propose_route().await)
+        .merge(create_github_link_route().await)
+        .merge(create_health_route().await)
         .merge(github_routes().await);
 
-    let app = Router::new()
-        .nest("/api", api_routes)
-        .layer(if cfg!(debug_assertions) {
-            CorsLayer::new()
-                // If this isn't set, cookies won't be sent across ports
-                .allow_credentials(true)
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>()?)
-                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-                .allow_headers([ALLOW, ACCEPT, CONTENT_TYPE])
-        } else {
-            CorsLayer::new()
-                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-                .allow_headers([ALLOW, ACCEPT, CONTENT_TYPE])
-        })
-        .with_state(state)
-        // Serve the assets folder from the repo
-        .nest_service(
-            &format!("/{asset_path}"),
-            ServeDir::new(format!("repo/{asset_path}")),
-        )
-        // Serve the frontend files
-        .fallback_service(
-            ServeDir::new(frontend_dir)
-                .precompressed_br()
-                .precompressed_gzip(),
-        )
+    let wiki_routes = |state: AppState| -> Result<Router> {
+        let files = &state.config.files;
+        Ok(Router::new()
+            .nest(
+                "/api",
+                api_routes
+                    .clone()
+                    .layer(middleware::from_fn_with_state(
+                        state.clone(),
+                        replica::read_only_guard,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        state.clone(),
+                        rate_limit::auth_rate_limit,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        state.clone(),
+                        ip_allowlist::admin_ip_allowlist,
+                    ))
+                    // For the permission middleware, see `require_sign_in`
+                    .layer(Extension(state.clone())),
+            )
+            .layer(if cfg!(debug_assertions) {
+                CorsLayer::new()
+                    // If this isn't set, cookies won't be sent across ports
+                    .allow_credentials(true)
+                    .allow_origin("http://localhost:5173".parse::<HeaderValue>()?)
+                    .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+                    .allow_headers([
+                        ALLOW,
+                        ACCEPT,
+                        CONTENT_TYPE,
+                        HeaderName::from_static(TOTP_HEADER),
+                    ])
+            } else {
+                CorsLayer::new()
+                    .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+                    .allow_headers([
+                        ALLOW,
+                        ACCEPT,
+                        CONTENT_TYPE,
+                        HeaderName::from_static(TOTP_HEADER),
+                    ])
+            })
+            // Serve the assets folder from the repo
+            .nest_service(
+                &format!("/{}", files.asset_path),
+                ServiceBuilder::new()
+                    .layer(middleware::from_fn_with_state(
+                        state.clone(),
+                        read_access_guard,
+                    ))
+                    .service(ServeDir::new(
+                        Path::new(&files.repo_path).join(&files.asset_path),
+                    )),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                ip_allowlist::resolve_client_address,
+            ))
+            .with_state(state)
+            // Serve the frontend files
+            .fallback_service(
+                ServeDir::new(&frontend_dir)
+                    .precompressed_br()
+                    .precompressed_gzip(),
+            ))
+    };
+
+    let app = if tenants.is_empty() {
+        wiki_routes(state)?
+    } else {
+        let mut by_hostname = HashMap::new();
+        for tenant in tenants {
+            let routes = wiki_routes(tenant.state)?;
+            for hostname in tenant.hostnames {
+                by_hostname.insert(hostname.to_lowercase(), routes.clone());
+            }
+        }
+        Router::new()
+            .fallback(route_by_hostname)
+            .with_state(WikiRouters {
+                default: wiki_routes(state)?,
+                by_hostname: Arc::new(by_hostname),
+            })
+    };
+
+    let app = app
         // Enable support for routes that have or don't have a trailing slash
         .layer(NormalizePathLayer::trim_trailing_slash())
         // https://github.com/tokio-rs/axum/blob/main/examples/tracing-aka-logging/src/main.rs
@@ -255,6 +732,10 @@ async fn start_server(state: AppState, cli_args: Args) -> Result<()> {
     };
     let listener = tokio::net::TcpListener::bind(&address).await?;
     info!("Application starting, listening at {:?}", address);
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
     unreachable!();
 }
@@ -2,12 +2,104 @@
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
 pub enum Permission {
     ManageContent, // TODO
     ManageUsers,
     ManageBranches,
-    // TODO: Submit for review
+    /// Save documents as pull requests, rather than straight to the default branch
+    SubmitForReview,
+    /// Upload and delete assets, which can be much larger than documents
+    ManageAssets,
+    /// Read documents and assets, only required if `auth.require_read_permission` is set
+    ReadContent,
+    /// Maintain the local repository: reclone it, refresh its metadata and check on the
+    /// webhook queue that keeps it up to date
+    ManageRepo,
+    /// A permission declared in `auth.custom_permissions`, which Hyde stores and checks but
+    /// doesn't use itself, so deployments can gate their own features with it
+    Custom(String),
+}
+
+/// Permissions as const generic arguments, for
+/// [`crate::handlers_prelude::RequirePermission`]
+///
+/// Custom permissions can't be used as const generic arguments, check them with
+/// [`crate::handlers_prelude::require_perms`] instead.
+pub mod required {
+    pub const MANAGE_CONTENT: u8 = 0;
+    pub const MANAGE_USERS: u8 = 1;
+    pub const MANAGE_BRANCHES: u8 = 2;
+    pub const SUBMIT_FOR_REVIEW: u8 = 3;
+    pub const MANAGE_ASSETS: u8 = 4;
+    pub const READ_CONTENT: u8 = 5;
+    pub const MANAGE_REPO: u8 = 6;
+}
+
+impl Permission {
+    /// Every permission built into Hyde
+    pub const BUILT_IN: [Self; 7] = [
+        Self::ManageContent,
+        Self::ManageUsers,
+        Self::ManageBranches,
+        Self::SubmitForReview,
+        Self::ManageAssets,
+        Self::ReadContent,
+        Self::ManageRepo,
+    ];
+
+    /// The permission with the id `id`, see [`required`]
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            required::MANAGE_CONTENT => Some(Self::ManageContent),
+            required::MANAGE_USERS => Some(Self::ManageUsers),
+            required::MANAGE_BRANCHES => Some(Self::ManageBranches),
+            required::SUBMIT_FOR_REVIEW => Some(Self::SubmitForReview),
+            required::MANAGE_ASSETS => Some(Self::ManageAssets),
+            required::READ_CONTENT => Some(Self::ReadContent),
+            required::MANAGE_REPO => Some(Self::ManageRepo),
+            _ => None,
+        }
+    }
+
+    /// The name shown to admins
+    pub fn label(&self) -> &str {
+        match self {
+            Self::ManageContent => "Manage Content",
+            Self::ManageUsers => "Manage Users",
+            Self::ManageBranches => "Manage Branches",
+            Self::SubmitForReview => "Submit For Review",
+            Self::ManageAssets => "Manage Assets",
+            Self::ReadContent => "Read Content",
+            Self::ManageRepo => "Manage Repository",
+            Self::Custom(name) => name,
+        }
+    }
+
+    /// What the permission lets its holders do, shown to admins
+    pub const fn description(&self) -> &'static str {
+        match self {
+            Self::ManageContent => "Edit, delete and archive documents, and open pull requests",
+            Self::ManageUsers => "Manage users, groups and permissions, and read the audit log",
+            Self::ManageBranches => "Close pull requests and publish releases",
+            Self::SubmitForReview => {
+                "Propose changes to documents, which are saved as pull requests for review"
+            }
+            Self::ManageAssets => "Upload and delete assets",
+            Self::ReadContent => "Read documents and assets, when reading requires a permission",
+            Self::ManageRepo => "Reclone the repository and check on the webhook queue",
+            Self::Custom(_) => "A custom permission declared in the config",
+        }
+    }
+
+    /// Whether the permission is built into Hyde, or one of the custom permissions in `custom`
+    pub fn is_declared(&self, custom: &[String]) -> bool {
+        match self {
+            Self::Custom(name) => custom.contains(name),
+            _ => true,
+        }
+    }
 }
 
 impl From<Permission> for String {
@@ -16,19 +108,64 @@ impl From<Permission> for String {
             Permission::ManageContent => "ManageContent",
             Permission::ManageUsers => "ManageUsers",
             Permission::ManageBranches => "ManageBranches",
+            Permission::SubmitForReview => "SubmitForReview",
+            Permission::ManageAssets => "ManageAssets",
+            Permission::ReadContent => "ReadContent",
+            Permission::ManageRepo => "ManageRepo",
+            Permission::Custom(name) => return name,
         }
         .to_string()
     }
 }
 
-impl TryInto<Permission> for &str {
-    type Error = &'static str;
-    fn try_into(self) -> Result<Permission, Self::Error> {
-        match self {
-            "ManageContent" => Ok(Permission::ManageContent),
-            "ManageUsers" => Ok(Permission::ManageUsers),
-            "ManageBranches" => Ok(Permission::ManageBranches),
-            _ => Err("Not a valid permission level"),
+impl From<&str> for Permission {
+    fn from(value: &str) -> Self {
+        match value {
+            "ManageContent" => Self::ManageContent,
+            "ManageUsers" => Self::ManageUsers,
+            "ManageBranches" => Self::ManageBranches,
+            "SubmitForReview" => Self::SubmitForReview,
+            "ManageAssets" => Self::ManageAssets,
+            "ReadContent" => Self::ReadContent,
+            "ManageRepo" => Self::ManageRepo,
+            custom => Self::Custom(custom.to_string()),
         }
     }
 }
+
+impl From<String> for Permission {
+    fn from(value: String) -> Self {
+        value.as_str().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permission_names() {
+        assert_eq!(Permission::from("ManageUsers"), Permission::ManageUsers);
+        assert_eq!(
+            Permission::from("ViewAnalytics"),
+            Permission::Custom(String::from("ViewAnalytics")),
+            "Permission::from: unknown names should be custom permissions"
+        );
+        assert_eq!(
+            String::from(Permission::Custom(String::from("ViewAnalytics"))),
+            "ViewAnalytics"
+        );
+        assert_eq!(
+            serde_json::to_string(&vec![
+                Permission::ReadContent,
+                Permission::Custom(String::from("ViewAnalytics"))
+            ])
+            .unwrap(),
+            r#"["ReadContent","ViewAnalytics"]"#
+        );
+        let custom = [String::from("ViewAnalytics")];
+        assert!(Permission::from("ViewAnalytics").is_declared(&custom));
+        assert!(!Permission::from("Unknown").is_declared(&custom));
+        assert!(Permission::ManageContent.is_declared(&[]));
+    }
+}
@@ -0,0 +1,99 @@
+//! An example plugin: warn about documents that skip heading levels (like a `###` directly
+//! under a `#`), which breaks the page outline for screen readers and the table of contents.
+
+use super::Plugin;
+use crate::app_conf::PolicyAction;
+use crate::policy::{Location, PolicyViolation};
+
+pub const NAME: &str = "heading_levels";
+
+#[derive(Debug)]
+pub struct HeadingLevels;
+
+impl Plugin for HeadingLevels {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn pre_save(&self, path: &str, contents: &[u8]) -> Vec<PolicyViolation> {
+        if !path.ends_with(".md") {
+            return Vec::new();
+        }
+        let Ok(contents) = std::str::from_utf8(contents) else {
+            return Vec::new();
+        };
+        skipped_levels(contents)
+            .into_iter()
+            .map(|(line, level, previous)| PolicyViolation {
+                check: NAME,
+                action: PolicyAction::Warn,
+                message: format!(
+                    "The level {level} heading at line {line} skips a level, the previous heading is level {previous}"
+                ),
+                location: Some(Location { line, column: 1 }),
+            })
+            .collect()
+    }
+}
+
+/// Every ATX heading that's more than one level deeper than the heading before it, as the line
+/// it's on, its level, and the level of the previous heading. Front matter and fenced code
+/// blocks are skipped.
+fn skipped_levels(contents: &str) -> Vec<(usize, usize, usize)> {
+    let mut skipped = Vec::new();
+    let mut previous = None;
+    let mut in_front_matter = false;
+    let mut fence: Option<&str> = None;
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if i == 0 && line == "---" {
+            in_front_matter = true;
+            continue;
+        }
+        if in_front_matter {
+            in_front_matter = line != "---";
+            continue;
+        }
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        let is_heading = (1..=6).contains(&level)
+            && trimmed[level..]
+                .chars()
+                .next()
+                .map_or(true, char::is_whitespace);
+        if !is_heading {
+            continue;
+        }
+        if let Some(previous) = previous {
+            if level > previous + 1 {
+                skipped.push((i + 1, level, previous));
+            }
+        }
+        previous = Some(level);
+    }
+    skipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skipped_heading_levels() {
+        let doc = "---\n# not a heading\n---\n# Foo\n## Bar\n```\n#### code\n```\n#### Baz\n#hashtag\n## Qux\n";
+        assert_eq!(
+            skipped_levels(doc),
+            [(9, 4, 2)],
+            "skipped_levels: front matter, code blocks and hashtags shouldn't count as headings"
+        );
+    }
+}
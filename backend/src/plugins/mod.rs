@@ -0,0 +1,170 @@
+//! Plugins add site-specific behavior (custom lint rules, notifications, et cetera) without
+//! forking Hyde. A plugin implements [`Plugin`], overriding the hooks it needs, and is added to
+//! [`BUILTIN_PLUGINS`]. Plugins are only run if they're listed in `[plugins]` in the config.
+//!
+//! Hooks are called synchronously from the operation that triggered them, so anything slow
+//! (like a network request) should be spawned onto the runtime instead.
+
+mod heading_levels;
+
+use crate::app_conf::Plugins;
+use crate::git::ChangedPath;
+use crate::policy::PolicyViolation;
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use std::fmt::Debug;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Creates an instance of a plugin
+type LoadPlugin = fn() -> Box<dyn Plugin>;
+
+/// Every plugin that can be enabled in the config, by name
+const BUILTIN_PLUGINS: &[(&str, LoadPlugin)] = &[(heading_levels::NAME, || {
+    Box::new(heading_levels::HeadingLevels)
+})];
+
+/// A commit made through Hyde, passed to [`Plugin::post_commit`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitEvent<'a> {
+    pub commit_id: String,
+    /// The branch the commit was pushed to, or `None` if it was pushed to the current branch
+    pub branch: Option<&'a str>,
+    pub message: &'a str,
+    /// The files changed by the commit, relative to the root of the repository
+    pub paths: Vec<String>,
+}
+
+/// Hooks into Hyde's operations. Every hook does nothing by default.
+pub trait Plugin: Send + Sync + Debug {
+    /// The name the plugin is enabled with in the config
+    fn name(&self) -> &'static str;
+
+    /// Called before a document or asset is committed, with the path of the file relative to
+    /// the root of the repository. Violations are handled like content policy checks, so a
+    /// violation with [`PolicyAction::Block`](crate::app_conf::PolicyAction::Block) rejects
+    /// the save.
+    fn pre_save(&self, _path: &str, _contents: &[u8]) -> Vec<PolicyViolation> {
+        Vec::new()
+    }
+
+    /// Called after a commit made through Hyde is pushed.
+    ///
+    /// # Errors
+    /// Errors are logged, the commit has already been pushed.
+    fn post_commit(&self, _event: &CommitEvent) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after changes are pulled from upstream, with every file that changed.
+    ///
+    /// # Errors
+    /// Errors are logged, the changes have already been pulled.
+    fn post_pull(&self, _changes: &[ChangedPath]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called with the HTML rendered from the document at `path` (relative to the root of the
+    /// repository), which the plugin can modify. Documents are currently rendered by the
+    /// frontend, so this is only called for documents Hyde renders itself. The output is cached
+    /// until the document or the enabled plugins change, so it shouldn't depend on anything
+    /// else.
+    fn on_render(&self, _path: &str, _html: &mut String) {}
+}
+
+/// The plugins enabled in the config, which hooks are dispatched to in the order they're listed
+#[derive(Debug, Clone, Default)]
+pub struct PluginRegistry {
+    plugins: Arc<Vec<Box<dyn Plugin>>>,
+}
+
+impl PluginRegistry {
+    /// Load the plugins enabled in `conf`.
+    ///
+    /// # Errors
+    /// This function returns an error if an enabled plugin doesn't exist.
+    pub fn new(conf: &Plugins) -> Result<Self> {
+        let mut plugins = Vec::new();
+        for name in &conf.enabled {
+            let Some((_, load)) = BUILTIN_PLUGINS.iter().find(|(n, _)| n == name) else {
+                let available: Vec<&str> = BUILTIN_PLUGINS.iter().map(|(n, _)| *n).collect();
+                bail!("Unknown plugin {name:?} in `plugins.enabled`, available plugins are {available:?}");
+            };
+            info!("Plugin {name:?} enabled");
+            plugins.push(load());
+        }
+        Ok(Self {
+            plugins: Arc::new(plugins),
+        })
+    }
+
+    /// The names of the enabled plugins, in the order their hooks run
+    pub fn names(&self) -> Vec<&'static str> {
+        self.plugins.iter().map(|p| p.name()).collect()
+    }
+
+    /// Run every plugin's [`Plugin::pre_save`] hook, returning all of their violations
+    pub fn pre_save(&self, path: &str, contents: &[u8]) -> Vec<PolicyViolation> {
+        self.plugins
+            .iter()
+            .flat_map(|p| p.pre_save(path, contents))
+            .collect()
+    }
+
+    /// Run every plugin's [`Plugin::post_commit`] hook
+    pub fn post_commit(&self, event: &CommitEvent) {
+        for plugin in self.plugins.iter() {
+            if let Err(e) = plugin.post_commit(event) {
+                warn!(
+                    "The post-commit hook of plugin {:?} failed: {e:?}",
+                    plugin.name()
+                );
+            }
+        }
+    }
+
+    /// Run every plugin's [`Plugin::post_pull`] hook
+    pub fn post_pull(&self, changes: &[ChangedPath]) {
+        for plugin in self.plugins.iter() {
+            if let Err(e) = plugin.post_pull(changes) {
+                warn!(
+                    "The post-pull hook of plugin {:?} failed: {e:?}",
+                    plugin.name()
+                );
+            }
+        }
+    }
+
+    /// Run every plugin's [`Plugin::on_render`] hook on HTML rendered from the document at
+    /// `path`
+    pub fn on_render(&self, path: &str, mut html: String) -> String {
+        for plugin in self.plugins.iter() {
+            plugin.on_render(path, &mut html);
+        }
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading() {
+        let registry = PluginRegistry::new(&Plugins {
+            enabled: vec![heading_levels::NAME.to_string()],
+        })
+        .unwrap();
+        assert_eq!(
+            registry.pre_save("docs/foo.md", b"# Foo\n### Bar\n").len(),
+            1
+        );
+        assert!(
+            PluginRegistry::new(&Plugins {
+                enabled: vec!["nonexistent".to_string()],
+            })
+            .is_err(),
+            "PluginRegistry::new: unknown plugins should be rejected"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Limiting how often something can be done, like asking the AI assist for suggestions, or
+//! signing in from the same address
+
+use crate::handlers_prelude::request_ip;
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use reqwest::StatusCode;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How long the sign in rate limit's window is
+pub const AUTH_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// Once this many clients are tracked, the ones that haven't made a request within the window
+/// are forgotten
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Limits how many requests each user (or other key, like an IP address) can make within a
+/// sliding window
+#[derive(Clone, Debug)]
+pub struct RateLimiter<K = i64> {
+    window: Duration,
+    max_requests: usize,
+    /// When each user's requests within the window were made, oldest first
+    requests: Arc<Mutex<HashMap<K, VecDeque<Instant>>>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(window: Duration, max_requests: usize) -> Self {
+        Self {
+            window,
+            max_requests,
+            requests: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Record a request from `key`, returning `false` (without recording it)
+    /// if they've already used up their requests for the window.
+    pub fn try_acquire(&self, key: K) -> bool {
+        self.try_acquire_at(key, Instant::now())
+    }
+
+    #[allow(clippy::significant_drop_tightening)]
+    fn try_acquire_at(&self, key: K, now: Instant) -> bool {
+        let mut requests = self.requests.lock().unwrap();
+        if requests.len() >= PRUNE_THRESHOLD {
+            requests.retain(|_, times| {
+                times
+                    .back()
+                    .is_some_and(|&t| now.duration_since(t) < self.window)
+            });
+        }
+        let key_requests = requests.entry(key).or_default();
+        while key_requests
+            .front()
+            .is_some_and(|&t| now.duration_since(t) >= self.window)
+        {
+            key_requests.pop_front();
+        }
+        if key_requests.len() >= self.max_requests {
+            return false;
+        }
+        key_requests.push_back(now);
+        true
+    }
+}
+
+/// Locks keys (like users guessing TOTP codes) out for longer and longer after repeated
+/// failures, so that guessing can't be sped up by spreading it across endpoints
+#[derive(Clone, Debug)]
+pub struct FailureBackoff<K = i64> {
+    /// How many failures in a row are allowed before the first lockout
+    free_failures: u32,
+    /// How long the first lockout is, each one after it is twice as long
+    base_lockout: Duration,
+    max_lockout: Duration,
+    /// How many failures in a row each key has had, and when the last one was
+    failures: Arc<Mutex<HashMap<K, (u32, Instant)>>>,
+}
+
+impl<K: Eq + Hash> FailureBackoff<K> {
+    pub fn new(free_failures: u32, base_lockout: Duration, max_lockout: Duration) -> Self {
+        Self {
+            free_failures,
+            base_lockout,
+            max_lockout,
+            failures: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// How long `key` is still locked out for, if they are
+    pub fn locked_for(&self, key: &K) -> Option<Duration> {
+        self.locked_for_at(key, Instant::now())
+    }
+
+    /// Count a failure against `key`
+    pub fn record_failure(&self, key: K) {
+        self.record_failure_at(key, Instant::now());
+    }
+
+    /// Forget `key`'s failures, after they succeed
+    pub fn reset(&self, key: &K) {
+        self.failures.lock().unwrap().remove(key);
+    }
+
+    fn lockout(&self, failures: u32) -> Option<Duration> {
+        let doublings = failures.checked_sub(self.free_failures + 1)?;
+        Some(
+            self.base_lockout
+                .saturating_mul(2u32.saturating_pow(doublings))
+                .min(self.max_lockout),
+        )
+    }
+
+    fn locked_for_at(&self, key: &K, now: Instant) -> Option<Duration> {
+        let (failures, last) = *self.failures.lock().unwrap().get(key)?;
+        let remaining = self
+            .lockout(failures)?
+            .checked_sub(now.duration_since(last))?;
+        (!remaining.is_zero()).then_some(remaining)
+    }
+
+    #[allow(clippy::significant_drop_tightening)]
+    fn record_failure_at(&self, key: K, now: Instant) {
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() >= PRUNE_THRESHOLD {
+            failures.retain(|_, (_, last)| now.duration_since(*last) < self.max_lockout);
+        }
+        let (count, last) = failures.entry(key).or_insert((0, now));
+        *count += 1;
+        *last = now;
+    }
+}
+
+/// Whether a path (relative to `/api`) signs users in or hands out credentials
+fn is_auth_path(path: &str) -> bool {
+    ["/oauth", "/login", "/tokens"]
+        .iter()
+        .any(|prefix| path == *prefix || path.starts_with(&format!("{prefix}/")))
+}
+
+/// Middleware limiting how often each address (see [`crate::ip_allowlist::resolve_client_ip`])
+/// can use the endpoints that sign users in or hand out credentials, so that they can't be used
+/// to guess tokens or flood the OAuth providers
+pub async fn auth_rate_limit(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.config.auth.requests_per_minute == 0 || !is_auth_path(request.uri().path()) {
+        return next.run(request).await;
+    }
+    // Only happens if a trusted proxy reported an address that can't be parsed. Rejected rather
+    // than sharing one limit, which a single client could use up for everyone.
+    let Some(address) = request_ip(&request) else {
+        warn!(
+            "A request to {} was turned away, its address couldn't be resolved",
+            request.uri().path()
+        );
+        return (
+            StatusCode::BAD_REQUEST,
+            "The address this request came from couldn't be determined.",
+        )
+            .into_response();
+    };
+    if !state.auth_rate_limiter.try_acquire(address.clone()) {
+        warn!("{address:?} was rate limited on {}", request.uri().path());
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many sign in attempts, try again in a minute.",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiting() {
+        let limiter = RateLimiter::new(Duration::from_secs(60), 2);
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at(1, start));
+        assert!(limiter.try_acquire_at(1, start + Duration::from_secs(1)));
+        assert!(
+            !limiter.try_acquire_at(1, start + Duration::from_secs(2)),
+            "try_acquire: should reject requests over the limit"
+        );
+        assert!(
+            limiter.try_acquire_at(2, start + Duration::from_secs(2)),
+            "try_acquire: limits should be per user"
+        );
+        assert!(
+            limiter.try_acquire_at(1, start + Duration::from_secs(60)),
+            "try_acquire: requests outside of the window should be forgotten"
+        );
+    }
+
+    #[test]
+    fn failure_backoff() {
+        let backoff = FailureBackoff::new(2, Duration::from_secs(30), Duration::from_secs(100));
+        let start = Instant::now();
+        backoff.record_failure_at(1, start);
+        backoff.record_failure_at(1, start);
+        assert_eq!(
+            backoff.locked_for_at(&1, start),
+            None,
+            "locked_for: the first failures should be free"
+        );
+        backoff.record_failure_at(1, start);
+        assert_eq!(
+            backoff.locked_for_at(&1, start + Duration::from_secs(10)),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(
+            backoff.locked_for_at(&1, start + Duration::from_secs(30)),
+            None,
+            "locked_for: lockouts should end"
+        );
+        assert_eq!(
+            backoff.locked_for_at(&2, start),
+            None,
+            "locked_for: lockouts should be per user"
+        );
+        backoff.record_failure_at(1, start);
+        assert_eq!(
+            backoff.locked_for_at(&1, start),
+            Some(Duration::from_secs(60)),
+            "locked_for: lockouts should double"
+        );
+        backoff.record_failure_at(1, start);
+        assert_eq!(
+            backoff.locked_for_at(&1, start),
+            Some(Duration::from_secs(100)),
+            "locked_for: lockouts should be capped"
+        );
+        backoff.reset(&1);
+        assert_eq!(backoff.locked_for_at(&1, start), None);
+    }
+
+    #[test]
+    fn auth_paths() {
+        assert!(is_auth_path("/oauth"));
+        assert!(is_auth_path("/oauth/url"));
+        assert!(is_auth_path("/login/github/callback"));
+        assert!(is_auth_path("/tokens/3"));
+        assert!(!is_auth_path("/logout"));
+        assert!(!is_auth_path("/tokensmith"));
+    }
+}
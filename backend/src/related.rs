@@ -0,0 +1,168 @@
+//! Content-based "see also" suggestions, using TF-IDF weighted cosine similarity between documents
+
+use crate::front_matter;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Common words that say nothing about what a document is about
+const STOP_WORDS: &[&str] = &[
+    "about", "after", "all", "also", "and", "any", "are", "because", "been", "before", "but",
+    "can", "could", "did", "does", "for", "from", "has", "have", "how", "into", "its", "just",
+    "may", "more", "most", "not", "now", "only", "other", "our", "out", "should", "some", "such",
+    "than", "that", "the", "their", "them", "then", "there", "these", "they", "this", "those",
+    "through", "too", "use", "very", "was", "were", "what", "when", "where", "which", "while",
+    "who", "why", "will", "with", "would", "you", "your",
+];
+
+/// A page similar to the one that was queried
+#[derive(Debug, Serialize, PartialEq)]
+pub struct RelatedPage {
+    /// The path of the page, relative to the documents folder
+    pub path: String,
+    /// Cosine similarity between the two pages, from 0 (unrelated) to 1 (identical)
+    pub score: f64,
+}
+
+/// TF-IDF vectors for a set of documents
+#[derive(Debug, Default)]
+pub struct RelatedIndex {
+    /// Each document's path, and its normalized term vector
+    docs: Vec<(String, HashMap<String, f64>)>,
+}
+
+impl RelatedIndex {
+    /// Build an index from each document's path and [`term_counts`].
+    pub fn new<I: IntoIterator<Item = (String, TermCounts)>>(docs: I) -> Self {
+        let term_counts: Vec<(String, TermCounts)> = docs.into_iter().collect();
+
+        // How many documents each term appears in
+        let mut doc_frequency: HashMap<&str, usize> = HashMap::new();
+        for (_, counts) in &term_counts {
+            for term in counts.keys() {
+                *doc_frequency.entry(term).or_insert(0) += 1;
+            }
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let doc_count = term_counts.len() as f64;
+        let docs = term_counts
+            .iter()
+            .map(|(path, counts)| {
+                let total: usize = counts.values().sum();
+                #[allow(clippy::cast_precision_loss)]
+                let mut vector: HashMap<String, f64> = counts
+                    .iter()
+                    .map(|(term, &count)| {
+                        let tf = count as f64 / total as f64;
+                        let idf = (doc_count / doc_frequency[term.as_str()] as f64).ln();
+                        (term.clone(), tf * idf)
+                    })
+                    // Terms found in every document have no weight
+                    .filter(|(_, weight)| *weight > 0.0)
+                    .collect();
+                let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+                if norm > 0.0 {
+                    vector.values_mut().for_each(|w| *w /= norm);
+                }
+                (path.clone(), vector)
+            })
+            .collect();
+
+        Self { docs }
+    }
+
+    /// Returns up to `limit` documents most similar to the document at `path`, most similar first.
+    /// Documents that share no meaningful terms with it are left out.
+    ///
+    /// Returns `None` if `path` isn't in the index.
+    pub fn related(&self, path: &str, limit: usize) -> Option<Vec<RelatedPage>> {
+        let (_, target) = self.docs.iter().find(|(p, _)| p == path)?;
+        let mut related: Vec<RelatedPage> = self
+            .docs
+            .iter()
+            .filter(|(p, _)| p != path)
+            .map(|(p, vector)| RelatedPage {
+                path: p.clone(),
+                // Both vectors are normalized, so the dot product is the cosine similarity
+                score: target
+                    .iter()
+                    .filter_map(|(term, weight)| vector.get(term).map(|w| w * weight))
+                    .sum(),
+            })
+            .filter(|page| page.score > 0.0)
+            .collect();
+        related.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.path.cmp(&b.path)));
+        related.truncate(limit);
+        Some(related)
+    }
+}
+
+/// How many times each term appears in a document
+pub type TermCounts = HashMap<String, usize>;
+
+/// Count the terms in a markdown document. This is the expensive part of building
+/// a [`RelatedIndex`], so the result is cached per document.
+pub fn term_counts(contents: &str) -> TermCounts {
+    let mut counts = HashMap::new();
+    for term in tokenize(contents) {
+        *counts.entry(term).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Split a markdown document into lowercase terms, skipping the front matter, short words
+/// and stop words.
+fn tokenize(contents: &str) -> Vec<String> {
+    let stop_words: HashSet<&str> = STOP_WORDS.iter().copied().collect();
+    front_matter::body(contents)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .filter(|word| !stop_words.contains(word.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn related_pages() {
+        let index = RelatedIndex::new(
+            [
+                (
+                    "gpu.md",
+                    "---\nlayout: default\n---\nReinstall your graphics driver with DDU. The GPU driver is broken.",
+                ),
+                (
+                    "drivers.md",
+                    "---\nlayout: default\n---\nUse DDU to remove a graphics driver.",
+                ),
+                (
+                    "ram.md",
+                    "---\nlayout: default\n---\nTest your memory with memtest86.",
+                ),
+            ]
+            .map(|(path, contents)| (path.to_string(), term_counts(contents))),
+        );
+
+        let related = index.related("gpu.md", 5).unwrap();
+        assert_eq!(
+            related.len(),
+            1,
+            "related: should leave out pages that share no terms"
+        );
+        assert_eq!(related[0].path, "drivers.md");
+        assert!(related[0].score > 0.0 && related[0].score <= 1.0);
+        assert!(index.related("gpu.md", 0).unwrap().is_empty());
+        assert!(index.related("missing.md", 5).is_none());
+    }
+
+    #[test]
+    fn tokenizing() {
+        assert_eq!(
+            tokenize("---\ntitle: Foo\n---\nThe quick brown fox, and a CPU!"),
+            ["quick", "brown", "fox", "cpu"]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Rendering documents to HTML on the server, so the viewer and other consumers of the API don't
+//! each need their own markdown renderer.
+//!
+//! Documents are Jekyll pages, so Liquid tags and Kramdown attribute lists are taken out before
+//! the markdown is rendered: the site fills those in when it's built, which Hyde doesn't do. The
+//! rendered HTML is sanitized, since documents can contain raw HTML.
+
+use crate::front_matter;
+use crate::plugins::PluginRegistry;
+use pulldown_cmark::{html, Options, Parser};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Bumped whenever [`render`] changes its output, so HTML cached before isn't used
+const RENDER_VERSION: u32 = 2;
+
+/// A hash of everything besides the document that decides what it's rendered to: this renderer
+/// and the plugins that can modify its output. See [`crate::doc_cache::DocCache::rendered`].
+pub fn config_hash(plugins: &PluginRegistry) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    RENDER_VERSION.hash(&mut hasher);
+    plugins.names().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Render the markdown document `contents` to sanitized HTML. Front matter is dropped, and so are
+/// Liquid tags (`{% include ... %}`), Liquid output (`{{ site.baseurl }}`) and Kramdown
+/// attribute lists (`{: .note }`). Text inside `{% raw %}` blocks is kept as is.
+pub fn render(contents: &str) -> String {
+    let markdown = strip_liquid(front_matter::body(contents));
+    let markdown = strip_attribute_lists(&markdown);
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, Parser::new_ext(&markdown, options));
+    ammonia::clean(&unsafe_html)
+}
+
+/// Remove Liquid tags and output from `markdown`, along with everything inside `{% comment %}`
+/// blocks. The contents of `{% raw %}` blocks are kept, without the tags around them.
+fn strip_liquid(markdown: &str) -> String {
+    let mut output = String::with_capacity(markdown.len());
+    let mut rest = markdown;
+    // Set while inside a block whose end tag is the value, e.g. `endraw`
+    let mut block_end: Option<&str> = None;
+    while let Some(start) = rest.find('{') {
+        let (before, from_brace) = rest.split_at(start);
+        let close = if from_brace.starts_with("{%") {
+            "%}"
+        } else if from_brace.starts_with("{{") && block_end.is_none() {
+            "}}"
+        } else {
+            if block_end != Some("endcomment") {
+                output.push_str(before);
+                output.push('{');
+            }
+            rest = &from_brace[1..];
+            continue;
+        };
+        let Some(end) = from_brace.find(close) else {
+            break;
+        };
+        let tag = from_brace[2..end].trim().trim_matches('-').trim();
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        if block_end != Some("endcomment") {
+            output.push_str(before);
+        }
+        match block_end {
+            Some(end_name) if name == end_name => block_end = None,
+            // Inside a raw block everything is text, tags included
+            Some("endraw") => output.push_str(&from_brace[..end + close.len()]),
+            Some(_) => {}
+            None if close == "%}" && name == "raw" => block_end = Some("endraw"),
+            None if close == "%}" && name == "comment" => block_end = Some("endcomment"),
+            None => {}
+        }
+        rest = &from_brace[end + close.len()..];
+    }
+    if block_end != Some("endcomment") {
+        output.push_str(rest);
+    }
+    output
+}
+
+/// Remove Kramdown inline attribute lists, which are either on a line of their own or at the end
+/// of a line, e.g. `{: .warning }`. Other lines are kept byte for byte, so that trailing spaces
+/// (hard line breaks) survive, and so are code blocks, where attribute lists are just text.
+fn strip_attribute_lists(markdown: &str) -> String {
+    let mut output = String::with_capacity(markdown.len());
+    // The character and length of the fence the open fenced code block started with
+    let mut fence: Option<(char, usize)> = None;
+    let mut in_indented_code = false;
+    let mut previous_blank = true;
+    for line in markdown.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let blank = content.trim().is_empty();
+        if let Some((fence_char, fence_len)) = fence {
+            if code_fence(content).is_some_and(|(c, len, info)| {
+                c == fence_char && len >= fence_len && info.is_empty()
+            }) {
+                fence = None;
+            }
+            output.push_str(line);
+            previous_blank = blank;
+            continue;
+        }
+        if let Some((fence_char, fence_len, _)) = code_fence(content) {
+            fence = Some((fence_char, fence_len));
+            output.push_str(line);
+            previous_blank = false;
+            continue;
+        }
+        if !blank {
+            // Indented code blocks can't interrupt a paragraph
+            let indented = content.starts_with('\t') || content.starts_with("    ");
+            in_indented_code = indented && (previous_blank || in_indented_code);
+        }
+        previous_blank = blank;
+        let trimmed = content.trim_end();
+        match trimmed.rfind("{:") {
+            Some(start) if !in_indented_code && trimmed.ends_with('}') => {
+                let kept = trimmed[..start].trim_end();
+                if kept.trim().is_empty() {
+                    // The line only held an attribute list
+                    continue;
+                }
+                output.push_str(kept);
+                output.push_str(&line[content.len()..]);
+            }
+            _ => output.push_str(line),
+        }
+    }
+    output
+}
+
+/// If `line` opens or closes a fenced code block, the fence's character (`` ` `` or `~`), its
+/// length, and the info string after it
+fn code_fence(line: &str) -> Option<(char, usize, &str)> {
+    let unindented = line.trim_start_matches(' ');
+    if line.len() - unindented.len() > 3 {
+        return None;
+    }
+    let fence_char = unindented
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '`' | '~'))?;
+    let fence_len = unindented.len() - unindented.trim_start_matches(fence_char).len();
+    (fence_len >= 3).then(|| (fence_char, fence_len, unindented[fence_len..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendering() {
+        assert_eq!(
+            render("---\ntitle: Foo\n---\n# Foo\n\nSome *text*\n"),
+            "<h1>Foo</h1>\n<p>Some <em>text</em></p>\n"
+        );
+        assert_eq!(
+            render("Hi<script>alert(1)</script> <a href=\"javascript:alert(1)\">x</a>\n"),
+            "<p>Hi <a rel=\"noopener noreferrer\">x</a></p>\n",
+            "render: should sanitize raw HTML"
+        );
+        assert_eq!(
+            render("{% include archived.html %}\n\nSee [here]({{ site.baseurl }}/foo)\n"),
+            "<p>See <a href=\"/foo\" rel=\"noopener noreferrer\">here</a></p>\n",
+            "render: should strip Liquid tags and output"
+        );
+        assert_eq!(
+            render("Careful\n{: .warning }\n"),
+            "<p>Careful</p>\n",
+            "render: should strip Kramdown attribute lists"
+        );
+    }
+
+    #[test]
+    fn attribute_lists() {
+        assert_eq!(
+            strip_attribute_lists("Careful {: .warning }\r\n{: .note }\nNext\n"),
+            "Careful\r\nNext\n"
+        );
+        assert_eq!(
+            strip_attribute_lists("Line  \nbreak\n"),
+            "Line  \nbreak\n",
+            "strip_attribute_lists: trailing spaces are hard line breaks and should be kept"
+        );
+        assert_eq!(render("Line  \nbreak\n"), "<p>Line<br>\nbreak</p>\n");
+        let fenced = "```liquid\n{: .kept }\n~~~\n{: .kept }\n```\n";
+        assert_eq!(
+            strip_attribute_lists(fenced),
+            fenced,
+            "strip_attribute_lists: fenced code blocks should be left alone"
+        );
+        let indented = "Text\n\n    {: .kept }\n\n    code {: .kept }\n";
+        assert_eq!(
+            strip_attribute_lists(indented),
+            indented,
+            "strip_attribute_lists: indented code blocks should be left alone"
+        );
+        assert_eq!(
+            strip_attribute_lists("Text\n    continued {: .x }\n"),
+            "Text\n    continued\n",
+            "strip_attribute_lists: indented paragraph continuations aren't code"
+        );
+    }
+
+    #[test]
+    fn liquid() {
+        assert_eq!(
+            strip_liquid("a {% raw %}{{ kept }} {% if %}{% endraw %} b"),
+            "a {{ kept }} {% if %} b"
+        );
+        assert_eq!(
+            strip_liquid("a {% comment %}gone {{ x }}{% endcomment %}b"),
+            "a b"
+        );
+        assert_eq!(
+            strip_liquid("{%- if page.x -%}shown{% endif %} {not liquid}"),
+            "shown {not liquid}"
+        );
+        assert_eq!(
+            strip_liquid("unterminated {% tag"),
+            "unterminated {% tag",
+            "strip_liquid: should leave unterminated tags alone"
+        );
+    }
+}
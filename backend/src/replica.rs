@@ -0,0 +1,247 @@
+//! A cold standby for when the primary's host fails. A replica serves the same wiki read-only:
+//! it pulls the repository and replaces its database with a snapshot downloaded from the primary
+//! on a schedule, and rejects every change until an admin promotes it.
+//!
+//! Promotion only lasts until the replica restarts, remove `replication.primary_url` from its
+//! config to make it permanent.
+
+use crate::app_conf::AppConf;
+use crate::db::create_private_file;
+use crate::webhook_queue::timestamp;
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use fs_err::tokio as fs;
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::task;
+use tracing::{error, info};
+
+/// Where primaries serve database snapshots, relative to their URL
+pub const SNAPSHOT_ENDPOINT: &str = "/api/replication/snapshot";
+/// The only endpoint that accepts changes on a replica, relative to `/api`
+const PROMOTE_ENDPOINT: &str = "/replication/promote";
+
+/// What this instance is doing
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Primary,
+    /// Following the primary, read-only
+    Replica,
+    /// A replica that was promoted, accepting changes and no longer following the primary
+    Promoted,
+}
+
+/// The outcome of a replica's most recent syncs
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncStatus {
+    /// RFC-3339 timestamp of the last successful sync
+    pub last_sync: Option<String>,
+    /// Why the last sync failed, if it did
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Replica {
+    promoted: Arc<AtomicBool>,
+    status: Arc<Mutex<SyncStatus>>,
+}
+
+impl Replica {
+    pub fn role(&self, config: &AppConf) -> Role {
+        if config.replication.primary_url.is_none() {
+            Role::Primary
+        } else if self.promoted.load(Ordering::SeqCst) {
+            Role::Promoted
+        } else {
+            Role::Replica
+        }
+    }
+
+    /// Stop following the primary and start accepting changes.
+    ///
+    /// Returns `false` if the replica was already promoted.
+    pub fn promote(&self) -> bool {
+        !self.promoted.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// If this instance is a replica, start following the primary in the background
+    pub fn spawn_sync(state: AppState) {
+        let replication = &state.config.replication;
+        let (Some(primary_url), Some(token)) = (&replication.primary_url, &replication.token)
+        else {
+            return;
+        };
+        let interval = Duration::from_secs(replication.sync_interval_secs);
+        info!("Running as a read-only replica of {primary_url}");
+        task::spawn(async move {
+            loop {
+                if state.replica.promoted.load(Ordering::SeqCst) {
+                    info!("Replica was promoted, no longer following {primary_url}");
+                    break;
+                }
+                let result = sync(&state, primary_url, token).await;
+                match result {
+                    Ok(()) => {
+                        let mut status = state.replica.status.lock().unwrap();
+                        status.last_sync = Some(timestamp(Utc::now()));
+                        status.last_error = None;
+                    }
+                    Err(e) => {
+                        error!("Failed to sync with the primary: {e:?}");
+                        state.replica.status.lock().unwrap().last_error = Some(format!("{e:#}"));
+                    }
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+/// Pull the repository, then replace the database with a snapshot of the primary's
+async fn sync(state: &AppState, primary_url: &str, token: &str) -> Result<()> {
+    let git = state.git.clone();
+    let changes = task::spawn_blocking(move || git.pull()).await??;
+    // The search index is in the snapshot, so it isn't updated here
+    state.doc_cache.invalidate(&changes);
+
+    let response = state
+        .reqwest_client
+        .get(format!(
+            "{}{SNAPSHOT_ENDPOINT}",
+            primary_url.trim_end_matches('/')
+        ))
+        .bearer_auth(token)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let response_text = response.text().await?;
+        bail!(
+            "Failed to download a database snapshot: {}, Response: {}",
+            status,
+            response_text
+        );
+    }
+    let snapshot = response.bytes().await?;
+    let path = state
+        .data_dir
+        .join(format!("hyde-replica-{}.db", rand::random::<u64>()));
+    let mut file = create_private_file(&path).await?;
+    // Flushed so that the whole snapshot is on disk before it's read back
+    let written = match file.write_all(&snapshot).await {
+        Ok(()) => file.flush().await,
+        Err(e) => Err(e),
+    };
+    drop(file);
+    let result = match written {
+        Ok(()) => state.db.restore_snapshot(&path).await,
+        Err(e) => Err(e.into()),
+    };
+    fs::remove_file(&path).await?;
+    result?;
+    info!(
+        "Synced with the primary, pulled {} changed files and restored a {} byte snapshot",
+        changes.len(),
+        snapshot.len()
+    );
+    Ok(())
+}
+
+/// Whether a `GET` request to `path` (relative to `/api`) changes something anyway: signing in
+/// or out, and linking a GitHub account
+fn changes_on_get(path: &str) -> bool {
+    let signs_in = path == "/oauth"
+        || path == "/oauth/url"
+        || (path.starts_with("/login/") && path != "/login/providers");
+    signs_in || path == "/logout" || path.starts_with("/github/link")
+}
+
+/// Middleware rejecting every request that could change something while this instance is a
+/// replica, except for promoting it. Users can't sign in to a replica, only use the sessions
+/// they have on the primary.
+pub async fn read_only_guard(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if state.replica.role(state.config) == Role::Replica
+        && (!request.method().is_safe() || changes_on_get(path))
+        && path != PROMOTE_ENDPOINT
+    {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "This instance is a read-only replica, changes have to be made on the primary.",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Compare a provided token against the expected one, in constant time
+pub fn token_matches(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_conf::Replication;
+
+    #[test]
+    fn changing_gets() {
+        assert!(changes_on_get("/oauth"));
+        assert!(changes_on_get("/login/github/callback"));
+        assert!(changes_on_get("/github/link/callback"));
+        assert!(changes_on_get("/logout"));
+        assert!(!changes_on_get("/login/providers"));
+        assert!(!changes_on_get("/doc"));
+    }
+
+    #[test]
+    fn roles() {
+        let mut config = AppConf::default();
+        let replica = Replica::default();
+        assert_eq!(replica.role(&config), Role::Primary);
+        config.replication = Replication {
+            token: Some("secret".to_string()),
+            primary_url: Some("https://hyde.example.org".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(replica.role(&config), Role::Replica);
+        assert!(replica.promote());
+        assert!(
+            !replica.promote(),
+            "promote: should report that the replica was already promoted"
+        );
+        assert_eq!(replica.role(&config), Role::Promoted);
+    }
+
+    #[test]
+    fn tokens() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secret", "secreT"));
+        assert!(!token_matches("secret", "secrets"));
+    }
+}
@@ -0,0 +1,109 @@
+//! Digests of the documentation changes merged through Hyde, for release notes. Pull requests
+//! opened through Hyde are recorded when they're created, and marked as merged by `pull_request`
+//! webhook events.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// The pull requests opened through Hyde that were merged in a period
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChangeReport {
+    /// When the report starts from (inclusive)
+    pub since: DateTime<Utc>,
+    pub pull_requests: Vec<MergedChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MergedChange {
+    pub number: i64,
+    pub title: String,
+    /// The username of whoever opened the pull request, if they're known
+    pub author: Option<String>,
+    pub merged_at: DateTime<Utc>,
+    /// The documents changed, relative to the documents folder
+    pub doc_paths: Vec<String>,
+}
+
+impl ChangeReport {
+    /// Render the report as markdown, suitable for the body of a GitHub release. Pull request
+    /// numbers are linked automatically by GitHub.
+    pub fn to_markdown(&self) -> String {
+        let mut output = format!(
+            "## Documentation changes since {}\n\n",
+            self.since.format("%Y-%m-%d")
+        );
+        if self.pull_requests.is_empty() {
+            output.push_str("No documentation changes were merged.\n");
+        }
+        for pr in &self.pull_requests {
+            let _ = write!(output, "- {} (#{})", pr.title, pr.number);
+            if let Some(author) = &pr.author {
+                let _ = write!(output, " by {author}");
+            }
+            output.push('\n');
+            for path in &pr.doc_paths {
+                let _ = writeln!(output, "  - `{path}`");
+            }
+        }
+        output
+    }
+}
+
+/// Parse a `since` parameter, either a date (`2025-01-31`, midnight UTC) or an RFC-3339
+/// timestamp.
+///
+/// # Errors
+/// This function returns an error if `since` is neither.
+pub fn parse_since(since: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        Ok(date.and_time(chrono::NaiveTime::MIN).and_utc())
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(since) {
+        Ok(time.with_timezone(&Utc))
+    } else {
+        bail!("{since:?} is neither a date (YYYY-MM-DD) nor an RFC-3339 timestamp");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    #[test]
+    fn since_parsing() {
+        assert_eq!(
+            parse_since("2025-01-31").unwrap(),
+            at("2025-01-31T00:00:00Z")
+        );
+        assert_eq!(
+            parse_since("2025-01-31T12:00:00+02:00").unwrap(),
+            at("2025-01-31T10:00:00Z"),
+            "parse_since: timestamps should be converted to UTC"
+        );
+        assert!(parse_since("last week").is_err());
+    }
+
+    #[test]
+    fn markdown_rendering() {
+        let report = ChangeReport {
+            since: at("2025-01-31T00:00:00Z"),
+            pull_requests: vec![MergedChange {
+                number: 12,
+                title: "Update driver guide".to_string(),
+                author: Some("foo".to_string()),
+                merged_at: at("2025-02-01T00:00:00Z"),
+                doc_paths: vec!["guides/drivers.md".to_string()],
+            }],
+        };
+        assert_eq!(
+            report.to_markdown(),
+            "## Documentation changes since 2025-01-31\n\n- Update driver guide (#12) by foo\n  - `guides/drivers.md`\n"
+        );
+    }
+}
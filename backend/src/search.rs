@@ -0,0 +1,181 @@
+//! Full-text search over the documents, backed by the `doc_search` table (an FTS5 index with
+//! SQLite, a `tsvector` column with PostgreSQL).
+//!
+//! The index is derived from the repository, so it's rebuilt on startup and whenever the whole
+//! working tree may have changed, and otherwise updated document by document alongside the
+//! [`DocCache`](crate::doc_cache::DocCache). Archived documents aren't indexed.
+
+use crate::front_matter;
+use crate::git::{ChangeKind, ChangedPath};
+use crate::AppState;
+use color_eyre::Result;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Marks the start of a match in search snippets
+pub const HIGHLIGHT_START: char = '\u{2}';
+/// Marks the end of a match in search snippets
+pub const HIGHLIGHT_END: char = '\u{3}';
+/// Queries are cut off after this many terms
+const MAX_QUERY_TERMS: usize = 16;
+
+/// Split a search query into lowercase alphanumeric terms, so that nothing in it is interpreted
+/// as query syntax.
+pub fn query_terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .take(MAX_QUERY_TERMS)
+        .collect()
+}
+
+/// The title and body a document is indexed with. The title is the `title` in its front
+/// matter, otherwise its first heading, otherwise its file name.
+pub fn indexed_text(path: &str, contents: &str) -> (String, String) {
+    let body = front_matter::body(contents);
+    let front_matter_title = front_matter::parse(contents)
+        .ok()
+        .flatten()
+        .and_then(|front_matter| Some(front_matter.get("title")?.as_str()?.trim().to_string()));
+    let title = front_matter_title
+        .filter(|title| !title.is_empty())
+        .or_else(|| {
+            body.lines()
+                .find_map(|line| line.strip_prefix("# "))
+                .map(|heading| heading.trim().to_string())
+        })
+        .unwrap_or_else(|| {
+            Path::new(path)
+                .file_stem()
+                .map_or_else(String::new, |stem| stem.to_string_lossy().to_string())
+        });
+    (title, body.to_string())
+}
+
+/// Escape a snippet for HTML and wrap each match in a `<mark>`
+pub fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            HIGHLIGHT_START => html.push_str("<mark>"),
+            HIGHLIGHT_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Whether the document at `path` (relative to the documents folder) should be indexed
+fn is_indexed(state: &AppState, path: &str) -> bool {
+    let archive = state.config.files.archive_path.trim_matches('/');
+    path.ends_with(".md") && !Path::new(path).starts_with(archive)
+}
+
+/// Index every document in the working tree, replacing the existing index.
+///
+/// Returns how many documents were indexed.
+pub async fn rebuild(state: &AppState) -> Result<usize> {
+    let paths = state.git.get_doc_tree(false)?.file_paths();
+    let mut docs = Vec::new();
+    for path in paths.into_iter().filter(|p| is_indexed(state, p)) {
+        if let Some(contents) = state.git.get_doc(&path)? {
+            let (title, body) = indexed_text(&path, &contents);
+            docs.push((path, title, body));
+        }
+    }
+    state.db.replace_search_index(&docs).await?;
+    Ok(docs.len())
+}
+
+/// Update the index for the changed files. Paths outside of the documents folder are ignored.
+pub async fn update(state: &AppState, changes: &[ChangedPath]) -> Result<()> {
+    let prefix = format!("{}/", state.config.files.docs_path.trim_matches('/'));
+    for change in changes {
+        let Some(path) = change.path.strip_prefix(&prefix) else {
+            continue;
+        };
+        let contents = if change.kind == ChangeKind::Deleted || !is_indexed(state, path) {
+            None
+        } else {
+            state.git.get_doc(path)?
+        };
+        match contents {
+            Some(contents) => {
+                let (title, body) = indexed_text(path, &contents);
+                state.db.index_doc(path, &title, &body).await?;
+            }
+            None => state.db.remove_indexed_doc(path).await?,
+        }
+    }
+    Ok(())
+}
+
+/// [`rebuild`] the index in the background
+pub fn spawn_rebuild(state: AppState) {
+    tokio::spawn(async move {
+        match rebuild(&state).await {
+            Ok(indexed) => info!("Indexed {indexed} documents for search"),
+            Err(e) => warn!("Failed to rebuild the search index: {e:?}"),
+        }
+    });
+}
+
+/// [`update`] the index in the background
+pub fn spawn_update(state: AppState, changes: Vec<ChangedPath>) {
+    tokio::spawn(async move {
+        if let Err(e) = update(&state, &changes).await {
+            warn!("Failed to update the search index: {e:?}");
+        }
+    });
+}
+
+/// Update the index for a single document, where `path` is relative to the documents folder
+pub fn spawn_update_doc(state: &AppState, path: &str, kind: ChangeKind) {
+    let path = format!(
+        "{}/{}",
+        state.config.files.docs_path.trim_matches('/'),
+        path.trim_start_matches('/')
+    );
+    spawn_update(state.clone(), vec![ChangedPath { path, kind }]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexed_titles() {
+        assert_eq!(
+            indexed_text(
+                "gpu.md",
+                "---\ntitle: \"GPU drivers\"\n---\n# Drivers\nUse DDU."
+            ),
+            ("GPU drivers".to_string(), "# Drivers\nUse DDU.".to_string())
+        );
+        assert_eq!(
+            indexed_text("gpu.md", "---\nlayout: default\n---\nIntro\n# Drivers\n").0,
+            "Drivers",
+            "indexed_text: should fall back to the first heading"
+        );
+        assert_eq!(indexed_text("guides/gpu.md", "Use DDU.").0, "gpu");
+    }
+
+    #[test]
+    fn queries() {
+        assert_eq!(
+            query_terms("\"GPU\" -drivers* OR"),
+            ["gpu", "drivers", "or"]
+        );
+        assert!(query_terms(" *\" ").is_empty());
+        assert_eq!(
+            highlight("a <b> \u{2}match\u{3} & more"),
+            "a &lt;b&gt; <mark>match</mark> &amp; more"
+        );
+    }
+}
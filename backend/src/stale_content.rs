@@ -0,0 +1,110 @@
+//! Reminding document owners about documents that haven't changed in a while, so that outdated
+//! content gets looked at. See `files.stale_after_days`.
+//!
+//! Owners are sent a `stale_content` notification for each of their documents that hasn't been
+//! changed in `stale_after_days`, and again every `stale_after_days` for as long as it doesn't.
+//! Archived documents and documents without owners are skipped.
+
+use crate::webhook_queue::timestamp;
+use crate::AppState;
+use chrono::{DateTime, Days, Utc};
+use color_eyre::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::task;
+use tracing::{info, warn};
+
+/// How often documents are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The documents among `paths` that last changed before `before` (a Unix timestamp), according
+/// to `last_changed`, with when they last changed. Documents that never changed are left out,
+/// they haven't been committed yet.
+fn stale_docs(
+    paths: &[String],
+    last_changed: &HashMap<String, i64>,
+    before: i64,
+) -> Vec<(String, i64)> {
+    paths
+        .iter()
+        .filter_map(|path| Some((path.clone(), *last_changed.get(path)?)))
+        .filter(|(_, changed_at)| *changed_at < before)
+        .collect()
+}
+
+/// Notify the owners of every document that hasn't changed in `stale_after_days` at `now`,
+/// unless they were already reminded within that time. Returns how many reminders were sent.
+pub async fn remind(state: &AppState, now: DateTime<Utc>, stale_after_days: u32) -> Result<usize> {
+    let stale_since = now
+        .checked_sub_days(Days::new(stale_after_days.into()))
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    let paths = state
+        .doc_cache
+        .doc_tree(false, || state.git.get_doc_tree(false))?
+        .file_paths();
+    let last_changed = state.git.docs_last_changed(&paths)?;
+    let mut reminders = 0;
+    for (path, changed_at) in stale_docs(&paths, &last_changed, stale_since.timestamp()) {
+        for owner in state.db.resolve_doc_owner_users(&path).await? {
+            if !state
+                .db
+                .record_stale_reminder(&path, owner.id, stale_since, now)
+                .await?
+            {
+                continue;
+            }
+            let changed_at = DateTime::from_timestamp(changed_at, 0).unwrap_or_default();
+            let payload = serde_json::json!({
+                "path": path,
+                "last_changed_at": timestamp(changed_at),
+            });
+            state
+                .db
+                .create_notification(owner.id, "stale_content", &payload.to_string(), now)
+                .await?;
+            reminders += 1;
+        }
+    }
+    Ok(reminders)
+}
+
+/// [`remind`] once a day, if `files.stale_after_days` is set, see [`crate::spawn_writers`]
+pub fn spawn(state: AppState) {
+    let Some(stale_after_days) = state.config.files.stale_after_days else {
+        return;
+    };
+    task::spawn(async move {
+        loop {
+            match remind(&state, Utc::now(), stale_after_days).await {
+                Ok(0) => {}
+                Ok(reminders) => info!("Reminded owners about {reminders} stale documents"),
+                Err(e) => warn!("Failed to remind owners about stale documents: {e:?}"),
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale() {
+        let paths = vec![
+            "old.md".to_string(),
+            "new.md".to_string(),
+            "uncommitted.md".to_string(),
+        ];
+        let last_changed = HashMap::from([
+            ("old.md".to_string(), 100),
+            ("new.md".to_string(), 300),
+            ("deleted.md".to_string(), 100),
+        ]);
+        assert_eq!(
+            stale_docs(&paths, &last_changed, 200),
+            vec![("old.md".to_string(), 100)],
+            "stale_docs: should only list documents that exist and changed before the cutoff"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Opt-in performance metrics. The editor reports how long saves took and which requests failed,
+//! and the server records how long it spent on the same saves, so maintainers can compare what
+//! users experience with what the server sees. Nothing identifying the user is stored.
+
+use crate::db::TelemetrySample;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The most events the editor may report at once
+pub const MAX_EVENTS_PER_REPORT: usize = 100;
+/// Longer save times are assumed to be a broken clock rather than a slow save
+const MAX_SAVE_DURATION_MS: u32 = 10 * 60 * 1000;
+
+/// Where a sample was measured
+pub const SOURCE_CLIENT: &str = "client";
+pub const SOURCE_SERVER: &str = "server";
+
+/// How long a save took in milliseconds, from the editor's click to the response, or from
+/// the server receiving the request to responding
+pub const METRIC_SAVE_LATENCY: &str = "save_latency_ms";
+/// A request the editor made failed, the value is its status code
+pub const METRIC_REQUEST_FAILED: &str = "request_failed";
+
+/// A measurement reported by the editor
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ClientEvent {
+    Save { duration_ms: u32 },
+    RequestFailed { status: u16 },
+}
+
+impl ClientEvent {
+    /// The metric name and value the event is stored as
+    ///
+    /// # Errors
+    /// This function returns an error if the value isn't plausible.
+    pub fn to_metric(self) -> Result<(&'static str, i64)> {
+        match self {
+            Self::Save { duration_ms } if duration_ms <= MAX_SAVE_DURATION_MS => {
+                Ok((METRIC_SAVE_LATENCY, i64::from(duration_ms)))
+            }
+            Self::Save { duration_ms } => bail!("A save can't take {duration_ms}ms"),
+            Self::RequestFailed { status } if (400..600).contains(&status) => {
+                Ok((METRIC_REQUEST_FAILED, i64::from(status)))
+            }
+            Self::RequestFailed { status } => bail!("{status} isn't an error status code"),
+        }
+    }
+}
+
+/// Percentiles of a set of durations, in milliseconds
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50: i64,
+    pub p95: i64,
+    pub max: i64,
+}
+
+impl LatencySummary {
+    fn new(mut durations: Vec<i64>) -> Self {
+        durations.sort_unstable();
+        Self {
+            count: durations.len(),
+            p50: percentile(&durations, 50),
+            p95: percentile(&durations, 95),
+            max: durations.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// The nearest-rank percentile of sorted values, 0 if there are none
+fn percentile(sorted: &[i64], percent: usize) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// The metrics recorded in a period
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TelemetrySummary {
+    /// When the summary starts from (inclusive)
+    pub since: DateTime<Utc>,
+    /// Save latency as measured by the editor
+    pub client_save_latency: LatencySummary,
+    /// Save latency as measured by the server
+    pub server_save_latency: LatencySummary,
+    /// How many requests failed, by status code
+    pub failed_requests: BTreeMap<i64, usize>,
+}
+
+impl TelemetrySummary {
+    pub fn new(since: DateTime<Utc>, samples: &[TelemetrySample]) -> Self {
+        let save_latencies = |source: &str| {
+            samples
+                .iter()
+                .filter(|s| s.metric == METRIC_SAVE_LATENCY && s.source == source)
+                .map(|s| s.value)
+                .collect::<Vec<_>>()
+        };
+        let mut failed_requests = BTreeMap::new();
+        for sample in samples.iter().filter(|s| s.metric == METRIC_REQUEST_FAILED) {
+            *failed_requests.entry(sample.value).or_default() += 1;
+        }
+        Self {
+            since,
+            client_save_latency: LatencySummary::new(save_latencies(SOURCE_CLIENT)),
+            server_save_latency: LatencySummary::new(save_latencies(SOURCE_SERVER)),
+            failed_requests,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(source: &str, metric: &str, value: i64) -> TelemetrySample {
+        TelemetrySample {
+            source: source.to_string(),
+            metric: metric.to_string(),
+            value,
+            recorded_at: DateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn client_events() {
+        assert_eq!(
+            ClientEvent::Save { duration_ms: 250 }.to_metric().unwrap(),
+            (METRIC_SAVE_LATENCY, 250)
+        );
+        assert!(
+            ClientEvent::Save {
+                duration_ms: u32::MAX
+            }
+            .to_metric()
+            .is_err(),
+            "to_metric: implausible durations should be rejected"
+        );
+        assert!(
+            ClientEvent::RequestFailed { status: 200 }
+                .to_metric()
+                .is_err(),
+            "to_metric: successful status codes should be rejected"
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let mut samples: Vec<TelemetrySample> = (1..=100)
+            .map(|ms| sample(SOURCE_CLIENT, METRIC_SAVE_LATENCY, ms))
+            .collect();
+        samples.push(sample(SOURCE_SERVER, METRIC_SAVE_LATENCY, 40));
+        samples.push(sample(SOURCE_CLIENT, METRIC_REQUEST_FAILED, 502));
+        samples.push(sample(SOURCE_CLIENT, METRIC_REQUEST_FAILED, 502));
+        samples.push(sample(SOURCE_CLIENT, METRIC_REQUEST_FAILED, 500));
+
+        let summary = TelemetrySummary::new(DateTime::UNIX_EPOCH, &samples);
+        assert_eq!(
+            summary.client_save_latency,
+            LatencySummary {
+                count: 100,
+                p50: 50,
+                p95: 95,
+                max: 100
+            }
+        );
+        assert_eq!(
+            summary.server_save_latency.count, 1,
+            "TelemetrySummary::new: server and client samples should be kept apart"
+        );
+        assert_eq!(
+            summary.failed_requests,
+            BTreeMap::from([(500, 1), (502, 2)])
+        );
+        assert_eq!(
+            TelemetrySummary::new(DateTime::UNIX_EPOCH, &[]).client_save_latency,
+            LatencySummary::default(),
+            "TelemetrySummary::new: should handle there being no samples"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Encrypting the OAuth tokens stored in the database, so that a leaked copy of the database
+//! (a backup, a replica snapshot) doesn't let anyone act on users' behalf. Tokens are encrypted
+//! with AES-256-GCM, using a key that's kept outside the database.
+//!
+//! Encrypted tokens are stored as `enc:v1:` followed by the base64 encoded nonce and
+//! ciphertext. Anything else is a token stored before encryption was added, and is read as is
+//! until [`crate::db::Database::encrypt_stored_tokens`] encrypts it.
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use color_eyre::eyre::{bail, eyre, Context};
+use color_eyre::Result;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use std::fmt;
+use std::path::Path;
+
+/// If set, the base64 encoded key is read from this environment variable instead of a file
+pub const TOKEN_KEY_ENV_VAR: &str = "HYDE_TOKEN_KEY";
+/// How many bytes a key is
+pub const KEY_LEN: usize = 32;
+/// Marks a stored token as encrypted, and which format it's in
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+pub struct TokenCipher {
+    key: LessSafeKey,
+}
+
+impl fmt::Debug for TokenCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenCipher").finish_non_exhaustive()
+    }
+}
+
+impl TokenCipher {
+    /// # Errors
+    /// This function returns an error if the key isn't [`KEY_LEN`] bytes.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, key)
+            .map_err(|_| eyre!("Token encryption keys must be {KEY_LEN} bytes"))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    /// Load the key from [`TOKEN_KEY_ENV_VAR`] if it's set, otherwise from the file at `path`.
+    /// Either way, the key is base64 encoded.
+    ///
+    /// # Errors
+    /// This function returns an error if the key can't be read or isn't valid.
+    pub fn load(path: &str) -> Result<Self> {
+        let (encoded, source) = match std::env::var(TOKEN_KEY_ENV_VAR) {
+            Ok(encoded) => (
+                encoded,
+                format!("the {TOKEN_KEY_ENV_VAR} environment variable"),
+            ),
+            Err(_) => (
+                fs_err::read_to_string(Path::new(path))?,
+                format!("{path:?}"),
+            ),
+        };
+        let key = BASE64_STANDARD
+            .decode(encoded.trim())
+            .wrap_err_with(|| format!("The token encryption key in {source} isn't valid base64"))?;
+        Self::new(&key).wrap_err_with(|| format!("The token encryption key in {source} is invalid"))
+    }
+
+    /// Encrypt a token for storage
+    ///
+    /// # Errors
+    /// This function returns an error if encryption fails.
+    pub fn encrypt(&self, token: &str) -> Result<String> {
+        let nonce_bytes: [u8; NONCE_LEN] = rand::random();
+        let mut sealed = token.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce_bytes),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .map_err(|_| eyre!("Failed to encrypt a token"))?;
+        let mut stored = nonce_bytes.to_vec();
+        stored.extend(sealed);
+        Ok(format!(
+            "{ENCRYPTED_PREFIX}{}",
+            BASE64_STANDARD.encode(stored)
+        ))
+    }
+
+    /// Decrypt a stored token. Tokens stored before encryption was added are returned as is.
+    ///
+    /// # Errors
+    /// This function returns an error if the token was encrypted with a different key, or has
+    /// been tampered with.
+    pub fn decrypt(&self, stored: &str) -> Result<String> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let mut sealed = BASE64_STANDARD.decode(encoded)?;
+        if sealed.len() < NONCE_LEN {
+            bail!("The stored token is too short to have been encrypted");
+        }
+        let ciphertext = sealed.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&sealed)
+            .map_err(|_| eyre!("The stored token's nonce is invalid"))?;
+        let mut ciphertext = ciphertext;
+        let token = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+            .map_err(|_| {
+                eyre!("Failed to decrypt a stored token, it was encrypted with a different key")
+            })?;
+        Ok(String::from_utf8(token.to_vec())?)
+    }
+}
+
+/// Whether a stored token is encrypted
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// A new random key, base64 encoded
+pub fn generate_key() -> String {
+    BASE64_STANDARD.encode(rand::random::<[u8; KEY_LEN]>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cipher = TokenCipher::new(&[7; KEY_LEN]).unwrap();
+        let stored = cipher.encrypt("discord token").unwrap();
+        assert!(is_encrypted(&stored));
+        assert!(!stored.contains("discord token"));
+        assert_ne!(
+            stored,
+            cipher.encrypt("discord token").unwrap(),
+            "encrypt: nonces should be random"
+        );
+        assert_eq!(cipher.decrypt(&stored).unwrap(), "discord token");
+        assert_eq!(
+            cipher.decrypt("legacy token").unwrap(),
+            "legacy token",
+            "decrypt: unencrypted tokens should be returned as is"
+        );
+
+        let other = TokenCipher::new(&[8; KEY_LEN]).unwrap();
+        assert!(
+            other.decrypt(&stored).is_err(),
+            "decrypt: tokens encrypted with a different key should be rejected"
+        );
+        assert!(TokenCipher::new(&[7; 16]).is_err());
+        assert_eq!(
+            BASE64_STANDARD.decode(generate_key()).unwrap().len(),
+            KEY_LEN
+        );
+    }
+}
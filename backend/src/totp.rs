@@ -0,0 +1,151 @@
+//! Time-based one-time passwords (RFC 6238), the second factor privileged users can be asked
+//! for before destructive actions like deleting a group or recloning the repository.
+//!
+//! Codes are 6 digits, change every 30 seconds, and are generated with HMAC-SHA1, which is what
+//! every authenticator app supports.
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use ring::hmac;
+use std::time::Duration;
+
+/// How many seconds each code is valid for
+const STEP_SECONDS: i64 = 30;
+/// How many digits codes are
+const DIGITS: u32 = 6;
+/// How many random bytes a secret is
+const SECRET_LEN: usize = 20;
+/// The header the code is sent in, lowercase so it can be used as a `HeaderName`
+pub const TOTP_HEADER: &str = "x-hyde-totp";
+/// How many wrong codes in a row a user can send before they're locked out
+pub const FREE_FAILURES: u32 = 5;
+/// How long the first lockout is, each one after it is twice as long
+pub const BASE_LOCKOUT: Duration = Duration::from_secs(30);
+/// The longest a user is locked out for
+pub const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+/// The alphabet secrets are encoded with (RFC 4648 base32)
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A new random secret, base32 encoded
+pub fn generate_secret() -> String {
+    base32_encode(&rand::random::<[u8; SECRET_LEN]>())
+}
+
+/// The `otpauth://` URI authenticator apps can scan as a QR code
+pub fn provisioning_uri(issuer: &str, username: &str, secret: &str) -> String {
+    let mut uri = Url::parse("otpauth://totp/").expect("The base URI is valid");
+    uri.set_path(&format!("{issuer}:{username}"));
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", issuer)
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECONDS.to_string());
+    uri.to_string()
+}
+
+/// Which time step `time` falls in
+pub const fn step(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(STEP_SECONDS)
+}
+
+/// The code for a time step, or `None` if the secret isn't valid base32
+pub fn code_at(secret: &str, step: i64) -> Option<String> {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &base32_decode(secret)?);
+    let digest = hmac::sign(&key, &step.to_be_bytes());
+    let digest = digest.as_ref();
+    // Dynamic truncation, RFC 4226 section 5.3
+    let offset = usize::from(digest[digest.len() - 1] & 0xf);
+    let binary = u32::from_be_bytes(digest[offset..offset + 4].try_into().ok()?) & 0x7fff_ffff;
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// If `code` is valid for `secret` at `now`, returns the time step it was generated for.
+/// Codes from the step before and after are accepted too, to allow for clock drift.
+pub fn verify(secret: &str, code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let current = step(now);
+    (current - 1..=current + 1).find(|&step| {
+        code_at(secret, step).is_some_and(|expected| {
+            ring::constant_time::verify_slices_are_equal(expected.as_bytes(), code.as_bytes())
+                .is_ok()
+        })
+    })
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(char::from(BASE32_ALPHABET[(buffer >> bits) as usize & 31]));
+        }
+    }
+    if bits > 0 {
+        encoded.push(char::from(
+            BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31],
+        ));
+    }
+    encoded
+}
+
+/// Decodes base32, ignoring case, spaces and padding like authenticator apps do
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| char::from(a) == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        // The SHA-1 test vectors from RFC 6238 appendix B, truncated to 6 digits
+        let secret = base32_encode(b"12345678901234567890");
+        assert_eq!(secret, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(
+            base32_decode(&secret.to_lowercase()).unwrap(),
+            b"12345678901234567890"
+        );
+        let at = |seconds| DateTime::from_timestamp(seconds, 0).unwrap();
+        assert_eq!(code_at(&secret, step(at(59))).unwrap(), "287082");
+        assert_eq!(code_at(&secret, step(at(1_111_111_109))).unwrap(), "081804");
+        assert_eq!(code_at(&secret, step(at(2_000_000_000))).unwrap(), "279037");
+
+        assert_eq!(verify(&secret, "287 082", at(59)), Some(1));
+        assert_eq!(
+            verify(&secret, "287082", at(89)),
+            Some(1),
+            "verify: codes from the previous step should be accepted"
+        );
+        assert_eq!(verify(&secret, "287082", at(120)), None);
+        assert_eq!(verify(&secret, "000000", at(59)), None);
+        assert_eq!(code_at("not base32!", 1), None);
+        assert_eq!(generate_secret().len(), 32);
+        assert_eq!(
+            provisioning_uri("Hyde", "alice", "JBSWY3DPEHPK3PXP"),
+            "otpauth://totp/Hyde:alice?secret=JBSWY3DPEHPK3PXP&issuer=Hyde&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Webhook events are stored in the database as they're received, then processed by a
+//! background worker. If processing fails (for example, because a long git operation is
+//! holding the repository), the event is retried with exponential backoff instead of being lost.
+
+use crate::db::WebhookEvent;
+use crate::search;
+use crate::AppState;
+use chrono::{DateTime, SecondsFormat, Utc};
+use color_eyre::Result;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task;
+use tracing::{debug, error, info, warn};
+
+/// How many times an event is attempted before it's marked as failed
+pub const MAX_ATTEMPTS: i64 = 8;
+/// How long to wait before the first retry, doubled with each failed attempt
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// The longest to wait between attempts
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+/// How often the worker checks for events that are due to be retried
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Format a time for messages and JSON payloads, in UTC with millisecond precision
+pub fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// How long to wait before retrying an event that has failed `attempts` times
+fn retry_delay(attempts: i64) -> Duration {
+    let exponent = u32::try_from(attempts.saturating_sub(1))
+        .unwrap_or(0)
+        .min(16);
+    BASE_RETRY_DELAY
+        .saturating_mul(2_u32.pow(exponent))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// A handle used to wake up the worker when an event is queued
+#[derive(Clone, Debug, Default)]
+pub struct WebhookQueue {
+    wakeup: Arc<Notify>,
+}
+
+impl WebhookQueue {
+    /// Store an event in the queue, and wake up the worker to process it.
+    ///
+    /// # Errors
+    /// This function returns an error if the event couldn't be stored.
+    pub async fn enqueue(
+        &self,
+        state: &AppState,
+        event_type: &str,
+        delivery_id: Option<&str>,
+        payload: &str,
+    ) -> Result<WebhookEvent> {
+        let event = state
+            .db
+            .enqueue_webhook_event(event_type, delivery_id, payload, Utc::now())
+            .await?;
+        debug!("Queued webhook event {} ({event_type:?})", event.id);
+        self.wakeup.notify_one();
+        Ok(event)
+    }
+
+    /// Start processing queued events in the background. Events left over from a previous
+    /// run are picked up as well.
+    pub fn spawn_worker(state: AppState) {
+        task::spawn(async move {
+            loop {
+                match state.db.next_due_webhook_event(Utc::now()).await {
+                    Ok(Some(event)) => handle_event(&state, event).await,
+                    Ok(None) => {
+                        // Sleep until a new event arrives, or a retry might be due
+                        let _ = tokio::time::timeout(
+                            POLL_INTERVAL,
+                            state.webhook_queue.wakeup.notified(),
+                        )
+                        .await;
+                    }
+                    Err(e) => {
+                        error!("Failed to read the webhook queue: {e:?}");
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Process an event, and record the result in the queue
+async fn handle_event(state: &AppState, event: WebhookEvent) {
+    let result = match process_event(state, &event).await {
+        Ok(()) => state.db.complete_webhook_event(event.id, Utc::now()).await,
+        Err(e) => {
+            let attempts = event.attempts + 1;
+            let next_attempt =
+                (attempts < MAX_ATTEMPTS).then(|| Utc::now() + retry_delay(attempts));
+            match &next_attempt {
+                Some(next_attempt) => warn!(
+                    "Webhook event {} failed (attempt {attempts}/{MAX_ATTEMPTS}), retrying at {next_attempt}: {e:?}",
+                    event.id
+                ),
+                None => error!(
+                    "Webhook event {} failed {attempts} times, giving up: {e:?}",
+                    event.id
+                ),
+            }
+            state
+                .db
+                .fail_webhook_event(event.id, &format!("{e:#}"), next_attempt)
+                .await
+        }
+    };
+    if let Err(e) = result {
+        error!("Failed to update webhook event {}: {e:?}", event.id);
+        // Avoid spinning on an event that can't be updated
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// The part of a `repository` event's payload Hyde uses. The repository object in the payload
+/// isn't trusted, the event only signals that the metadata should be fetched again.
+#[derive(Deserialize)]
+struct RepositoryEvent {
+    action: String,
+}
+
+/// The part of a `pull_request` event's payload Hyde uses. The pull request's state isn't taken
+/// from the payload, it's fetched from GitHub.
+#[derive(Deserialize)]
+struct PullRequestEvent {
+    action: String,
+    number: u64,
+}
+
+/// The part of a `create` or `delete` event's payload Hyde uses
+#[derive(Deserialize)]
+struct RefEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    /// "branch" or "tag"
+    ref_type: String,
+}
+
+/// The part of an `issues` event's payload Hyde uses
+#[derive(Deserialize)]
+struct IssuesEvent {
+    action: String,
+    issue: IssueEventDetails,
+}
+
+#[derive(Deserialize)]
+struct IssueEventDetails {
+    number: i64,
+}
+
+async fn process_event(state: &AppState, event: &WebhookEvent) -> Result<()> {
+    match event.event_type.as_str() {
+        "push" => {
+            info!("New changes pushed to Github, pulling changes...");
+            let git = state.git.clone();
+            let changes = task::spawn_blocking(move || git.pull()).await??;
+            info!("Pulled {} changed files", changes.len());
+            state.doc_cache.invalidate(&changes);
+            search::spawn_update(state.clone(), changes);
+        }
+        "repository" => {
+            // Renames, visibility changes, and default branch changes are all `edited` events
+            let event: RepositoryEvent = serde_json::from_str(&event.payload)?;
+            info!("Repository {}, refreshing cached metadata", event.action);
+            state.repo_metadata.refresh(&state.gh_client).await?;
+        }
+        "pull_request" => {
+            let event: PullRequestEvent = serde_json::from_str(&event.payload)?;
+            if !matches!(event.action.as_str(), "closed" | "reopened") {
+                return Ok(());
+            }
+            let Ok(number) = i64::try_from(event.number) else {
+                return Ok(());
+            };
+            if !state.db.is_hyde_pr(number).await? {
+                return Ok(());
+            }
+            let pr = state.gh_client.get_pull_request_state(event.number).await?;
+            let updated = match (pr.state.as_str(), pr.merged_at) {
+                (_, Some(merged_at)) => state
+                    .db
+                    .mark_hyde_pr_merged(number, merged_at)
+                    .await?
+                    .then_some("merged"),
+                ("closed", None) => state
+                    .db
+                    .set_hyde_pr_status(number, "closed")
+                    .await?
+                    .then_some("closed"),
+                (_, None) => state
+                    .db
+                    .set_hyde_pr_status(number, "open")
+                    .await?
+                    .then_some("reopened"),
+            };
+            if let Some(action) = updated {
+                info!("Pull request #{number} opened through Hyde was {action}");
+            }
+        }
+        "create" | "delete" => {
+            let ref_event: RefEvent = serde_json::from_str(&event.payload)?;
+            if ref_event.ref_type == "branch" {
+                info!(
+                    "Branch '{}' {}d on GitHub, invalidating cached branches",
+                    ref_event.git_ref, event.event_type
+                );
+                state.repo_lists.invalidate_branches().await;
+            }
+        }
+        "issues" => {
+            let issues_event: IssuesEvent = serde_json::from_str(&event.payload)?;
+            info!(
+                "Issue #{} {}, invalidating cached issues",
+                issues_event.issue.number, issues_event.action
+            );
+            state.repo_lists.invalidate_issues().await;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        assert_eq!(retry_delay(1), Duration::from_secs(5));
+        assert_eq!(retry_delay(3), Duration::from_secs(20));
+        assert_eq!(
+            retry_delay(MAX_ATTEMPTS * 10),
+            MAX_RETRY_DELAY,
+            "retry_delay: delays should be capped"
+        );
+    }
+}
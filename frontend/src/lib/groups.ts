@@ -102,7 +102,7 @@ export async function addPermissionToGroup(group: GroupListEntry, permission: Pe
 	});
 	if (response.ok) {
 		addToast(
-			`${group.name} was given the permission "${allPermissions.get(permission)}"`,
+			`${group.name} was given the permission "${allPermissions.get(permission) ?? permission}"`,
 			ToastType.Info,
 			true,
 			1500
@@ -133,7 +133,7 @@ export async function removePermissionFromGroup(group: GroupListEntry, permissio
 	});
 	if (response.ok) {
 		addToast(
-			`${group.name} lost the permission "${allPermissions.get(permission)}"`,
+			`${group.name} lost the permission "${allPermissions.get(permission) ?? permission}"`,
 			ToastType.Info,
 			true,
 			1500
@@ -9,7 +9,11 @@
 export enum Permission {
 	ManageUsers = 'ManageUsers',
 	ManageContent = 'ManageContent',
-	ManageBranches = 'ManageBranches'
+	ManageBranches = 'ManageBranches',
+	SubmitForReview = 'SubmitForReview',
+	ManageAssets = 'ManageAssets',
+	ReadContent = 'ReadContent',
+	ManageRepo = 'ManageRepo'
 }
 
 /**
@@ -19,6 +23,23 @@ export const allPermissions: Map<Permission, string> = new Map();
 allPermissions.set(Permission.ManageContent, 'Manage Content');
 allPermissions.set(Permission.ManageUsers, 'Manage Users');
 allPermissions.set(Permission.ManageBranches, 'Manage Branches');
+allPermissions.set(Permission.SubmitForReview, 'Submit For Review');
+allPermissions.set(Permission.ManageAssets, 'Manage Assets');
+allPermissions.set(Permission.ReadContent, 'Read Content');
+allPermissions.set(Permission.ManageRepo, 'Manage Repository');
+
+/**
+ * A permission groups can be given, as listed by `GET /api/permissions`
+ */
+export interface PermissionInfo {
+	permission: Permission;
+	label: string;
+	description: string;
+	/** Whether the permission is declared in the server's config rather than built in */
+	custom: boolean;
+	/** The ids of the groups that have the permission */
+	groups: number[];
+}
 
 export interface User {
 	id: number;
@@ -33,9 +54,20 @@ export interface Group {
 	name: string;
 }
 
+export interface DocMeta {
+	path: string;
+	last_editor_id: number | null;
+	last_editor_name: string;
+	last_edited_at: string;
+	edit_count: number;
+	description: string | null;
+}
+
 export interface INode {
 	name: string;
 	children: INode[];
+	/** Only set for documents that have been edited through Hyde */
+	meta?: DocMeta;
 }
 
 export interface Branch {